@@ -0,0 +1,196 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Guest stack walking and symbolication, for diagnostics.
+//!
+//! The armv7 Darwin ABI uses r7 as the frame pointer: each frame stores the
+//! caller's r7 at `[r7]` and the return address at `[r7 + 4]`. Apps are not
+//! guaranteed to follow this (and the stack may be corrupt when we get here),
+//! so the walk is bounded and stops at the first implausible frame.
+
+use crate::abi;
+use crate::cpu::Cpu;
+use crate::mem::{ConstPtr, Mem, Ptr, VAddr};
+use crate::Environment;
+use std::ops::RangeInclusive;
+
+/// Upper bound on the number of frames walked, in case of a cyclic chain.
+const MAX_FRAMES: usize = 256;
+
+/// Walk the saved frame pointer chain starting from `fp`, returning the
+/// return addresses found. The walk stops if a frame pointer is outside
+/// `stack`, misaligned or not strictly increasing (the stack grows down, so
+/// each caller's frame must be above its callee's), or if a return address in
+/// `stop_at` is found (it is included in the result).
+pub fn walk_frame_chain(
+    mem: &Mem,
+    stack: &RangeInclusive<VAddr>,
+    fp: VAddr,
+    stop_at: &[VAddr],
+) -> Vec<VAddr> {
+    let mut frames = Vec::new();
+    let mut fp = fp;
+    while frames.len() < MAX_FRAMES {
+        // Both words of the frame record must be on the stack.
+        if fp % 4 != 0 || !stack.contains(&fp) || !stack.contains(&fp.wrapping_add(7)) {
+            break;
+        }
+        let record: ConstPtr<u32> = Ptr::from_bits(fp);
+        let next_fp = mem.read(record);
+        let lr = mem.read(record + 1);
+        frames.push(lr);
+        if stop_at.contains(&lr) || next_fp <= fp {
+            break;
+        }
+        fp = next_fp;
+    }
+    frames
+}
+
+/// Produce a backtrace for the current guest thread: the PC, LR, then the
+/// return addresses from the frame pointer chain. Each address is paired with
+/// a symbol name if one could be found.
+pub fn guest_backtrace(env: &Environment) -> Vec<(VAddr, Option<String>)> {
+    let regs = env.cpu.regs();
+    let pc = env.cpu.pc_with_thumb_bit().addr_with_thumb_bit();
+    let lr = regs[Cpu::LR];
+    let return_to_host_routine_addr = env.dyld.return_to_host_routine().addr_with_thumb_bit();
+    let thread_exit_routine_addr = env.dyld.thread_exit_routine().addr_with_thumb_bit();
+
+    let mut addrs = vec![pc, lr];
+    if lr != thread_exit_routine_addr {
+        if let Some(stack) = env.threads[env.current_thread].stack.as_ref() {
+            addrs.extend(walk_frame_chain(
+                &env.mem,
+                stack,
+                regs[abi::FRAME_POINTER],
+                &[thread_exit_routine_addr],
+            ));
+        }
+    }
+
+    addrs
+        .into_iter()
+        .map(|addr| {
+            let symbol = if addr == return_to_host_routine_addr {
+                Some("[host function]".to_string())
+            } else if addr == thread_exit_routine_addr {
+                Some("[thread exit]".to_string())
+            } else {
+                symbolicate(env, addr)
+            };
+            (addr, symbol)
+        })
+        .collect()
+}
+
+/// Print [guest_backtrace] for the current thread.
+pub fn print_guest_backtrace(env: &Environment) {
+    if env.current_thread == 0 {
+        echo!("Guest backtrace for main thread:");
+    } else {
+        echo!("Guest backtrace for thread {}:", env.current_thread);
+    }
+    for (i, (addr, symbol)) in guest_backtrace(env).into_iter().enumerate() {
+        let suffix = match i {
+            0 => " (PC)",
+            1 => " (LR)",
+            _ => "",
+        };
+        match symbol {
+            Some(symbol) => echo!("{:2}. {:#010x} {}{}", i, addr, symbol, suffix),
+            None => echo!("{:2}. {:#010x}{}", i, addr, suffix),
+        }
+    }
+}
+
+/// Report that the app passed an invalid pointer to a function (e.g. a double
+/// free), along with a guest backtrace. Apps with bugs like this often survive
+/// them on iPhone OS, so this is only a warning unless the
+/// `--fatal-invalid-pointers` option is used.
+pub fn report_invalid_pointer(env: &Environment, message: std::fmt::Arguments) {
+    if env.options.fatal_invalid_pointers {
+        print_guest_backtrace(env);
+        panic!("{}", message);
+    }
+    log!("Warning: {}", message);
+    print_guest_backtrace(env);
+}
+
+/// Find a name for a guest address: either a host function trampoline known
+/// to the dynamic linker, or the nearest preceding symbol exported by the
+/// loaded binary whose sections contain the address.
+fn symbolicate(env: &Environment, addr: VAddr) -> Option<String> {
+    let addr = addr & !1;
+    if let Some(name) = env.dyld.host_function_name_at(&env.mem, addr) {
+        return Some(format!("{} [host function]", name));
+    }
+    for bin in &env.bins {
+        if !bin
+            .sections
+            .iter()
+            .any(|section| (section.addr..section.addr + section.size).contains(&addr))
+        {
+            continue;
+        }
+        let nearest = bin
+            .exported_symbols
+            .iter()
+            .map(|(name, &sym_addr)| (name, sym_addr & !1))
+            .filter(|&(_, sym_addr)| sym_addr <= addr)
+            .max_by_key(|&(_, sym_addr)| sym_addr);
+        return Some(match nearest {
+            Some((name, sym_addr)) => {
+                format!("{}+{:#x} ({})", name, addr - sym_addr, bin.name)
+            }
+            None => format!("({})", bin.name),
+        });
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::MutPtr;
+
+    #[test]
+    fn test_walk_frame_chain() {
+        let mut mem = Mem::new(Mem::DEFAULT_MAIN_THREAD_STACK_SIZE);
+        let stack = mem.main_thread_stack();
+        let base = stack.start() + 0x1000;
+        let write_frame = |mem: &mut Mem, fp: VAddr, next_fp: VAddr, lr: VAddr| {
+            let record: MutPtr<u32> = Ptr::from_bits(fp);
+            mem.write(record, next_fp);
+            mem.write(record + 1, lr);
+        };
+
+        // Well-formed chain ending in a frame pointer outside the stack.
+        write_frame(&mut mem, base, base + 0x20, 0x1001);
+        write_frame(&mut mem, base + 0x20, base + 0x40, 0x2001);
+        write_frame(&mut mem, base + 0x40, 0, 0x3001);
+        assert_eq!(
+            walk_frame_chain(&mem, &stack, base, &[]),
+            vec![0x1001, 0x2001, 0x3001]
+        );
+
+        // Stop address is included and ends the walk.
+        assert_eq!(
+            walk_frame_chain(&mem, &stack, base, &[0x2001]),
+            vec![0x1001, 0x2001]
+        );
+
+        // A cycle must not loop forever.
+        write_frame(&mut mem, base + 0x40, base, 0x3001);
+        assert_eq!(
+            walk_frame_chain(&mem, &stack, base, &[]),
+            vec![0x1001, 0x2001, 0x3001]
+        );
+
+        // Misaligned and out-of-stack starting points produce nothing.
+        assert!(walk_frame_chain(&mem, &stack, base + 2, &[]).is_empty());
+        assert!(walk_frame_chain(&mem, &stack, 0x1000, &[]).is_empty());
+    }
+}
@@ -13,6 +13,7 @@
 
 use crate::fs::{BundleData, Fs, GuestPath, GuestPathBuf};
 use crate::image::Image;
+use crate::window::DeviceOrientation;
 use plist::dictionary::Dictionary;
 use plist::Value;
 use std::io::Cursor;
@@ -30,23 +31,18 @@ impl Bundle {
         read_only_mode: bool,
     ) -> Result<(Bundle, Fs), String> {
         let plist_bytes = bundle_data.read_plist()?;
-
-        let plist = Value::from_reader(Cursor::new(plist_bytes))
-            .map_err(|_| "Could not deserialize plist data".to_string())?;
-
-        let plist = plist
-            .into_dictionary()
-            .ok_or_else(|| "plist root value is not a dictionary".to_string())?;
+        let plist = parse_info_plist(&plist_bytes)?;
 
         let bundle_name = format!(
             "{}.app",
-            if let Some(canonical) = plist.get("CFBundleName") {
-                canonical.as_string().unwrap()
+            if let Some(canonical) = info_string(&plist, "CFBundleName") {
+                canonical
             } else {
                 bundle_data.bundle_name()
             }
         );
-        let bundle_id = plist["CFBundleIdentifier"].as_string().unwrap();
+        let bundle_id = info_string(&plist, "CFBundleIdentifier")
+            .ok_or_else(|| "Info.plist has no CFBundleIdentifier".to_string())?;
 
         let (fs, guest_path) = Fs::new(bundle_data, bundle_name, bundle_id, read_only_mode);
 
@@ -70,12 +66,24 @@ impl Bundle {
         &self.path
     }
 
+    /// The parsed content of `Info.plist`. `NSBundle`'s `infoDictionary` is
+    /// created from this, so that the app sees the same values the accessors
+    /// below are based on.
+    pub fn info_dictionary(&self) -> &Dictionary {
+        &self.plist
+    }
+
     pub fn bundle_identifier(&self) -> &str {
-        self.plist["CFBundleIdentifier"].as_string().unwrap()
+        // Checked when the bundle is opened, but the fake bundle lacks it.
+        info_string(&self.plist, "CFBundleIdentifier").unwrap()
     }
 
-    pub fn bundle_version(&self) -> &str {
-        self.plist["CFBundleVersion"].as_string().unwrap()
+    pub fn bundle_version(&self) -> Option<&str> {
+        info_string(&self.plist, "CFBundleVersion")
+    }
+
+    pub fn short_version_string(&self) -> Option<&str> {
+        info_string(&self.plist, "CFBundleShortVersionString")
     }
 
     pub fn bundle_localizations(&self) -> &[Value] {
@@ -85,11 +93,16 @@ impl Bundle {
         self.plist["CFBundleLocalizations"].as_array().unwrap()
     }
 
+    /// The language of the app's own resources, used as the last resort when
+    /// looking for a localized resource. Apps usually specify this as a
+    /// language name (e.g. `English`), but sometimes as a language code.
+    pub fn development_region(&self) -> &str {
+        info_string(&self.plist, "CFBundleDevelopmentRegion").unwrap_or("English")
+    }
+
     /// Canonical name for the bundle according to Info.plist
     pub fn canonical_bundle_name(&self) -> Option<&str> {
-        self.plist
-            .get("CFBundleName")
-            .map(|name| name.as_string().unwrap())
+        info_string(&self.plist, "CFBundleName")
     }
 
     /// Name for the bundle, either the canonical name or, if there isn't one,
@@ -98,14 +111,37 @@ impl Bundle {
         self.path.file_name().unwrap().strip_suffix(".app").unwrap()
     }
 
+    /// Name shown on the home screen. Falls back to the bundle name like
+    /// iPhone OS does.
     pub fn display_name(&self) -> &str {
-        self.plist["CFBundleDisplayName"].as_string().unwrap()
+        info_string(&self.plist, "CFBundleDisplayName")
+            .or_else(|| self.canonical_bundle_name())
+            .unwrap_or_else(|| self.bundle_name())
     }
 
     pub fn minimum_os_version(&self) -> Option<&str> {
+        info_string(&self.plist, "MinimumOSVersion")
+    }
+
+    /// Whether the status bar should be hidden when the app starts.
+    pub fn status_bar_hidden(&self) -> bool {
         self.plist
-            .get("MinimumOSVersion")
-            .map(|v| v.as_string().unwrap())
+            .get("UIStatusBarHidden")
+            .and_then(Value::as_boolean)
+            .unwrap_or(false)
+    }
+
+    /// Orientation the app wants the device to have when it starts.
+    pub fn initial_orientation(&self) -> DeviceOrientation {
+        // Note that UIKit's interface orientations are named after the side
+        // the home button is on, which is the opposite of the direction the
+        // device was rotated in.
+        match info_string(&self.plist, "UIInterfaceOrientation") {
+            Some("UIInterfaceOrientationLandscapeLeft") => DeviceOrientation::LandscapeRight,
+            Some("UIInterfaceOrientationLandscapeRight") => DeviceOrientation::LandscapeLeft,
+            // TODO: UIInterfaceOrientationPortraitUpsideDown
+            _ => DeviceOrientation::Portrait,
+        }
     }
 
     pub fn executable_path(&self) -> GuestPathBuf {
@@ -115,37 +151,52 @@ impl Bundle {
     }
 
     pub fn launch_image_path(&self) -> GuestPathBuf {
-        if let Some(base_name) = self.plist.get("UILaunchImageFile") {
-            self.path
-                .join(format!("{}.png", base_name.as_string().unwrap()))
+        if let Some(base_name) = info_string(&self.plist, "UILaunchImageFile") {
+            self.path.join(format!("{}.png", base_name))
         } else {
             self.path.join("Default.png") // not guaranteed to exist!
         }
     }
 
-    fn icon_path(&self) -> GuestPathBuf {
-        if let Some(filename) = self.plist.get("CFBundleIconFile") {
-            if filename
-                .as_string()
-                .unwrap()
-                .to_lowercase()
-                .ends_with(".png")
-            {
-                self.path.join(filename.as_string().unwrap())
+    /// Candidate icon paths, in order of preference. iPhone OS 3.2 introduced
+    /// `CFBundleIconFiles`, which lists icons for different screen sizes;
+    /// older apps only have `CFBundleIconFile`, or rely on the default name.
+    fn icon_paths(&self) -> Vec<GuestPathBuf> {
+        let mut filenames: Vec<&str> = Vec::new();
+        if let Some(files) = self
+            .plist
+            .get("CFBundleIconFiles")
+            .and_then(Value::as_array)
+        {
+            filenames.extend(files.iter().filter_map(Value::as_string));
+        }
+        filenames.extend(info_string(&self.plist, "CFBundleIconFile"));
+        filenames.push("Icon.png");
+
+        let mut filenames_with_extension: Vec<String> = Vec::new();
+        for filename in filenames {
+            let filename = if filename.to_lowercase().ends_with(".png") {
+                filename.to_string()
             } else {
-                let filename_with_extension = format!("{}.png", filename.as_string().unwrap());
-                self.path.join(filename_with_extension)
+                format!("{}.png", filename)
+            };
+            if !filenames_with_extension.contains(&filename) {
+                filenames_with_extension.push(filename);
             }
-        } else {
-            self.path.join("Icon.png")
         }
+        filenames_with_extension
+            .into_iter()
+            .map(|filename| self.path.join(filename))
+            .collect()
     }
 
     /// Load icon and round off its corners for display.
     pub fn load_icon(&self, fs: &Fs) -> Result<Image, String> {
-        let bytes = fs
-            .read(self.icon_path())
-            .map_err(|_| "Could not read icon file".to_string())?;
+        let bytes = self
+            .icon_paths()
+            .into_iter()
+            .find_map(|path| fs.read(path).ok())
+            .ok_or_else(|| "Could not read icon file".to_string())?;
         let mut image =
             Image::from_bytes(&bytes).map_err(|e| format!("Could not parse icon image: {}", e))?;
         // iPhone OS icons are 57px by 57px and the OS always applies a
@@ -166,3 +217,116 @@ impl Bundle {
         })
     }
 }
+
+fn parse_info_plist(bytes: &[u8]) -> Result<Dictionary, String> {
+    Value::from_reader(Cursor::new(bytes))
+        .map_err(|_| "Could not deserialize plist data".to_string())?
+        .into_dictionary()
+        .ok_or_else(|| "plist root value is not a dictionary".to_string())
+}
+
+/// Get a string value from `Info.plist`. Values of the wrong type are treated
+/// as absent.
+fn info_string<'a>(plist: &'a Dictionary, key: &str) -> Option<&'a str> {
+    plist.get(key).and_then(Value::as_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE_INFO_PLIST: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>CFBundleDevelopmentRegion</key>
+	<string>Japanese</string>
+	<key>CFBundleDisplayName</key>
+	<string>Fixture</string>
+	<key>CFBundleExecutable</key>
+	<string>FixtureApp</string>
+	<key>CFBundleIconFile</key>
+	<string>LegacyIcon</string>
+	<key>CFBundleIconFiles</key>
+	<array>
+		<string>Icon-57.png</string>
+		<string>Icon-114</string>
+	</array>
+	<key>CFBundleIdentifier</key>
+	<string>org.touchhle.fixture</string>
+	<key>CFBundleName</key>
+	<string>FixtureApp</string>
+	<key>CFBundleShortVersionString</key>
+	<string>1.2</string>
+	<key>CFBundleVersion</key>
+	<string>1.2.3</string>
+	<key>UIInterfaceOrientation</key>
+	<string>UIInterfaceOrientationLandscapeRight</string>
+	<key>UIStatusBarHidden</key>
+	<true/>
+</dict>
+</plist>
+"#;
+
+    fn bundle_from_plist(plist: &str) -> Bundle {
+        Bundle {
+            path: GuestPathBuf::from(String::from("/User/Applications/FS.app")),
+            plist: parse_info_plist(plist.as_bytes()).unwrap(),
+        }
+    }
+
+    #[test]
+    fn info_plist_keys() {
+        let bundle = bundle_from_plist(FIXTURE_INFO_PLIST);
+        assert_eq!(bundle.bundle_identifier(), "org.touchhle.fixture");
+        assert_eq!(bundle.display_name(), "Fixture");
+        assert_eq!(bundle.canonical_bundle_name(), Some("FixtureApp"));
+        assert_eq!(bundle.bundle_version(), Some("1.2.3"));
+        assert_eq!(bundle.short_version_string(), Some("1.2"));
+        assert_eq!(bundle.development_region(), "Japanese");
+        assert!(bundle.status_bar_hidden());
+        assert!(matches!(
+            bundle.initial_orientation(),
+            DeviceOrientation::LandscapeLeft
+        ));
+        let icon_paths: Vec<_> = bundle.icon_paths().into_iter().map(String::from).collect();
+        assert_eq!(
+            icon_paths,
+            [
+                "/User/Applications/FS.app/Icon-57.png",
+                "/User/Applications/FS.app/Icon-114.png",
+                "/User/Applications/FS.app/LegacyIcon.png",
+                "/User/Applications/FS.app/Icon.png",
+            ]
+        );
+        assert_eq!(
+            bundle.info_dictionary()["CFBundleExecutable"].as_string(),
+            Some("FixtureApp")
+        );
+    }
+
+    #[test]
+    fn info_plist_defaults() {
+        let bundle = bundle_from_plist(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+	<key>CFBundleIdentifier</key>
+	<string>org.touchhle.minimal</string>
+</dict>
+</plist>
+"#,
+        );
+        assert_eq!(bundle.display_name(), "FS");
+        assert_eq!(bundle.bundle_version(), None);
+        assert_eq!(bundle.short_version_string(), None);
+        assert_eq!(bundle.development_region(), "English");
+        assert!(!bundle.status_bar_hidden());
+        assert!(matches!(
+            bundle.initial_orientation(),
+            DeviceOrientation::Portrait
+        ));
+        let icon_paths: Vec<_> = bundle.icon_paths().into_iter().map(String::from).collect();
+        assert_eq!(icon_paths, ["/User/Applications/FS.app/Icon.png"]);
+    }
+}
@@ -171,6 +171,10 @@ impl Cpu {
             .map_or(0, |mem| mem.null_segment_size() / 0x1000)
             .try_into()
             .unwrap();
+        // The main thread's stack guard page already exists at this point.
+        let stack_guards: Vec<_> = direct_memory_access
+            .as_ref()
+            .map_or(Vec::new(), |mem| mem.stack_guards().collect());
         // Safety: the direct memory access pointer will be retained directly by
         // the dynarmic wrapper and indirectly by cached JIT code, so we must
         // ensure we only execute the CPU while holding a &mut on the Mem object
@@ -181,10 +185,14 @@ impl Cpu {
             });
         let dynarmic_wrapper =
             unsafe { touchHLE_DynarmicWrapper_new(direct_memory_access_ptr, null_page_count) };
-        Cpu {
+        let mut cpu = Cpu {
             dynarmic_wrapper,
             direct_memory_access_ptr,
+        };
+        for (base, size) in stack_guards {
+            cpu.trap_pages(base, size);
         }
+        cpu
     }
 
     pub fn regs(&self) -> &[u32; 16] {
@@ -272,6 +280,14 @@ impl Cpu {
         }
     }
 
+    /// Make the CPU always use [Mem]'s checked accessors for a page-aligned
+    /// range of memory, rather than direct memory access, so that accesses to
+    /// it can be caught (this is used for stack guard pages).
+    pub fn trap_pages(&mut self, base: VAddr, size: GuestUSize) {
+        assert!(base % 0x1000 == 0 && size % 0x1000 == 0);
+        unsafe { touchHLE_DynarmicWrapper_trap_pages(self.dynarmic_wrapper, base, size) }
+    }
+
     /// Start CPU execution.
     ///
     /// If `ticks` is [Some], it is used as an abstract time limit. The value
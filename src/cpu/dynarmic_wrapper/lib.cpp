@@ -139,6 +139,7 @@ class DynarmicWrapper {
   std::unique_ptr<Dynarmic::A32::Jit> cpu;
   std::array<std::uint8_t *, Dynarmic::A32::UserConfig::NUM_PAGE_TABLE_ENTRIES>
       page_table;
+  bool uses_page_table = false;
 
 public:
   DynarmicWrapper(void *direct_memory_access_ptr, size_t null_page_count) {
@@ -166,6 +167,7 @@ public:
       }
       user_config.page_table = &page_table;
       user_config.absolute_offset_page_table = true;
+      uses_page_table = true;
     }
     cpu = std::make_unique<Dynarmic::A32::Jit>(user_config);
     env.cpu = cpu.get();
@@ -181,6 +183,19 @@ public:
     cpu->InvalidateCacheRange(start, size);
   }
 
+  void trap_pages(VAddr start, std::uint32_t size) {
+    // Without direct memory access, every access already goes through a
+    // memory callback.
+    if (!uses_page_table) {
+      return;
+    }
+    size_t first_page = start >> Dynarmic::A32::UserConfig::PAGE_BITS;
+    size_t page_count = size >> Dynarmic::A32::UserConfig::PAGE_BITS;
+    for (size_t i = first_page; i < first_page + page_count; i++) {
+      page_table[i] = nullptr;
+    }
+  }
+
   void swap_context(void *context) {
     Dynarmic::A32::Context tmp = cpu->SaveContext();
     cpu->LoadContext(*(Dynarmic::A32::Context *)context);
@@ -254,6 +269,11 @@ void touchHLE_DynarmicWrapper_invalidate_cache_range(DynarmicWrapper *cpu,
   cpu->invalidate_cache_range(start, size);
 }
 
+void touchHLE_DynarmicWrapper_trap_pages(DynarmicWrapper *cpu, VAddr start,
+                                         std::uint32_t size) {
+  cpu->trap_pages(start, size);
+}
+
 std::int32_t touchHLE_DynarmicWrapper_run_or_step(DynarmicWrapper *cpu,
                                                   touchHLE_Mem *mem,
                                                   std::uint64_t *ticks) {
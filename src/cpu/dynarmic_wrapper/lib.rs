@@ -44,6 +44,11 @@ extern "C" {
         start: VAddr,
         size: u32,
     );
+    pub fn touchHLE_DynarmicWrapper_trap_pages(
+        cpu: *mut touchHLE_DynarmicWrapper,
+        start: VAddr,
+        size: u32,
+    );
     pub fn touchHLE_DynarmicWrapper_run_or_step(
         cpu: *mut touchHLE_DynarmicWrapper,
         mem: *mut touchHLE_Mem,
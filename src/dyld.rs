@@ -20,15 +20,18 @@
 //!
 //! See [crate::mach_o] for resources.
 
+mod call_trace;
 mod constant_lists;
 mod function_lists;
 
+pub use call_trace::CallTraceFilter;
+
 use crate::abi::{CallFromGuest, GuestFunction};
 use crate::cpu::Cpu;
 use crate::frameworks::foundation::ns_string;
 use crate::mach_o::{MachO, SectionType};
-use crate::mem::{ConstVoidPtr, GuestUSize, Mem, MutPtr, Ptr};
-use crate::objc::{nil, ObjC};
+use crate::mem::{ConstPtr, ConstVoidPtr, GuestUSize, Mem, MutPtr, Ptr};
+use crate::objc::{id, nil, ObjC};
 use crate::Environment;
 use std::collections::HashMap;
 
@@ -107,6 +110,11 @@ pub enum HostConstant {
     NSString(&'static str),
     NullPtr,
     Custom(fn(&mut Mem) -> ConstVoidPtr),
+    /// An object provided by host code, e.g. a singleton.
+    Object(fn(&mut Environment) -> id),
+    /// A global variable that host code also needs to access, e.g. `optind`.
+    /// The function returns its address, which must be the same every time.
+    Variable(fn(&mut Environment) -> ConstVoidPtr),
 }
 
 /// Type for lists of constants exported by host implementations of frameworks.
@@ -174,6 +182,8 @@ pub struct Dyld {
     thread_exit_routine: Option<GuestFunction>,
     constants_to_link_later: Vec<(MutPtr<ConstVoidPtr>, &'static HostConstant)>,
     non_lazy_host_functions: HashMap<&'static str, GuestFunction>,
+    /// Only present if call tracing is enabled.
+    call_tracer: Option<call_trace::CallTracer>,
 }
 
 impl Dyld {
@@ -197,6 +207,30 @@ impl Dyld {
             thread_exit_routine: None,
             constants_to_link_later: Vec::new(),
             non_lazy_host_functions: HashMap::new(),
+            call_tracer: None,
+        }
+    }
+
+    /// Enable tracing of calls to host functions whose symbols match the
+    /// filter. This must be done before any linking happens. See
+    /// [call_trace].
+    pub fn enable_call_tracing(&mut self, filter: CallTraceFilter) {
+        assert!(self.linked_host_functions.is_empty());
+        self.call_tracer = Some(call_trace::CallTracer::new(filter));
+    }
+
+    /// Print the most recent traced calls, if call tracing is enabled.
+    pub fn dump_call_trace(&self) {
+        if let Some(ref call_tracer) = self.call_tracer {
+            call_tracer.dump_recent_calls();
+        }
+    }
+
+    /// Wrap a host function for call tracing, if appropriate.
+    fn maybe_trace(&self, symbol: &'static str, f: HostFunction) -> HostFunction {
+        match self.call_tracer {
+            Some(ref call_tracer) => call_tracer.wrap(symbol, f),
+            None => f,
         }
     }
 
@@ -448,6 +482,12 @@ impl Dyld {
                     null_ptr_ptr.cast().cast_const()
                 }
                 HostConstant::Custom(f) => f(&mut env.mem),
+                HostConstant::Object(f) => {
+                    let object = f(env);
+                    let object_ptr = env.mem.alloc_and_write(object);
+                    object_ptr.cast().cast_const()
+                }
+                HostConstant::Variable(f) => f(env),
             };
             env.mem.write(symbol_ptr_ptr, symbol_ptr.cast());
         }
@@ -563,6 +603,8 @@ impl Dyld {
         }
 
         if let Some(&(symbol, f)) = search_lists(function_lists::FUNCTION_LISTS, symbol) {
+            let f = self.maybe_trace(symbol, f);
+
             // Allocate an SVC ID for this host function
             let idx: u32 = self.linked_host_functions.len().try_into().unwrap();
             let svc = idx + Self::SVC_LINKED_FUNCTIONS_BASE;
@@ -642,12 +684,38 @@ impl Dyld {
         Ok(function_ptr)
     }
 
+    /// If `addr` is a stub or trampoline that calls a linked host function,
+    /// return that function's name. Used for symbolicating backtraces.
+    pub fn host_function_name_at(&self, mem: &Mem, addr: u32) -> Option<&'static str> {
+        if let Some((&symbol, _)) = self
+            .non_lazy_host_functions
+            .iter()
+            .find(|(_, f)| f.addr_without_thumb_bit() == addr)
+        {
+            return Some(symbol);
+        }
+        if addr % 4 != 0 || addr < mem.null_segment_size() {
+            return None;
+        }
+        let instruction = mem.read(ConstPtr::<u32>::from_bits(addr));
+        if instruction & 0xff000000 != encode_a32_svc(0) {
+            return None;
+        }
+        let svc = instruction & 0x00ffffff;
+        let idx = svc.checked_sub(Self::SVC_LINKED_FUNCTIONS_BASE)?;
+        self.linked_host_functions
+            .get(idx as usize)
+            .map(|&(symbol, _)| symbol)
+    }
+
     pub fn create_guest_function(
         &mut self,
         mem: &mut Mem,
         symbol: &'static str,
         f: HostFunction,
     ) -> GuestFunction {
+        let f = self.maybe_trace(symbol, f);
+
         // Allocate an SVC ID for this host function
         let idx: u32 = self.linked_host_functions.len().try_into().unwrap();
         let svc = idx + Self::SVC_LINKED_FUNCTIONS_BASE;
@@ -0,0 +1,333 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Opt-in tracing of calls from guest code to host functions, for debugging.
+//!
+//! When the `--trace-calls=` option is used, [super::Dyld] wraps every host
+//! function whose symbol matches the filter in a [TracedFunction] at link time,
+//! so functions that aren't traced (and everything when tracing is disabled)
+//! are called exactly as before. The wrapper logs the raw argument registers
+//! and return value registers of each call, since it has no knowledge of the
+//! real types. Logging is rate-limited per symbol, but every call goes into a
+//! small ring buffer that is dumped if touchHLE panics.
+
+use super::HostFunction;
+use crate::abi::CallFromGuest;
+use crate::environment::ThreadId;
+use crate::Environment;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+/// Set of glob patterns for symbols to trace, parsed from the `--trace-calls=`
+/// option.
+#[derive(Clone, Debug)]
+pub struct CallTraceFilter {
+    patterns: Vec<String>,
+}
+
+impl CallTraceFilter {
+    /// Parse a comma-separated list of glob patterns (`*` matches any sequence
+    /// of characters, `?` matches one), or `all` to match every symbol.
+    pub fn parse(value: &str) -> Result<CallTraceFilter, String> {
+        let patterns: Vec<String> = value
+            .split(',')
+            .map(str::trim)
+            .filter(|pattern| !pattern.is_empty())
+            .map(|pattern| match pattern {
+                "all" => "*".to_string(),
+                _ => pattern.to_string(),
+            })
+            .collect();
+        if patterns.is_empty() {
+            return Err("--trace-calls= requires at least one pattern".to_string());
+        }
+        Ok(CallTraceFilter { patterns })
+    }
+
+    /// Check whether a symbol should be traced. Patterns can be written either
+    /// with or without the leading underscore of C name mangling.
+    pub fn matches(&self, symbol: &str) -> bool {
+        let unmangled = symbol.strip_prefix('_').unwrap_or(symbol);
+        self.patterns
+            .iter()
+            .any(|pattern| glob_match(pattern, unmangled) || glob_match(pattern, symbol))
+    }
+}
+
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.as_bytes();
+    let text = text.as_bytes();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` seen and the text position it was tried at, for
+    // backtracking.
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => {
+                let Some((star_p, star_t)) = star else {
+                    return false;
+                };
+                p = star_p + 1;
+                t = star_t + 1;
+                star = Some((star_p, star_t + 1));
+            }
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// Record of a single traced call.
+#[derive(Clone, Debug)]
+struct TracedCall {
+    sequence_number: u64,
+    thread: ThreadId,
+    symbol: &'static str,
+    /// Contents of r0-r3 at the time of the call.
+    args: [u32; 4],
+    /// Contents of r0-r1 after the call, if it has returned.
+    retval: Option<[u32; 2]>,
+}
+
+impl fmt::Display for TracedCall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [r0, r1, r2, r3] = self.args;
+        write!(
+            f,
+            "#{} [thread {}] {}(r0={:#x}, r1={:#x}, r2={:#x}, r3={:#x})",
+            self.sequence_number, self.thread, self.symbol, r0, r1, r2, r3
+        )?;
+        match self.retval {
+            Some([r0, r1]) => write!(f, " => r0={:#x}, r1={:#x}", r0, r1),
+            None => write!(f, " => (did not return)"),
+        }
+    }
+}
+
+/// State of call tracing, owned by [super::Dyld].
+pub struct CallTracer {
+    filter: CallTraceFilter,
+    next_sequence_number: u64,
+    call_counts: HashMap<&'static str, u64>,
+    recent_calls: VecDeque<TracedCall>,
+}
+
+/// Returned by [CallTracer::begin_call], to be passed to
+/// [CallTracer::end_call].
+pub struct CallInProgress {
+    record: TracedCall,
+    call_count: u64,
+}
+
+impl CallTracer {
+    /// Every call to a symbol is logged until it has been called this many
+    /// times.
+    const LOG_ALL_LIMIT: u64 = 100;
+    /// After [Self::LOG_ALL_LIMIT] is reached, only one in this many calls to a
+    /// symbol is logged.
+    const SAMPLE_INTERVAL: u64 = 1000;
+    /// Number of calls kept for [Self::dump_recent_calls].
+    const RECENT_CALLS_CAPACITY: usize = 64;
+
+    pub fn new(filter: CallTraceFilter) -> CallTracer {
+        CallTracer {
+            filter,
+            next_sequence_number: 0,
+            call_counts: HashMap::new(),
+            recent_calls: VecDeque::with_capacity(Self::RECENT_CALLS_CAPACITY),
+        }
+    }
+
+    /// Wrap a host function in a [TracedFunction] if its symbol matches the
+    /// filter, otherwise return it unchanged.
+    pub fn wrap(&self, symbol: &'static str, f: HostFunction) -> HostFunction {
+        if !self.filter.matches(symbol) {
+            return f;
+        }
+        // The linker only creates a small number of these, and never frees
+        // them, so leaking is fine.
+        Box::leak(Box::new(TracedFunction { symbol, inner: f }))
+    }
+
+    fn begin_call(
+        &mut self,
+        symbol: &'static str,
+        thread: ThreadId,
+        args: [u32; 4],
+    ) -> CallInProgress {
+        let sequence_number = self.next_sequence_number;
+        self.next_sequence_number += 1;
+        let call_count = self.call_counts.entry(symbol).or_default();
+        *call_count += 1;
+        let call_count = *call_count;
+
+        let record = TracedCall {
+            sequence_number,
+            thread,
+            symbol,
+            args,
+            retval: None,
+        };
+        if self.recent_calls.len() == Self::RECENT_CALLS_CAPACITY {
+            self.recent_calls.pop_front();
+        }
+        self.recent_calls.push_back(record.clone());
+
+        CallInProgress { record, call_count }
+    }
+
+    /// Record the return value of a call. Returns the line to log, if this call
+    /// should be logged.
+    fn end_call(&mut self, call: CallInProgress, retval: [u32; 2]) -> Option<String> {
+        let CallInProgress {
+            mut record,
+            call_count,
+        } = call;
+        record.retval = Some(retval);
+        // Calls can be nested, so this isn't necessarily the last entry. It
+        // might also have been pushed out of the buffer already.
+        if let Some(buffered) = self
+            .recent_calls
+            .iter_mut()
+            .rev()
+            .find(|buffered| buffered.sequence_number == record.sequence_number)
+        {
+            buffered.retval = Some(retval);
+        }
+
+        if call_count < Self::LOG_ALL_LIMIT {
+            Some(record.to_string())
+        } else if call_count == Self::LOG_ALL_LIMIT {
+            Some(format!(
+                "{} (further calls to {} will only be logged once every {} calls)",
+                record,
+                record.symbol,
+                Self::SAMPLE_INTERVAL
+            ))
+        } else if call_count % Self::SAMPLE_INTERVAL == 0 {
+            Some(format!("{} (call {})", record, call_count))
+        } else {
+            None
+        }
+    }
+
+    /// Print the most recent traced calls, regardless of whether they were
+    /// logged. Used when touchHLE panics.
+    pub fn dump_recent_calls(&self) {
+        if self.recent_calls.is_empty() {
+            return;
+        }
+        echo!("Most recent traced calls (oldest first):");
+        for record in &self.recent_calls {
+            echo!("    {}", record);
+        }
+    }
+}
+
+/// Host function wrapper created by [CallTracer::wrap].
+pub struct TracedFunction {
+    symbol: &'static str,
+    inner: HostFunction,
+}
+
+impl CallFromGuest for TracedFunction {
+    fn call_from_guest(&self, env: &mut Environment) {
+        let regs = env.cpu.regs();
+        let args = [regs[0], regs[1], regs[2], regs[3]];
+        let call = env.dyld.call_tracer.as_mut().unwrap().begin_call(
+            self.symbol,
+            env.current_thread,
+            args,
+        );
+
+        self.inner.call_from_guest(env);
+
+        let regs = env.cpu.regs();
+        let retval = [regs[0], regs[1]];
+        if let Some(line) = env
+            .dyld
+            .call_tracer
+            .as_mut()
+            .unwrap()
+            .end_call(call, retval)
+        {
+            log!("Traced call: {}", line);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter_matching() {
+        let filter = CallTraceFilter::parse("*printf*, CFString*,_fopen").unwrap();
+        assert!(filter.matches("_printf"));
+        assert!(filter.matches("_vsnprintf"));
+        assert!(filter.matches("_CFStringGetLength"));
+        assert!(filter.matches("_fopen"));
+        assert!(!filter.matches("_fopen2"));
+        assert!(!filter.matches("_CFArrayGetCount"));
+
+        let filter = CallTraceFilter::parse("all").unwrap();
+        assert!(filter.matches("_objc_msgSend"));
+
+        let filter = CallTraceFilter::parse("str?en").unwrap();
+        assert!(filter.matches("_strlen"));
+        assert!(!filter.matches("_strnlen"));
+
+        assert!(CallTraceFilter::parse(" , ").is_err());
+    }
+
+    #[test]
+    fn traced_call_lines() {
+        let mut tracer = CallTracer::new(CallTraceFilter::parse("strlen").unwrap());
+        let call = tracer.begin_call("_strlen", 2, [0x1000, 1, 2, 3]);
+        // Nested call, as if strlen called back into the guest.
+        let nested = tracer.begin_call("_strlen", 2, [0x2000, 0, 0, 0]);
+        assert_eq!(
+            tracer.end_call(nested, [4, 0]).unwrap(),
+            "#1 [thread 2] _strlen(r0=0x2000, r1=0x0, r2=0x0, r3=0x0) => r0=0x4, r1=0x0"
+        );
+        assert_eq!(
+            tracer.end_call(call, [0x11, 0xffffffff]).unwrap(),
+            "#0 [thread 2] _strlen(r0=0x1000, r1=0x1, r2=0x2, r3=0x3) => r0=0x11, r1=0xffffffff"
+        );
+    }
+
+    #[test]
+    fn rate_limiting_and_ring_buffer() {
+        let mut tracer = CallTracer::new(CallTraceFilter::parse("*").unwrap());
+        let mut logged = 0;
+        for i in 0..5000 {
+            let call = tracer.begin_call("_rand", 0, [i, 0, 0, 0]);
+            if let Some(line) = tracer.end_call(call, [0, 0]) {
+                if i == 99 {
+                    assert!(line.contains("further calls to _rand"));
+                }
+                logged += 1;
+            }
+        }
+        // 100 calls, then the 1000th, 2000th, 3000th, 4000th and 5000th.
+        assert_eq!(logged, 105);
+
+        // Calls that were never finished still appear in the buffer.
+        let _unfinished = tracer.begin_call("_abort", 0, [0, 0, 0, 0]);
+        assert_eq!(tracer.recent_calls.len(), CallTracer::RECENT_CALLS_CAPACITY);
+        let last = tracer.recent_calls.back().unwrap().to_string();
+        assert!(last.starts_with("#5000 [thread 0] _abort("));
+        assert!(last.ends_with("=> (did not return)"));
+        let first = tracer.recent_calls.front().unwrap();
+        assert_eq!(first.sequence_number, 5000 - 63);
+    }
+}
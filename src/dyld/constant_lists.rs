@@ -6,21 +6,30 @@
 //! Separate module just for the constant lists, since this will probably be a
 //! very long and frequently-updated list.
 
-use crate::frameworks::{core_foundation, core_graphics, foundation, media_player, opengles};
+use crate::frameworks::{
+    core_foundation, core_graphics, foundation, media_player, opengles, uikit,
+};
 use crate::libc;
 
 /// All the lists of constants that the linker should search through.
 pub const CONSTANT_LISTS: &[super::ConstantExports] = &[
     libc::ctype::CONSTANTS,
+    libc::getopt::CONSTANTS,
+    libc::mach_vm::CONSTANTS,
     libc::stdio::CONSTANTS,
     core_foundation::cf_allocator::CONSTANTS,
+    core_foundation::cf_null::CONSTANTS,
     core_foundation::cf_run_loop::CONSTANTS,
     core_graphics::cg_affine_transform::CONSTANTS,
     core_graphics::cg_color_space::CONSTANTS,
     core_graphics::cg_geometry::CONSTANTS,
+    foundation::ns_error::CONSTANTS,
     foundation::ns_exception::CONSTANTS,
+    foundation::ns_file_manager::CONSTANTS,
+    foundation::ns_http_cookie::CONSTANTS,
     foundation::ns_locale::CONSTANTS,
     foundation::ns_run_loop::CONSTANTS,
     media_player::movie_player::CONSTANTS,
     opengles::eagl::CONSTANTS,
+    uikit::ui_application::CONSTANTS,
 ];
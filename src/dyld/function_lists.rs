@@ -13,36 +13,53 @@ use crate::libc;
 
 /// All the lists of functions that the linker should search through.
 pub const FUNCTION_LISTS: &[super::FunctionExports] = &[
+    libc::assert::FUNCTIONS,
     libc::ctype::FUNCTIONS,
     libc::cxxabi::FUNCTIONS,
     libc::dirent::FUNCTIONS,
     libc::dlfcn::FUNCTIONS,
+    libc::err::FUNCTIONS,
     libc::errno::FUNCTIONS,
+    libc::ftw::FUNCTIONS,
+    libc::getopt::FUNCTIONS,
+    libc::glob::FUNCTIONS,
     libc::ifaddrs::FUNCTIONS,
     libc::keymgr::FUNCTIONS,
+    libc::libgen::FUNCTIONS,
     libc::mach_thread_info::FUNCTIONS,
     libc::mach_time::FUNCTIONS,
+    libc::mach_vm::FUNCTIONS,
     libc::math::FUNCTIONS,
     libc::mmap::FUNCTIONS,
+    libc::osatomic::FUNCTIONS,
     libc::net::if_::FUNCTIONS,
     libc::posix_io::FUNCTIONS,
+    libc::posix_io::pipe::FUNCTIONS,
     libc::posix_io::stat::FUNCTIONS,
+    libc::process::FUNCTIONS,
     libc::pthread::cond::FUNCTIONS,
     libc::pthread::key::FUNCTIONS,
     libc::pthread::mutex::FUNCTIONS,
     libc::pthread::once::FUNCTIONS,
     libc::pthread::thread::FUNCTIONS,
+    libc::pwd::FUNCTIONS,
+    libc::regex::FUNCTIONS,
+    libc::resource::FUNCTIONS,
     libc::semaphore::FUNCTIONS,
     libc::setjmp::FUNCTIONS,
+    libc::signal::FUNCTIONS,
+    libc::socket::FUNCTIONS,
     libc::stdio::FUNCTIONS,
     libc::stdio::printf::FUNCTIONS,
     libc::stdlib::FUNCTIONS,
     libc::stdlib::qsort::FUNCTIONS,
     libc::string::FUNCTIONS,
     libc::sysctl::FUNCTIONS,
+    libc::syslog::FUNCTIONS,
     libc::time::FUNCTIONS,
     libc::unistd::FUNCTIONS,
     libc::wchar::FUNCTIONS,
+    libc::zlib::FUNCTIONS,
     crate::objc::FUNCTIONS,
     audio_toolbox::audio_file::FUNCTIONS,
     audio_toolbox::audio_queue::FUNCTIONS,
@@ -52,8 +69,12 @@ pub const FUNCTION_LISTS: &[super::FunctionExports] = &[
     core_foundation::cf_bundle::FUNCTIONS,
     core_foundation::cf_data::FUNCTIONS,
     core_foundation::cf_locale::FUNCTIONS,
+    core_foundation::cf_notification_center::FUNCTIONS,
+    core_foundation::cf_null::FUNCTIONS,
     core_foundation::cf_run_loop::FUNCTIONS,
     core_foundation::cf_run_loop_timer::FUNCTIONS,
+    core_foundation::cf_socket::FUNCTIONS,
+    core_foundation::cf_stream::FUNCTIONS,
     core_foundation::cf_string::FUNCTIONS,
     core_foundation::cf_type::FUNCTIONS,
     core_foundation::cf_url::FUNCTIONS,
@@ -66,9 +87,10 @@ pub const FUNCTION_LISTS: &[super::FunctionExports] = &[
     core_graphics::cg_geometry::FUNCTIONS,
     core_graphics::cg_image::FUNCTIONS,
     dnssd::FUNCTIONS,
-    foundation::ns_file_manager::FUNCTIONS,
+    foundation::ns_exception::FUNCTIONS,
     foundation::ns_log::FUNCTIONS,
     foundation::ns_objc_runtime::FUNCTIONS,
+    foundation::ns_paths::FUNCTIONS,
     openal::FUNCTIONS,
     opengles::FUNCTIONS,
     uikit::ui_application::FUNCTIONS,
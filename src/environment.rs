@@ -55,6 +55,13 @@ pub struct Thread {
     /// frame" of the thread is a host function, not whether there are any host
     /// functions at all.
     in_host_function: bool,
+    /// How many host-to-guest calls ([Environment::run_call]) are in progress
+    /// on this thread.
+    guest_call_depth: u32,
+    /// A `longjmp()` to do once the host function the guest called at the
+    /// given [Thread::guest_call_depth] returns, see
+    /// [Environment::schedule_longjmp].
+    pending_longjmp: Option<(u32, MutVoidPtr)>,
     /// Context object containing the CPU state for this thread.
     ///
     /// There should always be `(threads.len() - 1)` contexts in existence.
@@ -142,12 +149,14 @@ impl Environment {
     /// when allocating a second [mem::Mem] instance.
     pub fn new(
         bundle: bundle::Bundle,
-        fs: fs::Fs,
-        options: options::Options,
+        mut fs: fs::Fs,
+        mut options: options::Options,
         env_for_salvage: Option<Environment>,
     ) -> Result<Environment, String> {
         let startup_time = Instant::now();
 
+        fs.set_case_insensitive(options.case_insensitive_paths);
+
         // Extract things to salvage from the old environment, and then drop it.
         // This needs to be done before creating a new window, because SDL2 only
         // allows one window at once.
@@ -159,6 +168,10 @@ impl Environment {
             None
         };
 
+        options
+            .initial_orientation
+            .get_or_insert_with(|| bundle.initial_orientation());
+
         let window = if options.headless {
             None
         } else {
@@ -193,9 +206,9 @@ impl Environment {
         };
 
         let mut mem = if let Some(mem) = mem_for_salvage {
-            mem::Mem::refurbish(mem)
+            mem::Mem::refurbish(mem, options.main_thread_stack_size)
         } else {
-            mem::Mem::new()
+            mem::Mem::new(options.main_thread_stack_size)
         };
 
         let executable = mach_o::MachO::load_from_file(bundle.executable_path(), &fs, &mut mem)
@@ -239,8 +252,14 @@ impl Environment {
         bins.insert(0, executable);
 
         let mut objc = objc::ObjC::new();
+        if let Some(mode) = options.zombie_objects {
+            objc.enable_zombies(mode, options.zombie_limit);
+        }
 
         let mut dyld = dyld::Dyld::new();
+        if let Some(filter) = options.trace_calls.clone() {
+            dyld.enable_call_tracing(filter);
+        }
         dyld.do_initial_linking(&bins, &mut mem, &mut objc);
 
         let cpu = cpu::Cpu::new(match options.direct_memory_access {
@@ -254,8 +273,10 @@ impl Environment {
             return_value: None,
             in_start_routine: false, // main thread never terminates
             in_host_function: false,
+            guest_call_depth: 0,
+            pending_longjmp: None,
             context: None,
-            stack: Some(mem::Mem::MAIN_THREAD_STACK_LOW_END..=0u32.wrapping_sub(1)),
+            stack: Some(mem.main_thread_stack()),
         };
 
         let mut env = Environment {
@@ -367,11 +388,14 @@ impl Environment {
             &options,
         ));
 
-        let mut mem = mem::Mem::new();
+        let mut mem = mem::Mem::new(options.main_thread_stack_size);
 
         let bins = Vec::new();
 
         let mut objc = objc::ObjC::new();
+        if let Some(mode) = options.zombie_objects {
+            objc.enable_zombies(mode, options.zombie_limit);
+        }
 
         let mut dyld = dyld::Dyld::new();
         dyld.do_initial_linking_with_no_bins(&mut mem, &mut objc);
@@ -387,8 +411,10 @@ impl Environment {
             return_value: None,
             in_start_routine: false, // main thread never terminates
             in_host_function: false,
+            guest_call_depth: 0,
+            pending_longjmp: None,
             context: None,
-            stack: Some(mem::Mem::MAIN_THREAD_STACK_LOW_END..=0u32.wrapping_sub(1)),
+            stack: Some(mem.main_thread_stack()),
         };
 
         let mut env = Environment {
@@ -464,64 +490,23 @@ impl Environment {
     }
 
     fn stack_trace(&self) {
-        if self.current_thread == 0 {
-            echo!("Attempting to produce stack trace for main thread:");
-        } else {
-            echo!(
-                "Attempting to produce stack trace for thread {}:",
-                self.current_thread
-            );
-        }
-        let stack_range = self.threads[self.current_thread].stack.clone().unwrap();
-        echo!(
-            " 0. {:#x} (PC)",
-            self.cpu.pc_with_thumb_bit().addr_with_thumb_bit()
-        );
-        let regs = self.cpu.regs();
-        let mut lr = regs[cpu::Cpu::LR];
-        let return_to_host_routine_addr = self.dyld.return_to_host_routine().addr_with_thumb_bit();
-        let thread_exit_routine_addr = self.dyld.thread_exit_routine().addr_with_thumb_bit();
-        if lr == return_to_host_routine_addr {
-            echo!(" 1. [host function] (LR)");
-        } else if lr == thread_exit_routine_addr {
-            echo!(" 1. [thread exit] (LR)");
-            return;
-        } else {
-            echo!(" 1. {:#x} (LR)", lr);
-        }
-        let mut i = 2;
-        let mut fp: mem::ConstPtr<u8> = mem::Ptr::from_bits(regs[abi::FRAME_POINTER]);
-        loop {
-            if !stack_range.contains(&fp.to_bits()) {
-                echo!("Next FP ({:?}) is outside the stack.", fp);
-                break;
-            }
-            lr = self.mem.read((fp + 4).cast());
-            fp = self.mem.read(fp.cast());
-            if lr == return_to_host_routine_addr {
-                echo!("{:2}. [host function]", i);
-            } else if lr == thread_exit_routine_addr {
-                echo!("{:2}. [thread exit]", i);
-                return;
-            } else {
-                echo!("{:2}. {:#x}", i, lr);
-            }
-            i += 1;
-        }
+        crate::backtrace::print_guest_backtrace(self);
     }
 
     /// Create a new thread and return its ID. The `start_routine` and
     /// `user_data` arguments have the same meaning as the last two arguments to
-    /// `pthread_create`.
+    /// `pthread_create`. The stack size is rounded up to a whole page.
     pub fn new_thread(
         &mut self,
         start_routine: abi::GuestFunction,
         user_data: mem::MutVoidPtr,
+        stack_size: mem::GuestUSize,
     ) -> ThreadId {
-        let stack_size = mem::Mem::SECONDARY_THREAD_STACK_SIZE;
-        let stack_alloc = self.mem.alloc(stack_size);
-        let stack_high_addr = stack_alloc.to_bits() + stack_size;
-        assert!(stack_high_addr % 4 == 0);
+        let new_thread_id = self.threads.len();
+        let stack = self.mem.alloc_thread_stack(stack_size, new_thread_id);
+        let stack_high_addr = stack.end() + 1;
+        let guard_base = stack.start() - mem::Mem::STACK_GUARD_SIZE;
+        self.cpu.trap_pages(guard_base, mem::Mem::STACK_GUARD_SIZE);
 
         self.threads.push(Thread {
             active: true,
@@ -529,12 +514,13 @@ impl Environment {
             return_value: None,
             in_start_routine: true,
             in_host_function: false,
+            guest_call_depth: 0,
+            pending_longjmp: None,
             context: Some(cpu::CpuContext::new()),
-            stack: Some(stack_alloc.to_bits()..=(stack_high_addr - 1)),
+            stack: Some(stack.clone()),
         });
-        let new_thread_id = self.threads.len() - 1;
 
-        log_dbg!("Created new thread {} with stack {:#x}–{:#x}, will execute function {:?} with data {:?}", new_thread_id, stack_alloc.to_bits(), (stack_high_addr - 1), start_routine, user_data);
+        log_dbg!("Created new thread {} with stack {:#x}–{:#x}, will execute function {:?} with data {:?}", new_thread_id, stack.start(), stack.end(), start_routine, user_data);
 
         let old_thread = self.current_thread;
 
@@ -709,6 +695,7 @@ impl Environment {
             echo!("Register state immediately after panic:");
             self.cpu.dump_regs();
             self.stack_trace();
+            self.dyld.dump_call_trace();
             std::panic::resume_unwind(e);
         }
     }
@@ -722,11 +709,33 @@ impl Environment {
         let was_in_host_function = self.threads[self.current_thread].in_host_function;
         let old_thread = self.current_thread;
         self.threads[self.current_thread].in_host_function = false;
+        self.threads[self.current_thread].guest_call_depth += 1;
         self.run_inner(false);
         assert!(self.current_thread == old_thread);
+        self.threads[self.current_thread].guest_call_depth -= 1;
         self.threads[self.current_thread].in_host_function = was_in_host_function;
     }
 
+    /// Get how many host-to-guest calls are in progress on the current thread.
+    /// Guest code called directly by the app's main function or a thread's
+    /// start routine is at depth 0.
+    pub fn guest_call_depth(&self) -> u32 {
+        self.threads[self.current_thread].guest_call_depth
+    }
+
+    /// Make the current thread `longjmp()` to `jmp_buf` with the value 1 once
+    /// the host function that the guest called returns.
+    ///
+    /// This lets host code unwind the guest stack without having to unwind its
+    /// own: the host code finishes normally, and only then does the guest
+    /// jump. This is only correct if the `setjmp()` was done at the current
+    /// [guest_call_depth](Self::guest_call_depth), since the host functions
+    /// that called into guest code at lower depths can't be unwound.
+    pub fn schedule_longjmp(&mut self, jmp_buf: MutVoidPtr) {
+        let thread = &mut self.threads[self.current_thread];
+        thread.pending_longjmp = Some((thread.guest_call_depth, jmp_buf));
+    }
+
     pub fn switch_thread(&mut self, new_thread: ThreadId) {
         assert!(new_thread != self.current_thread);
 
@@ -763,10 +772,28 @@ impl Environment {
             self.cpu.regs_mut()[cpu::Cpu::PC] -= instruction_len;
         }
 
+        let stack_overflow = self.mem.take_stack_guard_hit();
+
         if self.gdb_server.is_none() {
+            if let Some(thread) = stack_overflow {
+                // The app's signal handler isn't called, as it would have to
+                // run on the stack that just overflowed.
+                panic!(
+                    "Stack overflow on thread {} (guest pc {:#x})",
+                    thread,
+                    self.cpu.regs()[cpu::Cpu::PC]
+                );
+            }
+            if matches!(error, cpu::CpuError::MemoryError) {
+                // Give the app's crash reporter (if any) a chance to run.
+                libc::signal::handle_memory_error(self);
+            }
             panic!("Error during CPU execution: {:?}", error);
         }
 
+        if let Some(thread) = stack_overflow {
+            echo!("Stack overflow on thread {}.", thread);
+        }
         echo!("Debuggable error during CPU execution: {:?}.", error);
         self.enter_debugger(Some(error))
     }
@@ -827,6 +854,7 @@ impl Environment {
                             let stack: mem::MutVoidPtr = mem::Ptr::from_bits(*stack.start());
                             log_dbg!("Freeing thread {} stack {:?}", self.current_thread, stack);
                             self.mem.free(stack);
+                            self.mem.remove_stack_guard(self.current_thread);
                             ThreadNextAction::Yield
                         }
                     }
@@ -873,6 +901,13 @@ impl Environment {
                             f.call_from_guest(self);
                             self.threads[self.current_thread].in_host_function =
                                 was_in_host_function;
+                            let thread = &mut self.threads[self.current_thread];
+                            if let Some((depth, jmp_buf)) = thread.pending_longjmp {
+                                if depth == thread.guest_call_depth {
+                                    thread.pending_longjmp = None;
+                                    crate::libc::setjmp::restore_jmp_buf(self, jmp_buf, 1);
+                                }
+                            }
                             // Host function might have put the thread to sleep.
                             if let ThreadBlock::NotBlocked =
                                 self.threads[self.current_thread].blocked_by
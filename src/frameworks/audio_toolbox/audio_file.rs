@@ -102,6 +102,58 @@ pub fn AudioFileOpenURL(
     0 // success
 }
 
+/// Get the format of an audio file as an [AudioStreamBasicDescription], as for
+/// `kAudioFilePropertyDataFormat`.
+pub fn stream_description(audio_file: &audio::AudioFile) -> AudioStreamBasicDescription {
+    let audio::AudioDescription {
+        sample_rate,
+        format,
+        bytes_per_packet,
+        frames_per_packet,
+        channels_per_frame,
+        bits_per_channel,
+    } = audio_file.audio_description();
+
+    match format {
+        audio::AudioFormat::LinearPcm {
+            is_float,
+            is_little_endian,
+        } => {
+            let is_packed = (bits_per_channel * channels_per_frame * frames_per_packet)
+                == (bytes_per_packet * 8);
+            let format_flags = (u32::from(is_float) * kAudioFormatFlagIsFloat)
+                | (u32::from((!is_float) && matches!(bits_per_channel, 16 | 24))
+                    * kAudioFormatFlagIsSignedInteger)
+                | (u32::from(is_packed) * kAudioFormatFlagIsPacked)
+                | (u32::from(!is_little_endian) * kAudioFormatFlagIsBigEndian);
+            AudioStreamBasicDescription {
+                sample_rate,
+                format_id: kAudioFormatLinearPCM,
+                format_flags,
+                bytes_per_packet,
+                frames_per_packet,
+                bytes_per_frame: bytes_per_packet / frames_per_packet,
+                channels_per_frame,
+                bits_per_channel,
+                _reserved: 0,
+            }
+        }
+        audio::AudioFormat::AppleIma4 => {
+            AudioStreamBasicDescription {
+                sample_rate,
+                format_id: kAudioFormatAppleIMA4,
+                format_flags: 0,
+                bytes_per_packet,
+                frames_per_packet,
+                bytes_per_frame: 0, // compressed
+                channels_per_frame,
+                bits_per_channel,
+                _reserved: 0,
+            }
+        }
+    }
+}
+
 fn property_size(property_id: AudioFilePropertyID) -> GuestUSize {
     match property_id {
         kAudioFilePropertyDataFormat => guest_size_of::<AudioStreamBasicDescription>(),
@@ -166,53 +218,7 @@ pub fn AudioFileGetProperty(
 
     match in_property_id {
         kAudioFilePropertyDataFormat => {
-            let audio::AudioDescription {
-                sample_rate,
-                format,
-                bytes_per_packet,
-                frames_per_packet,
-                channels_per_frame,
-                bits_per_channel,
-            } = host_object.audio_file.audio_description();
-
-            let desc: AudioStreamBasicDescription = match format {
-                audio::AudioFormat::LinearPcm {
-                    is_float,
-                    is_little_endian,
-                } => {
-                    let is_packed = (bits_per_channel * channels_per_frame * frames_per_packet)
-                        == (bytes_per_packet * 8);
-                    let format_flags = (u32::from(is_float) * kAudioFormatFlagIsFloat)
-                        | (u32::from((!is_float) && matches!(bits_per_channel, 16 | 24))
-                            * kAudioFormatFlagIsSignedInteger)
-                        | (u32::from(is_packed) * kAudioFormatFlagIsPacked)
-                        | (u32::from(!is_little_endian) * kAudioFormatFlagIsBigEndian);
-                    AudioStreamBasicDescription {
-                        sample_rate,
-                        format_id: kAudioFormatLinearPCM,
-                        format_flags,
-                        bytes_per_packet,
-                        frames_per_packet,
-                        bytes_per_frame: bytes_per_packet / frames_per_packet,
-                        channels_per_frame,
-                        bits_per_channel,
-                        _reserved: 0,
-                    }
-                }
-                audio::AudioFormat::AppleIma4 => {
-                    AudioStreamBasicDescription {
-                        sample_rate,
-                        format_id: kAudioFormatAppleIMA4,
-                        format_flags: 0,
-                        bytes_per_packet,
-                        frames_per_packet,
-                        bytes_per_frame: 0, // compressed
-                        channels_per_frame,
-                        bits_per_channel,
-                        _reserved: 0,
-                    }
-                }
-            };
+            let desc = stream_description(&host_object.audio_file);
             env.mem.write(out_property_data.cast(), desc);
         }
         kAudioFilePropertyAudioDataByteCount => {
@@ -63,8 +63,14 @@ impl State {
     }
 }
 
+/// Make touchHLE's internal OpenAL context current, for other parts of Audio
+/// Toolbox that play audio. See [ContextManager].
+pub(super) fn make_al_context_current(env: &mut Environment) -> ContextManager {
+    State::get(&mut env.framework_state).make_al_context_current()
+}
+
 #[must_use]
-struct ContextManager(*mut ALCcontext);
+pub(super) struct ContextManager(*mut ALCcontext);
 impl ContextManager {
     pub fn make_active(new_context: *mut ALCcontext) -> ContextManager {
         let old_context = unsafe { al::alcGetCurrentContext() };
@@ -429,7 +435,7 @@ fn AudioQueueGetProperty(
 
 /// Check if the format of an audio queue is one we currently support.
 /// If not, we should skip trying to play it rather than crash.
-fn is_supported_audio_format(format: &AudioStreamBasicDescription) -> bool {
+pub(super) fn is_supported_audio_format(format: &AudioStreamBasicDescription) -> bool {
     let &AudioStreamBasicDescription {
         format_id,
         format_flags,
@@ -459,7 +465,15 @@ fn decode_buffer(
     buffer: &AudioQueueBuffer,
 ) -> (ALenum, ALsizei, Vec<u8>) {
     let data_slice = mem.bytes_at(buffer.audio_data.cast(), buffer.audio_data_byte_size);
+    decode_audio_data(format, data_slice)
+}
 
+/// Decode audio data in a supported format (see [is_supported_audio_format])
+/// to raw PCM suitable for an OpenAL buffer.
+pub(super) fn decode_audio_data(
+    format: &AudioStreamBasicDescription,
+    data_slice: &[u8],
+) -> (ALenum, ALsizei, Vec<u8>) {
     assert!(is_supported_audio_format(format));
 
     match format.format_id {
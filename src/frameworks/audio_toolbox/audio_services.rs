@@ -4,20 +4,100 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 //! `AudioServices.h` (Audio Services)
+//!
+//! System sounds are short sound files loaded into memory in their entirety,
+//! which are played with OpenAL Soft like [super::audio_queue]. Completion
+//! callbacks are delivered by a run loop source (see [handle_completion]),
+//! which is ready once playback finishes, or straight away for vibration and
+//! sounds that can't be played.
 
+use super::audio_file::stream_description;
+use super::audio_queue::{decode_audio_data, is_supported_audio_format, make_al_context_current};
+use crate::abi::{CallFromHost, GuestFunction};
+use crate::audio; // Keep this module namespaced to avoid confusion
+use crate::audio::openal as al;
+use crate::audio::openal::al_types::*;
 use crate::dyld::{export_c_func, FunctionExports};
 use crate::frameworks::carbon_core::OSStatus;
 use crate::frameworks::core_audio_types::fourcc;
+use crate::frameworks::core_foundation::cf_run_loop::{
+    CFRunLoopGetMain, CFRunLoopMode, CFRunLoopRef, CFRunLoopSourceRef,
+};
+use crate::frameworks::core_foundation::cf_url::CFURLRef;
+use crate::frameworks::foundation::ns_run_loop;
+use crate::frameworks::foundation::ns_url::to_rust_path;
 use crate::mem::{MutPtr, MutVoidPtr, Ptr};
+use crate::objc::{id, msg_class, objc_classes, release, ClassExports, HostObject, NSZonePtr};
 use crate::Environment;
+use std::collections::HashMap;
+
+#[derive(Default)]
+pub struct State {
+    system_sounds: HashMap<SystemSoundID, SystemSound>,
+    completions: HashMap<SystemSoundID, Completion>,
+    /// Run loop sources for completion callbacks that are due to be called,
+    /// once the sound has finished playing. Weak references, the run loop
+    /// owns them.
+    pending_completions: HashMap<SystemSoundID, CFRunLoopSourceRef>,
+    last_system_sound_id: SystemSoundID,
+}
+impl State {
+    fn get(env: &mut Environment) -> &mut Self {
+        &mut env.framework_state.audio_toolbox.audio_services
+    }
+}
+
+struct SystemSound {
+    al_buffer: ALuint,
+    al_source: Option<ALuint>,
+}
+
+#[derive(Copy, Clone)]
+struct Completion {
+    /// Weak reference
+    run_loop: CFRunLoopRef,
+    callback: AudioServicesSystemSoundCompletionProc,
+    client_data: MutVoidPtr,
+}
+
+struct SystemSoundCompletionHostObject {
+    system_sound_id: SystemSoundID,
+    /// Weak reference
+    run_loop: CFRunLoopRef,
+}
+impl HostObject for SystemSoundCompletionHostObject {}
 
 /// Usually a FourCC.
 type AudioServicesPropertyID = u32;
 type SystemSoundID = u32;
 
+/// (*void)(SystemSoundID ssID, void *clientData)
+type AudioServicesSystemSoundCompletionProc = GuestFunction;
+
 const kAudioServicesUnsupportedPropertyError: OSStatus = fourcc(b"pty?") as _;
+const kAudioServicesSystemSoundUnspecifiedError: OSStatus = -1500;
 const kSystemSoundID_Vibrate: SystemSoundID = 0x00000FFF;
 
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+// Run loop source for a system sound completion callback, see
+// [handle_completion].
+@implementation _touchHLE_SystemSoundCompletion: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(SystemSoundCompletionHostObject {
+        system_sound_id: 0,
+        run_loop: Ptr::null(),
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+@end
+
+};
+
 fn AudioServicesGetProperty(
     _env: &mut Environment,
     in_property_id: AudioServicesPropertyID,
@@ -35,10 +115,261 @@ fn AudioServicesGetProperty(
     }
 }
 
-fn AudioServicesPlaySystemSound(_env: &mut Environment, in_system_sound_id: SystemSoundID) {
-    assert_eq!(in_system_sound_id, kSystemSoundID_Vibrate);
-    log!("TODO: vibration (AudioServicesPlaySystemSound)");
-    // TODO: implement other system sounds
+/// Load a whole audio file and decode it into an OpenAL buffer.
+fn load_system_sound(env: &mut Environment, in_file_url: CFURLRef) -> Result<ALuint, ()> {
+    let path = to_rust_path(env, in_file_url);
+    let mut audio_file = audio::AudioFile::open_for_reading(path, &env.fs)?;
+    let format = stream_description(&audio_file);
+    if !is_supported_audio_format(&format) {
+        log!(
+            "Warning: system sound {:?} has unsupported format {:?}",
+            in_file_url,
+            format
+        );
+        return Err(());
+    }
+    let mut data = vec![0; audio_file.byte_count().try_into().unwrap()];
+    let bytes_read = audio_file.read_bytes(0, &mut data)?;
+    data.truncate(bytes_read);
+    let (al_format, sample_rate, pcm) = decode_audio_data(&format, &data);
+
+    let _context_manager = make_al_context_current(env);
+    let mut al_buffer = 0;
+    unsafe {
+        al::alGenBuffers(1, &mut al_buffer);
+        al::alBufferData(
+            al_buffer,
+            al_format,
+            pcm.as_ptr() as *const ALvoid,
+            pcm.len().try_into().unwrap(),
+            sample_rate,
+        );
+        assert!(al::alGetError() == 0);
+    }
+    Ok(al_buffer)
+}
+
+fn AudioServicesCreateSystemSoundID(
+    env: &mut Environment,
+    in_file_url: CFURLRef,
+    out_system_sound_id: MutPtr<SystemSoundID>,
+) -> OSStatus {
+    return_if_null!(in_file_url);
+
+    let Ok(al_buffer) = load_system_sound(env, in_file_url) else {
+        log!(
+            "Warning: AudioServicesCreateSystemSoundID() for {:?} failed",
+            in_file_url
+        );
+        return kAudioServicesSystemSoundUnspecifiedError;
+    };
+
+    let state = State::get(env);
+    // Apple's implementation seems to start counting from 4096, right after
+    // kSystemSoundID_Vibrate.
+    let system_sound_id = state.last_system_sound_id.max(kSystemSoundID_Vibrate) + 1;
+    state.last_system_sound_id = system_sound_id;
+    state.system_sounds.insert(
+        system_sound_id,
+        SystemSound {
+            al_buffer,
+            al_source: None,
+        },
+    );
+    env.mem.write(out_system_sound_id, system_sound_id);
+    log_dbg!(
+        "AudioServicesCreateSystemSoundID({:?}) => {}",
+        in_file_url,
+        system_sound_id
+    );
+    0 // success
+}
+
+fn AudioServicesDisposeSystemSoundID(
+    env: &mut Environment,
+    in_system_sound_id: SystemSoundID,
+) -> OSStatus {
+    let Some(SystemSound {
+        al_buffer,
+        al_source,
+    }) = State::get(env).system_sounds.remove(&in_system_sound_id)
+    else {
+        return kAudioServicesSystemSoundUnspecifiedError;
+    };
+    cancel_pending_completion(env, in_system_sound_id);
+    State::get(env).completions.remove(&in_system_sound_id);
+
+    let _context_manager = make_al_context_current(env);
+    unsafe {
+        if let Some(al_source) = al_source {
+            al::alSourceStop(al_source);
+            al::alDeleteSources(1, &al_source);
+        }
+        al::alDeleteBuffers(1, &al_buffer);
+        assert!(al::alGetError() == 0);
+    }
+    log_dbg!("AudioServicesDisposeSystemSoundID({})", in_system_sound_id);
+    0 // success
+}
+
+fn AudioServicesPlaySystemSound(env: &mut Environment, in_system_sound_id: SystemSoundID) {
+    if in_system_sound_id == kSystemSoundID_Vibrate {
+        log_dbg!("TODO: vibration (AudioServicesPlaySystemSound)");
+    } else if let Some(system_sound) = State::get(env).system_sounds.get(&in_system_sound_id) {
+        let al_buffer = system_sound.al_buffer;
+        let al_source = system_sound.al_source;
+        let _context_manager = make_al_context_current(env);
+        let al_source = al_source.unwrap_or_else(|| {
+            let mut al_source = 0;
+            unsafe {
+                al::alGenSources(1, &mut al_source);
+                al::alSourcei(al_source, al::AL_BUFFER, al_buffer as ALint);
+                assert!(al::alGetError() == 0);
+            }
+            al_source
+        });
+        unsafe {
+            // Playing a sound that's already playing restarts it.
+            al::alSourcePlay(al_source);
+            assert!(al::alGetError() == 0);
+        }
+        State::get(env)
+            .system_sounds
+            .get_mut(&in_system_sound_id)
+            .unwrap()
+            .al_source = Some(al_source);
+    } else {
+        // TODO: implement the built-in system sounds
+        log!(
+            "TODO: AudioServicesPlaySystemSound({}), unknown system sound",
+            in_system_sound_id
+        );
+    }
+    schedule_completion(env, in_system_sound_id);
+}
+
+fn AudioServicesPlayAlertSound(env: &mut Environment, in_system_sound_id: SystemSoundID) {
+    // There's no way to vibrate, so this is the same as playing the sound.
+    AudioServicesPlaySystemSound(env, in_system_sound_id)
+}
+
+fn AudioServicesAddSystemSoundCompletion(
+    env: &mut Environment,
+    in_system_sound_id: SystemSoundID,
+    in_run_loop: CFRunLoopRef,
+    _in_run_loop_mode: CFRunLoopMode,
+    in_completion_routine: AudioServicesSystemSoundCompletionProc,
+    in_client_data: MutVoidPtr,
+) -> OSStatus {
+    // TODO: handle modes
+    let run_loop = if in_run_loop.is_null() {
+        CFRunLoopGetMain(env)
+    } else {
+        in_run_loop
+    };
+    log_dbg!(
+        "AudioServicesAddSystemSoundCompletion({}, {:?}, {:?}, {:?})",
+        in_system_sound_id,
+        run_loop,
+        in_completion_routine,
+        in_client_data
+    );
+    // A sound can only have one completion, so this replaces any existing one.
+    cancel_pending_completion(env, in_system_sound_id);
+    State::get(env).completions.insert(
+        in_system_sound_id,
+        Completion {
+            run_loop,
+            callback: in_completion_routine,
+            client_data: in_client_data,
+        },
+    );
+    0 // success
+}
+
+fn AudioServicesRemoveSystemSoundCompletion(
+    env: &mut Environment,
+    in_system_sound_id: SystemSoundID,
+) {
+    cancel_pending_completion(env, in_system_sound_id);
+    State::get(env).completions.remove(&in_system_sound_id);
+}
+
+/// Add a run loop source that will call the completion callback for a system
+/// sound, if it has one, once it's done playing.
+fn schedule_completion(env: &mut Environment, system_sound_id: SystemSoundID) {
+    let state = State::get(env);
+    let Some(&Completion { run_loop, .. }) = state.completions.get(&system_sound_id) else {
+        return;
+    };
+    if state.pending_completions.contains_key(&system_sound_id) {
+        return;
+    }
+    let source: CFRunLoopSourceRef = msg_class![env; _touchHLE_SystemSoundCompletion alloc];
+    *env.objc.borrow_mut(source) = SystemSoundCompletionHostObject {
+        system_sound_id,
+        run_loop,
+    };
+    ns_run_loop::add_source(env, run_loop, source);
+    release(env, source);
+    State::get(env)
+        .pending_completions
+        .insert(system_sound_id, source);
+}
+
+fn cancel_pending_completion(env: &mut Environment, system_sound_id: SystemSoundID) {
+    let Some(source) = State::get(env).pending_completions.remove(&system_sound_id) else {
+        return;
+    };
+    let run_loop = env
+        .objc
+        .borrow::<SystemSoundCompletionHostObject>(source)
+        .run_loop;
+    ns_run_loop::remove_source(env, run_loop, source);
+}
+
+/// Check whether a system sound has finished playing, and if so, call its
+/// completion callback, for use by
+/// [crate::frameworks::core_foundation::cf_run_loop::handle_source]. Returns
+/// `true` if the callback was called.
+pub fn handle_completion(env: &mut Environment, source: CFRunLoopSourceRef) -> bool {
+    let system_sound_id = env
+        .objc
+        .borrow::<SystemSoundCompletionHostObject>(source)
+        .system_sound_id;
+
+    let al_source = State::get(env)
+        .system_sounds
+        .get(&system_sound_id)
+        .and_then(|system_sound| system_sound.al_source);
+    if let Some(al_source) = al_source {
+        let _context_manager = make_al_context_current(env);
+        let mut al_source_state = 0;
+        unsafe {
+            al::alGetSourcei(al_source, al::AL_SOURCE_STATE, &mut al_source_state);
+            assert!(al::alGetError() == 0);
+        }
+        if al_source_state == al::AL_PLAYING {
+            return false;
+        }
+    }
+
+    cancel_pending_completion(env, system_sound_id);
+    let Some(Completion {
+        callback,
+        client_data,
+        ..
+    }) = State::get(env).completions.get(&system_sound_id).copied()
+    else {
+        return false;
+    };
+    log_dbg!(
+        "System sound {} completed, calling {:?}",
+        system_sound_id,
+        callback
+    );
+    () = callback.call_from_host(env, (system_sound_id, client_data));
+    true
 }
 
 fn AudioOutputUnitStop(_env: &mut Environment, ci: MutVoidPtr) -> OSStatus {
@@ -51,7 +382,12 @@ fn AudioComponentFindNext(_env: &mut Environment, inComponent: MutVoidPtr, inDes
 
 pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(AudioServicesGetProperty(_, _, _, _, _)),
+    export_c_func!(AudioServicesCreateSystemSoundID(_, _)),
+    export_c_func!(AudioServicesDisposeSystemSoundID(_)),
     export_c_func!(AudioServicesPlaySystemSound(_)),
+    export_c_func!(AudioServicesPlayAlertSound(_)),
+    export_c_func!(AudioServicesAddSystemSoundCompletion(_, _, _, _, _)),
+    export_c_func!(AudioServicesRemoveSystemSoundCompletion(_)),
     // export_c_func!(AudioOutputUnitStop(_)),
     // export_c_func!(AudioComponentFindNext(_, _)),
 ];
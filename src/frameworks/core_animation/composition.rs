@@ -103,6 +103,8 @@ pub fn recomposite_if_necessary(env: &mut Environment) -> Option<Instant> {
         .composition
         .recomposite_next = new_recomposite_next;
 
+    crate::libc::time::count_presented_frame(env);
+
     let root_layer: id = msg![env; top_window layer];
 
     // Ensure layer bitmaps are up to date.
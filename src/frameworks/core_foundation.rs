@@ -22,8 +22,12 @@ pub mod cf_bundle;
 pub mod cf_data;
 pub mod cf_dictionary;
 pub mod cf_locale;
+pub mod cf_notification_center;
+pub mod cf_null;
 pub mod cf_run_loop;
 pub mod cf_run_loop_timer;
+pub mod cf_socket;
+pub mod cf_stream;
 pub mod cf_string;
 pub mod cf_type;
 pub mod cf_url;
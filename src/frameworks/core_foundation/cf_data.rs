@@ -29,7 +29,7 @@ pub fn CFDataCreate(
     let bytes: ConstVoidPtr = bytes.cast();
     let length: NSUInteger = length.try_into().unwrap();
     let new: id = msg_class![env; NSData alloc];
-    msg![env; new dataWithBytes:bytes length:length]
+    msg![env; new initWithBytes:bytes length:length]
 }
 
 fn CFDataGetLength(env: &mut Environment, data: CFDataRef) -> CFIndex {
@@ -0,0 +1,156 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `CFNotificationCenter`.
+//!
+//! This isn't toll-free bridged with `NSNotificationCenter`, but the local
+//! center delivers notifications to observers from both APIs, so we simply use
+//! `NSNotificationCenter` as the implementation. See
+//! [crate::frameworks::foundation::ns_notification_center].
+//!
+//! The `object` of a local notification is meant to be an arbitrary pointer,
+//! but it is retained like an Objective-C object here, so only objects (or
+//! `NULL`) are supported.
+
+use super::cf_dictionary::CFDictionaryRef;
+use super::cf_string::CFStringRef;
+use super::CFIndex;
+use crate::abi::GuestFunction;
+use crate::dyld::{export_c_func, FunctionExports};
+use crate::frameworks::foundation::ns_notification_center;
+use crate::mem::ConstVoidPtr;
+use crate::objc::{id, msg, msg_class, nil};
+use crate::Environment;
+
+pub type CFNotificationCenterRef = super::CFTypeRef;
+pub type CFNotificationName = CFStringRef;
+type CFNotificationSuspensionBehavior = CFIndex;
+type CFOptionFlags = super::CFOptionFlags;
+
+// void (*)(CFNotificationCenterRef center, void *observer,
+//          CFNotificationName name, const void *object,
+//          CFDictionaryRef userInfo)
+type CFNotificationCallback = GuestFunction;
+
+fn CFNotificationCenterGetLocalCenter(env: &mut Environment) -> CFNotificationCenterRef {
+    msg_class![env; NSNotificationCenter defaultCenter]
+}
+
+fn CFNotificationCenterGetDarwinNotifyCenter(env: &mut Environment) -> CFNotificationCenterRef {
+    // Real Darwin notifications come from other processes or the system, which
+    // touchHLE doesn't have, so this center only ever gets the app's own posts.
+    ns_notification_center::darwin_notify_center(env)
+}
+
+fn CFNotificationCenterAddObserver(
+    env: &mut Environment,
+    center: CFNotificationCenterRef,
+    observer: ConstVoidPtr,
+    callback: CFNotificationCallback,
+    name: CFNotificationName,
+    object: ConstVoidPtr,
+    _suspension_behavior: CFNotificationSuspensionBehavior, // apps are never suspended
+) {
+    log_dbg!(
+        "CFNotificationCenterAddObserver({:?}, {:?}, {:?}, {:?}, {:?})",
+        center,
+        observer,
+        callback,
+        name,
+        object
+    );
+    // The Darwin notify center ignores the object.
+    let object: id = if ns_notification_center::is_darwin_notify_center(env, center) {
+        nil
+    } else {
+        object.cast_mut().cast()
+    };
+    ns_notification_center::add_cf_observer(env, center, observer, callback, name, object);
+}
+
+fn CFNotificationCenterRemoveObserver(
+    env: &mut Environment,
+    center: CFNotificationCenterRef,
+    observer: ConstVoidPtr,
+    name: CFNotificationName,
+    object: ConstVoidPtr,
+) {
+    log_dbg!(
+        "CFNotificationCenterRemoveObserver({:?}, {:?}, {:?}, {:?})",
+        center,
+        observer,
+        name,
+        object
+    );
+    let object: id = object.cast_mut().cast();
+    ns_notification_center::remove_cf_observer(env, center, observer, name, object);
+}
+
+fn CFNotificationCenterRemoveEveryObserver(
+    env: &mut Environment,
+    center: CFNotificationCenterRef,
+    observer: ConstVoidPtr,
+) {
+    log_dbg!(
+        "CFNotificationCenterRemoveEveryObserver({:?}, {:?})",
+        center,
+        observer
+    );
+    ns_notification_center::remove_cf_observer(env, center, observer, nil, nil);
+}
+
+fn CFNotificationCenterPostNotification(
+    env: &mut Environment,
+    center: CFNotificationCenterRef,
+    name: CFNotificationName,
+    object: ConstVoidPtr,
+    user_info: CFDictionaryRef,
+    _deliver_immediately: bool, // notifications are always delivered
+) {
+    CFNotificationCenterPostNotificationWithOptions(env, center, name, object, user_info, 0)
+}
+
+fn CFNotificationCenterPostNotificationWithOptions(
+    env: &mut Environment,
+    center: CFNotificationCenterRef,
+    name: CFNotificationName,
+    object: ConstVoidPtr,
+    user_info: CFDictionaryRef,
+    _options: CFOptionFlags,
+) {
+    log_dbg!(
+        "CFNotificationCenterPostNotification({:?}, {:?}, {:?}, {:?})",
+        center,
+        name,
+        object,
+        user_info
+    );
+    // The Darwin notify center ignores the object and user info.
+    let (object, user_info): (id, id) =
+        if ns_notification_center::is_darwin_notify_center(env, center) {
+            (nil, nil)
+        } else {
+            (object.cast_mut().cast(), user_info)
+        };
+    () = msg![env; center postNotificationName:name
+                                        object:object
+                                      userInfo:user_info];
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(CFNotificationCenterGetLocalCenter()),
+    export_c_func!(CFNotificationCenterGetDarwinNotifyCenter()),
+    export_c_func!(CFNotificationCenterAddObserver(_, _, _, _, _, _)),
+    export_c_func!(CFNotificationCenterRemoveObserver(_, _, _, _)),
+    export_c_func!(CFNotificationCenterRemoveEveryObserver(_, _)),
+    export_c_func!(CFNotificationCenterPostNotification(_, _, _, _, _)),
+    export_c_func!(CFNotificationCenterPostNotificationWithOptions(
+        _,
+        _,
+        _,
+        _,
+        _
+    )),
+];
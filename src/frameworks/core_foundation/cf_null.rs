@@ -0,0 +1,25 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `CFNull`.
+//!
+//! This is toll-free bridged to `NSNull` in Apple's implementation. Here it is
+//! the same type.
+
+use super::cf_type::CFTypeID;
+use crate::dyld::{export_c_func, ConstantExports, FunctionExports, HostConstant};
+use crate::frameworks::foundation::ns_null;
+use crate::Environment;
+
+/// Arbitrary, but it should be distinct from other types' IDs once those exist.
+const CFNullTypeID: CFTypeID = 16;
+
+fn CFNullGetTypeID(_env: &mut Environment) -> CFTypeID {
+    CFNullTypeID
+}
+
+pub const CONSTANTS: ConstantExports = &[("_kCFNull", HostConstant::Object(ns_null::get_null))];
+
+pub const FUNCTIONS: FunctionExports = &[export_c_func!(CFNullGetTypeID())];
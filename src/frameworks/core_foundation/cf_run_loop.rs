@@ -8,14 +8,18 @@
 //! This is not even toll-free bridged to `NSRunLoop` in Apple's implementation,
 //! but here it is the same type.
 
+use super::{cf_socket, cf_stream};
+use crate::frameworks::audio_toolbox::audio_services;
 use crate::dyld::{export_c_func, ConstantExports, FunctionExports, HostConstant};
-use crate::objc::msg_class;
+use crate::frameworks::foundation::ns_run_loop::{self, RunLoopExit, RunUntil};
+use crate::objc::{msg_class, Class};
 use crate::{Environment, msg};
 use crate::frameworks::core_foundation::cf_string::CFStringRef;
 use crate::frameworks::core_foundation::{CFIndex, CFTypeRef};
 use crate::frameworks::core_foundation::cf_allocator::CFAllocatorRef;
 use crate::frameworks::core_foundation::time::CFTimeInterval;
 use crate::mem::{ConstVoidPtr, Ptr};
+use std::time::{Duration, Instant};
 
 pub type CFRunLoopRef = super::CFTypeRef;
 pub type CFRunLoopMode = super::cf_string::CFStringRef;
@@ -28,13 +32,91 @@ pub fn CFRunLoopGetMain(env: &mut Environment) -> CFRunLoopRef {
     msg_class![env; NSRunLoop mainRunLoop]
 }
 
+type CFRunLoopRunResult = i32;
+const kCFRunLoopRunFinished: CFRunLoopRunResult = 1;
+const kCFRunLoopRunTimedOut: CFRunLoopRunResult = 3;
+const kCFRunLoopRunHandledSource: CFRunLoopRunResult = 4;
+
 fn CFRunLoopRunInMode(
-    env: &mut Environment, mode: CFRunLoopMode, seconds: CFTimeInterval, returnSomething: bool
-) -> i32 {
-    // let loop_ = CFRunLoopGetCurrent(env);
-    // () = msg![env; loop_ run];
-    // 0
-    1
+    env: &mut Environment,
+    mode: CFRunLoopMode,
+    seconds: CFTimeInterval,
+    return_after_source_handled: bool,
+) -> CFRunLoopRunResult {
+    // TODO: handle modes
+    if env.current_thread != 0 {
+        log!(
+            "TODO: CFRunLoopRunInMode({:?}, {}, {}) on a secondary thread, returning kCFRunLoopRunFinished",
+            mode,
+            seconds,
+            return_after_source_handled
+        );
+        return kCFRunLoopRunFinished;
+    }
+    let run_loop = CFRunLoopGetCurrent(env);
+    if !ns_run_loop::has_timers_or_sources(env, run_loop) {
+        return kCFRunLoopRunFinished;
+    }
+    let deadline = Duration::try_from_secs_f64(seconds.max(0.0))
+        .ok()
+        .and_then(|duration| Instant::now().checked_add(duration))
+        // Practically forever.
+        .unwrap_or_else(|| Instant::now() + Duration::from_secs(60 * 60 * 24 * 365));
+    let until = RunUntil::Deadline {
+        deadline,
+        return_after_source_handled,
+    };
+    match ns_run_loop::run_run_loop(env, run_loop, until) {
+        RunLoopExit::TimedOut => kCFRunLoopRunTimedOut,
+        RunLoopExit::HandledSource => kCFRunLoopRunHandledSource,
+    }
+}
+
+/// `CFRunLoopSourceRef`. In our implementation, a run loop source is the
+/// object it wraps: a `CFSocket` or a scheduled `CFReadStream` or
+/// `CFWriteStream`. Audio Toolbox also uses internal sources to call system
+/// sound completion callbacks.
+pub type CFRunLoopSourceRef = CFTypeRef;
+
+fn CFRunLoopAddSource(
+    env: &mut Environment,
+    run_loop: CFRunLoopRef,
+    source: CFRunLoopSourceRef,
+    _mode: CFRunLoopMode,
+) {
+    // TODO: handle modes
+    ns_run_loop::add_source(env, run_loop, source);
+}
+
+fn CFRunLoopRemoveSource(
+    env: &mut Environment,
+    run_loop: CFRunLoopRef,
+    source: CFRunLoopSourceRef,
+    _mode: CFRunLoopMode,
+) {
+    ns_run_loop::remove_source(env, run_loop, source);
+}
+
+fn CFRunLoopSourceInvalidate(env: &mut Environment, source: CFRunLoopSourceRef) {
+    let run_loop = CFRunLoopGetMain(env);
+    ns_run_loop::remove_source(env, run_loop, source);
+}
+
+/// Check a run loop source and call its callbacks if it's ready, for use by
+/// [ns_run_loop]. Returns `true` if a callback was called.
+pub fn handle_source(env: &mut Environment, source: CFRunLoopSourceRef) -> bool {
+    let class: Class = msg![env; source class];
+    if class == env.objc.get_known_class("_CFSocket", &mut env.mem) {
+        cf_socket::handle_socket(env, source)
+    } else if class
+        == env
+            .objc
+            .get_known_class("_touchHLE_SystemSoundCompletion", &mut env.mem)
+    {
+        audio_services::handle_completion(env, source)
+    } else {
+        cf_stream::handle_stream(env, source)
+    }
 }
 
 fn CFPreferencesCopyAppValue(env: &mut Environment, key: CFStringRef, appID: CFStringRef) -> CFTypeRef {
@@ -83,6 +165,9 @@ pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(CFRunLoopGetCurrent()),
     export_c_func!(CFRunLoopGetMain()),
     export_c_func!(CFRunLoopRunInMode(_, _, _)),
+    export_c_func!(CFRunLoopAddSource(_, _, _)),
+    export_c_func!(CFRunLoopRemoveSource(_, _, _)),
+    export_c_func!(CFRunLoopSourceInvalidate(_)),
     export_c_func!(CFPreferencesCopyAppValue(_, _)),
     export_c_func!(CFPreferencesSetAppValue(_, _, _)),
     export_c_func!(CFPreferencesAppSynchronize(_)),
@@ -39,7 +39,7 @@ unsafe impl SafeRead for CFRunLoopTimerContext {}
 fn CFRunLoopTimerCreate(
     env: &mut Environment,
     allocator: CFAllocatorRef,
-    _fire_date: CFAbsoluteTime, // TODO
+    fire_date: CFAbsoluteTime,
     interval: CFTimeInterval,
     flags: CFOptionFlags,
     order: CFIndex,
@@ -69,11 +69,14 @@ fn CFRunLoopTimerCreate(
     let selector = env.objc.lookup_selector("timerFireMethod:").unwrap();
 
     let repeats = interval > 0.0;
-    msg_class![env; NSTimer timerWithTimeInterval:interval
-                                           target:fake_target
-                                         selector:selector
-                                         userInfo:nil
-                                          repeats:repeats]
+    let timer: id = msg_class![env; NSTimer timerWithTimeInterval:interval
+                                                       target:fake_target
+                                                     selector:selector
+                                                     userInfo:nil
+                                                      repeats:repeats];
+    let fire_date: id = msg_class![env; NSDate dateWithTimeIntervalSinceReferenceDate:fire_date];
+    () = msg![env; timer setFireDate:fire_date];
+    timer
 }
 
 fn CFRunLoopAddTimer(
@@ -0,0 +1,564 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `CFSocket`.
+//!
+//! This is a wrapper around a socket from [crate::libc::socket]. A `CFSocket`
+//! is also its own run loop source: `CFSocketCreateRunLoopSource` returns the
+//! socket, and the run loop calls [handle_socket] to check whether it's ready
+//! and call its callbacks.
+
+use super::cf_allocator::{kCFAllocatorDefault, CFAllocatorRef};
+use super::cf_data::CFDataRef;
+use super::cf_run_loop::CFRunLoopGetMain;
+use super::time::CFTimeInterval;
+use super::{CFIndex, CFOptionFlags};
+use crate::abi::{CallFromHost, GuestFunction};
+use crate::dyld::{export_c_func, FunctionExports};
+use crate::frameworks::foundation::{ns_run_loop, NSUInteger};
+use crate::libc::errno::{EBADF, ETIMEDOUT};
+use crate::libc::posix_io::{close, FileDescriptor};
+use crate::libc::socket::{
+    self, sockaddr_in, socklen_t, with_socket, ReadReadiness, AF_INET, IPPROTO_TCP, SOCK_STREAM,
+};
+use crate::mem::{ConstPtr, ConstVoidPtr, MutPtr, MutVoidPtr, Ptr, SafeRead};
+use crate::objc::{
+    id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject, NSZonePtr,
+};
+use crate::Environment;
+use std::net::{SocketAddr, SocketAddrV4};
+use std::time::Duration;
+
+pub type CFSocketRef = super::CFTypeRef;
+pub type CFSocketNativeHandle = FileDescriptor;
+
+type CFSocketCallBackType = CFOptionFlags;
+const kCFSocketReadCallBack: CFSocketCallBackType = 1;
+const kCFSocketAcceptCallBack: CFSocketCallBackType = 2;
+const kCFSocketDataCallBack: CFSocketCallBackType = 3;
+const kCFSocketConnectCallBack: CFSocketCallBackType = 4;
+const kCFSocketWriteCallBack: CFSocketCallBackType = 8;
+/// The read, accept and data callbacks are mutually exclusive, so they're an
+/// enumeration in the low bits rather than separate flags.
+const READ_CALLBACK_MASK: CFSocketCallBackType = 3;
+
+const kCFSocketAutomaticallyReenableWriteCallBack: CFOptionFlags = 8;
+const kCFSocketCloseOnInvalidate: CFOptionFlags = 128;
+/// The read, accept and data callbacks are automatically re-enabled by
+/// default, but the write callback is not.
+const DEFAULT_SOCKET_FLAGS: CFOptionFlags = READ_CALLBACK_MASK | kCFSocketCloseOnInvalidate;
+
+type CFSocketError = CFIndex;
+const kCFSocketSuccess: CFSocketError = 0;
+const kCFSocketError: CFSocketError = -1;
+const kCFSocketTimeout: CFSocketError = -2;
+
+/// How much is read at once for the data callback.
+const DATA_CALLBACK_CHUNK_SIZE: u32 = 8192;
+
+// void (*CFSocketCallBack)(CFSocketRef s, CFSocketCallBackType type,
+//                          CFDataRef address, const void *data, void *info)
+type CFSocketCallBack = GuestFunction;
+
+#[repr(C, packed)]
+pub struct CFSocketContext {
+    version: CFIndex,
+    info: MutVoidPtr,
+    retain_callback: GuestFunction,
+    release_callback: GuestFunction,
+    copy_desc_callback: GuestFunction,
+}
+unsafe impl SafeRead for CFSocketContext {}
+
+struct CFSocketHostObject {
+    /// `None` once the socket has been invalidated.
+    fd: Option<FileDescriptor>,
+    /// Callbacks that are currently enabled. Some callbacks disable
+    /// themselves when called, see [DEFAULT_SOCKET_FLAGS].
+    enabled_callbacks: CFSocketCallBackType,
+    flags: CFOptionFlags,
+    callout: CFSocketCallBack,
+    info: MutVoidPtr,
+    release_callback: Option<GuestFunction>,
+    /// Set while a connection started by `CFSocketConnectToAddress` with a
+    /// negative timeout hasn't been reported by the connect callback yet.
+    connecting_in_background: bool,
+    /// Set once the data callback has reported the end of the connection.
+    reached_end: bool,
+}
+impl HostObject for CFSocketHostObject {}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+// Not a real class name, CFSocket isn't bridged to any Objective-C class.
+@implementation _CFSocket: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(CFSocketHostObject {
+        fd: None,
+        enabled_callbacks: 0,
+        flags: DEFAULT_SOCKET_FLAGS,
+        callout: GuestFunction::from_addr_with_thumb_bit(0),
+        info: Ptr::null(),
+        release_callback: None,
+        connecting_in_background: false,
+        reached_end: false,
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+- (())dealloc {
+    // The run loop retains its sources, so there's no need to remove it.
+    invalidate(env, this);
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+@end
+
+};
+
+fn create_with_fd(
+    env: &mut Environment,
+    fd: FileDescriptor,
+    callback_types: CFSocketCallBackType,
+    callout: CFSocketCallBack,
+    context_ptr: ConstPtr<CFSocketContext>,
+) -> CFSocketRef {
+    let (info, release_callback) = if context_ptr.is_null() {
+        (Ptr::null(), None)
+    } else {
+        let context = env.mem.read(context_ptr);
+        let version = context.version;
+        assert_eq!(version, 0);
+        let mut info = context.info;
+        let retain_callback = context.retain_callback;
+        if !retain_callback.to_ptr().is_null() {
+            let retained: ConstVoidPtr = retain_callback.call_from_host(env, (info.cast_const(),));
+            info = retained.cast_mut();
+        }
+        let release_callback = context.release_callback;
+        let release_callback = (!release_callback.to_ptr().is_null()).then_some(release_callback);
+        (info, release_callback)
+    };
+
+    let socket: CFSocketRef = msg_class![env; _CFSocket alloc];
+    let host_object = env.objc.borrow_mut::<CFSocketHostObject>(socket);
+    host_object.fd = Some(fd);
+    host_object.enabled_callbacks = callback_types;
+    host_object.callout = callout;
+    host_object.info = info;
+    host_object.release_callback = release_callback;
+    log_dbg!(
+        "Created CFSocket {:?} for fd {:?} with callbacks {:#x}",
+        socket,
+        fd,
+        callback_types
+    );
+    socket
+}
+
+fn CFSocketCreate(
+    env: &mut Environment,
+    allocator: CFAllocatorRef,
+    protocol_family: i32,
+    socket_type: i32,
+    protocol: i32,
+    callback_types: CFSocketCallBackType,
+    callout: CFSocketCallBack,
+    context: ConstPtr<CFSocketContext>,
+) -> CFSocketRef {
+    assert!(allocator == kCFAllocatorDefault); // unimplemented
+
+    // Zero or negative values mean the defaults, which are IPv4 and TCP.
+    if (protocol_family > 0 && protocol_family != AF_INET)
+        || (socket_type > 0 && socket_type != SOCK_STREAM)
+        || (protocol > 0 && protocol != IPPROTO_TCP)
+    {
+        log!(
+            "Warning: CFSocketCreate() with unsupported family {}, type {}, protocol {}, returning NULL",
+            protocol_family,
+            socket_type,
+            protocol
+        );
+        return nil;
+    }
+    let fd = socket::new_socket(env);
+    if fd == -1 {
+        log!("Warning: CFSocketCreate() could not create a socket, returning NULL");
+        return nil;
+    }
+    create_with_fd(env, fd, callback_types, callout, context)
+}
+
+fn CFSocketCreateWithNative(
+    env: &mut Environment,
+    allocator: CFAllocatorRef,
+    sock: CFSocketNativeHandle,
+    callback_types: CFSocketCallBackType,
+    callout: CFSocketCallBack,
+    context: ConstPtr<CFSocketContext>,
+) -> CFSocketRef {
+    assert!(allocator == kCFAllocatorDefault); // unimplemented
+    create_with_fd(env, sock, callback_types, callout, context)
+}
+
+/// Read a `struct sockaddr_in` from a `CFData`.
+fn address_from_data(env: &mut Environment, address: CFDataRef) -> Option<SocketAddrV4> {
+    let length: NSUInteger = msg![env; address length];
+    if (length as usize) < std::mem::size_of::<sockaddr_in>() {
+        return None;
+    }
+    let bytes: ConstVoidPtr = msg![env; address bytes];
+    let addr = env.mem.read(bytes.cast::<sockaddr_in>());
+    (addr.sin_family as i32 == AF_INET).then(|| addr.to_host())
+}
+
+/// Create a `CFData` containing a `struct sockaddr_in`. The caller owns it.
+fn data_from_address(env: &mut Environment, addr: SocketAddrV4) -> CFDataRef {
+    let bytes: MutVoidPtr = env.mem.alloc_and_write(sockaddr_in::from_host(addr)).cast();
+    let length = std::mem::size_of::<sockaddr_in>() as NSUInteger;
+    let data: id = msg_class![env; NSData alloc];
+    msg![env; data initWithBytesNoCopy:bytes length:length]
+}
+
+fn CFSocketConnectToAddress(
+    env: &mut Environment,
+    s: CFSocketRef,
+    address: CFDataRef,
+    timeout: CFTimeInterval,
+) -> CFSocketError {
+    let Some(fd) = env.objc.borrow::<CFSocketHostObject>(s).fd else {
+        return kCFSocketError;
+    };
+    let Some(addr) = address_from_data(env, address) else {
+        log!("Warning: CFSocketConnectToAddress() with unsupported address, returning error");
+        return kCFSocketError;
+    };
+
+    // A negative timeout means the connection should happen in the background
+    // and be reported by the connect callback, see handle_socket.
+    if timeout < 0.0 {
+        let result = socket::start_connect(env, fd, vec![SocketAddr::V4(addr)], None);
+        log_dbg!(
+            "CFSocketConnectToAddress({:?}, {}, {}) => {:?}",
+            s,
+            addr,
+            timeout,
+            result
+        );
+        if result.is_err() {
+            return kCFSocketError;
+        }
+        env.objc
+            .borrow_mut::<CFSocketHostObject>(s)
+            .connecting_in_background = true;
+        return kCFSocketSuccess;
+    }
+
+    let timeout = (timeout > 0.0).then(|| Duration::from_secs_f64(timeout));
+    let result = socket::connect_socket(env, fd, &[SocketAddr::V4(addr)], timeout);
+    log_dbg!(
+        "CFSocketConnectToAddress({:?}, {}, {:?}) => {:?}",
+        s,
+        addr,
+        timeout,
+        result
+    );
+    match result {
+        Ok(()) => kCFSocketSuccess,
+        Err(ETIMEDOUT) => kCFSocketTimeout,
+        Err(_) => kCFSocketError,
+    }
+}
+
+fn CFSocketSetAddress(env: &mut Environment, s: CFSocketRef, address: CFDataRef) -> CFSocketError {
+    let Some(fd) = env.objc.borrow::<CFSocketHostObject>(s).fd else {
+        return kCFSocketError;
+    };
+    let length: NSUInteger = msg![env; address length];
+    let bytes: ConstVoidPtr = msg![env; address bytes];
+    if socket::bind(env, fd, bytes.cast(), length as socklen_t) != 0
+        || socket::listen(env, fd, 256) != 0
+    {
+        return kCFSocketError;
+    }
+    kCFSocketSuccess
+}
+
+fn CFSocketSendData(
+    env: &mut Environment,
+    s: CFSocketRef,
+    address: CFDataRef,
+    data: CFDataRef,
+    _timeout: CFTimeInterval,
+) -> CFSocketError {
+    let Some(fd) = env.objc.borrow::<CFSocketHostObject>(s).fd else {
+        return kCFSocketError;
+    };
+    if !address.is_null() {
+        // Only meaningful for datagram sockets, which aren't supported.
+        log!("TODO: CFSocketSendData() with an address (ignored)");
+    }
+    let length: NSUInteger = msg![env; data length];
+    let bytes: ConstPtr<u8> = msg![env; data bytes];
+    let mut sent = 0;
+    while sent < length {
+        let res = socket::send(env, fd, (bytes + sent).cast(), length - sent, 0);
+        if res < 0 {
+            return kCFSocketError;
+        }
+        sent += res as NSUInteger;
+    }
+    kCFSocketSuccess
+}
+
+/// Close the socket and release the context's info, without removing the
+/// socket from the run loop.
+fn invalidate(env: &mut Environment, s: CFSocketRef) {
+    let host_object = env.objc.borrow_mut::<CFSocketHostObject>(s);
+    let Some(fd) = host_object.fd.take() else {
+        return;
+    };
+    let close_on_invalidate = host_object.flags & kCFSocketCloseOnInvalidate != 0;
+    let info = host_object.info;
+    let release_callback = host_object.release_callback.take();
+    log_dbg!("Invalidating CFSocket {:?} for fd {:?}", s, fd);
+    if close_on_invalidate {
+        close(env, fd);
+    }
+    if let Some(release_callback) = release_callback {
+        () = release_callback.call_from_host(env, (info.cast_const(),));
+    }
+}
+
+fn CFSocketInvalidate(env: &mut Environment, s: CFSocketRef) {
+    // Removing the socket from the run loop might release the last reference.
+    retain(env, s);
+    invalidate(env, s);
+    // There's only one run loop, see NSRunLoop.
+    let run_loop = CFRunLoopGetMain(env);
+    ns_run_loop::remove_source(env, run_loop, s);
+    release(env, s);
+}
+
+fn CFSocketIsValid(env: &mut Environment, s: CFSocketRef) -> bool {
+    env.objc.borrow::<CFSocketHostObject>(s).fd.is_some()
+}
+
+fn CFSocketGetNative(env: &mut Environment, s: CFSocketRef) -> CFSocketNativeHandle {
+    env.objc.borrow::<CFSocketHostObject>(s).fd.unwrap_or(-1)
+}
+
+fn CFSocketGetSocketFlags(env: &mut Environment, s: CFSocketRef) -> CFOptionFlags {
+    env.objc.borrow::<CFSocketHostObject>(s).flags
+}
+
+fn CFSocketSetSocketFlags(env: &mut Environment, s: CFSocketRef, flags: CFOptionFlags) {
+    env.objc.borrow_mut::<CFSocketHostObject>(s).flags = flags;
+}
+
+fn CFSocketEnableCallBacks(env: &mut Environment, s: CFSocketRef, types: CFSocketCallBackType) {
+    env.objc
+        .borrow_mut::<CFSocketHostObject>(s)
+        .enabled_callbacks |= types;
+}
+
+fn CFSocketDisableCallBacks(env: &mut Environment, s: CFSocketRef, types: CFSocketCallBackType) {
+    env.objc
+        .borrow_mut::<CFSocketHostObject>(s)
+        .enabled_callbacks &= !types;
+}
+
+fn CFSocketCreateRunLoopSource(
+    env: &mut Environment,
+    allocator: CFAllocatorRef,
+    s: CFSocketRef,
+    _order: CFIndex,
+) -> CFSocketRef {
+    assert!(allocator == kCFAllocatorDefault); // unimplemented
+
+    // In our implementation the socket is its own run loop source.
+    retain(env, s)
+}
+
+/// Check whether a socket is ready and call its callbacks if so, for use by the
+/// run loop. Returns `true` if a callback was called.
+pub fn handle_socket(env: &mut Environment, s: CFSocketRef) -> bool {
+    let host_object = env.objc.borrow_mut::<CFSocketHostObject>(s);
+    let Some(fd) = host_object.fd else {
+        return false;
+    };
+    let callout = host_object.callout;
+    let info = host_object.info;
+    let flags = host_object.flags;
+    let enabled = host_object.enabled_callbacks;
+    let connecting = host_object.connecting_in_background;
+    let read_callback = if host_object.reached_end {
+        0
+    } else {
+        enabled & READ_CALLBACK_MASK
+    };
+
+    if connecting {
+        let Some(result) =
+            with_socket(env, fd, |socket| Ok(socket.poll_connect())).unwrap_or(Some(Err(EBADF)))
+        else {
+            // Nothing else can happen until the socket is connected.
+            return false;
+        };
+        let host_object = env.objc.borrow_mut::<CFSocketHostObject>(s);
+        host_object.connecting_in_background = false;
+        if enabled & kCFSocketConnectCallBack == 0 {
+            return false;
+        }
+        // The connect callback is only ever called once.
+        host_object.enabled_callbacks &= !kCFSocketConnectCallBack;
+        // The data is NULL on success, or a pointer to an error code.
+        let error: MutPtr<i32> = match result {
+            Ok(()) => Ptr::null(),
+            Err(errno) => env.mem.alloc_and_write(errno),
+        };
+        () = callout.call_from_host(
+            env,
+            (
+                s,
+                kCFSocketConnectCallBack,
+                nil,
+                error.cast_const().cast_void(),
+                info,
+            ),
+        );
+        if !error.is_null() {
+            env.mem.free(error.cast());
+        }
+        return true;
+    }
+
+    let mut handled = false;
+
+    let readiness = if read_callback != 0 {
+        with_socket(env, fd, |socket| Ok(socket.poll_readable())).unwrap_or(ReadReadiness::Ready)
+    } else {
+        ReadReadiness::NotReady
+    };
+    if readiness != ReadReadiness::NotReady {
+        if flags & read_callback != read_callback {
+            env.objc
+                .borrow_mut::<CFSocketHostObject>(s)
+                .enabled_callbacks &= !READ_CALLBACK_MASK;
+        }
+        match read_callback {
+            kCFSocketReadCallBack => {
+                () = callout.call_from_host(
+                    env,
+                    (s, kCFSocketReadCallBack, nil, ConstVoidPtr::null(), info),
+                );
+            }
+            kCFSocketAcceptCallBack => {
+                if let Ok((new_fd, peer)) = socket::accept_socket(env, fd) {
+                    let address = data_from_address(env, peer);
+                    let handle = env.mem.alloc_and_write(new_fd);
+                    () = callout.call_from_host(
+                        env,
+                        (
+                            s,
+                            kCFSocketAcceptCallBack,
+                            address,
+                            handle.cast_const().cast_void(),
+                            info,
+                        ),
+                    );
+                    env.mem.free(handle.cast());
+                    release(env, address);
+                }
+            }
+            kCFSocketDataCallBack => handle_data_callback(env, s, fd, callout, info),
+            _ => unreachable!(),
+        }
+        handled = true;
+    }
+
+    // The socket might have been invalidated by a callback.
+    let host_object = env.objc.borrow_mut::<CFSocketHostObject>(s);
+    if host_object.fd.is_none() || host_object.enabled_callbacks & kCFSocketWriteCallBack == 0 {
+        return handled;
+    }
+    let writable = with_socket(env, fd, |socket| Ok(socket.poll_writable())).unwrap_or(false);
+    if writable {
+        if flags & kCFSocketAutomaticallyReenableWriteCallBack == 0 {
+            env.objc
+                .borrow_mut::<CFSocketHostObject>(s)
+                .enabled_callbacks &= !kCFSocketWriteCallBack;
+        }
+        () = callout.call_from_host(
+            env,
+            (s, kCFSocketWriteCallBack, nil, ConstVoidPtr::null(), info),
+        );
+        handled = true;
+    }
+    handled
+}
+
+/// Read what's available and pass it to the data callback. An empty `CFData`
+/// tells the callback that the connection was closed.
+fn handle_data_callback(
+    env: &mut Environment,
+    s: CFSocketRef,
+    fd: FileDescriptor,
+    callout: CFSocketCallBack,
+    info: MutVoidPtr,
+) {
+    let buffer = env.mem.alloc(DATA_CALLBACK_CHUNK_SIZE);
+    let received = socket::recv(env, fd, buffer, DATA_CALLBACK_CHUNK_SIZE, 0);
+    let data: id = msg_class![env; NSData alloc];
+    let data: id = if received > 0 {
+        let buffer = env.mem.realloc(buffer, received as u32);
+        msg![env; data initWithBytesNoCopy:buffer length:(received as NSUInteger)]
+    } else {
+        // Errors are treated like the end of the connection.
+        env.mem.free(buffer);
+        env.objc.borrow_mut::<CFSocketHostObject>(s).reached_end = true;
+        msg![env; data init]
+    };
+    let peer = with_socket(env, fd, |socket| Ok(socket.peer_address()))
+        .ok()
+        .flatten();
+    let address = match peer {
+        Some(peer) => data_from_address(env, peer),
+        None => nil,
+    };
+    () = callout.call_from_host(
+        env,
+        (
+            s,
+            kCFSocketDataCallBack,
+            address,
+            data.cast_const().cast_void(),
+            info,
+        ),
+    );
+    release(env, data);
+    if address != nil {
+        release(env, address);
+    }
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(CFSocketCreate(_, _, _, _, _, _, _)),
+    export_c_func!(CFSocketCreateWithNative(_, _, _, _, _)),
+    export_c_func!(CFSocketConnectToAddress(_, _, _)),
+    export_c_func!(CFSocketSetAddress(_, _)),
+    export_c_func!(CFSocketSendData(_, _, _, _)),
+    export_c_func!(CFSocketInvalidate(_)),
+    export_c_func!(CFSocketIsValid(_)),
+    export_c_func!(CFSocketGetNative(_)),
+    export_c_func!(CFSocketGetSocketFlags(_)),
+    export_c_func!(CFSocketSetSocketFlags(_, _)),
+    export_c_func!(CFSocketEnableCallBacks(_, _)),
+    export_c_func!(CFSocketDisableCallBacks(_, _)),
+    export_c_func!(CFSocketCreateRunLoopSource(_, _, _)),
+];
@@ -0,0 +1,565 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `CFReadStream` and `CFWriteStream`, for sockets only.
+//!
+//! These are toll-free bridged to `NSInputStream` and `NSOutputStream` in
+//! Apple's implementation, but that isn't implemented here.
+//!
+//! The two streams of a pair share a socket from [crate::libc::socket], which
+//! is connected when either stream is opened. A stream scheduled on a run loop
+//! is a run loop source, see [handle_stream].
+
+use super::cf_allocator::{kCFAllocatorDefault, CFAllocatorRef};
+use super::cf_run_loop::{CFRunLoopMode, CFRunLoopRef};
+use super::cf_string::CFStringRef;
+use super::{CFIndex, CFOptionFlags};
+use crate::abi::{CallFromHost, GuestFunction};
+use crate::dyld::{export_c_func, FunctionExports};
+use crate::frameworks::foundation::{ns_run_loop, ns_string};
+use crate::libc::posix_io::{close, FileDescriptor};
+use crate::libc::socket::{self, with_socket, ReadReadiness};
+use crate::mem::{ConstPtr, ConstVoidPtr, GuestUSize, MutPtr, MutVoidPtr, Ptr, SafeRead};
+use crate::objc::{
+    id, msg, msg_class, nil, objc_classes, release, retain, Class, ClassExports, HostObject,
+    NSZonePtr,
+};
+use crate::Environment;
+use std::cell::RefCell;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::rc::Rc;
+use std::time::Duration;
+
+pub type CFReadStreamRef = super::CFTypeRef;
+pub type CFWriteStreamRef = super::CFTypeRef;
+
+type CFStreamStatus = CFIndex;
+const kCFStreamStatusNotOpen: CFStreamStatus = 0;
+const kCFStreamStatusOpen: CFStreamStatus = 2;
+const kCFStreamStatusAtEnd: CFStreamStatus = 5;
+const kCFStreamStatusClosed: CFStreamStatus = 6;
+const kCFStreamStatusError: CFStreamStatus = 7;
+
+type CFStreamEventType = CFOptionFlags;
+const kCFStreamEventOpenCompleted: CFStreamEventType = 1;
+const kCFStreamEventHasBytesAvailable: CFStreamEventType = 2;
+const kCFStreamEventCanAcceptBytes: CFStreamEventType = 4;
+const kCFStreamEventErrorOccurred: CFStreamEventType = 8;
+const kCFStreamEventEndEncountered: CFStreamEventType = 16;
+
+/// How long connecting the socket to each address of the host may take when a
+/// stream is opened. Other guest threads keep running while it connects, see
+/// [crate::libc::socket].
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// void (*CFReadStreamClientCallBack)(CFReadStreamRef stream,
+//                                    CFStreamEventType type,
+//                                    void *clientCallBackInfo)
+// (CFWriteStreamClientCallBack is the same.)
+type CFStreamClientCallBack = GuestFunction;
+
+#[repr(C, packed)]
+pub struct CFStreamClientContext {
+    version: CFIndex,
+    info: MutVoidPtr,
+    retain_callback: GuestFunction,
+    release_callback: GuestFunction,
+    copy_desc_callback: GuestFunction,
+}
+unsafe impl SafeRead for CFStreamClientContext {}
+
+/// The socket shared by a pair of streams.
+struct StreamSocket {
+    fd: FileDescriptor,
+    /// Host and port to connect to when a stream is first opened, if the pair
+    /// was created with `CFStreamCreatePairWithSocketToHost`.
+    connect_to: Option<(String, u16)>,
+    /// Whether to close the socket when the last open stream is closed.
+    owns_socket: bool,
+    open_streams: u32,
+}
+
+struct CFStreamHostObject {
+    socket: Option<Rc<RefCell<StreamSocket>>>,
+    status: CFStreamStatus,
+    client_events: CFStreamEventType,
+    client_callback: Option<CFStreamClientCallBack>,
+    client_info: MutVoidPtr,
+    client_release_callback: Option<GuestFunction>,
+    /// Events to be delivered by the run loop that aren't found by polling
+    /// the socket.
+    pending_events: CFStreamEventType,
+    /// Set when `kCFStreamEventHasBytesAvailable` or
+    /// `kCFStreamEventCanAcceptBytes` has been delivered, and cleared when the
+    /// client reads or writes, so the event isn't repeated every iteration.
+    ready_event_delivered: bool,
+}
+impl HostObject for CFStreamHostObject {}
+
+impl CFStreamHostObject {
+    fn new() -> CFStreamHostObject {
+        CFStreamHostObject {
+            socket: None,
+            status: kCFStreamStatusNotOpen,
+            client_events: 0,
+            client_callback: None,
+            client_info: Ptr::null(),
+            client_release_callback: None,
+            pending_events: 0,
+            ready_event_delivered: false,
+        }
+    }
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+// Not real class names, see the module comment.
+@implementation _CFReadStream: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(CFStreamHostObject::new());
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+- (())dealloc {
+    // The run loop retains scheduled streams, so there's no need to
+    // unschedule it.
+    close_stream(env, this);
+    set_client(env, this, 0, None, Ptr::null());
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+@end
+
+@implementation _CFWriteStream: _CFReadStream
+@end
+
+};
+
+fn is_read_stream(env: &mut Environment, stream: id) -> bool {
+    let class: Class = msg![env; stream class];
+    class != env.objc.get_known_class("_CFWriteStream", &mut env.mem)
+}
+
+fn create_pair(
+    env: &mut Environment,
+    socket: StreamSocket,
+    read_stream: MutPtr<CFReadStreamRef>,
+    write_stream: MutPtr<CFWriteStreamRef>,
+) {
+    let socket = Rc::new(RefCell::new(socket));
+    if !read_stream.is_null() {
+        let new: id = msg_class![env; _CFReadStream alloc];
+        env.objc.borrow_mut::<CFStreamHostObject>(new).socket = Some(socket.clone());
+        env.mem.write(read_stream, new);
+    }
+    if !write_stream.is_null() {
+        let new: id = msg_class![env; _CFWriteStream alloc];
+        env.objc.borrow_mut::<CFStreamHostObject>(new).socket = Some(socket);
+        env.mem.write(write_stream, new);
+    }
+}
+
+fn CFStreamCreatePairWithSocketToHost(
+    env: &mut Environment,
+    allocator: CFAllocatorRef,
+    host: CFStringRef,
+    port: u32,
+    read_stream: MutPtr<CFReadStreamRef>,
+    write_stream: MutPtr<CFWriteStreamRef>,
+) {
+    assert!(allocator == kCFAllocatorDefault); // unimplemented
+    let host = ns_string::to_rust_string(env, host).into_owned();
+    log_dbg!("CFStreamCreatePairWithSocketToHost({:?}, {})", host, port);
+    let Ok(port) = u16::try_from(port) else {
+        log!(
+            "Warning: CFStreamCreatePairWithSocketToHost() with invalid port {}, returning NULL streams",
+            port
+        );
+        for stream in [read_stream, write_stream] {
+            if !stream.is_null() {
+                env.mem.write(stream, nil);
+            }
+        }
+        return;
+    };
+    let fd = socket::new_socket(env);
+    let socket = StreamSocket {
+        fd,
+        connect_to: Some((host, port)),
+        owns_socket: true,
+        open_streams: 0,
+    };
+    create_pair(env, socket, read_stream, write_stream);
+}
+
+fn CFStreamCreatePairWithSocket(
+    env: &mut Environment,
+    allocator: CFAllocatorRef,
+    sock: FileDescriptor,
+    read_stream: MutPtr<CFReadStreamRef>,
+    write_stream: MutPtr<CFWriteStreamRef>,
+) {
+    assert!(allocator == kCFAllocatorDefault); // unimplemented
+
+    // The socket is assumed to be connected already, and isn't closed with
+    // the streams unless kCFStreamPropertyShouldCloseNativeSocket is set
+    // (TODO).
+    let socket = StreamSocket {
+        fd: sock,
+        connect_to: None,
+        owns_socket: false,
+        open_streams: 0,
+    };
+    create_pair(env, socket, read_stream, write_stream);
+}
+
+fn open_stream(env: &mut Environment, stream: id) -> bool {
+    let host_object = env.objc.borrow_mut::<CFStreamHostObject>(stream);
+    if host_object.status != kCFStreamStatusNotOpen {
+        return false;
+    }
+    let socket = host_object.socket.clone().unwrap();
+
+    // The first stream of the pair to be opened connects the socket.
+    let (fd, connect_to) = {
+        let mut socket = socket.borrow_mut();
+        (socket.fd, socket.connect_to.take())
+    };
+    if let Some((host, port)) = connect_to {
+        let addrs: Vec<SocketAddr> = match (host.as_str(), port).to_socket_addrs() {
+            Ok(addrs) => addrs.collect(),
+            Err(e) => {
+                log!("Warning: couldn't resolve {:?}: {}", host, e);
+                Vec::new()
+            }
+        };
+        if let Err(errno) = socket::connect_socket(env, fd, &addrs, Some(CONNECT_TIMEOUT)) {
+            log!(
+                "Warning: stream {:?} couldn't connect to {}:{} (errno {}), open failed",
+                stream,
+                host,
+                port,
+                errno
+            );
+            env.objc.borrow_mut::<CFStreamHostObject>(stream).status = kCFStreamStatusError;
+            return false;
+        }
+    }
+
+    socket.borrow_mut().open_streams += 1;
+    let host_object = env.objc.borrow_mut::<CFStreamHostObject>(stream);
+    host_object.status = kCFStreamStatusOpen;
+    host_object.pending_events |= kCFStreamEventOpenCompleted;
+    log_dbg!("Opened stream {:?} for fd {:?}", stream, fd);
+    true
+}
+
+/// Close a stream, without unscheduling it from its run loop.
+fn close_stream(env: &mut Environment, stream: id) {
+    let host_object = env.objc.borrow_mut::<CFStreamHostObject>(stream);
+    if matches!(
+        host_object.status,
+        kCFStreamStatusNotOpen | kCFStreamStatusClosed
+    ) {
+        return;
+    }
+    host_object.status = kCFStreamStatusClosed;
+    host_object.pending_events = 0;
+    let Some(socket) = host_object.socket.clone() else {
+        return;
+    };
+    let mut socket = socket.borrow_mut();
+    socket.open_streams -= 1;
+    if socket.open_streams == 0 && socket.owns_socket {
+        log_dbg!("Closing socket {:?} of stream {:?}", socket.fd, stream);
+        socket.owns_socket = false;
+        let fd = socket.fd;
+        drop(socket);
+        close(env, fd);
+    }
+}
+
+fn stream_fd(env: &mut Environment, stream: id) -> FileDescriptor {
+    let host_object = env.objc.borrow::<CFStreamHostObject>(stream);
+    let fd = host_object.socket.as_ref().unwrap().borrow().fd;
+    fd
+}
+
+fn set_client(
+    env: &mut Environment,
+    stream: id,
+    events: CFStreamEventType,
+    callback: Option<CFStreamClientCallBack>,
+    context_ptr: ConstPtr<CFStreamClientContext>,
+) {
+    let host_object = env.objc.borrow_mut::<CFStreamHostObject>(stream);
+    let old_info = host_object.client_info;
+    let old_release_callback = host_object.client_release_callback.take();
+    host_object.client_events = 0;
+    host_object.client_callback = None;
+    host_object.client_info = Ptr::null();
+    if let Some(release_callback) = old_release_callback {
+        () = release_callback.call_from_host(env, (old_info.cast_const(),));
+    }
+
+    let Some(callback) = callback else {
+        return;
+    };
+    let (info, release_callback) = if context_ptr.is_null() {
+        (Ptr::null(), None)
+    } else {
+        let context = env.mem.read(context_ptr);
+        let version = context.version;
+        assert_eq!(version, 0);
+        let mut info = context.info;
+        let retain_callback = context.retain_callback;
+        if !retain_callback.to_ptr().is_null() {
+            let retained: ConstVoidPtr = retain_callback.call_from_host(env, (info.cast_const(),));
+            info = retained.cast_mut();
+        }
+        let release_callback = context.release_callback;
+        let release_callback = (!release_callback.to_ptr().is_null()).then_some(release_callback);
+        (info, release_callback)
+    };
+    let host_object = env.objc.borrow_mut::<CFStreamHostObject>(stream);
+    host_object.client_events = events;
+    host_object.client_callback = Some(callback);
+    host_object.client_info = info;
+    host_object.client_release_callback = release_callback;
+}
+
+fn schedule_stream(env: &mut Environment, stream: id, run_loop: CFRunLoopRef) {
+    // TODO: handle run loop modes
+    ns_run_loop::add_source(env, run_loop, stream);
+}
+
+fn unschedule_stream(env: &mut Environment, stream: id, run_loop: CFRunLoopRef) {
+    ns_run_loop::remove_source(env, run_loop, stream);
+}
+
+/// Check whether a scheduled stream has events for its client and deliver them
+/// if so, for use by the run loop. Returns `true` if the client was called.
+pub fn handle_stream(env: &mut Environment, stream: id) -> bool {
+    let is_read = is_read_stream(env, stream);
+    let host_object = env.objc.borrow_mut::<CFStreamHostObject>(stream);
+    let Some(callback) = host_object.client_callback else {
+        return false;
+    };
+    let mut events = std::mem::take(&mut host_object.pending_events);
+    if host_object.status == kCFStreamStatusOpen && !host_object.ready_event_delivered {
+        let fd = host_object.socket.as_ref().unwrap().borrow().fd;
+        if is_read {
+            match with_socket(env, fd, |socket| Ok(socket.poll_readable())) {
+                Ok(ReadReadiness::NotReady) => (),
+                Ok(ReadReadiness::Ready) => events |= kCFStreamEventHasBytesAvailable,
+                Ok(ReadReadiness::AtEnd) => {
+                    env.objc.borrow_mut::<CFStreamHostObject>(stream).status = kCFStreamStatusAtEnd;
+                    events |= kCFStreamEventEndEncountered;
+                }
+                Err(_) => {
+                    env.objc.borrow_mut::<CFStreamHostObject>(stream).status = kCFStreamStatusError;
+                    events |= kCFStreamEventErrorOccurred;
+                }
+            }
+        } else if with_socket(env, fd, |socket| Ok(socket.poll_writable())).unwrap_or(false) {
+            events |= kCFStreamEventCanAcceptBytes;
+        }
+    }
+
+    let host_object = env.objc.borrow_mut::<CFStreamHostObject>(stream);
+    let info = host_object.client_info;
+    events &= host_object.client_events;
+    if events & (kCFStreamEventHasBytesAvailable | kCFStreamEventCanAcceptBytes) != 0 {
+        host_object.ready_event_delivered = true;
+    }
+    if events == 0 {
+        return false;
+    }
+
+    // The client might close and release the stream in its callback.
+    retain(env, stream);
+    for event in [
+        kCFStreamEventOpenCompleted,
+        kCFStreamEventHasBytesAvailable,
+        kCFStreamEventCanAcceptBytes,
+        kCFStreamEventErrorOccurred,
+        kCFStreamEventEndEncountered,
+    ] {
+        if events & event == 0 {
+            continue;
+        }
+        let host_object = env.objc.borrow::<CFStreamHostObject>(stream);
+        if host_object.client_callback.is_none() || host_object.status == kCFStreamStatusClosed {
+            break;
+        }
+        log_dbg!("Delivering event {:#x} for stream {:?}", event, stream);
+        () = callback.call_from_host(env, (stream, event, info));
+    }
+    release(env, stream);
+    true
+}
+
+fn CFReadStreamOpen(env: &mut Environment, stream: CFReadStreamRef) -> bool {
+    open_stream(env, stream)
+}
+fn CFWriteStreamOpen(env: &mut Environment, stream: CFWriteStreamRef) -> bool {
+    open_stream(env, stream)
+}
+
+fn CFReadStreamClose(env: &mut Environment, stream: CFReadStreamRef) {
+    close_stream(env, stream);
+}
+fn CFWriteStreamClose(env: &mut Environment, stream: CFWriteStreamRef) {
+    close_stream(env, stream);
+}
+
+fn CFReadStreamGetStatus(env: &mut Environment, stream: CFReadStreamRef) -> CFStreamStatus {
+    env.objc.borrow::<CFStreamHostObject>(stream).status
+}
+fn CFWriteStreamGetStatus(env: &mut Environment, stream: CFWriteStreamRef) -> CFStreamStatus {
+    env.objc.borrow::<CFStreamHostObject>(stream).status
+}
+
+fn CFReadStreamRead(
+    env: &mut Environment,
+    stream: CFReadStreamRef,
+    buffer: MutPtr<u8>,
+    length: CFIndex,
+) -> CFIndex {
+    let host_object = env.objc.borrow_mut::<CFStreamHostObject>(stream);
+    match host_object.status {
+        kCFStreamStatusOpen => (),
+        kCFStreamStatusAtEnd => return 0,
+        _ => return -1,
+    }
+    host_object.ready_event_delivered = false;
+    let fd = stream_fd(env, stream);
+    let received = socket::recv(env, fd, buffer.cast(), length as GuestUSize, 0);
+    if received == 0 && length != 0 {
+        env.objc.borrow_mut::<CFStreamHostObject>(stream).status = kCFStreamStatusAtEnd;
+    } else if received < 0 {
+        env.objc.borrow_mut::<CFStreamHostObject>(stream).status = kCFStreamStatusError;
+    }
+    received
+}
+
+fn CFWriteStreamWrite(
+    env: &mut Environment,
+    stream: CFWriteStreamRef,
+    buffer: ConstPtr<u8>,
+    length: CFIndex,
+) -> CFIndex {
+    let host_object = env.objc.borrow_mut::<CFStreamHostObject>(stream);
+    if host_object.status != kCFStreamStatusOpen {
+        return -1;
+    }
+    host_object.ready_event_delivered = false;
+    let fd = stream_fd(env, stream);
+    let sent = socket::send(env, fd, buffer.cast(), length as GuestUSize, 0);
+    if sent < 0 {
+        env.objc.borrow_mut::<CFStreamHostObject>(stream).status = kCFStreamStatusError;
+    }
+    sent
+}
+
+fn CFReadStreamHasBytesAvailable(env: &mut Environment, stream: CFReadStreamRef) -> bool {
+    if env.objc.borrow::<CFStreamHostObject>(stream).status != kCFStreamStatusOpen {
+        return false;
+    }
+    let fd = stream_fd(env, stream);
+    with_socket(env, fd, |socket| {
+        Ok(socket.poll_readable() == ReadReadiness::Ready)
+    })
+    .unwrap_or(false)
+}
+
+fn CFWriteStreamCanAcceptBytes(env: &mut Environment, stream: CFWriteStreamRef) -> bool {
+    if env.objc.borrow::<CFStreamHostObject>(stream).status != kCFStreamStatusOpen {
+        return false;
+    }
+    let fd = stream_fd(env, stream);
+    with_socket(env, fd, |socket| Ok(socket.poll_writable())).unwrap_or(false)
+}
+
+fn CFReadStreamSetClient(
+    env: &mut Environment,
+    stream: CFReadStreamRef,
+    events: CFStreamEventType,
+    callback: CFStreamClientCallBack,
+    context: ConstPtr<CFStreamClientContext>,
+) -> bool {
+    let callback = (!callback.to_ptr().is_null()).then_some(callback);
+    set_client(env, stream, events, callback, context);
+    true
+}
+fn CFWriteStreamSetClient(
+    env: &mut Environment,
+    stream: CFWriteStreamRef,
+    events: CFStreamEventType,
+    callback: CFStreamClientCallBack,
+    context: ConstPtr<CFStreamClientContext>,
+) -> bool {
+    let callback = (!callback.to_ptr().is_null()).then_some(callback);
+    set_client(env, stream, events, callback, context);
+    true
+}
+
+fn CFReadStreamScheduleWithRunLoop(
+    env: &mut Environment,
+    stream: CFReadStreamRef,
+    run_loop: CFRunLoopRef,
+    _mode: CFRunLoopMode,
+) {
+    schedule_stream(env, stream, run_loop);
+}
+fn CFWriteStreamScheduleWithRunLoop(
+    env: &mut Environment,
+    stream: CFWriteStreamRef,
+    run_loop: CFRunLoopRef,
+    _mode: CFRunLoopMode,
+) {
+    schedule_stream(env, stream, run_loop);
+}
+
+fn CFReadStreamUnscheduleFromRunLoop(
+    env: &mut Environment,
+    stream: CFReadStreamRef,
+    run_loop: CFRunLoopRef,
+    _mode: CFRunLoopMode,
+) {
+    unschedule_stream(env, stream, run_loop);
+}
+fn CFWriteStreamUnscheduleFromRunLoop(
+    env: &mut Environment,
+    stream: CFWriteStreamRef,
+    run_loop: CFRunLoopRef,
+    _mode: CFRunLoopMode,
+) {
+    unschedule_stream(env, stream, run_loop);
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(CFStreamCreatePairWithSocketToHost(_, _, _, _, _)),
+    export_c_func!(CFStreamCreatePairWithSocket(_, _, _, _)),
+    export_c_func!(CFReadStreamOpen(_)),
+    export_c_func!(CFWriteStreamOpen(_)),
+    export_c_func!(CFReadStreamClose(_)),
+    export_c_func!(CFWriteStreamClose(_)),
+    export_c_func!(CFReadStreamGetStatus(_)),
+    export_c_func!(CFWriteStreamGetStatus(_)),
+    export_c_func!(CFReadStreamRead(_, _, _)),
+    export_c_func!(CFWriteStreamWrite(_, _, _)),
+    export_c_func!(CFReadStreamHasBytesAvailable(_)),
+    export_c_func!(CFWriteStreamCanAcceptBytes(_)),
+    export_c_func!(CFReadStreamSetClient(_, _, _, _)),
+    export_c_func!(CFWriteStreamSetClient(_, _, _, _)),
+    export_c_func!(CFReadStreamScheduleWithRunLoop(_, _, _)),
+    export_c_func!(CFWriteStreamScheduleWithRunLoop(_, _, _)),
+    export_c_func!(CFReadStreamUnscheduleFromRunLoop(_, _, _)),
+    export_c_func!(CFWriteStreamUnscheduleFromRunLoop(_, _, _)),
+];
@@ -12,10 +12,10 @@ use super::cf_allocator::{kCFAllocatorDefault, CFAllocatorRef};
 use super::cf_dictionary::CFDictionaryRef;
 use crate::abi::{DotDotDot, VaList};
 use crate::dyld::{export_c_func, FunctionExports};
-use crate::frameworks::core_foundation::{CFIndex, CFOptionFlags};
-use crate::frameworks::foundation::{ns_string, NSInteger};
+use crate::frameworks::core_foundation::{CFIndex, CFOptionFlags, CFRange};
+use crate::frameworks::foundation::{ns_string, NSInteger, NSRange, NSUInteger};
 use crate::mem::{ConstPtr, MutPtr};
-use crate::objc::{id, msg, msg_class};
+use crate::objc::{id, msg, msg_class, release};
 use crate::Environment;
 
 pub type CFStringRef = super::CFTypeRef;
@@ -90,8 +90,45 @@ fn CFStringCreateWithFormatAndArguments(
     ns_string::from_rust_string(env, res)
 }
 
+fn CFStringAppendFormat(
+    env: &mut Environment,
+    the_string: CFMutableStringRef,
+    format_options: CFDictionaryRef,
+    format: CFStringRef,
+    args: DotDotDot,
+) {
+    CFStringAppendFormatAndArguments(env, the_string, format_options, format, args.start())
+}
+
+fn CFStringAppendFormatAndArguments(
+    env: &mut Environment,
+    the_string: CFMutableStringRef,
+    _format_options: CFDictionaryRef,
+    format: CFStringRef,
+    args: VaList,
+) {
+    let res = ns_string::with_format(env, format, args);
+    let res = ns_string::from_rust_string(env, res);
+    () = msg![env; the_string appendString:res];
+    release(env, res);
+}
+
 pub type CFComparisonResult = CFIndex;
 pub type CFStringCompareFlags = CFOptionFlags;
+pub const kCFCompareNonliteral: CFStringCompareFlags = 16;
+pub const kCFCompareLocalized: CFStringCompareFlags = 32;
+
+/// Convert `CFStringCompareFlags` to `NSStringCompareOptions`. Most of the
+/// flags have the same values, but CoreFoundation comparisons are literal
+/// unless asked otherwise, whereas Foundation's are the opposite.
+fn compare_flags_to_options(flags: CFStringCompareFlags) -> ns_string::NSStringCompareOptions {
+    // TODO: kCFCompareLocalized (there's no locale support anyway)
+    let mut options = flags & !(kCFCompareNonliteral | kCFCompareLocalized);
+    if flags & kCFCompareNonliteral == 0 {
+        options |= ns_string::NSLiteralSearch;
+    }
+    options
+}
 
 fn CFStringCompare(
     env: &mut Environment,
@@ -99,7 +136,23 @@ fn CFStringCompare(
     b: CFStringRef,
     flags: CFStringCompareFlags,
 ) -> CFComparisonResult {
-    msg![env; a compare:b options:flags]
+    let options = compare_flags_to_options(flags);
+    msg![env; a compare:b options:options]
+}
+
+fn CFStringCompareWithOptions(
+    env: &mut Environment,
+    a: CFStringRef,
+    b: CFStringRef,
+    range: CFRange,
+    flags: CFStringCompareFlags,
+) -> CFComparisonResult {
+    let options = compare_flags_to_options(flags);
+    let range = NSRange {
+        location: range.location.try_into().unwrap(),
+        length: range.length.try_into().unwrap(),
+    };
+    msg![env; a compare:b options:options range:range]
 }
 
 fn CFStringGetCString(
@@ -110,12 +163,61 @@ fn CFStringGetCString(
     encoding: CFStringEncoding,
 ) -> bool {
     let encoding = CFStringConvertEncodingToNSStringEncoding(env, encoding);
-    let buffer_size: u32 = buffer_size.try_into().unwrap();
+    // A negative size can't fit anything.
+    let Ok(buffer_size) = NSUInteger::try_from(buffer_size) else {
+        return false;
+    };
     msg![env; string getCString:buffer
                       maxLength:buffer_size
                        encoding:encoding]
 }
 
+fn CFStringGetLength(env: &mut Environment, string: CFStringRef) -> CFIndex {
+    let length: NSUInteger = msg![env; string length];
+    length.try_into().unwrap()
+}
+
+fn CFStringGetMaximumSizeForEncoding(
+    _env: &mut Environment,
+    length: CFIndex,
+    encoding: CFStringEncoding,
+) -> CFIndex {
+    // The length is in UTF-16 code units. A surrogate pair takes four bytes
+    // in UTF-8, so three bytes per code unit is enough.
+    let bytes_per_unit = match encoding {
+        kCFStringEncodingASCII => 1,
+        kCFStringEncodingUTF8 => 3,
+        kCFStringEncodingUTF16 | kCFStringEncodingUTF16BE | kCFStringEncodingUTF16LE => 2,
+        _ => unimplemented!("Unhandled: CFStringEncoding {:#x}", encoding),
+    };
+    length.saturating_mul(bytes_per_unit)
+}
+
+fn CFStringGetMaximumSizeOfFileSystemRepresentation(
+    env: &mut Environment,
+    string: CFStringRef,
+) -> CFIndex {
+    let length = CFStringGetLength(env, string);
+    // include null terminator
+    CFStringGetMaximumSizeForEncoding(env, length, kCFStringEncodingUTF8).saturating_add(1)
+}
+
+fn CFStringGetFileSystemRepresentation(
+    env: &mut Environment,
+    string: CFStringRef,
+    buffer: MutPtr<u8>,
+    max_buffer_length: CFIndex,
+) -> bool {
+    // TODO: Apple uses decomposed UTF-8 for file system paths.
+    CFStringGetCString(
+        env,
+        string,
+        buffer,
+        max_buffer_length,
+        kCFStringEncodingUTF8,
+    )
+}
+
 fn CFStringCreateMutableCopy(
     env: &mut Environment,
     allocator: CFAllocatorRef,
@@ -140,8 +242,15 @@ pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(CFStringCreateWithCString(_, _, _)),
     export_c_func!(CFStringCreateWithFormat(_, _, _, _)),
     export_c_func!(CFStringCreateWithFormatAndArguments(_, _, _, _)),
+    export_c_func!(CFStringAppendFormat(_, _, _, _)),
+    export_c_func!(CFStringAppendFormatAndArguments(_, _, _, _)),
     export_c_func!(CFStringCompare(_, _, _)),
+    export_c_func!(CFStringCompareWithOptions(_, _, _, _)),
     export_c_func!(CFStringGetCString(_, _, _, _)),
+    export_c_func!(CFStringGetLength(_)),
+    export_c_func!(CFStringGetMaximumSizeForEncoding(_, _)),
+    export_c_func!(CFStringGetMaximumSizeOfFileSystemRepresentation(_)),
+    export_c_func!(CFStringGetFileSystemRepresentation(_, _, _)),
     export_c_func!(CFStringCreateMutableCopy(_, _, _)),
     export_c_func!(CFStringNormalize(_, _)),
 ];
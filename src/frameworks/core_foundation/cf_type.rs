@@ -10,6 +10,7 @@ use crate::objc;
 use crate::Environment;
 
 pub type CFTypeRef = objc::id;
+pub type CFTypeID = u32;
 
 pub fn CFRetain(env: &mut Environment, object: CFTypeRef) -> CFTypeRef {
     assert!(!object.is_null()); // not allowed, unlike for normal objc objects
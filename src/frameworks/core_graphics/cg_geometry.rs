@@ -13,11 +13,60 @@ use crate::dyld::{export_c_func, ConstantExports, FunctionExports, HostConstant}
 use crate::mem::SafeRead;
 use crate::Environment;
 
+/// Parse the inside of `{a, b}`. Whitespace has already been stripped by
+/// [strip_whitespace], so `"1,2"` and `"1 , 2"` are equally acceptable.
 fn parse_tuple(s: &str) -> Result<(f32, f32), ()> {
-    let (a, b) = s.split_once(", ").ok_or(())?;
+    let (a, b) = s.split_once(',').ok_or(())?;
     Ok((a.parse().map_err(|_| ())?, b.parse().map_err(|_| ())?))
 }
 
+/// Apple's parsers don't care about whitespace anywhere in the string.
+fn strip_whitespace(s: &str) -> String {
+    s.chars().filter(|c| !c.is_whitespace()).collect()
+}
+
+/// Format a number like C's `%g` with the precision Apple uses for
+/// `NSStringFromCGPoint` and friends (`FLT_DIG`, i.e. 6 on 32-bit).
+fn format_g(value: CGFloat) -> String {
+    const PRECISION: usize = 6;
+
+    let value = value as f64;
+    if value.is_nan() {
+        return "nan".to_string();
+    } else if value.is_infinite() {
+        return if value < 0.0 { "-inf" } else { "inf" }.to_string();
+    } else if value == 0.0 {
+        return if value.is_sign_negative() { "-0" } else { "0" }.to_string();
+    }
+
+    // Round to the precision first, since that can change the exponent
+    // (e.g. 999999.5 becomes 1e+06).
+    let scientific = format!("{:.*e}", PRECISION - 1, value);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+
+    fn trim_zeros(s: &str) -> &str {
+        if s.contains('.') {
+            s.trim_end_matches('0').trim_end_matches('.')
+        } else {
+            s
+        }
+    }
+
+    if exponent < -4 || exponent >= PRECISION as i32 {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!(
+            "{}e{}{:02}",
+            trim_zeros(mantissa),
+            sign,
+            exponent.unsigned_abs()
+        )
+    } else {
+        let decimals = (PRECISION as i32 - 1 - exponent) as usize;
+        trim_zeros(&format!("{:.*}", decimals, value)).to_string()
+    }
+}
+
 #[derive(Copy, Clone, Debug, Default, PartialEq)]
 #[repr(C, packed)]
 pub struct CGPoint {
@@ -43,6 +92,7 @@ impl GuestArg for CGPoint {
 impl std::str::FromStr for CGPoint {
     type Err = ();
     fn from_str(s: &str) -> Result<CGPoint, ()> {
+        let s = strip_whitespace(s);
         let s = s.strip_prefix('{').ok_or(())?.strip_suffix('}').ok_or(())?;
         let (x, y) = parse_tuple(s)?;
         Ok(CGPoint { x, y })
@@ -51,7 +101,7 @@ impl std::str::FromStr for CGPoint {
 impl std::fmt::Display for CGPoint {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
         let &CGPoint { x, y } = self;
-        write!(f, "{{{}, {}}}", x, y)
+        write!(f, "{{{}, {}}}", format_g(x), format_g(y))
     }
 }
 // This function is rare because it is usually inlined.
@@ -86,6 +136,7 @@ impl GuestArg for CGSize {
 impl std::str::FromStr for CGSize {
     type Err = ();
     fn from_str(s: &str) -> Result<CGSize, ()> {
+        let s = strip_whitespace(s);
         let s = s.strip_prefix('{').ok_or(())?.strip_suffix('}').ok_or(())?;
         let (w, h) = parse_tuple(s)?;
         Ok(CGSize {
@@ -97,7 +148,7 @@ impl std::str::FromStr for CGSize {
 impl std::fmt::Display for CGSize {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
         let &CGSize { width, height } = self;
-        write!(f, "{{{}, {}}}", width, height)
+        write!(f, "{{{}, {}}}", format_g(width), format_g(height))
     }
 }
 // This function is rare because it is usually inlined.
@@ -135,12 +186,13 @@ impl GuestArg for CGRect {
 impl std::str::FromStr for CGRect {
     type Err = ();
     fn from_str(s: &str) -> Result<CGRect, ()> {
+        let s = strip_whitespace(s);
         let s = s
             .strip_prefix("{{")
             .ok_or(())?
             .strip_suffix("}}")
             .ok_or(())?;
-        let (a, b) = s.split_once("}, {").ok_or(())?;
+        let (a, b) = s.split_once("},{").ok_or(())?;
         let (x, y) = parse_tuple(a)?;
         let (width, height) = parse_tuple(b)?;
         Ok(CGRect {
@@ -155,28 +207,262 @@ impl std::fmt::Display for CGRect {
         write!(f, "{{{}, {}}}", origin, size)
     }
 }
-// This function is rare because it is usually inlined.
-fn CGRectEqualToRect(_env: &mut Environment, a: CGRect, b: CGRect) -> bool {
-    a == b
-}
 
 pub const CGRectZero: CGRect = CGRect {
     origin: CGPointZero,
     size: CGSizeZero,
 };
 
+/// The "null rectangle", which is distinct from an empty one: it has no
+/// position at all, so for example [CGRect::union] ignores it.
+pub const CGRectNull: CGRect = CGRect {
+    origin: CGPoint {
+        x: CGFloat::INFINITY,
+        y: CGFloat::INFINITY,
+    },
+    size: CGSizeZero,
+};
+
+// The CGRect* functions are implemented as wrappers around these methods so
+// that host code can use them too, like with CGAffineTransform. As in Apple's
+// implementation, rectangles with negative sizes are standardized first.
+impl CGRect {
+    fn from_edges(min_x: CGFloat, min_y: CGFloat, max_x: CGFloat, max_y: CGFloat) -> Self {
+        CGRect {
+            origin: CGPoint { x: min_x, y: min_y },
+            size: CGSize {
+                width: max_x - min_x,
+                height: max_y - min_y,
+            },
+        }
+    }
+
+    pub fn is_null(self) -> bool {
+        self.origin.x == CGFloat::INFINITY || self.origin.y == CGFloat::INFINITY
+    }
+    pub fn is_empty(self) -> bool {
+        self.is_null() || self.size.width == 0.0 || self.size.height == 0.0
+    }
+    pub fn standardize(self) -> Self {
+        if self.is_null() {
+            return CGRectNull;
+        }
+        let CGRect {
+            mut origin,
+            mut size,
+        } = self;
+        if size.width < 0.0 {
+            origin.x += size.width;
+            size.width = -size.width;
+        }
+        if size.height < 0.0 {
+            origin.y += size.height;
+            size.height = -size.height;
+        }
+        CGRect { origin, size }
+    }
+
+    pub fn min_x(self) -> CGFloat {
+        self.standardize().origin.x
+    }
+    pub fn mid_x(self) -> CGFloat {
+        let rect = self.standardize();
+        rect.origin.x + rect.size.width / 2.0
+    }
+    pub fn max_x(self) -> CGFloat {
+        let rect = self.standardize();
+        rect.origin.x + rect.size.width
+    }
+    pub fn min_y(self) -> CGFloat {
+        self.standardize().origin.y
+    }
+    pub fn mid_y(self) -> CGFloat {
+        let rect = self.standardize();
+        rect.origin.y + rect.size.height / 2.0
+    }
+    pub fn max_y(self) -> CGFloat {
+        let rect = self.standardize();
+        rect.origin.y + rect.size.height
+    }
+    pub fn width(self) -> CGFloat {
+        self.size.width.abs()
+    }
+    pub fn height(self) -> CGFloat {
+        self.size.height.abs()
+    }
+
+    pub fn equal_to(self, other: Self) -> bool {
+        (self.is_null() && other.is_null()) || self.standardize() == other.standardize()
+    }
+    /// The minimum edges are inside the rectangle, the maximum edges are not.
+    pub fn contains_point(self, point: CGPoint) -> bool {
+        !self.is_null()
+            && self.min_x() <= point.x
+            && point.x < self.max_x()
+            && self.min_y() <= point.y
+            && point.y < self.max_y()
+    }
+    pub fn contains_rect(self, other: Self) -> bool {
+        self.union(other).equal_to(self)
+    }
+    /// Rectangles that only touch have an empty intersection, but it isn't the
+    /// null rectangle.
+    pub fn intersection(self, other: Self) -> Self {
+        if self.is_null() || other.is_null() {
+            return CGRectNull;
+        }
+        let min_x = self.min_x().max(other.min_x());
+        let max_x = self.max_x().min(other.max_x());
+        let min_y = self.min_y().max(other.min_y());
+        let max_y = self.max_y().min(other.max_y());
+        if max_x < min_x || max_y < min_y {
+            return CGRectNull;
+        }
+        Self::from_edges(min_x, min_y, max_x, max_y)
+    }
+    pub fn intersects(self, other: Self) -> bool {
+        !self.intersection(other).is_null()
+    }
+    pub fn union(self, other: Self) -> Self {
+        if self.is_null() {
+            return other.standardize();
+        } else if other.is_null() {
+            return self.standardize();
+        }
+        Self::from_edges(
+            self.min_x().min(other.min_x()),
+            self.min_y().min(other.min_y()),
+            self.max_x().max(other.max_x()),
+            self.max_y().max(other.max_y()),
+        )
+    }
+    /// Insetting by more than half the size gives the null rectangle.
+    pub fn inset(self, dx: CGFloat, dy: CGFloat) -> Self {
+        if self.is_null() {
+            return CGRectNull;
+        }
+        let rect = self.standardize();
+        let width = rect.size.width - dx * 2.0;
+        let height = rect.size.height - dy * 2.0;
+        if width < 0.0 || height < 0.0 {
+            return CGRectNull;
+        }
+        CGRect {
+            origin: CGPoint {
+                x: rect.origin.x + dx,
+                y: rect.origin.y + dy,
+            },
+            size: CGSize { width, height },
+        }
+    }
+    pub fn offset(self, dx: CGFloat, dy: CGFloat) -> Self {
+        if self.is_null() {
+            return CGRectNull;
+        }
+        let mut rect = self.standardize();
+        rect.origin.x += dx;
+        rect.origin.y += dy;
+        rect
+    }
+    /// The smallest rectangle with integer co-ordinates that contains this one.
+    pub fn integral(self) -> Self {
+        if self.is_null() {
+            return CGRectNull;
+        }
+        Self::from_edges(
+            self.min_x().floor(),
+            self.min_y().floor(),
+            self.max_x().ceil(),
+            self.max_y().ceil(),
+        )
+    }
+}
+
+// This function is rare because it is usually inlined.
+fn CGRectEqualToRect(_env: &mut Environment, a: CGRect, b: CGRect) -> bool {
+    a.equal_to(b)
+}
+fn CGRectIsNull(_env: &mut Environment, rect: CGRect) -> bool {
+    rect.is_null()
+}
+fn CGRectIsEmpty(_env: &mut Environment, rect: CGRect) -> bool {
+    rect.is_empty()
+}
+fn CGRectStandardize(_env: &mut Environment, rect: CGRect) -> CGRect {
+    rect.standardize()
+}
+fn CGRectGetMinX(_env: &mut Environment, rect: CGRect) -> CGFloat {
+    rect.min_x()
+}
+fn CGRectGetMidX(_env: &mut Environment, rect: CGRect) -> CGFloat {
+    rect.mid_x()
+}
+fn CGRectGetMaxX(_env: &mut Environment, rect: CGRect) -> CGFloat {
+    rect.max_x()
+}
+fn CGRectGetMinY(_env: &mut Environment, rect: CGRect) -> CGFloat {
+    rect.min_y()
+}
+fn CGRectGetMidY(_env: &mut Environment, rect: CGRect) -> CGFloat {
+    rect.mid_y()
+}
+fn CGRectGetMaxY(_env: &mut Environment, rect: CGRect) -> CGFloat {
+    rect.max_y()
+}
+fn CGRectGetWidth(_env: &mut Environment, rect: CGRect) -> CGFloat {
+    rect.width()
+}
+fn CGRectGetHeight(_env: &mut Environment, rect: CGRect) -> CGFloat {
+    rect.height()
+}
 fn CGRectContainsPoint(_env: &mut Environment, rect: CGRect, point: CGPoint) -> bool {
-    rect.origin.x <= point.x
-        && rect.origin.x + rect.size.width > point.x
-        && rect.origin.y <= point.y
-        && rect.origin.y + rect.size.height > point.y
+    rect.contains_point(point)
+}
+fn CGRectContainsRect(_env: &mut Environment, rect1: CGRect, rect2: CGRect) -> bool {
+    rect1.contains_rect(rect2)
+}
+fn CGRectIntersectsRect(_env: &mut Environment, rect1: CGRect, rect2: CGRect) -> bool {
+    rect1.intersects(rect2)
+}
+fn CGRectIntersection(_env: &mut Environment, rect1: CGRect, rect2: CGRect) -> CGRect {
+    rect1.intersection(rect2)
+}
+fn CGRectUnion(_env: &mut Environment, rect1: CGRect, rect2: CGRect) -> CGRect {
+    rect1.union(rect2)
+}
+fn CGRectInset(_env: &mut Environment, rect: CGRect, dx: CGFloat, dy: CGFloat) -> CGRect {
+    rect.inset(dx, dy)
+}
+fn CGRectOffset(_env: &mut Environment, rect: CGRect, dx: CGFloat, dy: CGFloat) -> CGRect {
+    rect.offset(dx, dy)
+}
+fn CGRectIntegral(_env: &mut Environment, rect: CGRect) -> CGRect {
+    rect.integral()
 }
 
 pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(CGPointEqualToPoint(_, _)),
     export_c_func!(CGSizeEqualToSize(_, _)),
     export_c_func!(CGRectEqualToRect(_, _)),
+    export_c_func!(CGRectIsNull(_)),
+    export_c_func!(CGRectIsEmpty(_)),
+    export_c_func!(CGRectStandardize(_)),
+    export_c_func!(CGRectGetMinX(_)),
+    export_c_func!(CGRectGetMidX(_)),
+    export_c_func!(CGRectGetMaxX(_)),
+    export_c_func!(CGRectGetMinY(_)),
+    export_c_func!(CGRectGetMidY(_)),
+    export_c_func!(CGRectGetMaxY(_)),
+    export_c_func!(CGRectGetWidth(_)),
+    export_c_func!(CGRectGetHeight(_)),
     export_c_func!(CGRectContainsPoint(_, _)),
+    export_c_func!(CGRectContainsRect(_, _)),
+    export_c_func!(CGRectIntersectsRect(_, _)),
+    export_c_func!(CGRectIntersection(_, _)),
+    export_c_func!(CGRectUnion(_, _)),
+    export_c_func!(CGRectInset(_, _, _)),
+    export_c_func!(CGRectOffset(_, _, _)),
+    export_c_func!(CGRectIntegral(_)),
 ];
 
 pub const CONSTANTS: ConstantExports = &[
@@ -192,8 +478,244 @@ pub const CONSTANTS: ConstantExports = &[
         "_CGRectZero",
         HostConstant::Custom(|mem| mem.alloc_and_write(CGRectZero).cast().cast_const()),
     ),
+    (
+        "_CGRectNull",
+        HostConstant::Custom(|mem| mem.alloc_and_write(CGRectNull).cast().cast_const()),
+    ),
     (
         "_kCLLocationAccuracyKilometer",
         HostConstant::Custom(|mem| mem.alloc_and_write(0.1f32).cast().cast_const()),
     ),
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_like_printf_g() {
+        assert_eq!(format_g(0.0), "0");
+        assert_eq!(format_g(-0.0), "-0");
+        assert_eq!(format_g(100.0), "100");
+        assert_eq!(format_g(1.5), "1.5");
+        assert_eq!(format_g(-2.25), "-2.25");
+        assert_eq!(format_g(0.1), "0.1");
+        assert_eq!(format_g(1.0 / 3.0), "0.333333");
+        assert_eq!(format_g(123456.0), "123456");
+        assert_eq!(format_g(1234567.0), "1.23457e+06");
+        assert_eq!(format_g(999999.5), "1e+06");
+        assert_eq!(format_g(0.0001), "0.0001");
+        assert_eq!(format_g(0.00001), "1e-05");
+        assert_eq!(format_g(-1e20), "-1e+20");
+    }
+
+    #[test]
+    fn display() {
+        let rect = CGRect {
+            origin: CGPoint { x: -1.5, y: 0.25 },
+            size: CGSize {
+                width: 320.0,
+                height: 480.0,
+            },
+        };
+        assert_eq!(rect.to_string(), "{{-1.5, 0.25}, {320, 480}}");
+        assert_eq!(rect.origin.to_string(), "{-1.5, 0.25}");
+        assert_eq!(rect.size.to_string(), "{320, 480}");
+    }
+
+    #[test]
+    fn parse_tolerates_whitespace() {
+        assert_eq!("{1,2}".parse(), Ok(CGPoint { x: 1.0, y: 2.0 }));
+        assert_eq!(
+            " { -3.5 ,\t4 } ".parse(),
+            Ok(CGSize {
+                width: -3.5,
+                height: 4.0
+            })
+        );
+        assert_eq!(
+            "{ {1, 2},{3,4} }".parse(),
+            Ok(CGRect {
+                origin: CGPoint { x: 1.0, y: 2.0 },
+                size: CGSize {
+                    width: 3.0,
+                    height: 4.0
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn parse_rejects_malformed() {
+        for s in ["", "{}", "{1}", "{1, 2", "1, 2", "{a, 2}", "{1, 2, 3}"] {
+            assert_eq!(s.parse::<CGPoint>(), Err(()), "{:?}", s);
+        }
+        for s in ["{1, 2}", "{{1, 2}, {3}}", "{{1, 2} {3, 4}}"] {
+            assert_eq!(s.parse::<CGRect>(), Err(()), "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        for &(x, y, width, height) in &[
+            (0.0, 0.0, 0.0, 0.0),
+            (-10.0, 20.5, 320.0, 480.0),
+            (0.125, -0.75, 1024.0, 768.0),
+            (-123.456, 1e-3, 99999.0, 0.5),
+        ] {
+            let rect = CGRect {
+                origin: CGPoint { x, y },
+                size: CGSize { width, height },
+            };
+            assert_eq!(rect.to_string().parse(), Ok(rect));
+            assert_eq!(rect.origin.to_string().parse(), Ok(rect.origin));
+            assert_eq!(rect.size.to_string().parse(), Ok(rect.size));
+        }
+    }
+
+    fn rect(x: CGFloat, y: CGFloat, width: CGFloat, height: CGFloat) -> CGRect {
+        CGRect {
+            origin: CGPoint { x, y },
+            size: CGSize { width, height },
+        }
+    }
+
+    /// A variety of rectangles, including empty, null and unstandardized ones,
+    /// for property checks.
+    fn sample_rects() -> Vec<CGRect> {
+        let mut rects = vec![CGRectNull, CGRectZero];
+        for x in [-2.0, 0.0, 1.5] {
+            for y in [-1.0, 0.0, 2.0] {
+                for (width, height) in
+                    [(0.0, 1.0), (2.0, 0.0), (3.0, 2.5), (-2.0, 1.0), (1.0, -4.0)]
+                {
+                    rects.push(rect(x, y, width, height));
+                }
+            }
+        }
+        rects
+    }
+
+    #[test]
+    fn null_and_empty() {
+        assert!(CGRectNull.is_null());
+        assert!(CGRectNull.is_empty());
+        assert!(!CGRectZero.is_null());
+        assert!(CGRectZero.is_empty());
+        assert!(rect(CGFloat::INFINITY, 0.0, 1.0, 1.0).is_null());
+        assert!(!rect(0.0, 0.0, -1.0, -1.0).is_empty());
+        assert!(CGRectNull.equal_to(rect(CGFloat::INFINITY, CGFloat::INFINITY, 5.0, 5.0)));
+        assert!(!CGRectNull.equal_to(CGRectZero));
+        assert!(!CGRectNull.contains_point(CGPoint { x: 0.0, y: 0.0 }));
+        assert!(CGRectNull.inset(-1.0, -1.0).is_null());
+        assert!(CGRectNull.offset(1.0, 1.0).is_null());
+        assert!(CGRectNull.integral().is_null());
+    }
+
+    #[test]
+    fn standardization() {
+        let r = rect(10.0, 20.0, -4.0, -6.0);
+        assert_eq!(r.standardize(), rect(6.0, 14.0, 4.0, 6.0));
+        assert!(r.equal_to(rect(6.0, 14.0, 4.0, 6.0)));
+        assert_eq!((r.min_x(), r.mid_x(), r.max_x()), (6.0, 8.0, 10.0));
+        assert_eq!((r.min_y(), r.mid_y(), r.max_y()), (14.0, 17.0, 20.0));
+        assert_eq!((r.width(), r.height()), (4.0, 6.0));
+        assert_eq!(r.offset(1.0, 1.0), rect(7.0, 15.0, 4.0, 6.0));
+        assert_eq!(r.inset(1.0, 2.0), rect(7.0, 16.0, 2.0, 2.0));
+    }
+
+    #[test]
+    fn intersection_edge_cases() {
+        let a = rect(0.0, 0.0, 10.0, 10.0);
+        // Touching edges give an empty, but not null, intersection.
+        let touching = a.intersection(rect(10.0, 0.0, 10.0, 10.0));
+        assert_eq!(touching, rect(10.0, 0.0, 0.0, 10.0));
+        assert!(touching.is_empty() && !touching.is_null());
+        assert!(a.intersects(rect(10.0, 0.0, 10.0, 10.0)));
+        // So do touching corners.
+        assert_eq!(
+            a.intersection(rect(10.0, 10.0, 5.0, 5.0)),
+            rect(10.0, 10.0, 0.0, 0.0)
+        );
+        // Disjoint rectangles give the null rectangle.
+        assert!(a.intersection(rect(10.5, 0.0, 1.0, 1.0)).is_null());
+        assert!(!a.intersects(rect(10.5, 0.0, 1.0, 1.0)));
+        assert!(a.intersection(CGRectNull).is_null());
+        assert_eq!(
+            a.intersection(rect(12.0, 12.0, -4.0, -4.0)),
+            rect(8.0, 8.0, 2.0, 2.0)
+        );
+    }
+
+    #[test]
+    fn union_inset_integral() {
+        let a = rect(0.0, 0.0, 10.0, 10.0);
+        assert_eq!(a.union(CGRectNull), a);
+        assert_eq!(
+            CGRectNull.union(rect(1.0, 1.0, -1.0, -1.0)),
+            rect(0.0, 0.0, 1.0, 1.0)
+        );
+        assert!(CGRectNull.union(CGRectNull).is_null());
+        // Empty rectangles are not ignored.
+        assert_eq!(
+            a.union(rect(20.0, 20.0, 0.0, 0.0)),
+            rect(0.0, 0.0, 20.0, 20.0)
+        );
+        assert_eq!(a.inset(5.0, 5.0), rect(5.0, 5.0, 0.0, 0.0));
+        assert!(a.inset(5.5, 0.0).is_null());
+        assert_eq!(a.inset(-1.0, -2.0), rect(-1.0, -2.0, 12.0, 14.0));
+        assert_eq!(
+            rect(0.5, -0.5, 1.0, 1.25).integral(),
+            rect(0.0, -1.0, 2.0, 2.0)
+        );
+        assert_eq!(
+            rect(1.0, 2.0, 3.0, 4.0).integral(),
+            rect(1.0, 2.0, 3.0, 4.0)
+        );
+    }
+
+    #[test]
+    fn contains() {
+        let a = rect(0.0, 0.0, 10.0, 10.0);
+        assert!(a.contains_point(CGPoint { x: 0.0, y: 0.0 }));
+        assert!(!a.contains_point(CGPoint { x: 10.0, y: 5.0 }));
+        assert!(rect(10.0, 10.0, -10.0, -10.0).contains_point(CGPoint { x: 0.0, y: 9.5 }));
+        assert!(a.contains_rect(rect(2.0, 2.0, 8.0, 8.0)));
+        assert!(!a.contains_rect(rect(2.0, 2.0, 8.5, 8.0)));
+        assert!(a.contains_rect(a));
+    }
+
+    #[test]
+    fn properties() {
+        let rects = sample_rects();
+        for &a in &rects {
+            let standard = a.standardize();
+            assert_eq!(standard.standardize(), standard, "{:?}", a);
+            assert!(a.equal_to(standard), "{:?}", a);
+            if !a.is_null() {
+                assert!(standard.size.width >= 0.0 && standard.size.height >= 0.0);
+                assert!(a.integral().contains_rect(a), "{:?}", a);
+            }
+            for &b in &rects {
+                let intersection = a.intersection(b);
+                let union = a.union(b);
+                assert!(intersection.equal_to(b.intersection(a)), "{:?} {:?}", a, b);
+                assert!(union.equal_to(b.union(a)), "{:?} {:?}", a, b);
+                assert_eq!(a.intersects(b), !intersection.is_null(), "{:?} {:?}", a, b);
+                assert!(
+                    union.contains_rect(a) && union.contains_rect(b),
+                    "{:?} {:?}",
+                    a,
+                    b
+                );
+                if !intersection.is_null() {
+                    assert!(a.contains_rect(intersection), "{:?} {:?}", a, b);
+                    assert!(b.contains_rect(intersection), "{:?} {:?}", a, b);
+                }
+                if a.is_null() || b.is_null() {
+                    assert!(intersection.is_null());
+                }
+            }
+        }
+    }
+}
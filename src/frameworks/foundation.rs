@@ -14,32 +14,46 @@
 pub mod ns_array;
 pub mod ns_autorelease_pool;
 pub mod ns_bundle;
+pub mod ns_cache;
 pub mod ns_character_set;
 pub mod ns_coder;
 pub mod ns_data;
 pub mod ns_date;
 pub mod ns_date_formatter;
+pub mod ns_decimal_number;
 pub mod ns_dictionary;
 pub mod ns_enumerator;
+pub mod ns_error;
 pub mod ns_file_handle;
 pub mod ns_exception;
 pub mod ns_file_manager;
+pub mod ns_http_cookie;
+pub mod ns_http_cookie_storage;
 pub mod ns_keyed_unarchiver;
 pub mod ns_locale;
 pub mod ns_log;
 pub mod ns_notification;
 pub mod ns_notification_center;
 pub mod ns_null;
+pub mod ns_number_formatter;
 pub mod ns_objc_runtime;
 pub mod ns_object;
+pub mod ns_paths;
+pub mod ns_pipe;
 pub mod ns_process_info;
 pub mod ns_property_list_serialization;
 pub mod ns_run_loop;
 pub mod ns_set;
 pub mod ns_string;
+pub mod ns_task;
 pub mod ns_thread;
 pub mod ns_timer;
+pub mod ns_unarchiver;
 pub mod ns_url;
+pub mod ns_url_cache;
+pub mod ns_url_connection;
+pub mod ns_url_request;
+pub mod ns_url_response;
 pub mod ns_user_defaults;
 pub mod ns_value;
 
@@ -47,13 +61,22 @@ pub mod ns_value;
 pub struct State {
     ns_autorelease_pool: ns_autorelease_pool::State,
     ns_bundle: ns_bundle::State,
+    ns_cache: ns_cache::State,
+    ns_decimal_number: ns_decimal_number::State,
+    ns_exception: ns_exception::State,
     ns_file_manager: ns_file_manager::State,
+    ns_http_cookie_storage: ns_http_cookie_storage::State,
     ns_locale: ns_locale::State,
     ns_notification_center: ns_notification_center::State,
     ns_null: ns_null::State,
     ns_run_loop: ns_run_loop::State,
     ns_string: ns_string::State,
+    ns_url_cache: ns_url_cache::State,
     ns_user_defaults: ns_user_defaults::State,
+    ns_value: ns_value::State,
+    /// Collections whose description is currently being built, innermost
+    /// last. See [describe_collection].
+    collections_being_described: Vec<crate::objc::id>,
 }
 
 pub type NSInteger = i32;
@@ -109,3 +132,82 @@ fn hash_helper<T: std::hash::Hash>(hashable: &T) -> NSUInteger {
     let hash_u64: u64 = hasher.finish();
     (hash_u64 as u32) ^ ((hash_u64 >> 32) as u32)
 }
+
+/// Utility to help with implementing `descriptionWithLocale:indent:` on the
+/// collection classes: build the description with `describe`, unless
+/// `collection` contains itself and its description is already being built,
+/// in which case it's replaced with `(...)` like CoreFoundation does, rather
+/// than recursing forever.
+fn describe_collection(
+    env: &mut crate::Environment,
+    collection: crate::objc::id,
+    describe: impl FnOnce(&mut crate::Environment) -> String,
+) -> crate::objc::id {
+    let being_described = &mut env.framework_state.foundation.collections_being_described;
+    let description = if being_described.contains(&collection) {
+        String::from("(...)")
+    } else {
+        being_described.push(collection);
+        let description = describe(env);
+        let popped = env
+            .framework_state
+            .foundation
+            .collections_being_described
+            .pop();
+        assert_eq!(popped, Some(collection));
+        description
+    };
+    let description = ns_string::from_rust_string(env, description);
+    crate::objc::autorelease(env, description)
+}
+
+/// Utility to help with implementing `descriptionWithLocale:indent:` on the
+/// collection classes: the description of an element at a given nesting level.
+/// Like Apple's, this recurses into nested collections and quotes strings.
+fn element_description(
+    env: &mut crate::Environment,
+    object: crate::objc::id,
+    level: NSUInteger,
+) -> String {
+    use crate::objc::{msg, nil};
+
+    if env
+        .objc
+        .object_has_method_named(&env.mem, object, "descriptionWithLocale:indent:")
+    {
+        let description: crate::objc::id = msg![env; object descriptionWithLocale:nil indent:level];
+        return ns_string::to_rust_string(env, description).into_owned();
+    }
+
+    let string_class = env.objc.get_known_class("NSString", &mut env.mem);
+    if msg![env; object isKindOfClass:string_class] {
+        let string = ns_string::to_rust_string(env, object);
+        return quote_for_description(&string);
+    }
+
+    ns_object::description_string(env, object)
+}
+
+/// Quote a string the way old-style property lists do, unless it consists only
+/// of characters that don't need it.
+fn quote_for_description(string: &str) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "_$/:.-".contains(c);
+    if !string.is_empty() && string.chars().all(is_safe) {
+        return string.to_string();
+    }
+
+    let mut res = String::with_capacity(string.len() + 2);
+    res.push('"');
+    for c in string.chars() {
+        match c {
+            '"' | '\\' => {
+                res.push('\\');
+                res.push(c);
+            }
+            '\n' => res.push_str("\\n"),
+            _ => res.push(c),
+        }
+    }
+    res.push('"');
+    res
+}
@@ -5,15 +5,23 @@
  */
 //! The `NSArray` class cluster, including `NSMutableArray`.
 
-use crate::abi::DotDotDot;
-use super::ns_property_list_serialization::deserialize_plist_from_file;
-use super::{ns_keyed_unarchiver, ns_string, ns_url, NSUInteger};
+use crate::abi::{CallFromHost, DotDotDot, GuestFunction};
+use super::ns_property_list_serialization::{
+    deserialize_plist_from_file, serialize_plist_to_file,
+};
+use super::ns_unarchiver::{self, TypedValue};
+use super::{
+    ns_keyed_unarchiver, ns_string, ns_url, NSComparisonResult, NSOrderedAscending,
+    NSOrderedDescending, NSUInteger,
+};
 use crate::fs::GuestPath;
+use crate::mem::MutVoidPtr;
 use crate::objc::{
-    autorelease, id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject,
-    NSZonePtr,
+    autorelease, id, msg, msg_class, msg_send, nil, objc_classes, release, retain, ClassExports,
+    HostObject, NSZonePtr, SEL,
 };
 use crate::Environment;
+use std::fmt::Write;
 
 struct ObjectEnumeratorHostObject {
     iterator: std::vec::IntoIter<id>,
@@ -60,7 +68,7 @@ pub const CLASSES: ClassExports = objc_classes! {
     autorelease(env, res)
 }
 + (id)arrayWithObjects:(id)first, ...rest {
-    let new = msg_class![env; NSArray alloc];
+    let new: id = msg![env; this alloc];
     from_va_args(env, new, first, rest);
     autorelease(env, new)
 }
@@ -85,13 +93,40 @@ pub const CLASSES: ClassExports = objc_classes! {
     let path = ns_url::to_rust_path(env, url);
     deserialize_plist_from_file(env, &path, /* array_expected: */ true)
 }
+// FIXME: writes should be atomic
+- (bool)writeToFile:(id)path // NSString*
+         atomically:(bool)_use_aux_file {
+    let path = ns_string::to_rust_string(env, path);
+    serialize_plist_to_file(env, this, GuestPath::new(&path))
+}
 
 // NSCopying implementation
 - (id)copyWithZone:(NSZonePtr)_zone {
-    // TODO: override this once we have NSMutableArray!
     retain(env, this)
 }
 
+// NSMutableCopying implementation
+- (id)mutableCopyWithZone:(NSZonePtr)_zone {
+    let objects = retained_objects(env, this);
+    let new = msg_class![env; NSMutableArray alloc];
+    env.objc.borrow_mut::<ArrayHostObject>(new).array = objects;
+    new
+}
+
+- (id)sortedArrayUsingFunction:(GuestFunction)function // NSInteger (*)(id, id, void *)
+                       context:(MutVoidPtr)context {
+    let mut objects = retained_objects(env, this);
+    Comparator::Function(function, context).sort(env, &mut objects);
+    let new = from_vec(env, objects);
+    autorelease(env, new)
+}
+- (id)sortedArrayUsingSelector:(SEL)selector {
+    let mut objects = retained_objects(env, this);
+    Comparator::Selector(selector).sort(env, &mut objects);
+    let new = from_vec(env, objects);
+    autorelease(env, new)
+}
+
 - (id)lastObject {
     let size: NSUInteger = msg![env; this count];
     if size == 0 {
@@ -100,6 +135,26 @@ pub const CLASSES: ClassExports = objc_classes! {
     msg![env; this objectAtIndex: (size - 1)]
 }
 
+- (id)description {
+    msg![env; this descriptionWithLocale:nil indent:(0 as NSUInteger)]
+}
+- (id)descriptionWithLocale:(id)_locale // NSLocale*
+                     indent:(NSUInteger)level {
+    super::describe_collection(env, this, |env| {
+        let indent = "    ".repeat(level as usize);
+        let count: NSUInteger = msg![env; this count];
+        let mut res = format!("{}(\n", indent);
+        for i in 0..count {
+            let object: id = msg![env; this objectAtIndex:i];
+            let description = super::element_description(env, object, level + 1);
+            let separator = if i + 1 < count { "," } else { "" };
+            writeln!(res, "{}    {}{}", indent, description, separator).unwrap();
+        }
+        write!(res, "{})", indent).unwrap();
+        res
+    })
+}
+
 @end
 
 // NSMutableArray is an abstract class. A subclass must provide everything
@@ -120,9 +175,16 @@ pub const CLASSES: ClassExports = objc_classes! {
     msg_class![env; _touchHLE_NSMutableArray allocWithZone:zone]
 }
 
++ (id)arrayWithCapacity:(NSUInteger)numItems {
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initWithCapacity:numItems];
+    autorelease(env, new)
+}
+
 // NSCopying implementation
 - (id)copyWithZone:(NSZonePtr)_zone {
-    todo!(); // TODO: this should produce an immutable copy
+    let objects = retained_objects(env, this);
+    from_vec(env, objects)
 }
 
 @end
@@ -152,8 +214,7 @@ pub const CLASSES: ClassExports = objc_classes! {
     // arrays though (maybe it's `decodeObjectForKey:`), and in any case
     // allocating an NSString here would be inconvenient, so let's just take a
     // shortcut.
-    // FIXME: What if it's not an NSKeyedUnarchiver?
-    let objects = ns_keyed_unarchiver::decode_current_array(env, coder);
+    let objects = decode_array(env, coder);
     let host_object: &mut ArrayHostObject = env.objc.borrow_mut(this);
     assert!(host_object.array.is_empty());
     host_object.array = objects; // objects are already retained
@@ -216,7 +277,7 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 // NSCoding implementation
 - (id)initWithCoder:(id)coder {
-    let objects = ns_keyed_unarchiver::decode_current_array(env, coder);
+    let objects = decode_array(env, coder);
     let host_object: &mut ArrayHostObject = env.objc.borrow_mut(this);
     assert!(host_object.array.is_empty());
     host_object.array = objects; // objects are already retained
@@ -267,6 +328,14 @@ pub const CLASSES: ClassExports = objc_classes! {
     release(env, object)
 }
 
+- (())sortUsingFunction:(GuestFunction)function // NSInteger (*)(id, id, void *)
+                context:(MutVoidPtr)context {
+    sort_in_place(env, this, Comparator::Function(function, context));
+}
+- (())sortUsingSelector:(SEL)selector {
+    sort_in_place(env, this, Comparator::Selector(selector));
+}
+
 @end
 
 // Special variant for use by CFArray with NULL callbacks: objects aren't
@@ -292,6 +361,27 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 };
 
+/// Shared part of the `initWithCoder:` implementations.
+///
+/// The objects are to be considered retained by the `Vec`.
+fn decode_array(env: &mut Environment, coder: id) -> Vec<id> {
+    if msg![env; coder allowsKeyedCoding] {
+        return ns_keyed_unarchiver::decode_current_array(env, coder);
+    }
+
+    // NSArchiver writes the count followed by each object.
+    let TypedValue::Integer(count) = ns_unarchiver::decode_value(env, coder, b"i") else {
+        unreachable!();
+    };
+    (0..count)
+        .map(|_| {
+            let object = ns_unarchiver::decode_object(env, coder);
+            // object is retained by the Vec
+            retain(env, object)
+        })
+        .collect()
+}
+
 /// Shortcut for host code, roughly equivalent to
 /// `[[NSArray alloc] initWithObjects:count]` but without copying.
 /// The elements should already be "retained by" the `Vec`.
@@ -301,6 +391,19 @@ pub fn from_vec(env: &mut Environment, objects: Vec<id>) -> id {
     array
 }
 
+/// Get the elements of any array, retaining each of them. Copies of
+/// collections are shallow, so this is what `copyWithZone:` and
+/// `mutableCopyWithZone:` use.
+fn retained_objects(env: &mut Environment, array: id) -> Vec<id> {
+    let count: NSUInteger = msg![env; array count];
+    (0..count)
+        .map(|i| {
+            let object: id = msg![env; array objectAtIndex:i];
+            retain(env, object)
+        })
+        .collect()
+}
+
 fn from_va_args(env: &mut Environment, array: id, first: id, rest: DotDotDot) {
     let mut va_args = rest.start();
     let mut v = vec![retain(env, first)];
@@ -313,3 +416,77 @@ fn from_va_args(env: &mut Environment, array: id, first: id, rest: DotDotDot) {
     }
     env.objc.borrow_mut::<ArrayHostObject>(array).array = v;
 }
+
+/// How the elements are compared by the sorting methods.
+enum Comparator {
+    /// `sortUsingFunction:context:` etc.
+    Function(GuestFunction, MutVoidPtr),
+    /// `sortUsingSelector:` etc. The selector is sent to the first object with
+    /// the second as the argument.
+    Selector(SEL),
+}
+impl Comparator {
+    fn compare(&self, env: &mut Environment, a: id, b: id) -> NSComparisonResult {
+        match *self {
+            Comparator::Function(function, context) => {
+                function.call_from_host(env, (a, b, context))
+            }
+            Comparator::Selector(selector) => msg_send(env, (a, selector, b)),
+        }
+    }
+
+    /// Stable merge sort. This only uses host memory, and halves that are
+    /// already in order aren't merged, so an already-sorted array only needs
+    /// `n - 1` comparisons.
+    fn sort(&self, env: &mut Environment, objects: &mut [id]) {
+        let len = objects.len();
+        if len < 2 {
+            return;
+        }
+        let mid = len / 2;
+        self.sort(env, &mut objects[..mid]);
+        self.sort(env, &mut objects[mid..]);
+        if self.compare(env, objects[mid - 1], objects[mid]) != NSOrderedDescending {
+            return;
+        }
+
+        // Merge back into `objects`. The next element of the right half is
+        // always at or after the write position, so only the left half needs
+        // to be copied out.
+        let left = objects[..mid].to_vec();
+        let (mut i, mut j) = (0, mid);
+        for k in 0..len {
+            let take_right = i == left.len()
+                || (j < len && self.compare(env, objects[j], left[i]) == NSOrderedAscending);
+            if take_right {
+                if j == k {
+                    // Everything left is already in place.
+                    break;
+                }
+                objects[k] = objects[j];
+                j += 1;
+            } else {
+                objects[k] = left[i];
+                i += 1;
+            }
+        }
+    }
+}
+
+/// Shared part of the in-place sorting methods of `NSMutableArray`. The
+/// comparator might try to mutate the array, so a copy is sorted and only
+/// written back if the array is unchanged afterwards.
+fn sort_in_place(env: &mut Environment, array: id, comparator: Comparator) {
+    let original = env.objc.borrow::<ArrayHostObject>(array).array.clone();
+    let mut sorted = original.clone();
+    comparator.sort(env, &mut sorted);
+    let host_object = env.objc.borrow_mut::<ArrayHostObject>(array);
+    if host_object.array != original {
+        log!(
+            "Warning: array {:?} was mutated while being sorted, leaving it unsorted",
+            array
+        );
+        return;
+    }
+    host_object.array = sorted;
+}
@@ -5,7 +5,9 @@
  */
 //! `NSBundle`.
 
-use super::{ns_string, NSUInteger};
+use super::ns_property_list_serialization::deserialize_plist;
+use super::{ns_file_manager, ns_string, NSUInteger};
+use crate::fs::PathIntent;
 use crate::bundle::Bundle;
 use crate::frameworks::core_foundation::cf_bundle::{
     CFBundleCopyBundleLocalizations, CFBundleCopyPreferredLocalizationsFromArray,
@@ -14,8 +16,10 @@ use crate::objc::{
     autorelease, id, msg, msg_class, nil, objc_classes, release, ClassExports, HostObject,
 };
 use crate::Environment;
+use plist::Value;
 use std::collections::HashSet;
 use crate::frameworks::foundation::ns_string::from_rust_string;
+use crate::frameworks::uikit::ui_nib::load_nib_file;
 
 // Should be ISO 639-1 (or ISO 639-2) compliant
 // TODO: complete this list or use some crate for mapping
@@ -161,11 +165,25 @@ pub const CLASSES: ClassExports = objc_classes! {
         }
     }
 
-    // As a last resort, fallback to English
-    // TODO: fallback to a development language (CFBundleDevelopmentRegion from
-    // Info.plist)
     if !unknown_codes.is_empty() {
-        log!("TODO: language codes {:?} aren't mapped to a language name, falling back to English", unknown_codes);
+        log!("TODO: language codes {:?} aren't mapped to a language name, falling back to the development region", unknown_codes);
+    }
+
+    // As a last resort, fall back to the development region from Info.plist,
+    // and then to English
+    let development_region = bundle_for(env, this).development_region();
+    let development_lproj = if let Some(&(_, lproj)) = LANG_ID_TO_LANG_PROJ.iter().find(|&&(code, _)| code == development_region) {
+        lproj.to_string()
+    } else {
+        format!("{}.lproj", development_region)
+    };
+    if development_lproj != "English.lproj" {
+        let lproj: id = ns_string::from_rust_string(env, development_lproj);
+        let localized_path = path_for_resource_helper(env, this, name, lproj, directory, extension);
+        release(env, lproj);
+        if localized_path != nil {
+            return localized_path;
+        }
     }
     let lproj: id = ns_string::get_static_str(env, "English.lproj");
     path_for_resource_helper(env, this, name, lproj, directory, extension)
@@ -190,28 +208,45 @@ pub const CLASSES: ClassExports = objc_classes! {
 }
 
 - (id)infoDictionary {
-    let &NSBundleHostObject {
-        bundle_path,
-        info_dictionary,
-        ..
-    } = env.objc.borrow(this);
-    if let Some(dict) = info_dictionary {
+    if let Some(dict) = env.objc.borrow::<NSBundleHostObject>(this).info_dictionary {
         return dict;
     }
 
-    let plist_path = ns_string::get_static_str(env, "Info.plist");
-    let plist_path: id = msg![env; bundle_path stringByAppendingPathComponent:plist_path];
-    let dict: id = msg_class![env; NSDictionary alloc];
-    let dict: id = msg![env; dict initWithContentsOfFile:plist_path];
+    // This is created from the same parsed data the host side uses, so the
+    // two can't disagree.
+    let plist = Value::Dictionary(bundle_for(env, this).info_dictionary().clone());
+    let dict = deserialize_plist(env, &plist);
     env.objc.borrow_mut::<NSBundleHostObject>(this).info_dictionary = Some(dict);
     dict
 }
+- (id)objectForInfoDictionaryKey:(id)key { // NSString*
+    let dict: id = msg![env; this infoDictionary];
+    msg![env; dict objectForKey:key]
+}
 
 - (id)localizations {
     let localizations = CFBundleCopyBundleLocalizations(env, this);
     autorelease(env, localizations)
 }
 
+// This comes from a category in UIKit's UINibLoading.h
+- (id)loadNibNamed:(id)name // NSString*
+             owner:(id)owner
+           options:(id)_options { // NSDictionary*
+    let extension = ns_string::get_static_str(env, "nib");
+    let path: id = msg![env; this pathForResource:name ofType:extension];
+    if path == nil {
+        log!(
+            "Warning: [{:?} loadNibNamed:{:?} owner:{:?} options:_] failed, nib not found",
+            this,
+            ns_string::to_rust_string(env, name),
+            owner,
+        );
+        return nil;
+    }
+    load_nib_file(env, path, owner)
+}
+
 // TODO: constructors, more accessors
 
 @end
@@ -237,10 +272,22 @@ fn path_for_resource_helper(
     if extension != nil {
         path = msg![env; path stringByAppendingPathExtension:extension];
     }
-    let file_manager: id = msg_class![env; NSFileManager defaultManager];
-    let file_exists: bool = msg![env; file_manager fileExistsAtPath:path];
-    if file_exists {
-        return path;
+    // Return the path with the resource's actual case, in case the app
+    // compares it with something.
+    match ns_file_manager::resolve_ns_path(env, path, PathIntent::Read) {
+        Ok(resolved) => {
+            let resolved = ns_string::from_rust_string(env, String::from(resolved));
+            autorelease(env, resolved)
+        }
+        Err(_) => nil,
     }
-    nil
+}
+
+/// Get the [Bundle] an `NSBundle` instance refers to.
+fn bundle_for(env: &Environment, bundle: id) -> &Bundle {
+    env.objc
+        .borrow::<NSBundleHostObject>(bundle)
+        .bundle
+        .as_ref()
+        .unwrap_or(&env.bundle)
 }
@@ -0,0 +1,208 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `NSCache`.
+//!
+//! Apple doesn't document which objects get evicted first. This one evicts the
+//! least recently used, and evicts everything when there's a memory warning.
+
+use super::NSUInteger;
+use crate::objc::{
+    id, msg, nil, objc_classes, release, retain, ClassExports, HostObject, NSZonePtr,
+};
+use crate::Environment;
+
+#[derive(Default)]
+pub struct State {
+    /// List of caches, so they can be emptied on memory warnings.
+    /// Non-retaining!
+    caches: Vec<id>,
+}
+impl State {
+    fn get(env: &mut Environment) -> &mut Self {
+        &mut env.framework_state.foundation.ns_cache
+    }
+}
+
+struct CacheEntry {
+    key: id,
+    hash: NSUInteger,
+    object: id,
+    cost: NSUInteger,
+}
+
+#[derive(Default)]
+struct NSCacheHostObject {
+    /// Least recently used first. The keys and objects are retained.
+    entries: Vec<CacheEntry>,
+    total_cost: u64,
+    /// Zero means no limit.
+    count_limit: NSUInteger,
+    /// Zero means no limit.
+    total_cost_limit: NSUInteger,
+    /// `NSString*`
+    name: id,
+}
+impl HostObject for NSCacheHostObject {}
+impl NSCacheHostObject {
+    fn position(&self, env: &mut Environment, key: id) -> Option<usize> {
+        let hash: NSUInteger = msg![env; key hash];
+        self.entries.iter().position(|entry| {
+            entry.key == key || (entry.hash == hash && msg![env; key isEqual:(entry.key)])
+        })
+    }
+    fn remove_at(&mut self, env: &mut Environment, idx: usize) {
+        let CacheEntry {
+            key, object, cost, ..
+        } = self.entries.remove(idx);
+        self.total_cost -= u64::from(cost);
+        release(env, key);
+        release(env, object);
+    }
+    fn evict_over_limits(&mut self, env: &mut Environment) {
+        while !self.entries.is_empty()
+            && ((self.count_limit != 0 && self.entries.len() > self.count_limit as usize)
+                || (self.total_cost_limit != 0 && self.total_cost > self.total_cost_limit.into()))
+        {
+            self.remove_at(env, 0);
+        }
+    }
+    fn remove_all(&mut self, env: &mut Environment) {
+        while !self.entries.is_empty() {
+            self.remove_at(env, self.entries.len() - 1);
+        }
+    }
+}
+
+/// Empty every cache. This is called when the app receives a memory warning.
+pub fn handle_memory_warning(env: &mut Environment) {
+    let caches = State::get(env).caches.clone();
+    for cache in caches {
+        // Emptying one cache might have deallocated another.
+        if !State::get(env).caches.contains(&cache) {
+            continue;
+        }
+        () = msg![env; cache removeAllObjects];
+    }
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation NSCache: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::<NSCacheHostObject>::default();
+    let new = env.objc.alloc_object(this, host_object, &mut env.mem);
+    State::get(env).caches.push(new);
+    new
+}
+
+- (())dealloc {
+    let mut host_obj: NSCacheHostObject = std::mem::take(env.objc.borrow_mut(this));
+    host_obj.remove_all(env);
+    release(env, host_obj.name);
+
+    let caches = &mut State::get(env).caches;
+    caches.swap_remove(caches.iter().position(|&c| c == this).unwrap());
+
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+- (id)name {
+    env.objc.borrow::<NSCacheHostObject>(this).name
+}
+- (())setName:(id)name { // NSString*
+    let name: id = msg![env; name copy];
+    let host_obj = env.objc.borrow_mut::<NSCacheHostObject>(this);
+    let old_name = std::mem::replace(&mut host_obj.name, name);
+    release(env, old_name);
+}
+
+- (NSUInteger)countLimit {
+    env.objc.borrow::<NSCacheHostObject>(this).count_limit
+}
+- (())setCountLimit:(NSUInteger)limit {
+    let mut host_obj: NSCacheHostObject = std::mem::take(env.objc.borrow_mut(this));
+    host_obj.count_limit = limit;
+    host_obj.evict_over_limits(env);
+    *env.objc.borrow_mut(this) = host_obj;
+}
+
+- (NSUInteger)totalCostLimit {
+    env.objc.borrow::<NSCacheHostObject>(this).total_cost_limit
+}
+- (())setTotalCostLimit:(NSUInteger)limit {
+    let mut host_obj: NSCacheHostObject = std::mem::take(env.objc.borrow_mut(this));
+    host_obj.total_cost_limit = limit;
+    host_obj.evict_over_limits(env);
+    *env.objc.borrow_mut(this) = host_obj;
+}
+
+- (id)objectForKey:(id)key {
+    let mut host_obj: NSCacheHostObject = std::mem::take(env.objc.borrow_mut(this));
+    let res = match host_obj.position(env, key) {
+        Some(idx) => {
+            // Mark this entry as the most recently used.
+            let entry = host_obj.entries.remove(idx);
+            let object = entry.object;
+            host_obj.entries.push(entry);
+            object
+        }
+        None => nil,
+    };
+    *env.objc.borrow_mut(this) = host_obj;
+    res
+}
+
+- (())setObject:(id)object
+         forKey:(id)key {
+    msg![env; this setObject:object forKey:key cost:(0 as NSUInteger)]
+}
+- (())setObject:(id)object
+         forKey:(id)key
+           cost:(NSUInteger)cost {
+    assert!(object != nil); // TODO: raise proper exception
+    assert!(key != nil); // TODO: raise proper exception
+
+    // Unlike NSMutableDictionary, NSCache doesn't copy its keys. Retain before
+    // removing any existing entry, in case it's for the same object.
+    let key = retain(env, key);
+    let object = retain(env, object);
+
+    let mut host_obj: NSCacheHostObject = std::mem::take(env.objc.borrow_mut(this));
+    if let Some(idx) = host_obj.position(env, key) {
+        host_obj.remove_at(env, idx);
+    }
+    let hash: NSUInteger = msg![env; key hash];
+    host_obj.entries.push(CacheEntry {
+        key,
+        hash,
+        object,
+        cost,
+    });
+    host_obj.total_cost += u64::from(cost);
+    host_obj.evict_over_limits(env);
+    *env.objc.borrow_mut(this) = host_obj;
+}
+
+- (())removeObjectForKey:(id)key {
+    let mut host_obj: NSCacheHostObject = std::mem::take(env.objc.borrow_mut(this));
+    if let Some(idx) = host_obj.position(env, key) {
+        host_obj.remove_at(env, idx);
+    }
+    *env.objc.borrow_mut(this) = host_obj;
+}
+
+- (())removeAllObjects {
+    let mut host_obj: NSCacheHostObject = std::mem::take(env.objc.borrow_mut(this));
+    host_obj.remove_all(env);
+    *env.objc.borrow_mut(this) = host_obj;
+}
+
+@end
+
+};
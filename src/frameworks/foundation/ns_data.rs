@@ -5,7 +5,8 @@
  */
 //! `NSData` and `NSMutableData`.
 
-use super::ns_string::to_rust_string;
+use super::ns_string::{from_rust_string, to_rust_string};
+use super::ns_unarchiver::{self, TypedValue};
 use super::{NSRange, NSUInteger};
 use crate::fs::GuestPath;
 use crate::mem::{ConstVoidPtr, MutPtr, MutVoidPtr, Ptr};
@@ -13,6 +14,10 @@ use crate::objc::{
     autorelease, id, msg, nil, objc_classes, release, retain, ClassExports, HostObject, NSZonePtr,
 };
 use crate::{msg_class, Environment};
+use std::fmt::Write;
+
+/// `description` shows at most this many bytes, followed by the total length.
+const DESCRIPTION_MAX_BYTES: NSUInteger = 64;
 
 struct NSDataHostObject {
     bytes: MutVoidPtr,
@@ -82,6 +87,31 @@ pub const CLASSES: ClassExports = objc_classes! {
     this
 }
 
+// NSCoding implementation
+- (id)initWithCoder:(id)coder {
+    // TODO: keyed archives
+    assert!(!msg![env; coder allowsKeyedCoding]);
+
+    // NSArchiver writes the length followed by an array of that many chars.
+    let TypedValue::Integer(length) = ns_unarchiver::decode_value(env, coder, b"i") else {
+        unreachable!();
+    };
+    let ty = format!("[{}c]", length).into_bytes();
+    let TypedValue::Bytes(bytes) = ns_unarchiver::decode_value(env, coder, &ty) else {
+        unreachable!();
+    };
+
+    let host_object = env.objc.borrow_mut::<NSDataHostObject>(this);
+    assert!(host_object.bytes.is_null() && host_object.length == 0);
+    let size = bytes.len().try_into().unwrap();
+    let alloc = env.mem.alloc(size);
+    env.mem.bytes_at_mut(alloc.cast(), size).copy_from_slice(&bytes);
+    let host_object = env.objc.borrow_mut::<NSDataHostObject>(this);
+    host_object.bytes = alloc;
+    host_object.length = size;
+    this
+}
+
 - (id)initWithContentsOfURL:(id)url { // NSURL *
     let path: id = msg![env; url absoluteString];
     let path = to_rust_string(env, path);
@@ -156,6 +186,29 @@ pub const CLASSES: ClassExports = objc_classes! {
     msg![env; new initWithBytes:(bytes.cast_mut()) length:length]
 }
 
+- (id)description {
+    let &NSDataHostObject { bytes, length } = env.objc.borrow(this);
+    let shown = length.min(DESCRIPTION_MAX_BYTES);
+    let slice = if shown == 0 {
+        &[]
+    } else {
+        env.mem.bytes_at(bytes.cast(), shown)
+    };
+    let mut res = String::from("<");
+    for (i, byte) in slice.iter().enumerate() {
+        if i != 0 && i % 4 == 0 {
+            res.push(' ');
+        }
+        write!(res, "{:02x}", byte).unwrap();
+    }
+    if shown < length {
+        write!(res, " ... {} bytes", length).unwrap();
+    }
+    res.push('>');
+    let res = from_rust_string(env, res);
+    autorelease(env, res)
+}
+
 - (ConstVoidPtr)bytes {
     env.objc.borrow::<NSDataHostObject>(this).bytes.cast_const()
 }
@@ -5,17 +5,43 @@
  */
 //! `NSDate`.
 
+use super::ns_string::from_rust_string;
 use super::NSTimeInterval;
 use crate::frameworks::core_foundation::time::apple_epoch;
-use crate::objc::{autorelease, id, objc_classes, ClassExports, HostObject};
+use crate::libc::time::{current_time, timestamp_to_calendar_date, tm};
+use crate::objc::{
+    autorelease, id, msg, msg_class, objc_classes, retain, ClassExports, HostObject, NSZonePtr,
+};
+use crate::Environment;
 
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
 struct NSDateHostObject {
     time_interval: NSTimeInterval,
 }
 impl HostObject for NSDateHostObject {}
 
+/// Create an autoreleased `NSDate*` for a [SystemTime], for use by host code.
+pub fn from_system_time(env: &mut Environment, time: SystemTime) -> id {
+    let time_interval = match time.duration_since(apple_epoch()) {
+        Ok(after) => after.as_secs_f64(),
+        Err(before) => -before.duration().as_secs_f64(),
+    };
+    msg_class![env; NSDate dateWithTimeIntervalSinceReferenceDate:time_interval]
+}
+
+/// Get the [SystemTime] an `NSDate*` represents, for use by host code.
+pub fn to_system_time(env: &mut Environment, date: id) -> SystemTime {
+    let time_interval: NSTimeInterval = msg![env; date timeIntervalSinceReferenceDate];
+    let offset = Duration::try_from_secs_f64(time_interval.abs()).unwrap_or(Duration::MAX);
+    if time_interval >= 0.0 {
+        apple_epoch().checked_add(offset)
+    } else {
+        apple_epoch().checked_sub(offset)
+    }
+    .unwrap_or(apple_epoch())
+}
+
 pub const CLASSES: ClassExports = objc_classes! {
 
 (env, this, _cmd);
@@ -25,7 +51,7 @@ pub const CLASSES: ClassExports = objc_classes! {
 + (id)date {
     // "Date objects are immutable, representing an invariant time interval
     // relative to an absolute reference date (00:00:00 UTC on 1 January 2001)."
-    let time_interval = SystemTime::now()
+    let time_interval = current_time(env)
         .duration_since(apple_epoch())
         .unwrap()
         .as_secs_f64();
@@ -39,6 +65,14 @@ pub const CLASSES: ClassExports = objc_classes! {
     autorelease(env, new)
 }
 
++ (id)dateWithTimeIntervalSinceReferenceDate:(NSTimeInterval)time_interval {
+    let host_object = Box::new(NSDateHostObject {
+        time_interval
+    });
+    let new = env.objc.alloc_object(this, host_object, &mut env.mem);
+    autorelease(env, new)
+}
+
 + (id)distantFuture {
     let time_interval = SystemTime::now()
         .duration_since(apple_epoch())
@@ -54,6 +88,38 @@ pub const CLASSES: ClassExports = objc_classes! {
     autorelease(env, new)
 }
 
+// NSCopying implementation
+- (id)copyWithZone:(NSZonePtr)_zone {
+    // Dates are immutable, so there's no need to make a new one.
+    retain(env, this)
+}
+
+- (id)description {
+    let time_interval = env.objc.borrow::<NSDateHostObject>(this).time_interval;
+    // The reference date is 978307200 in UNIX time. The conversion saturates
+    // for dates that don't fit in a time_t, like distantFuture.
+    let tm {
+        tm_year,
+        tm_mon,
+        tm_mday,
+        tm_hour,
+        tm_min,
+        tm_sec,
+        ..
+    } = timestamp_to_calendar_date((time_interval + 978_307_200.0).floor() as i32);
+    let res = format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} +0000",
+        tm_year + 1900,
+        tm_mon + 1,
+        tm_mday,
+        tm_hour,
+        tm_min,
+        tm_sec
+    );
+    let res = from_rust_string(env, res);
+    autorelease(env, res)
+}
+
 - (NSTimeInterval)timeIntervalSinceDate:(id)anotherDate {
     assert!(!anotherDate.is_null());
     let host_object = env.objc.borrow::<NSDateHostObject>(this);
@@ -0,0 +1,1120 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `NSDecimalNumber` and `NSDecimalNumberHandler`.
+//!
+//! Like Apple's `NSDecimal`, values are stored as a mantissa of up to 38
+//! decimal digits and a base-10 exponent in the range -128 to 127, so that
+//! e.g. 0.1 is represented exactly. The guest-visible `NSDecimal` struct isn't
+//! implemented, only the object API.
+
+use super::ns_string::{from_rust_string, get_static_str, to_rust_string};
+use super::{NSComparisonResult, NSInteger, NSUInteger};
+use super::{NSOrderedAscending, NSOrderedDescending, NSOrderedSame};
+use crate::objc::{
+    autorelease, id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject,
+    NSZonePtr, SEL,
+};
+use crate::Environment;
+use std::cmp::Ordering;
+use std::fmt;
+
+/// Largest mantissa that fits in the 38 significant digits `NSDecimal` has.
+const MAX_MANTISSA: u128 = 10u128.pow(38) - 1;
+const MIN_EXPONENT: i32 = -128;
+const MAX_EXPONENT: i32 = 127;
+
+/// Scale value meaning "don't round".
+pub const NSDecimalNoScale: i16 = i16::MAX;
+
+pub type NSRoundingMode = NSUInteger;
+pub const NSRoundPlain: NSRoundingMode = 0;
+pub const NSRoundDown: NSRoundingMode = 1;
+pub const NSRoundUp: NSRoundingMode = 2;
+pub const NSRoundBankers: NSRoundingMode = 3;
+
+pub type NSCalculationError = NSUInteger;
+pub const NSCalculationNoError: NSCalculationError = 0;
+pub const NSCalculationLossOfPrecision: NSCalculationError = 1;
+pub const NSCalculationUnderflow: NSCalculationError = 2;
+pub const NSCalculationOverflow: NSCalculationError = 3;
+pub const NSCalculationDivideByZero: NSCalculationError = 4;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RoundingMode {
+    /// Round half away from zero.
+    Plain,
+    /// Round towards negative infinity.
+    Down,
+    /// Round towards positive infinity.
+    Up,
+    /// Round half to even.
+    Bankers,
+}
+impl RoundingMode {
+    fn from_raw(mode: NSRoundingMode) -> RoundingMode {
+        match mode {
+            NSRoundPlain => RoundingMode::Plain,
+            NSRoundDown => RoundingMode::Down,
+            NSRoundUp => RoundingMode::Up,
+            NSRoundBankers => RoundingMode::Bankers,
+            _ => unimplemented!("NSRoundingMode {}", mode),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CalculationError {
+    Underflow,
+    Overflow,
+    DivideByZero,
+}
+impl CalculationError {
+    fn to_raw(self) -> NSCalculationError {
+        match self {
+            CalculationError::Underflow => NSCalculationUnderflow,
+            CalculationError::Overflow => NSCalculationOverflow,
+            CalculationError::DivideByZero => NSCalculationDivideByZero,
+        }
+    }
+}
+
+fn digit_count(mut mantissa: u128) -> u32 {
+    let mut count = 0;
+    while mantissa != 0 {
+        mantissa /= 10;
+        count += 1;
+    }
+    count
+}
+
+/// Divide `mantissa` by `10^places`, rounding the result. `negative` is the
+/// sign of the number the mantissa belongs to, which matters for rounding
+/// towards an infinity.
+fn shift_right(mantissa: u128, places: u32, negative: bool, mode: RoundingMode) -> u128 {
+    if places == 0 {
+        return mantissa;
+    }
+    // 10^39 doesn't fit in a u128, but any u128 is less than half of it.
+    let (quotient, remainder, half) = if places > 38 {
+        (0, mantissa, Ordering::Less)
+    } else {
+        let divisor = 10u128.pow(places);
+        let remainder = mantissa % divisor;
+        (mantissa / divisor, remainder, (remainder * 2).cmp(&divisor))
+    };
+    if remainder == 0 {
+        return quotient;
+    }
+    let away_from_zero = match mode {
+        RoundingMode::Plain => half != Ordering::Less,
+        RoundingMode::Down => negative,
+        RoundingMode::Up => !negative,
+        RoundingMode::Bankers => {
+            half == Ordering::Greater || (half == Ordering::Equal && quotient % 2 == 1)
+        }
+    };
+    quotient + away_from_zero as u128
+}
+
+/// A decimal floating-point value, equivalent to Apple's `NSDecimal`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Decimal {
+    negative: bool,
+    mantissa: u128,
+    exponent: i32,
+    is_nan: bool,
+}
+impl Decimal {
+    pub const ZERO: Decimal = Decimal {
+        negative: false,
+        mantissa: 0,
+        exponent: 0,
+        is_nan: false,
+    };
+    pub const ONE: Decimal = Decimal {
+        mantissa: 1,
+        ..Decimal::ZERO
+    };
+    pub const NAN: Decimal = Decimal {
+        is_nan: true,
+        ..Decimal::ZERO
+    };
+
+    /// Create a value of `mantissa * 10^exponent`, rounding the mantissa to 38
+    /// digits if necessary.
+    pub fn new(negative: bool, mantissa: u128, exponent: i32) -> Result<Decimal, CalculationError> {
+        Decimal {
+            negative,
+            mantissa,
+            exponent,
+            is_nan: false,
+        }
+        .normalize()
+    }
+
+    pub fn is_nan(&self) -> bool {
+        self.is_nan
+    }
+
+    /// Bring the mantissa and exponent into range and strip trailing zeros
+    /// from the mantissa.
+    fn normalize(self) -> Result<Decimal, CalculationError> {
+        if self.is_nan {
+            return Ok(self);
+        }
+        let Decimal {
+            negative,
+            mut mantissa,
+            mut exponent,
+            ..
+        } = self;
+
+        let excess = digit_count(mantissa).saturating_sub(38);
+        if excess > 0 {
+            mantissa = shift_right(mantissa, excess, negative, RoundingMode::Plain);
+            exponent += excess as i32;
+            // Rounding up can carry into a 39th digit, but then the mantissa
+            // is exactly 10^38.
+            if mantissa > MAX_MANTISSA {
+                mantissa /= 10;
+                exponent += 1;
+            }
+        }
+        if exponent < MIN_EXPONENT {
+            let lost = mantissa;
+            let places = (MIN_EXPONENT - exponent) as u32;
+            mantissa = shift_right(mantissa, places, negative, RoundingMode::Plain);
+            exponent = MIN_EXPONENT;
+            if mantissa == 0 && lost != 0 {
+                return Err(CalculationError::Underflow);
+            }
+        }
+
+        if mantissa == 0 {
+            return Ok(Decimal::ZERO);
+        }
+        while mantissa % 10 == 0 {
+            mantissa /= 10;
+            exponent += 1;
+        }
+        while exponent > MAX_EXPONENT && mantissa <= MAX_MANTISSA / 10 {
+            mantissa *= 10;
+            exponent -= 1;
+        }
+        if exponent > MAX_EXPONENT {
+            return Err(CalculationError::Overflow);
+        }
+
+        Ok(Decimal {
+            negative,
+            mantissa,
+            exponent,
+            is_nan: false,
+        })
+    }
+
+    /// Parse a number (optional sign, digits, optional fraction and optional
+    /// exponent) from the start of `string`, returning it and the unparsed
+    /// remainder. `None` is returned if there are no digits.
+    pub fn parse_prefix<'a>(
+        string: &'a str,
+        decimal_separator: &str,
+    ) -> Option<(Decimal, &'a str)> {
+        let mut rest = string;
+        let negative = if let Some(after) = rest.strip_prefix('-') {
+            rest = after;
+            true
+        } else {
+            if let Some(after) = rest.strip_prefix('+') {
+                rest = after;
+            }
+            false
+        };
+
+        let mut mantissa: u128 = 0;
+        let mut exponent: i32 = 0;
+        let mut seen_digits = false;
+        // The first digit that didn't fit into the mantissa, for rounding.
+        let mut first_dropped: Option<u8> = None;
+        let mut push_digit = |digit: u8, fractional: bool| {
+            seen_digits = true;
+            if mantissa <= MAX_MANTISSA / 10 {
+                mantissa = mantissa * 10 + digit as u128;
+                if fractional {
+                    exponent -= 1;
+                }
+            } else {
+                first_dropped.get_or_insert(digit);
+                if !fractional {
+                    exponent += 1;
+                }
+            }
+        };
+
+        while let Some(digit) = rest.bytes().next().filter(u8::is_ascii_digit) {
+            push_digit(digit - b'0', false);
+            rest = &rest[1..];
+        }
+        if !decimal_separator.is_empty() {
+            if let Some(after) = rest.strip_prefix(decimal_separator) {
+                rest = after;
+                while let Some(digit) = rest.bytes().next().filter(u8::is_ascii_digit) {
+                    push_digit(digit - b'0', true);
+                    rest = &rest[1..];
+                }
+            }
+        }
+        if !seen_digits {
+            return None;
+        }
+
+        if let Some(after) = rest.strip_prefix(['e', 'E']) {
+            let (exponent_negative, after) = match after.as_bytes().first() {
+                Some(b'-') => (true, &after[1..]),
+                Some(b'+') => (false, &after[1..]),
+                _ => (false, after),
+            };
+            let digits = after.bytes().take_while(u8::is_ascii_digit).count();
+            if digits > 0 {
+                let value = after[..digits].bytes().fold(0i32, |acc, digit| {
+                    (acc * 10 + (digit - b'0') as i32).min(1000)
+                });
+                exponent += if exponent_negative { -value } else { value };
+                rest = &after[digits..];
+            }
+        }
+
+        if first_dropped.is_some_and(|digit| digit >= 5) {
+            mantissa += 1;
+        }
+        Decimal::new(negative, mantissa, exponent)
+            .ok()
+            .map(|decimal| (decimal, rest))
+    }
+
+    pub fn from_f64(value: f64) -> Decimal {
+        if !value.is_finite() {
+            return Decimal::NAN;
+        }
+        // Rust's formatting gives the shortest representation that round-trips
+        // and never uses scientific notation.
+        Decimal::parse_prefix(&value.to_string(), ".").map_or(Decimal::NAN, |(decimal, _)| decimal)
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.to_string().parse().unwrap()
+    }
+
+    pub fn negate(self) -> Decimal {
+        if self.is_nan || self.mantissa == 0 {
+            return self;
+        }
+        Decimal {
+            negative: !self.negative,
+            ..self
+        }
+    }
+
+    pub fn add(self, other: Decimal) -> Result<Decimal, CalculationError> {
+        if self.is_nan || other.is_nan {
+            return Ok(Decimal::NAN);
+        }
+
+        // Scale up the mantissa of the operand with the larger exponent while
+        // there's room, leaving enough headroom that the sum can't overflow.
+        const LIMIT: u128 = u128::MAX / 20;
+        let (mut a, mut a_exponent) = (self.mantissa, self.exponent);
+        let (mut b, mut b_exponent) = (other.mantissa, other.exponent);
+        while a_exponent > b_exponent && a <= LIMIT {
+            a *= 10;
+            a_exponent -= 1;
+        }
+        while b_exponent > a_exponent && b <= LIMIT {
+            b *= 10;
+            b_exponent -= 1;
+        }
+        // If that wasn't enough, the other operand has to lose precision.
+        if a_exponent > b_exponent {
+            let places = (a_exponent - b_exponent) as u32;
+            b = shift_right(b, places, other.negative, RoundingMode::Plain);
+        } else if b_exponent > a_exponent {
+            let places = (b_exponent - a_exponent) as u32;
+            a = shift_right(a, places, self.negative, RoundingMode::Plain);
+            a_exponent = b_exponent;
+        }
+
+        let (negative, mantissa) = if self.negative == other.negative {
+            (self.negative, a + b)
+        } else if a >= b {
+            (self.negative, a - b)
+        } else {
+            (other.negative, b - a)
+        };
+        Decimal::new(negative, mantissa, a_exponent)
+    }
+
+    pub fn sub(self, other: Decimal) -> Result<Decimal, CalculationError> {
+        self.add(other.negate())
+    }
+
+    pub fn mul(self, other: Decimal) -> Result<Decimal, CalculationError> {
+        if self.is_nan || other.is_nan {
+            return Ok(Decimal::NAN);
+        }
+        let negative = self.negative != other.negative;
+        let (mut a, mut b) = (self.mantissa, other.mantissa);
+        let mut exponent = self.exponent + other.exponent;
+        while a.checked_mul(b).is_none() {
+            if a > b {
+                a = shift_right(a, 1, negative, RoundingMode::Plain);
+            } else {
+                b = shift_right(b, 1, negative, RoundingMode::Plain);
+            }
+            exponent += 1;
+        }
+        Decimal::new(negative, a * b, exponent)
+    }
+
+    pub fn mul_power_of_10(self, power: i16) -> Result<Decimal, CalculationError> {
+        if self.is_nan {
+            return Ok(Decimal::NAN);
+        }
+        Decimal::new(
+            self.negative,
+            self.mantissa,
+            self.exponent + i32::from(power),
+        )
+    }
+
+    pub fn div(self, other: Decimal) -> Result<Decimal, CalculationError> {
+        if self.is_nan || other.is_nan {
+            return Ok(Decimal::NAN);
+        }
+        if other.mantissa == 0 {
+            return Err(CalculationError::DivideByZero);
+        }
+        if self.mantissa == 0 {
+            return Ok(Decimal::ZERO);
+        }
+        let negative = self.negative != other.negative;
+        let mut exponent = self.exponent - other.exponent;
+
+        // Keep the divisor small enough that the remainder can always be
+        // multiplied by 10 without overflowing.
+        let mut divisor = other.mantissa;
+        while divisor > MAX_MANTISSA / 10 {
+            divisor = shift_right(divisor, 1, negative, RoundingMode::Plain);
+            exponent -= 1;
+        }
+
+        // Long division until there's no remainder or all 38 digits are used.
+        let mut quotient = self.mantissa / divisor;
+        let mut remainder = self.mantissa % divisor;
+        while remainder != 0 && quotient <= MAX_MANTISSA / 10 {
+            remainder *= 10;
+            quotient = quotient * 10 + remainder / divisor;
+            remainder %= divisor;
+            exponent -= 1;
+        }
+        if remainder * 2 >= divisor {
+            quotient += 1;
+        }
+        Decimal::new(negative, quotient, exponent)
+    }
+
+    /// Round to `scale` digits after the decimal point (or before it, if
+    /// `scale` is negative).
+    pub fn round(self, scale: i32, mode: RoundingMode) -> Result<Decimal, CalculationError> {
+        if self.is_nan || self.exponent >= -scale {
+            return Ok(self);
+        }
+        let places = (-scale - self.exponent) as u32;
+        let mantissa = shift_right(self.mantissa, places, self.negative, mode);
+        Decimal::new(self.negative, mantissa, -scale)
+    }
+
+    /// Compare two values. NaN is considered smaller than any other value.
+    pub fn compare(&self, other: &Decimal) -> Ordering {
+        match (self.is_nan, other.is_nan) {
+            (true, true) => return Ordering::Equal,
+            (true, false) => return Ordering::Less,
+            (false, true) => return Ordering::Greater,
+            (false, false) => (),
+        }
+        let sign = |decimal: &Decimal| match (decimal.mantissa, decimal.negative) {
+            (0, _) => 0,
+            (_, true) => -1,
+            (_, false) => 1,
+        };
+        let (a_sign, b_sign) = (sign(self), sign(other));
+        if a_sign != b_sign || a_sign == 0 {
+            return a_sign.cmp(&b_sign);
+        }
+
+        let a_magnitude = digit_count(self.mantissa) as i32 + self.exponent;
+        let b_magnitude = digit_count(other.mantissa) as i32 + other.exponent;
+        let ordering = if a_magnitude != b_magnitude {
+            a_magnitude.cmp(&b_magnitude)
+        } else {
+            // Same number of integer digits, so scaling them to the same
+            // exponent can't overflow.
+            let (a, b) = match self.exponent.cmp(&other.exponent) {
+                Ordering::Greater => {
+                    let scale = 10u128.pow((self.exponent - other.exponent) as u32);
+                    (self.mantissa * scale, other.mantissa)
+                }
+                _ => {
+                    let scale = 10u128.pow((other.exponent - self.exponent) as u32);
+                    (self.mantissa, other.mantissa * scale)
+                }
+            };
+            a.cmp(&b)
+        };
+        if self.negative {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+}
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_nan {
+            return f.write_str("NaN");
+        }
+        if self.negative && self.mantissa != 0 {
+            f.write_str("-")?;
+        }
+        let digits = self.mantissa.to_string();
+        if self.exponent >= 0 {
+            if self.mantissa == 0 {
+                return f.write_str("0");
+            }
+            f.write_str(&digits)?;
+            f.write_str(&"0".repeat(self.exponent as usize))
+        } else {
+            let point = digits.len() as i32 + self.exponent;
+            if point > 0 {
+                let (integer, fraction) = digits.split_at(point as usize);
+                write!(f, "{}.{}", integer, fraction)
+            } else {
+                write!(f, "0.{}{}", "0".repeat(-point as usize), digits)
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct State {
+    not_a_number: Option<id>,
+    zero: Option<id>,
+    one: Option<id>,
+    default_handler: Option<id>,
+    default_behavior: Option<id>,
+}
+impl State {
+    fn get(env: &mut Environment) -> &mut State {
+        &mut env.framework_state.foundation.ns_decimal_number
+    }
+}
+
+struct NSDecimalNumberHostObject {
+    decimal: Decimal,
+}
+impl HostObject for NSDecimalNumberHostObject {}
+
+struct NSDecimalNumberHandlerHostObject {
+    rounding_mode: NSRoundingMode,
+    scale: i16,
+    raise_on_exactness: bool,
+    raise_on_overflow: bool,
+    raise_on_underflow: bool,
+    raise_on_divide_by_zero: bool,
+}
+impl HostObject for NSDecimalNumberHandlerHostObject {}
+
+fn get_static_number(
+    env: &mut Environment,
+    slot: fn(&mut State) -> &mut Option<id>,
+    decimal: Decimal,
+) -> id {
+    if let Some(existing) = *slot(State::get(env)) {
+        return existing;
+    }
+    let class = env.objc.get_known_class("NSDecimalNumber", &mut env.mem);
+    let host_object = Box::new(NSDecimalNumberHostObject { decimal });
+    let new = env
+        .objc
+        .alloc_static_object(class, host_object, &mut env.mem);
+    *slot(State::get(env)) = Some(new);
+    new
+}
+
+fn get_default_handler(env: &mut Environment) -> id {
+    if let Some(existing) = State::get(env).default_handler {
+        return existing;
+    }
+    let class = env
+        .objc
+        .get_known_class("NSDecimalNumberHandler", &mut env.mem);
+    let host_object = Box::new(NSDecimalNumberHandlerHostObject {
+        rounding_mode: NSRoundPlain,
+        scale: NSDecimalNoScale,
+        raise_on_exactness: false,
+        raise_on_overflow: true,
+        raise_on_underflow: true,
+        raise_on_divide_by_zero: true,
+    });
+    let new = env
+        .objc
+        .alloc_static_object(class, host_object, &mut env.mem);
+    State::get(env).default_handler = Some(new);
+    new
+}
+
+/// Create a new (autoreleased) `NSDecimalNumber`.
+pub fn from_decimal(env: &mut Environment, decimal: Decimal) -> id {
+    let new: id = msg_class![env; NSDecimalNumber alloc];
+    env.objc
+        .borrow_mut::<NSDecimalNumberHostObject>(new)
+        .decimal = decimal;
+    autorelease(env, new)
+}
+
+/// Get the value of an `NSNumber` (which might be an `NSDecimalNumber`).
+pub fn decimal_from_number(env: &mut Environment, number: id) -> Decimal {
+    let class = env.objc.get_known_class("NSDecimalNumber", &mut env.mem);
+    if msg![env; number isKindOfClass:class] {
+        env.objc.borrow::<NSDecimalNumberHostObject>(number).decimal
+    } else {
+        let value: f64 = msg![env; number doubleValue];
+        Decimal::from_f64(value)
+    }
+}
+
+/// Shared implementation of the arithmetic methods, which like Apple's report
+/// errors to the behavior and round the result according to it.
+fn operation(
+    env: &mut Environment,
+    this: id,
+    selector: SEL,
+    other: id,
+    behavior: id,
+    op: fn(Decimal, Decimal) -> Result<Decimal, CalculationError>,
+) -> id {
+    let b = decimal_from_number(env, other);
+    unary_operation(env, this, selector, other, behavior, |a| op(a, b))
+}
+
+/// Like [operation], but for methods with only one `NSDecimalNumber` operand.
+/// `right_operand` is what gets passed to the behavior if there's an error.
+fn unary_operation(
+    env: &mut Environment,
+    this: id,
+    selector: SEL,
+    right_operand: id,
+    behavior: id,
+    op: impl FnOnce(Decimal) -> Result<Decimal, CalculationError>,
+) -> id {
+    let behavior = if behavior == nil {
+        msg_class![env; NSDecimalNumber defaultBehavior]
+    } else {
+        behavior
+    };
+    let a = decimal_from_number(env, this);
+    let result = op(a).and_then(|result| {
+        let scale: i16 = msg![env; behavior scale];
+        if scale == NSDecimalNoScale {
+            return Ok(result);
+        }
+        let mode: NSRoundingMode = msg![env; behavior roundingMode];
+        result.round(scale.into(), RoundingMode::from_raw(mode))
+    });
+    match result {
+        Ok(result) => from_decimal(env, result),
+        Err(error) => {
+            let error = error.to_raw();
+            let replacement: id = msg![env; behavior exceptionDuringOperation:selector
+                                                                          error:error
+                                                                    leftOperand:this
+                                                                   rightOperand:right_operand];
+            if replacement != nil {
+                replacement
+            } else if error == NSCalculationUnderflow {
+                msg_class![env; NSDecimalNumber zero]
+            } else {
+                msg_class![env; NSDecimalNumber notANumber]
+            }
+        }
+    }
+}
+
+fn to_comparison_result(ordering: Ordering) -> NSComparisonResult {
+    match ordering {
+        Ordering::Less => NSOrderedAscending,
+        Ordering::Equal => NSOrderedSame,
+        Ordering::Greater => NSOrderedDescending,
+    }
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation NSDecimalNumber: NSNumber
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(NSDecimalNumberHostObject {
+        decimal: Decimal::ZERO,
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
++ (id)decimalNumberWithString:(id)string { // NSString*
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initWithString:string];
+    autorelease(env, new)
+}
+
++ (id)decimalNumberWithString:(id)string // NSString*
+                       locale:(id)locale {
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initWithString:string locale:locale];
+    autorelease(env, new)
+}
+
++ (id)decimalNumberWithMantissa:(u64)mantissa
+                       exponent:(i16)exponent
+                     isNegative:(bool)negative {
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initWithMantissa:mantissa
+                                        exponent:exponent
+                                      isNegative:negative];
+    autorelease(env, new)
+}
+
++ (id)notANumber {
+    get_static_number(env, |state| &mut state.not_a_number, Decimal::NAN)
+}
+
++ (id)zero {
+    get_static_number(env, |state| &mut state.zero, Decimal::ZERO)
+}
+
++ (id)one {
+    get_static_number(env, |state| &mut state.one, Decimal::ONE)
+}
+
++ (id)defaultBehavior {
+    if let Some(behavior) = State::get(env).default_behavior {
+        behavior
+    } else {
+        get_default_handler(env)
+    }
+}
+
++ (())setDefaultBehavior:(id)behavior {
+    retain(env, behavior);
+    if let Some(old) = State::get(env).default_behavior.take() {
+        release(env, old);
+    }
+    State::get(env).default_behavior = (behavior != nil).then_some(behavior);
+}
+
+- (id)initWithString:(id)string { // NSString*
+    msg![env; this initWithString:string locale:nil]
+}
+
+- (id)initWithString:(id)string // NSString*
+              locale:(id)locale { // NSLocale* or NSDictionary*
+    let decimal_separator = if locale == nil {
+        ".".to_string()
+    } else {
+        let locale_class = env.objc.get_known_class("NSLocale", &mut env.mem);
+        if msg![env; locale isKindOfClass:locale_class] {
+            super::ns_locale::number_symbols(env, locale).decimal_separator
+        } else {
+            // Old-style locale dictionary
+            let key = get_static_str(env, "NSDecimalSeparator");
+            let separator: id = msg![env; locale objectForKey:key];
+            if separator == nil {
+                ".".to_string()
+            } else {
+                to_rust_string(env, separator).into_owned()
+            }
+        }
+    };
+    let string = to_rust_string(env, string);
+    let decimal = Decimal::parse_prefix(string.trim_start(), &decimal_separator)
+        .map_or(Decimal::NAN, |(decimal, _rest)| decimal);
+    env.objc.borrow_mut::<NSDecimalNumberHostObject>(this).decimal = decimal;
+    this
+}
+
+- (id)initWithMantissa:(u64)mantissa
+              exponent:(i16)exponent
+            isNegative:(bool)negative {
+    let decimal = Decimal::new(negative, mantissa.into(), exponent.into())
+        .unwrap_or(Decimal::NAN);
+    env.objc.borrow_mut::<NSDecimalNumberHostObject>(this).decimal = decimal;
+    this
+}
+
+// NSNumber's initializers would replace the host object with the wrong type.
+- (id)initWithBool:(bool)value {
+    msg![env; this initWithLongLong:(value as i64)]
+}
+- (id)initWithDouble:(f64)value {
+    env.objc.borrow_mut::<NSDecimalNumberHostObject>(this).decimal = Decimal::from_f64(value);
+    this
+}
+- (id)initWithFloat:(f32)value {
+    msg![env; this initWithDouble:(value as f64)]
+}
+- (id)initWithInt:(i32)value {
+    msg![env; this initWithLongLong:(value as i64)]
+}
+- (id)initWithInteger:(NSInteger)value {
+    msg![env; this initWithLongLong:(value as i64)]
+}
+- (id)initWithLongLong:(i64)value {
+    let decimal = Decimal::new(value < 0, value.unsigned_abs().into(), 0).unwrap();
+    env.objc.borrow_mut::<NSDecimalNumberHostObject>(this).decimal = decimal;
+    this
+}
+- (id)initWithUnsignedLongLong:(u64)value {
+    let decimal = Decimal::new(false, value.into(), 0).unwrap();
+    env.objc.borrow_mut::<NSDecimalNumberHostObject>(this).decimal = decimal;
+    this
+}
+
+- (id)decimalNumberByAdding:(id)other {
+    msg![env; this decimalNumberByAdding:other withBehavior:nil]
+}
+- (id)decimalNumberByAdding:(id)other
+               withBehavior:(id)behavior {
+    operation(env, this, _cmd, other, behavior, Decimal::add)
+}
+
+- (id)decimalNumberBySubtracting:(id)other {
+    msg![env; this decimalNumberBySubtracting:other withBehavior:nil]
+}
+- (id)decimalNumberBySubtracting:(id)other
+                    withBehavior:(id)behavior {
+    operation(env, this, _cmd, other, behavior, Decimal::sub)
+}
+
+- (id)decimalNumberByMultiplyingBy:(id)other {
+    msg![env; this decimalNumberByMultiplyingBy:other withBehavior:nil]
+}
+- (id)decimalNumberByMultiplyingBy:(id)other
+                      withBehavior:(id)behavior {
+    operation(env, this, _cmd, other, behavior, Decimal::mul)
+}
+
+- (id)decimalNumberByDividingBy:(id)other {
+    msg![env; this decimalNumberByDividingBy:other withBehavior:nil]
+}
+- (id)decimalNumberByDividingBy:(id)other
+                   withBehavior:(id)behavior {
+    operation(env, this, _cmd, other, behavior, Decimal::div)
+}
+
+- (id)decimalNumberByMultiplyingByPowerOf10:(i16)power {
+    msg![env; this decimalNumberByMultiplyingByPowerOf10:power withBehavior:nil]
+}
+- (id)decimalNumberByMultiplyingByPowerOf10:(i16)power
+                               withBehavior:(id)behavior {
+    unary_operation(env, this, _cmd, nil, behavior, |a| a.mul_power_of_10(power))
+}
+
+- (id)decimalNumberByRoundingAccordingToBehavior:(id)behavior {
+    // Multiplying by one does nothing except apply the behavior's rounding.
+    let one: id = msg_class![env; NSDecimalNumber one];
+    operation(env, this, _cmd, one, behavior, Decimal::mul)
+}
+
+- (NSComparisonResult)compare:(id)other { // NSNumber*
+    let a = env.objc.borrow::<NSDecimalNumberHostObject>(this).decimal;
+    let b = decimal_from_number(env, other);
+    to_comparison_result(a.compare(&b))
+}
+
+- (bool)isEqual:(id)other {
+    if this == other {
+        return true;
+    }
+    let class = env.objc.get_known_class("NSNumber", &mut env.mem);
+    if other == nil || !msg![env; other isKindOfClass:class] {
+        return false;
+    }
+    let result: NSComparisonResult = msg![env; this compare:other];
+    result == NSOrderedSame
+}
+
+- (NSUInteger)hash {
+    // This must match NSNumber's hash, since they can be equal.
+    let decimal = env.objc.borrow::<NSDecimalNumberHostObject>(this).decimal;
+    super::ns_value::hash_number_value(decimal.to_f64())
+}
+
+- (id)description {
+    let decimal = env.objc.borrow::<NSDecimalNumberHostObject>(this).decimal;
+    let description = from_rust_string(env, decimal.to_string());
+    autorelease(env, description)
+}
+
+- (id)descriptionWithLocale:(id)locale {
+    let description: id = msg![env; this description];
+    if locale == nil {
+        return description;
+    }
+    let decimal_separator = super::ns_locale::number_symbols(env, locale).decimal_separator;
+    let description = to_rust_string(env, description).replace('.', &decimal_separator);
+    let description = from_rust_string(env, description);
+    autorelease(env, description)
+}
+
+- (id)stringValue {
+    msg![env; this description]
+}
+
+- (f64)doubleValue {
+    env.objc.borrow::<NSDecimalNumberHostObject>(this).decimal.to_f64()
+}
+
+- (f32)floatValue {
+    env.objc.borrow::<NSDecimalNumberHostObject>(this).decimal.to_f64() as f32
+}
+
+- (bool)boolValue {
+    env.objc.borrow::<NSDecimalNumberHostObject>(this).decimal.to_f64() != 0.0
+}
+
+- (u64)unsignedLongLongValue {
+    env.objc.borrow::<NSDecimalNumberHostObject>(this).decimal.to_f64() as u64
+}
+
+- (i64)longLongValue {
+    env.objc.borrow::<NSDecimalNumberHostObject>(this).decimal.to_f64() as i64
+}
+
+- (i32)intValue {
+    env.objc.borrow::<NSDecimalNumberHostObject>(this).decimal.to_f64() as i32
+}
+
+- (NSInteger)integerValue {
+    msg![env; this intValue]
+}
+
+@end
+
+@implementation NSDecimalNumberHandler: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(NSDecimalNumberHandlerHostObject {
+        rounding_mode: NSRoundPlain,
+        scale: NSDecimalNoScale,
+        raise_on_exactness: false,
+        raise_on_overflow: true,
+        raise_on_underflow: true,
+        raise_on_divide_by_zero: true,
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
++ (id)defaultDecimalNumberHandler {
+    get_default_handler(env)
+}
+
++ (id)decimalNumberHandlerWithRoundingMode:(NSRoundingMode)rounding_mode
+                                     scale:(i16)scale
+                          raiseOnExactness:(bool)exactness
+                           raiseOnOverflow:(bool)overflow
+                          raiseOnUnderflow:(bool)underflow
+                       raiseOnDivideByZero:(bool)divide_by_zero {
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initWithRoundingMode:rounding_mode
+                                               scale:scale
+                                    raiseOnExactness:exactness
+                                     raiseOnOverflow:overflow
+                                    raiseOnUnderflow:underflow
+                                 raiseOnDivideByZero:divide_by_zero];
+    autorelease(env, new)
+}
+
+- (id)initWithRoundingMode:(NSRoundingMode)rounding_mode
+                     scale:(i16)scale
+          raiseOnExactness:(bool)exactness
+           raiseOnOverflow:(bool)overflow
+          raiseOnUnderflow:(bool)underflow
+       raiseOnDivideByZero:(bool)divide_by_zero {
+    // Check the mode is valid now rather than when it's used.
+    RoundingMode::from_raw(rounding_mode);
+    *env.objc.borrow_mut(this) = NSDecimalNumberHandlerHostObject {
+        rounding_mode,
+        scale,
+        raise_on_exactness: exactness,
+        raise_on_overflow: overflow,
+        raise_on_underflow: underflow,
+        raise_on_divide_by_zero: divide_by_zero,
+    };
+    this
+}
+
+// NSDecimalNumberBehaviors implementation
+
+- (NSRoundingMode)roundingMode {
+    env.objc.borrow::<NSDecimalNumberHandlerHostObject>(this).rounding_mode
+}
+
+- (i16)scale {
+    env.objc.borrow::<NSDecimalNumberHandlerHostObject>(this).scale
+}
+
+- (id)exceptionDuringOperation:(SEL)_operation
+                         error:(NSCalculationError)error
+                   leftOperand:(id)_left
+                  rightOperand:(id)_right {
+    let host_object = env.objc.borrow::<NSDecimalNumberHandlerHostObject>(this);
+    let (raise, name, reason) = match error {
+        NSCalculationNoError => return nil,
+        NSCalculationLossOfPrecision => (
+            host_object.raise_on_exactness,
+            "NSDecimalNumberExactnessException",
+            "NSDecimalNumber exactness exception",
+        ),
+        NSCalculationUnderflow => (
+            host_object.raise_on_underflow,
+            "NSDecimalNumberUnderflowException",
+            "NSDecimalNumber underflow exception",
+        ),
+        NSCalculationOverflow => (
+            host_object.raise_on_overflow,
+            "NSDecimalNumberOverflowException",
+            "NSDecimalNumber overflow exception",
+        ),
+        NSCalculationDivideByZero => (
+            host_object.raise_on_divide_by_zero,
+            "NSDecimalNumberDivideByZeroException",
+            "NSDecimalNumber divide by zero exception",
+        ),
+        _ => unimplemented!("NSCalculationError {}", error),
+    };
+    if raise {
+        let name = get_static_str(env, name);
+        let reason = get_static_str(env, reason);
+        let exception: id = msg_class![env; NSException exceptionWithName:name
+                                                                   reason:reason
+                                                                 userInfo:nil];
+        () = msg![env; exception raise];
+    }
+    nil
+}
+
+@end
+
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(string: &str) -> Decimal {
+        Decimal::parse_prefix(string, ".").unwrap().0
+    }
+
+    #[test]
+    fn parse_and_display() {
+        assert_eq!(parse("1234567.891").to_string(), "1234567.891");
+        assert_eq!(parse("-0.0500").to_string(), "-0.05");
+        assert_eq!(parse("1.5e3").to_string(), "1500");
+        assert_eq!(parse("+12abc").to_string(), "12");
+        assert_eq!(parse("0").to_string(), "0");
+        assert_eq!(
+            Decimal::parse_prefix("1,25 €", ","),
+            Some((parse("1.25"), " €"))
+        );
+        assert_eq!(Decimal::parse_prefix("abc", "."), None);
+        assert_eq!(Decimal::NAN.to_string(), "NaN");
+        // More than 38 significant digits get rounded
+        assert_eq!(
+            parse("123456789012345678901234567890123456789").to_string(),
+            "123456789012345678901234567890123456790"
+        );
+    }
+
+    #[test]
+    fn from_f64() {
+        assert_eq!(Decimal::from_f64(0.1).to_string(), "0.1");
+        assert_eq!(Decimal::from_f64(-1234567.891).to_string(), "-1234567.891");
+        assert!(Decimal::from_f64(f64::NAN).is_nan());
+        assert_eq!(parse("1234567.891").to_f64(), 1234567.891);
+    }
+
+    #[test]
+    fn arithmetic() {
+        let sum = parse("0.1").add(parse("0.2")).unwrap();
+        assert_eq!(sum.compare(&parse("0.3")), Ordering::Equal);
+        assert_eq!(parse("1").sub(parse("1.75")).unwrap().to_string(), "-0.75");
+        assert_eq!(parse("1.5").mul(parse("-4")).unwrap().to_string(), "-6");
+        assert_eq!(parse("1").div(parse("8")).unwrap().to_string(), "0.125");
+        assert_eq!(
+            parse("1").div(parse("3")).unwrap().to_string(),
+            format!("0.{}", "3".repeat(38))
+        );
+        assert_eq!(
+            parse("2").div(parse("3")).unwrap().to_string(),
+            format!("0.{}7", "6".repeat(37))
+        );
+        assert_eq!(
+            parse("1").div(Decimal::ZERO),
+            Err(CalculationError::DivideByZero)
+        );
+        assert!(Decimal::NAN.add(Decimal::ONE).unwrap().is_nan());
+        assert_eq!(
+            parse(&format!("{}e127", "9".repeat(38))).mul(parse("10")),
+            Err(CalculationError::Overflow)
+        );
+        assert_eq!(
+            parse("1.5").mul_power_of_10(-3).unwrap().to_string(),
+            "0.0015"
+        );
+        assert_eq!(
+            parse("1").mul_power_of_10(200),
+            Err(CalculationError::Overflow)
+        );
+        assert_eq!(
+            parse("1").mul_power_of_10(-200),
+            Err(CalculationError::Underflow)
+        );
+        // Adding values with very different exponents loses the smaller one
+        assert_eq!(parse("1e100").add(parse("1e-100")).unwrap(), parse("1e100"));
+    }
+
+    #[test]
+    fn rounding() {
+        let round = |string, scale, mode| parse(string).round(scale, mode).unwrap().to_string();
+        assert_eq!(round("2.345", 2, RoundingMode::Plain), "2.35");
+        assert_eq!(round("-2.345", 2, RoundingMode::Plain), "-2.35");
+        assert_eq!(round("2.345", 2, RoundingMode::Bankers), "2.34");
+        assert_eq!(round("2.355", 2, RoundingMode::Bankers), "2.36");
+        assert_eq!(round("2.341", 2, RoundingMode::Up), "2.35");
+        assert_eq!(round("-2.341", 2, RoundingMode::Up), "-2.34");
+        assert_eq!(round("-2.341", 2, RoundingMode::Down), "-2.35");
+        assert_eq!(round("1250", -2, RoundingMode::Plain), "1300");
+        assert_eq!(round("1.5", 3, RoundingMode::Plain), "1.5");
+    }
+
+    #[test]
+    fn compare() {
+        assert_eq!(parse("10").compare(&parse("9.99")), Ordering::Greater);
+        assert_eq!(parse("-10").compare(&parse("-9.99")), Ordering::Less);
+        assert_eq!(parse("0").compare(&parse("-0.0")), Ordering::Equal);
+        assert_eq!(parse("1.10").compare(&parse("1.1")), Ordering::Equal);
+        assert_eq!(Decimal::NAN.compare(&parse("-1")), Ordering::Less);
+    }
+}
@@ -5,8 +5,11 @@
  */
 //! The `NSDictionary` class cluster, including `NSMutableDictionary`.
 
-use super::ns_property_list_serialization::deserialize_plist_from_file;
-use super::{ns_array, ns_string, ns_url, NSInteger, NSUInteger};
+use super::ns_property_list_serialization::{
+    deserialize_plist_from_file, serialize_plist_to_file,
+};
+use super::ns_unarchiver::{self, TypedValue};
+use super::{ns_array, ns_keyed_unarchiver, ns_string, ns_url, NSInteger, NSUInteger};
 use crate::abi::VaList;
 use crate::fs::GuestPath;
 use crate::objc::{
@@ -15,6 +18,7 @@ use crate::objc::{
 };
 use crate::Environment;
 use std::collections::HashMap;
+use std::fmt::Write;
 
 /// Alias for the return type of the `hash` method of the `NSObject` protocol.
 type Hash = NSUInteger;
@@ -39,7 +43,7 @@ impl DictionaryHostObject {
             return nil;
         };
         for &(candidate_key, value) in collisions {
-            if candidate_key == key || msg![env; candidate_key isEqualTo:key] {
+            if candidate_key == key || msg![env; candidate_key isEqual:key] {
                 return value;
             }
         }
@@ -61,7 +65,7 @@ impl DictionaryHostObject {
             return;
         };
         for &mut (candidate_key, ref mut existing_value) in collisions.iter_mut() {
-            if candidate_key == key || msg![env; candidate_key isEqualTo:key] {
+            if candidate_key == key || msg![env; candidate_key isEqual:key] {
                 release(env, *existing_value);
                 *existing_value = value;
                 return;
@@ -76,7 +80,7 @@ impl DictionaryHostObject {
             return;
         };
         let idx = collisions.iter().position(|&(candidate_key, _)| {
-            candidate_key == key || msg![env; candidate_key isEqualTo:key]
+            candidate_key == key || msg![env; candidate_key isEqual:key]
         }).unwrap();
         let (existing_key, value) = collisions[idx];
         release(env, existing_key);
@@ -91,6 +95,17 @@ impl DictionaryHostObject {
             }
         }
     }
+    /// Make a shallow copy: the keys and values are retained, not copied.
+    pub(super) fn clone_retained(&self, env: &mut Environment) -> DictionaryHostObject {
+        for &(key, value) in self.map.values().flatten() {
+            retain(env, key);
+            retain(env, value);
+        }
+        DictionaryHostObject {
+            map: self.map.clone(),
+            count: self.count,
+        }
+    }
     pub(super) fn iter_keys(&self) -> impl Iterator<Item = id> + '_ {
         self.map.values().flatten().map(|&(key, _value)| key)
     }
@@ -195,16 +210,69 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 // NSCopying implementation
 - (id)copyWithZone:(NSZonePtr)_zone {
-    // TODO: override this once we have NSMutableString!
     retain(env, this)
 }
 
+// NSMutableCopying implementation
+- (id)mutableCopyWithZone:(NSZonePtr)_zone {
+    shallow_copy(env, this, "NSMutableDictionary")
+}
+
+// FIXME: writes should be atomic
+- (bool)writeToFile:(id)path // NSString*
+         atomically:(bool)_use_aux_file {
+    let path = ns_string::to_rust_string(env, path);
+    serialize_plist_to_file(env, this, GuestPath::new(&path))
+}
+
 // TODO
 
+- (id)description {
+    msg![env; this descriptionWithLocale:nil indent:(0 as NSUInteger)]
+}
+- (id)descriptionWithLocale:(id)_locale // NSLocale*
+                     indent:(NSUInteger)level {
+    super::describe_collection(env, this, |env| {
+        let mut pairs: Vec<(id, id)> = env
+            .objc
+            .borrow::<DictionaryHostObject>(this)
+            .map
+            .values()
+            .flatten()
+            .copied()
+            .collect();
+
+        // Apple sorts the entries if the keys can be compared. Only strings are
+        // handled here, which is the common case.
+        let string_class = env.objc.get_known_class("NSString", &mut env.mem);
+        if pairs.iter().all(|&(key, _)| msg![env; key isKindOfClass:string_class]) {
+            let mut keyed: Vec<_> = pairs
+                .into_iter()
+                .map(|(key, value)| (ns_string::to_rust_string(env, key), key, value))
+                .collect();
+            keyed.sort_by(|(a, _, _), (b, _, _)| a.cmp(b));
+            pairs = keyed.into_iter().map(|(_, key, value)| (key, value)).collect();
+        }
+
+        let indent = "    ".repeat(level as usize);
+        let mut res = format!("{}{{\n", indent);
+        for (key, value) in pairs {
+            let key = super::element_description(env, key, level + 1);
+            let value = super::element_description(env, value, level + 1);
+            writeln!(res, "{}    {} = {};", indent, key, value).unwrap();
+        }
+        write!(res, "{}}}", indent).unwrap();
+        res
+    })
+}
+
 - (id)allKeys {
-    let dict_host_obj: DictionaryHostObject = std::mem::take(env.objc.borrow_mut(this));
-    let keys: Vec<id> = dict_host_obj.iter_keys().collect();
-    ns_array::from_vec(env, keys)
+    let keys: Vec<id> = env.objc.borrow::<DictionaryHostObject>(this).iter_keys().collect();
+    for &key in &keys {
+        retain(env, key);
+    }
+    let keys = ns_array::from_vec(env, keys);
+    autorelease(env, keys)
 }
 
 // FIXME: those are from NSUserDefaults!
@@ -275,6 +343,12 @@ pub const CLASSES: ClassExports = objc_classes! {
     this
 }
 
+// NSCoding implementation
+- (id)initWithCoder:(id)coder {
+    *env.objc.borrow_mut(this) = decode_dictionary(env, coder);
+    this
+}
+
 // TODO: enumeration, more init methods, etc
 
 - (NSUInteger)count {
@@ -292,8 +366,147 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 @end
 
+// NSMutableDictionary is an abstract class. A subclass must provide everything
+// NSDictionary provides, plus:
+// - (void)setObject:(id)object forKey:(id)key;
+// - (void)removeObjectForKey:(id)key;
+// Note that it inherits from NSDictionary, so we must ensure we override any
+// default methods that would be inappropriate for mutability.
+@implementation NSMutableDictionary: NSDictionary
+
++ (id)allocWithZone:(NSZonePtr)zone {
+    // NSMutableDictionary might be subclassed by something which needs
+    // allocWithZone: to have the normal behaviour. Unimplemented: call
+    // superclass alloc then.
+    assert!(this == env.objc.get_known_class("NSMutableDictionary", &mut env.mem));
+    msg_class![env; _touchHLE_NSMutableDictionary allocWithZone:zone]
+}
+
++ (id)dictionaryWithCapacity:(NSUInteger)capacity {
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initWithCapacity:capacity];
+    autorelease(env, new)
+}
+
+// NSCopying implementation
+- (id)copyWithZone:(NSZonePtr)_zone {
+    shallow_copy(env, this, "NSDictionary")
+}
+
+@end
+
+// Our private subclass that is the single implementation of
+// NSMutableDictionary for the time being.
+@implementation _touchHLE_NSMutableDictionary: NSMutableDictionary
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::<DictionaryHostObject>::default();
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+- (())dealloc {
+    std::mem::take(env.objc.borrow_mut::<DictionaryHostObject>(this)).release(env);
+
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+- (id)initWithObjectsAndKeys:(id)first_object, ...dots {
+    init_with_objects_and_keys(env, this, first_object, dots.start())
+}
+
+- (id)init {
+    *env.objc.borrow_mut(this) = <DictionaryHostObject as Default>::default();
+    this
+}
+
+- (id)initWithCapacity:(NSUInteger)capacity {
+    *env.objc.borrow_mut(this) = DictionaryHostObject {
+        map: HashMap::with_capacity(capacity as usize),
+        count: 0,
+    };
+    this
+}
+
+// NSCoding implementation
+- (id)initWithCoder:(id)coder {
+    *env.objc.borrow_mut(this) = decode_dictionary(env, coder);
+    this
+}
+
+- (NSUInteger)count {
+    env.objc.borrow::<DictionaryHostObject>(this).count
+}
+- (id)objectForKey:(id)key {
+    let host_obj: DictionaryHostObject = std::mem::take(env.objc.borrow_mut(this));
+    let res = host_obj.lookup(env, key);
+    *env.objc.borrow_mut(this) = host_obj;
+    res
+}
+
+// TODO: more mutation methods
+
+- (())setObject:(id)object
+         forKey:(id)key {
+    assert!(object != nil); // TODO: raise proper exception
+    assert!(key != nil); // TODO: raise proper exception
+    let mut host_obj: DictionaryHostObject = std::mem::take(env.objc.borrow_mut(this));
+    host_obj.insert(env, key, object, /* copy_key: */ true);
+    *env.objc.borrow_mut(this) = host_obj;
+}
+
+// NSKeyValueCoding
+- (())setValue:(id)value
+       forKey:(id)key { // NSString*
+    let mut host_obj: DictionaryHostObject = std::mem::take(env.objc.borrow_mut(this));
+    if value == nil {
+        host_obj.remove(env, key);
+    } else {
+        host_obj.insert(env, key, value, /* copy_key: */ true);
+    }
+    *env.objc.borrow_mut(this) = host_obj;
+}
+
+@end
+
 };
 
+/// Shared implementation of `initWithCoder:`.
+fn decode_dictionary(env: &mut Environment, coder: id) -> DictionaryHostObject {
+    let mut host_object = <DictionaryHostObject as Default>::default();
+
+    if msg![env; coder allowsKeyedCoding] {
+        for (key, value) in ns_keyed_unarchiver::decode_current_dictionary(env, coder) {
+            host_object.insert(env, key, value, /* copy_key: */ true);
+        }
+        return host_object;
+    }
+
+    // NSArchiver writes the count followed by each key and its value.
+    let TypedValue::Integer(count) = ns_unarchiver::decode_value(env, coder, b"i") else {
+        unreachable!();
+    };
+    for _ in 0..count {
+        let key = ns_unarchiver::decode_object(env, coder);
+        let value = ns_unarchiver::decode_object(env, coder);
+        host_object.insert(env, key, value, /* copy_key: */ true);
+    }
+    host_object
+}
+
+/// Shared implementation of `copyWithZone:` and `mutableCopyWithZone:`: a new
+/// instance of `class_name` with the same keys and values, which are retained
+/// rather than copied.
+fn shallow_copy(env: &mut Environment, dict: id, class_name: &str) -> id {
+    let host_obj: DictionaryHostObject = std::mem::take(env.objc.borrow_mut(dict));
+    let new_host_obj = host_obj.clone_retained(env);
+    *env.objc.borrow_mut(dict) = host_obj;
+
+    let class = env.objc.get_known_class(class_name, &mut env.mem);
+    let new: id = msg![env; class alloc];
+    *env.objc.borrow_mut(new) = new_host_obj;
+    new
+}
+
 /// Direct constructor for use by host code, similar to
 /// `[[NSDictionary alloc] initWithObjectsAndKeys:]` but without variadics and
 /// with a more intuitive argument order. Unlike [super::ns_array::from_vec],
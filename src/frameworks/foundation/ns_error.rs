@@ -0,0 +1,156 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `NSError`.
+
+use super::ns_string::{from_rust_string, get_static_str, to_rust_string};
+use super::NSInteger;
+use crate::dyld::{ConstantExports, HostConstant};
+use crate::mem::MutPtr;
+use crate::objc::{
+    autorelease, id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject,
+    NSZonePtr,
+};
+use crate::Environment;
+
+pub const NSCocoaErrorDomain: &str = "NSCocoaErrorDomain";
+pub const NSPOSIXErrorDomain: &str = "NSPOSIXErrorDomain";
+pub const NSURLErrorDomain: &str = "NSURLErrorDomain";
+
+// Error codes in NSCocoaErrorDomain (FoundationErrors.h)
+pub const NSFileNoSuchFileError: NSInteger = 4;
+pub const NSFileReadNoSuchFileError: NSInteger = 260;
+pub const NSFileWriteNoPermissionError: NSInteger = 513;
+pub const NSFileWriteFileExistsError: NSInteger = 516;
+
+// Error codes in NSURLErrorDomain (NSURLError.h)
+pub const NSURLErrorNotConnectedToInternet: NSInteger = -1009;
+
+pub const CONSTANTS: ConstantExports = &[
+    (
+        "_NSCocoaErrorDomain",
+        HostConstant::NSString(NSCocoaErrorDomain),
+    ),
+    (
+        "_NSPOSIXErrorDomain",
+        HostConstant::NSString(NSPOSIXErrorDomain),
+    ),
+    (
+        "_NSURLErrorDomain",
+        HostConstant::NSString(NSURLErrorDomain),
+    ),
+];
+
+#[derive(Default)]
+struct NSErrorHostObject {
+    /// `NSString*`
+    domain: id,
+    code: NSInteger,
+    /// `NSDictionary*`
+    user_info: id,
+}
+impl HostObject for NSErrorHostObject {}
+
+/// Create an `NSError` and store it in an `NSError**` out parameter, if the
+/// app provided one. For use by host implementations of methods with an
+/// `error:` parameter.
+pub fn set_error(
+    env: &mut Environment,
+    error_out: MutPtr<id>,
+    domain: &'static str,
+    code: NSInteger,
+) {
+    if error_out.is_null() {
+        return;
+    }
+    let domain = get_static_str(env, domain);
+    let error: id = msg_class![env; NSError errorWithDomain:domain code:code userInfo:nil];
+    env.mem.write(error_out, error);
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation NSError: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::<NSErrorHostObject>::default();
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
++ (id)errorWithDomain:(id)domain // NSString*
+                 code:(NSInteger)code
+             userInfo:(id)user_info { // NSDictionary*
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initWithDomain:domain code:code userInfo:user_info];
+    autorelease(env, new)
+}
+
+- (id)initWithDomain:(id)domain // NSString*
+                code:(NSInteger)code
+            userInfo:(id)user_info { // NSDictionary*
+    assert!(domain != nil);
+    let domain: id = msg![env; domain copy];
+    let user_info = retain(env, user_info);
+    *env.objc.borrow_mut(this) = NSErrorHostObject {
+        domain,
+        code,
+        user_info,
+    };
+    this
+}
+
+- (())dealloc {
+    let NSErrorHostObject {
+        domain,
+        user_info,
+        ..
+    } = std::mem::take(env.objc.borrow_mut(this));
+    release(env, domain);
+    release(env, user_info);
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+// NSCopying implementation
+- (id)copyWithZone:(NSZonePtr)_zone {
+    // Errors are immutable, so there's no need to make a new one.
+    retain(env, this)
+}
+
+- (id)domain {
+    env.objc.borrow::<NSErrorHostObject>(this).domain
+}
+- (NSInteger)code {
+    env.objc.borrow::<NSErrorHostObject>(this).code
+}
+- (id)userInfo {
+    env.objc.borrow::<NSErrorHostObject>(this).user_info
+}
+
+- (id)localizedDescription {
+    let &NSErrorHostObject { domain, code, .. } = env.objc.borrow(this);
+    // TODO: use NSLocalizedDescriptionKey from the user info, and Apple's
+    // descriptions for well-known errors
+    let domain = to_rust_string(env, domain);
+    let description = format!(
+        "The operation couldn’t be completed. ({} error {}.)",
+        domain, code
+    );
+    let description = from_rust_string(env, description);
+    autorelease(env, description)
+}
+
+- (id)description {
+    let &NSErrorHostObject { domain, code, .. } = env.objc.borrow(this);
+    let domain = to_rust_string(env, domain);
+    let description = format!("Error Domain={} Code={}", domain, code);
+    let description = from_rust_string(env, description);
+    autorelease(env, description)
+}
+
+@end
+
+};
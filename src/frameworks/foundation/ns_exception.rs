@@ -3,8 +3,351 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
+//! `NSException`, `NSAssertionHandler` and related functions.
+//!
+//! On armv6 and armv7, `@try`/`@catch` uses `setjmp()`-based exceptions: each
+//! `@try` registers a frame with `objc_exception_try_enter` and then calls
+//! `_setjmp()` itself, and `objc_exception_throw` jumps to the innermost frame.
+//! touchHLE can't unwind host stack frames, so a frame can only catch an
+//! exception thrown by host code that the frame's own guest code called
+//! (directly or through other host code). Otherwise, or if there is no frame,
+//! the exception is treated as uncaught: the app's uncaught exception handler
+//! is called, if there is one (crash reporters like to install these), and
+//! then the app is aborted with a message describing the exception.
 
-use crate::dyld::{ConstantExports, HostConstant};
+use super::ns_string::{from_rust_string, get_static_str, to_rust_string, with_format};
+use super::NSInteger;
+use crate::abi::{CallFromHost, GuestFunction};
+use crate::dyld::{export_c_func, ConstantExports, FunctionExports, HostConstant};
+use crate::environment::ThreadId;
+use crate::mem::{ConstVoidPtr, GuestUSize, MutPtr, MutVoidPtr, Ptr};
+use crate::objc::{
+    autorelease, id, msg, msg_class, nil, objc_classes, release, retain, Class, ClassExports,
+    HostObject, NSZonePtr, SEL,
+};
+use crate::Environment;
+use std::collections::HashMap;
+
+pub const NSInternalInconsistencyException: &str = "NSInternalInconsistencyException";
+
+#[derive(Default)]
+pub struct State {
+    /// `NSUncaughtExceptionHandler*`
+    uncaught_exception_handler: Option<GuestFunction>,
+    /// Apple has one assertion handler per thread, but since it has no state,
+    /// touchHLE shares one between all threads.
+    assertion_handler: Option<id>,
+    /// Frames registered by `objc_exception_try_enter`, innermost last.
+    try_frames: HashMap<ThreadId, Vec<TryFrame>>,
+}
+
+/// A `@try` block, see [objc_exception_try_enter].
+struct TryFrame {
+    /// `objc_exception_data*` (a `jmp_buf` followed by four pointers)
+    data: MutVoidPtr,
+    /// [Environment::guest_call_depth] of the guest code in the `@try` block.
+    guest_call_depth: u32,
+}
+
+/// Size of `jmp_buf`, which is at the start of the data for a [TryFrame].
+const JMP_BUF_SIZE: GuestUSize = (10 + 16 + 2) * 4;
+
+/// Get the pointer in the data for a [TryFrame] where the caught exception is
+/// stored.
+fn caught_exception_ptr(data: MutVoidPtr) -> MutPtr<id> {
+    (data.cast::<u8>() + JMP_BUF_SIZE).cast()
+}
+impl State {
+    fn get(env: &mut Environment) -> &mut Self {
+        &mut env.framework_state.foundation.ns_exception
+    }
+}
+
+#[derive(Default)]
+struct NSExceptionHostObject {
+    /// `NSString*`
+    name: id,
+    /// `NSString*`
+    reason: id,
+    /// `NSDictionary*`
+    user_info: id,
+}
+impl HostObject for NSExceptionHostObject {}
+
+/// Message logged when an exception reaches the top level. This is the same
+/// wording Apple uses, which makes it easy to search for.
+fn uncaught_exception_message(name: &str, reason: &str) -> String {
+    format!(
+        "*** Terminating app due to uncaught exception '{}', reason: '{}'",
+        name, reason
+    )
+}
+
+/// Message logged by `NSAssertionHandler` before it raises an exception.
+/// `function` is e.g. `-[MyClass doThing:]` or the name of a C function.
+fn assertion_failure_message(function: &str, file: &str, line: NSInteger) -> String {
+    format!("*** Assertion failure in {}, {}:{}", function, file, line)
+}
+
+fn string_or_null(env: &mut Environment, string: id) -> String {
+    if string == nil {
+        "(null)".to_string()
+    } else {
+        to_rust_string(env, string).into_owned()
+    }
+}
+
+/// Implementation of `@throw`. If the exception is caught (see the module
+/// docs), the guest jumps to the `@catch` once the host function it called
+/// returns, so host code calling this must not rely on it not returning.
+fn objc_exception_throw(env: &mut Environment, exception: id) {
+    let thread = env.current_thread;
+    if let Some(frame) = State::get(env)
+        .try_frames
+        .get_mut(&thread)
+        .and_then(Vec::pop)
+    {
+        if frame.guest_call_depth == env.guest_call_depth() {
+            log_dbg!(
+                "objc_exception_throw({:?}) caught by frame {:?}",
+                exception,
+                frame.data
+            );
+            env.mem.write(caught_exception_ptr(frame.data), exception);
+            env.schedule_longjmp(frame.data);
+            return;
+        }
+        log!(
+            "Warning: can't catch exception {:?} in frame {:?}, since that would mean unwinding host code",
+            exception,
+            frame.data
+        );
+    }
+
+    let name: id = msg![env; exception name];
+    let reason: id = msg![env; exception reason];
+    let name = string_or_null(env, name);
+    let reason = string_or_null(env, reason);
+    echo!("{}", uncaught_exception_message(&name, &reason));
+
+    // Taken rather than copied, so that a handler that throws an exception
+    // itself doesn't loop forever.
+    if let Some(handler) = State::get(env).uncaught_exception_handler.take() {
+        echo!("Calling the app's uncaught exception handler.");
+        () = handler.call_from_host(env, (exception,));
+    }
+
+    crate::libc::stdlib::abort(env);
+}
+
+/// Called at the start of a `@try` block, before `_setjmp(data)`.
+fn objc_exception_try_enter(env: &mut Environment, data: MutVoidPtr) {
+    let guest_call_depth = env.guest_call_depth();
+    let thread = env.current_thread;
+    State::get(env)
+        .try_frames
+        .entry(thread)
+        .or_default()
+        .push(TryFrame {
+            data,
+            guest_call_depth,
+        });
+}
+
+/// Called when leaving a `@try` block without an exception.
+fn objc_exception_try_exit(env: &mut Environment, data: MutVoidPtr) {
+    let thread = env.current_thread;
+    let frames = State::get(env).try_frames.entry(thread).or_default();
+    // The frame should be the innermost one, but be forgiving if the app left
+    // some other frame without telling us.
+    if let Some(index) = frames.iter().rposition(|frame| frame.data == data) {
+        frames.truncate(index);
+    }
+}
+
+/// Get the exception that was caught by a `@try` block.
+fn objc_exception_extract(env: &mut Environment, data: MutVoidPtr) -> id {
+    env.mem.read(caught_exception_ptr(data))
+}
+
+/// Check whether a `@catch` clause for a class matches an exception.
+fn objc_exception_match(env: &mut Environment, class: Class, exception: id) -> bool {
+    msg![env; exception isKindOfClass:class]
+}
+
+fn NSSetUncaughtExceptionHandler(env: &mut Environment, handler: GuestFunction) {
+    State::get(env).uncaught_exception_handler =
+        (handler.addr_with_thumb_bit() != 0).then_some(handler);
+}
+
+fn NSGetUncaughtExceptionHandler(env: &mut Environment) -> ConstVoidPtr {
+    match State::get(env).uncaught_exception_handler {
+        Some(handler) => handler.to_ptr(),
+        None => Ptr::null(),
+    }
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(objc_exception_throw(_)),
+    export_c_func!(objc_exception_try_enter(_)),
+    export_c_func!(objc_exception_try_exit(_)),
+    export_c_func!(objc_exception_extract(_)),
+    export_c_func!(objc_exception_match(_, _)),
+    export_c_func!(NSSetUncaughtExceptionHandler(_)),
+    export_c_func!(NSGetUncaughtExceptionHandler()),
+];
+
+/// Log an assertion failure and raise `NSInternalInconsistencyException` with
+/// the formatted description as its reason.
+fn handle_failure(
+    env: &mut Environment,
+    function: &str,
+    file_name: id,
+    line: NSInteger,
+    reason: id,
+) {
+    let file_name = string_or_null(env, file_name);
+    echo!("{}", assertion_failure_message(function, &file_name, line));
+
+    let name = get_static_str(env, NSInternalInconsistencyException);
+    let exception: id = msg_class![env; NSException exceptionWithName:name
+                                                               reason:reason
+                                                             userInfo:nil];
+    () = msg![env; exception raise];
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation NSException: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::<NSExceptionHostObject>::default();
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
++ (id)exceptionWithName:(id)name // NSString*
+                 reason:(id)reason // NSString*
+               userInfo:(id)user_info { // NSDictionary*
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initWithName:name reason:reason userInfo:user_info];
+    autorelease(env, new)
+}
+
++ (())raise:(id)name // NSString*
+     format:(id)format, // NSString*
+            ...args {
+    let reason = with_format(env, format, args.start());
+    let reason = from_rust_string(env, reason);
+    let reason = autorelease(env, reason);
+    let exception: id = msg![env; this exceptionWithName:name reason:reason userInfo:nil];
+    () = msg![env; exception raise];
+}
+
+- (id)initWithName:(id)name // NSString*
+            reason:(id)reason // NSString*
+          userInfo:(id)user_info { // NSDictionary*
+    let name: id = msg![env; name copy];
+    let reason: id = msg![env; reason copy];
+    let user_info = retain(env, user_info);
+    *env.objc.borrow_mut(this) = NSExceptionHostObject {
+        name,
+        reason,
+        user_info,
+    };
+    this
+}
+
+- (())dealloc {
+    let NSExceptionHostObject {
+        name,
+        reason,
+        user_info,
+    } = std::mem::take(env.objc.borrow_mut(this));
+    release(env, name);
+    release(env, reason);
+    release(env, user_info);
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+- (id)name {
+    env.objc.borrow::<NSExceptionHostObject>(this).name
+}
+- (id)reason {
+    env.objc.borrow::<NSExceptionHostObject>(this).reason
+}
+- (id)userInfo {
+    env.objc.borrow::<NSExceptionHostObject>(this).user_info
+}
+
+- (id)description {
+    env.objc.borrow::<NSExceptionHostObject>(this).reason
+}
+
+- (())raise {
+    objc_exception_throw(env, this)
+}
+
+@end
+
+@implementation NSAssertionHandler: NSObject
+
++ (id)currentHandler {
+    if let Some(handler) = State::get(env).assertion_handler {
+        return handler;
+    }
+    let new: id = msg![env; this new];
+    State::get(env).assertion_handler = Some(new);
+    new
+}
+
+// These are what the NSAssert() and NSCAssert() family of macros call.
+
+- (())handleFailureInMethod:(SEL)selector
+                     object:(id)object
+                       file:(id)file_name // NSString*
+                 lineNumber:(NSInteger)line
+                description:(id)format, // NSString*
+                            ...args {
+    // Sending -class to a class returns the class itself.
+    let class: id = msg![env; object class];
+    let kind = if class == object { '+' } else { '-' };
+    let function = format!(
+        "{}[{} {}]",
+        kind,
+        env.objc.get_class_name(class),
+        selector.as_str(&env.mem)
+    );
+    let reason = if format == nil {
+        nil
+    } else {
+        let reason = with_format(env, format, args.start());
+        let reason = from_rust_string(env, reason);
+        autorelease(env, reason)
+    };
+    handle_failure(env, &function, file_name, line, reason)
+}
+
+- (())handleFailureInFunction:(id)function_name // NSString*
+                         file:(id)file_name // NSString*
+                   lineNumber:(NSInteger)line
+                  description:(id)format, // NSString*
+                              ...args {
+    let function = string_or_null(env, function_name);
+    let reason = if format == nil {
+        nil
+    } else {
+        let reason = with_format(env, format, args.start());
+        let reason = from_rust_string(env, reason);
+        autorelease(env, reason)
+    };
+    handle_failure(env, &function, file_name, line, reason)
+}
+
+@end
+
+};
 
 // All constants are NSExceptionName
 pub const CONSTANTS: ConstantExports = &[
@@ -42,7 +385,7 @@ pub const CONSTANTS: ConstantExports = &[
     ),
     (
         "_NSInternalInconsistencyException",
-        HostConstant::NSString("NSInternalInconsistencyException"),
+        HostConstant::NSString(NSInternalInconsistencyException),
     ),
     (
         "_NSInvalidArchiveOperationException",
@@ -273,3 +616,29 @@ pub const CONSTANTS: ConstantExports = &[
         HostConstant::NSString("UIApplicationInvalidInterfaceOrientationException"),
     ),
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uncaught_exception_message() {
+        assert_eq!(
+            uncaught_exception_message("NSInternalInconsistencyException", "bad state"),
+            "*** Terminating app due to uncaught exception \
+             'NSInternalInconsistencyException', reason: 'bad state'"
+        );
+    }
+
+    #[test]
+    fn test_assertion_failure_message() {
+        assert_eq!(
+            assertion_failure_message("-[Foo bar:]", "/src/Foo.m", 42),
+            "*** Assertion failure in -[Foo bar:], /src/Foo.m:42"
+        );
+        assert_eq!(
+            assertion_failure_message("main", "main.m", 7),
+            "*** Assertion failure in main, main.m:7"
+        );
+    }
+}
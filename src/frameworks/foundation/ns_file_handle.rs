@@ -1,21 +1,53 @@
 use super::ns_string;
 use super::NSUInteger;
 use crate::libc::posix_io;
-use crate::mem::{ConstPtr, ConstVoidPtr};
-use crate::objc::{autorelease, id, nil, objc_classes, ClassExports, HostObject};
-use crate::{msg, msg_class};
+use crate::mem::{ConstPtr, ConstVoidPtr, GuestUSize, MutVoidPtr};
+use crate::objc::{autorelease, id, nil, objc_classes, ClassExports, HostObject, NSZonePtr};
+use crate::{msg, msg_class, Environment};
 
 struct NSFileHandleHostObject {
+    /// -1 once the handle has been closed.
     fd: posix_io::FileDescriptor,
+    close_on_dealloc: bool,
 }
 impl HostObject for NSFileHandleHostObject {}
 
+/// Read until end-of-file, or until there's nothing more available right now
+/// (e.g. an empty pipe whose write end is still open).
+fn read_until_end(env: &mut Environment, fd: posix_io::FileDescriptor) -> id {
+    const CHUNK_SIZE: GuestUSize = 4096;
+    let mut bytes = Vec::new();
+    if fd != -1 {
+        let chunk: MutVoidPtr = env.mem.alloc(CHUNK_SIZE);
+        loop {
+            let bytes_read = posix_io::read(env, fd, chunk, CHUNK_SIZE);
+            if bytes_read <= 0 {
+                break;
+            }
+            bytes.extend_from_slice(env.mem.bytes_at(chunk.cast(), bytes_read as GuestUSize));
+        }
+        env.mem.free(chunk);
+    }
+    let length: NSUInteger = bytes.len().try_into().unwrap();
+    let buffer: MutVoidPtr = env.mem.alloc(length);
+    env.mem.bytes_at_mut(buffer.cast(), length).copy_from_slice(&bytes);
+    msg_class![env; NSData dataWithBytesNoCopy:buffer length:length]
+}
+
 pub const CLASSES: ClassExports = objc_classes! {
 
 (env, this, _cmd);
 
 @implementation NSFileHandle: NSObject
 
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(NSFileHandleHostObject {
+        fd: -1,
+        close_on_dealloc: false,
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
 + (id)fileHandleForReadingAtPath:(id)path { // NSString*
     log_dbg!("fileHandleForReadingAtPath {}", ns_string::to_rust_string(env, path));
     let path_str: ConstPtr<u8> = msg![env; path UTF8String];
@@ -23,7 +55,8 @@ pub const CLASSES: ClassExports = objc_classes! {
         -1 => nil,
         fd => {
             let host_object = Box::new(NSFileHandleHostObject {
-                fd
+                fd,
+                close_on_dealloc: true,
             });
             let new = env.objc.alloc_object(this, host_object, &mut env.mem);
             autorelease(env, new)
@@ -38,7 +71,8 @@ pub const CLASSES: ClassExports = objc_classes! {
         -1 => nil,
         fd => {
             let host_object = Box::new(NSFileHandleHostObject {
-                fd
+                fd,
+                close_on_dealloc: true,
             });
             let new = env.objc.alloc_object(this, host_object, &mut env.mem);
             autorelease(env, new)
@@ -53,7 +87,8 @@ pub const CLASSES: ClassExports = objc_classes! {
         -1 => nil,
         fd => {
             let host_object = Box::new(NSFileHandleHostObject {
-                fd
+                fd,
+                close_on_dealloc: true,
             });
             let new = env.objc.alloc_object(this, host_object, &mut env.mem);
             autorelease(env, new)
@@ -61,9 +96,22 @@ pub const CLASSES: ClassExports = objc_classes! {
     }
 }
 
+- (id)initWithFileDescriptor:(posix_io::FileDescriptor)fd {
+    msg![env; this initWithFileDescriptor:fd closeOnDealloc:false]
+}
+
+- (id)initWithFileDescriptor:(posix_io::FileDescriptor)fd
+              closeOnDealloc:(bool)close_on_dealloc {
+    *env.objc.borrow_mut(this) = NSFileHandleHostObject {
+        fd,
+        close_on_dealloc,
+    };
+    this
+}
+
 - (i64)offsetInFile {
     let &NSFileHandleHostObject {
-        fd
+        fd, ..
     } = env.objc.borrow(this);
     match posix_io::lseek(env, fd, 0, posix_io::SEEK_CUR) {
         -1 => panic!("offsetInFile failed"),
@@ -74,7 +122,7 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 - (())seekToFileOffset:(i64)offset {
     let &NSFileHandleHostObject {
-        fd
+        fd, ..
     } = env.objc.borrow(this);
     match posix_io::lseek(env, fd, offset, posix_io::SEEK_SET) {
         -1 => panic!("seekToFileOffset: failed"),
@@ -84,7 +132,7 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 - (i64)seekToEndOfFile {
     let &NSFileHandleHostObject {
-        fd
+        fd, ..
     } = env.objc.borrow(this);
     match posix_io::lseek(env, fd, 0, posix_io::SEEK_END) {
         -1 => panic!("seekToFileOffset: failed"),
@@ -94,7 +142,7 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 - (id)readDataOfLength:(NSUInteger)length { // NSData*
     let &NSFileHandleHostObject {
-        fd
+        fd, ..
     } = env.objc.borrow(this);
     let buffer = env.mem.alloc(length);
     match posix_io::read(env, fd, buffer, length) {
@@ -108,7 +156,7 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 - (())writeData:(id)data { // NSData *
     let &NSFileHandleHostObject {
-        fd
+        fd, ..
     } = env.objc.borrow(this);
     let bytes: ConstVoidPtr = msg![env; data bytes];
     let length: NSUInteger = msg![env; data length];
@@ -118,16 +166,40 @@ pub const CLASSES: ClassExports = objc_classes! {
     }
 }
 
+- (id)readDataToEndOfFile { // NSData*
+    let &NSFileHandleHostObject {
+        fd, ..
+    } = env.objc.borrow(this);
+    read_until_end(env, fd)
+}
+
+- (id)availableData { // NSData*
+    let &NSFileHandleHostObject {
+        fd, ..
+    } = env.objc.borrow(this);
+    read_until_end(env, fd)
+}
+
+- (posix_io::FileDescriptor)fileDescriptor {
+    env.objc.borrow::<NSFileHandleHostObject>(this).fd
+}
+
 - (())closeFile {
-    // file is closed on dealloc
-    // TODO: keep closed state and raise an exception if handle is used after the closing
+    // TODO: raise an exception if handle is used after the closing
+    let host_object = env.objc.borrow_mut::<NSFileHandleHostObject>(this);
+    let fd = std::mem::replace(&mut host_object.fd, -1);
+    if fd != -1 {
+        posix_io::close(env, fd);
+    }
 }
 
 - (())dealloc {
     let &NSFileHandleHostObject {
-        fd
+        fd, close_on_dealloc
     } = env.objc.borrow(this);
-    posix_io::close(env, fd);
+    if close_on_dealloc && fd != -1 {
+        posix_io::close(env, fd);
+    }
     env.objc.dealloc_object(this, &mut env.mem)
 }
 
@@ -5,61 +5,48 @@
  */
 //! `NSFileManager` etc.
 
-use super::{ns_array, ns_string, NSUInteger};
-use crate::dyld::{export_c_func, FunctionExports};
-use crate::fs::{GuestPath, GuestPathBuf};
-use crate::mem::MutPtr;
+use super::ns_dictionary::dict_from_keys_and_objects;
+use super::ns_error::{
+    set_error, NSCocoaErrorDomain, NSFileNoSuchFileError, NSFileReadNoSuchFileError,
+    NSFileWriteFileExistsError, NSFileWriteNoPermissionError,
+};
+use super::ns_string::get_static_str;
+use super::{ns_array, ns_date, ns_string, NSInteger, NSUInteger};
+use crate::dyld::{ConstantExports, HostConstant};
+use crate::fs::{resolve_guest_path, GuestPath, GuestPathBuf, PathIntent, ResolveError};
+use crate::mem::{MutPtr, Ptr};
 use crate::objc::{
     autorelease, id, msg, msg_class, nil, objc_classes, release, ClassExports, HostObject,
 };
 use crate::Environment;
 
-type NSSearchPathDirectory = NSUInteger;
-const NSApplicationDirectory: NSSearchPathDirectory = 1;
-const NSDocumentDirectory: NSSearchPathDirectory = 9;
-
-type NSSearchPathDomainMask = NSUInteger;
-const NSUserDomainMask: NSSearchPathDomainMask = 1;
-
-fn NSSearchPathForDirectoriesInDomains(
-    env: &mut Environment,
-    directory: NSSearchPathDirectory,
-    domain_mask: NSSearchPathDomainMask,
-    expand_tilde: bool,
-) -> id {
-    // TODO: other cases not implemented
-    assert!(domain_mask == NSUserDomainMask);
-    assert!(expand_tilde);
-
-    let dir = match directory {
-        // This might not actually be correct. I haven't bothered to test it
-        // because I can't think of a good reason an iPhone OS app would have to
-        // request this; Wolfenstein 3D requests it but never uses it.
-        NSApplicationDirectory => GuestPath::new(crate::fs::APPLICATIONS).to_owned(),
-        NSDocumentDirectory => env.fs.home_directory().join("Documents"),
-        _ => todo!("NSSearchPathDirectory {}", directory),
-    };
-    let dir = ns_string::from_rust_string(env, String::from(dir));
-    let dir_list = ns_array::from_vec(env, vec![dir]);
-    autorelease(env, dir_list)
-}
-
-fn NSHomeDirectory(env: &mut Environment) -> id {
-    let dir = env.fs.home_directory();
-    let dir = ns_string::from_rust_string(env, String::from(dir.as_str()));
-    autorelease(env, dir)
-}
-
-fn NSTemporaryDirectory(env: &mut Environment) -> id {
-    let dir = env.fs.home_directory().join("tmp");
-    let dir = ns_string::from_rust_string(env, String::from(dir.as_str()));
-    autorelease(env, dir)
-}
+// File attribute keys and values
+pub const NSFileType: &str = "NSFileType";
+pub const NSFileTypeDirectory: &str = "NSFileTypeDirectory";
+pub const NSFileTypeRegular: &str = "NSFileTypeRegular";
+pub const NSFileSize: &str = "NSFileSize";
+pub const NSFileModificationDate: &str = "NSFileModificationDate";
+pub const NSFilePosixPermissions: &str = "NSFilePosixPermissions";
 
-pub const FUNCTIONS: FunctionExports = &[
-    export_c_func!(NSHomeDirectory()),
-    export_c_func!(NSTemporaryDirectory()),
-    export_c_func!(NSSearchPathForDirectoriesInDomains(_, _, _)),
+pub const CONSTANTS: ConstantExports = &[
+    ("_NSFileType", HostConstant::NSString(NSFileType)),
+    (
+        "_NSFileTypeDirectory",
+        HostConstant::NSString(NSFileTypeDirectory),
+    ),
+    (
+        "_NSFileTypeRegular",
+        HostConstant::NSString(NSFileTypeRegular),
+    ),
+    ("_NSFileSize", HostConstant::NSString(NSFileSize)),
+    (
+        "_NSFileModificationDate",
+        HostConstant::NSString(NSFileModificationDate),
+    ),
+    (
+        "_NSFilePosixPermissions",
+        HostConstant::NSString(NSFilePosixPermissions),
+    ),
 ];
 
 #[derive(Default)]
@@ -72,6 +59,24 @@ struct NSDirectoryEnumeratorHostObject {
 }
 impl HostObject for NSDirectoryEnumeratorHostObject {}
 
+/// Resolve an `NSString*` path from the app with [resolve_guest_path].
+pub fn resolve_ns_path(
+    env: &mut Environment,
+    path: id,
+    intent: PathIntent,
+) -> Result<GuestPathBuf, ResolveError> {
+    let path = ns_string::to_rust_string(env, path); // TODO: avoid copy
+    resolve_guest_path(env, GuestPath::new(&path), intent)
+}
+
+/// `NSCocoaErrorDomain` code for a directory creation failure.
+fn error_code_for_create(err: ResolveError) -> NSInteger {
+    match err {
+        ResolveError::NotFound => NSFileNoSuchFileError,
+        ResolveError::NotADirectory | ResolveError::ReadOnly => NSFileWriteNoPermissionError,
+    }
+}
+
 pub const CLASSES: ClassExports = objc_classes! {
 
 (env, this, _cmd);
@@ -89,22 +94,21 @@ pub const CLASSES: ClassExports = objc_classes! {
 }
 
 - (bool)fileExistsAtPath:(id)path { // NSString*
-    let path = ns_string::to_rust_string(env, path); // TODO: avoid copy
-    // fileExistsAtPath: will return true for directories, hence Fs::exists()
-    // rather than Fs::is_file() is appropriate.
-    let res = env.fs.exists(GuestPath::new(&path));
-    log_dbg!("fileExistsAtPath:{:?} => {}", path, res);
-    res
+    // fileExistsAtPath: will return true for directories too.
+    let res = resolve_ns_path(env, path, PathIntent::Read);
+    log_dbg!("fileExistsAtPath:{:?} => {:?}", path, res);
+    res.is_ok()
 }
 
 - (bool)fileExistsAtPath:(id)path // NSString*
              isDirectory:(MutPtr<bool>)is_dir {
-    // TODO: mutualize with fileExistsAtPath:
-    let path = ns_string::to_rust_string(env, path); // TODO: avoid copy
-    let guest_path = GuestPath::new(&path);
-    let res_exists = env.fs.exists(guest_path);
+    let Ok(guest_path) = resolve_ns_path(env, path, PathIntent::Read) else {
+        log_dbg!("fileExistsAtPath:{:?} isDirectory:{:?} => false", path, is_dir);
+        return false;
+    };
+    let res_exists = true;
     if !is_dir.is_null() {
-        let res_is_dir = !env.fs.is_file(guest_path);
+        let res_is_dir = env.fs.is_dir(&guest_path);
         env.mem.write(is_dir, res_is_dir);
         log_dbg!("fileExistsAtPath:{:?} isDirectory:{:?} => {}", path, res_is_dir, res_exists);
     } else {
@@ -118,11 +122,12 @@ pub const CLASSES: ClassExports = objc_classes! {
               attributes:(id)attributes { // NSDictionary*
     assert!(attributes == nil); // TODO
 
-    let path_str = ns_string::to_rust_string(env, path); // TODO: avoid copy
     // createFileAtPath: returns true if there's already a file at a given path.
     // If there's a directory, that's an error, though.
-    if env.fs.is_file(GuestPath::new(&path_str)) {
-        return true;
+    if let Ok(existing) = resolve_ns_path(env, path, PathIntent::Read) {
+        if env.fs.is_file(&existing) {
+            return true;
+        }
     }
 
     if data == nil {
@@ -135,10 +140,56 @@ pub const CLASSES: ClassExports = objc_classes! {
     }
 }
 
+- (bool)createDirectoryAtPath:(id)path // NSString*
+  withIntermediateDirectories:(bool)intermediates
+                   attributes:(id)attributes // NSDictionary*
+                        error:(MutPtr<id>)error { // NSError**
+    if attributes != nil {
+        log!("TODO: ignoring attributes for createDirectoryAtPath:{:?}", path);
+    }
+    let res = if intermediates {
+        let path = ns_string::to_rust_string(env, path); // TODO: avoid copy
+        env.fs
+            .create_dir_all(GuestPath::new(&path))
+            .map_err(error_code_for_create)
+    } else {
+        resolve_ns_path(env, path, PathIntent::Write)
+            .map_err(error_code_for_create)
+            .and_then(|path| {
+                env.fs
+                    .create_dir(path)
+                    .map_err(|()| NSFileWriteFileExistsError)
+            })
+    };
+    log_dbg!(
+        "createDirectoryAtPath:{:?} withIntermediateDirectories:{} => {:?}",
+        path,
+        intermediates,
+        res
+    );
+    match res {
+        Ok(()) => true,
+        Err(code) => {
+            set_error(env, error, NSCocoaErrorDomain, code);
+            false
+        }
+    }
+}
+- (bool)createDirectoryAtPath:(id)path // NSString*
+                   attributes:(id)attributes { // NSDictionary*
+    let error: MutPtr<id> = Ptr::null();
+    msg![env; this createDirectoryAtPath:path
+             withIntermediateDirectories:false
+                              attributes:attributes
+                                   error:error]
+}
+
 - (bool)removeItemAtPath:(id)path // NSString*
                    error:(MutPtr<id>)error { // NSError**
-    let path = ns_string::to_rust_string(env, path); // TODO: avoid copy
-    match env.fs.remove(GuestPath::new(&path)) {
+    let res = resolve_ns_path(env, path, PathIntent::Write)
+        .map_err(|_| ())
+        .and_then(|path| env.fs.remove(path));
+    match res {
         Ok(()) => true,
         Err(()) => {
             if !error.is_null() {
@@ -150,8 +201,10 @@ pub const CLASSES: ClassExports = objc_classes! {
 }
 
 - (id)enumeratorAtPath:(id)path { // NSString*
-    let path = ns_string::to_rust_string(env, path); // TODO: avoid copy
-    let Ok(paths) = env.fs.enumerate_recursive(GuestPath::new(&path)) else {
+    let Ok(path) = resolve_ns_path(env, path, PathIntent::Read) else {
+        return nil;
+    };
+    let Ok(paths) = env.fs.enumerate_recursive(path) else {
         return nil;
     };
     let host_object = Box::new(NSDirectoryEnumeratorHostObject {
@@ -171,14 +224,16 @@ pub const CLASSES: ClassExports = objc_classes! {
 }
 
 - (id)directoryContentsAtPath:(id)path /* NSString* */ { // NSArray*
-    let path = ns_string::to_rust_string(env, path); // TODO: avoid copy
-    let Ok(paths) = env.fs.enumerate(GuestPath::new(&path)) else {
+    let Ok(path) = resolve_ns_path(env, path, PathIntent::Read) else {
+        return nil;
+    };
+    let Ok(paths) = env.fs.enumerate(&path) else {
         return nil;
     };
     let paths: Vec<GuestPathBuf> = paths
         .map(|path| GuestPathBuf::from(GuestPath::new(path)))
         .collect();
-    log_dbg!("directoryContentsAtPath {}: {:?}", path, paths);
+    log_dbg!("directoryContentsAtPath {:?}: {:?}", path, paths);
     let path_strings = paths
         .iter()
         .map(|name| ns_string::from_rust_string(env, name.as_str().to_string()))
@@ -199,16 +254,106 @@ pub const CLASSES: ClassExports = objc_classes! {
 - (bool)copyItemAtPath:(id)src // NSString*
                 toPath:(id)dst // NSString*
                  error:(MutPtr<id>)_error { // NSError**
-    let src = ns_string::to_rust_string(env, src);
-    let dst = ns_string::to_rust_string(env, dst);
-    let data = match env.fs.read(GuestPath::new(src.as_ref())) {
-        Ok(d) => d,
-        Err(_) => todo!()
+    // TODO: create an NSError if requested
+    let Ok(src) = resolve_ns_path(env, src, PathIntent::Read) else {
+        return false;
+    };
+    let Ok(dst) = resolve_ns_path(env, dst, PathIntent::Write) else {
+        return false;
+    };
+    let Ok(data) = env.fs.read(&src) else {
+        return false;
+    };
+    env.fs.write(&dst, &data).is_ok()
+}
+
+- (id)attributesOfItemAtPath:(id)path // NSString*
+                        error:(MutPtr<id>)error { // NSError**
+    let metadata = resolve_ns_path(env, path, PathIntent::Read)
+        .map_err(|_| ())
+        .and_then(|path| env.fs.metadata(path));
+    let Ok(metadata) = metadata else {
+        set_error(env, error, NSCocoaErrorDomain, NSFileReadNoSuchFileError);
+        return nil;
+    };
+    log_dbg!("attributesOfItemAtPath:{:?} => {:?}", path, metadata);
+
+    let file_type = if metadata.is_dir {
+        NSFileTypeDirectory
+    } else {
+        NSFileTypeRegular
     };
-    if env.fs.write(GuestPath::new(dst.as_ref()), &data).is_err() {
-        todo!();
+    let file_type = get_static_str(env, file_type);
+    let size = metadata.size;
+    let size: id = msg_class![env; NSNumber numberWithUnsignedLongLong:size];
+    let permissions = NSInteger::from(metadata.permissions);
+    let permissions: id = msg_class![env; NSNumber numberWithInteger:permissions];
+    let mut attributes = vec![
+        (get_static_str(env, NSFileType), file_type),
+        (get_static_str(env, NSFileSize), size),
+        (get_static_str(env, NSFilePosixPermissions), permissions),
+    ];
+    if let Some(modified) = metadata.modified {
+        let modified = ns_date::from_system_time(env, modified);
+        attributes.push((get_static_str(env, NSFileModificationDate), modified));
+    }
+    let attributes = dict_from_keys_and_objects(env, &attributes);
+    autorelease(env, attributes)
+}
+
+- (bool)setAttributes:(id)attributes // NSDictionary*
+         ofItemAtPath:(id)path // NSString*
+                error:(MutPtr<id>)error { // NSError**
+    let Ok(guest_path) = resolve_ns_path(env, path, PathIntent::Read) else {
+        set_error(env, error, NSCocoaErrorDomain, NSFileNoSuchFileError);
+        return false;
+    };
+
+    let mut handled: NSUInteger = 0;
+    let mut res = Ok(());
+
+    let key = get_static_str(env, NSFilePosixPermissions);
+    let permissions: id = msg![env; attributes objectForKey:key];
+    if permissions != nil {
+        handled += 1;
+        let permissions: i32 = msg![env; permissions intValue];
+        res = res.and(env.fs.set_permissions(&guest_path, permissions as u16));
+    }
+
+    let key = get_static_str(env, NSFileModificationDate);
+    let modified: id = msg![env; attributes objectForKey:key];
+    if modified != nil {
+        handled += 1;
+        let modified = ns_date::to_system_time(env, modified);
+        // Only the modification time is meant to change, but both have to be
+        // set at once.
+        let accessed = env
+            .fs
+            .metadata(&guest_path)
+            .ok()
+            .and_then(|metadata| metadata.accessed)
+            .unwrap_or(modified);
+        res = res.and(env.fs.set_times(&guest_path, accessed, modified));
+    }
+
+    let count: NSUInteger = msg![env; attributes count];
+    if count != handled {
+        log!(
+            "TODO: setAttributes:{:?} ofItemAtPath:{:?} error:{:?} ignored {} unsupported attribute(s)",
+            attributes,
+            path,
+            error,
+            count - handled,
+        );
+    }
+
+    match res {
+        Ok(()) => true,
+        Err(()) => {
+            set_error(env, error, NSCocoaErrorDomain, NSFileWriteNoPermissionError);
+            false
+        }
     }
-    true
 }
 
 @end
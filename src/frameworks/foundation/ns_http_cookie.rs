@@ -0,0 +1,718 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `NSHTTPCookie`.
+//!
+//! Cookies are parsed and matched following [RFC 6265](https://www.rfc-editor.org/rfc/rfc6265),
+//! the same way as `NSHTTPCookie`: a cookie set with a `Domain` attribute has
+//! its domain stored with a leading `.` and is sent to subdomains too, while
+//! one without is only sent to the host that set it.
+
+use super::ns_array;
+use super::ns_date;
+use super::ns_dictionary::dict_from_keys_and_objects;
+use super::ns_string::{from_rust_string, get_static_str, to_rust_string};
+use super::ns_url_request::header_fields_from_dict;
+use crate::dyld::{ConstantExports, HostConstant};
+use crate::libc::time::current_time;
+use crate::objc::{
+    autorelease, id, msg, nil, objc_classes, release, ClassExports, HostObject, NSZonePtr,
+};
+use crate::Environment;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Keys of the properties dictionary. Only the ones used here are listed, but
+// the values are the same as the strings used in Set-Cookie headers.
+pub const NSHTTPCookieName: &str = "Name";
+pub const NSHTTPCookieValue: &str = "Value";
+pub const NSHTTPCookieDomain: &str = "Domain";
+pub const NSHTTPCookiePath: &str = "Path";
+pub const NSHTTPCookieExpires: &str = "Expires";
+pub const NSHTTPCookieMaximumAge: &str = "Max-Age";
+pub const NSHTTPCookieSecure: &str = "Secure";
+pub const NSHTTPCookieDiscard: &str = "Discard";
+pub const NSHTTPCookieOriginURL: &str = "OriginURL";
+pub const NSHTTPCookieVersion: &str = "Version";
+/// Not a public constant, but `properties` includes it.
+const HTTP_ONLY: &str = "HttpOnly";
+
+pub const CONSTANTS: ConstantExports = &[
+    (
+        "_NSHTTPCookieName",
+        HostConstant::NSString(NSHTTPCookieName),
+    ),
+    (
+        "_NSHTTPCookieValue",
+        HostConstant::NSString(NSHTTPCookieValue),
+    ),
+    (
+        "_NSHTTPCookieDomain",
+        HostConstant::NSString(NSHTTPCookieDomain),
+    ),
+    (
+        "_NSHTTPCookiePath",
+        HostConstant::NSString(NSHTTPCookiePath),
+    ),
+    (
+        "_NSHTTPCookieExpires",
+        HostConstant::NSString(NSHTTPCookieExpires),
+    ),
+    (
+        "_NSHTTPCookieMaximumAge",
+        HostConstant::NSString(NSHTTPCookieMaximumAge),
+    ),
+    (
+        "_NSHTTPCookieSecure",
+        HostConstant::NSString(NSHTTPCookieSecure),
+    ),
+    (
+        "_NSHTTPCookieDiscard",
+        HostConstant::NSString(NSHTTPCookieDiscard),
+    ),
+    (
+        "_NSHTTPCookieOriginURL",
+        HostConstant::NSString(NSHTTPCookieOriginURL),
+    ),
+    (
+        "_NSHTTPCookieVersion",
+        HostConstant::NSString(NSHTTPCookieVersion),
+    ),
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    /// Lowercase. Starts with `.` if subdomains should match too.
+    pub domain: String,
+    pub path: String,
+    /// [None] for session cookies.
+    pub expires: Option<SystemTime>,
+    pub secure: bool,
+    pub http_only: bool,
+}
+
+/// The parts of a URL that matter for cookies.
+#[derive(Debug, PartialEq)]
+pub struct CookieURL {
+    pub secure: bool,
+    /// Lowercase.
+    pub host: String,
+    pub path: String,
+}
+impl CookieURL {
+    /// Split an absolute URL string. Returns [None] if it has no host.
+    pub fn parse(url: &str) -> Option<CookieURL> {
+        let (scheme, rest) = url.split_once("://")?;
+        let authority_end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+        let (authority, rest) = rest.split_at(authority_end);
+        let host_and_port = authority.rsplit_once('@').map_or(authority, |(_, h)| h);
+        let host = host_and_port.split(':').next().unwrap();
+        if host.is_empty() {
+            return None;
+        }
+        let path = &rest[..rest.find(['?', '#']).unwrap_or(rest.len())];
+        Some(CookieURL {
+            secure: scheme.eq_ignore_ascii_case("https"),
+            host: host.to_ascii_lowercase(),
+            path: if path.is_empty() { "/" } else { path }.to_string(),
+        })
+    }
+
+    /// The path a cookie gets if it doesn't have a `Path` attribute.
+    fn default_cookie_path(&self) -> &str {
+        match self.path.rfind('/') {
+            Some(0) | None => "/",
+            Some(idx) => &self.path[..idx],
+        }
+    }
+}
+
+impl Cookie {
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    pub fn domain_matches(&self, host: &str) -> bool {
+        match self.domain.strip_prefix('.') {
+            Some(domain) => {
+                host == domain
+                    || host
+                        .strip_suffix(domain)
+                        .is_some_and(|prefix| prefix.ends_with('.'))
+            }
+            None => host == self.domain,
+        }
+    }
+
+    pub fn path_matches(&self, path: &str) -> bool {
+        path == self.path
+            || (path.starts_with(&self.path)
+                && (self.path.ends_with('/') || path[self.path.len()..].starts_with('/')))
+    }
+
+    /// Whether this cookie should be sent with a request for `url`.
+    pub fn matches(&self, url: &CookieURL, now: SystemTime) -> bool {
+        (url.secure || !self.secure)
+            && self.domain_matches(&url.host)
+            && self.path_matches(&url.path)
+            && !self.is_expired(now)
+    }
+
+    /// Whether `other` would replace this cookie if stored.
+    pub fn same_identity(&self, other: &Cookie) -> bool {
+        self.name == other.name && self.domain == other.domain && self.path == other.path
+    }
+
+    /// Parse one cookie from a `Set-Cookie` header value. Returns [None] if
+    /// it's malformed or `url` isn't allowed to set it.
+    pub fn parse_set_cookie(header: &str, url: &CookieURL, now: SystemTime) -> Option<Cookie> {
+        let mut parts = header.split(';');
+        let (name, value) = parts.next()?.split_once('=')?;
+        let name = name.trim();
+        if name.is_empty() {
+            return None;
+        }
+        let mut cookie = Cookie {
+            name: name.to_string(),
+            value: value.trim().to_string(),
+            domain: url.host.clone(),
+            path: url.default_cookie_path().to_string(),
+            expires: None,
+            secure: false,
+            http_only: false,
+        };
+
+        let mut max_age = None;
+        for attribute in parts {
+            let (key, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+            let (key, value) = (key.trim(), value.trim());
+            if key.eq_ignore_ascii_case("Expires") {
+                if let Some(expires) = parse_cookie_date(value) {
+                    cookie.expires = Some(expires);
+                }
+            } else if key.eq_ignore_ascii_case("Max-Age") {
+                if let Ok(seconds) = value.parse::<i64>() {
+                    max_age = Some(seconds);
+                }
+            } else if key.eq_ignore_ascii_case("Domain") {
+                let domain = value.trim_start_matches('.').to_ascii_lowercase();
+                if !domain.is_empty() {
+                    cookie.domain = format!(".{}", domain);
+                }
+            } else if key.eq_ignore_ascii_case("Path") {
+                if value.starts_with('/') {
+                    cookie.path = value.to_string();
+                }
+            } else if key.eq_ignore_ascii_case("Secure") {
+                cookie.secure = true;
+            } else if key.eq_ignore_ascii_case("HttpOnly") {
+                cookie.http_only = true;
+            }
+        }
+        // Max-Age takes precedence over Expires. Zero or less means the cookie
+        // has already expired.
+        if let Some(seconds) = max_age {
+            cookie.expires = Some(match u64::try_from(seconds) {
+                Ok(seconds) if seconds > 0 => now + Duration::from_secs(seconds),
+                _ => UNIX_EPOCH,
+            });
+        }
+
+        // A host can't set cookies for a domain it's not part of.
+        if !cookie.domain_matches(&url.host) {
+            return None;
+        }
+        Some(cookie)
+    }
+
+    /// Parse all the cookies in a `Set-Cookie` header value. When a response
+    /// has several `Set-Cookie` headers, they get joined with commas, which
+    /// can also appear in `Expires` dates. A comma only starts a new cookie if
+    /// what follows looks like `name=value`.
+    pub fn parse_set_cookie_list(header: &str, url: &CookieURL, now: SystemTime) -> Vec<Cookie> {
+        let mut headers: Vec<String> = Vec::new();
+        for segment in header.split(',') {
+            let first_part = segment.split(';').next().unwrap();
+            match headers.last_mut() {
+                Some(last) if !first_part.contains('=') => {
+                    last.push(',');
+                    last.push_str(segment);
+                }
+                _ => headers.push(segment.to_string()),
+            }
+        }
+        headers
+            .iter()
+            .filter_map(|header| Cookie::parse_set_cookie(header, url, now))
+            .collect()
+    }
+
+    /// The value of a `Cookie` request header for these cookies.
+    pub fn request_header(cookies: &[Cookie]) -> String {
+        cookies
+            .iter()
+            .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+
+    /// Serialize for storage in a plist. The keys are the same as in the
+    /// `properties` dictionary.
+    pub fn to_plist(&self) -> plist::Dictionary {
+        let mut dict = plist::Dictionary::new();
+        dict.insert(NSHTTPCookieName.into(), self.name.clone().into());
+        dict.insert(NSHTTPCookieValue.into(), self.value.clone().into());
+        dict.insert(NSHTTPCookieDomain.into(), self.domain.clone().into());
+        dict.insert(NSHTTPCookiePath.into(), self.path.clone().into());
+        if let Some(expires) = self.expires {
+            dict.insert(
+                NSHTTPCookieExpires.into(),
+                plist::Date::from(expires).into(),
+            );
+        }
+        if self.secure {
+            dict.insert(NSHTTPCookieSecure.into(), "TRUE".into());
+        }
+        if self.http_only {
+            dict.insert(HTTP_ONLY.into(), "TRUE".into());
+        }
+        dict
+    }
+
+    pub fn from_plist(dict: &plist::Dictionary) -> Option<Cookie> {
+        let string = |key: &str| dict.get(key).and_then(|value| value.as_string());
+        Some(Cookie {
+            name: string(NSHTTPCookieName)?.to_string(),
+            value: string(NSHTTPCookieValue)?.to_string(),
+            domain: string(NSHTTPCookieDomain)?.to_string(),
+            path: string(NSHTTPCookiePath)?.to_string(),
+            expires: dict
+                .get(NSHTTPCookieExpires)
+                .and_then(|value| value.as_date())
+                .map(SystemTime::from),
+            secure: dict.contains_key(NSHTTPCookieSecure),
+            http_only: dict.contains_key(HTTP_ONLY),
+        })
+    }
+}
+
+/// Parse a date in a cookie's `Expires` attribute, using the lenient algorithm
+/// from RFC 6265 section 5.1.1, which accepts the various formats of HTTP
+/// dates, e.g. `Wed, 21 Oct 2015 07:28:00 GMT` or `Wed, 21-Oct-15 07:28:00 GMT`.
+pub fn parse_cookie_date(date: &str) -> Option<SystemTime> {
+    const MONTHS: [&str; 12] = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ];
+    let mut time = None;
+    let mut day = None;
+    let mut month = None;
+    let mut year = None;
+    for token in date
+        .split(|c: char| !(c.is_ascii_alphanumeric() || c == ':'))
+        .filter(|token| !token.is_empty())
+    {
+        let is_number = token.bytes().all(|c| c.is_ascii_digit());
+        if time.is_none() && token.contains(':') {
+            let mut fields = token.split(':').map(|field| field.parse::<u64>().ok());
+            match (fields.next(), fields.next(), fields.next(), fields.next()) {
+                (Some(Some(h)), Some(Some(m)), Some(Some(s)), None) => time = Some((h, m, s)),
+                _ => return None,
+            }
+        } else if day.is_none() && is_number && token.len() <= 2 {
+            day = token.parse::<u64>().ok();
+        } else if month.is_none() && token.len() >= 3 && !is_number {
+            let prefix = token[..3].to_ascii_lowercase();
+            month = MONTHS.iter().position(|&name| name == prefix);
+        } else if year.is_none() && is_number && (token.len() == 2 || token.len() == 4) {
+            year = token.parse::<i64>().ok();
+        }
+    }
+    let ((hour, minute, second), day, month, year) = (time?, day?, month?, year?);
+    let year = match year {
+        0..=69 => year + 2000,
+        70..=99 => year + 1900,
+        _ => year,
+    };
+    if !(1..=31).contains(&day) || year < 1970 || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+
+    // Days since 1970-01-01, from Howard Hinnant's days_from_civil algorithm.
+    let (y, m) = if month < 2 {
+        (year - 1, month as i64 + 10)
+    } else {
+        (year, month as i64 - 2)
+    };
+    let era = y.div_euclid(400);
+    let year_of_era = y - era * 400;
+    let day_of_year = (153 * m + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = u64::try_from(era * 146097 + day_of_era - 719468).ok()?;
+
+    let seconds = days * 86400 + hour * 3600 + minute * 60 + second;
+    Some(UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
+struct NSHTTPCookieHostObject {
+    cookie: Option<Cookie>,
+}
+impl HostObject for NSHTTPCookieHostObject {}
+
+/// Shortcut for host code: the URL an `NSURL*` is for, split up for matching
+/// cookies against.
+pub fn cookie_url(env: &mut Environment, url: id) -> Option<CookieURL> {
+    if url == nil {
+        return None;
+    }
+    let string: id = msg![env; url absoluteString];
+    CookieURL::parse(&to_rust_string(env, string))
+}
+
+/// Shortcut for host code: create an `NSHTTPCookie*` (+1 reference).
+pub fn from_cookie(env: &mut Environment, cookie: Cookie) -> id {
+    let class = env.objc.get_known_class("NSHTTPCookie", &mut env.mem);
+    let host_object = Box::new(NSHTTPCookieHostObject {
+        cookie: Some(cookie),
+    });
+    env.objc.alloc_object(class, host_object, &mut env.mem)
+}
+
+/// Shortcut for host code: get the cookie an `NSHTTPCookie*` represents.
+pub fn to_cookie(env: &mut Environment, cookie: id) -> Cookie {
+    let host_obj = env.objc.borrow::<NSHTTPCookieHostObject>(cookie);
+    host_obj.cookie.clone().unwrap()
+}
+
+/// Get the string value of a key in a properties dictionary.
+fn string_property(env: &mut Environment, properties: id, key: &'static str) -> Option<String> {
+    let key = get_static_str(env, key);
+    let value: id = msg![env; properties objectForKey:key];
+    (value != nil).then(|| to_rust_string(env, value).into_owned())
+}
+
+/// Make a cookie from a properties dictionary, like `initWithProperties:`.
+fn cookie_from_properties(env: &mut Environment, properties: id) -> Option<Cookie> {
+    let name = string_property(env, properties, NSHTTPCookieName)?;
+    let value = string_property(env, properties, NSHTTPCookieValue)?;
+    let path = string_property(env, properties, NSHTTPCookiePath)?;
+
+    // Without a domain, the cookie is only for the host of the origin URL.
+    let domain = match string_property(env, properties, NSHTTPCookieDomain) {
+        Some(domain) => domain.to_ascii_lowercase(),
+        None => {
+            let origin_key = get_static_str(env, NSHTTPCookieOriginURL);
+            let origin: id = msg![env; properties objectForKey:origin_key];
+            if origin == nil {
+                return None;
+            }
+            let string_class = env.objc.get_known_class("NSString", &mut env.mem);
+            let origin = if msg![env; origin isKindOfClass:string_class] {
+                to_rust_string(env, origin).into_owned()
+            } else {
+                let string: id = msg![env; origin absoluteString];
+                to_rust_string(env, string).into_owned()
+            };
+            CookieURL::parse(&origin)?.host
+        }
+    };
+
+    let discard = string_property(env, properties, NSHTTPCookieDiscard)
+        .is_some_and(|discard| discard.eq_ignore_ascii_case("TRUE"));
+    let max_age = string_property(env, properties, NSHTTPCookieMaximumAge)
+        .and_then(|max_age| max_age.trim().parse::<u64>().ok());
+    let expires_key = get_static_str(env, NSHTTPCookieExpires);
+    let expires: id = msg![env; properties objectForKey:expires_key];
+    let expires = if discard {
+        None
+    } else if let Some(max_age) = max_age {
+        Some(current_time(env) + Duration::from_secs(max_age))
+    } else if expires == nil {
+        None
+    } else {
+        let date_class = env.objc.get_known_class("NSDate", &mut env.mem);
+        if msg![env; expires isKindOfClass:date_class] {
+            Some(ns_date::to_system_time(env, expires))
+        } else {
+            parse_cookie_date(&to_rust_string(env, expires))
+        }
+    };
+
+    let secure = string_property(env, properties, NSHTTPCookieSecure)
+        .is_some_and(|secure| !secure.eq_ignore_ascii_case("FALSE"));
+    let http_only = string_property(env, properties, HTTP_ONLY).is_some();
+
+    Some(Cookie {
+        name,
+        value,
+        domain,
+        path,
+        expires,
+        secure,
+        http_only,
+    })
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation NSHTTPCookie: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(NSHTTPCookieHostObject { cookie: None });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
++ (id)cookieWithProperties:(id)properties { // NSDictionary*
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initWithProperties:properties];
+    autorelease(env, new)
+}
+
++ (id)cookiesWithResponseHeaderFields:(id)fields // NSDictionary*
+                               forURL:(id)url { // NSURL*
+    let fields = header_fields_from_dict(env, fields);
+    let now = current_time(env);
+    let cookies = match cookie_url(env, url) {
+        Some(url) => fields
+            .iter()
+            .filter(|(name, _)| name.eq_ignore_ascii_case("Set-Cookie"))
+            .flat_map(|(_, value)| Cookie::parse_set_cookie_list(value, &url, now))
+            .collect(),
+        None => Vec::new(),
+    };
+    let cookies = cookies
+        .into_iter()
+        .map(|cookie| from_cookie(env, cookie))
+        .collect();
+    let array = ns_array::from_vec(env, cookies);
+    autorelease(env, array)
+}
+
++ (id)requestHeaderFieldsWithCookies:(id)cookies { // NSArray*
+    let count: u32 = msg![env; cookies count];
+    let cookies: Vec<Cookie> = (0..count)
+        .map(|i| {
+            let cookie: id = msg![env; cookies objectAtIndex:i];
+            to_cookie(env, cookie)
+        })
+        .collect();
+    let pairs = if cookies.is_empty() {
+        Vec::new()
+    } else {
+        let name = get_static_str(env, "Cookie");
+        let value = from_rust_string(env, Cookie::request_header(&cookies));
+        vec![(name, value)]
+    };
+    let dict = dict_from_keys_and_objects(env, &pairs);
+    for (_, value) in pairs {
+        release(env, value);
+    }
+    autorelease(env, dict)
+}
+
+- (id)initWithProperties:(id)properties { // NSDictionary*
+    match cookie_from_properties(env, properties) {
+        Some(cookie) => {
+            env.objc.borrow_mut::<NSHTTPCookieHostObject>(this).cookie = Some(cookie);
+            this
+        }
+        None => {
+            release(env, this);
+            nil
+        }
+    }
+}
+
+// NSCopying implementation
+- (id)copyWithZone:(NSZonePtr)_zone {
+    // Cookies are immutable.
+    msg![env; this retain]
+}
+
+- (id)name {
+    let name = to_cookie(env, this).name;
+    let name = from_rust_string(env, name);
+    autorelease(env, name)
+}
+- (id)value {
+    let value = to_cookie(env, this).value;
+    let value = from_rust_string(env, value);
+    autorelease(env, value)
+}
+- (id)domain {
+    let domain = to_cookie(env, this).domain;
+    let domain = from_rust_string(env, domain);
+    autorelease(env, domain)
+}
+- (id)path {
+    let path = to_cookie(env, this).path;
+    let path = from_rust_string(env, path);
+    autorelease(env, path)
+}
+- (id)expiresDate {
+    match to_cookie(env, this).expires {
+        Some(expires) => ns_date::from_system_time(env, expires),
+        None => nil,
+    }
+}
+- (bool)isSessionOnly {
+    to_cookie(env, this).expires.is_none()
+}
+- (bool)isSecure {
+    to_cookie(env, this).secure
+}
+- (bool)isHTTPOnly {
+    to_cookie(env, this).http_only
+}
+
+- (id)properties {
+    let cookie = to_cookie(env, this);
+    let mut pairs = vec![
+        (NSHTTPCookieName, from_rust_string(env, cookie.name)),
+        (NSHTTPCookieValue, from_rust_string(env, cookie.value)),
+        (NSHTTPCookieDomain, from_rust_string(env, cookie.domain)),
+        (NSHTTPCookiePath, from_rust_string(env, cookie.path)),
+    ];
+    if let Some(expires) = cookie.expires {
+        let expires = ns_date::from_system_time(env, expires);
+        pairs.push((NSHTTPCookieExpires, msg![env; expires retain]));
+    }
+    if cookie.secure {
+        pairs.push((NSHTTPCookieSecure, from_rust_string(env, "TRUE".into())));
+    }
+    if cookie.http_only {
+        pairs.push((HTTP_ONLY, from_rust_string(env, "TRUE".into())));
+    }
+    let pairs: Vec<(id, id)> = pairs
+        .into_iter()
+        .map(|(key, value)| (get_static_str(env, key), value))
+        .collect();
+    let dict = dict_from_keys_and_objects(env, &pairs);
+    for (_, value) in pairs {
+        release(env, value);
+    }
+    autorelease(env, dict)
+}
+
+- (id)description {
+    let cookie = to_cookie(env, this);
+    let description = format!(
+        "<NSHTTPCookie {}={}; domain={}; path={}>",
+        cookie.name, cookie.value, cookie.domain, cookie.path
+    );
+    let description = from_rust_string(env, description);
+    autorelease(env, description)
+}
+
+@end
+
+};
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_cookie_date, Cookie, CookieURL};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn url(url: &str) -> CookieURL {
+        CookieURL::parse(url).unwrap()
+    }
+
+    #[test]
+    fn test_parse_url() {
+        let parsed = url("https://user@Example.com:8080/a/b?q=1#frag");
+        assert!(parsed.secure);
+        assert_eq!(parsed.host, "example.com");
+        assert_eq!(parsed.path, "/a/b");
+        assert_eq!(url("http://example.com").path, "/");
+        assert!(CookieURL::parse("file:///tmp/a").is_none());
+    }
+
+    #[test]
+    fn test_parse_date() {
+        // 2015-10-21 07:28:00 UTC
+        let expected = Some(UNIX_EPOCH + Duration::from_secs(1445412480));
+        assert_eq!(parse_cookie_date("Wed, 21 Oct 2015 07:28:00 GMT"), expected);
+        assert_eq!(parse_cookie_date("Wed, 21-Oct-15 07:28:00 GMT"), expected);
+        assert_eq!(
+            parse_cookie_date("Wednesday, 21-Oct-2015 07:28:00 GMT"),
+            expected
+        );
+        assert_eq!(
+            parse_cookie_date("Thu, 01 Jan 1970 00:00:00 GMT"),
+            Some(UNIX_EPOCH)
+        );
+        assert_eq!(parse_cookie_date("not a date"), None);
+        assert_eq!(parse_cookie_date("Wed, 32 Oct 2015 07:28:00 GMT"), None);
+    }
+
+    #[test]
+    fn test_parse_set_cookie() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        let cookies = Cookie::parse_set_cookie_list(
+            "a=1; Path=/; Expires=Wed, 21 Oct 2015 07:28:00 GMT, b=2; Domain=.Example.com; \
+             Max-Age=60; Secure; HttpOnly, c=3",
+            &url("http://www.example.com/dir/page"),
+            now,
+        );
+        assert_eq!(cookies.len(), 3);
+
+        assert_eq!(cookies[0].name, "a");
+        assert_eq!(cookies[0].domain, "www.example.com");
+        assert_eq!(cookies[0].path, "/");
+        assert_eq!(
+            cookies[0].expires,
+            Some(UNIX_EPOCH + Duration::from_secs(1445412480))
+        );
+
+        assert_eq!(cookies[1].value, "2");
+        assert_eq!(cookies[1].domain, ".example.com");
+        assert_eq!(cookies[1].expires, Some(now + Duration::from_secs(60)));
+        assert!(cookies[1].secure && cookies[1].http_only);
+
+        // The default path is the directory of the request's path.
+        assert_eq!(cookies[2].path, "/dir");
+        assert_eq!(cookies[2].expires, None);
+
+        // Hosts can't set cookies for other domains.
+        let rejected =
+            Cookie::parse_set_cookie("d=4; Domain=other.com", &url("http://example.com/"), now);
+        assert_eq!(rejected, None);
+    }
+
+    #[test]
+    fn test_matching() {
+        let now = UNIX_EPOCH;
+        let cookie = Cookie::parse_set_cookie(
+            "a=1; Domain=example.com; Path=/docs",
+            &url("http://example.com/"),
+            now,
+        )
+        .unwrap();
+        assert!(cookie.matches(&url("http://example.com/docs"), now));
+        assert!(cookie.matches(&url("http://sub.example.com/docs/a"), now));
+        assert!(!cookie.matches(&url("http://badexample.com/docs"), now));
+        assert!(!cookie.matches(&url("http://example.com/docsets"), now));
+        assert!(!cookie.matches(&url("http://example.com/"), now));
+
+        let host_only =
+            Cookie::parse_set_cookie("b=2; Secure", &url("https://example.com/"), now).unwrap();
+        assert!(host_only.matches(&url("https://example.com/"), now));
+        assert!(!host_only.matches(&url("http://example.com/"), now));
+        assert!(!host_only.matches(&url("https://sub.example.com/"), now));
+    }
+
+    #[test]
+    fn test_plist_round_trip() {
+        let cookie = Cookie::parse_set_cookie(
+            "a=1; Domain=example.com; Max-Age=3600; Secure",
+            &url("https://example.com/"),
+            UNIX_EPOCH + Duration::from_secs(1_000_000_000),
+        )
+        .unwrap();
+        assert_eq!(Cookie::from_plist(&cookie.to_plist()), Some(cookie));
+    }
+}
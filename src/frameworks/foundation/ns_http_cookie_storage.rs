@@ -0,0 +1,248 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `NSHTTPCookieStorage`.
+//!
+//! The cookies are saved to `Library/Cookies/Cookies.plist` in the app's
+//! sandbox after every change, and loaded from there the first time the
+//! shared storage is used. Unlike on iOS, session cookies are saved too, so
+//! that logins etc survive touchHLE being restarted.
+
+use super::ns_array;
+use super::ns_http_cookie::{cookie_url, from_cookie, to_cookie, Cookie, CookieURL};
+use super::NSUInteger;
+use crate::fs::GuestPathBuf;
+use crate::libc::time::current_time;
+use crate::objc::{
+    autorelease, id, msg, msg_class, objc_classes, ClassExports, HostObject, NSZonePtr,
+};
+use crate::Environment;
+use std::io::Cursor;
+use std::time::SystemTime;
+
+pub type NSHTTPCookieAcceptPolicy = NSUInteger;
+pub const NSHTTPCookieAcceptPolicyAlways: NSHTTPCookieAcceptPolicy = 0;
+pub const NSHTTPCookieAcceptPolicyNever: NSHTTPCookieAcceptPolicy = 1;
+pub const NSHTTPCookieAcceptPolicyOnlyFromMainDocumentDomain: NSHTTPCookieAcceptPolicy = 2;
+
+#[derive(Default)]
+pub struct State {
+    /// `NSHTTPCookieStorage*`
+    shared_storage: Option<id>,
+}
+impl State {
+    fn get(env: &mut Environment) -> &mut Self {
+        &mut env.framework_state.foundation.ns_http_cookie_storage
+    }
+}
+
+struct NSHTTPCookieStorageHostObject {
+    cookies: Vec<Cookie>,
+    accept_policy: NSHTTPCookieAcceptPolicy,
+}
+impl HostObject for NSHTTPCookieStorageHostObject {}
+impl NSHTTPCookieStorageHostObject {
+    /// Store a cookie, replacing any with the same name, domain and path.
+    /// Storing an expired cookie just removes the old one.
+    fn set_cookie(&mut self, cookie: Cookie, now: SystemTime) {
+        self.cookies
+            .retain(|existing| !existing.same_identity(&cookie));
+        if !cookie.is_expired(now) {
+            self.cookies.push(cookie);
+        }
+    }
+}
+
+fn cookies_path(env: &Environment) -> GuestPathBuf {
+    env.fs
+        .home_directory()
+        .join("Library/Cookies/Cookies.plist")
+}
+
+fn load_cookies(env: &mut Environment) -> Vec<Cookie> {
+    let path = cookies_path(env);
+    let Ok(bytes) = env.fs.read(&path) else {
+        return Vec::new();
+    };
+    let Some(dicts) = plist::Value::from_reader(Cursor::new(bytes))
+        .ok()
+        .and_then(|plist| plist.into_array())
+    else {
+        log!(
+            "Warning: couldn't parse {:?}, ignoring saved cookies.",
+            path
+        );
+        return Vec::new();
+    };
+    let now = current_time(env);
+    dicts
+        .iter()
+        .filter_map(|dict| dict.as_dictionary().and_then(Cookie::from_plist))
+        .filter(|cookie| !cookie.is_expired(now))
+        .collect()
+}
+
+fn save_cookies(env: &mut Environment, storage: id) {
+    // Only the shared storage is backed by a file.
+    if State::get(env).shared_storage != Some(storage) {
+        return;
+    }
+    let cookies: Vec<plist::Value> = env
+        .objc
+        .borrow::<NSHTTPCookieStorageHostObject>(storage)
+        .cookies
+        .iter()
+        .map(|cookie| cookie.to_plist().into())
+        .collect();
+    let mut bytes = Vec::new();
+    plist::Value::Array(cookies)
+        .to_writer_xml(&mut bytes)
+        .unwrap();
+
+    let path = cookies_path(env);
+    if env.fs.create_dir_all(path.parent().unwrap()).is_err()
+        || env.fs.write(&path, &bytes).is_err()
+    {
+        log!("Warning: couldn't save cookies to {:?}", path);
+    }
+}
+
+/// The unexpired cookies in a storage that should be sent with a request for
+/// `url`. Cookies with longer paths are listed first (RFC 6265 section 5.4).
+fn cookies_for_url(env: &mut Environment, storage: id, url: &CookieURL) -> Vec<Cookie> {
+    let now = current_time(env);
+    let host_obj = env.objc.borrow::<NSHTTPCookieStorageHostObject>(storage);
+    let mut cookies: Vec<Cookie> = host_obj
+        .cookies
+        .iter()
+        .filter(|cookie| cookie.matches(url, now))
+        .cloned()
+        .collect();
+    cookies.sort_by_key(|cookie| std::cmp::Reverse(cookie.path.len()));
+    cookies
+}
+
+/// Shortcut for host code: the value of the `Cookie` header for a request to
+/// an `NSURL*`, if there are any cookies for it.
+pub fn request_header_for_url(env: &mut Environment, url: id) -> Option<String> {
+    let url = cookie_url(env, url)?;
+    let storage: id = msg_class![env; NSHTTPCookieStorage sharedHTTPCookieStorage];
+    let cookies = cookies_for_url(env, storage, &url);
+    (!cookies.is_empty()).then(|| Cookie::request_header(&cookies))
+}
+
+/// Make an autoreleased `NSArray*` of `NSHTTPCookie*`s.
+fn cookies_to_array(env: &mut Environment, cookies: Vec<Cookie>) -> id {
+    let cookies = cookies
+        .into_iter()
+        .map(|cookie| from_cookie(env, cookie))
+        .collect();
+    let array = ns_array::from_vec(env, cookies);
+    autorelease(env, array)
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation NSHTTPCookieStorage: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(NSHTTPCookieStorageHostObject {
+        cookies: Vec::new(),
+        accept_policy: NSHTTPCookieAcceptPolicyAlways,
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
++ (id)sharedHTTPCookieStorage {
+    if let Some(existing) = State::get(env).shared_storage {
+        existing
+    } else {
+        let new: id = msg![env; this alloc];
+        let cookies = load_cookies(env);
+        env.objc.borrow_mut::<NSHTTPCookieStorageHostObject>(new).cookies = cookies;
+        State::get(env).shared_storage = Some(new);
+        new
+    }
+}
+
+- (NSHTTPCookieAcceptPolicy)cookieAcceptPolicy {
+    env.objc.borrow::<NSHTTPCookieStorageHostObject>(this).accept_policy
+}
+- (())setCookieAcceptPolicy:(NSHTTPCookieAcceptPolicy)policy {
+    env.objc.borrow_mut::<NSHTTPCookieStorageHostObject>(this).accept_policy = policy;
+}
+
+- (id)cookies {
+    let now = current_time(env);
+    let host_obj = env.objc.borrow::<NSHTTPCookieStorageHostObject>(this);
+    let cookies = host_obj
+        .cookies
+        .iter()
+        .filter(|cookie| !cookie.is_expired(now))
+        .cloned()
+        .collect();
+    cookies_to_array(env, cookies)
+}
+
+- (id)cookiesForURL:(id)url { // NSURL*
+    let Some(url) = cookie_url(env, url) else {
+        return cookies_to_array(env, Vec::new());
+    };
+    let cookies = cookies_for_url(env, this, &url);
+    cookies_to_array(env, cookies)
+}
+
+- (())setCookie:(id)cookie { // NSHTTPCookie*
+    let cookie = to_cookie(env, cookie);
+    let now = current_time(env);
+    let host_obj = env.objc.borrow_mut::<NSHTTPCookieStorageHostObject>(this);
+    if host_obj.accept_policy == NSHTTPCookieAcceptPolicyNever {
+        return;
+    }
+    host_obj.set_cookie(cookie, now);
+    save_cookies(env, this);
+}
+
+- (())setCookies:(id)cookies // NSArray*
+          forURL:(id)_url // NSURL*
+ mainDocumentURL:(id)main_document_url { // NSURL*
+    let main_document_url = cookie_url(env, main_document_url);
+    let count: NSUInteger = msg![env; cookies count];
+    let cookies: Vec<Cookie> = (0..count)
+        .map(|i| {
+            let cookie: id = msg![env; cookies objectAtIndex:i];
+            to_cookie(env, cookie)
+        })
+        .collect();
+
+    let now = current_time(env);
+    let host_obj = env.objc.borrow_mut::<NSHTTPCookieStorageHostObject>(this);
+    for cookie in cookies {
+        let accept = match host_obj.accept_policy {
+            NSHTTPCookieAcceptPolicyNever => false,
+            NSHTTPCookieAcceptPolicyOnlyFromMainDocumentDomain => main_document_url
+                .as_ref()
+                .is_none_or(|main_document_url| cookie.domain_matches(&main_document_url.host)),
+            _ => true,
+        };
+        if accept {
+            host_obj.set_cookie(cookie, now);
+        }
+    }
+    save_cookies(env, this);
+}
+
+- (())deleteCookie:(id)cookie { // NSHTTPCookie*
+    let cookie = to_cookie(env, cookie);
+    let host_obj = env.objc.borrow_mut::<NSHTTPCookieStorageHostObject>(this);
+    host_obj.cookies.retain(|existing| !existing.same_identity(&cookie));
+    save_cookies(env, this);
+}
+
+@end
+
+};
@@ -19,7 +19,8 @@ use crate::frameworks::uikit::ui_geometry::{
 };
 use crate::mem::ConstVoidPtr;
 use crate::objc::{
-    autorelease, id, msg, nil, objc_classes, release, retain, ClassExports, HostObject, NSZonePtr,
+    autorelease, id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject,
+    NSZonePtr,
 };
 use crate::Environment;
 use plist::{Dictionary, Uid, Value};
@@ -30,6 +31,9 @@ struct NSKeyedUnarchiverHostObject {
     current_key: Option<Uid>,
     /// linear map of Uid => id
     already_unarchived: Vec<Option<id>>,
+    /// Class to instantiate instead of classes that have no implementation,
+    /// see [set_fallback_class].
+    fallback_class: Option<&'static str>,
 }
 impl HostObject for NSKeyedUnarchiverHostObject {}
 
@@ -44,6 +48,7 @@ pub const CLASSES: ClassExports = objc_classes! {
         plist: Dictionary::new(),
         current_key: None,
         already_unarchived: Vec::new(),
+        fallback_class: None,
     });
     env.objc.alloc_object(this, unarchiver, &mut env.mem)
 }
@@ -96,6 +101,10 @@ pub const CLASSES: ClassExports = objc_classes! {
     env.objc.dealloc_object(this, &mut env.mem)
 }
 
+- (bool)allowsKeyedCoding {
+    true
+}
+
 // These methods drive most of the decoding. They get called in two cases:
 // - By the code that initiates the unarchival, e.g. UINib, to retrieve
 //   top-level objects.
@@ -104,6 +113,10 @@ pub const CLASSES: ClassExports = objc_classes! {
 // They are all from the NSCoder abstract class and they return default values
 // if the key is unknown.
 
+- (bool)containsValueForKey:(id)key { // NSString *
+    get_value_to_decode_for_key(env, this, key).is_some()
+}
+
 - (bool)decodeBoolForKey:(id)key { // NSString *
     get_value_to_decode_for_key(env, this, key).map_or(
         false,
@@ -248,7 +261,20 @@ fn unarchive_key(env: &mut Environment, unarchiver: id, key: Uid) -> id {
                     // while holding a reference to the class name, since it
                     // is ultimately owned by ObjC via the host object
                     let class_name = class_name.to_string();
-                    env.objc.get_known_class(&class_name, &mut env.mem)
+                    match host_obj.fallback_class {
+                        None => env.objc.get_known_class(&class_name, &mut env.mem),
+                        Some(fallback) => env
+                            .objc
+                            .get_implemented_class(&class_name, &mut env.mem)
+                            .unwrap_or_else(|| {
+                                log!(
+                                    "Warning: no implementation of class {}, unarchiving as {}",
+                                    class_name,
+                                    fallback
+                                );
+                                env.objc.get_known_class(fallback, &mut env.mem)
+                            }),
+                    }
                 };
                 let host_obj = borrow_host_obj(env, unarchiver); // reborrow
 
@@ -271,6 +297,24 @@ fn unarchive_key(env: &mut Environment, unarchiver: id, key: Uid) -> id {
             let s = s.to_string();
             from_rust_string(env, s)
         }
+        // Numbers are stored directly, e.g. the keys of a dictionary.
+        &Value::Integer(int) => {
+            let number: id = msg_class![env; NSNumber alloc];
+            if let Some(int) = int.as_signed() {
+                msg![env; number initWithLongLong:int]
+            } else {
+                let int = int.as_unsigned().unwrap();
+                msg![env; number initWithUnsignedLongLong:int]
+            }
+        }
+        &Value::Real(real) => {
+            let number: id = msg_class![env; NSNumber alloc];
+            msg![env; number initWithDouble:real]
+        }
+        &Value::Boolean(boolean) => {
+            let number: id = msg_class![env; NSNumber alloc];
+            msg![env; number initWithBool:boolean]
+        }
         _ => unimplemented!("Unarchive: {:#?}", item),
     };
 
@@ -279,6 +323,12 @@ fn unarchive_key(env: &mut Environment, unarchiver: id, key: Uid) -> id {
     new_object
 }
 
+/// Shortcut for use by the nib loader: instantiate `class_name` instead of any
+/// class that has no implementation, rather than panicking.
+pub fn set_fallback_class(env: &mut Environment, unarchiver: id, class_name: &'static str) {
+    borrow_host_obj(env, unarchiver).fallback_class = Some(class_name);
+}
+
 /// Shortcut for use by `[_touchHLE_NSArray initWithCoder:]`.
 ///
 /// The objects are to be considered retained by the `Vec`.
@@ -303,3 +353,35 @@ pub fn decode_current_array(env: &mut Environment, unarchiver: id) -> Vec<id> {
         })
         .collect()
 }
+
+/// Shortcut for use by `[_touchHLE_NSDictionary initWithCoder:]`.
+///
+/// Like [unarchive_key], the keys and objects returned are retained only by
+/// the archiver.
+pub fn decode_current_dictionary(env: &mut Environment, unarchiver: id) -> Vec<(id, id)> {
+    let (keys, objects): (Vec<Uid>, Vec<Uid>) = {
+        let host_obj = borrow_host_obj(env, unarchiver);
+        let objects = host_obj.plist["$objects"].as_array().unwrap();
+        let item = &objects[host_obj.current_key.unwrap().get() as usize];
+        let item = item.as_dictionary().unwrap();
+        let get_uids = |key: &str| -> Vec<Uid> {
+            item[key]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|value| value.as_uid().copied().unwrap())
+                .collect()
+        };
+        (get_uids("NS.keys"), get_uids("NS.objects"))
+    };
+    assert!(keys.len() == objects.len());
+
+    keys.into_iter()
+        .zip(objects)
+        .map(|(key, object)| {
+            let key = unarchive_key(env, unarchiver, key);
+            let object = unarchive_key(env, unarchiver, object);
+            (key, object)
+        })
+        .collect()
+}
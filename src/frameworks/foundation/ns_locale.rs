@@ -7,17 +7,47 @@
 
 use super::{ns_array, ns_string};
 use crate::dyld::{ConstantExports, HostConstant};
-use crate::objc::{id, objc_classes, ClassExports, HostObject};
+use crate::objc::{
+    autorelease, id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject,
+    NSZonePtr,
+};
 use crate::options::Options;
 use crate::Environment;
 use std::ffi::CStr;
 
+const NSLocaleIdentifier: &str = "NSLocaleIdentifier";
 const NSLocaleCountryCode: &str = "NSLocaleCountryCode";
+const NSLocaleDecimalSeparator: &str = "NSLocaleDecimalSeparator";
+const NSLocaleGroupingSeparator: &str = "NSLocaleGroupingSeparator";
+const NSLocaleCurrencySymbol: &str = "NSLocaleCurrencySymbol";
+const NSLocaleCurrencyCode: &str = "NSLocaleCurrencyCode";
 
-pub const CONSTANTS: ConstantExports = &[(
-    "_NSLocaleCountryCode",
-    HostConstant::NSString(NSLocaleCountryCode),
-)];
+pub const CONSTANTS: ConstantExports = &[
+    (
+        "_NSLocaleIdentifier",
+        HostConstant::NSString(NSLocaleIdentifier),
+    ),
+    (
+        "_NSLocaleCountryCode",
+        HostConstant::NSString(NSLocaleCountryCode),
+    ),
+    (
+        "_NSLocaleDecimalSeparator",
+        HostConstant::NSString(NSLocaleDecimalSeparator),
+    ),
+    (
+        "_NSLocaleGroupingSeparator",
+        HostConstant::NSString(NSLocaleGroupingSeparator),
+    ),
+    (
+        "_NSLocaleCurrencySymbol",
+        HostConstant::NSString(NSLocaleCurrencySymbol),
+    ),
+    (
+        "_NSLocaleCurrencyCode",
+        HostConstant::NSString(NSLocaleCurrencyCode),
+    ),
+];
 
 #[derive(Default)]
 pub struct State {
@@ -32,7 +62,7 @@ impl State {
 
 /// Use `msg_class![env; NSLocale preferredLanguages]` rather than calling this
 /// directly, because it may be slow and there is no caching.
-fn get_preferred_languages(options: &Options) -> Vec<String> {
+pub(crate) fn get_preferred_languages(options: &Options) -> Vec<String> {
     if let Some(ref preferred_languages) = options.preferred_languages {
         log!("The app requested your preferred languages. {:?} will reported based on your --preferred-languages= option.", preferred_languages);
         return preferred_languages.clone();
@@ -108,10 +138,73 @@ fn get_preferred_countries() -> Vec<String> {
 }
 
 struct NSLocaleHostObject {
+    /// `NSString*`
+    locale_identifier: id,
+    /// `NSString*`, may be `nil`
     country_code: id,
 }
 impl HostObject for NSLocaleHostObject {}
 
+/// Extract the region from a locale identifier like `en_US` or `zh-Hans_CN`.
+fn country_code_from_identifier(identifier: &str) -> Option<&str> {
+    let identifier = identifier.split('@').next().unwrap();
+    identifier.split('_').nth(1).filter(|region| {
+        (region.len() == 2 && region.bytes().all(|c| c.is_ascii_uppercase()))
+            || (region.len() == 3 && region.bytes().all(|c| c.is_ascii_digit()))
+    })
+}
+
+/// The symbols used when formatting numbers in a locale.
+pub struct NumberSymbols {
+    pub decimal_separator: String,
+    pub grouping_separator: String,
+    pub currency_symbol: String,
+    pub currency_code: &'static str,
+    /// Whether the currency symbol goes after the number rather than before.
+    pub currency_symbol_after: bool,
+    /// Number of digits after the decimal separator for amounts of money.
+    pub currency_digits: u32,
+}
+
+/// Get the number formatting symbols for an `NSLocale*`, or for the current
+/// locale if it's `nil`. Only a handful of regions are known, anything else
+/// gets the `en_US` symbols.
+pub fn number_symbols(env: &mut Environment, locale: id) -> NumberSymbols {
+    let locale = if locale == nil {
+        msg_class![env; NSLocale currentLocale]
+    } else {
+        locale
+    };
+    let country_code = env.objc.borrow::<NSLocaleHostObject>(locale).country_code;
+    let country_code = if country_code == nil {
+        None
+    } else {
+        Some(ns_string::to_rust_string(env, country_code))
+    };
+
+    // (decimal, grouping, currency symbol, currency code, symbol after, digits)
+    let (decimal, grouping, symbol, code, after, digits) =
+        match country_code.as_deref().unwrap_or("US") {
+            "GB" => (".", ",", "£", "GBP", false, 2),
+            "CA" => (".", ",", "$", "CAD", false, 2),
+            "AU" => (".", ",", "$", "AUD", false, 2),
+            "JP" => (".", ",", "¥", "JPY", false, 0),
+            "CN" => (".", ",", "¥", "CNY", false, 2),
+            "KR" => (".", ",", "₩", "KRW", false, 0),
+            "DE" | "AT" | "ES" | "IT" | "NL" => (",", ".", "€", "EUR", true, 2),
+            "FR" => (",", "\u{a0}", "€", "EUR", true, 2),
+            _ => (".", ",", "$", "USD", false, 2),
+        };
+    NumberSymbols {
+        decimal_separator: decimal.to_string(),
+        grouping_separator: grouping.to_string(),
+        currency_symbol: symbol.to_string(),
+        currency_code: code,
+        currency_symbol_after: after,
+        currency_digits: digits,
+    }
+}
+
 pub const CLASSES: ClassExports = objc_classes! {
 
 (env, this, _cmd);
@@ -139,8 +232,16 @@ pub const CLASSES: ClassExports = objc_classes! {
         locale
     } else {
         let countries = get_preferred_countries();
+        let languages: id = msg![env; this preferredLanguages];
+        let language: id = msg![env; languages objectAtIndex:0u32];
+        let language = ns_string::to_rust_string(env, language);
+        // The language might already have a region suffix.
+        let language = language.split(['-', '_']).next().unwrap();
+        let identifier = format!("{}_{}", language, countries[0]);
+        let locale_identifier = ns_string::from_rust_string(env, identifier);
         let country_code = ns_string::from_rust_string(env, countries[0].clone());
         let host_object = NSLocaleHostObject {
+            locale_identifier,
             country_code
         };
         let new_locale = env.objc.alloc_object(
@@ -153,16 +254,76 @@ pub const CLASSES: ClassExports = objc_classes! {
     }
 }
 
-// TODO: constructors, more accessors
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(NSLocaleHostObject {
+        locale_identifier: nil,
+        country_code: nil,
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
++ (id)localeWithLocaleIdentifier:(id)identifier { // NSString*
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initWithLocaleIdentifier:identifier];
+    autorelease(env, new)
+}
+
+- (id)initWithLocaleIdentifier:(id)identifier { // NSString*
+    let identifier: id = msg![env; identifier copy];
+    let identifier_str = ns_string::to_rust_string(env, identifier);
+    let country_code = country_code_from_identifier(&identifier_str).map(str::to_string);
+    let country_code = match country_code {
+        Some(country_code) => ns_string::from_rust_string(env, country_code),
+        None => nil,
+    };
+    *env.objc.borrow_mut(this) = NSLocaleHostObject {
+        locale_identifier: identifier,
+        country_code,
+    };
+    this
+}
+
+- (())dealloc {
+    let &NSLocaleHostObject { locale_identifier, country_code } = env.objc.borrow(this);
+    release(env, locale_identifier);
+    release(env, country_code);
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+// NSCopying implementation
+- (id)copyWithZone:(NSZonePtr)_zone {
+    retain(env, this)
+}
+
+- (id)localeIdentifier {
+    env.objc.borrow::<NSLocaleHostObject>(this).locale_identifier
+}
+
+// TODO: more accessors
 
 - (id)objectForKey:(id)key {
     let key_str: &str = &ns_string::to_rust_string(env, key);
     match key_str {
+        NSLocaleIdentifier => env.objc.borrow::<NSLocaleHostObject>(this).locale_identifier,
         NSLocaleCountryCode => {
-            let &NSLocaleHostObject { country_code } = env.objc.borrow(this);
+            let &NSLocaleHostObject { country_code, .. } = env.objc.borrow(this);
             country_code
         },
-        _ => unimplemented!()
+        NSLocaleDecimalSeparator
+        | NSLocaleGroupingSeparator
+        | NSLocaleCurrencySymbol
+        | NSLocaleCurrencyCode => {
+            let symbols = number_symbols(env, this);
+            let value = match key_str {
+                NSLocaleDecimalSeparator => symbols.decimal_separator,
+                NSLocaleGroupingSeparator => symbols.grouping_separator,
+                NSLocaleCurrencySymbol => symbols.currency_symbol,
+                _ => symbols.currency_code.to_string(),
+            };
+            let value = ns_string::from_rust_string(env, value);
+            autorelease(env, value)
+        },
+        _ => unimplemented!("NSLocale objectForKey: {}", key_str)
     }
 }
 
@@ -4,34 +4,178 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 //! `NSNotificationCenter`.
+//!
+//! This also provides the implementation of `CFNotificationCenter` (see
+//! [crate::frameworks::core_foundation::cf_notification_center]), so observers
+//! added with either API receive notifications posted with either API.
 
 use super::ns_notification::NSNotificationName;
 use super::ns_string;
 
+use crate::abi::{CallFromHost, GuestFunction};
+use crate::mem::ConstVoidPtr;
 use crate::objc::{
     id, msg, msg_class, msg_send, nil, objc_classes, release, retain, ClassExports, HostObject,
     NSZonePtr, SEL,
 };
+use crate::Environment;
 use std::borrow::Cow;
 use std::collections::HashMap;
 
 #[derive(Default)]
 pub struct State {
     default_center: Option<id>,
+    /// Returned by `CFNotificationCenterGetDarwinNotifyCenter`. This is a
+    /// separate center that only receives what the app posts to it itself.
+    darwin_notify_center: Option<id>,
+}
+
+#[derive(Clone)]
+enum ObserverTarget {
+    /// Added with `addObserver:selector:name:object:`. The observer is
+    /// retained.
+    ObjC { observer: id, selector: SEL },
+    /// Added with `CFNotificationCenterAddObserver`. The observer is an opaque
+    /// pointer that is only passed back to the callback.
+    CF {
+        observer: ConstVoidPtr,
+        callback: GuestFunction,
+    },
 }
 
 #[derive(Clone)]
 struct Observer {
-    observer: id,
-    selector: SEL,
+    target: ObserverTarget,
     object: id,
 }
 
+/// Key for observers of a particular notification name, or [None] for
+/// observers of all notifications.
+type ObserverName = Option<Cow<'static, str>>;
+
 struct NSNotificationCenterHostObject {
-    observers: HashMap<Cow<'static, str>, Vec<Observer>>,
+    observers: HashMap<ObserverName, Vec<Observer>>,
 }
 impl HostObject for NSNotificationCenterHostObject {}
 
+fn observer_name(env: &mut Environment, name: NSNotificationName) -> ObserverName {
+    if name == nil {
+        None
+    } else {
+        // Usually a static string, so no real copy will happen
+        Some(ns_string::to_rust_string(env, name))
+    }
+}
+
+fn add_observer(env: &mut Environment, center: id, name: ObserverName, observer: Observer) {
+    if let ObserverTarget::ObjC { observer, .. } = observer.target {
+        retain(env, observer);
+    }
+    retain(env, observer.object); // TODO: is it correct that this is retained?
+
+    let host_obj = env
+        .objc
+        .borrow_mut::<NSNotificationCenterHostObject>(center);
+    host_obj.observers.entry(name).or_default().push(observer);
+}
+
+/// Remove observers for which `predicate` returns [true], either for one
+/// notification name or for all of them if `name` is [None].
+fn remove_observers(
+    env: &mut Environment,
+    center: id,
+    name: ObserverName,
+    predicate: impl Fn(&Observer) -> bool,
+) {
+    let host_obj = env
+        .objc
+        .borrow_mut::<NSNotificationCenterHostObject>(center);
+
+    // TODO: is this the correct behaviour, can an observer be registered
+    // several times?
+    let mut removed_observers = Vec::new();
+
+    for (observers_name, observers) in host_obj.observers.iter_mut() {
+        if name.is_some() && name != *observers_name {
+            continue;
+        }
+        let mut i = 0;
+        while i < observers.len() {
+            if predicate(&observers[i]) {
+                removed_observers.push(observers.swap_remove(i));
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    for removed_observer in removed_observers {
+        if let ObserverTarget::ObjC { observer, .. } = removed_observer.target {
+            release(env, observer);
+        }
+        release(env, removed_observer.object);
+    }
+}
+
+/// Get the object returned by `CFNotificationCenterGetDarwinNotifyCenter`.
+pub fn darwin_notify_center(env: &mut Environment) -> id {
+    let state = &mut env.framework_state.foundation.ns_notification_center;
+    if let Some(c) = state.darwin_notify_center {
+        c
+    } else {
+        let new: id = msg_class![env; NSNotificationCenter new];
+        env.framework_state
+            .foundation
+            .ns_notification_center
+            .darwin_notify_center = Some(new);
+        new
+    }
+}
+
+pub fn is_darwin_notify_center(env: &Environment, center: id) -> bool {
+    env.framework_state
+        .foundation
+        .ns_notification_center
+        .darwin_notify_center
+        == Some(center)
+}
+
+/// Implementation of `CFNotificationCenterAddObserver`.
+pub fn add_cf_observer(
+    env: &mut Environment,
+    center: id,
+    observer: ConstVoidPtr,
+    callback: GuestFunction,
+    name: NSNotificationName,
+    object: id,
+) {
+    let name = observer_name(env, name);
+    let observer = Observer {
+        target: ObserverTarget::CF { observer, callback },
+        object,
+    };
+    add_observer(env, center, name, observer);
+}
+
+/// Implementation of `CFNotificationCenterRemoveObserver` and
+/// `CFNotificationCenterRemoveEveryObserver`. A `nil` name or object matches
+/// any name or object.
+pub fn remove_cf_observer(
+    env: &mut Environment,
+    center: id,
+    observer: ConstVoidPtr,
+    name: NSNotificationName,
+    object: id,
+) {
+    let name = observer_name(env, name);
+    remove_observers(env, center, name, |existing| {
+        matches!(
+            existing.target,
+            ObserverTarget::CF { observer: existing_observer, .. } if existing_observer == observer
+        ) && (object == nil || object == existing.object)
+    });
+}
+
 pub const CLASSES: ClassExports = objc_classes! {
 
 (env, this, _cmd);
@@ -59,7 +203,9 @@ pub const CLASSES: ClassExports = objc_classes! {
     let host_obj = env.objc.borrow_mut::<NSNotificationCenterHostObject>(this);
     let observers = std::mem::take(&mut host_obj.observers);
     for observer in observers.values().flatten() {
-        release(env, observer.observer);
+        if let ObserverTarget::ObjC { observer, .. } = observer.target {
+            release(env, observer);
+        }
         release(env, observer.object);
     }
     env.objc.dealloc_object(this, &mut env.mem);
@@ -69,9 +215,7 @@ pub const CLASSES: ClassExports = objc_classes! {
          selector:(SEL)selector
              name:(NSNotificationName)name
            object:(id)object {
-    // TODO: handle case where name is nil
-    // Usually a static string, so no real copy will happen
-    let name = ns_string::to_rust_string(env, name);
+    let name = observer_name(env, name);
 
     log_dbg!(
         "[(NSNotificationCenter*){:?} addObserver:{:?} selector:{:?} name:{:?} object:{:?}",
@@ -82,15 +226,11 @@ pub const CLASSES: ClassExports = objc_classes! {
         object,
     );
 
-    retain(env, observer);
-    retain(env, object); // TODO: is it correct that this is retained?
-
-    let host_obj = env.objc.borrow_mut::<NSNotificationCenterHostObject>(this);
-    host_obj.observers.entry(name).or_default().push(Observer {
-        observer,
-        selector,
+    let observer = Observer {
+        target: ObserverTarget::ObjC { observer, selector },
         object,
-    });
+    };
+    add_observer(env, this, name, observer);
 }
 
 - (())removeObserver:(id)observer
@@ -98,9 +238,7 @@ pub const CLASSES: ClassExports = objc_classes! {
               object:(id)object {
     assert!(observer != nil); // TODO
 
-    // TODO: handle case where name is nil
-    // Usually a static string, so no real copy will happen
-    let name = ns_string::to_rust_string(env, name);
+    let name = observer_name(env, name);
 
     log_dbg!(
         "[(NSNotificationCenter*){:?} removeObserver:{:?} name:{:?} object:{:?}",
@@ -110,28 +248,16 @@ pub const CLASSES: ClassExports = objc_classes! {
         object,
     );
 
-    let host_obj = env.objc.borrow_mut::<NSNotificationCenterHostObject>(this);
-    let Some(observers) = host_obj.observers.get_mut(&name) else {
-        return;
-    };
-
-    // TODO: is this the correct behaviour, can an observer be registered
-    // several times?
-    let mut removed_observers = Vec::new();
-
-    let mut i = 0;
-    while i < observers.len() {
-        if observers[i].observer == observer && (object == nil || object == observers[i].object) {
-            removed_observers.push(observers.swap_remove(i));
-        } else {
-            i += 1;
-        }
-    }
-
-    for removed_observer in removed_observers {
-        release(env, removed_observer.observer);
-        release(env, removed_observer.object);
-    }
+    remove_observers(env, this, name, |existing| {
+        matches!(
+            existing.target,
+            ObserverTarget::ObjC { observer: existing_observer, .. }
+                if existing_observer == observer
+        ) && (object == nil || object == existing.object)
+    });
+}
+- (())removeObserver:(id)observer {
+    msg![env; this removeObserver:observer name:nil object:nil]
 }
 
 - (())postNotification:(id)notification {
@@ -141,34 +267,57 @@ pub const CLASSES: ClassExports = objc_classes! {
         notification,
     );
 
-    let name: id = msg![env; notification name];
+    let name_string: id = msg![env; notification name];
     // Usually a static string, so no real copy will happen
-    let name = ns_string::to_rust_string(env, name);
+    let name = ns_string::to_rust_string(env, name_string);
 
     let notification_poster: id = msg![env; notification object];
 
     log_dbg!("Notification is a {:?} posted by {:?}", name, notification_poster);
 
     let host_obj = env.objc.borrow_mut::<NSNotificationCenterHostObject>(this);
-    let Some(observers) = host_obj.observers.get(&name).cloned() else {
-        return;
-    };
-    for Observer { observer, selector, object } in observers {
+    let observers: Vec<Observer> = [Some(name), None]
+        .iter()
+        .filter_map(|name| host_obj.observers.get(name))
+        .flatten()
+        .cloned()
+        .collect();
+    for Observer { target, object } in observers {
         // The object argument is a filter for which notification sources the
         // observer is interested in.
         if object != nil && notification_poster != object {
             continue;
         }
 
-        log_dbg!(
-            "Notification {:?} observed, sending {:?} message to {:?}",
-            notification,
-            selector.as_str(&env.mem),
-            observer
-        );
+        match target {
+            ObserverTarget::ObjC { observer, selector } => {
+                log_dbg!(
+                    "Notification {:?} observed, sending {:?} message to {:?}",
+                    notification,
+                    selector.as_str(&env.mem),
+                    observer
+                );
 
-        // Signature should be `- (void)notification:(NSNotification *)notif`.
-        let _: () = msg_send(env, (observer, selector, notification));
+                // Signature should be
+                // `- (void)notification:(NSNotification *)notif`.
+                let _: () = msg_send(env, (observer, selector, notification));
+            }
+            ObserverTarget::CF { observer, callback } => {
+                log_dbg!(
+                    "Notification {:?} observed, calling {:?} with observer {:?}",
+                    notification,
+                    callback,
+                    observer
+                );
+
+                let user_info: id = msg![env; notification userInfo];
+                // Signature is `void (*)(CFNotificationCenterRef center,
+                // void *observer, CFNotificationName name, const void *object,
+                // CFDictionaryRef userInfo)`.
+                let args = (this, observer, name_string, notification_poster, user_info);
+                () = callback.call_from_host(env, args);
+            }
+        }
     }
 }
 - (())postNotificationName:(NSNotificationName)name
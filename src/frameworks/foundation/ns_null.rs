@@ -5,13 +5,29 @@
  */
 //! `NSNull`.
 
-use crate::objc::{id, objc_classes, ClassExports, TrivialHostObject};
+use super::ns_string::get_static_str;
+use crate::objc::{id, objc_classes, ClassExports, NSZonePtr, TrivialHostObject};
+use crate::Environment;
 
 #[derive(Default)]
 pub struct State {
     null: Option<id>,
 }
 
+/// Get the `NSNull` singleton (also known as `kCFNull`), creating it if
+/// needed.
+pub fn get_null(env: &mut Environment) -> id {
+    if let Some(null) = env.framework_state.foundation.ns_null.null {
+        return null;
+    }
+    let class = env.objc.get_known_class("NSNull", &mut env.mem);
+    let new = env
+        .objc
+        .alloc_static_object(class, Box::new(TrivialHostObject), &mut env.mem);
+    env.framework_state.foundation.ns_null.null = Some(new);
+    new
+}
+
 pub const CLASSES: ClassExports = objc_classes! {
 
 (env, this, _cmd);
@@ -20,24 +36,29 @@ pub const CLASSES: ClassExports = objc_classes! {
 // allow that value.
 @implementation NSNull: NSObject
 
++ (id)allocWithZone:(NSZonePtr)_zone {
+    // There is only one instance, so [[NSNull alloc] init] is the same object
+    // as [NSNull null]. NSObject's init just returns it.
+    get_null(env)
+}
+
 + (id)null {
-    if let Some(null) = env.framework_state.foundation.ns_null.null {
-        null
-    } else {
-        let new = env.objc.alloc_static_object(
-            this,
-            Box::new(TrivialHostObject),
-            &mut env.mem
-        );
-        env.framework_state.foundation.ns_null.null = Some(new);
-        new
-   }
+    get_null(env)
 }
 
 - (id)retain { this }
 - (())release {}
 - (id)autorelease { this }
 
+// NSCopying implementation
+- (id)copyWithZone:(NSZonePtr)_zone {
+    this
+}
+
+- (id)description {
+    get_static_str(env, "<null>")
+}
+
 @end
 
 };
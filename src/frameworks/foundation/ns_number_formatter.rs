@@ -0,0 +1,488 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `NSNumberFormatter`.
+//!
+//! Only the "10.4+" behavior is implemented, and only the no-style, decimal,
+//! currency and percent styles. Patterns (`setPositiveFormat:` etc) are not
+//! supported. Values are formatted via [Decimal] so that rounding is done on
+//! the decimal representation, like Apple's.
+//!
+//! Resources:
+//! - Apple's [Introduction to Data Formatting Programming Guide For Cocoa](https://developer.apple.com/library/archive/documentation/Cocoa/Conceptual/DataFormatting/DataFormatting.html)
+
+use super::ns_decimal_number::{self, Decimal, RoundingMode};
+use super::ns_locale::{self, NumberSymbols};
+use super::ns_string::{from_rust_string, to_rust_string};
+use super::NSUInteger;
+use crate::objc::{
+    autorelease, id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject,
+    NSZonePtr,
+};
+use crate::Environment;
+
+pub type NSNumberFormatterStyle = NSUInteger;
+pub const NSNumberFormatterNoStyle: NSNumberFormatterStyle = 0;
+pub const NSNumberFormatterDecimalStyle: NSNumberFormatterStyle = 1;
+pub const NSNumberFormatterCurrencyStyle: NSNumberFormatterStyle = 2;
+pub const NSNumberFormatterPercentStyle: NSNumberFormatterStyle = 3;
+
+pub type NSNumberFormatterBehavior = NSUInteger;
+pub const NSNumberFormatterBehavior10_4: NSNumberFormatterBehavior = 1040;
+
+/// A format with the defaults of the style and locale applied.
+#[derive(Debug)]
+struct NumberFormat {
+    minimum_fraction_digits: u32,
+    maximum_fraction_digits: u32,
+    uses_grouping_separator: bool,
+    grouping_size: u32,
+    grouping_separator: String,
+    decimal_separator: String,
+    prefix: String,
+    suffix: String,
+    /// The power of ten values are multiplied by for display, i.e. 2 for
+    /// percentages.
+    multiplier_power: i32,
+}
+impl NumberFormat {
+    fn format(&self, value: Decimal) -> String {
+        if value.is_nan() {
+            return "NaN".to_string();
+        }
+        let multiplier = Decimal::new(false, 1, self.multiplier_power).unwrap();
+        let value = value.mul(multiplier).unwrap_or(value);
+        // Apple's formatter defaults to rounding half to even.
+        let value = value
+            .round(self.maximum_fraction_digits as i32, RoundingMode::Bankers)
+            .unwrap_or(value);
+
+        let digits = value.to_string();
+        let (negative, digits) = match digits.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, &digits[..]),
+        };
+        let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+
+        let mut result = String::new();
+        if negative {
+            result.push('-');
+        }
+        result.push_str(&self.prefix);
+        if self.uses_grouping_separator && self.grouping_size > 0 {
+            let grouping_size = self.grouping_size as usize;
+            for (i, digit) in integer.chars().enumerate() {
+                if i != 0 && (integer.len() - i) % grouping_size == 0 {
+                    result.push_str(&self.grouping_separator);
+                }
+                result.push(digit);
+            }
+        } else {
+            result.push_str(integer);
+        }
+        let padding = (self.minimum_fraction_digits as usize).saturating_sub(fraction.len());
+        if !fraction.is_empty() || padding > 0 {
+            result.push_str(&self.decimal_separator);
+            result.push_str(fraction);
+            result.push_str(&"0".repeat(padding));
+        }
+        result.push_str(&self.suffix);
+        result
+    }
+
+    fn parse(&self, string: &str) -> Option<Decimal> {
+        let string = string.trim();
+        let (negative, rest) = match string.strip_prefix('-') {
+            Some(rest) => (true, rest.trim_start()),
+            None => (false, string),
+        };
+        let rest = rest.strip_prefix(&self.prefix[..]).unwrap_or(rest);
+        let rest = rest.strip_suffix(&self.suffix[..]).unwrap_or(rest).trim();
+
+        let (integer, fraction) = if self.decimal_separator.is_empty() {
+            (rest, "")
+        } else {
+            rest.split_once(&self.decimal_separator[..])
+                .unwrap_or((rest, ""))
+        };
+        let integer = if self.grouping_separator.is_empty() {
+            integer.to_string()
+        } else {
+            integer.replace(&self.grouping_separator[..], "")
+        };
+        if (integer.is_empty() && fraction.is_empty())
+            || !integer
+                .bytes()
+                .chain(fraction.bytes())
+                .all(|c| c.is_ascii_digit())
+        {
+            return None;
+        }
+
+        let normalized = format!(
+            "{}{}.{}",
+            if negative { "-" } else { "" },
+            if integer.is_empty() { "0" } else { &integer },
+            fraction
+        );
+        let (value, _) = Decimal::parse_prefix(&normalized, ".")?;
+        let divisor = Decimal::new(false, 1, self.multiplier_power).unwrap();
+        value.div(divisor).ok()
+    }
+}
+
+struct NSNumberFormatterHostObject {
+    number_style: NSNumberFormatterStyle,
+    /// `NSLocale*`, or `nil` for the current locale
+    locale: id,
+    generates_decimal_numbers: bool,
+    // These override the defaults of the style and locale if set.
+    minimum_fraction_digits: Option<u32>,
+    maximum_fraction_digits: Option<u32>,
+    uses_grouping_separator: Option<bool>,
+    grouping_size: Option<u32>,
+    grouping_separator: Option<String>,
+    decimal_separator: Option<String>,
+    currency_symbol: Option<String>,
+}
+impl HostObject for NSNumberFormatterHostObject {}
+
+fn resolve_format(env: &mut Environment, this: id) -> NumberFormat {
+    let locale = env.objc.borrow::<NSNumberFormatterHostObject>(this).locale;
+    let NumberSymbols {
+        decimal_separator,
+        grouping_separator,
+        currency_symbol,
+        currency_symbol_after,
+        currency_digits,
+        ..
+    } = ns_locale::number_symbols(env, locale);
+    let host = env.objc.borrow::<NSNumberFormatterHostObject>(this);
+
+    let (min, max, grouping) = match host.number_style {
+        NSNumberFormatterNoStyle => (0, 0, false),
+        NSNumberFormatterDecimalStyle => (0, 3, true),
+        NSNumberFormatterCurrencyStyle => (currency_digits, currency_digits, true),
+        NSNumberFormatterPercentStyle => (0, 0, true),
+        _ => unreachable!(), // checked by setNumberStyle:
+    };
+    let currency_symbol = host.currency_symbol.clone().unwrap_or(currency_symbol);
+    let (prefix, suffix, multiplier_power) = match host.number_style {
+        NSNumberFormatterCurrencyStyle if currency_symbol_after => {
+            (String::new(), format!("\u{a0}{}", currency_symbol), 0)
+        }
+        NSNumberFormatterCurrencyStyle => (currency_symbol, String::new(), 0),
+        NSNumberFormatterPercentStyle => (String::new(), "%".to_string(), 2),
+        _ => (String::new(), String::new(), 0),
+    };
+    let minimum_fraction_digits = host.minimum_fraction_digits.unwrap_or(min);
+    let maximum_fraction_digits = host.maximum_fraction_digits.unwrap_or(max);
+
+    NumberFormat {
+        minimum_fraction_digits,
+        maximum_fraction_digits: maximum_fraction_digits.max(minimum_fraction_digits),
+        uses_grouping_separator: host.uses_grouping_separator.unwrap_or(grouping),
+        grouping_size: host.grouping_size.unwrap_or(3),
+        grouping_separator: host
+            .grouping_separator
+            .clone()
+            .unwrap_or(grouping_separator),
+        decimal_separator: host.decimal_separator.clone().unwrap_or(decimal_separator),
+        prefix,
+        suffix,
+        multiplier_power,
+    }
+}
+
+fn string_or_none(env: &mut Environment, string: id) -> Option<String> {
+    if string == nil {
+        None
+    } else {
+        Some(to_rust_string(env, string).into_owned())
+    }
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation NSNumberFormatter: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(NSNumberFormatterHostObject {
+        number_style: NSNumberFormatterNoStyle,
+        locale: nil,
+        generates_decimal_numbers: false,
+        minimum_fraction_digits: None,
+        maximum_fraction_digits: None,
+        uses_grouping_separator: None,
+        grouping_size: None,
+        grouping_separator: None,
+        decimal_separator: None,
+        currency_symbol: None,
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
++ (id)localizedStringFromNumber:(id)number // NSNumber*
+                    numberStyle:(NSNumberFormatterStyle)style {
+    let formatter: id = msg![env; this new];
+    () = msg![env; formatter setNumberStyle:style];
+    let string: id = msg![env; formatter stringFromNumber:number];
+    release(env, formatter);
+    string
+}
+
+- (())dealloc {
+    let locale = env.objc.borrow::<NSNumberFormatterHostObject>(this).locale;
+    release(env, locale);
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+- (NSNumberFormatterBehavior)formatterBehavior {
+    NSNumberFormatterBehavior10_4
+}
+- (())setFormatterBehavior:(NSNumberFormatterBehavior)behavior {
+    if behavior != NSNumberFormatterBehavior10_4 {
+        log!("TODO: NSNumberFormatter behavior {}, using 10.4 behavior", behavior);
+    }
+}
+
+- (NSNumberFormatterStyle)numberStyle {
+    env.objc.borrow::<NSNumberFormatterHostObject>(this).number_style
+}
+- (())setNumberStyle:(NSNumberFormatterStyle)style {
+    let style = if style > NSNumberFormatterPercentStyle {
+        log!("TODO: NSNumberFormatter style {}, using decimal style", style);
+        NSNumberFormatterDecimalStyle
+    } else {
+        style
+    };
+    let host = env.objc.borrow_mut::<NSNumberFormatterHostObject>(this);
+    host.number_style = style;
+    // Changing the style resets these to the style's defaults.
+    host.minimum_fraction_digits = None;
+    host.maximum_fraction_digits = None;
+    host.uses_grouping_separator = None;
+}
+
+- (id)locale {
+    let locale = env.objc.borrow::<NSNumberFormatterHostObject>(this).locale;
+    if locale == nil {
+        msg_class![env; NSLocale currentLocale]
+    } else {
+        locale
+    }
+}
+- (())setLocale:(id)locale { // NSLocale*
+    retain(env, locale);
+    let host = env.objc.borrow_mut::<NSNumberFormatterHostObject>(this);
+    let old = std::mem::replace(&mut host.locale, locale);
+    release(env, old);
+}
+
+- (bool)generatesDecimalNumbers {
+    env.objc.borrow::<NSNumberFormatterHostObject>(this).generates_decimal_numbers
+}
+- (())setGeneratesDecimalNumbers:(bool)generates {
+    env.objc.borrow_mut::<NSNumberFormatterHostObject>(this).generates_decimal_numbers = generates;
+}
+
+- (NSUInteger)minimumFractionDigits {
+    resolve_format(env, this).minimum_fraction_digits
+}
+- (())setMinimumFractionDigits:(NSUInteger)digits {
+    let max = resolve_format(env, this).maximum_fraction_digits;
+    let host = env.objc.borrow_mut::<NSNumberFormatterHostObject>(this);
+    host.minimum_fraction_digits = Some(digits);
+    if max < digits {
+        host.maximum_fraction_digits = Some(digits);
+    }
+}
+
+- (NSUInteger)maximumFractionDigits {
+    resolve_format(env, this).maximum_fraction_digits
+}
+- (())setMaximumFractionDigits:(NSUInteger)digits {
+    let min = resolve_format(env, this).minimum_fraction_digits;
+    let host = env.objc.borrow_mut::<NSNumberFormatterHostObject>(this);
+    host.maximum_fraction_digits = Some(digits);
+    if min > digits {
+        host.minimum_fraction_digits = Some(digits);
+    }
+}
+
+- (bool)usesGroupingSeparator {
+    resolve_format(env, this).uses_grouping_separator
+}
+- (())setUsesGroupingSeparator:(bool)uses {
+    env.objc.borrow_mut::<NSNumberFormatterHostObject>(this).uses_grouping_separator = Some(uses);
+}
+
+- (NSUInteger)groupingSize {
+    resolve_format(env, this).grouping_size
+}
+- (())setGroupingSize:(NSUInteger)size {
+    env.objc.borrow_mut::<NSNumberFormatterHostObject>(this).grouping_size = Some(size);
+}
+
+- (id)groupingSeparator {
+    let separator = resolve_format(env, this).grouping_separator;
+    let separator = from_rust_string(env, separator);
+    autorelease(env, separator)
+}
+- (())setGroupingSeparator:(id)separator { // NSString*
+    let separator = string_or_none(env, separator);
+    env.objc.borrow_mut::<NSNumberFormatterHostObject>(this).grouping_separator = separator;
+}
+
+- (id)decimalSeparator {
+    let separator = resolve_format(env, this).decimal_separator;
+    let separator = from_rust_string(env, separator);
+    autorelease(env, separator)
+}
+- (())setDecimalSeparator:(id)separator { // NSString*
+    let separator = string_or_none(env, separator);
+    env.objc.borrow_mut::<NSNumberFormatterHostObject>(this).decimal_separator = separator;
+}
+
+- (id)currencySymbol {
+    let locale = env.objc.borrow::<NSNumberFormatterHostObject>(this).locale;
+    let default = ns_locale::number_symbols(env, locale).currency_symbol;
+    let host = env.objc.borrow::<NSNumberFormatterHostObject>(this);
+    let symbol = host.currency_symbol.clone().unwrap_or(default);
+    let symbol = from_rust_string(env, symbol);
+    autorelease(env, symbol)
+}
+- (())setCurrencySymbol:(id)symbol { // NSString*
+    let symbol = string_or_none(env, symbol);
+    env.objc.borrow_mut::<NSNumberFormatterHostObject>(this).currency_symbol = symbol;
+}
+
+- (id)stringFromNumber:(id)number { // NSNumber*
+    if number == nil {
+        return nil;
+    }
+    let value = ns_decimal_number::decimal_from_number(env, number);
+    let string = resolve_format(env, this).format(value);
+    let string = from_rust_string(env, string);
+    autorelease(env, string)
+}
+
+- (id)numberFromString:(id)string { // NSString*
+    if string == nil {
+        return nil;
+    }
+    let string = to_rust_string(env, string);
+    let Some(value) = resolve_format(env, this).parse(&string) else {
+        return nil;
+    };
+    if env.objc.borrow::<NSNumberFormatterHostObject>(this).generates_decimal_numbers {
+        return ns_decimal_number::from_decimal(env, value);
+    }
+    let value = value.to_f64();
+    if value.fract() == 0.0 && value.abs() < i64::MAX as f64 {
+        msg_class![env; NSNumber numberWithLongLong:(value as i64)]
+    } else {
+        msg_class![env; NSNumber numberWithDouble:value]
+    }
+}
+
+@end
+
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn en_us(style: NSNumberFormatterStyle) -> NumberFormat {
+        let (min, max, prefix, suffix, multiplier_power) = match style {
+            NSNumberFormatterDecimalStyle => (0, 3, "", "", 0),
+            NSNumberFormatterCurrencyStyle => (2, 2, "$", "", 0),
+            NSNumberFormatterPercentStyle => (0, 0, "", "%", 2),
+            _ => unreachable!(),
+        };
+        NumberFormat {
+            minimum_fraction_digits: min,
+            maximum_fraction_digits: max,
+            uses_grouping_separator: true,
+            grouping_size: 3,
+            grouping_separator: ",".to_string(),
+            decimal_separator: ".".to_string(),
+            prefix: prefix.to_string(),
+            suffix: suffix.to_string(),
+            multiplier_power,
+        }
+    }
+
+    fn parse(string: &str) -> Decimal {
+        Decimal::parse_prefix(string, ".").unwrap().0
+    }
+
+    #[test]
+    fn decimal_style() {
+        let format = en_us(NSNumberFormatterDecimalStyle);
+        assert_eq!(format.format(parse("1234567.891")), "1,234,567.891");
+        assert_eq!(format.format(parse("-1234.5")), "-1,234.5");
+        assert_eq!(format.format(parse("123")), "123");
+        assert_eq!(format.format(parse("0.00049")), "0");
+        assert_eq!(format.parse("1,234,567.891"), Some(parse("1234567.891")));
+        assert_eq!(format.parse("-1234.5"), Some(parse("-1234.5")));
+        assert_eq!(format.parse(".5"), Some(parse("0.5")));
+        assert_eq!(format.parse("12a"), None);
+        assert_eq!(format.parse(""), None);
+    }
+
+    #[test]
+    fn currency_style() {
+        let format = en_us(NSNumberFormatterCurrencyStyle);
+        assert_eq!(format.format(parse("1234567.891")), "$1,234,567.89");
+        assert_eq!(format.format(parse("-5")), "-$5.00");
+        // Half to even
+        assert_eq!(format.format(parse("0.125")), "$0.12");
+        assert_eq!(format.parse("$1,234,567.89"), Some(parse("1234567.89")));
+        assert_eq!(format.parse("-$5.00"), Some(parse("-5")));
+
+        let format = NumberFormat {
+            grouping_separator: ".".to_string(),
+            decimal_separator: ",".to_string(),
+            prefix: String::new(),
+            suffix: "\u{a0}€".to_string(),
+            ..format
+        };
+        assert_eq!(format.format(parse("1234567.891")), "1.234.567,89\u{a0}€");
+        assert_eq!(
+            format.parse("1.234.567,89\u{a0}€"),
+            Some(parse("1234567.89"))
+        );
+    }
+
+    #[test]
+    fn percent_style() {
+        let format = en_us(NSNumberFormatterPercentStyle);
+        assert_eq!(format.format(parse("0.25")), "25%");
+        assert_eq!(format.format(parse("12.345")), "1,234%");
+        assert_eq!(format.parse("25%"), Some(parse("0.25")));
+    }
+
+    #[test]
+    fn fraction_digits_and_grouping() {
+        let format = NumberFormat {
+            minimum_fraction_digits: 2,
+            maximum_fraction_digits: 4,
+            grouping_size: 2,
+            grouping_separator: " ".to_string(),
+            ..en_us(NSNumberFormatterDecimalStyle)
+        };
+        assert_eq!(format.format(parse("1234567")), "1 23 45 67.00");
+        assert_eq!(format.format(parse("1.23456")), "1.2346");
+        let format = NumberFormat {
+            uses_grouping_separator: false,
+            ..format
+        };
+        assert_eq!(format.format(parse("1234567.5")), "1234567.50");
+    }
+}
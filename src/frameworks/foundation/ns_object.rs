@@ -15,14 +15,15 @@
 //! See also: [crate::objc], especially the `objects` module.
 
 use super::ns_string::to_rust_string;
-use super::ns_run_loop::NSDefaultRunLoopMode;
-use super::{NSUInteger, ns_string};
+use super::ns_run_loop::{self, NSDefaultRunLoopMode, NSRunLoopCommonModes};
+use super::{NSTimeInterval, NSUInteger, ns_string};
 use super::ns_dictionary::dict_from_keys_and_objects;
-use crate::mem::MutVoidPtr;
+use crate::mem::{MutPtr, MutVoidPtr, Ptr};
 use crate::objc::{
-    id, nil, msg, msg_class, msg_send, objc_classes, Class, ClassExports, NSZonePtr, ObjC,
+    autorelease, id, nil, msg, msg_class, msg_send, objc_classes, release, retain, Class, ClassExports, NSZonePtr, ObjC,
     TrivialHostObject, SEL,
 };
+use crate::Environment;
 
 pub const CLASSES: ClassExports = objc_classes! {
 
@@ -58,6 +59,15 @@ pub const CLASSES: ClassExports = objc_classes! {
     // classes are not refcounted
 }
 
++ (id)description {
+    let description = env.objc.get_class_name(this).to_string();
+    let description = ns_string::from_rust_string(env, description);
+    autorelease(env, description)
+}
++ (id)debugDescription {
+    msg![env; this description]
+}
+
 + (bool)instancesRespondToSelector:(SEL)selector {
     env.objc.class_has_method(this, selector)
 }
@@ -82,6 +92,9 @@ pub const CLASSES: ClassExports = objc_classes! {
     () = msg_class![env; NSAutoreleasePool addObject:this];
     this
 }
+- (NSUInteger)retainCount {
+    env.objc.get_refcount(this).unwrap_or(NSUInteger::MAX)
+}
 
 - (())dealloc {
     log_dbg!("[{:?} dealloc]", this);
@@ -106,11 +119,20 @@ pub const CLASSES: ClassExports = objc_classes! {
 - (bool)isEqual:(id)other {
     this == other
 }
+// This is from the NSComparisonMethods category. Subclasses override isEqual:
+// rather than this.
+- (bool)isEqualTo:(id)other {
+    msg![env; this isEqual:other]
+}
 
-// TODO: description and debugDescription (both the instance and class method).
-// This is not hard to add, but before adding a fallback implementation of it,
-// we should make sure all the Foundation classes' overrides of it are there,
-// to prevent weird behavior.
+- (id)description {
+    let description = default_description(env, this);
+    let description = ns_string::from_rust_string(env, description);
+    autorelease(env, description)
+}
+- (id)debugDescription {
+    msg![env; this description]
+}
 // TODO: localized description methods also? (not sure if NSObject has them)
 
 // Helper for NSCopying
@@ -125,33 +147,96 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 // NSKeyValueCoding
 - (())setValue:(id)value
-       forKey:(id)key { // NSString*
-    let key = to_rust_string(env, key); // TODO: avoid copy?
+       forKey:(id)key_string { // NSString*
+    let key = to_rust_string(env, key_string); // TODO: avoid copy?
     assert!(key.is_ascii()); // TODO: do we have to handle non-ASCII keys?
 
     let class = msg![env; this class];
-
-    if let Some(sel) = env.objc.lookup_selector(&format!(
-        "set{}{}:",
+    let capitalized_key = format!(
+        "{}{}",
         key.as_bytes()[0].to_ascii_uppercase() as char,
         &key[1..],
-    )) {
+    );
+
+    if let Some(sel) = env.objc.lookup_selector(&format!("set{}:", capitalized_key)) {
         if env.objc.class_has_method(class, sel) {
             return msg_send(env, (this, sel, value));
         }
     }
 
-    if let Some(sel) = env.objc.lookup_selector(&format!(
-        "_set{}{}:",
-        key.as_bytes()[0].to_ascii_uppercase() as char,
-        &key[1..],
-    )) {
+    if let Some(sel) = env.objc.lookup_selector(&format!("_set{}:", capitalized_key)) {
         if env.objc.class_has_method(class, sel) {
             return msg_send(env, (this, sel, value));
         }
     }
 
-    unimplemented!("TODO: object {:?} does not have simple setter method for {}, use fallback", this, key);
+    // TODO: respect accessInstanceVariablesDirectly
+    for ivar_name in [
+        format!("_{}", key),
+        format!("_is{}", capitalized_key),
+        key.to_string(),
+        format!("is{}", capitalized_key),
+    ] {
+        let Some(ivar) = env.objc.lookup_ivar(class, &ivar_name) else {
+            continue;
+        };
+        let (offset, type_) = (this.to_bits() + ivar.offset, ivar.type_.clone());
+        if ivar.is_object() {
+            let ivar: MutPtr<id> = Ptr::from_bits(offset);
+            retain(env, value);
+            let old = env.mem.read(ivar);
+            env.mem.write(ivar, value);
+            release(env, old);
+            return;
+        }
+        if value == nil {
+            // TODO: call setNilValueForKey:, which raises by default
+            log!("Warning: [{:?} setValue:nil forKey:{:?}] for scalar ivar {}, ignoring", this, key, ivar_name);
+            return;
+        }
+        // Scalar ivars are set from the NSNumber's value.
+        // TODO: NSValue for struct ivars, 64-bit integers
+        match type_.as_str() {
+            "c" | "C" | "B" => {
+                let value: i32 = msg![env; value intValue];
+                env.mem.write(Ptr::from_bits(offset), value as u8);
+            }
+            "s" | "S" => {
+                let value: i32 = msg![env; value intValue];
+                env.mem.write(Ptr::from_bits(offset), value as u16);
+            }
+            "i" | "I" | "l" | "L" => {
+                let value: i32 = msg![env; value intValue];
+                env.mem.write(Ptr::from_bits(offset), value);
+            }
+            "f" => {
+                let value: f32 = msg![env; value floatValue];
+                env.mem.write(Ptr::from_bits(offset), value);
+            }
+            "d" => {
+                let value: f64 = msg![env; value doubleValue];
+                env.mem.write(Ptr::from_bits(offset), value);
+            }
+            _ => {
+                log!("TODO: setValue:forKey: for ivar {} of type {}", ivar_name, type_);
+                () = msg![env; this setValue:value forUndefinedKey:key_string];
+            }
+        }
+        return;
+    }
+
+    () = msg![env; this setValue:value forUndefinedKey:key_string];
+}
+
+- (())setValue:(id)value
+forUndefinedKey:(id)key { // NSString*
+    // TODO: raise NSUndefinedKeyException like Apple's implementation does
+    log!(
+        "Warning: {:?} has no setter method or ivar for key {:?}, ignoring [setValue:{:?} forUndefinedKey:]",
+        this,
+        to_rust_string(env, key),
+        value,
+    );
 }
 
 - (bool)respondsToSelector:(SEL)selector {
@@ -178,26 +263,60 @@ pub const CLASSES: ClassExports = objc_classes! {
 }
 
 - (())performSelectorOnMainThread:(SEL)sel withObject:(id)arg waitUntilDone:(bool)wait {
-    // // FIXME: main thread...
-    // () = msg_send(env, (this, sel, arg));
+    assert!(!sel.is_null());
+    log_dbg!("performSelectorOnMainThread:{} withObject:{:?} waitUntilDone:{}", sel.as_str(&env.mem), arg, wait);
+    ns_run_loop::perform_on_main_thread(env, this, sel, arg, wait, true);
+}
 
-    assert!(env.current_thread != 0);
-    log!("performSelectorOnMainThread:{} withObject:{:?} waitUntilDone:{}", sel.as_str(&env.mem), arg, wait);
-    assert!(!wait);
+- (())performSelectorOnMainThread:(SEL)sel
+                       withObject:(id)arg
+                    waitUntilDone:(bool)wait
+                            modes:(id)modes { // NSArray<NSString*>*
+    assert!(!sel.is_null());
+    log_dbg!("performSelectorOnMainThread:{} withObject:{:?} waitUntilDone:{} modes:{:?}", sel.as_str(&env.mem), arg, wait, modes);
+
+    let default_mode = ns_string::get_static_str(env, NSDefaultRunLoopMode);
+    let common_modes = ns_string::get_static_str(env, NSRunLoopCommonModes);
+    let count: NSUInteger = msg![env; modes count];
+    let mut in_default_mode = false;
+    for i in 0..count {
+        let mode: id = msg![env; modes objectAtIndex:i];
+        let is_default: bool = msg![env; mode isEqualToString:default_mode];
+        let is_common: bool = msg![env; mode isEqualToString:common_modes];
+        in_default_mode |= is_default || is_common;
+    }
+    if !in_default_mode {
+        log!("TODO: performSelectorOnMainThread:{} for modes {:?} other than the default mode, the message won't be sent", sel.as_str(&env.mem), modes);
+    }
+    ns_run_loop::perform_on_main_thread(env, this, sel, arg, wait, in_default_mode);
+}
 
+// This is really part of a category declared in NSRunLoop.h.
+- (())performSelector:(SEL)sel withObject:(id)arg afterDelay:(NSTimeInterval)delay {
+    assert!(!sel.is_null());
+    log_dbg!("performSelector:{} withObject:{:?} afterDelay:{}", sel.as_str(&env.mem), arg, delay);
+
+    // The timer retains the receiver and the argument until it fires.
     let sel_key: id = ns_string::get_static_str(env, "SEL");
     let sel_str = ns_string::from_rust_string(env, sel.as_str(&env.mem).to_string());
     let arg_key: id = ns_string::get_static_str(env, "arg");
-    let dict = dict_from_keys_and_objects(env, &[(sel_key, sel_str), (arg_key, arg)]);
+    let dict = if arg == nil {
+        // Dictionaries can't contain nil, but looking up a missing key gives nil.
+        dict_from_keys_and_objects(env, &[(sel_key, sel_str)])
+    } else {
+        dict_from_keys_and_objects(env, &[(sel_key, sel_str), (arg_key, arg)])
+    };
+    release(env, sel_str);
 
     let selector = env.objc.lookup_selector("timerFireMethod:").unwrap();
-    let timer:id = msg_class![env; NSTimer timerWithTimeInterval:0.0
-                                              target:this
-                                            selector:selector
-                                            userInfo:dict
-                                             repeats:false];
-
-    let run_loop: id = msg_class![env; NSRunLoop mainRunLoop];
+    let timer: id = msg_class![env; NSTimer timerWithTimeInterval:delay
+                                                            target:this
+                                                          selector:selector
+                                                          userInfo:dict
+                                                           repeats:false];
+    release(env, dict);
+
+    let run_loop: id = msg_class![env; NSRunLoop currentRunLoop];
     let mode: id = ns_string::get_static_str(env, NSDefaultRunLoopMode);
     let _: () = msg![env; run_loop addTimer:timer forMode:mode];
 }
@@ -219,3 +338,28 @@ pub const CLASSES: ClassExports = objc_classes! {
 @end
 
 };
+
+/// The description `NSObject` gives an object, e.g. `<UIView: 0x1234abcd>`.
+pub fn default_description(env: &mut Environment, object: id) -> String {
+    let class: Class = msg![env; object class];
+    format!(
+        "<{}: {:#x}>",
+        env.objc.get_class_name(class),
+        object.to_bits()
+    )
+}
+
+/// The text `%@` formats an object as: its description, `(null)` for [nil],
+/// or [default_description] if the description is [nil].
+pub fn description_string(env: &mut Environment, object: id) -> String {
+    if object == nil {
+        return String::from("(null)");
+    }
+    // TODO: use localized description if available?
+    let description: id = msg![env; object description];
+    if description == nil {
+        return default_description(env, object);
+    }
+    // TODO: what if the description isn't valid UTF-16?
+    ns_string::to_rust_string(env, description).into_owned()
+}
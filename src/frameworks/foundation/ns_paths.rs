@@ -0,0 +1,125 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `NSPathUtilities.h` functions: `NSHomeDirectory`,
+//! `NSSearchPathForDirectoriesInDomains` etc.
+//!
+//! These return paths in the guest filesystem, so they agree with what the
+//! POSIX functions see. See [crate::fs] for how those map to the host.
+
+use super::{ns_array, ns_string, NSUInteger};
+use crate::dyld::{export_c_func, FunctionExports};
+use crate::fs::{GuestPath, GuestPathBuf};
+use crate::libc::pwd::{MOBILE_USER_FULL_NAME, MOBILE_USER_NAME};
+use crate::objc::{autorelease, id};
+use crate::Environment;
+
+type NSSearchPathDirectory = NSUInteger;
+const NSApplicationDirectory: NSSearchPathDirectory = 1;
+const NSLibraryDirectory: NSSearchPathDirectory = 5;
+const NSDocumentDirectory: NSSearchPathDirectory = 9;
+const NSCachesDirectory: NSSearchPathDirectory = 13;
+const NSApplicationSupportDirectory: NSSearchPathDirectory = 14;
+
+type NSSearchPathDomainMask = NSUInteger;
+const NSUserDomainMask: NSSearchPathDomainMask = 1;
+
+fn NSSearchPathForDirectoriesInDomains(
+    env: &mut Environment,
+    directory: NSSearchPathDirectory,
+    domain_mask: NSSearchPathDomainMask,
+    expand_tilde: bool,
+) -> id {
+    // Apps only have access to their own home directory, so there's nothing
+    // to return for the other domains.
+    if domain_mask & NSUserDomainMask == 0 {
+        log!(
+            "Warning: NSSearchPathForDirectoriesInDomains({}, {:#x}, _) has no user domain, returning empty array",
+            directory,
+            domain_mask
+        );
+        let empty = ns_array::from_vec(env, Vec::new());
+        return autorelease(env, empty);
+    }
+
+    let home = env.fs.home_directory().to_owned();
+    let dir = match directory {
+        // This might not actually be correct. I haven't bothered to test it
+        // because I can't think of a good reason an iPhone OS app would have to
+        // request this; Wolfenstein 3D requests it but never uses it.
+        NSApplicationDirectory => GuestPath::new(crate::fs::APPLICATIONS).to_owned(),
+        NSLibraryDirectory => home.join("Library"),
+        NSDocumentDirectory => home.join("Documents"),
+        NSCachesDirectory => home.join("Library/Caches"),
+        NSApplicationSupportDirectory => home.join("Library/Application Support"),
+        _ => {
+            log!(
+                "Warning: NSSearchPathForDirectoriesInDomains({}, {:#x}, _) has no location for that directory, returning empty array",
+                directory,
+                domain_mask
+            );
+            let empty = ns_array::from_vec(env, Vec::new());
+            return autorelease(env, empty);
+        }
+    };
+
+    // Directories inside Library don't exist until something asks for them.
+    if dir.as_str().starts_with(home.join("Library/").as_str())
+        && env.fs.create_dir_all(&dir).is_err()
+    {
+        log!("Warning: couldn't create directory {:?}", dir);
+    }
+
+    let dir = if expand_tilde {
+        String::from(dir)
+    } else {
+        abbreviate_home(&home, dir)
+    };
+    let dir = ns_string::from_rust_string(env, dir);
+    let dir_list = ns_array::from_vec(env, vec![dir]);
+    autorelease(env, dir_list)
+}
+
+/// Replace the home directory at the start of a path with `~`, the inverse of
+/// `-[NSString stringByExpandingTildeInPath]`.
+fn abbreviate_home(home: &GuestPath, path: GuestPathBuf) -> String {
+    match path.as_str().strip_prefix(home.as_str()) {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => format!("~{}", rest),
+        _ => String::from(path),
+    }
+}
+
+fn NSHomeDirectory(env: &mut Environment) -> id {
+    let dir = env.fs.home_directory();
+    let dir = ns_string::from_rust_string(env, String::from(dir.as_str()));
+    autorelease(env, dir)
+}
+
+fn NSTemporaryDirectory(env: &mut Environment) -> id {
+    // Like TMPDIR and confstr(_CS_DARWIN_USER_TEMP_DIR), this ends in a slash.
+    let dir = format!("{}/", env.fs.temporary_directory().as_str());
+    let dir = ns_string::from_rust_string(env, dir);
+    autorelease(env, dir)
+}
+
+fn NSUserName(env: &mut Environment) -> id {
+    let name = String::from_utf8(MOBILE_USER_NAME.to_vec()).unwrap();
+    let name = ns_string::from_rust_string(env, name);
+    autorelease(env, name)
+}
+
+fn NSFullUserName(env: &mut Environment) -> id {
+    let name = String::from_utf8(MOBILE_USER_FULL_NAME.to_vec()).unwrap();
+    let name = ns_string::from_rust_string(env, name);
+    autorelease(env, name)
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(NSHomeDirectory()),
+    export_c_func!(NSTemporaryDirectory()),
+    export_c_func!(NSSearchPathForDirectoriesInDomains(_, _, _)),
+    export_c_func!(NSUserName()),
+    export_c_func!(NSFullUserName()),
+];
@@ -0,0 +1,80 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `NSPipe`.
+
+use crate::libc::posix_io::pipe::new_pipe;
+use crate::objc::{
+    autorelease, id, msg, msg_class, nil, objc_classes, release, ClassExports, HostObject,
+    NSZonePtr,
+};
+
+struct NSPipeHostObject {
+    /// `NSFileHandle*`
+    read_handle: id,
+    /// `NSFileHandle*`
+    write_handle: id,
+}
+impl HostObject for NSPipeHostObject {}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation NSPipe: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(NSPipeHostObject {
+        read_handle: nil,
+        write_handle: nil,
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
++ (id)pipe {
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new init];
+    autorelease(env, new)
+}
+
+- (id)init {
+    let Some((read_fd, write_fd)) = new_pipe(env) else {
+        release(env, this);
+        return nil;
+    };
+    let read_handle: id = msg_class![env; NSFileHandle alloc];
+    let read_handle: id = msg![env; read_handle initWithFileDescriptor:read_fd
+                                                       closeOnDealloc:true];
+    let write_handle: id = msg_class![env; NSFileHandle alloc];
+    let write_handle: id = msg![env; write_handle initWithFileDescriptor:write_fd
+                                                         closeOnDealloc:true];
+    *env.objc.borrow_mut(this) = NSPipeHostObject {
+        read_handle,
+        write_handle,
+    };
+    this
+}
+
+- (id)fileHandleForReading {
+    env.objc.borrow::<NSPipeHostObject>(this).read_handle
+}
+
+- (id)fileHandleForWriting {
+    env.objc.borrow::<NSPipeHostObject>(this).write_handle
+}
+
+- (())dealloc {
+    let &NSPipeHostObject {
+        read_handle,
+        write_handle,
+    } = env.objc.borrow(this);
+    release(env, read_handle);
+    release(env, write_handle);
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+@end
+
+};
@@ -1,13 +1,60 @@
 //! `NSPropertyListSerialization`.
 
+use super::ns_value::NSNumberHostObject;
 use super::{ns_array, ns_dictionary, ns_string, NSUInteger};
 use crate::fs::GuestPath;
-use crate::mem::MutPtr;
-use crate::objc::{id, msg, msg_class, nil, release};
+use crate::mem::{ConstVoidPtr, MutPtr};
+use crate::objc::{
+    autorelease, id, msg, msg_class, nil, objc_classes, release, Class, ClassExports,
+};
 use crate::Environment;
 use plist::Value;
 use std::io::Cursor;
 
+type NSPropertyListFormat = NSUInteger;
+const NSPropertyListOpenStepFormat: NSPropertyListFormat = 1;
+const NSPropertyListXMLFormat_v1_0: NSPropertyListFormat = 100;
+const NSPropertyListBinaryFormat_v1_0: NSPropertyListFormat = 200;
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation NSPropertyListSerialization: NSObject
+
++ (id)dataFromPropertyList:(id)plist
+                    format:(NSPropertyListFormat)format
+          errorDescription:(MutPtr<id>)error_string { // NSString**
+    match serialize_plist(env, plist, format) {
+        Ok(bytes) => {
+            let length: NSUInteger = bytes.len().try_into().unwrap();
+            let alloc: MutPtr<u8> = env.mem.alloc(length).cast();
+            env.mem.bytes_at_mut(alloc, length).copy_from_slice(&bytes);
+            let data: id = msg_class![env; NSData alloc];
+            let data: id = msg![env; data initWithBytesNoCopy:alloc length:length];
+            autorelease(env, data)
+        }
+        Err(description) => {
+            log!(
+                "[NSPropertyListSerialization dataFromPropertyList:{:?} format:{}] failed: {}",
+                plist,
+                format,
+                description,
+            );
+            if !error_string.is_null() {
+                let description = ns_string::from_rust_string(env, description);
+                let description = autorelease(env, description);
+                env.mem.write(error_string, description);
+            }
+            nil
+        }
+    }
+}
+
+@end
+
+};
+
 // TODO: Implement reading of property lists other than Info.plist.
 // [NSDictionary contentsOfFile:] and [NSArray contentsOfFile:] in particular.
 
@@ -41,7 +88,109 @@ pub(super) fn deserialize_plist_from_file(
     deserialize_plist(env, &root)
 }
 
-fn deserialize_plist(env: &mut Environment, value: &Value) -> id {
+/// Internals of `writeToFile:atomically:` on `NSArray` and `NSDictionary`.
+/// Returns `false` on failure, including if the object isn't a property list.
+pub(super) fn serialize_plist_to_file(env: &mut Environment, plist: id, path: &GuestPath) -> bool {
+    log_dbg!("Writing plist to {:?}.", path);
+    let bytes = match serialize_plist(env, plist, NSPropertyListXMLFormat_v1_0) {
+        Ok(bytes) => bytes,
+        Err(description) => {
+            log!("Couldn't write plist to {:?}: {}", path, description);
+            return false;
+        }
+    };
+    env.fs.write(path, &bytes).is_ok()
+}
+
+/// Serialize an object graph in one of the property list formats, or return a
+/// description of why that isn't possible.
+fn serialize_plist(
+    env: &mut Environment,
+    plist: id,
+    format: NSPropertyListFormat,
+) -> Result<Vec<u8>, String> {
+    let value = to_plist_value(env, plist)
+        .map_err(|description| format!("Property list invalid for format: {}", description))?;
+    let mut bytes = Vec::new();
+    let res = match format {
+        NSPropertyListXMLFormat_v1_0 => value.to_writer_xml(&mut bytes),
+        NSPropertyListBinaryFormat_v1_0 => value.to_writer_binary(&mut bytes),
+        // Apple's implementation can't write this either.
+        NSPropertyListOpenStepFormat => return Err("Can't write OpenStep format".to_string()),
+        _ => return Err(format!("Unknown property list format {}", format)),
+    };
+    res.map_err(|err| err.to_string())?;
+    Ok(bytes)
+}
+
+fn is_kind_of(env: &mut Environment, object: id, class_name: &str) -> bool {
+    let class = env.objc.get_known_class(class_name, &mut env.mem);
+    msg![env; object isKindOfClass:class]
+}
+
+fn to_plist_value(env: &mut Environment, object: id) -> Result<Value, String> {
+    if object == nil {
+        Err("property lists cannot contain nil".to_string())
+    } else if is_kind_of(env, object, "NSString") {
+        Ok(Value::String(
+            ns_string::to_rust_string(env, object).into_owned(),
+        ))
+    } else if is_kind_of(env, object, "NSDecimalNumber") {
+        // This doesn't have an NSNumber host object.
+        let value: f64 = msg![env; object doubleValue];
+        Ok(Value::Real(value))
+    } else if is_kind_of(env, object, "NSNumber") {
+        Ok(match *env.objc.borrow::<NSNumberHostObject>(object) {
+            NSNumberHostObject::Bool(value) => Value::Boolean(value),
+            NSNumberHostObject::Int(value) => Value::Integer(value.into()),
+            NSNumberHostObject::UnsignedLongLong(value) => Value::Integer(value.into()),
+            NSNumberHostObject::LongLong(value) => Value::Integer(value.into()),
+            NSNumberHostObject::Float(value) => Value::Real(value.into()),
+            NSNumberHostObject::Double(value) => Value::Real(value),
+        })
+    } else if is_kind_of(env, object, "NSData") {
+        let length: NSUInteger = msg![env; object length];
+        let bytes: ConstVoidPtr = msg![env; object bytes];
+        // The pointer can be NULL if the length is 0.
+        let bytes = if length == 0 {
+            Vec::new()
+        } else {
+            env.mem.bytes_at(bytes.cast(), length).to_vec()
+        };
+        Ok(Value::Data(bytes))
+    } else if is_kind_of(env, object, "NSArray") {
+        let count: NSUInteger = msg![env; object count];
+        let mut array = Vec::new();
+        for i in 0..count {
+            let item: id = msg![env; object objectAtIndex:i];
+            array.push(to_plist_value(env, item)?);
+        }
+        Ok(Value::Array(array))
+    } else if is_kind_of(env, object, "NSDictionary") {
+        let keys: id = msg![env; object allKeys];
+        let count: NSUInteger = msg![env; keys count];
+        let mut dict = plist::Dictionary::new();
+        for i in 0..count {
+            let key: id = msg![env; keys objectAtIndex:i];
+            if !is_kind_of(env, key, "NSString") {
+                return Err("dictionary keys must be strings".to_string());
+            }
+            let item: id = msg![env; object objectForKey:key];
+            let key = ns_string::to_rust_string(env, key).into_owned();
+            dict.insert(key, to_plist_value(env, item)?);
+        }
+        Ok(Value::Dictionary(dict))
+    } else {
+        // TODO: NSDate
+        let class: Class = msg![env; object class];
+        Err(format!(
+            "property lists cannot contain objects of type '{}'",
+            env.objc.get_class_name(class)
+        ))
+    }
+}
+
+pub(super) fn deserialize_plist(env: &mut Environment, value: &Value) -> id {
     match value {
         Value::Array(array) => {
             let array = array
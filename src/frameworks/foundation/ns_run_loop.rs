@@ -12,11 +12,14 @@ use super::{ns_string, ns_timer, NSTimeInterval};
 use crate::dyld::{ConstantExports, HostConstant};
 use crate::frameworks::audio_toolbox::audio_queue::{handle_audio_queue, AudioQueueRef};
 use crate::frameworks::core_foundation::cf_run_loop::{
-    kCFRunLoopCommonModes, kCFRunLoopDefaultMode, CFRunLoopRef,
+    self, kCFRunLoopCommonModes, kCFRunLoopDefaultMode, CFRunLoopRef,
 };
 use crate::frameworks::{core_animation, media_player, uikit};
-use crate::objc::{id, msg, objc_classes, release, retain, ClassExports, HostObject};
+use crate::objc::{
+    id, msg, msg_send, objc_classes, release, retain, ClassExports, HostObject, SEL,
+};
 use crate::{Environment, msg_class};
+use std::collections::{BTreeMap, VecDeque};
 use std::time::{Duration, Instant};
 
 /// `NSString*`
@@ -45,12 +48,40 @@ struct NSRunLoopHostObject {
     /// Weak reference. Audio queue must remove itself when destroyed (TODO).
     /// They are in no particular order.
     audio_queues: Vec<AudioQueueRef>,
-    /// Strong references to `NSTimer*` in no particular order. Timers are owned
-    /// by the run loop. The timer must remove itself when invalidated.
-    timers: Vec<id>,
+    /// Strong references to `NSTimer*`, ordered by when they are next due to
+    /// fire. The timer's address breaks ties between timers due at the same
+    /// time. Timers are owned by the run loop. The timer must remove itself
+    /// when invalidated.
+    ///
+    /// Some apps have hundreds of timers, so only the ones that are due should
+    /// be looked at on each iteration.
+    timers: BTreeMap<(Instant, u32), id>,
+    /// Strong references to Core Foundation run loop sources (`CFSocket`,
+    /// or a scheduled `CFReadStream`/`CFWriteStream`) in no particular order.
+    /// See [crate::frameworks::core_foundation::cf_run_loop::handle_source].
+    sources: Vec<id>,
+    /// Messages queued by `performSelectorOnMainThread:`, in the order they
+    /// were queued. See [perform_on_main_thread].
+    performs: VecDeque<QueuedPerform>,
+    /// Tickets of the queued messages that are currently being sent. There can
+    /// be more than one if a message runs the run loop re-entrantly.
+    running_performs: Vec<u64>,
+    next_perform_ticket: u64,
 }
 impl HostObject for NSRunLoopHostObject {}
 
+struct QueuedPerform {
+    ticket: u64,
+    /// Strong reference
+    target: id,
+    selector: SEL,
+    /// Strong reference
+    argument: id,
+    /// Whether the message was queued for the default mode or the common
+    /// modes. touchHLE's run loop never runs in any other mode.
+    in_default_mode: bool,
+}
+
 pub const CLASSES: ClassExports = objc_classes! {
 
 (env, this, _cmd);
@@ -63,7 +94,11 @@ pub const CLASSES: ClassExports = objc_classes! {
     } else {
         let host_object = Box::new(NSRunLoopHostObject {
             audio_queues: Vec::new(),
-            timers: Vec::new(),
+            timers: BTreeMap::new(),
+            sources: Vec::new(),
+            performs: VecDeque::new(),
+            running_performs: Vec::new(),
+            next_perform_ticket: 0,
         });
         let new = env.objc.alloc_static_object(this, host_object, &mut env.mem);
         env.framework_state.foundation.ns_run_loop.main_thread_run_loop = Some(new);
@@ -101,16 +136,15 @@ pub const CLASSES: ClassExports = objc_classes! {
         ns_string::to_rust_string(env, mode),
     );
 
-    retain(env, timer);
-
-    let host_object = env.objc.borrow_mut::<NSRunLoopHostObject>(this);
-    assert!(!host_object.timers.contains(&timer)); // TODO: what do we do here?
-    host_object.timers.push(timer);
+    // TODO: what do we do with invalidated timers?
+    let due_by = ns_timer::due_by(env, timer).unwrap();
     ns_timer::set_run_loop(env, timer, this);
+    retain(env, timer);
+    schedule_timer(env, this, timer, due_by);
 }
 
 - (())run {
-    run_run_loop(env, this, /* single_iteration: */ false);
+    run_run_loop(env, this, RunUntil::Forever);
 }
 - (())runMode:(NSRunLoopMode)mode
    beforeDate:(id)limit_date { // NSDate *
@@ -119,7 +153,7 @@ pub const CLASSES: ClassExports = objc_classes! {
     let distant_future: id = msg_class![env; NSDate distantFuture];
     let delta: NSTimeInterval = msg![env; limit_date timeIntervalSinceDate:distant_future];
     assert!(delta < 10.0);
-    run_run_loop(env, this, /* single_iteration: */ false);
+    run_run_loop(env, this, RunUntil::Forever);
 }
 // TODO: other run methods
 
@@ -148,44 +182,244 @@ pub fn remove_audio_queue(env: &mut Environment, run_loop: id, queue: AudioQueue
     queues.remove(queue_idx);
 }
 
-/// For use by NSTimer so it can remove itself once it's invalidated.
-pub(super) fn remove_timer(env: &mut Environment, run_loop: id, timer: id) {
-    let NSRunLoopHostObject { timers, .. } = env.objc.borrow_mut(run_loop);
+/// For use by NSTimer. The run loop takes ownership of the caller's reference
+/// to the timer.
+pub(super) fn schedule_timer(env: &mut Environment, run_loop: id, timer: id, due_by: Instant) {
+    let timers = &mut env.objc.borrow_mut::<NSRunLoopHostObject>(run_loop).timers;
+    let old = timers.insert((due_by, timer.to_bits()), timer);
+    assert!(old.is_none());
+}
+
+/// For use by NSTimer so it can remove itself once it's invalidated. `due_by`
+/// must be the date the timer was scheduled for. A timer that is currently
+/// being fired isn't in the schedule, so this does nothing in that case.
+pub(super) fn remove_timer(env: &mut Environment, run_loop: id, timer: id, due_by: Instant) {
+    let timers = &mut env.objc.borrow_mut::<NSRunLoopHostObject>(run_loop).timers;
+    if timers.remove(&(due_by, timer.to_bits())).is_some() {
+        release(env, timer);
+    }
+}
 
-    let mut i = 0;
-    let mut release_count = 0;
-    while i < timers.len() {
-        if timers[i] == timer {
-            timers.swap_remove(i);
-            release_count += 1;
-        } else {
-            i += 1;
+/// Fire all the timers that are due, and return when the next one is due.
+fn fire_due_timers(env: &mut Environment, run_loop: id) -> Option<Instant> {
+    // Repeating timers are always rescheduled for a time after this, and new
+    // timers can't be due immediately, so this loop always terminates.
+    let now = Instant::now();
+    let mut fired_count = 0;
+    loop {
+        let timers = &mut env.objc.borrow_mut::<NSRunLoopHostObject>(run_loop).timers;
+        let Some(entry) = timers.first_entry() else {
+            break;
+        };
+        if entry.key().0 > now {
+            break;
         }
+        let timer = entry.remove();
+        ns_timer::handle_timer(env, timer);
+        fired_count += 1;
     }
-    assert!(release_count == 1); // TODO?
-    for _ in 0..release_count {
-        release(env, timer);
+
+    let timers = &env.objc.borrow::<NSRunLoopHostObject>(run_loop).timers;
+    if fired_count > 0 {
+        log_dbg!(
+            "Run loop {:?} fired {} timer(s), {} timer(s) still scheduled",
+            run_loop,
+            fired_count,
+            timers.len()
+        );
+    }
+    timers.first_key_value().map(|(&(due_by, _), _)| due_by)
+}
+
+/// For use by Core Foundation. The run loop retains the source. Adding a
+/// source that is already in the run loop has no effect.
+/// TODO: Handle run loop modes. Currently assumes the common modes.
+pub fn add_source(env: &mut Environment, run_loop: id, source: id) {
+    let sources = &mut env.objc.borrow_mut::<NSRunLoopHostObject>(run_loop).sources;
+    if sources.contains(&source) {
+        return;
+    }
+    sources.push(source);
+    retain(env, source);
+}
+
+/// For use by Core Foundation. Removing a source that isn't in the run loop
+/// has no effect.
+pub fn remove_source(env: &mut Environment, run_loop: id, source: id) {
+    let sources = &mut env.objc.borrow_mut::<NSRunLoopHostObject>(run_loop).sources;
+    let Some(source_idx) = sources.iter().position(|&item| item == source) else {
+        return;
+    };
+    sources.swap_remove(source_idx);
+    release(env, source);
+}
+
+/// Whether the run loop has any timers or sources, i.e. whether running it
+/// could do anything other than wait. Messages queued with
+/// [perform_on_main_thread] count as a source, as they do on Apple's platforms.
+pub fn has_timers_or_sources(env: &mut Environment, run_loop: id) -> bool {
+    let host_object = env.objc.borrow::<NSRunLoopHostObject>(run_loop);
+    !host_object.timers.is_empty()
+        || !host_object.sources.is_empty()
+        || !host_object.performs.is_empty()
+}
+
+/// Implementation of `performSelectorOnMainThread:` and friends. The target
+/// and argument are retained until the message has been sent.
+///
+/// The messages are sent by the main thread's run loop, in the order they were
+/// queued, each with its own autorelease pool. If `wait` is set, this doesn't
+/// return until the message has been sent, and if it's also called on the main
+/// thread, the message is sent immediately without involving the run loop.
+pub(super) fn perform_on_main_thread(
+    env: &mut Environment,
+    target: id,
+    selector: SEL,
+    argument: id,
+    wait: bool,
+    in_default_mode: bool,
+) {
+    if wait && env.current_thread == 0 {
+        let _: () = msg_send(env, (target, selector, argument));
+        return;
+    }
+
+    let run_loop: id = msg_class![env; NSRunLoop mainRunLoop];
+    retain(env, target);
+    retain(env, argument);
+    let host_object = env.objc.borrow_mut::<NSRunLoopHostObject>(run_loop);
+    let ticket = host_object.next_perform_ticket;
+    host_object.next_perform_ticket += 1;
+    host_object.performs.push_back(QueuedPerform {
+        ticket,
+        target,
+        selector,
+        argument,
+        in_default_mode,
+    });
+
+    if !wait {
+        return;
+    }
+    if !in_default_mode {
+        log!(
+            "Warning: not waiting for [{:?} {}] on the main thread, since it was queued only for modes the run loop never runs in",
+            target,
+            selector.as_str(&env.mem),
+        );
+        return;
+    }
+    loop {
+        let host_object = env.objc.borrow::<NSRunLoopHostObject>(run_loop);
+        if !host_object.running_performs.contains(&ticket)
+            && !host_object.performs.iter().any(|p| p.ticket == ticket)
+        {
+            break;
+        }
+        env.sleep(Duration::from_millis(1), false);
+    }
+}
+
+/// Send the messages queued with [perform_on_main_thread]. Only messages that
+/// were already queued when this started are sent, so that a message which
+/// queues another one can't keep the run loop busy forever. Returns whether any
+/// messages were sent.
+fn run_queued_performs(env: &mut Environment, run_loop: id) -> bool {
+    let mut to_check = env
+        .objc
+        .borrow::<NSRunLoopHostObject>(run_loop)
+        .performs
+        .len();
+    // Messages for other modes stay in the queue, and are skipped over.
+    let mut skipped = 0;
+    let mut sent_any = false;
+    while to_check > 0 {
+        to_check -= 1;
+        let host_object = env.objc.borrow_mut::<NSRunLoopHostObject>(run_loop);
+        // A message may have run the run loop re-entrantly and emptied it.
+        let Some(perform) = host_object.performs.get(skipped) else {
+            break;
+        };
+        if !perform.in_default_mode {
+            skipped += 1;
+            continue;
+        }
+        let QueuedPerform {
+            ticket,
+            target,
+            selector,
+            argument,
+            ..
+        } = host_object.performs.remove(skipped).unwrap();
+        host_object.running_performs.push(ticket);
+
+        log_dbg!(
+            "Run loop {:?} sending queued message [{:?} {}] with {:?}",
+            run_loop,
+            target,
+            selector.as_str(&env.mem),
+            argument
+        );
+        let pool: id = msg_class![env; NSAutoreleasePool new];
+        let _: () = msg_send(env, (target, selector, argument));
+        release(env, pool);
+        release(env, target);
+        release(env, argument);
+
+        env.objc
+            .borrow_mut::<NSRunLoopHostObject>(run_loop)
+            .running_performs
+            .retain(|&running| running != ticket);
+        sent_any = true;
     }
+    sent_any
+}
+
+/// How long [run_run_loop] should run for.
+pub enum RunUntil {
+    Forever,
+    /// Special mode just for the app picker, since we don't have
+    /// `runMode:beforeDate:` or `runUntilDate:` yet. (TODO: implement those to
+    /// replace this.)
+    SingleIteration,
+    /// For `CFRunLoopRunInMode`.
+    Deadline {
+        deadline: Instant,
+        return_after_source_handled: bool,
+    },
+}
+
+/// Why [run_run_loop] returned.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RunLoopExit {
+    TimedOut,
+    HandledSource,
 }
 
 /// Run the run loop for just a single iteration. This is a special mode just
 /// for the app picker, since we don't have `runMode:beforeDate:` or
 /// `runUntilDate:` yet. (TODO: implement those to replace this.)
 pub fn run_run_loop_single_iteration(env: &mut Environment, run_loop: id) {
-    run_run_loop(env, run_loop, /* single_iteration: */ true)
+    run_run_loop(env, run_loop, RunUntil::SingleIteration);
 }
 
-fn run_run_loop(env: &mut Environment, run_loop: id, single_iteration: bool) {
-    if single_iteration {
-        log_dbg!("Entering run loop {:?} (single iteration)", run_loop);
-    } else {
-        log_dbg!("Entering run loop {:?} (indefinitely)", run_loop);
+pub fn run_run_loop(env: &mut Environment, run_loop: id, until: RunUntil) -> RunLoopExit {
+    match until {
+        RunUntil::Forever => log_dbg!("Entering run loop {:?} (indefinitely)", run_loop),
+        RunUntil::SingleIteration => {
+            log_dbg!("Entering run loop {:?} (single iteration)", run_loop)
+        }
+        RunUntil::Deadline { deadline, .. } => log_dbg!(
+            "Entering run loop {:?} (for {:?})",
+            run_loop,
+            deadline.saturating_duration_since(Instant::now())
+        ),
     }
 
     // Temporary vectors used to track things without needing a reference to the
     // environment or to lock the object. Re-used each iteration for efficiency.
-    let mut timers_tmp = Vec::new();
     let mut audio_queues_tmp = Vec::new();
+    let mut sources_tmp = Vec::new();
 
     fn limit_sleep_time(current: &mut Option<Instant>, new: Option<Instant>) {
         if let Some(new) = new {
@@ -196,25 +430,44 @@ fn run_run_loop(env: &mut Environment, run_loop: id, single_iteration: bool) {
     loop {
         let mut sleep_until = None;
 
-        env.window
-            .as_mut()
-            .expect("NSRunLoop not supported in headless mode")
-            .poll_for_events(&env.options);
+        // There are no events or windows in headless mode, but timers and
+        // sources still work, which lets the integration tests use them.
+        if let Some(window) = env.window.as_mut() {
+            window.poll_for_events(&env.options);
 
-        let next_due = uikit::handle_events(env);
-        limit_sleep_time(&mut sleep_until, next_due);
+            let next_due = uikit::handle_events(env);
+            limit_sleep_time(&mut sleep_until, next_due);
+        }
 
-        let next_due = core_animation::recomposite_if_necessary(env);
+        // Timers are fired before compositing, so that a frame drawn by a
+        // timer (the usual way to drive a game loop) is shown straight away
+        // rather than an iteration later.
+        let next_due = fire_due_timers(env, run_loop);
         limit_sleep_time(&mut sleep_until, next_due);
 
-        assert!(timers_tmp.is_empty());
-        timers_tmp.extend_from_slice(&env.objc.borrow::<NSRunLoopHostObject>(run_loop).timers);
-
-        for timer in timers_tmp.drain(..) {
-            let next_due = ns_timer::handle_timer(env, timer);
+        if env.window.is_some() {
+            let next_due = core_animation::recomposite_if_necessary(env);
             limit_sleep_time(&mut sleep_until, next_due);
         }
 
+        let mut handled_source = run_queued_performs(env, run_loop);
+
+        assert!(sources_tmp.is_empty());
+        sources_tmp.extend_from_slice(&env.objc.borrow::<NSRunLoopHostObject>(run_loop).sources);
+
+        for source in sources_tmp.drain(..) {
+            // A callback may have removed (and freed) a later source.
+            if !env
+                .objc
+                .borrow::<NSRunLoopHostObject>(run_loop)
+                .sources
+                .contains(&source)
+            {
+                continue;
+            }
+            handled_source |= cf_run_loop::handle_source(env, source);
+        }
+
         assert!(audio_queues_tmp.is_empty());
         audio_queues_tmp.extend_from_slice(
             &env.objc
@@ -228,6 +481,16 @@ fn run_run_loop(env: &mut Environment, run_loop: id, single_iteration: bool) {
 
         media_player::handle_players(env);
 
+        if let RunUntil::Deadline {
+            return_after_source_handled: true,
+            ..
+        } = until
+        {
+            if handled_source {
+                return RunLoopExit::HandledSource;
+            }
+        }
+
         // Unfortunately, touchHLE has to poll for certain things repeatedly;
         // it can't just wait until the next event appears.
         //
@@ -237,19 +500,25 @@ fn run_run_loop(env: &mut Environment, run_loop: id, single_iteration: bool) {
         // the next scheduled event (e.g. the next timer), but this would lead
         // to late handling of unscheduled events (e.g. a finger movement) and
         // events that are scheduled but we can't get the time for currently
-        // (audio queue buffer exhaustion).
+        // (audio queue buffer exhaustion, socket readiness).
         //
         // The compromise used here is that we will wait for a 60th of a second,
         // or until the next scheduled event, whichever is sooner. iPhone OS
         // apps can't do more than 60fps so this should be fine.
         let limit = Duration::from_millis(1000 / 60);
+        if let RunUntil::Deadline { deadline, .. } = until {
+            if Instant::now() >= deadline {
+                return RunLoopExit::TimedOut;
+            }
+            limit_sleep_time(&mut sleep_until, Some(deadline));
+        }
         env.sleep(
             sleep_until.map_or(limit, |i| i.duration_since(Instant::now()).min(limit)),
             false,
         );
 
-        if single_iteration {
-            break;
+        if let RunUntil::SingleIteration = until {
+            return RunLoopExit::TimedOut;
         }
     }
 }
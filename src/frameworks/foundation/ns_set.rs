@@ -5,15 +5,15 @@
  */
 //! The `NSSet` class cluster, including `NSMutableSet` and `NSCountedSet`.
 
-use super::ns_array;
 use super::ns_dictionary::DictionaryHostObject;
 use super::ns_enumerator::NSFastEnumerationState;
-use super::NSUInteger;
+use super::{ns_array, NSUInteger};
 use crate::mem::MutPtr;
 use crate::objc::{
     autorelease, id, msg, msg_class, nil, objc_classes, retain, ClassExports, HostObject, NSZonePtr,
 };
 use crate::Environment;
+use std::fmt::Write;
 
 struct ObjectEnumeratorHostObject {
     iterator: std::vec::IntoIter<id>,
@@ -58,6 +58,31 @@ pub const CLASSES: ClassExports = objc_classes! {
     retain(env, this)
 }
 
+// NSMutableCopying implementation
+- (id)mutableCopyWithZone:(NSZonePtr)_zone {
+    shallow_copy(env, this, "NSMutableSet")
+}
+
+- (id)description {
+    msg![env; this descriptionWithLocale:nil indent:(0 as NSUInteger)]
+}
+- (id)descriptionWithLocale:(id)_locale // NSLocale*
+                     indent:(NSUInteger)level {
+    super::describe_collection(env, this, |env| {
+        let objects: Vec<id> = env.objc.borrow::<SetHostObject>(this).dict.iter_keys().collect();
+        let indent = "    ".repeat(level as usize);
+        let mut res = format!("{}{{(\n", indent);
+        let count = objects.len();
+        for (i, object) in objects.into_iter().enumerate() {
+            let description = super::element_description(env, object, level + 1);
+            let separator = if i + 1 < count { "," } else { "" };
+            writeln!(res, "{}    {}{}", indent, description, separator).unwrap();
+        }
+        write!(res, "{})}}", indent).unwrap();
+        res
+    })
+}
+
 @end
 
 // NSMutableSet is an abstract class. A subclass must provide everything
@@ -77,7 +102,7 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 // NSCopying implementation
 - (id)copyWithZone:(NSZonePtr)_zone {
-    todo!(); // TODO: this should produce an immutable copy
+    shallow_copy(env, this, "NSSet")
 }
 
 @end
@@ -224,6 +249,20 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 };
 
+/// Shared implementation of `copyWithZone:` and `mutableCopyWithZone:`: a new
+/// instance of `class_name` with the same objects, which are retained rather
+/// than copied.
+fn shallow_copy(env: &mut Environment, set: id, class_name: &str) -> id {
+    let host_obj: SetHostObject = std::mem::take(env.objc.borrow_mut(set));
+    let dict = host_obj.dict.clone_retained(env);
+    *env.objc.borrow_mut(set) = host_obj;
+
+    let class = env.objc.get_known_class(class_name, &mut env.mem);
+    let new: id = msg![env; class alloc];
+    env.objc.borrow_mut::<SetHostObject>(new).dict = dict;
+    new
+}
+
 fn fast_enumeration_helper(
     env: &mut Environment,
     set: id,
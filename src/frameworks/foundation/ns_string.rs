@@ -8,9 +8,10 @@
 //! Resources:
 //! - Apple's [String Programming Guide](https://developer.apple.com/library/archive/documentation/Cocoa/Conceptual/Strings/introStrings.html)
 
-mod path_algorithms;
+mod comparison;
+pub mod path_algorithms;
 
-use super::ns_array;
+use super::{ns_array, ns_unarchiver};
 use super::{
     NSComparisonResult, NSNotFound, NSOrderedAscending, NSOrderedDescending, NSOrderedSame,
     NSRange, NSUInteger,
@@ -30,7 +31,6 @@ use crate::objc::{
 use crate::Environment;
 use std::borrow::Cow;
 use std::collections::HashMap;
-use std::iter::Peekable;
 use std::string::FromUtf16Error;
 
 pub type NSStringEncoding = NSUInteger;
@@ -42,8 +42,12 @@ pub const NSUTF16BigEndianStringEncoding: NSUInteger = 0x90000100;
 pub const NSUTF16LittleEndianStringEncoding: NSUInteger = 0x94000100;
 
 pub type NSStringCompareOptions = NSUInteger;
+pub const NSCaseInsensitiveSearch: NSUInteger = 1;
 pub const NSLiteralSearch: NSUInteger = 2;
 pub const NSNumericSearch: NSUInteger = 64;
+pub const NSDiacriticInsensitiveSearch: NSUInteger = 128;
+pub const NSWidthInsensitiveSearch: NSUInteger = 256;
+pub const NSForcedOrderingSearch: NSUInteger = 512;
 
 /// Encodings that C strings (null-terminated byte strings) can use.
 const C_STRING_FRIENDLY_ENCODINGS: &[NSStringEncoding] =
@@ -54,6 +58,10 @@ pub const NSMaximumStringLength: NSUInteger = (i32::MAX - 1) as _;
 #[derive(Default)]
 pub struct State {
     static_str_pool: HashMap<&'static str, id>,
+    /// Hashes of immutable strings, which are often dictionary keys, so that
+    /// they aren't recomputed on every lookup. Entries are removed when the
+    /// string is deallocated.
+    hash_cache: HashMap<id, NSUInteger>,
 }
 impl State {
     fn get(env: &mut Environment) -> &mut Self {
@@ -252,7 +260,7 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 + (id)string {
     let null: NSZonePtr = MutPtr::null();
-    msg_class![env; NSString allocWithZone:null]
+    msg![env; this allocWithZone:null]
 }
 
 + (id)allocWithZone:(NSZonePtr)zone {
@@ -377,7 +385,7 @@ pub const CLASSES: ClassExports = objc_classes! {
     // TODO: avoid copying
     super::hash_helper(&to_rust_string(env, this))
 }
-- (bool)isEqualTo:(id)other {
+- (bool)isEqual:(id)other {
     if this == other {
         return true;
     }
@@ -401,80 +409,77 @@ pub const CLASSES: ClassExports = objc_classes! {
 }
 
 - (NSComparisonResult)compare:(id)other options:(NSStringCompareOptions)mask { // NSString*
-    fn ascii_number(iter: &mut Peekable<CodeUnitIterator>, leftmost_digit: char) -> u32 {
-        let mut num = leftmost_digit.to_digit(10).unwrap();
-        while let Some(a_digit_char) = iter.next_if(
-            |&x| char::from_u32(x as u32).map_or(false, |y| y.is_ascii_digit())
-        ) {
-            num = num * 10 + char::from_u32(a_digit_char as u32).unwrap().to_digit(10).unwrap();
-        }
-        num
-    }
+    let length: NSUInteger = msg![env; this length];
+    let range = NSRange { location: 0, length };
+    msg![env; this compare:other options:mask range:range]
+}
 
+- (NSComparisonResult)compare:(id)other // NSString*
+                      options:(NSStringCompareOptions)mask
+                        range:(NSRange)range {
     // TODO: support foreign subclasses (perhaps via a helper function that
     // copies the string first)
-    let mut a_iter = env.objc.borrow::<StringHostObject>(this).iter_code_units().peekable();
-    let mut b_iter = env.objc.borrow::<StringHostObject>(other).iter_code_units().peekable();
-    // TODO: OR'ing of compare options
-    match mask {
-        NSLiteralSearch => {
-            from_rust_ordering(a_iter.cmp(b_iter))
-        },
-        NSNumericSearch => {
-            loop {
-                let a_next = a_iter.next();
-                let b_next = b_iter.next();
-                let (Some(a_unit), Some(b_unit)) = (a_next, b_next) else {
-                    return from_rust_ordering(a_next.cmp(&b_next));
-                };
-                let (Some(a_c), Some(b_c)) = (char::from_u32(a_unit as u32), char::from_u32(b_unit as u32)) else {
-                    panic!("Invalid chars in the strings!");
-                };
+    let a: Vec<u16> = env.objc.borrow::<StringHostObject>(this).iter_code_units().collect();
+    let b: Vec<u16> = env.objc.borrow::<StringHostObject>(other).iter_code_units().collect();
+    // TODO: raise NSRangeException instead of panicking?
+    let start = range.location as usize;
+    let end = start.checked_add(range.length as usize).filter(|&end| end <= a.len());
+    let Some(end) = end else {
+        panic!("Range {:?} out of bounds for string of length {}", range, a.len());
+    };
+    from_rust_ordering(comparison::compare(&a[start..end], &b, mask))
+}
 
-                if a_c.is_ascii_digit() && b_c.is_ascii_digit() {
-                    let a_int = ascii_number(&mut a_iter, a_c);
-                    let b_int = ascii_number(&mut b_iter, b_c);
+- (NSComparisonResult)caseInsensitiveCompare:(id)other { // NSString*
+    msg![env; this compare:other options:NSCaseInsensitiveSearch]
+}
 
-                    let numeric_order = a_int.cmp(&b_int);
-                    if numeric_order != std::cmp::Ordering::Equal {
-                        return from_rust_ordering(numeric_order);
-                    }
-                } else {
-                    let char_order = a_c.cmp(&b_c);
-                    if char_order != std::cmp::Ordering::Equal {
-                        return from_rust_ordering(char_order);
-                    }
-                }
-            }
-        },
-        _ => unimplemented!("Other masks"),
-    }
+// TODO: locale-aware collation. These give an ordinal ordering instead, e.g.
+// "Z" sorts before "a" with localizedCompare:.
+- (NSComparisonResult)localizedCompare:(id)other { // NSString*
+    let options: NSStringCompareOptions = 0;
+    msg![env; this compare:other options:options]
+}
+- (NSComparisonResult)localizedCaseInsensitiveCompare:(id)other { // NSString*
+    msg![env; this compare:other options:NSCaseInsensitiveSearch]
 }
 
 // NSCopying implementation
 - (id)copyWithZone:(NSZonePtr)_zone {
-    // TODO: override this once we have NSMutableString!
     retain(env, this)
 }
 
+// NSMutableCopying implementation
+- (id)mutableCopyWithZone:(NSZonePtr)_zone {
+    let new: id = msg_class![env; NSMutableString alloc];
+    msg![env; new initWithString:this]
+}
+
 - (bool)getCString:(MutPtr<u8>)buffer
          maxLength:(NSUInteger)buffer_size
           encoding:(NSStringEncoding)encoding {
-    // TODO: other encodings
-    assert!(encoding == NSUTF8StringEncoding || encoding == NSASCIIStringEncoding);
-
     let src = to_rust_string(env, this);
-    if encoding == NSASCIIStringEncoding {
-        assert!(src.as_bytes().iter().all(|byte| byte.is_ascii()));
+    match encoding {
+        NSUTF8StringEncoding => (),
+        NSASCIIStringEncoding => {
+            if !src.is_ascii() {
+                return false;
+            }
+        },
+        // TODO: other encodings
+        _ => unimplemented!("getCString with encoding {:#x}", encoding),
     }
-    let dest = env.mem.bytes_at_mut(buffer, buffer_size);
-    if dest.len() < src.as_bytes().len() + 1 { // include null terminator
+
+    // Apps probe with small buffers and expect to get NO back, so the size
+    // check has to come before touching the buffer, which might be NULL.
+    let len = src.len();
+    if len + 1 > buffer_size as usize { // include null terminator
         return false;
     }
 
-    for (i, &byte) in src.as_bytes().iter().chain(b"\0".iter()).enumerate() {
-        dest[i] = byte;
-    }
+    let dest = env.mem.bytes_at_mut(buffer, (len + 1).try_into().unwrap());
+    dest[..len].copy_from_slice(src.as_bytes());
+    dest[len] = b'\0';
 
     true
 }
@@ -883,6 +888,21 @@ pub const CLASSES: ClassExports = objc_classes! {
     env.objc.alloc_object(this, host_object, &mut env.mem)
 }
 
+- (())dealloc {
+    State::get(env).hash_cache.remove(&this);
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+// NSMutableString overrides this, since the content can't change otherwise.
+- (NSUInteger)hash {
+    if let Some(&hash) = State::get(env).hash_cache.get(&this) {
+        return hash;
+    }
+    let hash = super::hash_helper(&to_rust_string(env, this));
+    State::get(env).hash_cache.insert(this, hash);
+    hash
+}
+
 // TODO: more init methods
 
 - (id)initWithFormat:(id)format, // NSString*
@@ -920,6 +940,18 @@ pub const CLASSES: ClassExports = objc_classes! {
     msg![env; this initWithBytes:bytes length:len encoding:encoding]
 }
 
+// NSCoding implementation
+- (id)initWithCoder:(id)coder {
+    // TODO: keyed archives
+    assert!(!msg![env; coder allowsKeyedCoding]);
+
+    // NSArchiver writes the string as UTF-8 bytes.
+    let bytes = ns_unarchiver::decode_bytes(env, coder);
+    let host_object = StringHostObject::decode(Cow::Owned(bytes), NSUTF8StringEncoding);
+    *env.objc.borrow_mut(this) = host_object;
+    this
+}
+
 - (id)initWithString:(id)string { // NSString *
     // TODO: optimize for more common cases (or maybe just call copy?)
     let mut code_units = Vec::new();
@@ -974,15 +1006,30 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 @implementation NSMutableString: _touchHLE_NSString
 
-+ (id)allocWithZone:(NSZonePtr)zone {
++ (id)allocWithZone:(NSZonePtr)_zone {
+    // The instance must really be an NSMutableString, so that copying it can
+    // tell it apart from an immutable string.
     assert!(this == env.objc.get_known_class("NSMutableString", &mut env.mem));
-    msg_class![env; _touchHLE_NSString allocWithZone:zone]
+    let host_object = Box::new(StringHostObject::Utf8(Cow::Borrowed("")));
+    env.objc.alloc_object(this, host_object, &mut env.mem)
 }
 
 + (id)stringWithCapacity:(NSUInteger)_capacity {
     msg_class![env; NSMutableString string]
 }
 
+- (NSUInteger)hash {
+    // TODO: avoid copying
+    super::hash_helper(&to_rust_string(env, this))
+}
+
+// NSCopying implementation
+- (id)copyWithZone:(NSZonePtr)_zone {
+    // The copy must not change when the receiver does.
+    let new: id = msg_class![env; NSString alloc];
+    msg![env; new initWithString:this]
+}
+
 - (())setString:(id)aString { // NSString*
     let str = to_rust_string(env, aString);
     let host_object = StringHostObject::Utf8(str);
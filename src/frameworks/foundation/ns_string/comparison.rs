@@ -0,0 +1,330 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! The string comparison algorithm behind `compare:options:range:` and
+//! friends.
+//!
+//! Strings are compared by UTF-16 code unit after folding each character
+//! according to the options, which matches Apple's behavior for the common
+//! cases. There's no locale support and no Unicode normalization data, so
+//! `localizedCompare:` is only an approximation, and canonically equivalent
+//! strings (e.g. `é` precomposed or with a combining accent) are only equal
+//! when diacritics are ignored.
+
+use super::{
+    NSCaseInsensitiveSearch, NSDiacriticInsensitiveSearch, NSForcedOrderingSearch, NSNumericSearch,
+    NSStringCompareOptions, NSWidthInsensitiveSearch,
+};
+use std::cmp::Ordering;
+
+/// Precomposed Latin letters and the letters they're based on, for
+/// `NSDiacriticInsensitiveSearch`. This covers Latin-1 Supplement and Latin
+/// Extended-A, which is what the apps we've seen use. Letters like `Ø` and `Ł`
+/// have no canonical decomposition, so they aren't folded (Apple agrees).
+const DECOMPOSITIONS: &[(char, &str)] = &[
+    ('A', "ÀÁÂÃÄÅĀĂĄ"),
+    ('a', "àáâãäåāăą"),
+    ('C', "ÇĆĈĊČ"),
+    ('c', "çćĉċč"),
+    ('D', "Ď"),
+    ('d', "ď"),
+    ('E', "ÈÉÊËĒĔĖĘĚ"),
+    ('e', "èéêëēĕėęě"),
+    ('G', "ĜĞĠĢ"),
+    ('g', "ĝğġģ"),
+    ('H', "Ĥ"),
+    ('h', "ĥ"),
+    ('I', "ÌÍÎÏĨĪĬĮİ"),
+    ('i', "ìíîïĩīĭį"),
+    ('J', "Ĵ"),
+    ('j', "ĵ"),
+    ('K', "Ķ"),
+    ('k', "ķ"),
+    ('L', "ĹĻĽ"),
+    ('l', "ĺļľ"),
+    ('N', "ÑŃŅŇ"),
+    ('n', "ñńņň"),
+    ('O', "ÒÓÔÕÖŌŎŐ"),
+    ('o', "òóôõöōŏő"),
+    ('R', "ŔŖŘ"),
+    ('r', "ŕŗř"),
+    ('S', "ŚŜŞŠ"),
+    ('s', "śŝşš"),
+    ('T', "ŢŤ"),
+    ('t', "ţť"),
+    ('U', "ÙÚÛÜŨŪŬŮŰŲ"),
+    ('u', "ùúûüũūŭůűų"),
+    ('W', "Ŵ"),
+    ('w', "ŵ"),
+    ('Y', "ÝŶŸ"),
+    ('y', "ýÿŷ"),
+    ('Z', "ŹŻŽ"),
+    ('z', "źżž"),
+];
+
+/// Lowercase characters whose simple case folding isn't themselves, i.e. the
+/// places where folding differs from [char::to_lowercase].
+const EXTRA_CASE_FOLDINGS: &[(char, char)] = &[
+    ('µ', 'μ'),
+    ('ſ', 's'),
+    ('\u{345}', 'ι'),
+    ('ς', 'σ'),
+    ('ϐ', 'β'),
+    ('ϑ', 'θ'),
+    ('ϕ', 'φ'),
+    ('ϖ', 'π'),
+    ('ϰ', 'κ'),
+    ('ϱ', 'ρ'),
+    ('ϵ', 'ε'),
+    ('ẛ', 'ṡ'),
+    ('\u{1fbe}', 'ι'),
+];
+
+fn is_combining_mark(c: char) -> bool {
+    matches!(
+        c,
+        '\u{300}'..='\u{36f}'
+            | '\u{1ab0}'..='\u{1aff}'
+            | '\u{1dc0}'..='\u{1dff}'
+            | '\u{20d0}'..='\u{20ff}'
+            | '\u{fe20}'..='\u{fe2f}'
+    )
+}
+
+fn fold_width(c: char) -> char {
+    // TODO: halfwidth katakana and Hangul
+    match c {
+        '\u{3000}' => ' ',
+        '\u{ff01}'..='\u{ff5e}' => char::from_u32(c as u32 - 0xfee0).unwrap(),
+        '\u{ffe0}' => '¢',
+        '\u{ffe1}' => '£',
+        '\u{ffe2}' => '¬',
+        '\u{ffe3}' => '¯',
+        '\u{ffe4}' => '¦',
+        '\u{ffe5}' => '¥',
+        '\u{ffe6}' => '₩',
+        _ => c,
+    }
+}
+
+fn fold_diacritics(c: char) -> char {
+    DECOMPOSITIONS
+        .iter()
+        .find(|(_, precomposed)| precomposed.contains(c))
+        .map_or(c, |&(base, _)| base)
+}
+
+fn fold_case(c: char) -> char {
+    if let Some(&(_, folded)) = EXTRA_CASE_FOLDINGS.iter().find(|&&(from, _)| from == c) {
+        return folded;
+    }
+    // Characters that lowercase to several (e.g. `İ`) have no simple folding.
+    let mut lower = c.to_lowercase();
+    match (lower.next(), lower.next()) {
+        (Some(lower), None) => lower,
+        _ => c,
+    }
+}
+
+/// Decode a string and fold its characters according to `options`. Unpaired
+/// surrogates are kept as they are, which is why this doesn't produce [char]s.
+fn fold(string: &[u16], options: NSStringCompareOptions) -> Vec<u32> {
+    char::decode_utf16(string.iter().copied())
+        .filter_map(|c| {
+            let Ok(mut c) = c else {
+                return Some(c.unwrap_err().unpaired_surrogate().into());
+            };
+            if options & NSWidthInsensitiveSearch != 0 {
+                c = fold_width(c);
+            }
+            if options & NSDiacriticInsensitiveSearch != 0 {
+                if is_combining_mark(c) {
+                    return None;
+                }
+                c = fold_diacritics(c);
+            }
+            if options & NSCaseInsensitiveSearch != 0 {
+                c = fold_case(c);
+            }
+            Some(c.into())
+        })
+        .collect()
+}
+
+/// Get a key for comparing a folded character by its UTF-16 encoding, so that
+/// supplementary characters sort before U+E000 and above, like on Apple's side.
+fn utf16_key(c: u32) -> (u16, u16) {
+    match char::from_u32(c) {
+        Some(c) if c.len_utf16() == 2 => {
+            let mut units = [0; 2];
+            c.encode_utf16(&mut units);
+            (units[0], units[1])
+        }
+        _ => (c as u16, 0),
+    }
+}
+
+fn is_digit(c: u32) -> bool {
+    (b'0'.into()..=b'9'.into()).contains(&c)
+}
+
+/// `NSNumericSearch` comparison: runs of digits compare by their numeric value,
+/// which can be arbitrarily large. If two strings differ only in the leading
+/// zeros of some number, the one with more zeros in the first such number sorts
+/// first, which is the same way round as a literal comparison would have it.
+fn compare_numeric(a: &[u32], b: &[u32]) -> Ordering {
+    fn digit_run(string: &[u32]) -> &[u32] {
+        let len = string.iter().take_while(|&&c| is_digit(c)).count();
+        &string[..len]
+    }
+    fn strip_leading_zeros(run: &[u32]) -> &[u32] {
+        let zeros = run.iter().take_while(|&&c| c == b'0'.into()).count();
+        &run[zeros..]
+    }
+
+    let mut tie_break = Ordering::Equal;
+    let (mut a, mut b) = (a, b);
+    loop {
+        match (a.first(), b.first()) {
+            (Some(&a_c), Some(&b_c)) if is_digit(a_c) && is_digit(b_c) => {
+                let a_run = digit_run(a);
+                let b_run = digit_run(b);
+                let a_value = strip_leading_zeros(a_run);
+                let b_value = strip_leading_zeros(b_run);
+                // Without leading zeros, a longer run is always a bigger
+                // number, and runs of the same length compare digit-wise.
+                let order = a_value
+                    .len()
+                    .cmp(&b_value.len())
+                    .then_with(|| a_value.cmp(b_value));
+                if order != Ordering::Equal {
+                    return order;
+                }
+                if tie_break == Ordering::Equal {
+                    tie_break = b_run.len().cmp(&a_run.len());
+                }
+                a = &a[a_run.len()..];
+                b = &b[b_run.len()..];
+            }
+            (Some(&a_c), Some(&b_c)) => {
+                let order = utf16_key(a_c).cmp(&utf16_key(b_c));
+                if order != Ordering::Equal {
+                    return order;
+                }
+                a = &a[1..];
+                b = &b[1..];
+            }
+            (a_c, b_c) => return a_c.is_some().cmp(&b_c.is_some()).then(tie_break),
+        }
+    }
+}
+
+/// Compare two UTF-16 strings with `NSStringCompareOptions`.
+/// `NSLiteralSearch` makes no difference, because canonically equivalent
+/// sequences aren't recognized either way.
+pub fn compare(a: &[u16], b: &[u16], options: NSStringCompareOptions) -> Ordering {
+    let a_folded = fold(a, options);
+    let b_folded = fold(b, options);
+    let order = if options & NSNumericSearch != 0 {
+        compare_numeric(&a_folded, &b_folded)
+    } else {
+        let a_keys = a_folded.iter().map(|&c| utf16_key(c));
+        let b_keys = b_folded.iter().map(|&c| utf16_key(c));
+        a_keys.cmp(b_keys)
+    };
+    if order == Ordering::Equal && options & NSForcedOrderingSearch != 0 {
+        a.cmp(b)
+    } else {
+        order
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::NSLiteralSearch;
+    use super::*;
+    use std::cmp::Ordering::*;
+
+    fn utf16(string: &str) -> Vec<u16> {
+        string.encode_utf16().collect()
+    }
+
+    #[test]
+    fn test_numeric_ordering() {
+        const CASE_NUMERIC: NSStringCompareOptions = NSCaseInsensitiveSearch | NSNumericSearch;
+        let cases = [
+            ("a9", "a10", NSLiteralSearch, Greater),
+            ("a9", "A9", NSLiteralSearch, Greater),
+            ("A9", "a10", NSLiteralSearch, Less),
+            ("a9", "a10", NSNumericSearch, Less),
+            ("a9", "A9", NSNumericSearch, Greater),
+            ("A9", "a10", NSNumericSearch, Less),
+            ("a9", "a10", NSCaseInsensitiveSearch, Greater),
+            ("a9", "A9", NSCaseInsensitiveSearch, Equal),
+            ("A9", "a10", NSCaseInsensitiveSearch, Greater),
+            ("a9", "a10", CASE_NUMERIC, Less),
+            ("a9", "A9", CASE_NUMERIC, Equal),
+            ("A9", "a10", CASE_NUMERIC, Less),
+            ("a9", "A9", CASE_NUMERIC | NSForcedOrderingSearch, Greater),
+            ("level2", "level10", CASE_NUMERIC, Less),
+            ("a09", "a9", NSNumericSearch, Less),
+            ("a09", "a9b", NSNumericSearch, Less),
+            ("a9z", "a09", NSNumericSearch, Greater),
+            (
+                "99999999999999999999",
+                "100000000000000000000",
+                NSNumericSearch,
+                Less,
+            ),
+        ];
+        for (a, b, options, expected) in cases {
+            assert_eq!(
+                compare(&utf16(a), &utf16(b), options),
+                expected,
+                "compare({:?}, {:?}, {:#x})",
+                a,
+                b,
+                options
+            );
+        }
+    }
+
+    #[test]
+    fn test_folding() {
+        let cases = [
+            ("résumé", "resume", 0, Greater),
+            ("résumé", "resume", NSDiacriticInsensitiveSearch, Equal),
+            (
+                "re\u{301}sume\u{301}",
+                "résumé",
+                NSDiacriticInsensitiveSearch,
+                Equal,
+            ),
+            ("ÉCOLE", "école", NSCaseInsensitiveSearch, Equal),
+            ("ÉCOLE", "ecole", NSCaseInsensitiveSearch, Greater),
+            (
+                "ÉCOLE",
+                "ecole",
+                NSCaseInsensitiveSearch | NSDiacriticInsensitiveSearch,
+                Equal,
+            ),
+            ("ΣΟΦΟΣ", "σοφος", NSCaseInsensitiveSearch, Equal),
+            ("Ａｂｃ１", "Abc1", 0, Greater),
+            ("Ａｂｃ１", "Abc1", NSWidthInsensitiveSearch, Equal),
+            ("Ａｂｃ１", "abc1", NSWidthInsensitiveSearch, Less),
+            ("\u{10000}", "\u{e000}", 0, Less),
+        ];
+        for (a, b, options, expected) in cases {
+            assert_eq!(
+                compare(&utf16(a), &utf16(b), options),
+                expected,
+                "compare({:?}, {:?}, {:#x})",
+                a,
+                b,
+                options
+            );
+        }
+    }
+}
@@ -13,6 +13,9 @@
 //! The examples in Apple's documentation for the corresponding NSString methods
 //! are a useful reference for figuring out how the algorithm should work, and
 //! as a source of inspiration for test cases.
+//!
+//! `basename()` and `dirname()` are also built on these (see
+//! [crate::libc::libgen]), so they agree with NSString where they overlap.
 
 pub fn trim_trailing_slashes(path: &str) -> &str {
     let without_trailing_slashes = path.trim_end_matches('/');
@@ -31,6 +34,7 @@ pub fn split_last_path_component(path: &str) -> (&str, &str) {
     if path == "/" {
         ("/", "/")
     } else if let Some((rest, last_path_component)) = path.rsplit_once('/') {
+        let rest = trim_trailing_slashes(rest);
         let rest = if rest.is_empty() && path.starts_with('/') {
             "/"
         } else {
@@ -106,6 +110,8 @@ mod tests {
         assert_eq!(string_by_deleting_last_path_component("/a/b///"), "/a");
         assert_eq!(string_by_deleting_last_path_component("/a/"), "/");
         assert_eq!(string_by_deleting_last_path_component("/a"), "/");
+        assert_eq!(string_by_deleting_last_path_component("/a//b"), "/a");
+        assert_eq!(string_by_deleting_last_path_component("//a"), "/");
         assert_eq!(string_by_deleting_last_path_component("/"), "/");
         assert_eq!(string_by_deleting_last_path_component("a"), "");
 
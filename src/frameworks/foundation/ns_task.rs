@@ -0,0 +1,120 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `NSTask`.
+//!
+//! This isn't public API on iPhone OS, but it's still there, and some apps
+//! look it up to check whether they're running on a jailbroken device. Tasks
+//! can be configured, but launching one never starts anything, like it would
+//! in the sandbox. See also [crate::libc::process].
+
+use super::ns_string::to_rust_string;
+use crate::objc::{
+    autorelease, id, msg, nil, objc_classes, release, ClassExports, HostObject, NSZonePtr,
+};
+use crate::Environment;
+
+struct NSTaskHostObject {
+    /// `NSString*`
+    launch_path: id,
+    /// `NSArray*` of `NSString*`
+    arguments: id,
+}
+impl HostObject for NSTaskHostObject {}
+
+fn string_or_nil(env: &mut Environment, string: id) -> String {
+    if string == nil {
+        "(nil)".to_string()
+    } else {
+        to_rust_string(env, string).into_owned()
+    }
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation NSTask: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(NSTaskHostObject {
+        launch_path: nil,
+        arguments: nil,
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
++ (id)launchedTaskWithLaunchPath:(id)path // NSString*
+                       arguments:(id)arguments { // NSArray*
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new init];
+    let new = autorelease(env, new);
+    () = msg![env; new setLaunchPath:path];
+    () = msg![env; new setArguments:arguments];
+    () = msg![env; new launch];
+    new
+}
+
+- (id)launchPath {
+    env.objc.borrow::<NSTaskHostObject>(this).launch_path
+}
+- (())setLaunchPath:(id)path { // NSString*
+    let path: id = msg![env; path copy];
+    let old = std::mem::replace(&mut env.objc.borrow_mut::<NSTaskHostObject>(this).launch_path, path);
+    release(env, old);
+}
+
+- (id)arguments {
+    env.objc.borrow::<NSTaskHostObject>(this).arguments
+}
+- (())setArguments:(id)arguments { // NSArray*
+    let arguments: id = msg![env; arguments copy];
+    let old = std::mem::replace(&mut env.objc.borrow_mut::<NSTaskHostObject>(this).arguments, arguments);
+    release(env, old);
+}
+
+// The standard streams and environment are accepted but never used, since the
+// task can't run.
+- (())setStandardInput:(id)_input {}
+- (())setStandardOutput:(id)_output {}
+- (())setStandardError:(id)_error {}
+- (())setEnvironment:(id)_environment {}
+- (())setCurrentDirectoryPath:(id)_path {}
+
+- (())launch {
+    let &NSTaskHostObject { launch_path, arguments } = env.objc.borrow(this);
+    let launch_path = string_or_nil(env, launch_path);
+    let arguments: id = msg![env; arguments description];
+    let arguments = string_or_nil(env, arguments);
+    log!(
+        "App tried to launch an NSTask with path {:?} and arguments {}, ignoring",
+        launch_path,
+        arguments,
+    );
+}
+
+- (bool)isRunning {
+    false
+}
+// The task never ran, so report it as having failed.
+- (i32)terminationStatus {
+    1
+}
+- (i32)processIdentifier {
+    0
+}
+- (())waitUntilExit {}
+- (())terminate {}
+
+- (())dealloc {
+    let &NSTaskHostObject { launch_path, arguments } = env.objc.borrow(this);
+    release(env, launch_path);
+    release(env, arguments);
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+@end
+
+};
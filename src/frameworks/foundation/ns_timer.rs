@@ -7,7 +7,8 @@
 
 use super::ns_run_loop::NSDefaultRunLoopMode;
 use super::NSTimeInterval;
-use super::{ns_run_loop, ns_string};
+use super::{ns_date, ns_run_loop, ns_string};
+use crate::libc::time::current_time;
 use crate::objc::{
     autorelease, id, msg, msg_class, msg_send, nil, objc_classes, release, retain, ClassExports,
     HostObject, SEL,
@@ -118,11 +119,53 @@ pub const CLASSES: ClassExports = objc_classes! {
     env.objc.borrow::<NSTimerHostObject>(this).due_by.is_some()
 }
 
+- (id)fireDate {
+    let due_by = env.objc.borrow::<NSTimerHostObject>(this).due_by;
+    // TODO: what does this return for an invalidated timer?
+    let due_by = due_by.unwrap_or_else(Instant::now);
+    let now = current_time(env);
+    let fire_date = match due_by.checked_duration_since(Instant::now()) {
+        Some(ahead) => now.checked_add(ahead),
+        None => now.checked_sub(Instant::now().duration_since(due_by)),
+    }
+    .unwrap();
+    ns_date::from_system_time(env, fire_date)
+}
+- (())setFireDate:(id)fire_date { // NSDate *
+    let fire_date = ns_date::to_system_time(env, fire_date);
+    let now = current_time(env);
+    // Dates in the past mean the timer is due immediately.
+    let new_due_by = match fire_date.duration_since(now) {
+        Ok(ahead) => Instant::now().checked_add(ahead).unwrap(),
+        Err(_) => Instant::now(),
+    };
+    let host_object = env.objc.borrow_mut::<NSTimerHostObject>(this);
+    let run_loop = host_object.run_loop;
+    // Changing the fire date of an invalidated timer does nothing.
+    let Some(old_due_by) = host_object.due_by else {
+        return;
+    };
+    host_object.due_by = Some(new_due_by);
+    log_dbg!(
+        "Timer {:?} rescheduled to fire in {:?}",
+        this,
+        new_due_by.saturating_duration_since(Instant::now())
+    );
+    if run_loop != nil {
+        ns_run_loop::remove_timer(env, run_loop, this, old_due_by);
+        retain(env, this);
+        ns_run_loop::schedule_timer(env, run_loop, this, new_due_by);
+    }
+}
+
 -(())invalidate {
+    let host_object = env.objc.borrow_mut::<NSTimerHostObject>(this);
+    let run_loop = host_object.run_loop;
     // Timer might already be invalid, don't try to remove it twice.
-    if env.objc.borrow_mut::<NSTimerHostObject>(this).due_by.take().is_some() {
-        let run_loop: id = msg_class![env; NSRunLoop currentRunLoop];
-        ns_run_loop::remove_timer(env, run_loop, this);
+    if let Some(due_by) = host_object.due_by.take() {
+        if run_loop != nil {
+            ns_run_loop::remove_timer(env, run_loop, this, due_by);
+        }
     }
 }
 
@@ -160,11 +203,16 @@ pub(super) fn set_run_loop(env: &mut Environment, timer: id, run_loop: id) {
     host_object.run_loop = run_loop;
 }
 
-/// For use by `NSRunLoop`: check if a timer is due to fire and fire it if
-/// necessary.
-///
-/// Returns the next firing time, if any.
-pub(super) fn handle_timer(env: &mut Environment, timer: id) -> Option<Instant> {
+/// For use by `NSRunLoop`: get the date a timer is next due to fire, or
+/// [None] if it has been invalidated.
+pub(super) fn due_by(env: &mut Environment, timer: id) -> Option<Instant> {
+    env.objc.borrow::<NSTimerHostObject>(timer).due_by
+}
+
+/// For use by `NSRunLoop`: fire a timer that is due. The run loop must already
+/// have removed the timer from its schedule, and passes on its reference to
+/// the timer. Repeating timers are scheduled again before the message is sent.
+pub(super) fn handle_timer(env: &mut Environment, timer: id) {
     let &NSTimerHostObject {
         ns_interval,
         rust_interval,
@@ -180,50 +228,31 @@ pub(super) fn handle_timer(env: &mut Environment, timer: id) -> Option<Instant>
     let due_by = due_by.unwrap();
 
     let now = Instant::now();
-
-    if due_by > now {
-        return Some(due_by);
-    }
-
-    let overdue_by = now.duration_since(due_by);
-
-    // Timer may be released when it's invalidated, so we need to retain it so
-    // it's still around to pass to the timer target.
-    retain(env, timer);
+    let overdue_by = now.saturating_duration_since(due_by);
 
     // Advancing the timer before sending its message seems like a good idea
     // considering this function is potentially re-entrant.
     let new_due_by = if repeats {
-        // When rescheduling a repeating timer, the next firing should be based
-        // on when the timer should have fired, not when it actually fired, so
-        // that there is no drift over time.
-        //
-        // For example, if a timer has an interval of 60s and starts at 00:00,
-        // the first firing would be scheduled for 01:00, and the second firing
-        // should be scheduled for 02:00, even if the first firing was at 01:01.
-        //
-        // However: if the timer handling is delayed past a whole interval, it
-        // should not try to catch up. For example, if the first firing is
-        // scheduled for 01:00 but happens at 02:30, then the next firing should
-        // be scheduled for 03:00.
-        // TODO: Use `.div_duration_f64()` once that is stabilized.
-        let advance_by = (overdue_by.as_secs_f64() / ns_interval).max(1.0).ceil();
-        assert!(advance_by == (advance_by as u32) as f64);
-        let advance_by = advance_by as u32;
-        if advance_by > 1 {
-            log_dbg!("Warning: Timer {:?} is lagging. It is overdue by {}s and has missed {} interval(s)!", timer, overdue_by.as_secs_f64(), advance_by - 1);
+        let (new_due_by, missed) = next_due_by(due_by, now, ns_interval, rust_interval);
+        if missed > 0 {
+            log_dbg!("Warning: Timer {:?} is lagging. It is overdue by {}s and has missed {} interval(s)!", timer, overdue_by.as_secs_f64(), missed);
         }
-        let advance_by = rust_interval.checked_mul(advance_by).unwrap();
-        Some(due_by.checked_add(advance_by).unwrap())
+        Some(new_due_by)
     } else {
-        ns_run_loop::remove_timer(env, run_loop, timer);
         None
     };
     env.objc.borrow_mut::<NSTimerHostObject>(timer).due_by = new_due_by;
+    if let Some(new_due_by) = new_due_by {
+        // The reference passed in is kept until the message has been sent, in
+        // case the target invalidates the timer, so the schedule needs its own.
+        retain(env, timer);
+        ns_run_loop::schedule_timer(env, run_loop, timer, new_due_by);
+    }
 
     log_dbg!(
-        "Timer {:?} fired, sending {:?} message to {:?}",
+        "Timer {:?} fired {:?} late, sending {:?} message to {:?}",
         timer,
+        overdue_by,
         selector.as_str(&env.mem),
         target
     );
@@ -235,6 +264,92 @@ pub(super) fn handle_timer(env: &mut Environment, timer: id) -> Option<Instant>
 
     release(env, timer);
     release(env, pool);
+}
 
-    new_due_by
+/// Work out when a repeating timer that was due at `due_by` and is being fired
+/// at `now` should next fire, and how many firings it missed.
+///
+/// The next firing is based on when the timer should have fired, not when it
+/// actually fired, so that there is no drift over time. For example, if a
+/// timer has an interval of 60s and starts at 00:00, the first firing would be
+/// scheduled for 01:00, and the second firing should be scheduled for 02:00,
+/// even if the first firing was at 01:01.
+///
+/// However: if the timer handling is delayed past a whole interval, it should
+/// not try to catch up. For example, if the first firing is scheduled for
+/// 01:00 but happens at 02:30, then the next firing should be scheduled for
+/// 03:00.
+fn next_due_by(
+    due_by: Instant,
+    now: Instant,
+    ns_interval: NSTimeInterval,
+    rust_interval: Duration,
+) -> (Instant, u32) {
+    let overdue_by = now.saturating_duration_since(due_by);
+    // TODO: Use `.div_duration_f64()` once that is stabilized.
+    let advance_by = (overdue_by.as_secs_f64() / ns_interval).max(1.0).ceil();
+    assert!(advance_by == (advance_by as u32) as f64);
+    let advance_by = advance_by as u32;
+    let new_due_by = due_by
+        .checked_add(rust_interval.checked_mul(advance_by).unwrap())
+        .unwrap();
+    (new_due_by, advance_by - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::next_due_by;
+    use std::time::{Duration, Instant};
+
+    /// Simulate a run loop driving a 60Hz timer for 10 seconds, where each
+    /// iteration handles the timer some time after it is due. Returns how many
+    /// times the timer fired.
+    fn simulate_60hz_timer(mut delay_for_iteration: impl FnMut(u32) -> Duration) -> u32 {
+        let ns_interval = 1.0 / 60.0;
+        let rust_interval = Duration::from_secs_f64(ns_interval);
+        let start = Instant::now();
+        let end = start + Duration::from_secs(10);
+        let mut due_by = start + rust_interval;
+        let mut fired = 0;
+        for iteration in 0.. {
+            // The run loop sleeps until the timer is due, but wakes up late.
+            let now = due_by + delay_for_iteration(iteration);
+            if now > end {
+                break;
+            }
+            fired += 1;
+            (due_by, _) = next_due_by(due_by, now, ns_interval, rust_interval);
+        }
+        fired
+    }
+
+    #[test]
+    fn test_no_drift() {
+        // The interval isn't exactly representable, so allow an off-by-one.
+        let fired = simulate_60hz_timer(|_| Duration::ZERO);
+        assert!((599..=601).contains(&fired), "{}", fired);
+        // Late firings don't delay the ones after them.
+        let fired = simulate_60hz_timer(|i| Duration::from_millis((i % 7 * 2).into()));
+        assert!((599..=601).contains(&fired), "{}", fired);
+        let fired =
+            simulate_60hz_timer(|i| Duration::from_micros(if i % 3 == 0 { 16_000 } else { 500 }));
+        assert!((599..=601).contains(&fired), "{}", fired);
+    }
+
+    #[test]
+    fn test_missed_intervals_are_skipped() {
+        let start = Instant::now();
+        let interval = Duration::from_millis(100);
+        // Firing 250ms late means the next two firings were missed.
+        let due_by = start + interval;
+        let (new_due_by, missed) =
+            next_due_by(due_by, due_by + Duration::from_millis(250), 0.1, interval);
+        assert_eq!(missed, 2);
+        assert_eq!(new_due_by, due_by + interval * 3);
+        // Firing a little late doesn't miss anything.
+        let (new_due_by, missed) =
+            next_due_by(due_by, due_by + Duration::from_millis(10), 0.1, interval);
+        assert_eq!(missed, 0);
+        assert_eq!(new_due_by, due_by + interval);
+    }
 }
@@ -0,0 +1,728 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `NSUnarchiver` and deserialization of the non-keyed "typedstream" format
+//! written by `NSArchiver`.
+//!
+//! This is the format inherited from NeXTSTEP's `NXTypedStream`. A stream is a
+//! header followed by groups of values. Each group starts with an Objective-C
+//! type encoding string (e.g. `@` or `ii`), followed by one value for each type
+//! in it. Strings, C strings, classes and objects are shared: the first
+//! occurrence is written in full and later ones refer back to it by number.
+//!
+//! Resources:
+//! - [pytypedstream](https://github.com/dgelessus/python-typedstream), whose
+//!   documentation describes the format in detail.
+
+use super::ns_string::to_rust_string;
+use super::{NSInteger, NSNotFound, NSUInteger};
+use crate::mem::{ConstPtr, ConstVoidPtr, GuestUSize, MutPtr, MutVoidPtr, Ptr};
+use crate::objc::{
+    autorelease, id, msg, msg_class, nil, objc_classes, release, retain, Class, ClassExports,
+    HostObject, NSZonePtr,
+};
+use crate::Environment;
+
+const STREAMER_VERSION: u8 = 4;
+const SIGNATURE_LITTLE_ENDIAN: &[u8] = b"streamtyped";
+const SIGNATURE_BIG_ENDIAN: &[u8] = b"typedstream";
+
+// Special values of the first byte of an integer, string, class or object.
+const TAG_INTEGER_2: i8 = -127;
+const TAG_INTEGER_4: i8 = -126;
+const TAG_FLOATING_POINT: i8 = -125;
+const TAG_NEW: i8 = -124;
+const TAG_NIL: i8 = -123;
+const TAG_END_OF_OBJECT: i8 = -122;
+const FIRST_TAG: i8 = -128;
+const LAST_TAG: i8 = -111;
+/// Reference numbers are written as integers offset by this amount, so that
+/// the first few fit in a single byte without clashing with the tags.
+const FIRST_REFERENCE_NUMBER: i64 = LAST_TAG as i64 + 1;
+
+/// Something that can be referred back to after its first occurrence. Classes,
+/// objects and C strings share a single numbering.
+enum SharedObject {
+    Class {
+        name: String,
+        version: NSInteger,
+    },
+    /// This is retained by the unarchiver.
+    Object(id),
+    CString(Vec<u8>),
+}
+
+/// A value decoded from a typedstream, before it's written to guest memory.
+pub enum TypedValue {
+    /// Any integer type, including `char`.
+    Integer(i64),
+    Float(f64),
+    CString(Option<Vec<u8>>),
+    /// Unshared bytes (`+`) or an array of `char`.
+    Bytes(Vec<u8>),
+    /// This is retained only by the unarchiver.
+    Object(id),
+    Class(Class),
+    Array(Vec<TypedValue>),
+    Struct(Vec<TypedValue>),
+}
+
+struct TypedStream {
+    bytes: Vec<u8>,
+    position: usize,
+    big_endian: bool,
+    system_version: u32,
+    shared_strings: Vec<Vec<u8>>,
+    shared_objects: Vec<SharedObject>,
+}
+
+impl TypedStream {
+    /// Check the header and prepare to read the first group, or return [None]
+    /// if this isn't a typedstream we understand.
+    fn new(bytes: Vec<u8>) -> Option<TypedStream> {
+        let mut stream = TypedStream {
+            bytes,
+            position: 0,
+            big_endian: false,
+            system_version: 0,
+            shared_strings: Vec::new(),
+            shared_objects: Vec::new(),
+        };
+        if stream.bytes.len() < 2 + SIGNATURE_LITTLE_ENDIAN.len()
+            || stream.bytes[0] != STREAMER_VERSION
+            || usize::from(stream.bytes[1]) != SIGNATURE_LITTLE_ENDIAN.len()
+        {
+            return None;
+        }
+        stream.position = 2;
+        stream.big_endian = match stream.read_exact(SIGNATURE_LITTLE_ENDIAN.len()) {
+            SIGNATURE_LITTLE_ENDIAN => false,
+            SIGNATURE_BIG_ENDIAN => true,
+            _ => return None,
+        };
+        stream.system_version = stream.read_integer(/* signed: */ false) as u32;
+        Some(stream)
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.position >= self.bytes.len()
+    }
+
+    fn read_exact(&mut self, count: usize) -> &[u8] {
+        let start = self.position;
+        let end = start.checked_add(count).unwrap();
+        assert!(end <= self.bytes.len(), "Unexpected end of typedstream");
+        self.position = end;
+        &self.bytes[start..end]
+    }
+    fn read_array<const N: usize>(&mut self) -> [u8; N] {
+        self.read_exact(N).try_into().unwrap()
+    }
+
+    fn peek_head(&self) -> i8 {
+        assert!(!self.is_at_end(), "Unexpected end of typedstream");
+        self.bytes[self.position] as i8
+    }
+    fn read_head(&mut self) -> i8 {
+        let [head] = self.read_array();
+        head as i8
+    }
+
+    fn read_integer(&mut self, signed: bool) -> i64 {
+        let head = self.read_head();
+        self.read_integer_with_head(head, signed)
+    }
+    fn read_integer_with_head(&mut self, head: i8, signed: bool) -> i64 {
+        match head {
+            TAG_INTEGER_2 => {
+                let bytes = self.read_array();
+                let value = if self.big_endian {
+                    u16::from_be_bytes(bytes)
+                } else {
+                    u16::from_le_bytes(bytes)
+                };
+                if signed {
+                    (value as i16).into()
+                } else {
+                    value.into()
+                }
+            }
+            TAG_INTEGER_4 => {
+                let bytes = self.read_array();
+                let value = if self.big_endian {
+                    u32::from_be_bytes(bytes)
+                } else {
+                    u32::from_le_bytes(bytes)
+                };
+                if signed {
+                    (value as i32).into()
+                } else {
+                    value.into()
+                }
+            }
+            FIRST_TAG..=LAST_TAG => panic!("Unexpected tag {} in typedstream integer", head),
+            _ if signed => head.into(),
+            _ => (head as u8).into(),
+        }
+    }
+
+    fn read_float(&mut self, double: bool) -> f64 {
+        let head = self.read_head();
+        if head != TAG_FLOATING_POINT {
+            // Floats with integral values are written as integers.
+            return self.read_integer_with_head(head, /* signed: */ true) as f64;
+        }
+        match (double, self.big_endian) {
+            (false, false) => f32::from_le_bytes(self.read_array()).into(),
+            (false, true) => f32::from_be_bytes(self.read_array()).into(),
+            (true, false) => f64::from_le_bytes(self.read_array()),
+            (true, true) => f64::from_be_bytes(self.read_array()),
+        }
+    }
+
+    fn read_reference(&mut self, head: i8) -> usize {
+        let number = self.read_integer_with_head(head, /* signed: */ true);
+        (number - FIRST_REFERENCE_NUMBER).try_into().unwrap()
+    }
+
+    fn read_unshared_string(&mut self) -> Vec<u8> {
+        let length = self.read_integer(/* signed: */ false);
+        self.read_exact(length.try_into().unwrap()).to_vec()
+    }
+
+    fn read_shared_string(&mut self) -> Option<Vec<u8>> {
+        match self.read_head() {
+            TAG_NIL => None,
+            TAG_NEW => {
+                let string = self.read_unshared_string();
+                self.shared_strings.push(string.clone());
+                Some(string)
+            }
+            head => {
+                let reference = self.read_reference(head);
+                Some(self.shared_strings[reference].clone())
+            }
+        }
+    }
+
+    fn read_c_string(&mut self) -> Option<Vec<u8>> {
+        match self.read_head() {
+            TAG_NIL => None,
+            TAG_NEW => {
+                let string = self.read_shared_string().unwrap();
+                self.shared_objects
+                    .push(SharedObject::CString(string.clone()));
+                Some(string)
+            }
+            head => {
+                let reference = self.read_reference(head);
+                match self.shared_objects[reference] {
+                    SharedObject::CString(ref string) => Some(string.clone()),
+                    _ => panic!("Typedstream reference is not to a C string"),
+                }
+            }
+        }
+    }
+
+    /// Read a class and its superclasses, returning the class's name.
+    fn read_class(&mut self) -> Option<String> {
+        match self.read_head() {
+            TAG_NIL => None,
+            TAG_NEW => {
+                let name = self.read_shared_string().unwrap();
+                let name = String::from_utf8(name).unwrap();
+                let version = self.read_integer(/* signed: */ true);
+                self.shared_objects.push(SharedObject::Class {
+                    name: name.clone(),
+                    version: version.try_into().unwrap(),
+                });
+                // Nothing needs the superclass chain yet, but it has to be read
+                // so the reference numbers stay in sync.
+                self.read_class();
+                Some(name)
+            }
+            head => {
+                let reference = self.read_reference(head);
+                match self.shared_objects[reference] {
+                    SharedObject::Class { ref name, .. } => Some(name.clone()),
+                    _ => panic!("Typedstream reference is not to a class"),
+                }
+            }
+        }
+    }
+
+    fn version_for_class_name(&self, class_name: &str) -> Option<NSInteger> {
+        self.shared_objects.iter().find_map(|object| match object {
+            SharedObject::Class { name, version } if name == class_name => Some(*version),
+            _ => None,
+        })
+    }
+}
+
+/// Split the first complete type off an Objective-C type encoding string,
+/// e.g. `{CGPoint=ff}i` becomes `{CGPoint=ff}` and `i`.
+fn split_type(types: &[u8]) -> (&[u8], &[u8]) {
+    let mut depth = 0;
+    for (i, &c) in types.iter().enumerate() {
+        match c {
+            b'[' | b'{' | b'(' => depth += 1,
+            b']' | b'}' | b')' => depth -= 1,
+            _ => (),
+        }
+        if depth == 0 {
+            return types.split_at(i + 1);
+        }
+    }
+    panic!(
+        "Unterminated type encoding {:?}",
+        String::from_utf8_lossy(types)
+    );
+}
+
+/// Get the element count and type of an array type like `[16c]`.
+fn array_type_parts(ty: &[u8]) -> (usize, &[u8]) {
+    let inner = &ty[1..ty.len() - 1];
+    let digits = inner.iter().take_while(|c| c.is_ascii_digit()).count();
+    let count = std::str::from_utf8(&inner[..digits])
+        .unwrap()
+        .parse()
+        .unwrap();
+    (count, &inner[digits..])
+}
+
+/// Get the field types of a struct type like `{CGPoint=ff}`.
+fn struct_type_fields(ty: &[u8]) -> &[u8] {
+    let inner = &ty[1..ty.len() - 1];
+    match inner.iter().position(|&c| c == b'=') {
+        Some(equals) => &inner[equals + 1..],
+        None => &[],
+    }
+}
+
+fn align_to(offset: GuestUSize, align: GuestUSize) -> GuestUSize {
+    offset.div_ceil(align) * align
+}
+
+/// Size and alignment of a type in guest memory.
+fn size_and_alignment(ty: &[u8]) -> (GuestUSize, GuestUSize) {
+    match ty[0] {
+        b'c' | b'C' => (1, 1),
+        b's' | b'S' => (2, 2),
+        b'i' | b'I' | b'l' | b'L' | b'f' | b'*' | b'@' | b'#' => (4, 4),
+        // iPhone OS's ARM ABI only aligns 64-bit types to 4 bytes.
+        b'q' | b'Q' | b'd' => (8, 4),
+        b'[' => {
+            let (count, element) = array_type_parts(ty);
+            let (size, align) = size_and_alignment(element);
+            (size * GuestUSize::try_from(count).unwrap(), align)
+        }
+        b'{' => {
+            let mut fields = struct_type_fields(ty);
+            let mut offset = 0;
+            let mut struct_align = 1;
+            while !fields.is_empty() {
+                let (field, rest) = split_type(fields);
+                let (size, align) = size_and_alignment(field);
+                offset = align_to(offset, align) + size;
+                struct_align = struct_align.max(align);
+                fields = rest;
+            }
+            (align_to(offset, struct_align), struct_align)
+        }
+        _ => unimplemented!("Type encoding {:?}", String::from_utf8_lossy(ty)),
+    }
+}
+
+struct NSUnarchiverHostObject {
+    stream: Option<TypedStream>,
+    /// Memory handed out for C strings and by
+    /// `decodeBytesWithReturnedLength:`. This is freed along with the
+    /// unarchiver.
+    buffers: Vec<MutVoidPtr>,
+}
+impl HostObject for NSUnarchiverHostObject {}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation NSUnarchiver: NSCoder
+
++ (id)allocWithZone:(NSZonePtr)_zone { // struct _NSZone*
+    let unarchiver = Box::new(NSUnarchiverHostObject {
+        stream: None,
+        buffers: Vec::new(),
+    });
+    env.objc.alloc_object(this, unarchiver, &mut env.mem)
+}
+
++ (id)unarchiveObjectWithData:(id)data { // NSData *
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initForReadingWithData:data];
+    if new == nil {
+        return nil;
+    }
+    let result: id = msg![env; new decodeObject];
+    // The result is autoreleased, so it can outlive the unarchiver.
+    release(env, new);
+    result
+}
+
++ (id)unarchiveObjectWithFile:(id)path { // NSString *
+    let data: id = msg_class![env; NSData dataWithContentsOfFile:path];
+    if data == nil {
+        return nil;
+    }
+    msg![env; this unarchiveObjectWithData:data]
+}
+
+- (id)initForReadingWithData:(id)data { // NSData *
+    if data == nil {
+        release(env, this);
+        return nil;
+    }
+
+    let length: NSUInteger = msg![env; data length];
+    let bytes: ConstVoidPtr = msg![env; data bytes];
+    let bytes = env.mem.bytes_at(bytes.cast(), length).to_vec();
+
+    let Some(stream) = TypedStream::new(bytes) else {
+        log!("Warning: [(NSUnarchiver*){:?} initForReadingWithData:] got data that isn't a typedstream, returning nil", this);
+        release(env, this);
+        return nil;
+    };
+
+    let host_obj = borrow_host_obj(env, this);
+    assert!(host_obj.stream.is_none());
+    host_obj.stream = Some(stream);
+
+    this
+}
+
+- (())dealloc {
+    let host_obj = borrow_host_obj(env, this);
+    let stream = host_obj.stream.take();
+    let buffers = std::mem::take(&mut host_obj.buffers);
+
+    for object in stream.into_iter().flat_map(|stream| stream.shared_objects) {
+        if let SharedObject::Object(object) = object {
+            release(env, object);
+        }
+    }
+    for buffer in buffers {
+        env.mem.free(buffer);
+    }
+
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+- (bool)allowsKeyedCoding {
+    false
+}
+
+- (bool)isAtEnd {
+    borrow_stream(env, this).is_at_end()
+}
+
+- (u32)systemVersion {
+    borrow_stream(env, this).system_version
+}
+
+- (NSInteger)versionForClassName:(id)class_name { // NSString *
+    let class_name = to_rust_string(env, class_name);
+    borrow_stream(env, this)
+        .version_for_class_name(&class_name)
+        .unwrap_or(NSNotFound)
+}
+
+- (id)decodeObject {
+    let object = decode_object(env, this);
+    // on behalf of the caller
+    retain(env, object);
+    autorelease(env, object)
+}
+
+- (())decodeValueOfObjCType:(ConstPtr<u8>)ty
+                         at:(MutVoidPtr)data {
+    let ty = env.mem.cstr_at(ty).to_vec();
+    let value = decode_value(env, this, &ty);
+    write_value(env, this, data, &ty, value);
+}
+
+- (())decodeValuesOfObjCTypes:(ConstPtr<u8>)types, ...dots {
+    let types = env.mem.cstr_at(types).to_vec();
+    read_group_types(env, this, &types);
+    let mut args = dots.start();
+    let mut remaining: &[u8] = &types;
+    while !remaining.is_empty() {
+        let (ty, rest) = split_type(remaining);
+        let data: MutVoidPtr = args.next(env);
+        let value = read_value(env, this, ty);
+        write_value(env, this, data, ty, value);
+        remaining = rest;
+    }
+}
+
+- (())decodeArrayOfObjCType:(ConstPtr<u8>)item_type
+                      count:(NSUInteger)count
+                         at:(MutVoidPtr)array {
+    let item_type = env.mem.cstr_at(item_type);
+    let mut ty = format!("[{}", count).into_bytes();
+    ty.extend_from_slice(item_type);
+    ty.push(b']');
+    let value = decode_value(env, this, &ty);
+    write_value(env, this, array, &ty, value);
+}
+
+- (MutVoidPtr)decodeBytesWithReturnedLength:(MutPtr<NSUInteger>)length_ptr {
+    let bytes = decode_bytes(env, this);
+    let length: GuestUSize = bytes.len().try_into().unwrap();
+    let buffer = env.mem.alloc(length);
+    env.mem
+        .bytes_at_mut(buffer.cast(), length)
+        .copy_from_slice(&bytes);
+    borrow_host_obj(env, this).buffers.push(buffer);
+    if !length_ptr.is_null() {
+        env.mem.write(length_ptr, length);
+    }
+    buffer
+}
+
+@end
+
+};
+
+fn borrow_host_obj(env: &mut Environment, unarchiver: id) -> &mut NSUnarchiverHostObject {
+    env.objc.borrow_mut(unarchiver)
+}
+
+fn borrow_stream(env: &mut Environment, unarchiver: id) -> &mut TypedStream {
+    borrow_host_obj(env, unarchiver)
+        .stream
+        .as_mut()
+        .expect("NSUnarchiver used before initForReadingWithData:")
+}
+
+/// Read the type encoding string at the start of a group of values and check
+/// it is the one the caller is about to decode.
+fn read_group_types(env: &mut Environment, unarchiver: id, expected: &[u8]) {
+    let types = borrow_stream(env, unarchiver).read_shared_string();
+    if types.as_deref() != Some(expected) {
+        panic!(
+            "Typedstream has values of type {:?} where {:?} was expected",
+            types.as_deref().map(String::from_utf8_lossy),
+            String::from_utf8_lossy(expected)
+        );
+    }
+}
+
+/// Read a single value of a type, which must not be the start of a group.
+fn read_value(env: &mut Environment, unarchiver: id, ty: &[u8]) -> TypedValue {
+    let stream = borrow_stream(env, unarchiver);
+    match ty[0] {
+        // chars are written as a raw byte
+        b'c' => TypedValue::Integer(stream.read_head().into()),
+        b'C' => TypedValue::Integer((stream.read_head() as u8).into()),
+        b's' | b'i' | b'l' | b'q' => TypedValue::Integer(stream.read_integer(true)),
+        b'S' | b'I' | b'L' | b'Q' => TypedValue::Integer(stream.read_integer(false)),
+        b'f' => TypedValue::Float(stream.read_float(/* double: */ false)),
+        b'd' => TypedValue::Float(stream.read_float(/* double: */ true)),
+        b'*' => TypedValue::CString(stream.read_c_string()),
+        b'+' => TypedValue::Bytes(stream.read_unshared_string()),
+        b'#' => {
+            let class = stream
+                .read_class()
+                .map_or(nil, |name| env.objc.get_known_class(&name, &mut env.mem));
+            TypedValue::Class(class)
+        }
+        b'@' => TypedValue::Object(read_object(env, unarchiver)),
+        b'[' => {
+            let (count, element) = array_type_parts(ty);
+            // Arrays of chars are written as raw bytes.
+            if element == b"c" || element == b"C" {
+                return TypedValue::Bytes(stream.read_exact(count).to_vec());
+            }
+            TypedValue::Array(
+                (0..count)
+                    .map(|_| read_value(env, unarchiver, element))
+                    .collect(),
+            )
+        }
+        b'{' => {
+            let mut fields = struct_type_fields(ty);
+            let mut values = Vec::new();
+            while !fields.is_empty() {
+                let (field, rest) = split_type(fields);
+                values.push(read_value(env, unarchiver, field));
+                fields = rest;
+            }
+            TypedValue::Struct(values)
+        }
+        _ => unimplemented!(
+            "Typedstream value of type {:?}",
+            String::from_utf8_lossy(ty)
+        ),
+    }
+}
+
+fn read_object(env: &mut Environment, unarchiver: id) -> id {
+    let stream = borrow_stream(env, unarchiver);
+    match stream.read_head() {
+        TAG_NIL => nil,
+        TAG_NEW => {
+            // The object is numbered before its class.
+            let index = stream.shared_objects.len();
+            stream.shared_objects.push(SharedObject::Object(nil));
+            let class_name = stream.read_class().unwrap();
+
+            let class = env.objc.get_known_class(&class_name, &mut env.mem);
+            let object: id = msg![env; class alloc];
+            // The object's own data might refer back to it.
+            borrow_stream(env, unarchiver).shared_objects[index] = SharedObject::Object(object);
+            let object: id = msg![env; object initWithCoder:unarchiver];
+            borrow_stream(env, unarchiver).shared_objects[index] = SharedObject::Object(object);
+
+            skip_to_end_of_object(env, unarchiver, &class_name);
+            object
+        }
+        head => {
+            let reference = stream.read_reference(head);
+            match stream.shared_objects[reference] {
+                SharedObject::Object(object) => object,
+                _ => panic!("Typedstream reference is not to an object"),
+            }
+        }
+    }
+}
+
+/// Consume the end of an object's data, skipping anything its
+/// `initWithCoder:` didn't read.
+fn skip_to_end_of_object(env: &mut Environment, unarchiver: id, class_name: &str) {
+    loop {
+        if borrow_stream(env, unarchiver).peek_head() == TAG_END_OF_OBJECT {
+            borrow_stream(env, unarchiver).read_head();
+            return;
+        }
+        let types = borrow_stream(env, unarchiver).read_shared_string().unwrap();
+        log!(
+            "Warning: skipping values of type {:?} not decoded by {}'s initWithCoder:",
+            String::from_utf8_lossy(&types),
+            class_name
+        );
+        let mut remaining: &[u8] = &types;
+        while !remaining.is_empty() {
+            let (ty, rest) = split_type(remaining);
+            read_value(env, unarchiver, ty);
+            remaining = rest;
+        }
+    }
+}
+
+fn write_value(
+    env: &mut Environment,
+    unarchiver: id,
+    data: MutVoidPtr,
+    ty: &[u8],
+    value: TypedValue,
+) {
+    let data = data.cast::<u8>();
+    match value {
+        TypedValue::Integer(value) => match size_and_alignment(ty).0 {
+            1 => env.mem.write(data, value as u8),
+            2 => env.mem.write(data.cast(), value as u16),
+            4 => env.mem.write(data.cast(), value as u32),
+            8 => env.mem.write(data.cast(), value as u64),
+            _ => unreachable!(),
+        },
+        TypedValue::Float(value) if ty[0] == b'f' => env.mem.write(data.cast(), value as f32),
+        TypedValue::Float(value) => env.mem.write(data.cast(), value),
+        TypedValue::CString(None) => env.mem.write(data.cast(), Ptr::<u8, true>::null()),
+        TypedValue::CString(Some(string)) => {
+            let buffer = env.mem.alloc_and_write_cstr(&string);
+            borrow_host_obj(env, unarchiver).buffers.push(buffer.cast());
+            env.mem.write(data.cast(), buffer);
+        }
+        TypedValue::Bytes(bytes) => {
+            let length = bytes.len().try_into().unwrap();
+            env.mem.bytes_at_mut(data, length).copy_from_slice(&bytes);
+        }
+        TypedValue::Object(object) => {
+            // Unlike with decodeObject, the caller owns objects decoded this
+            // way.
+            retain(env, object);
+            env.mem.write(data.cast(), object);
+        }
+        TypedValue::Class(class) => env.mem.write(data.cast(), class),
+        TypedValue::Array(values) => {
+            let (_, element) = array_type_parts(ty);
+            let (size, _) = size_and_alignment(element);
+            for (i, value) in values.into_iter().enumerate() {
+                let offset = size * GuestUSize::try_from(i).unwrap();
+                write_value(env, unarchiver, (data + offset).cast(), element, value);
+            }
+        }
+        TypedValue::Struct(values) => {
+            let mut fields = struct_type_fields(ty);
+            let mut offset = 0;
+            for value in values {
+                let (field, rest) = split_type(fields);
+                let (size, align) = size_and_alignment(field);
+                offset = align_to(offset, align);
+                write_value(env, unarchiver, (data + offset).cast(), field, value);
+                offset += size;
+                fields = rest;
+            }
+        }
+    }
+}
+
+/// Shortcut for host implementations of `initWithCoder:`: decode a group
+/// containing a single value of the given type, like
+/// `decodeValueOfObjCType:at:` but without going via guest memory.
+///
+/// Objects in the result are retained only by the unarchiver. Remember to
+/// retain them as appropriate.
+pub fn decode_value(env: &mut Environment, unarchiver: id, ty: &[u8]) -> TypedValue {
+    read_group_types(env, unarchiver, ty);
+    read_value(env, unarchiver, ty)
+}
+
+/// Shortcut for host implementations of `initWithCoder:`, like `decodeObject`.
+///
+/// The object returned is retained only by the unarchiver. Remember to retain
+/// it as appropriate.
+pub fn decode_object(env: &mut Environment, unarchiver: id) -> id {
+    let TypedValue::Object(object) = decode_value(env, unarchiver, b"@") else {
+        unreachable!();
+    };
+    object
+}
+
+/// Shortcut for host implementations of `initWithCoder:`, like
+/// `decodeBytesWithReturnedLength:`.
+pub fn decode_bytes(env: &mut Environment, unarchiver: id) -> Vec<u8> {
+    let types = borrow_stream(env, unarchiver).read_shared_string().unwrap();
+    if types == b"+" {
+        let TypedValue::Bytes(bytes) = read_value(env, unarchiver, b"+") else {
+            unreachable!();
+        };
+        return bytes;
+    }
+    // NSCoder's generic implementation writes a length followed by an array.
+    let TypedValue::Integer(_length) = read_value(env, unarchiver, &types) else {
+        panic!(
+            "Typedstream has values of type {:?} where bytes were expected",
+            String::from_utf8_lossy(&types)
+        );
+    };
+    let types = borrow_stream(env, unarchiver).read_shared_string().unwrap();
+    match read_value(env, unarchiver, &types) {
+        TypedValue::Bytes(bytes) if types[0] == b'[' => bytes,
+        _ => panic!(
+            "Typedstream has values of type {:?} where bytes were expected",
+            String::from_utf8_lossy(&types)
+        ),
+    }
+}
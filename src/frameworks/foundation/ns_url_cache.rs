@@ -0,0 +1,300 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `NSURLCache` and `NSCachedURLResponse`.
+//!
+//! Responses are only cached in memory, least recently used first out. The
+//! disk capacity is remembered but nothing is ever written to disk.
+
+use super::ns_string::to_rust_string;
+use super::NSUInteger;
+use crate::objc::{
+    id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject, NSZonePtr,
+};
+use crate::Environment;
+
+pub type NSURLCacheStoragePolicy = NSUInteger;
+pub const NSURLCacheStorageAllowed: NSURLCacheStoragePolicy = 0;
+#[allow(dead_code)]
+pub const NSURLCacheStorageAllowedInMemoryOnly: NSURLCacheStoragePolicy = 1;
+pub const NSURLCacheStorageNotAllowed: NSURLCacheStoragePolicy = 2;
+
+/// Capacities of the default shared cache, which are the same as iOS's.
+const DEFAULT_MEMORY_CAPACITY: NSUInteger = 4 * 1024 * 1024;
+const DEFAULT_DISK_CAPACITY: NSUInteger = 20 * 1024 * 1024;
+
+#[derive(Default)]
+pub struct State {
+    /// `NSURLCache*`
+    shared_cache: Option<id>,
+}
+impl State {
+    fn get(env: &mut Environment) -> &mut Self {
+        &mut env.framework_state.foundation.ns_url_cache
+    }
+}
+
+#[derive(Default)]
+struct NSCachedURLResponseHostObject {
+    /// `NSURLResponse*`
+    response: id,
+    /// `NSData*`
+    data: id,
+    /// `NSDictionary*`
+    user_info: id,
+    storage_policy: NSURLCacheStoragePolicy,
+}
+impl HostObject for NSCachedURLResponseHostObject {}
+
+struct CacheEntry {
+    /// The absolute URL string of the request.
+    key: String,
+    /// `NSCachedURLResponse*`, retained.
+    cached_response: id,
+    cost: u64,
+}
+
+#[derive(Default)]
+struct NSURLCacheHostObject {
+    /// Least recently used first.
+    entries: Vec<CacheEntry>,
+    memory_usage: u64,
+    memory_capacity: NSUInteger,
+    disk_capacity: NSUInteger,
+}
+impl HostObject for NSURLCacheHostObject {}
+impl NSURLCacheHostObject {
+    fn remove_at(&mut self, env: &mut Environment, idx: usize) {
+        let CacheEntry {
+            cached_response,
+            cost,
+            ..
+        } = self.entries.remove(idx);
+        self.memory_usage -= cost;
+        release(env, cached_response);
+    }
+    fn evict_over_capacity(&mut self, env: &mut Environment) {
+        while !self.entries.is_empty() && self.memory_usage > self.memory_capacity.into() {
+            self.remove_at(env, 0);
+        }
+    }
+    fn remove_all(&mut self, env: &mut Environment) {
+        while !self.entries.is_empty() {
+            self.remove_at(env, self.entries.len() - 1);
+        }
+    }
+}
+
+/// The key a request's response is cached under, if it has a URL.
+fn cache_key(env: &mut Environment, request: id) -> Option<String> {
+    let url: id = msg![env; request URL];
+    if url == nil {
+        return None;
+    }
+    let string: id = msg![env; url absoluteString];
+    Some(to_rust_string(env, string).into_owned())
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation NSCachedURLResponse: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::<NSCachedURLResponseHostObject>::default();
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+- (id)initWithResponse:(id)response // NSURLResponse*
+                  data:(id)data { // NSData*
+    msg![env; this initWithResponse:response
+                               data:data
+                           userInfo:nil
+                      storagePolicy:NSURLCacheStorageAllowed]
+}
+- (id)initWithResponse:(id)response // NSURLResponse*
+                  data:(id)data // NSData*
+              userInfo:(id)user_info // NSDictionary*
+         storagePolicy:(NSURLCacheStoragePolicy)storage_policy {
+    let response: id = msg![env; response copy];
+    let data: id = msg![env; data copy];
+    let user_info: id = msg![env; user_info copy];
+    *env.objc.borrow_mut(this) = NSCachedURLResponseHostObject {
+        response,
+        data,
+        user_info,
+        storage_policy,
+    };
+    this
+}
+
+- (())dealloc {
+    let NSCachedURLResponseHostObject {
+        response,
+        data,
+        user_info,
+        ..
+    } = std::mem::take(env.objc.borrow_mut(this));
+    release(env, response);
+    release(env, data);
+    release(env, user_info);
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+// NSCopying implementation
+- (id)copyWithZone:(NSZonePtr)_zone {
+    // Cached responses are immutable.
+    retain(env, this)
+}
+
+- (id)response {
+    env.objc.borrow::<NSCachedURLResponseHostObject>(this).response
+}
+- (id)data {
+    env.objc.borrow::<NSCachedURLResponseHostObject>(this).data
+}
+- (id)userInfo {
+    env.objc.borrow::<NSCachedURLResponseHostObject>(this).user_info
+}
+- (NSURLCacheStoragePolicy)storagePolicy {
+    env.objc.borrow::<NSCachedURLResponseHostObject>(this).storage_policy
+}
+
+@end
+
+@implementation NSURLCache: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::<NSURLCacheHostObject>::default();
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
++ (id)sharedURLCache {
+    if let Some(existing) = State::get(env).shared_cache {
+        existing
+    } else {
+        let new: id = msg_class![env; NSURLCache alloc];
+        let new: id = msg![env; new initWithMemoryCapacity:DEFAULT_MEMORY_CAPACITY
+                                             diskCapacity:DEFAULT_DISK_CAPACITY
+                                                 diskPath:nil];
+        State::get(env).shared_cache = Some(new);
+        new
+    }
+}
++ (())setSharedURLCache:(id)cache { // NSURLCache*
+    retain(env, cache);
+    if let Some(old) = State::get(env).shared_cache.replace(cache) {
+        release(env, old);
+    }
+}
+
+- (id)initWithMemoryCapacity:(NSUInteger)memory_capacity
+                diskCapacity:(NSUInteger)disk_capacity
+                    diskPath:(id)_disk_path { // NSString*
+    let host_obj = env.objc.borrow_mut::<NSURLCacheHostObject>(this);
+    host_obj.memory_capacity = memory_capacity;
+    host_obj.disk_capacity = disk_capacity;
+    this
+}
+
+- (())dealloc {
+    let mut host_obj: NSURLCacheHostObject = std::mem::take(env.objc.borrow_mut(this));
+    host_obj.remove_all(env);
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+- (NSUInteger)memoryCapacity {
+    env.objc.borrow::<NSURLCacheHostObject>(this).memory_capacity
+}
+- (())setMemoryCapacity:(NSUInteger)capacity {
+    let mut host_obj: NSURLCacheHostObject = std::mem::take(env.objc.borrow_mut(this));
+    host_obj.memory_capacity = capacity;
+    host_obj.evict_over_capacity(env);
+    *env.objc.borrow_mut(this) = host_obj;
+}
+- (NSUInteger)diskCapacity {
+    env.objc.borrow::<NSURLCacheHostObject>(this).disk_capacity
+}
+- (())setDiskCapacity:(NSUInteger)capacity {
+    env.objc.borrow_mut::<NSURLCacheHostObject>(this).disk_capacity = capacity;
+}
+- (NSUInteger)currentMemoryUsage {
+    let usage = env.objc.borrow::<NSURLCacheHostObject>(this).memory_usage;
+    usage.try_into().unwrap_or(NSUInteger::MAX)
+}
+- (NSUInteger)currentDiskUsage {
+    0
+}
+
+- (id)cachedResponseForRequest:(id)request { // NSURLRequest*
+    let Some(key) = cache_key(env, request) else {
+        return nil;
+    };
+    let host_obj = env.objc.borrow_mut::<NSURLCacheHostObject>(this);
+    let Some(idx) = host_obj.entries.iter().position(|entry| entry.key == key) else {
+        return nil;
+    };
+    // Mark this entry as the most recently used.
+    let entry = host_obj.entries.remove(idx);
+    let cached_response = entry.cached_response;
+    host_obj.entries.push(entry);
+    cached_response
+}
+
+- (())storeCachedResponse:(id)cached_response // NSCachedURLResponse*
+               forRequest:(id)request { // NSURLRequest*
+    let storage_policy: NSURLCacheStoragePolicy = msg![env; cached_response storagePolicy];
+    if storage_policy == NSURLCacheStorageNotAllowed {
+        return;
+    }
+    let Some(key) = cache_key(env, request) else {
+        return;
+    };
+    let data: id = msg![env; cached_response data];
+    let cost: NSUInteger = msg![env; data length];
+    let cost = u64::from(cost);
+
+    // Retain before removing any existing entry, in case it's the same object.
+    retain(env, cached_response);
+    let mut host_obj: NSURLCacheHostObject = std::mem::take(env.objc.borrow_mut(this));
+    if let Some(idx) = host_obj.entries.iter().position(|entry| entry.key == key) {
+        host_obj.remove_at(env, idx);
+    }
+    if cost > host_obj.memory_capacity.into() {
+        // It would only evict everything else and then itself.
+        release(env, cached_response);
+    } else {
+        host_obj.entries.push(CacheEntry {
+            key,
+            cached_response,
+            cost,
+        });
+        host_obj.memory_usage += cost;
+        host_obj.evict_over_capacity(env);
+    }
+    *env.objc.borrow_mut(this) = host_obj;
+}
+
+- (())removeCachedResponseForRequest:(id)request { // NSURLRequest*
+    let Some(key) = cache_key(env, request) else {
+        return;
+    };
+    let mut host_obj: NSURLCacheHostObject = std::mem::take(env.objc.borrow_mut(this));
+    if let Some(idx) = host_obj.entries.iter().position(|entry| entry.key == key) {
+        host_obj.remove_at(env, idx);
+    }
+    *env.objc.borrow_mut(this) = host_obj;
+}
+
+- (())removeAllCachedResponses {
+    let mut host_obj: NSURLCacheHostObject = std::mem::take(env.objc.borrow_mut(this));
+    host_obj.remove_all(env);
+    *env.objc.borrow_mut(this) = host_obj;
+}
+
+@end
+
+};
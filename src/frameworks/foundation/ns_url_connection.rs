@@ -0,0 +1,102 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `NSURLConnection`.
+//!
+//! touchHLE has no network access, so requests can only be answered from the
+//! shared `NSURLCache`. Anything else fails the same way it would on a device
+//! without a connection. Responses still update the cache and the cookie
+//! storage, see [update_from_response].
+
+use super::ns_error::{set_error, NSURLErrorDomain, NSURLErrorNotConnectedToInternet};
+use super::ns_http_cookie_storage::request_header_for_url;
+use super::ns_string::to_rust_string;
+use super::ns_url_request::{NSURLRequestCachePolicy, NSURLRequestReloadIgnoringLocalCacheData};
+use crate::mem::MutPtr;
+use crate::objc::{
+    autorelease, id, msg, msg_class, nil, objc_classes, release, retain, ClassExports,
+};
+use crate::Environment;
+
+/// Store an `NSHTTPURLResponse*` and its body (`NSData*`), which were loaded
+/// for an `NSURLRequest*`, in the shared URL cache, and save any cookies its
+/// headers set in the shared cookie storage.
+fn update_from_response(env: &mut Environment, request: id, response: id, data: id) {
+    let cached: id = msg_class![env; NSCachedURLResponse alloc];
+    let cached: id = msg![env; cached initWithResponse:response data:data];
+    let cache: id = msg_class![env; NSURLCache sharedURLCache];
+    () = msg![env; cache storeCachedResponse:cached forRequest:request];
+    release(env, cached);
+
+    let should_handle_cookies: bool = msg![env; request HTTPShouldHandleCookies];
+    if !should_handle_cookies {
+        return;
+    }
+    let url: id = msg![env; response URL];
+    let fields: id = msg![env; response allHeaderFields];
+    let cookies: id = msg_class![env; NSHTTPCookie cookiesWithResponseHeaderFields:fields
+                                                                           forURL:url];
+    let storage: id = msg_class![env; NSHTTPCookieStorage sharedHTTPCookieStorage];
+    // NSURLRequest doesn't have a main document URL, so every response is
+    // treated as being for the main document.
+    () = msg![env; storage setCookies:cookies forURL:url mainDocumentURL:url];
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation NSURLConnection: NSObject
+
++ (id)sendSynchronousRequest:(id)request // NSURLRequest*
+           returningResponse:(MutPtr<id>)response_out // NSURLResponse**
+                       error:(MutPtr<id>)error_out { // NSError**
+    let cache_policy: NSURLRequestCachePolicy = msg![env; request cachePolicy];
+    if cache_policy != NSURLRequestReloadIgnoringLocalCacheData {
+        let cache: id = msg_class![env; NSURLCache sharedURLCache];
+        let cached: id = msg![env; cache cachedResponseForRequest:request];
+        if cached != nil {
+            // The cache could evict these while the app is still using them.
+            let response: id = msg![env; cached response];
+            let data: id = msg![env; cached data];
+            retain(env, response);
+            autorelease(env, response);
+            retain(env, data);
+            autorelease(env, data);
+            let http_response_class = env.objc.get_known_class("NSHTTPURLResponse", &mut env.mem);
+            let is_http: bool = msg![env; response isKindOfClass:http_response_class];
+            if is_http {
+                update_from_response(env, request, response, data);
+            }
+            if !response_out.is_null() {
+                env.mem.write(response_out, response);
+            }
+            return data;
+        }
+    }
+
+    let url: id = msg![env; request URL];
+    let should_handle_cookies: bool = msg![env; request HTTPShouldHandleCookies];
+    let cookie = if should_handle_cookies {
+        request_header_for_url(env, url)
+    } else {
+        None
+    };
+    let url: id = msg![env; url absoluteString];
+    log!(
+        "TODO: No network access, failing request for {:?} (cookies: {:?})",
+        to_rust_string(env, url),
+        cookie,
+    );
+    if !response_out.is_null() {
+        env.mem.write(response_out, nil);
+    }
+    set_error(env, error_out, NSURLErrorDomain, NSURLErrorNotConnectedToInternet);
+    nil
+}
+
+@end
+
+};
@@ -0,0 +1,293 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `NSURLRequest` and `NSMutableURLRequest`.
+
+use super::ns_dictionary::{dict_from_keys_and_objects, DictionaryHostObject};
+use super::ns_string::{from_rust_string, to_rust_string};
+use super::{NSTimeInterval, NSUInteger};
+use crate::objc::{
+    autorelease, id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject,
+    NSZonePtr,
+};
+use crate::Environment;
+
+pub type NSURLRequestCachePolicy = NSUInteger;
+pub const NSURLRequestUseProtocolCachePolicy: NSURLRequestCachePolicy = 0;
+pub const NSURLRequestReloadIgnoringLocalCacheData: NSURLRequestCachePolicy = 1;
+#[allow(dead_code)]
+pub const NSURLRequestReturnCacheDataElseLoad: NSURLRequestCachePolicy = 2;
+#[allow(dead_code)]
+pub const NSURLRequestReturnCacheDataDontLoad: NSURLRequestCachePolicy = 3;
+
+struct NSURLRequestHostObject {
+    /// `NSURL*`
+    url: id,
+    cache_policy: NSURLRequestCachePolicy,
+    timeout_interval: NSTimeInterval,
+    http_method: String,
+    /// Header fields in the order they were first set. Names are matched
+    /// case-insensitively, but keep the case they were set with.
+    header_fields: Vec<(String, String)>,
+    should_handle_cookies: bool,
+    /// `NSData*`
+    body: id,
+}
+impl HostObject for NSURLRequestHostObject {}
+impl Default for NSURLRequestHostObject {
+    fn default() -> Self {
+        NSURLRequestHostObject {
+            url: nil,
+            cache_policy: NSURLRequestUseProtocolCachePolicy,
+            timeout_interval: 60.0,
+            http_method: String::from("GET"),
+            header_fields: Vec::new(),
+            should_handle_cookies: true,
+            body: nil,
+        }
+    }
+}
+impl NSURLRequestHostObject {
+    fn header_field(&self, name: &str) -> Option<&str> {
+        self.header_fields
+            .iter()
+            .find(|(existing, _)| existing.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+    fn set_header_field(&mut self, name: String, value: Option<String>) {
+        let existing = self
+            .header_fields
+            .iter()
+            .position(|(existing, _)| existing.eq_ignore_ascii_case(&name));
+        match (existing, value) {
+            (Some(idx), Some(value)) => self.header_fields[idx].1 = value,
+            (Some(idx), None) => _ = self.header_fields.remove(idx),
+            (None, Some(value)) => self.header_fields.push((name, value)),
+            (None, None) => (),
+        }
+    }
+}
+
+/// Copy a request's state for a new request object, retaining the objects.
+fn clone_retained(env: &mut Environment, request: id) -> NSURLRequestHostObject {
+    let host_obj = env.objc.borrow::<NSURLRequestHostObject>(request);
+    let new = NSURLRequestHostObject {
+        http_method: host_obj.http_method.clone(),
+        header_fields: host_obj.header_fields.clone(),
+        ..*host_obj
+    };
+    retain(env, new.url);
+    retain(env, new.body);
+    new
+}
+
+/// Read the string keys and values of an `NSDictionary*` of HTTP header
+/// fields. A nil dictionary has no fields.
+pub(super) fn header_fields_from_dict(env: &mut Environment, dict: id) -> Vec<(String, String)> {
+    if dict == nil {
+        return Vec::new();
+    }
+    let keys: Vec<id> = env
+        .objc
+        .borrow::<DictionaryHostObject>(dict)
+        .iter_keys()
+        .collect();
+    keys.into_iter()
+        .map(|key| {
+            let value: id = msg![env; dict objectForKey:key];
+            let key = to_rust_string(env, key).into_owned();
+            let value = to_rust_string(env, value).into_owned();
+            (key, value)
+        })
+        .collect()
+}
+
+/// Make an autoreleased `NSDictionary*` of HTTP header fields.
+pub(super) fn header_fields_to_dict(env: &mut Environment, fields: &[(String, String)]) -> id {
+    let pairs: Vec<(id, id)> = fields
+        .iter()
+        .map(|(key, value)| {
+            let key = from_rust_string(env, key.clone());
+            let value = from_rust_string(env, value.clone());
+            (key, value)
+        })
+        .collect();
+    let dict = dict_from_keys_and_objects(env, &pairs);
+    for (key, value) in pairs {
+        release(env, key);
+        release(env, value);
+    }
+    autorelease(env, dict)
+}
+
+/// Shortcut for host code: the value of a request's header field, if set.
+pub fn header_field(env: &mut Environment, request: id, name: &str) -> Option<String> {
+    let host_obj = env.objc.borrow::<NSURLRequestHostObject>(request);
+    host_obj.header_field(name).map(String::from)
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation NSURLRequest: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::<NSURLRequestHostObject>::default();
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
++ (id)requestWithURL:(id)url { // NSURL*
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initWithURL:url];
+    autorelease(env, new)
+}
++ (id)requestWithURL:(id)url // NSURL*
+         cachePolicy:(NSURLRequestCachePolicy)cache_policy
+     timeoutInterval:(NSTimeInterval)timeout_interval {
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initWithURL:url
+                                cachePolicy:cache_policy
+                            timeoutInterval:timeout_interval];
+    autorelease(env, new)
+}
+
+- (id)initWithURL:(id)url { // NSURL*
+    msg![env; this initWithURL:url
+                   cachePolicy:NSURLRequestUseProtocolCachePolicy
+               timeoutInterval:60.0]
+}
+- (id)initWithURL:(id)url // NSURL*
+      cachePolicy:(NSURLRequestCachePolicy)cache_policy
+  timeoutInterval:(NSTimeInterval)timeout_interval {
+    let url: id = msg![env; url copy];
+    let host_obj = env.objc.borrow_mut::<NSURLRequestHostObject>(this);
+    host_obj.url = url;
+    host_obj.cache_policy = cache_policy;
+    host_obj.timeout_interval = timeout_interval;
+    this
+}
+
+- (())dealloc {
+    let NSURLRequestHostObject { url, body, .. } = std::mem::take(env.objc.borrow_mut(this));
+    release(env, url);
+    release(env, body);
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+// NSCopying implementation
+- (id)copyWithZone:(NSZonePtr)_zone {
+    let host_obj = clone_retained(env, this);
+    let new: id = msg_class![env; NSURLRequest alloc];
+    *env.objc.borrow_mut(new) = host_obj;
+    new
+}
+// NSMutableCopying implementation
+- (id)mutableCopyWithZone:(NSZonePtr)_zone {
+    let host_obj = clone_retained(env, this);
+    let new: id = msg_class![env; NSMutableURLRequest alloc];
+    *env.objc.borrow_mut(new) = host_obj;
+    new
+}
+
+- (id)URL {
+    env.objc.borrow::<NSURLRequestHostObject>(this).url
+}
+- (NSURLRequestCachePolicy)cachePolicy {
+    env.objc.borrow::<NSURLRequestHostObject>(this).cache_policy
+}
+- (NSTimeInterval)timeoutInterval {
+    env.objc.borrow::<NSURLRequestHostObject>(this).timeout_interval
+}
+- (bool)HTTPShouldHandleCookies {
+    env.objc.borrow::<NSURLRequestHostObject>(this).should_handle_cookies
+}
+- (id)HTTPBody {
+    env.objc.borrow::<NSURLRequestHostObject>(this).body
+}
+
+- (id)HTTPMethod {
+    let method = env.objc.borrow::<NSURLRequestHostObject>(this).http_method.clone();
+    let method = from_rust_string(env, method);
+    autorelease(env, method)
+}
+
+- (id)allHTTPHeaderFields {
+    let host_obj = env.objc.borrow::<NSURLRequestHostObject>(this);
+    let fields = host_obj.header_fields.clone();
+    header_fields_to_dict(env, &fields)
+}
+- (id)valueForHTTPHeaderField:(id)field { // NSString*
+    let field = to_rust_string(env, field);
+    match header_field(env, this, &field) {
+        Some(value) => {
+            let value = from_rust_string(env, value);
+            autorelease(env, value)
+        }
+        None => nil,
+    }
+}
+
+@end
+
+@implementation NSMutableURLRequest: NSURLRequest
+
+- (())setURL:(id)url { // NSURL*
+    let url: id = msg![env; url copy];
+    let host_obj = env.objc.borrow_mut::<NSURLRequestHostObject>(this);
+    let old = std::mem::replace(&mut host_obj.url, url);
+    release(env, old);
+}
+- (())setCachePolicy:(NSURLRequestCachePolicy)cache_policy {
+    env.objc.borrow_mut::<NSURLRequestHostObject>(this).cache_policy = cache_policy;
+}
+- (())setTimeoutInterval:(NSTimeInterval)timeout_interval {
+    env.objc.borrow_mut::<NSURLRequestHostObject>(this).timeout_interval = timeout_interval;
+}
+- (())setHTTPShouldHandleCookies:(bool)should_handle_cookies {
+    env.objc.borrow_mut::<NSURLRequestHostObject>(this).should_handle_cookies =
+        should_handle_cookies;
+}
+- (())setHTTPBody:(id)body { // NSData*
+    let body: id = msg![env; body copy];
+    let host_obj = env.objc.borrow_mut::<NSURLRequestHostObject>(this);
+    let old = std::mem::replace(&mut host_obj.body, body);
+    release(env, old);
+}
+- (())setHTTPMethod:(id)method { // NSString*
+    let method = to_rust_string(env, method).into_owned();
+    env.objc.borrow_mut::<NSURLRequestHostObject>(this).http_method = method;
+}
+
+- (())setAllHTTPHeaderFields:(id)fields { // NSDictionary*
+    let fields = header_fields_from_dict(env, fields);
+    let host_obj = env.objc.borrow_mut::<NSURLRequestHostObject>(this);
+    for (name, value) in fields {
+        host_obj.set_header_field(name, Some(value));
+    }
+}
+- (())setValue:(id)value // NSString*
+forHTTPHeaderField:(id)field { // NSString*
+    let field = to_rust_string(env, field).into_owned();
+    let value = (value != nil).then(|| to_rust_string(env, value).into_owned());
+    let host_obj = env.objc.borrow_mut::<NSURLRequestHostObject>(this);
+    host_obj.set_header_field(field, value);
+}
+- (())addValue:(id)value // NSString*
+forHTTPHeaderField:(id)field { // NSString*
+    let field = to_rust_string(env, field).into_owned();
+    let value = to_rust_string(env, value).into_owned();
+    let host_obj = env.objc.borrow_mut::<NSURLRequestHostObject>(this);
+    // Repeated fields are combined with commas, like HTTP allows.
+    let value = match host_obj.header_field(&field) {
+        Some(existing) => format!("{},{}", existing, value),
+        None => value,
+    };
+    host_obj.set_header_field(field, Some(value));
+}
+
+@end
+
+};
@@ -0,0 +1,142 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `NSURLResponse` and `NSHTTPURLResponse`.
+
+use super::ns_string::from_rust_string;
+use super::ns_url_request::{header_fields_from_dict, header_fields_to_dict};
+use super::NSInteger;
+use crate::objc::{id, msg, nil, objc_classes, release, ClassExports, HostObject, NSZonePtr};
+use crate::Environment;
+
+/// Value of `expectedContentLength` when the length isn't known.
+const NSURLResponseUnknownLength: i64 = -1;
+
+#[derive(Default)]
+struct NSURLResponseHostObject {
+    /// `NSURL*`
+    url: id,
+    /// `NSString*`
+    mime_type: id,
+    expected_content_length: i64,
+    /// `NSString*`
+    text_encoding_name: id,
+    /// Only used by `NSHTTPURLResponse`.
+    status_code: NSInteger,
+    /// Only used by `NSHTTPURLResponse`.
+    header_fields: Vec<(String, String)>,
+}
+impl HostObject for NSURLResponseHostObject {}
+
+/// Shortcut for host code: the header fields of an `NSHTTPURLResponse*`.
+pub fn header_fields(env: &mut Environment, response: id) -> Vec<(String, String)> {
+    let host_obj = env.objc.borrow::<NSURLResponseHostObject>(response);
+    host_obj.header_fields.clone()
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation NSURLResponse: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::<NSURLResponseHostObject>::default();
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+- (id)initWithURL:(id)url // NSURL*
+         MIMEType:(id)mime_type // NSString*
+expectedContentLength:(NSInteger)length
+ textEncodingName:(id)text_encoding_name { // NSString*
+    let url: id = msg![env; url copy];
+    let mime_type: id = msg![env; mime_type copy];
+    let text_encoding_name: id = msg![env; text_encoding_name copy];
+    let host_obj = env.objc.borrow_mut::<NSURLResponseHostObject>(this);
+    host_obj.url = url;
+    host_obj.mime_type = mime_type;
+    host_obj.expected_content_length = length.into();
+    host_obj.text_encoding_name = text_encoding_name;
+    this
+}
+
+- (())dealloc {
+    let NSURLResponseHostObject {
+        url,
+        mime_type,
+        text_encoding_name,
+        ..
+    } = std::mem::take(env.objc.borrow_mut(this));
+    release(env, url);
+    release(env, mime_type);
+    release(env, text_encoding_name);
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+// NSCopying implementation
+- (id)copyWithZone:(NSZonePtr)_zone {
+    // Responses are immutable.
+    msg![env; this retain]
+}
+
+- (id)URL {
+    env.objc.borrow::<NSURLResponseHostObject>(this).url
+}
+- (id)MIMEType {
+    env.objc.borrow::<NSURLResponseHostObject>(this).mime_type
+}
+- (i64)expectedContentLength {
+    env.objc.borrow::<NSURLResponseHostObject>(this).expected_content_length
+}
+- (id)textEncodingName {
+    env.objc.borrow::<NSURLResponseHostObject>(this).text_encoding_name
+}
+
+@end
+
+@implementation NSHTTPURLResponse: NSURLResponse
+
+- (id)initWithURL:(id)url // NSURL*
+       statusCode:(NSInteger)status_code
+      HTTPVersion:(id)_http_version // NSString*
+     headerFields:(id)fields { // NSDictionary*
+    let fields = header_fields_from_dict(env, fields);
+    let mime_type = fields
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("Content-Type"))
+        .and_then(|(_, value)| value.split(';').next())
+        .map(|mime_type| mime_type.trim().to_string());
+    let length = fields
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("Content-Length"))
+        .and_then(|(_, value)| value.trim().parse().ok())
+        .unwrap_or(NSURLResponseUnknownLength);
+
+    let url: id = msg![env; url copy];
+    let mime_type = match mime_type {
+        Some(mime_type) => from_rust_string(env, mime_type),
+        None => nil,
+    };
+    let host_obj = env.objc.borrow_mut::<NSURLResponseHostObject>(this);
+    host_obj.url = url;
+    host_obj.mime_type = mime_type;
+    host_obj.expected_content_length = length;
+    host_obj.status_code = status_code;
+    host_obj.header_fields = fields;
+    this
+}
+
+- (NSInteger)statusCode {
+    env.objc.borrow::<NSURLResponseHostObject>(this).status_code
+}
+
+- (id)allHeaderFields {
+    let fields = header_fields(env, this);
+    header_fields_to_dict(env, &fields)
+}
+
+@end
+
+};
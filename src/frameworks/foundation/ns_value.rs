@@ -5,15 +5,26 @@
  */
 //! The `NSValue` class cluster, including `NSNumber`.
 
+use super::ns_unarchiver::{self, TypedValue};
 use super::{NSInteger, NSUInteger};
+use crate::frameworks::core_graphics::cg_affine_transform::CGAffineTransform;
 use crate::frameworks::foundation::ns_string::from_rust_string;
 use crate::objc::{
     autorelease, id, msg, msg_class, objc_classes, retain, Class, ClassExports, HostObject,
     NSZonePtr,
 };
+use crate::Environment;
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+
+#[derive(Default)]
+pub struct State {
+    /// Shared instances returned for small integers, see [cached_integer].
+    cached_integers: HashMap<i32, id>,
+}
 
 #[derive(Debug)]
-enum NSNumberHostObject {
+pub(super) enum NSNumberHostObject {
     Bool(bool),
     Int(i32),
     UnsignedLongLong(u64),
@@ -22,15 +33,118 @@ enum NSNumberHostObject {
     Double(f64),
 }
 impl HostObject for NSNumberHostObject {}
+impl NSNumberHostObject {
+    /// Get the value in a form that can be hashed and compared regardless of
+    /// the type it was boxed with, since e.g. `@1` and `@1.0` are equal.
+    fn comparison_key(&self) -> NumberComparisonKey {
+        match *self {
+            NSNumberHostObject::Bool(value) => NumberComparisonKey::Integer(value.into()),
+            NSNumberHostObject::Int(value) => NumberComparisonKey::Integer(value.into()),
+            NSNumberHostObject::UnsignedLongLong(value) => {
+                NumberComparisonKey::Integer(value.into())
+            }
+            NSNumberHostObject::LongLong(value) => NumberComparisonKey::Integer(value.into()),
+            NSNumberHostObject::Float(value) => NumberComparisonKey::from_float(value.into()),
+            NSNumberHostObject::Double(value) => NumberComparisonKey::from_float(value),
+        }
+    }
+}
+
+#[derive(PartialEq)]
+enum NumberComparisonKey {
+    Integer(i128),
+    Float(f64),
+}
+impl NumberComparisonKey {
+    fn from_float(value: f64) -> NumberComparisonKey {
+        // Floats beyond this range are always integers, but can't necessarily
+        // be converted to an i128 losslessly.
+        if value.fract() == 0.0 && value.abs() < 2f64.powi(100) {
+            NumberComparisonKey::Integer(value as i128)
+        } else {
+            NumberComparisonKey::Float(value)
+        }
+    }
+}
+
+/// Get the comparison key for any `NSNumber`. `NSDecimalNumber` has its own
+/// host object, so its value has to be asked for with a message.
+fn comparison_key(env: &mut Environment, number: id) -> NumberComparisonKey {
+    let decimal_class = env.objc.get_known_class("NSDecimalNumber", &mut env.mem);
+    if msg![env; number isKindOfClass:decimal_class] {
+        let value: f64 = msg![env; number doubleValue];
+        NumberComparisonKey::from_float(value)
+    } else {
+        env.objc
+            .borrow::<NSNumberHostObject>(number)
+            .comparison_key()
+    }
+}
+
+/// Hash a number by its value, so that equal numbers of different types and
+/// classes have the same hash.
+pub(super) fn hash_number_value(value: f64) -> NSUInteger {
+    hash_comparison_key(NumberComparisonKey::from_float(value))
+}
+
+fn hash_comparison_key(key: NumberComparisonKey) -> NSUInteger {
+    match key {
+        NumberComparisonKey::Integer(value) => super::hash_helper(&value),
+        NumberComparisonKey::Float(value) => super::hash_helper(&value.to_bits()),
+    }
+}
+
+/// Integers for which `numberWithInt:` and `numberWithInteger:` return a shared
+/// instance rather than allocating a new one. Apple does the same for a smaller
+/// range, but apps that box lots of small integers (e.g. tile indices) benefit
+/// from a bigger one.
+const CACHED_INTEGERS: RangeInclusive<i32> = -128..=1023;
+
+/// Get the shared `NSNumber` instance for an integer, if it's in
+/// [CACHED_INTEGERS]. The cache owns a reference to each instance, so they are
+/// never deallocated, and callers don't own the returned object.
+fn cached_integer(env: &mut Environment, value: i32) -> Option<id> {
+    if !CACHED_INTEGERS.contains(&value) {
+        return None;
+    }
+    let state = &env.framework_state.foundation.ns_value;
+    if let Some(&number) = state.cached_integers.get(&value) {
+        return Some(number);
+    }
+    let number: id = msg_class![env; NSNumber alloc];
+    let number: id = msg![env; number initWithInt:value];
+    env.framework_state
+        .foundation
+        .ns_value
+        .cached_integers
+        .insert(value, number);
+    Some(number)
+}
+
+/// Host object for `_touchHLE_NSValue`, the concrete class used for boxed
+/// structs.
+#[derive(Debug)]
+enum NSValueHostObject {
+    CGAffineTransform(CGAffineTransform),
+}
+impl HostObject for NSValueHostObject {}
 
 pub const CLASSES: ClassExports = objc_classes! {
 
 (env, this, _cmd);
 
-// NSValue is an abstract class. None of the things it should provide are
+// NSValue is an abstract class. Most of the things it should provide are not
 // implemented here yet (TODO).
 @implementation NSValue: NSObject
 
+// These are from a category declared in UIKit's UIGeometry.h.
++ (id)valueWithCGAffineTransform:(CGAffineTransform)transform {
+    let host_object = Box::new(NSValueHostObject::CGAffineTransform(transform));
+    let class = env.objc.get_known_class("_touchHLE_NSValue", &mut env.mem);
+    let new = env.objc.alloc_object(class, host_object, &mut env.mem);
+    autorelease(env, new)
+}
+
 // NSCopying implementation
 - (id)copyWithZone:(NSZonePtr)_zone {
     retain(env, this)
@@ -38,6 +152,16 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 @end
 
+// Private subclass of NSValue that holds a struct.
+@implementation _touchHLE_NSValue: NSValue
+
+- (CGAffineTransform)CGAffineTransformValue {
+    let &NSValueHostObject::CGAffineTransform(transform) = env.objc.borrow(this);
+    transform
+}
+
+@end
+
 // NSNumber is not an abstract class.
 @implementation NSNumber: NSValue
 
@@ -55,7 +179,12 @@ pub const CLASSES: ClassExports = objc_classes! {
 }
 
 + (id)numberWithInteger:(NSInteger)value {
-    // TODO: for greater efficiency we could return a static-lifetime value
+    // Subclasses must get an instance of their own class.
+    if this == env.objc.get_known_class("NSNumber", &mut env.mem) {
+        if let Some(number) = cached_integer(env, value) {
+            return number;
+        }
+    }
 
     let new: id = msg![env; this alloc];
     let new: id = msg![env; new initWithInteger:value];
@@ -63,7 +192,12 @@ pub const CLASSES: ClassExports = objc_classes! {
 }
 
 + (id)numberWithInt:(i32)value {
-    // TODO: for greater efficiency we could return a static-lifetime value
+    // Subclasses must get an instance of their own class.
+    if this == env.objc.get_known_class("NSNumber", &mut env.mem) {
+        if let Some(number) = cached_integer(env, value) {
+            return number;
+        }
+    }
 
     let new: id = msg![env; this alloc];
     let new: id = msg![env; new initWithInt:value];
@@ -139,23 +273,46 @@ pub const CLASSES: ClassExports = objc_classes! {
     this
 }
 
-- (id)description {
-    match env.objc.borrow(this) {
-        NSNumberHostObject::Bool(value) => from_rust_string(env, (*value as i32).to_string()),
-        NSNumberHostObject::Int(value) => from_rust_string(env, value.to_string()),
-        NSNumberHostObject::UnsignedLongLong(value) => from_rust_string(env, value.to_string()),
-        NSNumberHostObject::LongLong(value) => from_rust_string(env, value.to_string()),
-        NSNumberHostObject::Float(value) => from_rust_string(env, value.to_string()),
-        NSNumberHostObject::Double(value) => from_rust_string(env, value.to_string())
+// NSCoding implementation
+- (id)initWithCoder:(id)coder {
+    // TODO: keyed archives
+    assert!(!msg![env; coder allowsKeyedCoding]);
+
+    // NSArchiver writes the type encoding followed by a value of that type.
+    let TypedValue::CString(Some(ty)) = ns_unarchiver::decode_value(env, coder, b"*") else {
+        panic!("NSNumber archived without a type");
+    };
+    // The initializers are used rather than setting the host object directly,
+    // since subclasses like NSDecimalNumber have their own.
+    match ns_unarchiver::decode_value(env, coder, &ty) {
+        TypedValue::Integer(value) => match ty[0] {
+            b'q' | b'L' | b'I' => msg![env; this initWithLongLong:value],
+            b'Q' => msg![env; this initWithUnsignedLongLong:(value as u64)],
+            _ => msg![env; this initWithInt:(value as i32)],
+        },
+        TypedValue::Float(value) if ty[0] == b'f' => msg![env; this initWithFloat:(value as f32)],
+        TypedValue::Float(value) => msg![env; this initWithDouble:value],
+        _ => unimplemented!("NSNumber of type {:?}", String::from_utf8_lossy(&ty)),
     }
 }
-- (NSUInteger)hash {
-    let &NSNumberHostObject::Bool(value) = env.objc.borrow(this) else {
-        todo!();
+
+- (id)description {
+    let description = match env.objc.borrow(this) {
+        NSNumberHostObject::Bool(value) => (*value as i32).to_string(),
+        NSNumberHostObject::Int(value) => value.to_string(),
+        NSNumberHostObject::UnsignedLongLong(value) => value.to_string(),
+        NSNumberHostObject::LongLong(value) => value.to_string(),
+        NSNumberHostObject::Float(value) => value.to_string(),
+        NSNumberHostObject::Double(value) => value.to_string()
     };
-    super::hash_helper(&value)
+    let description = from_rust_string(env, description);
+    autorelease(env, description)
+}
+- (NSUInteger)hash {
+    let key = env.objc.borrow::<NSNumberHostObject>(this).comparison_key();
+    hash_comparison_key(key)
 }
-- (bool)isEqualTo:(id)other {
+- (bool)isEqual:(id)other {
     if this == other {
         return true;
     }
@@ -163,12 +320,8 @@ pub const CLASSES: ClassExports = objc_classes! {
     if !msg![env; other isKindOfClass:class] {
         return false;
     }
-    let &NSNumberHostObject::Bool(a) = env.objc.borrow(this) else {
-        todo!();
-    };
-    let &NSNumberHostObject::Bool(b) = env.objc.borrow(other) else {
-        todo!();
-    };
+    let a = env.objc.borrow::<NSNumberHostObject>(this).comparison_key();
+    let b = comparison_key(env, other);
     a == b
 }
 
@@ -188,6 +341,17 @@ pub const CLASSES: ClassExports = objc_classes! {
     }
 }
 
+- (f64)doubleValue {
+    match *env.objc.borrow(this) {
+        NSNumberHostObject::Bool(value) => value as i32 as f64,
+        NSNumberHostObject::Int(value) => value as f64,
+        NSNumberHostObject::UnsignedLongLong(value) => value as f64,
+        NSNumberHostObject::LongLong(value) => value as f64,
+        NSNumberHostObject::Float(value) => value as f64,
+        NSNumberHostObject::Double(value) => value,
+    }
+}
+
 // TODO: accessors etc
 
 @end
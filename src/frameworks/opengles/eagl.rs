@@ -243,6 +243,7 @@ pub const CLASSES: ClassExports = objc_classes! {
     // current, but it would be weird if it didn't?
     let window = env.window.as_mut().expect("OpenGL ES is not supported in headless mode");
     let mut need_return = false;
+    let mut presented_directly = false;
     super::sync_context(&mut env.framework_state.opengles, &mut env.objc, window, env.current_thread, |gles, objc, window| {
         let renderbuffer: GLuint = unsafe {
             let mut renderbuffer = 0;
@@ -267,6 +268,7 @@ pub const CLASSES: ClassExports = objc_classes! {
             unsafe {
                 present_renderbuffer(gles, window);
             }
+            presented_directly = true;
             // // re-borrow
             // super::sync_context(&mut env.framework_state.opengles, &mut env.objc, env.window.as_mut().unwrap(), env.current_thread, |gles| {
             //
@@ -322,6 +324,10 @@ pub const CLASSES: ClassExports = objc_classes! {
             }
         }
     });
+    // Otherwise the frame is counted when it's composited.
+    if presented_directly {
+        crate::libc::time::count_presented_frame(env);
+    }
     if need_return {
         if let Some(sleep_for) = sleep_for {
             env.sleep(sleep_for, /* tail_call: */ false);
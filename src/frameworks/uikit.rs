@@ -38,9 +38,11 @@ pub struct State {
     ui_device: ui_device::State,
     ui_font: ui_font::State,
     ui_graphics: ui_graphics::State,
+    ui_nib: ui_nib::State,
     ui_screen: ui_screen::State,
     ui_touch: ui_touch::State,
     pub ui_view: ui_view::State,
+    ui_view_controller: ui_view_controller::State,
 }
 
 /// For use by `NSRunLoop`: handles any events that have queued up.
@@ -87,6 +89,10 @@ pub fn handle_events(env: &mut Environment) -> Option<Instant> {
                 log!("Handling app-will-terminate event.");
                 ui_application::exit(env);
             }
+            Event::MemoryWarning => {
+                log!("Handling MemoryWarning event.");
+                ui_application::send_memory_warning(env);
+            }
             Event::EnterDebugger => {
                 if env.is_debugging_enabled() {
                     log!("Handling EnterDebugger event: entering debugger.");
@@ -99,5 +105,7 @@ pub fn handle_events(env: &mut Environment) -> Option<Instant> {
         }
     }
 
+    ui_application::check_memory_usage(env);
+
     ui_accelerometer::handle_accelerometer(env)
 }
@@ -6,8 +6,9 @@
 //! `UIApplication` and `UIApplicationMain`.
 
 use super::ui_device::*;
-use crate::dyld::{export_c_func, FunctionExports};
-use crate::frameworks::foundation::{ns_array, ns_string, NSInteger, NSTimeInterval};
+use super::ui_view_controller;
+use crate::dyld::{export_c_func, ConstantExports, FunctionExports, HostConstant};
+use crate::frameworks::foundation::{ns_array, ns_cache, ns_string, NSInteger, NSTimeInterval};
 use crate::frameworks::uikit::ui_nib::load_main_nib_file;
 use crate::mem::MutPtr;
 use crate::objc::{
@@ -22,6 +23,9 @@ pub struct State {
     /// [UIApplication sharedApplication]
     shared_application: Option<id>,
     pub(super) status_bar_hidden: bool,
+    /// Whether a memory warning has been sent since memory usage last crossed
+    /// the threshold set by `--memory-warning-threshold=`.
+    memory_warning_sent: bool,
 }
 
 struct UIApplicationHostObject {
@@ -32,6 +36,15 @@ impl HostObject for UIApplicationHostObject {}
 
 type UIInterfaceOrientation = UIDeviceOrientation;
 
+pub const UIApplicationDidReceiveMemoryWarningNotification: &str =
+    "UIApplicationDidReceiveMemoryWarningNotification";
+
+/// `NSNotificationName` values.
+pub const CONSTANTS: ConstantExports = &[(
+    "_UIApplicationDidReceiveMemoryWarningNotification",
+    HostConstant::NSString(UIApplicationDidReceiveMemoryWarningNotification),
+)];
+
 pub const CLASSES: ClassExports = objc_classes! {
 
 (env, this, _cmd);
@@ -76,7 +89,9 @@ pub const CLASSES: ClassExports = objc_classes! {
     }
 }
 
-// TODO: statusBarHidden getter
+- (bool)isStatusBarHidden {
+    env.framework_state.uikit.ui_application.status_bar_hidden
+}
 - (())setStatusBarHidden:(bool)hidden {
     env.framework_state.uikit.ui_application.status_bar_hidden = hidden;
 }
@@ -114,9 +129,6 @@ pub const CLASSES: ClassExports = objc_classes! {
 - (NSTimeInterval)statusBarOrientationAnimationDuration {
     0.0
 }
-- (bool)isStatusBarHidden {
-    true
-}
 
 - (bool)isIdleTimerDisabled {
     !env.window().is_screen_saver_enabled()
@@ -164,6 +176,12 @@ pub const CLASSES: ClassExports = objc_classes! {
         .unwrap()
 }
 
+// Apparently an undocumented, private API. It's used to simulate memory
+// warnings when debugging.
+- (())_performMemoryWarning {
+    send_memory_warning(env);
+}
+
 - (id)windows {
     log!("TODO: UIApplication's windows getter is returning only visible windows");
     let visible_windows: Vec<id> = (*env
@@ -197,6 +215,8 @@ pub(super) fn UIApplicationMain(
     // It's not clear what granularity this should happen with, but this
     // granularity has already caught several bugs. :)
 
+    env.framework_state.uikit.ui_application.status_bar_hidden = env.bundle.status_bar_hidden();
+
     let ui_application = {
         let pool: id = msg_class![env; NSAutoreleasePool new];
 
@@ -292,6 +312,61 @@ pub(super) fn UIApplicationMain(
     let _: () = msg![env; run_loop run];
 }
 
+/// Tell the app that memory is running low, so it can free what it doesn't
+/// need. This also empties our own caches.
+pub(super) fn send_memory_warning(env: &mut Environment) {
+    log!("Sending memory warning to app.");
+    let (zombie_count, zombie_bytes) = env.objc.zombie_stats();
+    if zombie_count > 0 {
+        log!(
+            "Note: {} zombie objects are keeping {} bytes of memory allocated.",
+            zombie_count,
+            zombie_bytes
+        );
+    }
+
+    let pool: id = msg_class![env; NSAutoreleasePool new];
+
+    ns_cache::handle_memory_warning(env);
+
+    let ui_application: id = msg_class![env; UIApplication sharedApplication];
+    let delegate: id = msg![env; ui_application delegate];
+    if delegate != nil
+        && env.objc.object_has_method_named(
+            &env.mem,
+            delegate,
+            "applicationDidReceiveMemoryWarning:",
+        )
+    {
+        () = msg![env; delegate applicationDidReceiveMemoryWarning:ui_application];
+    }
+
+    ui_view_controller::handle_memory_warning(env);
+
+    let name = ns_string::get_static_str(env, UIApplicationDidReceiveMemoryWarningNotification);
+    let center: id = msg_class![env; NSNotificationCenter defaultCenter];
+    () = msg![env; center postNotificationName:name object:ui_application];
+
+    let _: () = msg![env; pool drain];
+}
+
+/// Send a memory warning if the app's memory usage has crossed the threshold
+/// set by `--memory-warning-threshold=`. Only one warning is sent each time it
+/// crosses the threshold.
+pub(super) fn check_memory_usage(env: &mut Environment) {
+    let Some(threshold) = env.options.memory_warning_threshold else {
+        return;
+    };
+    let over_threshold = env.mem.used_bytes() > threshold;
+    let state = &mut env.framework_state.uikit.ui_application;
+    if !over_threshold {
+        state.memory_warning_sent = false;
+    } else if !state.memory_warning_sent {
+        state.memory_warning_sent = true;
+        send_memory_warning(env);
+    }
+}
+
 /// Tell the app it's about to quit and then exit.
 pub(super) fn exit(env: &mut Environment) {
     let ui_application: id = msg_class![env; UIApplication sharedApplication];
@@ -322,7 +397,8 @@ pub(super) fn exit(env: &mut Environment) {
         let _: () = msg![env; pool drain];
     };
 
-    std::process::exit(0);
+    crate::libc::stdlib::run_atexit_handlers(env, None);
+    crate::libc::stdlib::exit_process(env, 0);
 }
 
 pub const FUNCTIONS: FunctionExports = &[export_c_func!(UIApplicationMain(_, _, _, _))];
@@ -6,9 +6,8 @@
 //! `UIDevice`.
 
 use crate::frameworks::foundation::ns_string;
-use crate::frameworks::foundation::{NSInteger, NSUInteger, NSTimeInterval};
-use crate::objc::{id, nil, objc_classes, ClassExports, TrivialHostObject};
-use crate::mem::MutPtr;
+use crate::frameworks::foundation::NSInteger;
+use crate::objc::{id, objc_classes, ClassExports, TrivialHostObject};
 
 pub type UIDeviceOrientation = NSInteger;
 #[allow(dead_code)]
@@ -86,27 +85,4 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 @end
 
-@implementation NSMutableURLRequest: NSObject
-+ (id)requestWithURL:(id)url {
-    nil
-}
-+ (id)requestWithURL:(id)url
-         cachePolicy:(NSUInteger)policy
-     timeoutInterval:(NSTimeInterval)timeoutInterval {
-    nil
-}
-@end
-
-@implementation NSURLConnection: NSObject
-+ (id)sendSynchronousRequest:(id)request
-           returningResponse:(MutPtr<id>)response
-                       error:(MutPtr<id>)error {
-    nil
-}
-@end
-
-@implementation NSHTTPURLResponse: NSObject
-
-@end
-
 };
@@ -5,28 +5,29 @@
 use crate::dyld::{export_c_func, FunctionExports};
 use crate::frameworks::core_graphics::{CGPoint, CGRect, CGSize};
 use crate::frameworks::foundation::ns_string;
-use crate::objc::{autorelease, id};
+use crate::objc::{autorelease, id, nil};
 use crate::Environment;
 
 // Apple's documentation says all of these return zeroes if the input is not
-// well-formed.
-pub fn CGPointFromString(env: &mut Environment, string: id) -> CGPoint {
+// well-formed. A nil string is treated the same way.
+fn parse_or_default<T: std::str::FromStr + Default>(env: &mut Environment, string: id) -> T {
+    if string == nil {
+        return T::default();
+    }
     // TODO: avoid copy
     ns_string::to_rust_string(env, string)
         .parse()
         .unwrap_or_default()
 }
+
+pub fn CGPointFromString(env: &mut Environment, string: id) -> CGPoint {
+    parse_or_default(env, string)
+}
 pub fn CGSizeFromString(env: &mut Environment, string: id) -> CGSize {
-    // TODO: avoid copy
-    ns_string::to_rust_string(env, string)
-        .parse()
-        .unwrap_or_default()
+    parse_or_default(env, string)
 }
 pub fn CGRectFromString(env: &mut Environment, string: id) -> CGRect {
-    // TODO: avoid copy
-    ns_string::to_rust_string(env, string)
-        .parse()
-        .unwrap_or_default()
+    parse_or_default(env, string)
 }
 
 pub fn NSStringFromCGPoint(env: &mut Environment, point: CGPoint) -> id {
@@ -9,13 +9,21 @@
 //! - Apple's [Resource Programming Guide](https://developer.apple.com/library/archive/documentation/Cocoa/Conceptual/LoadingResources/CocoaNibs/CocoaNibs.html) is very helpful.
 //! - GitHub user 0xced's [reverse-engineering of UIClassSwapper](https://gist.github.com/0xced/45daf79b62ad6a20be1c).
 
+use crate::frameworks::foundation::ns_keyed_unarchiver::set_fallback_class;
 use crate::frameworks::foundation::ns_string::{get_static_str, to_rust_string};
-use crate::frameworks::foundation::{ns_string, NSUInteger};
+use crate::frameworks::foundation::{ns_array, ns_string, NSUInteger};
 use crate::objc::{
-    id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject,
+    autorelease, id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject,
 };
 use crate::Environment;
 
+#[derive(Default)]
+pub struct State {
+    /// Owners of the nib files currently being loaded, innermost last. These
+    /// are what the "File's Owner" proxy objects get replaced with.
+    file_owners: Vec<id>,
+}
+
 struct UIRuntimeOutletConnectionHostObject {
     destination: id,
     label: id,
@@ -41,25 +49,24 @@ pub const CLASSES: ClassExports = objc_classes! {
     let id = to_rust_string(env, id_nss);
 
     if id == "IBFilesOwner" {
-        // The file owner is usually the UIApplication instance.
-        // Replacing the proxy with that instance is important so that the
-        // "delegate" outlet can be connected between it and the
+        // The file owner is supplied by whoever is loading the nib, e.g. it is
+        // the UIApplication instance for the main nib file.
+        // Replacing the proxy with that object is important so that outlets
+        // like the "delegate" outlet can be connected between it and the
         // UIApplicationDelegate.
         //
         // TODO: This is a bit of a hack. Eventually it would be good to fix:
         // - The name "UIProxyObject" implies that it might be intended to
         //   proxy messages to another object, rather than be replaced by it.
         //   Check what iPhone OS does?
-        // - If/when the UINib class is implemented and arbitrary nib files can
-        //   be deserialized, an app could pick some other object to be the nib
-        //   file owner, which this would need to handle.
         // - If this object is meant to be replaced, it's probably not meant to
         //   be done via `initWithCoder:`, but instead by providing a delegate
         //   to the NSKeyedUnarchiver. That might be needed to implement
-        //   replacement for objects other than the UIApplication instance.
+        //   replacement for other external objects.
 
+        let owner = *env.framework_state.uikit.ui_nib.file_owners.last().unwrap();
         release(env, this);
-        msg_class![env; UIApplication sharedApplication]
+        retain(env, owner)
     } else {
         log!("TODO: UIProxyObject replacement for {}, instance {:?} left unreplaced", id, this);
         this
@@ -99,6 +106,21 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 @end
 
+// Another undocumented type used by nib files. This one stands in for an image
+// from the app bundle, e.g. the image of a UIImageView.
+@implementation UIImageNibPlaceholder: NSObject
+
+// NSCoding implementation
+- (id)initWithCoder:(id)coder {
+    let name_key = get_static_str(env, "UIResourceName");
+    let name: id = msg![env; coder decodeObjectForKey:name_key];
+    let image: id = msg_class![env; UIImage imageNamed:name];
+    release(env, this);
+    retain(env, image)
+}
+
+@end
+
 // Another undocumented type used by nib files. This one's purpose seems to be
 // to connect outlets once all the objects are deserialized.
 @implementation UIRuntimeOutletConnection: NSObject
@@ -170,23 +192,39 @@ pub const CLASSES: ClassExports = objc_classes! {
 /// return [nib instantiateWithOwner:[UIApplication sharedApplication]
 ///                     optionsOrNil:nil];
 /// ```
-pub fn load_main_nib_file(env: &mut Environment, _ui_application: id) {
+pub fn load_main_nib_file(env: &mut Environment, ui_application: id) {
     let Some(path) = env.bundle.main_nib_file_path() else {
         return;
     };
 
     let path = ns_string::from_rust_string(env, path.as_str().to_string());
     assert!(msg![env; path isAbsolutePath]);
-    let ns_data: id = msg_class![env; NSData dataWithContentsOfFile:path];
-    if ns_data == nil {
+    if load_nib_file(env, path, ui_application) == nil {
         // Apparently it's permitted to specify the nib file key in the
         // Info.plist, yet not have it point to a valid nib file?!
         log!("Warning: couldn't load main nib file");
-        return;
+    }
+    release(env, path);
+}
+
+/// Load the nib file at `path` (an `NSString*`), with `owner` as the file's
+/// owner. Returns the (autoreleased) `NSArray*` of top-level objects, or `nil`
+/// if the file couldn't be read.
+///
+/// Used by `loadNibNamed:owner:options:` and [load_main_nib_file].
+pub fn load_nib_file(env: &mut Environment, path: id, owner: id) -> id {
+    let ns_data: id = msg_class![env; NSData dataWithContentsOfFile:path];
+    if ns_data == nil {
+        return nil;
     };
 
     let unarchiver = msg_class![env; NSKeyedUnarchiver alloc];
     let unarchiver = msg![env; unarchiver initForReadingWithData:ns_data];
+    // Failing to load a whole nib because of e.g. a custom view class that
+    // hasn't been implemented would be unhelpful.
+    set_fallback_class(env, unarchiver, "UIView");
+
+    env.framework_state.uikit.ui_nib.file_owners.push(owner);
 
     // The top-level keys in a nib file's keyed archive appear to be
     // UINibAccessibilityConfigurationsKey, UINibConnectionsKey,
@@ -216,5 +254,20 @@ pub fn load_main_nib_file(env: &mut Environment, _ui_application: id) {
         () = msg![env; visible setHidden:false];
     }
 
+    // This is already autoreleased. It keeps the top-level objects alive once
+    // the unarchiver is gone, but only the owner's outlets retain them beyond
+    // that, as on iPhone OS.
+    let top_level_key = get_static_str(env, "UINibTopLevelObjectsKey");
+    let mut top_level_objects: id = msg![env; unarchiver decodeObjectForKey:top_level_key];
+    if top_level_objects == nil {
+        top_level_objects = ns_array::from_vec(env, Vec::new());
+        autorelease(env, top_level_objects);
+    }
+
+    let popped = env.framework_state.uikit.ui_nib.file_owners.pop();
+    assert!(popped == Some(owner));
+
     release(env, unarchiver);
+
+    top_level_objects
 }
@@ -47,6 +47,10 @@ pub(super) struct UIViewHostObject {
     clears_context_before_drawing: bool,
     user_interaction_enabled: bool,
     multiple_touch_enabled: bool,
+    tag: NSInteger,
+    /// `UIViewAutoresizing` flags. Stored but not yet acted upon.
+    autoresizing_mask: NSUInteger,
+    autoresizes_subviews: bool,
 }
 impl HostObject for UIViewHostObject {}
 impl Default for UIViewHostObject {
@@ -60,6 +64,9 @@ impl Default for UIViewHostObject {
             clears_context_before_drawing: true,
             user_interaction_enabled: true,
             multiple_touch_enabled: false,
+            tag: 0,
+            autoresizing_mask: 0,
+            autoresizes_subviews: true,
         }
     }
 }
@@ -158,18 +165,34 @@ pub const CLASSES: ClassExports = objc_classes! {
     let key_ns_string = get_static_str(env, "UIOpaque");
     let opaque: bool = msg![env; coder decodeBoolForKey:key_ns_string];
 
+    let key_ns_string = get_static_str(env, "UITag");
+    let tag: NSInteger = msg![env; coder decodeIntegerForKey:key_ns_string];
+
+    let key_ns_string = get_static_str(env, "UIAutoresizingMask");
+    let autoresizing_mask: NSInteger = msg![env; coder decodeIntegerForKey:key_ns_string];
+
+    // This one defaults to true, so absence is not the same as false.
+    let key_ns_string = get_static_str(env, "UIAutoresizeSubviews");
+    let autoresizes_subviews: bool = if msg![env; coder containsValueForKey:key_ns_string] {
+        msg![env; coder decodeBoolForKey:key_ns_string]
+    } else {
+        true
+    };
+
     let key_ns_string = get_static_str(env, "UISubviews");
     let subviews: id = msg![env; coder decodeObjectForKey:key_ns_string];
     let subview_count: NSUInteger = msg![env; subviews count];
 
     log_dbg!(
-        "[(UIView*){:?} initWithCoder:{:?}] => bounds {}, center {}, hidden {}, opaque {}, {} subviews",
+        "[(UIView*){:?} initWithCoder:{:?}] => bounds {}, center {}, hidden {}, opaque {}, tag {}, autoresizing mask {:#x}, {} subviews",
         this,
         coder,
         bounds,
         center,
         hidden,
         opaque,
+        tag,
+        autoresizing_mask,
         subview_count,
     );
 
@@ -177,6 +200,9 @@ pub const CLASSES: ClassExports = objc_classes! {
     () = msg![env; this setCenter:center];
     () = msg![env; this setHidden:hidden];
     () = msg![env; this setOpaque:opaque];
+    () = msg![env; this setTag:tag];
+    () = msg![env; this setAutoresizingMask:(autoresizing_mask as NSUInteger)];
+    () = msg![env; this setAutoresizesSubviews:autoresizes_subviews];
 
     for i in 0..subview_count {
         let subview: id = msg![env; subviews objectAtIndex:i];
@@ -203,8 +229,41 @@ pub const CLASSES: ClassExports = objc_classes! {
 - (())setExclusiveTouch:(bool)enabled {
 
 }
-- (())setAutoresizesSubviews:(bool)enabled {
 
+- (NSInteger)tag {
+    env.objc.borrow::<UIViewHostObject>(this).tag
+}
+- (())setTag:(NSInteger)tag {
+    env.objc.borrow_mut::<UIViewHostObject>(this).tag = tag;
+}
+
+// Depth-first search of this view and its subviews.
+- (id)viewWithTag:(NSInteger)tag {
+    if env.objc.borrow::<UIViewHostObject>(this).tag == tag {
+        return this;
+    }
+    let subviews = env.objc.borrow::<UIViewHostObject>(this).subviews.clone();
+    for subview in subviews {
+        let view: id = msg![env; subview viewWithTag:tag];
+        if view != nil {
+            return view;
+        }
+    }
+    nil
+}
+
+// TODO: actually resize subviews when the bounds change
+- (NSUInteger)autoresizingMask {
+    env.objc.borrow::<UIViewHostObject>(this).autoresizing_mask
+}
+- (())setAutoresizingMask:(NSUInteger)mask {
+    env.objc.borrow_mut::<UIViewHostObject>(this).autoresizing_mask = mask;
+}
+- (bool)autoresizesSubviews {
+    env.objc.borrow::<UIViewHostObject>(this).autoresizes_subviews
+}
+- (())setAutoresizesSubviews:(bool)enabled {
+    env.objc.borrow_mut::<UIViewHostObject>(this).autoresizes_subviews = enabled;
 }
 
 - (())layoutSubviews {
@@ -299,6 +358,9 @@ pub const CLASSES: ClassExports = objc_classes! {
         clears_context_before_drawing: _,
         user_interaction_enabled: _,
         multiple_touch_enabled: _,
+        tag: _,
+        autoresizing_mask: _,
+        autoresizes_subviews: _,
     } = std::mem::take(env.objc.borrow_mut(this));
 
     release(env, layer);
@@ -490,10 +552,6 @@ pub const CLASSES: ClassExports = objc_classes! {
     () = msg![env; layer displayIfNeeded];
 }
 
-- (())setAutoresizingMask:(NSUInteger)mask {
-    log!("WARNING: Ignoring setAutoresizingMask: for an UIView");
-}
-
 - (bool)endEditing:(bool)_force {
     true
 }
@@ -7,11 +7,12 @@
 
 use super::{UIControlState, UIControlStateNormal};
 use crate::frameworks::core_graphics::{CGPoint, CGRect};
-use crate::frameworks::foundation::NSInteger;
+use crate::frameworks::foundation::ns_string::get_static_str;
+use crate::frameworks::foundation::{NSInteger, NSUInteger};
 use crate::frameworks::uikit::ui_font::UITextAlignmentCenter;
 use crate::objc::{
     autorelease, id, impl_HostObject_with_superclass, msg, msg_class, msg_super, nil, objc_classes,
-    release, retain, ClassExports, NSZonePtr,
+    release, retain, ClassExports, HostObject, NSZonePtr,
 };
 use crate::Environment;
 use std::collections::HashMap;
@@ -57,6 +58,14 @@ impl Default for UIButtonHostObject {
     }
 }
 
+struct UIButtonContentHostObject {
+    /// `NSString*`
+    title: id,
+    /// `UIImage*`
+    image: id,
+}
+impl HostObject for UIButtonContentHostObject {}
+
 fn update(env: &mut Environment, this: id) {
     let title_label: id = msg![env; this titleLabel];
     let title: id = msg![env; this currentTitle];
@@ -69,6 +78,35 @@ fn update(env: &mut Environment, this: id) {
     () = msg![env; image_view setImage:image];
 }
 
+/// Shared parts of `initWithFrame:` and `initWithCoder:`.
+fn init_common(env: &mut Environment, this: id) {
+    () = msg![env; this setOpaque:false];
+    let bg_color: id = msg_class![env; UIColor clearColor];
+    () = msg![env; this setBackgroundColor:bg_color];
+
+    let title_label: id = msg_class![env; UILabel new];
+    () = msg![env; title_label setBackgroundColor:bg_color];
+    () = msg![env; title_label setTextAlignment:UITextAlignmentCenter];
+
+    let text_color: id = msg_class![env; UIColor whiteColor];
+
+    let image_view: id = msg_class![env; UIImageView new];
+
+    let host_obj = env.objc.borrow_mut::<UIButtonHostObject>(this);
+    host_obj.title_label = title_label;
+    host_obj.image_view = image_view;
+    host_obj.titles_for_states.insert(UIControlStateNormal, nil);
+    host_obj
+        .title_colors_for_states
+        .insert(UIControlStateNormal, text_color);
+    host_obj.images_for_states.insert(UIControlStateNormal, nil);
+
+    () = msg![env; this addSubview:title_label];
+    () = msg![env; this addSubview:image_view];
+
+    update(env, this);
+}
+
 pub const CLASSES: ClassExports = objc_classes! {
 
 (env, this, _cmd);
@@ -105,36 +143,36 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 - (id)initWithFrame:(CGRect)frame {
     let this: id = msg_super![env; this initWithFrame:frame];
+    init_common(env, this);
+    this
+}
 
-    () = msg![env; this setOpaque:false];
-    let bg_color: id = msg_class![env; UIColor clearColor];
-    () = msg![env; this setBackgroundColor:bg_color];
-
-    let title_label: id = msg_class![env; UILabel new];
-    () = msg![env; title_label setBackgroundColor:bg_color];
-    () = msg![env; title_label setTextAlignment:UITextAlignmentCenter];
-
-    let text_color: id = msg_class![env; UIColor whiteColor];
-
-    let image_view: id = msg_class![env; UIImageView new];
-
-    let host_obj = env.objc.borrow_mut::<UIButtonHostObject>(this);
-    host_obj.title_label = title_label;
-    host_obj.image_view = image_view;
-    host_obj.titles_for_states.insert(UIControlStateNormal, nil);
-    host_obj.title_colors_for_states.insert(UIControlStateNormal, text_color);
-    host_obj.images_for_states.insert(UIControlStateNormal, nil);
-
-    () = msg![env; this addSubview:title_label];
-    () = msg![env; this addSubview:image_view];
-
-    update(env, this);
+- (id)initWithCoder:(id)coder {
+    let this: id = msg_super![env; this initWithCoder:coder];
+    init_common(env, this);
+
+    // TODO: decode the button type, title colors, etc.
+    let key_ns_string = get_static_str(env, "UIButtonStatefulContent");
+    let contents: id = msg![env; coder decodeObjectForKey:key_ns_string];
+    let states: id = msg![env; contents allKeys];
+    let state_count: NSUInteger = msg![env; states count];
+    for i in 0..state_count {
+        let state: id = msg![env; states objectAtIndex:i]; // NSNumber*
+        let content: id = msg![env; contents objectForKey:state];
+        let state: i32 = msg![env; state intValue];
+        let state = state as UIControlState;
+        let &UIButtonContentHostObject { title, image } = env.objc.borrow(content);
+        if title != nil {
+            () = msg![env; this setTitle:title forState:state];
+        }
+        if image != nil {
+            () = msg![env; this setImage:image forState:state];
+        }
+    }
 
     this
 }
 
-// TODO: initWithCoder:
-
 - (())dealloc {
     let UIButtonHostObject {
         superclass: _,
@@ -273,4 +311,41 @@ pub const CLASSES: ClassExports = objc_classes! {
 @implementation UIRoundedRectButton: UIButton
 @end
 
+// An undocumented type used by nib files to store the title and image of a
+// UIButton for a particular state.
+@implementation UIButtonContent: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(UIButtonContentHostObject {
+        title: nil,
+        image: nil,
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+// NSCoding implementation
+- (id)initWithCoder:(id)coder {
+    // TODO: title colors, background images, shadows
+    let key_ns_string = get_static_str(env, "UITitle");
+    let title: id = msg![env; coder decodeObjectForKey:key_ns_string];
+    let key_ns_string = get_static_str(env, "UIImage");
+    let image: id = msg![env; coder decodeObjectForKey:key_ns_string];
+
+    retain(env, title);
+    retain(env, image);
+    let host_obj = env.objc.borrow_mut::<UIButtonContentHostObject>(this);
+    host_obj.title = title;
+    host_obj.image = image;
+    this
+}
+
+- (())dealloc {
+    let &UIButtonContentHostObject { title, image } = env.objc.borrow(this);
+    release(env, title);
+    release(env, image);
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+@end
+
 };
@@ -7,6 +7,7 @@
 
 use crate::frameworks::core_graphics::cg_context::CGContextSetRGBFillColor;
 use crate::frameworks::core_graphics::{CGFloat, CGPoint, CGRect, CGSize};
+use crate::frameworks::foundation::ns_string::get_static_str;
 use crate::frameworks::foundation::NSInteger;
 use crate::frameworks::uikit::ui_color;
 use crate::frameworks::uikit::ui_font::{
@@ -60,12 +61,33 @@ pub const CLASSES: ClassExports = objc_classes! {
 - (id)initWithCoder:(id)coder {
     let this: id = msg_super![env; this initWithCoder: coder];
     // Use default values by calling the setters
-    // TODO: Decode the actual property values from the coder
+    // TODO: Decode the font and colors from the coder
     () = msg![env; this setFont:nil];
     () = msg![env; this setTextColor:nil];
     () = msg![env; this setBackgroundColor:nil];
     // Built-in views don't have user-controlled opaqueness.
     () = msg_super![env; this setOpaque:false];
+
+    let key_ns_string = get_static_str(env, "UIText");
+    let text: id = msg![env; coder decodeObjectForKey:key_ns_string];
+    () = msg![env; this setText:text];
+
+    let key_ns_string = get_static_str(env, "UITextAlignment");
+    let text_alignment: NSInteger = msg![env; coder decodeIntegerForKey:key_ns_string];
+    () = msg![env; this setTextAlignment:(text_alignment as UITextAlignment)];
+
+    // Absent keys mean the default value, which isn't 0 for these.
+    let key_ns_string = get_static_str(env, "UILineBreakMode");
+    if msg![env; coder containsValueForKey:key_ns_string] {
+        let mode: NSInteger = msg![env; coder decodeIntegerForKey:key_ns_string];
+        () = msg![env; this setLineBreakMode:(mode as UILineBreakMode)];
+    }
+    let key_ns_string = get_static_str(env, "UINumberOfLines");
+    if msg![env; coder containsValueForKey:key_ns_string] {
+        let number: NSInteger = msg![env; coder decodeIntegerForKey:key_ns_string];
+        () = msg![env; this setNumberOfLines:number];
+    }
+
     this
 }
 
@@ -96,8 +118,6 @@ pub const CLASSES: ClassExports = objc_classes! {
     msg_super![env; this dealloc]
 }
 
-// TODO: initWithCoder:
-
 - (id)text {
     env.objc.borrow::<UILabelHostObject>(this).text
 }
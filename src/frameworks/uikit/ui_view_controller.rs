@@ -9,6 +9,19 @@ use crate::frameworks::foundation::ns_string::{get_static_str, to_rust_string};
 use crate::objc::{
     id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject, NSZonePtr,
 };
+use crate::Environment;
+
+#[derive(Default)]
+pub struct State {
+    /// List of view controllers, so they can be sent memory warnings.
+    /// Non-retaining!
+    view_controllers: Vec<id>,
+}
+impl State {
+    fn get(env: &mut Environment) -> &mut Self {
+        &mut env.framework_state.uikit.ui_view_controller
+    }
+}
 
 #[derive(Default)]
 struct UIViewControllerHostObject {
@@ -24,7 +37,9 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 + (id)allocWithZone:(NSZonePtr)_zone {
     let host_object = Box::<UIViewControllerHostObject>::default();
-    env.objc.alloc_object(this, host_object, &mut env.mem)
+    let new = env.objc.alloc_object(this, host_object, &mut env.mem);
+    State::get(env).view_controllers.push(new);
+    new
 }
 
 // TODO: this should be the designated initializer
@@ -66,6 +81,9 @@ pub const CLASSES: ClassExports = objc_classes! {
 
     release(env, view);
 
+    let view_controllers = &mut State::get(env).view_controllers;
+    view_controllers.swap_remove(view_controllers.iter().position(|&vc| vc == this).unwrap());
+
     env.objc.dealloc_object(this, &mut env.mem);
 }
 
@@ -93,6 +111,11 @@ pub const CLASSES: ClassExports = objc_classes! {
     }
 }
 
+- (())didReceiveMemoryWarning {
+    // Apple's implementation releases the view if it's not in a window, but
+    // our loadView can't load it back from a nib yet, so keep it.
+}
+
 - (())setEditing:(bool)editing {
     log!("TODO: [(UIViewController*){:?} setEditing:{}]", this, editing); // TODO
 }
@@ -100,3 +123,15 @@ pub const CLASSES: ClassExports = objc_classes! {
 @end
 
 };
+
+/// Send `didReceiveMemoryWarning` to every view controller.
+pub(super) fn handle_memory_warning(env: &mut Environment) {
+    let view_controllers = State::get(env).view_controllers.clone();
+    for view_controller in view_controllers {
+        // An earlier view controller might have released this one.
+        if !State::get(env).view_controllers.contains(&view_controller) {
+            continue;
+        }
+        () = msg![env; view_controller didReceiveMemoryWarning];
+    }
+}
@@ -29,11 +29,16 @@ mod bundle;
 pub use bundle::BundleData;
 
 use crate::fs::bundle::{IpaFile, IpaFileRef};
+use crate::libc::errno::{EINVAL, EISDIR, ENOENT, ENOTDIR, ENOTEMPTY, EROFS};
+use crate::libc::socket::errno_for_io_error;
 use crate::paths;
+use crate::Environment;
+use std::cell::RefCell;
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::{Read, Seek, Write};
+use std::fs::{File, FileTimes, Permissions};
+use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
 /// The actual location of a file outside the virtual filesystem, e.g. a host
 /// file path.
@@ -284,6 +289,19 @@ fn resolve_path<'a>(path: &'a GuestPath, relative_to: Option<&'a GuestPath>) ->
     components
 }
 
+/// Turn the result of [resolve_path] back into an absolute path.
+fn path_from_components(components: &[&str]) -> GuestPathBuf {
+    if components.is_empty() {
+        return GuestPathBuf::from(String::from("/"));
+    }
+    let mut path = String::with_capacity(components.iter().map(|c| c.len() + 1).sum());
+    for component in components {
+        path.push('/');
+        path.push_str(component);
+    }
+    GuestPathBuf::from(path)
+}
+
 /// Like [std::fs::OpenOptions] but for the guest filesystem.
 /// TODO: `create_new`.
 #[derive(Debug)]
@@ -341,12 +359,111 @@ fn handle_open_err<T, E: std::fmt::Display, P: std::fmt::Debug>(
     }
 }
 
+/// Metadata about a file or directory in the guest filesystem, for `stat()`
+/// and similar. See [Fs::metadata] and [GuestFile::metadata].
+#[derive(Copy, Clone, Debug)]
+pub struct GuestMetadata {
+    pub is_dir: bool,
+    /// Size in bytes. Always 0 for directories.
+    pub size: u64,
+    /// Permission bits (e.g. `0o644`). Files and directories with a host
+    /// counterpart get these from the host, the rest are read-only.
+    pub permissions: u16,
+    /// Last access time. Only known for files and directories with a host
+    /// counterpart.
+    pub accessed: Option<SystemTime>,
+    /// Last modification time. Only known for files and directories with a
+    /// host counterpart.
+    pub modified: Option<SystemTime>,
+}
+impl GuestMetadata {
+    fn from_host(metadata: std::fs::Metadata, writeable: bool) -> Self {
+        let is_dir = metadata.is_dir();
+        #[cfg(unix)]
+        let permissions = {
+            use std::os::unix::fs::PermissionsExt;
+            (metadata.permissions().mode() & 0o777) as u16
+        };
+        #[cfg(not(unix))]
+        let permissions = match (is_dir, metadata.permissions().readonly()) {
+            (true, false) => 0o755,
+            (true, true) => 0o555,
+            (false, false) => 0o644,
+            (false, true) => 0o444,
+        };
+        GuestMetadata {
+            is_dir,
+            size: if is_dir { 0 } else { metadata.len() },
+            permissions: if writeable {
+                permissions
+            } else {
+                permissions & !0o222
+            },
+            accessed: metadata.accessed().ok(),
+            modified: metadata.modified().ok(),
+        }
+    }
+
+    fn read_only_dir() -> Self {
+        GuestMetadata {
+            is_dir: true,
+            size: 0,
+            permissions: 0o555,
+            accessed: None,
+            modified: None,
+        }
+    }
+}
+
+/// Apply guest permission bits to a host file or directory's permissions.
+///
+/// The owner can always read (and search, for directories) on the host side,
+/// so that touchHLE itself is never locked out. Where there are no Unix-style
+/// permissions, only the owner's write bit is used, to set the read-only flag.
+fn apply_permissions(permissions: &mut Permissions, mode: u16, is_dir: bool) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let owner_minimum = if is_dir { 0o500 } else { 0o400 };
+        permissions.set_mode(u32::from(mode & 0o777) | owner_minimum);
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = is_dir;
+        permissions.set_readonly(mode & 0o200 == 0);
+    }
+}
+
+/// Like [File::set_times], but for a host path, which may be a directory.
+fn set_host_times(host_path: &Path, times: FileTimes) -> std::io::Result<()> {
+    // Some hosts need the file to be opened for writing to change its times,
+    // but that doesn't work for directories or read-only files, which some
+    // other hosts can change the times of anyway.
+    let file = File::options()
+        .write(true)
+        .open(host_path)
+        .or_else(|_| File::open(host_path))?;
+    file.set_times(times)
+}
+
+fn read_only_error() -> std::io::Error {
+    std::io::Error::other("Read-only file system")
+}
+
 /// Like [File] but for the guest filesystem.
 #[derive(Debug)]
 pub enum GuestFile {
     File(File),
     IpaBundleFile(IpaFile),
     ResourceFile(paths::ResourceFile),
+    /// An open directory. Only the absolute path is kept, it can't be read
+    /// from or written to.
+    Directory(GuestPathBuf),
+    /// A socket, which isn't really part of the filesystem but shares its
+    /// descriptor table. See [crate::libc::socket].
+    Socket(crate::libc::socket::Socket),
+    /// One end of a pipe. See [crate::libc::posix_io::pipe].
+    Pipe(crate::libc::posix_io::pipe::PipeEnd),
 }
 
 impl GuestFile {
@@ -365,7 +482,11 @@ impl GuestFile {
     pub fn sync_all(&self) -> std::io::Result<()> {
         match self {
             GuestFile::File(file) => file.sync_all(),
-            GuestFile::IpaBundleFile(_) | GuestFile::ResourceFile(_) => Ok(()),
+            GuestFile::IpaBundleFile(_)
+            | GuestFile::ResourceFile(_)
+            | GuestFile::Directory(_)
+            | GuestFile::Socket(_)
+            | GuestFile::Pipe(_) => Ok(()),
         }
     }
     pub fn set_len(&self, len: u64) -> std::io::Result<()> {
@@ -377,6 +498,67 @@ impl GuestFile {
             GuestFile::ResourceFile(file) => {
                 panic!("Attempt to resize a read-only file: {:?}", file)
             }
+            GuestFile::Directory(_) => Err(is_a_directory_error()),
+            GuestFile::Socket(_) | GuestFile::Pipe(_) => {
+                Err(std::io::Error::other("Invalid argument"))
+            }
+        }
+    }
+}
+
+impl GuestFile {
+    /// Like [File::metadata]. Directories and sockets are not supported, use
+    /// [Fs::metadata] for the former.
+    pub fn metadata(&mut self) -> std::io::Result<GuestMetadata> {
+        match self {
+            GuestFile::File(file) => Ok(GuestMetadata::from_host(file.metadata()?, true)),
+            GuestFile::IpaBundleFile(_) | GuestFile::ResourceFile(_) => {
+                // TODO: Use the stream_len() method if that ever gets
+                // stabilized.
+                let old_pos = self.stream_position()?;
+                let size = self.seek(SeekFrom::End(0))?;
+                self.seek(SeekFrom::Start(old_pos))?;
+                Ok(GuestMetadata {
+                    is_dir: false,
+                    size,
+                    permissions: 0o444,
+                    accessed: None,
+                    modified: None,
+                })
+            }
+            GuestFile::Directory(_) => Err(is_a_directory_error()),
+            GuestFile::Socket(_) | GuestFile::Pipe(_) => Err(std::io::Error::other("Not a file")),
+        }
+    }
+
+    /// Like [File::set_times]. Directories are not supported, use
+    /// [Fs::set_times] instead.
+    pub fn set_times(&self, accessed: SystemTime, modified: SystemTime) -> std::io::Result<()> {
+        match self {
+            GuestFile::File(file) => file.set_times(
+                FileTimes::new()
+                    .set_accessed(accessed)
+                    .set_modified(modified),
+            ),
+            GuestFile::IpaBundleFile(_) | GuestFile::ResourceFile(_) => Err(read_only_error()),
+            GuestFile::Directory(_) => Err(is_a_directory_error()),
+            GuestFile::Socket(_) | GuestFile::Pipe(_) => Err(std::io::Error::other("Not a file")),
+        }
+    }
+
+    /// Like [File::set_permissions], but taking Unix-style permission bits.
+    /// See [Fs::set_permissions] for caveats. Directories are not supported,
+    /// use [Fs::set_permissions] instead.
+    pub fn set_permissions(&self, mode: u16) -> std::io::Result<()> {
+        match self {
+            GuestFile::File(file) => {
+                let mut permissions = file.metadata()?.permissions();
+                apply_permissions(&mut permissions, mode, false);
+                file.set_permissions(permissions)
+            }
+            GuestFile::IpaBundleFile(_) | GuestFile::ResourceFile(_) => Err(read_only_error()),
+            GuestFile::Directory(_) => Err(is_a_directory_error()),
+            GuestFile::Socket(_) | GuestFile::Pipe(_) => Err(std::io::Error::other("Not a file")),
         }
     }
 }
@@ -387,6 +569,9 @@ impl Read for GuestFile {
             GuestFile::File(file) => file.read(buf),
             GuestFile::IpaBundleFile(file) => file.read(buf),
             GuestFile::ResourceFile(file) => file.get().read(buf),
+            GuestFile::Directory(_) => Err(is_a_directory_error()),
+            GuestFile::Socket(socket) => socket.read(buf),
+            GuestFile::Pipe(pipe) => pipe.read(buf),
         }
     }
 }
@@ -401,6 +586,9 @@ impl Write for GuestFile {
             GuestFile::ResourceFile(file) => {
                 panic!("Attempt to write to a read-only file: {:?}", file)
             }
+            GuestFile::Directory(_) => Err(is_a_directory_error()),
+            GuestFile::Socket(socket) => socket.write(buf),
+            GuestFile::Pipe(pipe) => pipe.write(buf),
         }
     }
 
@@ -413,6 +601,9 @@ impl Write for GuestFile {
             GuestFile::ResourceFile(file) => {
                 panic!("Attempt to flush a read-only file: {:?}", file)
             }
+            GuestFile::Directory(_) => Ok(()),
+            GuestFile::Socket(socket) => socket.flush(),
+            GuestFile::Pipe(pipe) => pipe.flush(),
         }
     }
 }
@@ -423,16 +614,82 @@ impl Seek for GuestFile {
             GuestFile::File(file) => file.seek(pos),
             GuestFile::IpaBundleFile(file) => file.seek(pos),
             GuestFile::ResourceFile(file) => file.get().seek(pos),
+            GuestFile::Directory(_) => Ok(0),
+            GuestFile::Socket(_) | GuestFile::Pipe(_) => Err(std::io::Error::other("Illegal seek")),
         }
     }
 }
 
+fn is_a_directory_error() -> std::io::Error {
+    std::io::Error::other("Is a directory")
+}
+
+/// What the caller of [resolve_guest_path] wants to do with the path.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PathIntent {
+    /// Read or inspect an existing file or directory.
+    Read,
+    /// Write to a file, or create a file or directory.
+    Write,
+}
+
+/// Reason why [resolve_guest_path] failed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ResolveError {
+    /// Nothing exists at the path, or (for [PathIntent::Write]) its parent
+    /// directory doesn't exist.
+    NotFound,
+    /// A component of the path that should be a directory is a file.
+    NotADirectory,
+    /// The path is somewhere read-only, like the app bundle.
+    ReadOnly,
+}
+
+/// Resolve a path the guest app wants to use into an absolute guest path,
+/// following the same rules everywhere:
+///
+/// * The path is made absolute relative to the working directory, and `.`,
+///   `..` and repeated `/` are removed.
+/// * Components are matched case-insensitively against the existing files and
+///   directories, like on iPhone OS's HFS+ (unless `--case-sensitive-paths` is
+///   used). The result uses the actual case.
+/// * For [PathIntent::Read], a path that doesn't exist is also tried relative
+///   to the app bundle, since some apps expect to find their resources there
+///   without changing directory.
+/// * For [PathIntent::Write], the path must be somewhere writeable, i.e. in
+///   the sandbox and not in the bundle.
+///
+/// Shims that take paths from the app should use this before touching [Fs].
+pub fn resolve_guest_path(
+    env: &Environment,
+    path: &GuestPath,
+    intent: PathIntent,
+) -> Result<GuestPathBuf, ResolveError> {
+    let res = match intent {
+        PathIntent::Read => env.fs.resolve_existing(path).or_else(|err| {
+            let in_bundle = env
+                .bundle
+                .bundle_path()
+                .join(path.as_str().trim_start_matches('/'));
+            env.fs.resolve_existing(&in_bundle).map_err(|_| err)
+        }),
+        PathIntent::Write => env.fs.resolve_writeable(path),
+    };
+    log_dbg!("resolve_guest_path({:?}, {:?}) => {:?}", path, intent, res);
+    res
+}
+
 /// The type that owns the guest filesystem and provides accessors for it.
 #[derive(Debug)]
 pub struct Fs {
     root: FsNode,
     working_directory: GuestPathBuf,
     home_directory: GuestPathBuf,
+    case_insensitive: bool,
+    /// For each directory that has had a case-insensitive lookup, a map from
+    /// lowercased child names to the real ones. Cleared whenever a directory's
+    /// contents change.
+    folded_names: RefCell<HashMap<String, HashMap<String, String>>>,
 }
 impl Fs {
     /// Construct a filesystem containing a home directory for the app, its
@@ -467,20 +724,27 @@ impl Fs {
 
         let bundle_guest_path = home_directory.join(&bundle_dir_name);
 
-        let documents_host_path = if !read_only_mode {
-            let path = paths::user_data_base_path()
-                .join(paths::SANDBOX_DIR)
-                .join(bundle_id)
-                .join("Documents");
-            if let Err(e) = std::fs::create_dir_all(&path) {
-                panic!(
-                    "Could not create documents directory for app at {:?}: {:?}",
-                    path, e
-                );
-            }
-            Some(path)
+        // Writeable directories in the app's home directory. These are backed
+        // by directories in the app's sandbox directory on the host.
+        let home_subdir_host_paths: Vec<(&str, PathBuf)> = if !read_only_mode {
+            ["Documents", "Library", "tmp"]
+                .into_iter()
+                .map(|name| {
+                    let path = paths::user_data_base_path()
+                        .join(paths::SANDBOX_DIR)
+                        .join(bundle_id)
+                        .join(name);
+                    if let Err(e) = std::fs::create_dir_all(&path) {
+                        panic!(
+                            "Could not create {} directory for app at {:?}: {:?}",
+                            name, path, e
+                        );
+                    }
+                    (name, path)
+                })
+                .collect()
         } else {
-            None
+            Vec::new()
         };
 
         // Some Free Software libraries are bundled with touchHLE.
@@ -502,10 +766,10 @@ impl Fs {
 
         let mut app_dir_children = HashMap::new();
         app_dir_children.insert(bundle_dir_name, app_bundle.into_fs_node());
-        if let Some(documents_host_path) = documents_host_path {
+        for (name, host_path) in home_subdir_host_paths {
             app_dir_children.insert(
-                "Documents".to_string(),
-                FsNode::from_host_dir(&documents_host_path, /* writeable: */ true),
+                name.to_string(),
+                FsNode::from_host_dir(&host_path, /* writeable: */ true),
             );
         }
 
@@ -534,6 +798,8 @@ impl Fs {
             root,
             working_directory,
             home_directory,
+            case_insensitive: true,
+            folded_names: Default::default(),
         };
         assert!(fs.lookup_node(&bundle_guest_path).is_some());
         (fs, bundle_guest_path)
@@ -545,14 +811,28 @@ impl Fs {
             root: FsNode::dir(),
             working_directory: GuestPathBuf::from(String::new()),
             home_directory: GuestPathBuf::from(String::new()),
+            case_insensitive: true,
+            folded_names: Default::default(),
         }
     }
 
+    /// Set whether paths are matched case-insensitively. See
+    /// [resolve_guest_path].
+    pub fn set_case_insensitive(&mut self, case_insensitive: bool) {
+        self.case_insensitive = case_insensitive;
+    }
+
     /// Get the absolute path of the guest app's (sandboxed) home directory.
     pub fn home_directory(&self) -> &GuestPath {
         &self.home_directory
     }
 
+    /// Get the absolute path of the guest app's temporary directory, `tmp` in
+    /// its home directory.
+    pub fn temporary_directory(&self) -> GuestPathBuf {
+        self.home_directory.join("tmp")
+    }
+
     /// Get the absolute path of the current working directory. The resulting
     /// path may be invalid if the directory was moved or deleted.
     pub fn working_directory(&self) -> &GuestPath {
@@ -561,27 +841,148 @@ impl Fs {
 
     /// Attempts to change the working directory.
     pub fn change_working_directory(&mut self, new_path: &GuestPath) -> Result<&GuestPath, ()> {
-        let resolved = resolve_path(new_path, Some(&self.working_directory));
+        let resolved = self.resolve_components(new_path);
         if !matches!(
             self.lookup_node_inner(&resolved),
             Some(FsNode::Directory { .. })
         ) {
             return Err(());
         }
-        let new_path = if resolved.is_empty() {
-            String::from("/")
-        } else {
-            let mut new_path = String::with_capacity(resolved.iter().map(|c| c.len() + 1).sum());
-            for component in resolved {
-                new_path.push('/');
-                new_path.push_str(component);
-            }
-            new_path
-        };
-        self.working_directory = GuestPathBuf::from(new_path);
+        self.working_directory = path_from_components(&resolved);
         Ok(&self.working_directory)
     }
 
+    /// The [PathIntent::Read] part of [resolve_guest_path].
+    fn resolve_existing(&self, path: &GuestPath) -> Result<GuestPathBuf, ResolveError> {
+        let components = self.resolve_components(path);
+        self.lookup_node_checked(&components)?;
+        Ok(path_from_components(&components))
+    }
+
+    /// The [PathIntent::Write] part of [resolve_guest_path].
+    fn resolve_writeable(&self, path: &GuestPath) -> Result<GuestPathBuf, ResolveError> {
+        let components = self.resolve_components(path);
+        // The root directory can't be written to.
+        let (&name, parent_components) = components.split_last().ok_or(ResolveError::ReadOnly)?;
+        let FsNode::Directory {
+            children,
+            writeable: dir_writeable,
+        } = self.lookup_node_checked(parent_components)?
+        else {
+            return Err(ResolveError::NotADirectory);
+        };
+        let writeable = match children.get(name) {
+            Some(FsNode::File { writeable, .. }) => *writeable,
+            Some(FsNode::Directory { writeable, .. }) => writeable.is_some(),
+            None => dir_writeable.is_some(),
+        };
+        if !writeable {
+            return Err(ResolveError::ReadOnly);
+        }
+        Ok(path_from_components(&components))
+    }
+
+    /// Like [Self::lookup_node_inner], but says why the lookup failed.
+    fn lookup_node_checked(
+        &self,
+        resolved_path_components: &[&str],
+    ) -> Result<&FsNode, ResolveError> {
+        let mut node = &self.root;
+        for component in resolved_path_components {
+            let FsNode::Directory { children, .. } = node else {
+                return Err(ResolveError::NotADirectory);
+            };
+            node = children.get(*component).ok_or(ResolveError::NotFound)?;
+        }
+        Ok(node)
+    }
+
+    /// [resolve_path] relative to the working directory, followed by
+    /// [Self::match_case].
+    ///
+    /// On iPhone OS, `/tmp` is a symlink to `/private/var/tmp`, which a
+    /// sandboxed app can't write to, but some apps try to put temporary files
+    /// there anyway (e.g. with `mkstemp()`). Since symlinks don't exist in the
+    /// guest filesystem, `/tmp` is instead treated as an alias of the app's own
+    /// `tmp` directory.
+    fn resolve_components<'a>(&'a self, path: &'a GuestPath) -> Vec<&'a str> {
+        let mut components = resolve_path(path, Some(&self.working_directory));
+        let is_tmp =
+            |c: &str| c == "tmp" || (self.case_insensitive && c.eq_ignore_ascii_case("tmp"));
+        if components.first().is_some_and(|&c| is_tmp(c))
+            && !self.home_directory.as_str().is_empty()
+        {
+            let home_tmp = resolve_path(&self.home_directory, None)
+                .into_iter()
+                .chain(["tmp"]);
+            components.splice(..1, home_tmp);
+        }
+        self.match_case(components)
+    }
+
+    /// Replace each component of a resolved path with the name of the file or
+    /// directory it matches, if it only matches case-insensitively. Components
+    /// after the first one that doesn't exist are left as they are.
+    fn match_case<'a>(&'a self, components: Vec<&'a str>) -> Vec<&'a str> {
+        if !self.case_insensitive {
+            return components;
+        }
+        let mut matched = Vec::with_capacity(components.len());
+        let mut rest = components.into_iter();
+        let mut node = &self.root;
+        for component in rest.by_ref() {
+            let FsNode::Directory { children, .. } = node else {
+                matched.push(component);
+                break;
+            };
+            let found = children
+                .get_key_value(component)
+                .or_else(|| self.find_folded(&matched, children, component));
+            let Some((name, child)) = found else {
+                matched.push(component);
+                break;
+            };
+            matched.push(name.as_str());
+            node = child;
+        }
+        matched.extend(rest);
+        matched
+    }
+
+    /// Case-insensitive lookup of a child of the directory at
+    /// `dir_components`, using [Self::folded_names].
+    fn find_folded<'a>(
+        &self,
+        dir_components: &[&str],
+        children: &'a HashMap<String, FsNode>,
+        component: &str,
+    ) -> Option<(&'a String, &'a FsNode)> {
+        let mut folded_names = self.folded_names.borrow_mut();
+        let names = folded_names
+            .entry(dir_components.join("/"))
+            .or_insert_with(|| {
+                // If several names only differ in case, which can happen on a
+                // case-sensitive host filesystem, the first in sorted order
+                // wins so the result is at least consistent.
+                let mut names: Vec<&String> = children.keys().collect();
+                names.sort();
+                let mut folded = HashMap::new();
+                for name in names {
+                    folded
+                        .entry(name.to_lowercase())
+                        .or_insert_with(|| name.clone());
+                }
+                folded
+            });
+        children.get_key_value(names.get(&component.to_lowercase())?)
+    }
+
+    /// Must be called after a directory's contents change. See
+    /// [Self::folded_names].
+    fn invalidate_folded_names(&mut self) {
+        self.folded_names.get_mut().clear();
+    }
+
     /// [Self::lookup_node] with a pre-resolved path.
     fn lookup_node_inner(&self, resolved_path_components: &[&str]) -> Option<&FsNode> {
         let mut node = &self.root;
@@ -600,7 +1001,7 @@ impl Fs {
 
     /// Get the node at a given path, if it exists.
     fn lookup_node(&self, path: &GuestPath) -> Option<&FsNode> {
-        self.lookup_node_inner(&resolve_path(path, Some(&self.working_directory)))
+        self.lookup_node_inner(&self.resolve_components(path))
     }
 
     /// Get the parent of the node at a given path, if it exists, and return it
@@ -608,11 +1009,15 @@ impl Fs {
     /// [Self::lookup_node] useful when writing to a file, where it might not
     /// exist yet (but its parent directory does).
     fn lookup_parent_node(&mut self, path: &GuestPath) -> Option<(&mut FsNode, String)> {
-        let components = resolve_path(path, Some(&self.working_directory));
-        let (&final_component, parent_components) = components.split_last()?;
+        let components: Vec<String> = self
+            .resolve_components(path)
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let (final_component, parent_components) = components.split_last()?;
 
         let mut parent = &mut self.root;
-        for &component in parent_components {
+        for component in parent_components {
             let FsNode::Directory {
                 children,
                 writeable: _,
@@ -623,7 +1028,7 @@ impl Fs {
             parent = children.get_mut(component)?
         }
 
-        Some((parent, final_component.to_string()))
+        Some((parent, final_component.clone()))
     }
 
     /// Like [Path::exists] but for the guest filesystem.
@@ -659,6 +1064,104 @@ impl Fs {
         matches!(self.lookup_node(path), Some(FsNode::Directory { .. }))
     }
 
+    /// Like [std::fs::metadata] but for the guest filesystem.
+    pub fn metadata<P: AsRef<GuestPath>>(&self, path: P) -> Result<GuestMetadata, ()> {
+        match self.lookup_node(path.as_ref()).ok_or(())? {
+            &FsNode::File {
+                location: FileLocation::Path(ref host_path),
+                writeable,
+            } => std::fs::metadata(host_path)
+                .map(|metadata| GuestMetadata::from_host(metadata, writeable))
+                .map_err(|_| ()),
+            FsNode::File { .. } => self.open(path)?.metadata().map_err(|_| ()),
+            FsNode::Directory {
+                writeable: Some(host_path),
+                ..
+            } => std::fs::metadata(host_path)
+                .map(|metadata| GuestMetadata::from_host(metadata, true))
+                .map_err(|_| ()),
+            FsNode::Directory {
+                writeable: None, ..
+            } => Ok(GuestMetadata::read_only_dir()),
+        }
+    }
+
+    /// Get the host path of a file or directory, if it is writeable.
+    fn writeable_host_path(&self, path: &GuestPath) -> Option<&Path> {
+        match self.lookup_node(path)? {
+            FsNode::File {
+                location: FileLocation::Path(host_path),
+                writeable: true,
+            } => Some(host_path),
+            FsNode::Directory {
+                writeable: Some(host_path),
+                ..
+            } => Some(host_path),
+            _ => None,
+        }
+    }
+
+    /// Set the access and modification times of a file or directory. This
+    /// fails for the read-only parts of the filesystem.
+    pub fn set_times<P: AsRef<GuestPath>>(
+        &self,
+        path: P,
+        accessed: SystemTime,
+        modified: SystemTime,
+    ) -> Result<(), ()> {
+        let path = path.as_ref();
+        let Some(host_path) = self.writeable_host_path(path) else {
+            log!(
+                "Warning: attempt to set times of {:?}, which is read-only",
+                path
+            );
+            return Err(());
+        };
+        let times = FileTimes::new()
+            .set_accessed(accessed)
+            .set_modified(modified);
+        set_host_times(host_path, times).map_err(|e| {
+            log!(
+                "Warning: couldn't set times of {:?} (host path: {:?}): {}",
+                path,
+                host_path,
+                e
+            );
+        })
+    }
+
+    /// Set the Unix-style permission bits of a file or directory. This fails
+    /// for the read-only parts of the filesystem.
+    ///
+    /// The permissions are stored on the host where possible, but the owner
+    /// always keeps read access there, and if the host doesn't have Unix-style
+    /// permissions, only the owner's write bit is kept. Failing to change the
+    /// host permissions is not considered an error, since the app can't tell.
+    pub fn set_permissions<P: AsRef<GuestPath>>(&self, path: P, mode: u16) -> Result<(), ()> {
+        let path = path.as_ref();
+        let Some(host_path) = self.writeable_host_path(path) else {
+            log!(
+                "Warning: attempt to set permissions of {:?}, which is read-only",
+                path
+            );
+            return Err(());
+        };
+        let res = std::fs::metadata(host_path).and_then(|metadata| {
+            let mut permissions = metadata.permissions();
+            apply_permissions(&mut permissions, mode, metadata.is_dir());
+            std::fs::set_permissions(host_path, permissions)
+        });
+        if let Err(e) = res {
+            log!(
+                "Warning: couldn't set permissions of {:?} (host path: {:?}): {}",
+                path,
+                host_path,
+                e
+            );
+        }
+        Ok(())
+    }
+
     /// Get an iterator over the names of files/directories in a directory.
     pub fn enumerate<P: AsRef<GuestPath>>(
         &self,
@@ -745,6 +1248,18 @@ impl Fs {
         }
     }
 
+    /// Open a directory. The result can't be read from or written to, but
+    /// remembers the directory's path (e.g. for `fchdir()`).
+    pub fn open_dir<P: AsRef<GuestPath>>(&self, path: P) -> Result<GuestFile, ()> {
+        let resolved = self.resolve_components(path.as_ref());
+        match self.lookup_node_inner(&resolved) {
+            Some(FsNode::Directory { .. }) => {
+                Ok(GuestFile::Directory(path_from_components(&resolved)))
+            }
+            _ => Err(()),
+        }
+    }
+
     /// Like [File::options] but for the guest filesystem.
     pub fn open_with_options<P: AsRef<GuestPath>>(
         &mut self,
@@ -785,6 +1300,15 @@ impl Fs {
                     }
                     match location {
                         FileLocation::Path(host_path) => {
+                            let host_readonly = std::fs::metadata(host_path)
+                                .is_ok_and(|metadata| metadata.permissions().readonly());
+                            if host_readonly && (append || write) {
+                                log!(
+                                    "Warning: attempt to write to file {:?}, which has been made read-only",
+                                    path
+                                );
+                                return Err(());
+                            }
                             let file = handle_open_err(
                                 File::options()
                                     .read(read)
@@ -859,6 +1383,7 @@ impl Fs {
                 writeable: true,
             },
         );
+        self.invalidate_folded_names();
         Ok(GuestFile::File(file))
     }
 
@@ -935,6 +1460,122 @@ impl Fs {
         }
 
         children.remove(&node_name).unwrap();
+        self.invalidate_folded_names();
+
+        Ok(())
+    }
+
+    /// Like [std::fs::rename] but for the guest filesystem. Both the old and
+    /// new parent directories must be writeable. An existing file at the new
+    /// path is replaced, as is an existing empty directory if the node being
+    /// renamed is also a directory. The error is an `errno` value.
+    pub fn rename<P: AsRef<GuestPath>, Q: AsRef<GuestPath>>(
+        &mut self,
+        from: P,
+        to: Q,
+    ) -> Result<(), i32> {
+        let (from, to) = (from.as_ref(), to.as_ref());
+
+        {
+            let from_components = self.resolve_components(from);
+            let to_components = self.resolve_components(to);
+            // A directory can't be moved inside itself.
+            if to_components.len() > from_components.len()
+                && to_components.starts_with(&from_components)
+            {
+                return Err(EINVAL);
+            }
+        }
+
+        let (to_host_path, to_existing_is_dir) = {
+            let (to_parent, to_name) = self.lookup_parent_node(to).ok_or(ENOENT)?;
+            let FsNode::Directory {
+                children,
+                writeable: Some(dir_host_path),
+            } = to_parent
+            else {
+                log!(
+                    "Warning: attempt to rename to path {:?}, but directory is read-only",
+                    to
+                );
+                return Err(EROFS);
+            };
+            let existing_is_dir = match children.get(&to_name) {
+                None => None,
+                Some(FsNode::File { .. }) => Some(false),
+                Some(FsNode::Directory { children, .. }) => {
+                    if !children.is_empty() {
+                        return Err(ENOTEMPTY);
+                    }
+                    Some(true)
+                }
+            };
+            (dir_host_path.join(&to_name), existing_is_dir)
+        };
+
+        let (from_parent, from_name) = self.lookup_parent_node(from).ok_or(ENOENT)?;
+        let FsNode::Directory {
+            children,
+            writeable: Some(_),
+        } = from_parent
+        else {
+            log!(
+                "Warning: attempt to rename path {:?}, but directory is read-only",
+                from
+            );
+            return Err(EROFS);
+        };
+        let (from_host_path, is_dir) = match children.get(&from_name).ok_or(ENOENT)? {
+            FsNode::File {
+                location: FileLocation::Path(host_path),
+                writeable: true,
+            } => (host_path.clone(), false),
+            FsNode::Directory {
+                writeable: Some(host_path),
+                ..
+            } => (host_path.clone(), true),
+            _ => return Err(EROFS),
+        };
+        match to_existing_is_dir {
+            Some(true) if !is_dir => return Err(EISDIR),
+            Some(false) if is_dir => return Err(ENOTDIR),
+            _ => (),
+        }
+
+        if let Err(e) = std::fs::rename(&from_host_path, &to_host_path) {
+            log!(
+                "Warning: couldn't rename {:?} to {:?} (host path: {:?} to {:?}): {}",
+                from,
+                to,
+                from_host_path,
+                to_host_path,
+                e
+            );
+            return Err(errno_for_io_error(&e));
+        }
+        children.remove(&from_name).unwrap();
+        log_dbg!(
+            "Renamed {:?} to {:?} (host path: {:?} to {:?})",
+            from,
+            to,
+            from_host_path,
+            to_host_path
+        );
+
+        let node = if is_dir {
+            FsNode::from_host_dir(&to_host_path, /* writeable: */ true)
+        } else {
+            FsNode::File {
+                location: FileLocation::Path(to_host_path),
+                writeable: true,
+            }
+        };
+        let (FsNode::Directory { children, .. }, to_name) = self.lookup_parent_node(to).unwrap()
+        else {
+            unreachable!();
+        };
+        children.insert(to_name, node);
+        self.invalidate_folded_names();
 
         Ok(())
     }
@@ -985,6 +1626,181 @@ impl Fs {
                 writeable: Some(host_path),
             },
         );
+        self.invalidate_folded_names();
+        Ok(())
+    }
+
+    /// Like [std::fs::create_dir_all] but for the guest filesystem: creates the
+    /// directory and any missing parents. A directory that already exists is
+    /// not an error, but a file in the way is ([ResolveError::NotADirectory]),
+    /// and so is anything that would have to be created somewhere read-only,
+    /// like the app bundle ([ResolveError::ReadOnly]).
+    ///
+    /// Shims that need a directory to exist, e.g. for `mkdir -p`-style
+    /// creation, should use this rather than looping over [Self::create_dir].
+    pub fn create_dir_all<P: AsRef<GuestPath>>(&mut self, path: P) -> Result<(), ResolveError> {
+        let components: Vec<String> = self
+            .resolve_components(path.as_ref())
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let components: Vec<&str> = components.iter().map(String::as_str).collect();
+        for i in 1..=components.len() {
+            match self.lookup_node_inner(&components[..i]) {
+                Some(FsNode::Directory { .. }) => continue,
+                Some(FsNode::File { .. }) => return Err(ResolveError::NotADirectory),
+                None => (),
+            }
+            // The parent is known to be an existing directory and this
+            // component is missing, so this can only fail if it's read-only.
+            self.create_dir(path_from_components(&components[..i]))
+                .map_err(|()| ResolveError::ReadOnly)?;
+        }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_fs(case_insensitive: bool) -> Fs {
+        let bundle = FsNode::dir().with_child(
+            "Data",
+            FsNode::dir().with_child("level1.dat", FsNode::resource_file("level1.dat".into())),
+        );
+        let documents = FsNode::Directory {
+            children: HashMap::new(),
+            writeable: Some(PathBuf::from("Documents")),
+        };
+        Fs {
+            root: FsNode::dir()
+                .with_child("Game.app", bundle)
+                .with_child("Documents", documents),
+            working_directory: GuestPathBuf::from("/Game.app".to_string()),
+            home_directory: GuestPathBuf::from("/".to_string()),
+            case_insensitive,
+            folded_names: Default::default(),
+        }
+    }
+
+    fn resolve(fs: &Fs, path: &str, intent: PathIntent) -> Result<String, ResolveError> {
+        let path = GuestPath::new(path);
+        match intent {
+            PathIntent::Read => fs.resolve_existing(path),
+            PathIntent::Write => fs.resolve_writeable(path),
+        }
+        .map(String::from)
+    }
+
+    #[test]
+    fn case_insensitive_matching() {
+        let fs = test_fs(true);
+        for path in [
+            "/Game.app/Data/level1.dat",
+            "/game.APP/data/LEVEL1.dat",
+            "Data/Level1.DAT",
+            "//Game.app/./DATA/../Data//level1.dat",
+        ] {
+            assert_eq!(
+                resolve(&fs, path, PathIntent::Read).as_deref(),
+                Ok("/Game.app/Data/level1.dat"),
+                "{:?}",
+                path
+            );
+        }
+        assert!(fs.is_file(GuestPath::new("/GAME.app/data/level1.DAT")));
+        assert_eq!(
+            resolve(&fs, "/Game.app/Data/level2.dat", PathIntent::Read),
+            Err(ResolveError::NotFound)
+        );
+        assert_eq!(
+            resolve(&fs, "/Game.app/Data/level1.dat/x", PathIntent::Read),
+            Err(ResolveError::NotADirectory)
+        );
+    }
+
+    #[test]
+    fn case_sensitive_matching() {
+        let fs = test_fs(false);
+        assert_eq!(
+            resolve(&fs, "Data/level1.dat", PathIntent::Read).as_deref(),
+            Ok("/Game.app/Data/level1.dat")
+        );
+        assert_eq!(
+            resolve(&fs, "data/level1.dat", PathIntent::Read),
+            Err(ResolveError::NotFound)
+        );
+    }
+
+    #[test]
+    fn writeable_split() {
+        let fs = test_fs(true);
+        assert_eq!(
+            resolve(&fs, "/documents/Save.dat", PathIntent::Write).as_deref(),
+            Ok("/Documents/Save.dat")
+        );
+        for path in ["/", "/new", "Data/level1.dat", "Data/new.dat"] {
+            assert_eq!(
+                resolve(&fs, path, PathIntent::Write),
+                Err(ResolveError::ReadOnly),
+                "{:?}",
+                path
+            );
+        }
+        assert_eq!(
+            resolve(&fs, "/Documents/missing/Save.dat", PathIntent::Write),
+            Err(ResolveError::NotFound)
+        );
+    }
+
+    #[test]
+    fn tmp_alias() {
+        let tmp = FsNode::Directory {
+            children: HashMap::new(),
+            writeable: Some(PathBuf::from("tmp")),
+        };
+        let fs = Fs {
+            root: FsNode::dir().with_child("Home", FsNode::dir().with_child("tmp", tmp)),
+            working_directory: GuestPathBuf::from("/".to_string()),
+            home_directory: GuestPathBuf::from("/Home".to_string()),
+            case_insensitive: true,
+            folded_names: Default::default(),
+        };
+        for path in [
+            "/tmp/level1",
+            "/TMP/level1",
+            "tmp/level1",
+            "/Home/tmp/level1",
+        ] {
+            assert_eq!(
+                resolve(&fs, path, PathIntent::Write).as_deref(),
+                Ok("/Home/tmp/level1"),
+                "{:?}",
+                path
+            );
+        }
+        assert!(fs.is_dir(GuestPath::new("/tmp")));
+    }
+
+    #[test]
+    fn create_dir_all_failures() {
+        let mut fs = test_fs(true);
+        // Existing directories are fine, and nothing needs to be created.
+        assert_eq!(fs.create_dir_all(GuestPath::new("/Documents")), Ok(()));
+        assert_eq!(fs.create_dir_all(GuestPath::new("/game.app/DATA")), Ok(()));
+        assert_eq!(
+            fs.create_dir_all(GuestPath::new("/Game.app/Data/level1.dat/Saves")),
+            Err(ResolveError::NotADirectory)
+        );
+        assert_eq!(
+            fs.create_dir_all(GuestPath::new("Data/Saves/Slot1")),
+            Err(ResolveError::ReadOnly)
+        );
+        assert_eq!(
+            fs.create_dir_all(GuestPath::new("/Saves")),
+            Err(ResolveError::ReadOnly)
+        );
+        assert!(!fs.exists(GuestPath::new("/Game.app/Data/Saves")));
+    }
+}
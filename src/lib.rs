@@ -30,6 +30,7 @@ mod log;
 mod abi;
 mod app_picker;
 mod audio;
+mod backtrace;
 mod bundle;
 mod cpu;
 mod debug;
@@ -204,7 +205,11 @@ pub fn main<T: Iterator<Item = String>>(mut args: T) -> Result<(), String> {
 
     echo!("App bundle info:");
     echo!("- Display name: {}", bundle.display_name());
-    echo!("- Version: {}", bundle.bundle_version());
+    echo!(
+        "- Version: {} ({})",
+        bundle.short_version_string().unwrap_or("(not specified)"),
+        bundle.bundle_version().unwrap_or("(not specified)")
+    );
     echo!("- Identifier: {}", app_id);
     if let Some(canonical_name) = bundle.canonical_bundle_name() {
         echo!("- Internal name (canonical): {}.app", canonical_name);
@@ -11,40 +11,131 @@
 
 mod generic_char;
 
+pub mod assert;
 pub mod ctype;
 pub mod cxxabi;
 pub mod dirent;
 pub mod dlfcn;
+pub mod err;
 pub mod errno;
+pub mod ftw;
+pub mod getopt;
+pub mod glob;
 pub mod ifaddrs;
 pub mod keymgr;
+pub mod libgen;
 pub mod mach_thread_info;
 pub mod mach_time;
+pub mod mach_vm;
 pub mod math;
 pub mod mmap;
+pub mod osatomic;
 pub mod net;
 pub mod posix_io;
+pub mod process;
 pub mod pthread;
+pub mod pwd;
+pub mod regex;
+pub mod resource;
 pub mod semaphore;
 pub mod setjmp;
+pub mod signal;
+pub mod socket;
 pub mod stdio;
 pub mod stdlib;
 pub mod string;
 pub mod sysctl;
+pub mod syslog;
 pub mod time;
 pub mod unistd;
 pub mod wchar;
+pub mod zlib;
 
 /// Container for state of various child modules
 #[derive(Default)]
 pub struct State {
     dirent: dirent::State,
+    getopt: getopt::State,
     keymgr: keymgr::State,
+    libgen: libgen::State,
     posix_io: posix_io::State,
     pub pthread: pthread::State,
+    pwd: pwd::State,
+    regex: regex::State,
     pub semaphore: semaphore::State,
+    signal: signal::State,
+    stdio: stdio::State,
     stdlib: stdlib::State,
     string: string::State,
+    syslog: syslog::State,
     time: time::State,
+    zlib: zlib::State,
     errno: errno::State,
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    /// Find calls like `cstr_at_utf8(ptr).unwrap()` in Rust source, returning
+    /// the line number of each.
+    fn find_cstr_at_utf8_unwraps(source: &str) -> Vec<usize> {
+        const CALL: &str = "cstr_at_utf8(";
+        let mut lines = Vec::new();
+        for (start, _) in source.match_indices(CALL) {
+            let mut depth = 1;
+            let mut end = start + CALL.len();
+            for (i, c) in source[end..].char_indices() {
+                match c {
+                    '(' => depth += 1,
+                    ')' => depth -= 1,
+                    _ => (),
+                }
+                if depth == 0 {
+                    end += i + 1;
+                    break;
+                }
+            }
+            let rest = source[end..].trim_start();
+            if rest.starts_with(".unwrap()") || rest.starts_with(".expect(") {
+                lines.push(source[..start].matches('\n').count() + 1);
+            }
+        }
+        lines
+    }
+
+    fn check_dir(dir: &Path, offenders: &mut Vec<String>) {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                check_dir(&path, offenders);
+            } else if path.extension().is_some_and(|ext| ext == "rs") {
+                let source = std::fs::read_to_string(&path).unwrap();
+                for line in find_cstr_at_utf8_unwraps(&source) {
+                    offenders.push(format!("{}:{}", path.display(), line));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn find_unwraps() {
+        let source = "a\nlet s = mem.cstr_at_utf8(ptr.cast())\n    .unwrap();\nmem.cstr_at_utf8(p).ok()";
+        assert_eq!(find_cstr_at_utf8_unwraps(source), [2]);
+    }
+
+    /// Guest strings needn't be valid UTF-8, so unwrapping the result of
+    /// `cstr_at_utf8` lets an app crash the emulator with a bad string. Use the
+    /// bytes from `cstr_at` instead, or a lossy conversion for logging.
+    #[test]
+    fn no_cstr_at_utf8_unwraps() {
+        let libc_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/libc");
+        let mut offenders = Vec::new();
+        check_dir(&libc_dir, &mut offenders);
+        assert!(
+            offenders.is_empty(),
+            "cstr_at_utf8(...).unwrap() in: {}",
+            offenders.join(", ")
+        );
+    }
+}
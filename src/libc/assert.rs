@@ -0,0 +1,60 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `assert.h`
+//!
+//! The `assert()` macro is expanded in the app's code, so only the function it
+//! calls on failure needs to be provided here.
+
+use crate::dyld::{export_c_func, FunctionExports};
+use crate::mem::ConstPtr;
+use crate::Environment;
+
+/// Produce the same message as Apple's libc does for a failed assertion.
+fn assertion_failure_message(func: Option<&str>, file: &str, line: i32, expr: &str) -> String {
+    match func {
+        Some(func) => format!(
+            "Assertion failed: ({}), function {}, file {}, line {}.",
+            expr, func, file, line
+        ),
+        None => format!(
+            "Assertion failed: ({}), file {}, line {}.",
+            expr, file, line
+        ),
+    }
+}
+
+fn __assert_rtn(
+    env: &mut Environment,
+    func: ConstPtr<u8>,
+    file: ConstPtr<u8>,
+    line: i32,
+    expr: ConstPtr<u8>,
+) {
+    let read = |ptr: ConstPtr<u8>| String::from_utf8_lossy(env.mem.cstr_at(ptr)).into_owned();
+    let func = (!func.is_null()).then(|| read(func));
+    let message = assertion_failure_message(func.as_deref(), &read(file), line, &read(expr));
+    echo!("{}", message);
+    crate::libc::stdlib::abort(env);
+}
+
+pub const FUNCTIONS: FunctionExports = &[export_c_func!(__assert_rtn(_, _, _, _))];
+
+#[cfg(test)]
+mod tests {
+    use super::assertion_failure_message;
+
+    #[test]
+    fn test_assertion_failure_message() {
+        assert_eq!(
+            assertion_failure_message(Some("main"), "main.c", 42, "x != 0"),
+            "Assertion failed: (x != 0), function main, file main.c, line 42."
+        );
+        assert_eq!(
+            assertion_failure_message(None, "main.c", 7, "ptr"),
+            "Assertion failed: (ptr), file main.c, line 7."
+        );
+    }
+}
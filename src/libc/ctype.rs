@@ -11,21 +11,143 @@ use crate::dyld::{export_c_func, ConstantExports, FunctionExports, HostConstant}
 use crate::mem::{ConstVoidPtr, Mem, MutVoidPtr, Ptr, SafeRead};
 use crate::Environment;
 
+// Darwin's character class bits, as found in `__runetype`.
+const _CTYPE_A: u32 = 0x100; // alpha
+const _CTYPE_C: u32 = 0x200; // control
+const _CTYPE_D: u32 = 0x400; // digit
+const _CTYPE_G: u32 = 0x800; // graph
+const _CTYPE_L: u32 = 0x1000; // lower
+const _CTYPE_P: u32 = 0x2000; // punct
+const _CTYPE_S: u32 = 0x4000; // space
+const _CTYPE_U: u32 = 0x8000; // upper
+const _CTYPE_X: u32 = 0x10000; // hex digit
+const _CTYPE_B: u32 = 0x20000; // blank
+const _CTYPE_R: u32 = 0x40000; // print
+const _CTYPE_SW1: u32 = 0x40000000; // width 1
+/// The low bits of a digit's entry contain its value, for `digittoint()`.
+const _CTYPE_VALUE_MASK: u32 = 0xff;
+
+const EOF: i32 = -1;
+
+/// The C locale's classification of `c`, in the format of `__runetype`. Only
+/// ASCII characters have any classes, and anything outside `0..=255`
+/// (including `EOF`) has none.
+fn runetype(c: i32) -> u32 {
+    let Ok(c) = u8::try_from(c) else {
+        return 0;
+    };
+
+    let mut runetype = 0u32;
+    if c.is_ascii_alphabetic() {
+        runetype |= _CTYPE_A;
+    }
+    if c.is_ascii_control() {
+        runetype |= _CTYPE_C;
+    }
+    if c.is_ascii_digit() {
+        runetype |= _CTYPE_D;
+    }
+    if c.is_ascii_graphic() {
+        runetype |= _CTYPE_G;
+    }
+    if c.is_ascii_lowercase() {
+        runetype |= _CTYPE_L;
+    }
+    if c.is_ascii_punctuation() {
+        runetype |= _CTYPE_P;
+    }
+    // Rust's definition excludes vertical tab
+    if c.is_ascii_whitespace() || c == b'\x0b' {
+        runetype |= _CTYPE_S;
+    }
+    if c.is_ascii_uppercase() {
+        runetype |= _CTYPE_U;
+    }
+    if c.is_ascii_hexdigit() {
+        runetype |= _CTYPE_X;
+        runetype |= (c as char).to_digit(16).unwrap();
+    }
+    // isblank()
+    if c == b' ' || c == b'\t' {
+        runetype |= _CTYPE_B;
+    }
+    // isprint()
+    if c.is_ascii_graphic() || c == b' ' {
+        runetype |= _CTYPE_R | _CTYPE_SW1;
+    }
+    // TODO: There are some other flags: "ideogram", "special" and
+    // "phonogram". These aren't standard C and aren't used by the C locale.
+    runetype
+}
+
+fn tolower_inner(c: i32) -> i32 {
+    match u8::try_from(c) {
+        Ok(c) => c.to_ascii_lowercase().into(),
+        Err(_) => c,
+    }
+}
+fn toupper_inner(c: i32) -> i32 {
+    match u8::try_from(c) {
+        Ok(c) => c.to_ascii_uppercase().into(),
+        Err(_) => c,
+    }
+}
+
 /// Called by inlined `tolower()` on Darwin
 fn __tolower(_env: &mut Environment, c: i32) -> i32 {
-    if (c as u8) as i32 == c {
-        (c as u8).to_ascii_lowercase().into()
-    } else {
-        c
-    }
+    tolower_inner(c)
 }
 /// Called by inlined `toupper()` on Darwin
 fn __toupper(_env: &mut Environment, c: i32) -> i32 {
-    if (c as u8) as i32 == c {
-        (c as u8).to_ascii_uppercase().into()
-    } else {
-        c
-    }
+    toupper_inner(c)
+}
+/// Called by inlined ctype functions on Darwin for characters outside the
+/// lookup table.
+fn __maskrune(_env: &mut Environment, c: darwin_rune_t, f: u32) -> u32 {
+    runetype(c) & f
+}
+fn __istype(_env: &mut Environment, c: darwin_rune_t, f: u32) -> i32 {
+    ((runetype(c) & f) != 0).into()
+}
+fn __isctype(_env: &mut Environment, c: darwin_rune_t, f: u32) -> i32 {
+    ((runetype(c) & f) != 0).into()
+}
+
+fn tolower(_env: &mut Environment, c: i32) -> i32 {
+    tolower_inner(c)
+}
+fn toupper(_env: &mut Environment, c: i32) -> i32 {
+    toupper_inner(c)
+}
+
+macro_rules! ctype_func {
+    ($name:ident, $mask:expr) => {
+        fn $name(_env: &mut Environment, c: i32) -> i32 {
+            ((runetype(c) & $mask) != 0).into()
+        }
+    };
+}
+ctype_func!(isalnum, _CTYPE_A | _CTYPE_D);
+ctype_func!(isalpha, _CTYPE_A);
+ctype_func!(isblank, _CTYPE_B);
+ctype_func!(iscntrl, _CTYPE_C);
+ctype_func!(isdigit, _CTYPE_D);
+ctype_func!(isgraph, _CTYPE_G);
+ctype_func!(islower, _CTYPE_L);
+ctype_func!(isprint, _CTYPE_R);
+ctype_func!(ispunct, _CTYPE_P);
+ctype_func!(isspace, _CTYPE_S);
+ctype_func!(isupper, _CTYPE_U);
+ctype_func!(isxdigit, _CTYPE_X);
+
+fn isascii(_env: &mut Environment, c: i32) -> i32 {
+    ((c & !0x7f) == 0).into()
+}
+fn toascii(_env: &mut Environment, c: i32) -> i32 {
+    c & 0x7f
+}
+fn digittoint(_env: &mut Environment, c: i32) -> i32 {
+    (runetype(c) & _CTYPE_VALUE_MASK) as i32
 }
 
 #[allow(non_camel_case_types)]
@@ -64,55 +186,10 @@ fn get_default_rune_locale(mem: &mut Mem) -> ConstVoidPtr {
     let mut map_upper = [0 as darwin_rune_t; LOOKUP_TABLE_SIZE];
 
     for idx in 0..LOOKUP_TABLE_SIZE {
-        let c: u8 = idx.try_into().unwrap();
-
-        let as_lower = c.to_ascii_lowercase();
-        let as_upper = c.to_ascii_lowercase();
-
-        let mut as_runetype = 0u32;
-        if c.is_ascii_alphabetic() {
-            as_runetype |= 0x100;
-        }
-        if c.is_ascii_control() {
-            as_runetype |= 0x200;
-        }
-        if c.is_ascii_digit() {
-            as_runetype |= 0x400;
-        }
-        if c.is_ascii_graphic() {
-            as_runetype |= 0x800;
-        }
-        if c.is_ascii_lowercase() {
-            as_runetype |= 0x1000;
-        }
-        if c.is_ascii_punctuation() {
-            as_runetype |= 0x2000;
-        }
-        // Rust's definition excludes vertical tab
-        if c.is_ascii_whitespace() || c == b'\x0b' {
-            as_runetype |= 0x4000;
-        }
-        if c.is_ascii_uppercase() {
-            as_runetype |= 0x8000;
-        }
-        if c.is_ascii_hexdigit() {
-            as_runetype |= 0x10000;
-        }
-        // isblank()
-        if c == b' ' || c == b'\t' {
-            as_runetype |= 0x20000;
-        }
-        // isprint()
-        if c.is_ascii_graphic() || c == b' ' {
-            as_runetype |= 0x40000;
-        }
-        // TODO: There are some other flags: "ideogram", "special", "phonogram",
-        // and a character "width" between 0 and 4. These aren't standard C and
-        // aren't implemented here.
-
-        runetype[idx] = as_runetype;
-        map_lower[idx] = as_lower.into();
-        map_upper[idx] = as_upper.into();
+        let c = idx as i32;
+        runetype[idx] = self::runetype(c);
+        map_lower[idx] = tolower_inner(c);
+        map_upper[idx] = toupper_inner(c);
     }
 
     let mut encoding = [0u8; 32];
@@ -124,7 +201,7 @@ fn get_default_rune_locale(mem: &mut Mem) -> ConstVoidPtr {
 
         getrune: GuestFunction::from_addr_with_thumb_bit(0), // TODO
         putrune: GuestFunction::from_addr_with_thumb_bit(0), // TODO
-        invalid_rune: -1,                                    // probably not correct
+        invalid_rune: EOF,                                   // probably not correct
 
         runetype,
         map_lower,
@@ -145,5 +222,58 @@ pub const CONSTANTS: ConstantExports = &[(
     HostConstant::Custom(get_default_rune_locale),
 )];
 
-pub const FUNCTIONS: FunctionExports =
-    &[export_c_func!(__tolower(_)), export_c_func!(__toupper(_))];
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(__tolower(_)),
+    export_c_func!(__toupper(_)),
+    export_c_func!(__maskrune(_, _)),
+    export_c_func!(__istype(_, _)),
+    export_c_func!(__isctype(_, _)),
+    export_c_func!(tolower(_)),
+    export_c_func!(toupper(_)),
+    export_c_func!(isalnum(_)),
+    export_c_func!(isalpha(_)),
+    export_c_func!(isblank(_)),
+    export_c_func!(iscntrl(_)),
+    export_c_func!(isdigit(_)),
+    export_c_func!(isgraph(_)),
+    export_c_func!(islower(_)),
+    export_c_func!(isprint(_)),
+    export_c_func!(ispunct(_)),
+    export_c_func!(isspace(_)),
+    export_c_func!(isupper(_)),
+    export_c_func!(isxdigit(_)),
+    export_c_func!(isascii(_)),
+    export_c_func!(toascii(_)),
+    export_c_func!(digittoint(_)),
+];
+
+#[cfg(test)]
+#[test]
+fn test_runetype_table() {
+    for c in 0..=255u8 {
+        let t = runetype(c.into());
+        assert_eq!(t & _CTYPE_A != 0, c.is_ascii_alphabetic(), "{}", c);
+        assert_eq!(t & _CTYPE_D != 0, c.is_ascii_digit(), "{}", c);
+        assert_eq!(t & _CTYPE_L != 0, c.is_ascii_lowercase(), "{}", c);
+        assert_eq!(t & _CTYPE_U != 0, c.is_ascii_uppercase(), "{}", c);
+        assert_eq!(t & _CTYPE_X != 0, c.is_ascii_hexdigit(), "{}", c);
+        assert_eq!(t & _CTYPE_P != 0, c.is_ascii_punctuation(), "{}", c);
+        assert_eq!(t & _CTYPE_C != 0, c.is_ascii_control(), "{}", c);
+        assert_eq!(t & _CTYPE_G != 0, c.is_ascii_graphic(), "{}", c);
+        assert_eq!(t & _CTYPE_R != 0, c.is_ascii_graphic() || c == b' ', "{}", c);
+        assert_eq!(
+            t & _CTYPE_S != 0,
+            matches!(c, b' ' | b'\t' | b'\n' | b'\x0b' | b'\x0c' | b'\r'),
+            "{}",
+            c
+        );
+        assert_eq!(tolower_inner(c.into()), c.to_ascii_lowercase().into());
+        assert_eq!(toupper_inner(c.into()), c.to_ascii_uppercase().into());
+    }
+    assert_eq!(runetype(b'7'.into()) & _CTYPE_VALUE_MASK, 7);
+    assert_eq!(runetype(b'f'.into()) & _CTYPE_VALUE_MASK, 15);
+    assert_eq!(runetype(EOF), 0);
+    assert_eq!(runetype(256), 0);
+    assert_eq!(tolower_inner(EOF), EOF);
+    assert_eq!(toupper_inner(EOF), EOF);
+}
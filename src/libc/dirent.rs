@@ -6,11 +6,12 @@
 //! `dirent.h`
 
 use crate::dyld::FunctionExports;
-use crate::fs::GuestPath;
+use crate::fs::{GuestPath, PathIntent};
 use crate::mem::{ConstPtr, guest_size_of, MutPtr, Ptr, SafeRead};
 use crate::{export_c_func, impl_GuestRet_for_large_struct, Environment};
 use std::collections::HashMap;
 use crate::abi::GuestFunction;
+use crate::libc::posix_io::resolve_path_at;
 use crate::libc::stdio::puts;
 
 /// This is an opaque struct and doesn't necessary
@@ -50,9 +51,11 @@ impl State {
 }
 
 fn opendir(env: &mut Environment, filename: ConstPtr<u8>) -> MutPtr<DIR> {
-    let path_string = env.mem.cstr_at_utf8(filename).unwrap().to_owned();
-    log_dbg!("opendir: filename {}", path_string);
-    let guest_path = GuestPath::new(&path_string);
+    let Some(guest_path) = resolve_path_at(env, filename, PathIntent::Read) else {
+        return Ptr::null();
+    };
+    log_dbg!("opendir: filename {:?}", guest_path);
+    let guest_path: &GuestPath = &guest_path;
     let is_dir = env.fs.is_dir(guest_path);
     if is_dir {
         let dir = env.mem.alloc_and_write(DIR { idx: 0 });
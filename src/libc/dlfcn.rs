@@ -29,7 +29,17 @@ fn dlsym(env: &mut Environment, handle: MutVoidPtr, symbol: ConstPtr<u8>) -> Mut
     let handle_val = env.mem.cstr_at_utf8(handle.cast());
     assert!(ALLOWED_LIBRARIES.contains(&handle_val), "{:?}", handle_val);
     // For some reason, the symbols passed to dlsym() don't have the leading _.
-    let symbol = format!("_{}", env.mem.cstr_at_utf8(symbol).unwrap());
+    let symbol = match env.mem.cstr_at_utf8(symbol) {
+        Ok(symbol) => format!("_{}", symbol),
+        Err(bytes) => {
+            // No exported symbol has a name like this.
+            log!(
+                "Warning: dlsym() for non-UTF-8 symbol {:?}, returning NULL",
+                String::from_utf8_lossy(bytes)
+            );
+            return Ptr::null();
+        }
+    };
     // TODO: error handling. dlsym() should just return NULL in this case, but
     // currently it's probably more useful to have the emulator crash if there's
     // no symbol found, since it most likely indicates a missing host function.
@@ -0,0 +1,95 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `err.h`
+//!
+//! These print `progname: message: strerror(errno)` to stderr. The `x`
+//! variants leave out the `strerror(errno)` part, and the `err` ones exit
+//! afterwards.
+
+use crate::abi::{DotDotDot, VaList};
+use crate::dyld::{export_c_func, FunctionExports};
+use crate::libc::errno::{error_message, get_errno};
+use crate::libc::stdio::printf::printf_inner;
+use crate::libc::stdlib::{exit_process, run_atexit_handlers};
+use crate::mem::ConstPtr;
+use crate::Environment;
+use std::io::Write;
+
+/// The name messages are prefixed with. Like `getprogname()`, this is the
+/// last component of the executable's path.
+pub fn program_name(env: &Environment) -> String {
+    let path = env.bundle.executable_path();
+    path.file_name().unwrap_or(path.as_str()).to_string()
+}
+
+/// Write a message to stderr, with the error message for `errnum` if it's not
+/// [None]. `format` may be null, in which case only the error message is
+/// written.
+fn report(env: &mut Environment, errnum: Option<i32>, format: ConstPtr<u8>, args: VaList) {
+    let mut message = format!("{}: ", program_name(env)).into_bytes();
+    if !format.is_null() {
+        let formatted = printf_inner::<false, _>(env, |mem, idx| mem.read(format + idx), args);
+        message.extend_from_slice(&formatted);
+        if errnum.is_some() {
+            message.extend_from_slice(b": ");
+        }
+    }
+    if let Some(errnum) = errnum {
+        message.extend_from_slice(error_message(errnum).as_bytes());
+    }
+    message.push(b'\n');
+    let _ = std::io::stderr().write_all(&message);
+}
+
+/// Like `exit()`, but with a message saying which function the app called.
+fn exit_after_report(env: &mut Environment, function: &str, eval: i32) -> ! {
+    echo!("App called {}(), exiting.", function);
+    run_atexit_handlers(env, None);
+    exit_process(env, eval);
+}
+
+fn err(env: &mut Environment, eval: i32, format: ConstPtr<u8>, args: DotDotDot) {
+    verr(env, eval, format, args.start())
+}
+fn verr(env: &mut Environment, eval: i32, format: ConstPtr<u8>, args: VaList) {
+    let errnum = get_errno(env);
+    report(env, Some(errnum), format, args);
+    exit_after_report(env, "err", eval);
+}
+
+fn errx(env: &mut Environment, eval: i32, format: ConstPtr<u8>, args: DotDotDot) {
+    verrx(env, eval, format, args.start())
+}
+fn verrx(env: &mut Environment, eval: i32, format: ConstPtr<u8>, args: VaList) {
+    report(env, None, format, args);
+    exit_after_report(env, "errx", eval);
+}
+
+fn warn(env: &mut Environment, format: ConstPtr<u8>, args: DotDotDot) {
+    vwarn(env, format, args.start())
+}
+fn vwarn(env: &mut Environment, format: ConstPtr<u8>, args: VaList) {
+    let errnum = get_errno(env);
+    report(env, Some(errnum), format, args);
+}
+
+fn warnx(env: &mut Environment, format: ConstPtr<u8>, args: DotDotDot) {
+    vwarnx(env, format, args.start())
+}
+fn vwarnx(env: &mut Environment, format: ConstPtr<u8>, args: VaList) {
+    report(env, None, format, args);
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(err(_, _, _)),
+    export_c_func!(verr(_, _, _)),
+    export_c_func!(errx(_, _, _)),
+    export_c_func!(verrx(_, _, _)),
+    export_c_func!(warn(_, _)),
+    export_c_func!(vwarn(_, _)),
+    export_c_func!(warnx(_, _)),
+    export_c_func!(vwarnx(_, _)),
+];
@@ -13,9 +13,36 @@ use std::io::Write;
 
 pub const EPERM: i32 = 1;
 pub const ENOENT: i32 = 2;
+pub const EIO: i32 = 5;
+pub const EBADF: i32 = 9;
 pub const EDEADLK: i32 = 11;
+pub const ENOMEM: i32 = 12;
+pub const EACCES: i32 = 13;
 pub const EBUSY: i32 = 16;
+pub const EEXIST: i32 = 17;
+pub const ENOTDIR: i32 = 20;
+pub const EISDIR: i32 = 21;
 pub const EINVAL: i32 = 22;
+pub const EMFILE: i32 = 24;
+pub const EROFS: i32 = 30;
+pub const EPIPE: i32 = 32;
+pub const ERANGE: i32 = 34;
+pub const EAGAIN: i32 = 35;
+pub const EINPROGRESS: i32 = 36;
+pub const EALREADY: i32 = 37;
+pub const ENOTSOCK: i32 = 38;
+pub const EPROTONOSUPPORT: i32 = 43;
+pub const EAFNOSUPPORT: i32 = 47;
+pub const EADDRINUSE: i32 = 48;
+pub const EADDRNOTAVAIL: i32 = 49;
+pub const ECONNRESET: i32 = 54;
+pub const EISCONN: i32 = 56;
+pub const ENOTCONN: i32 = 57;
+pub const ETIMEDOUT: i32 = 60;
+pub const ECONNREFUSED: i32 = 61;
+pub const ENAMETOOLONG: i32 = 63;
+pub const ENOTEMPTY: i32 = 66;
+pub const EILSEQ: i32 = 92;
 
 #[derive(Default)]
 pub struct State {
@@ -28,10 +55,7 @@ impl State {
         thread: crate::ThreadId,
     ) -> MutPtr<i32> {
         *self.errnos.entry(thread).or_insert_with(|| {
-            log!(
-                "TODO: errno accessed on thread {} (will always be 0)",
-                thread
-            );
+            log_dbg!("Allocating errno for thread {}", thread);
             mem.alloc_and_write(0i32)
         })
     }
@@ -43,17 +67,68 @@ fn __error(env: &mut Environment) -> MutPtr<i32> {
         .errno_for_thread(&mut env.mem, env.current_thread)
 }
 
+/// Set the calling thread's `errno` to `value`. Host functions that fail should
+/// call this before returning their error indicator.
+pub fn set_errno(env: &mut Environment, value: i32) {
+    let ptr = __error(env);
+    env.mem.write(ptr, value);
+}
+
+/// The calling thread's `errno`.
+pub fn get_errno(env: &mut Environment) -> i32 {
+    let ptr = __error(env);
+    env.mem.read(ptr)
+}
+
+/// The message Darwin's `strerror()` gives for `errnum`.
+pub fn error_message(errnum: i32) -> String {
+    let message = match errnum {
+        EPERM => "Operation not permitted",
+        ENOENT => "No such file or directory",
+        EIO => "Input/output error",
+        EBADF => "Bad file descriptor",
+        EDEADLK => "Resource deadlock avoided",
+        ENOMEM => "Cannot allocate memory",
+        EACCES => "Permission denied",
+        EBUSY => "Resource busy",
+        EEXIST => "File exists",
+        ENOTDIR => "Not a directory",
+        EISDIR => "Is a directory",
+        EINVAL => "Invalid argument",
+        EMFILE => "Too many open files",
+        EROFS => "Read-only file system",
+        EPIPE => "Broken pipe",
+        ERANGE => "Result too large",
+        EAGAIN => "Resource temporarily unavailable",
+        ENOTSOCK => "Socket operation on non-socket",
+        EPROTONOSUPPORT => "Protocol not supported",
+        EAFNOSUPPORT => "Address family not supported by protocol family",
+        EADDRINUSE => "Address already in use",
+        EADDRNOTAVAIL => "Can't assign requested address",
+        ECONNRESET => "Connection reset by peer",
+        EISCONN => "Socket is already connected",
+        ENOTCONN => "Socket is not connected",
+        ETIMEDOUT => "Operation timed out",
+        ECONNREFUSED => "Connection refused",
+        ENAMETOOLONG => "File name too long",
+        ENOTEMPTY => "Directory not empty",
+        EILSEQ => "Illegal byte sequence",
+        0 => return "Undefined error: 0".to_string(),
+        _ => return format!("Unknown error: {}", errnum),
+    };
+    message.to_string()
+}
+
 fn perror(env: &mut Environment, s: ConstPtr<u8>) {
-    // TODO: errno mapping
-    let errno_msg = "<TODO: errno>\n";
-    let msg = if !s.is_null() {
-        if let Ok(str) = env.mem.cstr_at_utf8(s) {
-            format!("{}: {}", str, errno_msg)
-        } else {
-            errno_msg.to_string()
-        }
+    let errno_msg = format!("{}\n", error_message(get_errno(env)));
+    let msg = if !s.is_null() && env.mem.read(s) != b'\0' {
+        format!(
+            "{}: {}",
+            String::from_utf8_lossy(env.mem.cstr_at(s)),
+            errno_msg
+        )
     } else {
-        errno_msg.to_string()
+        errno_msg
     };
     let _ = std::io::stderr().write_all(msg.as_bytes());
 }
@@ -0,0 +1,174 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `ftw.h`
+//!
+//! The `depth` parameter of `ftw()` and `nftw()` is the maximum number of file
+//! descriptors to use at once, not a limit on how deep the walk goes. touchHLE
+//! doesn't need any, so it is only validated.
+
+use crate::abi::{CallFromHost, GuestFunction};
+use crate::dyld::{export_c_func, FunctionExports};
+use crate::fs::GuestPath;
+use crate::libc::errno::{set_errno, EINVAL, ENOENT};
+use crate::libc::posix_io::stat::stat;
+use crate::mem::{guest_size_of, ConstPtr, MutVoidPtr, SafeRead};
+use crate::Environment;
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Copy, Clone)]
+#[repr(C, packed)]
+struct FTW {
+    base: i32,
+    level: i32,
+}
+unsafe impl SafeRead for FTW {}
+
+/// File
+const FTW_F: i32 = 0;
+/// Directory, visited before its contents
+const FTW_D: i32 = 1;
+/// Directory, visited after its contents (with `FTW_DEPTH`)
+const FTW_DP: i32 = 3;
+
+// FTW_PHYS (0x1) and FTW_MOUNT (0x2) make no difference, since there are no
+// symbolic links or mount points in the guest filesystem.
+const FTW_DEPTH: i32 = 0x4;
+const FTW_CHDIR: i32 = 0x8;
+
+struct Walk {
+    callback: GuestFunction,
+    /// `false` for `ftw()`, which passes fewer arguments to the callback.
+    is_nftw: bool,
+    depth_first: bool,
+    stat_buf: MutVoidPtr,
+}
+
+impl Walk {
+    fn visit(&self, env: &mut Environment, path: &str, base: usize, level: i32) -> i32 {
+        let is_dir = env.fs.is_dir(GuestPath::new(path));
+        if !is_dir {
+            return self.call(env, path, base, level, FTW_F);
+        }
+
+        if !self.depth_first {
+            let res = self.call(env, path, base, level, FTW_D);
+            if res != 0 {
+                return res;
+            }
+        }
+
+        let mut names: Vec<String> = env
+            .fs
+            .enumerate(GuestPath::new(path))
+            .unwrap()
+            .map(|name| name.to_string())
+            .collect();
+        // The guest filesystem has no inherent order, so sort to make the walk
+        // deterministic.
+        names.sort();
+        for name in names {
+            let child = if path.ends_with('/') {
+                format!("{}{}", path, name)
+            } else {
+                format!("{}/{}", path, name)
+            };
+            let res = self.visit(env, &child, child.len() - name.len(), level + 1);
+            if res != 0 {
+                return res;
+            }
+        }
+
+        if self.depth_first {
+            self.call(env, path, base, level, FTW_DP)
+        } else {
+            0
+        }
+    }
+
+    fn call(&self, env: &mut Environment, path: &str, base: usize, level: i32, flag: i32) -> i32 {
+        log_dbg!("ftw: visiting {:?} (level {}, flag {})", path, level, flag);
+        let path = env.mem.alloc_and_write_cstr(path.as_bytes());
+        env.mem
+            .bytes_at_mut(self.stat_buf.cast(), guest_size_of::<stat>())
+            .fill(0);
+        stat(env, path.cast_const(), self.stat_buf);
+        let res: i32 = if self.is_nftw {
+            let ftw = env.mem.alloc_and_write(FTW {
+                base: base.try_into().unwrap(),
+                level,
+            });
+            let res = self.callback.call_from_host(
+                env,
+                (path.cast_const(), self.stat_buf.cast_const(), flag, ftw),
+            );
+            env.mem.free(ftw.cast());
+            res
+        } else {
+            self.callback
+                .call_from_host(env, (path.cast_const(), self.stat_buf.cast_const(), flag))
+        };
+        env.mem.free(path.cast());
+        res
+    }
+}
+
+fn walk(
+    env: &mut Environment,
+    path: ConstPtr<u8>,
+    callback: GuestFunction,
+    depth: i32,
+    flags: i32,
+    is_nftw: bool,
+) -> i32 {
+    if depth < 1 {
+        set_errno(env, EINVAL);
+        return -1;
+    }
+    let path = String::from_utf8_lossy(env.mem.cstr_at(path)).into_owned();
+    if !env.fs.exists(GuestPath::new(&path)) {
+        set_errno(env, ENOENT);
+        return -1;
+    }
+    if flags & FTW_CHDIR != 0 {
+        log!("TODO: nftw({:?}) with FTW_CHDIR, ignoring", path);
+    }
+
+    let walk = Walk {
+        callback,
+        is_nftw,
+        depth_first: flags & FTW_DEPTH != 0,
+        stat_buf: env.mem.alloc(guest_size_of::<stat>()),
+    };
+    let base = path.trim_end_matches('/').rfind('/').map_or(0, |i| i + 1);
+    let res = walk.visit(env, &path, base, 0);
+    env.mem.free(walk.stat_buf);
+    res
+}
+
+fn ftw(
+    env: &mut Environment,
+    path: ConstPtr<u8>,
+    callback: GuestFunction, // int (*)(const char *, const struct stat *, int)
+    depth: i32,
+) -> i32 {
+    walk(env, path, callback, depth, 0, false)
+}
+
+fn nftw(
+    env: &mut Environment,
+    path: ConstPtr<u8>,
+    // int (*)(const char *, const struct stat *, int, struct FTW *)
+    callback: GuestFunction,
+    depth: i32,
+    flags: i32,
+) -> i32 {
+    walk(env, path, callback, depth, flags, true)
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(ftw(_, _, _)),
+    export_c_func!(nftw(_, _, _, _)),
+];
@@ -8,6 +8,7 @@
 
 use crate::mem::{guest_size_of, ConstPtr, GuestUSize, MutPtr, Ptr, SafeRead};
 use crate::Environment;
+use std::any::{Any, TypeId};
 use std::cmp::Ordering;
 use std::fmt::Debug;
 
@@ -17,11 +18,21 @@ pub(super) struct GenericChar<T> {
     _spooky: std::marker::PhantomData<T>,
 }
 
-impl<T: Copy + Default + Eq + Ord + SafeRead + Debug> GenericChar<T> {
+impl<T: Copy + Default + Eq + Ord + SafeRead + Debug + 'static> GenericChar<T> {
     fn null() -> T {
         Default::default()
     }
 
+    /// Whether this is the `char` instantiation, for which some functions have
+    /// faster implementations that work on whole slices of guest memory rather
+    /// than reading one character at a time.
+    fn is_byte() -> bool {
+        TypeId::of::<T>() == TypeId::of::<u8>()
+    }
+    fn as_byte(c: T) -> Option<u8> {
+        (&c as &dyn Any).downcast_ref::<u8>().copied()
+    }
+
     pub(super) fn memset(
         env: &mut Environment,
         dest: MutPtr<T>,
@@ -56,6 +67,26 @@ impl<T: Copy + Default + Eq + Ord + SafeRead + Debug> GenericChar<T> {
         dest
     }
 
+    /// Copy at most `size` characters, stopping after the first `c`. Returns a
+    /// pointer just past the copy of `c` in `dest`, or null if `c` wasn't
+    /// among the first `size` characters.
+    pub(super) fn memccpy(
+        env: &mut Environment,
+        dest: MutPtr<T>,
+        src: ConstPtr<T>,
+        c: T,
+        size: GuestUSize,
+    ) -> MutPtr<T> {
+        for i in 0..size {
+            let ch = env.mem.read(src + i);
+            env.mem.write(dest + i, ch);
+            if ch == c {
+                return dest + i + 1;
+            }
+        }
+        Ptr::null()
+    }
+
     pub(super) fn memcmp(
         env: &mut Environment,
         a: ConstPtr<T>,
@@ -95,7 +126,25 @@ impl<T: Copy + Default + Eq + Ord + SafeRead + Debug> GenericChar<T> {
         Ptr::null()
     }
 
+    pub(super) fn memrchr(
+        env: &mut Environment,
+        string: ConstPtr<T>,
+        c: T,
+        size: GuestUSize,
+    ) -> ConstPtr<T> {
+        for i in (0..size).rev() {
+            if env.mem.read(string + i) == c {
+                return string + i;
+            }
+        }
+        Ptr::null()
+    }
+
     pub(super) fn strlen(env: &mut Environment, s: ConstPtr<T>) -> GuestUSize {
+        if Self::is_byte() {
+            return env.mem.find_byte(s.cast(), b'\0', GuestUSize::MAX).unwrap();
+        }
+
         let mut i = 0;
         while env.mem.read(s + i) != Self::null() {
             i += 1;
@@ -103,12 +152,33 @@ impl<T: Copy + Default + Eq + Ord + SafeRead + Debug> GenericChar<T> {
         i
     }
 
+    /// Like [Self::strlen], but never reads more than `maxlen` characters.
+    pub(super) fn strnlen(env: &mut Environment, s: ConstPtr<T>, maxlen: GuestUSize) -> GuestUSize {
+        if Self::is_byte() {
+            return env.mem.find_byte(s.cast(), b'\0', maxlen).unwrap_or(maxlen);
+        }
+
+        let mut i = 0;
+        while i < maxlen && env.mem.read(s + i) != Self::null() {
+            i += 1;
+        }
+        i
+    }
+
     pub(super) fn strcpy(
         env: &mut Environment,
         dest: MutPtr<T>,
         src: ConstPtr<T>,
         mut bufsz: GuestUSize,
     ) -> MutPtr<T> {
+        if Self::is_byte() {
+            let Some(len) = env.mem.find_byte(src.cast(), b'\0', bufsz) else {
+                panic!("Buffer overrun");
+            };
+            env.mem.copy_within_guest(dest.cast(), src.cast(), len + 1);
+            return dest;
+        }
+
         {
             let (mut dest, mut src) = (dest, src);
             loop {
@@ -142,27 +212,131 @@ impl<T: Copy + Default + Eq + Ord + SafeRead + Debug> GenericChar<T> {
         dest
     }
 
-    pub(super) fn strcspn(env: &mut Environment, s: ConstPtr<T>, charset: ConstPtr<T>) -> GuestUSize {
+    /// Like [Self::strcpy], but returns a pointer to the copied terminator.
+    pub(super) fn stpcpy(
+        env: &mut Environment,
+        dest: MutPtr<T>,
+        src: ConstPtr<T>,
+        bufsz: GuestUSize,
+    ) -> MutPtr<T> {
+        let len = Self::strnlen(env, src, bufsz);
+        Self::strcpy(env, dest, src, bufsz) + len
+    }
+
+    /// Whether `c` is one of the characters in the null-terminated `charset`.
+    fn charset_contains(env: &Environment, charset: ConstPtr<T>, c: T) -> bool {
+        let mut j = 0;
+        loop {
+            let cc = env.mem.read(charset + j);
+            if cc == Self::null() {
+                return false;
+            }
+            if c == cc {
+                return true;
+            }
+            j += 1;
+        }
+    }
+
+    /// Length of the initial segment of `s` whose characters are all in
+    /// `charset` (if `in_charset` is [true]) or all not in it (if [false]).
+    fn span(
+        env: &Environment,
+        s: ConstPtr<T>,
+        charset: ConstPtr<T>,
+        in_charset: bool,
+    ) -> GuestUSize {
         let mut i = 0;
         loop {
             let c = env.mem.read(s + i);
-            if c == Self::null() {
-                break;
-            }
-            let mut j = 0;
-            loop {
-                let cc = env.mem.read(charset + j);
-                if c == cc {
-                    return i;
-                }
-                if cc == Self::null() {
-                    break;
-                }
-                j += 1;
+            if c == Self::null() || Self::charset_contains(env, charset, c) != in_charset {
+                return i;
             }
             i += 1;
         }
-        i
+    }
+
+    pub(super) fn strspn(
+        env: &mut Environment,
+        s: ConstPtr<T>,
+        charset: ConstPtr<T>,
+    ) -> GuestUSize {
+        Self::span(env, s, charset, true)
+    }
+
+    pub(super) fn strcspn(
+        env: &mut Environment,
+        s: ConstPtr<T>,
+        charset: ConstPtr<T>,
+    ) -> GuestUSize {
+        Self::span(env, s, charset, false)
+    }
+
+    pub(super) fn strpbrk(
+        env: &mut Environment,
+        s: ConstPtr<T>,
+        charset: ConstPtr<T>,
+    ) -> ConstPtr<T> {
+        let found = s + Self::span(env, s, charset, false);
+        if env.mem.read(found) == Self::null() {
+            Ptr::null()
+        } else {
+            found
+        }
+    }
+
+    /// `strtok_r()`: find the next token of `s`, or of the rest of the
+    /// previous string if `s` is null, that is delimited by characters in
+    /// `sep`. The token is terminated in-place, and where to continue from is
+    /// stored in `last`.
+    pub(super) fn strtok_r(
+        env: &mut Environment,
+        s: MutPtr<T>,
+        sep: ConstPtr<T>,
+        last: MutPtr<MutPtr<T>>,
+    ) -> MutPtr<T> {
+        let s = if s.is_null() { env.mem.read(last) } else { s };
+        if s.is_null() {
+            return Ptr::null();
+        }
+        // Leading delimiters are skipped, so consecutive ones are collapsed.
+        let token_start = s + Self::span(env, s.cast_const(), sep, true);
+        if env.mem.read(token_start) == Self::null() {
+            env.mem.write(last, Ptr::null());
+            return Ptr::null();
+        }
+        let token_end = token_start + Self::span(env, token_start.cast_const(), sep, false);
+        let next = if env.mem.read(token_end) == Self::null() {
+            Ptr::null()
+        } else {
+            env.mem.write(token_end, Self::null());
+            token_end + 1
+        };
+        env.mem.write(last, next);
+        token_start
+    }
+
+    /// `strsep()`: like [Self::strtok_r], but every delimiter ends a token,
+    /// so empty tokens are returned rather than skipped. `*stringp` is
+    /// advanced past the delimiter, or set to null after the last token.
+    pub(super) fn strsep(
+        env: &mut Environment,
+        stringp: MutPtr<MutPtr<T>>,
+        delim: ConstPtr<T>,
+    ) -> MutPtr<T> {
+        let token = env.mem.read(stringp);
+        if token.is_null() {
+            return Ptr::null();
+        }
+        let token_end = token + Self::span(env, token.cast_const(), delim, false);
+        let next = if env.mem.read(token_end) == Self::null() {
+            Ptr::null()
+        } else {
+            env.mem.write(token_end, Self::null());
+            token_end + 1
+        };
+        env.mem.write(stringp, next);
+        token
     }
 
     pub(super) fn strncpy(
@@ -186,6 +360,18 @@ impl<T: Copy + Default + Eq + Ord + SafeRead + Debug> GenericChar<T> {
         dest
     }
 
+    /// Like [Self::strncpy], but returns a pointer to the first padding
+    /// character written, or `dest + size` if `src` filled all of `dest`.
+    pub(super) fn stpncpy(
+        env: &mut Environment,
+        dest: MutPtr<T>,
+        src: ConstPtr<T>,
+        size: GuestUSize,
+    ) -> MutPtr<T> {
+        let len = Self::strnlen(env, src, size);
+        Self::strncpy(env, dest, src, size) + len
+    }
+
     pub(super) fn strdup(env: &mut Environment, src: ConstPtr<T>) -> MutPtr<T> {
         let len = Self::strlen(env, src);
         let new = env.mem.alloc((len + 1) * guest_size_of::<T>()).cast();
@@ -193,6 +379,23 @@ impl<T: Copy + Default + Eq + Ord + SafeRead + Debug> GenericChar<T> {
     }
 
     pub(super) fn strcmp(env: &mut Environment, a: ConstPtr<T>, b: ConstPtr<T>) -> i32 {
+        if Self::is_byte() {
+            // Unlike the loop below, this reads all of a even if b differs
+            // earlier, which only matters if a isn't properly terminated.
+            let a_len = env.mem.find_byte(a.cast(), b'\0', GuestUSize::MAX).unwrap() + 1;
+            let b_len = env
+                .mem
+                .find_byte(b.cast(), b'\0', a_len)
+                .map_or(a_len, |len| len + 1);
+            let a = env.mem.bytes_at(a.cast(), a_len);
+            let b = env.mem.bytes_at(b.cast(), b_len);
+            return match a.cmp(b) {
+                Ordering::Less => -1,
+                Ordering::Greater => 1,
+                Ordering::Equal => 0,
+            };
+        }
+
         let mut offset = 0;
         loop {
             let char_a = env.mem.read(a + offset);
@@ -287,13 +490,23 @@ impl<T: Copy + Default + Eq + Ord + SafeRead + Debug> GenericChar<T> {
     }
 
     pub(super) fn strchr(env: &mut Environment, string: ConstPtr<T>, char: T) -> ConstPtr<T> {
+        if let Some(byte) = Self::as_byte(char) {
+            let len = Self::strlen(env, string);
+            // if c is '\0', the function should locate the terminating '\0'
+            return match env.mem.find_byte(string.cast(), byte, len + 1) {
+                Some(offset) => string + offset,
+                None => Ptr::null(),
+            };
+        }
+
         let mut offset = 0;
         loop {
+            let c = env.mem.read(string + offset);
             // if c is '\0', the function should locate the terminating '\0'
-            if env.mem.read(string + offset) == char {
+            if c == char {
                 return string + offset;
             }
-            if offset == Self::strlen(env, string) {
+            if c == Self::null() {
                 return Ptr::null();
             }
             offset += 1;
@@ -301,15 +514,17 @@ impl<T: Copy + Default + Eq + Ord + SafeRead + Debug> GenericChar<T> {
     }
 
     pub(super) fn strrchr(env: &mut Environment, string: ConstPtr<T>, char: T) -> ConstPtr<T> {
-        let mut offset = Self::strlen(env, string);
+        let mut last = Ptr::null();
+        let mut offset = 0;
         loop {
-            if env.mem.read(string + offset) == char {
-                return string + offset;
+            let c = env.mem.read(string + offset);
+            if c == char {
+                last = string + offset;
             }
-            if offset == 0 {
-                return Ptr::null();
+            if c == Self::null() {
+                return last;
             }
-            offset -= 1;
+            offset += 1;
         }
     }
 
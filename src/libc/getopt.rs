@@ -0,0 +1,361 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `getopt.h` and the `getopt` part of `unistd.h`.
+//!
+//! Arguments are never permuted: parsing stops at the first non-option
+//! argument, as with `POSIXLY_CORRECT` set for GNU's implementation.
+
+use crate::dyld::{export_c_func, ConstantExports, FunctionExports, HostConstant};
+use crate::mem::{ConstPtr, MutPtr, Ptr, SafeRead};
+use crate::Environment;
+use std::io::Write;
+
+#[derive(Default)]
+pub struct State {
+    globals: Option<Globals>,
+    /// Index of the next character to look at within `argv[optind]`, when
+    /// partway through a group of bundled options like `-abc`, otherwise 0.
+    next_char: u32,
+    /// The value of `optind` after the last call, so that the app changing it
+    /// (usually back to 1, to parse another `argv`) can be detected.
+    last_optind: i32,
+}
+
+/// Addresses of the global variables shared with the guest app.
+#[derive(Copy, Clone)]
+struct Globals {
+    optarg: MutPtr<MutPtr<u8>>,
+    optind: MutPtr<i32>,
+    opterr: MutPtr<i32>,
+    optopt: MutPtr<i32>,
+}
+
+fn globals(env: &mut Environment) -> Globals {
+    if let Some(globals) = env.libc_state.getopt.globals {
+        return globals;
+    }
+    let globals = Globals {
+        optarg: env.mem.alloc_and_write(Ptr::null()),
+        optind: env.mem.alloc_and_write(1),
+        opterr: env.mem.alloc_and_write(1),
+        optopt: env.mem.alloc_and_write(b'?'.into()),
+    };
+    env.libc_state.getopt.globals = Some(globals);
+    env.libc_state.getopt.last_optind = 1;
+    globals
+}
+
+const NO_ARGUMENT: i32 = 0;
+const REQUIRED_ARGUMENT: i32 = 1;
+const OPTIONAL_ARGUMENT: i32 = 2;
+
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone)]
+#[repr(C, packed)]
+struct option {
+    name: ConstPtr<u8>,
+    has_arg: i32,
+    flag: MutPtr<i32>,
+    val: i32,
+}
+unsafe impl SafeRead for option {}
+
+/// The `longopts` and `longindex` arguments of `getopt_long`.
+type LongOptions = (ConstPtr<option>, MutPtr<i32>);
+
+fn report_error(env: &mut Environment, argv: ConstPtr<MutPtr<u8>>, message: String) {
+    let opterr = globals(env).opterr;
+    if env.mem.read(opterr) == 0 {
+        return;
+    }
+    let program = env.mem.read(argv);
+    let program = if program.is_null() {
+        Default::default()
+    } else {
+        String::from_utf8_lossy(env.mem.cstr_at(program)).into_owned()
+    };
+    let program = program.rsplit('/').next().unwrap();
+    let _ = std::io::stderr().write_all(format!("{}: {}\n", program, message).as_bytes());
+}
+
+fn getopt_inner(
+    env: &mut Environment,
+    argc: i32,
+    argv: ConstPtr<MutPtr<u8>>,
+    optstring: ConstPtr<u8>,
+    long_options: Option<LongOptions>,
+) -> i32 {
+    let globals = globals(env);
+
+    let mut optind = env.mem.read(globals.optind);
+    if optind != env.libc_state.getopt.last_optind || optind == 0 {
+        env.libc_state.getopt.next_char = 0;
+        // GNU extension: setting optind to 0 also requests a reset.
+        optind = optind.max(1);
+    }
+
+    let mut optstring = env.mem.cstr_at(optstring).to_vec();
+    // GNU extensions for choosing the permutation behavior, which is fixed.
+    if let Some(b'+' | b'-') = optstring.first() {
+        optstring.remove(0);
+    }
+    let silent = optstring.first() == Some(&b':');
+
+    env.mem.write(globals.optarg, Ptr::null());
+    let result = if env.libc_state.getopt.next_char == 0 {
+        let arg = if optind < argc {
+            env.mem.read(argv + optind as u32)
+        } else {
+            Ptr::null()
+        };
+        let arg_bytes = if arg.is_null() {
+            &b""[..]
+        } else {
+            env.mem.cstr_at(arg)
+        };
+        if arg_bytes.len() < 2 || arg_bytes[0] != b'-' {
+            // Not an option, or the end of argv.
+            -1
+        } else if arg_bytes == b"--" {
+            optind += 1;
+            -1
+        } else if let (Some(long_options), Some(b'-')) = (long_options, arg_bytes.get(1)) {
+            getopt_long_option(env, argc, argv, &mut optind, silent, long_options)
+        } else {
+            env.libc_state.getopt.next_char = 1;
+            getopt_short_option(env, argc, argv, &mut optind, &optstring, silent)
+        }
+    } else {
+        getopt_short_option(env, argc, argv, &mut optind, &optstring, silent)
+    };
+
+    env.mem.write(globals.optind, optind);
+    env.libc_state.getopt.last_optind = optind;
+    result
+}
+
+/// Move on to the argument after `argv[optind]`.
+fn advance(env: &mut Environment, optind: &mut i32) {
+    *optind += 1;
+    env.libc_state.getopt.next_char = 0;
+}
+
+/// Handle the next option character in `argv[optind]`, which is known to be
+/// an option group.
+fn getopt_short_option(
+    env: &mut Environment,
+    argc: i32,
+    argv: ConstPtr<MutPtr<u8>>,
+    optind: &mut i32,
+    optstring: &[u8],
+    silent: bool,
+) -> i32 {
+    let globals = globals(env);
+    let arg = env.mem.read(argv + *optind as u32);
+    let next_char = env.libc_state.getopt.next_char;
+    let c = env.mem.read(arg + next_char);
+    let rest = arg + next_char + 1;
+    let at_end = env.mem.read(rest) == b'\0';
+    env.mem.write(globals.optopt, c.into());
+
+    let spec = optstring
+        .iter()
+        .position(|&o| o == c && o != b':')
+        .map(|i| &optstring[i + 1..]);
+    let Some(spec) = spec else {
+        if !silent {
+            report_error(env, argv, format!("illegal option -- {}", c as char));
+        }
+        if at_end {
+            advance(env, optind);
+        } else {
+            env.libc_state.getopt.next_char += 1;
+        }
+        return b'?'.into();
+    };
+
+    match spec {
+        [b':', b':', ..] => {
+            // Optional argument, which must be attached.
+            if !at_end {
+                env.mem.write(globals.optarg, rest);
+            }
+            advance(env, optind);
+        }
+        [b':', ..] => {
+            // Required argument, which may be attached or the next argument.
+            if !at_end {
+                env.mem.write(globals.optarg, rest);
+                advance(env, optind);
+            } else if *optind + 1 < argc {
+                let optarg = env.mem.read(argv + (*optind + 1) as u32);
+                env.mem.write(globals.optarg, optarg);
+                advance(env, optind);
+                *optind += 1;
+            } else {
+                advance(env, optind);
+                if silent {
+                    return b':'.into();
+                }
+                let message = format!("option requires an argument -- {}", c as char);
+                report_error(env, argv, message);
+                return b'?'.into();
+            }
+        }
+        _ => {
+            if at_end {
+                advance(env, optind);
+            } else {
+                env.libc_state.getopt.next_char += 1;
+            }
+        }
+    }
+    c.into()
+}
+
+/// Handle `argv[optind]`, which is known to start with `--` and be followed by
+/// an option name.
+fn getopt_long_option(
+    env: &mut Environment,
+    argc: i32,
+    argv: ConstPtr<MutPtr<u8>>,
+    optind: &mut i32,
+    silent: bool,
+    (longopts, longindex): LongOptions,
+) -> i32 {
+    let globals = globals(env);
+    let arg = env.mem.read(argv + *optind as u32);
+    *optind += 1;
+    let arg_bytes = &env.mem.cstr_at(arg)[2..];
+    let name_len = arg_bytes
+        .iter()
+        .position(|&c| c == b'=')
+        .unwrap_or(arg_bytes.len());
+    let name = arg_bytes[..name_len].to_vec();
+    // Points to the value after the '=', if there is one.
+    let value = (name_len < arg_bytes.len()).then(|| arg + 2 + name_len as u32 + 1);
+
+    // Exact matches take priority, otherwise an unambiguous prefix is allowed.
+    let mut exact_match = None;
+    let mut prefix_matches = Vec::new();
+    for i in 0.. {
+        let option = env.mem.read(longopts + i);
+        if option.name.is_null() {
+            break;
+        }
+        let option_name = env.mem.cstr_at(option.name);
+        if option_name == name {
+            exact_match = Some((i, option));
+            break;
+        } else if option_name.starts_with(&name) {
+            prefix_matches.push((i, option));
+        }
+    }
+    let name = String::from_utf8_lossy(&name).into_owned();
+    let (index, option) = match (exact_match, prefix_matches.as_slice()) {
+        (Some(found), _) | (None, &[found]) => found,
+        (None, matches) => {
+            env.mem.write(globals.optopt, 0);
+            if !silent {
+                let message = if matches.is_empty() {
+                    format!("unrecognized option `--{}'", name)
+                } else {
+                    format!("option `--{}' is ambiguous", name)
+                };
+                report_error(env, argv, message);
+            }
+            return b'?'.into();
+        }
+    };
+
+    match option.has_arg {
+        NO_ARGUMENT if value.is_some() => {
+            env.mem.write(globals.optopt, option.val);
+            if !silent {
+                let message = format!("option `--{}' doesn't allow an argument", name);
+                report_error(env, argv, message);
+            }
+            return b'?'.into();
+        }
+        REQUIRED_ARGUMENT => {
+            if let Some(value) = value {
+                env.mem.write(globals.optarg, value);
+            } else if *optind < argc {
+                let optarg = env.mem.read(argv + *optind as u32);
+                env.mem.write(globals.optarg, optarg);
+                *optind += 1;
+            } else {
+                env.mem.write(globals.optopt, option.val);
+                if silent {
+                    return b':'.into();
+                }
+                let message = format!("option `--{}' requires an argument", name);
+                report_error(env, argv, message);
+                return b'?'.into();
+            }
+        }
+        OPTIONAL_ARGUMENT => {
+            if let Some(value) = value {
+                env.mem.write(globals.optarg, value);
+            }
+        }
+        _ => (),
+    }
+
+    if !longindex.is_null() {
+        env.mem.write(longindex, index as i32);
+    }
+    if option.flag.is_null() {
+        option.val
+    } else {
+        env.mem.write(option.flag, option.val);
+        0
+    }
+}
+
+fn getopt(
+    env: &mut Environment,
+    argc: i32,
+    argv: ConstPtr<MutPtr<u8>>,
+    optstring: ConstPtr<u8>,
+) -> i32 {
+    getopt_inner(env, argc, argv, optstring, None)
+}
+
+fn getopt_long(
+    env: &mut Environment,
+    argc: i32,
+    argv: ConstPtr<MutPtr<u8>>,
+    optstring: ConstPtr<u8>,
+    longopts: ConstPtr<option>,
+    longindex: MutPtr<i32>,
+) -> i32 {
+    getopt_inner(env, argc, argv, optstring, Some((longopts, longindex)))
+}
+
+pub const CONSTANTS: ConstantExports = &[
+    (
+        "_optarg",
+        HostConstant::Variable(|env| globals(env).optarg.cast().cast_const()),
+    ),
+    (
+        "_optind",
+        HostConstant::Variable(|env| globals(env).optind.cast().cast_const()),
+    ),
+    (
+        "_opterr",
+        HostConstant::Variable(|env| globals(env).opterr.cast().cast_const()),
+    ),
+    (
+        "_optopt",
+        HostConstant::Variable(|env| globals(env).optopt.cast().cast_const()),
+    ),
+];
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(getopt(_, _, _)),
+    export_c_func!(getopt_long(_, _, _, _, _)),
+];
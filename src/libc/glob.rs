@@ -0,0 +1,301 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `glob.h`
+
+use crate::abi::GuestFunction;
+use crate::dyld::{export_c_func, FunctionExports};
+use crate::fs::GuestPath;
+use crate::mem::{guest_size_of, ConstPtr, GuestUSize, MutPtr, MutVoidPtr, Ptr, SafeRead};
+use crate::Environment;
+
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone)]
+#[repr(C, packed)]
+pub struct glob_t {
+    gl_pathc: GuestUSize,
+    gl_matchc: i32,
+    gl_offs: GuestUSize,
+    gl_flags: i32,
+    gl_pathv: MutPtr<MutPtr<u8>>,
+    // The rest are only used with GLOB_ALTDIRFUNC, which isn't supported.
+    gl_errfunc: GuestFunction,
+    gl_closedir: GuestFunction,
+    gl_readdir: GuestFunction,
+    gl_opendir: GuestFunction,
+    gl_lstat: GuestFunction,
+    gl_stat: GuestFunction,
+}
+unsafe impl SafeRead for glob_t {}
+
+const GLOB_APPEND: i32 = 0x1;
+const GLOB_DOOFFS: i32 = 0x2;
+const GLOB_ERR: i32 = 0x4;
+const GLOB_MARK: i32 = 0x8;
+const GLOB_NOCHECK: i32 = 0x10;
+const GLOB_NOSORT: i32 = 0x20;
+const GLOB_MAGCHAR: i32 = 0x100;
+const GLOB_NOESCAPE: i32 = 0x2000;
+const SUPPORTED_FLAGS: i32 =
+    GLOB_APPEND | GLOB_DOOFFS | GLOB_ERR | GLOB_MARK | GLOB_NOCHECK | GLOB_NOSORT | GLOB_NOESCAPE;
+
+const GLOB_NOMATCH: i32 = -3;
+
+fn has_magic(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '['])
+}
+
+/// Match a character class (the part of the pattern after the `[`). Returns
+/// whether it matched and the rest of the pattern, or [None] if the class is
+/// unterminated, in which case the `[` should be matched literally.
+fn match_class(class: &[u8], c: u8) -> Option<(bool, &[u8])> {
+    let (negate, mut class) = match class {
+        [b'!' | b'^', rest @ ..] => (true, rest),
+        _ => (false, class),
+    };
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        match class {
+            [] => return None,
+            [b']', rest @ ..] if !first => return Some((matched != negate, rest)),
+            [lo, b'-', hi, rest @ ..] if *hi != b']' => {
+                matched |= (*lo..=*hi).contains(&c);
+                class = rest;
+            }
+            [x, rest @ ..] => {
+                matched |= *x == c;
+                class = rest;
+            }
+        }
+        first = false;
+    }
+}
+
+/// Match a single path component against a pattern, like `fnmatch()` with
+/// `FNM_PERIOD` (a leading `.` must be matched explicitly).
+fn matches(pattern: &[u8], name: &[u8], escape: bool) -> bool {
+    if name.first() == Some(&b'.') && pattern.first() != Some(&b'.') {
+        return false;
+    }
+    matches_inner(pattern, name, escape)
+}
+
+fn matches_inner(pattern: &[u8], name: &[u8], escape: bool) -> bool {
+    match pattern {
+        [] => name.is_empty(),
+        [b'*', rest @ ..] => (0..=name.len()).any(|i| matches_inner(rest, &name[i..], escape)),
+        [b'?', rest @ ..] => !name.is_empty() && matches_inner(rest, &name[1..], escape),
+        [b'[', class @ ..] if !name.is_empty() => match match_class(class, name[0]) {
+            Some((matched, rest)) => matched && matches_inner(rest, &name[1..], escape),
+            None => name[0] == b'[' && matches_inner(class, &name[1..], escape),
+        },
+        [b'\\', c, rest @ ..] if escape => {
+            name.first() == Some(c) && matches_inner(rest, &name[1..], escape)
+        }
+        [c, rest @ ..] => name.first() == Some(c) && matches_inner(rest, &name[1..], escape),
+    }
+}
+
+/// Remove backslash escapes from a pattern component without magic.
+fn unescape(component: &str, escape: bool) -> String {
+    if !escape {
+        return component.to_string();
+    }
+    let mut res = String::with_capacity(component.len());
+    let mut chars = component.chars();
+    while let Some(c) = chars.next() {
+        res.push(if c == '\\' {
+            chars.next().unwrap_or(c)
+        } else {
+            c
+        });
+    }
+    res
+}
+
+/// Find the paths matching a pattern in the guest filesystem. The paths are in
+/// the order the filesystem lists them.
+fn find_matches(env: &Environment, pattern: &str, escape: bool) -> Vec<String> {
+    let (mut paths, components) = match pattern.strip_prefix('/') {
+        Some(rest) => (vec!["/".to_string()], rest),
+        None => (vec![String::new()], pattern),
+    };
+    for component in components.split('/').filter(|c| !c.is_empty()) {
+        let mut new_paths = Vec::new();
+        for path in paths {
+            let join = |name: &str| {
+                if path.is_empty() || path.ends_with('/') {
+                    format!("{}{}", path, name)
+                } else {
+                    format!("{}/{}", path, name)
+                }
+            };
+            if !has_magic(component) {
+                let new_path = join(&unescape(component, escape));
+                if env.fs.exists(GuestPath::new(&new_path)) {
+                    new_paths.push(new_path);
+                }
+                continue;
+            }
+            let dir = if path.is_empty() {
+                env.fs.working_directory()
+            } else {
+                GuestPath::new(&path)
+            };
+            let Ok(names) = env.fs.enumerate(dir) else {
+                continue;
+            };
+            for name in names {
+                if matches(component.as_bytes(), name.as_bytes(), escape) {
+                    new_paths.push(join(name));
+                }
+            }
+        }
+        paths = new_paths;
+    }
+    // A pattern of just "/" matches the root, but an empty one matches nothing.
+    paths.retain(|path| !path.is_empty());
+    paths
+}
+
+fn glob(
+    env: &mut Environment,
+    pattern: ConstPtr<u8>,
+    flags: i32,
+    errfunc: GuestFunction,
+    pglob: MutPtr<glob_t>,
+) -> i32 {
+    let pattern_str = String::from_utf8_lossy(env.mem.cstr_at(pattern)).into_owned();
+    if flags & !SUPPORTED_FLAGS != 0 {
+        log!(
+            "TODO: glob({:?}) flags {:#x} are unsupported, ignoring them",
+            pattern_str,
+            flags & !SUPPORTED_FLAGS
+        );
+    }
+    if !errfunc.to_ptr().is_null() {
+        // There are no read errors in the guest filesystem to report.
+        log_dbg!("glob({:?}) ignoring errfunc {:?}", pattern_str, errfunc);
+    }
+
+    let escape = flags & GLOB_NOESCAPE == 0;
+    let mut paths = find_matches(env, &pattern_str, escape);
+    let match_count = paths.len();
+    if flags & GLOB_NOSORT == 0 {
+        paths.sort();
+    }
+    if flags & GLOB_MARK != 0 {
+        for path in paths.iter_mut() {
+            if !path.ends_with('/') && env.fs.is_dir(GuestPath::new(path)) {
+                path.push('/');
+            }
+        }
+    }
+    if paths.is_empty() && flags & GLOB_NOCHECK != 0 {
+        paths.push(pattern_str.clone());
+    }
+    log_dbg!("glob({:?}, {:#x}) => {:?}", pattern_str, flags, paths);
+
+    let mut glob = env.mem.read(pglob);
+    if flags & GLOB_APPEND == 0 {
+        glob.gl_pathc = 0;
+        glob.gl_pathv = Ptr::null();
+        if flags & GLOB_DOOFFS == 0 {
+            glob.gl_offs = 0;
+        }
+    }
+    let offs = if flags & GLOB_DOOFFS != 0 {
+        glob.gl_offs
+    } else {
+        0
+    };
+
+    // The list has the reserved slots, the old paths, the new paths, then a
+    // null terminator.
+    let old_count = offs + glob.gl_pathc;
+    let new_count = old_count + GuestUSize::try_from(paths.len()).unwrap();
+    let size = (new_count + 1) * guest_size_of::<MutPtr<u8>>();
+    let pathv: MutPtr<MutPtr<u8>> = if glob.gl_pathv.is_null() {
+        env.mem.alloc(size).cast()
+    } else {
+        env.mem.realloc(glob.gl_pathv.cast(), size).cast()
+    };
+    if glob.gl_pathv.is_null() {
+        for i in 0..offs {
+            env.mem.write(pathv + i, Ptr::null());
+        }
+    }
+    for (i, path) in paths.iter().enumerate() {
+        let path = env.mem.alloc_and_write_cstr(path.as_bytes());
+        env.mem
+            .write(pathv + old_count + GuestUSize::try_from(i).unwrap(), path);
+    }
+    env.mem.write(pathv + new_count, Ptr::null());
+
+    glob.gl_pathv = pathv;
+    glob.gl_pathc = new_count - offs;
+    glob.gl_matchc = match_count.try_into().unwrap();
+    glob.gl_flags = flags
+        | if has_magic(&pattern_str) {
+            GLOB_MAGCHAR
+        } else {
+            0
+        };
+    env.mem.write(pglob, glob);
+
+    if paths.is_empty() {
+        GLOB_NOMATCH
+    } else {
+        0 // success
+    }
+}
+
+fn globfree(env: &mut Environment, pglob: MutPtr<glob_t>) {
+    let mut glob = env.mem.read(pglob);
+    if glob.gl_pathv.is_null() {
+        return;
+    }
+    let offs = if glob.gl_flags & GLOB_DOOFFS != 0 {
+        glob.gl_offs
+    } else {
+        0
+    };
+    for i in offs..(offs + glob.gl_pathc) {
+        let path: MutVoidPtr = env.mem.read(glob.gl_pathv + i).cast();
+        env.mem.free(path);
+    }
+    env.mem.free(glob.gl_pathv.cast());
+    glob.gl_pathv = Ptr::null();
+    glob.gl_pathc = 0;
+    env.mem.write(pglob, glob);
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(glob(_, _, _, _)),
+    export_c_func!(globfree(_)),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::matches;
+
+    #[test]
+    fn test_matches() {
+        assert!(matches(b"*.lvl", b"level1.lvl", true));
+        assert!(!matches(b"*.lvl", b"level1.lvl.bak", true));
+        assert!(!matches(b"*.lvl", b".hidden.lvl", true));
+        assert!(matches(b".*", b".hidden", true));
+        assert!(matches(b"level?.lvl", b"level2.lvl", true));
+        assert!(!matches(b"level?.lvl", b"level10.lvl", true));
+        assert!(matches(b"[a-c]x", b"bx", true));
+        assert!(!matches(b"[!a-c]x", b"bx", true));
+        assert!(matches(b"[]]", b"]", true));
+        assert!(matches(b"[", b"[", true));
+        assert!(matches(b"\\*", b"*", true));
+        assert!(!matches(b"\\*", b"a", true));
+        assert!(matches(b"\\*", b"\\abc", false));
+    }
+}
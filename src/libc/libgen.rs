@@ -0,0 +1,127 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `libgen.h`
+//!
+//! Unlike glibc, Darwin never modifies the string passed to `basename()` or
+//! `dirname()`. The result is written to a buffer owned by the library instead.
+
+use crate::dyld::{export_c_func, FunctionExports};
+use crate::frameworks::foundation::ns_string::path_algorithms::split_last_path_component;
+use crate::libc::errno::{set_errno, ENAMETOOLONG};
+use crate::mem::{ConstPtr, GuestUSize, MutPtr, Ptr};
+use crate::{Environment, ThreadId};
+use std::collections::HashMap;
+
+const MAXPATHLEN: GuestUSize = 1024;
+
+#[derive(Default)]
+pub struct State {
+    /// Darwin uses a single buffer for each function, but a buffer per thread
+    /// is safer and apps can't tell the difference.
+    basename_buffers: HashMap<ThreadId, MutPtr<u8>>,
+    dirname_buffers: HashMap<ThreadId, MutPtr<u8>>,
+}
+
+fn basename_str(path: &str) -> &str {
+    if path.is_empty() {
+        "."
+    } else {
+        split_last_path_component(path).1
+    }
+}
+
+fn dirname_str(path: &str) -> &str {
+    match split_last_path_component(path).0 {
+        "" => ".",
+        dirname => dirname,
+    }
+}
+
+/// Shared part of the `_r` variants: write `f`'s result for `path` to `buf`,
+/// which must have room for `MAXPATHLEN` bytes.
+fn write_result(
+    env: &mut Environment,
+    path: ConstPtr<u8>,
+    buf: MutPtr<u8>,
+    f: fn(&str) -> &str,
+) -> MutPtr<u8> {
+    let path = if path.is_null() {
+        String::new()
+    } else {
+        String::from_utf8_lossy(env.mem.cstr_at(path)).into_owned()
+    };
+    let res = f(&path);
+    let len: GuestUSize = res.len().try_into().unwrap();
+    if len + 1 > MAXPATHLEN {
+        set_errno(env, ENAMETOOLONG);
+        return Ptr::null();
+    }
+    env.mem
+        .bytes_at_mut(buf, len)
+        .copy_from_slice(res.as_bytes());
+    env.mem.write(buf + len, b'\0');
+    buf
+}
+
+fn basename_r(env: &mut Environment, path: ConstPtr<u8>, bname: MutPtr<u8>) -> MutPtr<u8> {
+    write_result(env, path, bname, basename_str)
+}
+
+fn dirname_r(env: &mut Environment, path: ConstPtr<u8>, dname: MutPtr<u8>) -> MutPtr<u8> {
+    write_result(env, path, dname, dirname_str)
+}
+
+fn basename(env: &mut Environment, path: ConstPtr<u8>) -> MutPtr<u8> {
+    let thread = env.current_thread;
+    let buffers = &mut env.libc_state.libgen.basename_buffers;
+    let buf = *buffers
+        .entry(thread)
+        .or_insert_with(|| env.mem.alloc(MAXPATHLEN).cast());
+    basename_r(env, path, buf)
+}
+
+fn dirname(env: &mut Environment, path: ConstPtr<u8>) -> MutPtr<u8> {
+    let thread = env.current_thread;
+    let buffers = &mut env.libc_state.libgen.dirname_buffers;
+    let buf = *buffers
+        .entry(thread)
+        .or_insert_with(|| env.mem.alloc(MAXPATHLEN).cast());
+    dirname_r(env, path, buf)
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(basename(_)),
+    export_c_func!(basename_r(_, _)),
+    export_c_func!(dirname(_)),
+    export_c_func!(dirname_r(_, _)),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::{basename_str, dirname_str};
+
+    #[test]
+    fn test_edge_cases() {
+        // From the table in the basename(3) and dirname(3) man pages, plus
+        // some other cases apps are known to rely on.
+        let cases = [
+            ("/usr/lib", "/usr", "lib"),
+            ("/usr/", "/", "usr"),
+            ("usr", ".", "usr"),
+            ("/", "/", "/"),
+            (".", ".", "."),
+            ("..", ".", ".."),
+            ("", ".", "."),
+            ("a/", ".", "a"),
+            ("//foo", "/", "foo"),
+            ("/a//b//", "/a", "b"),
+        ];
+        for (path, dirname, basename) in cases {
+            assert_eq!(dirname_str(path), dirname, "dirname({:?})", path);
+            assert_eq!(basename_str(path), basename, "basename({:?})", path);
+        }
+    }
+}
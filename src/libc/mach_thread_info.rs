@@ -10,7 +10,7 @@
 #![allow(non_camel_case_types)]
 
 use crate::dyld::{export_c_func, FunctionExports};
-use crate::mem::{guest_size_of, GuestUSize, MutPtr, MutVoidPtr, Ptr, SafeRead};
+use crate::mem::{guest_size_of, GuestUSize, MutPtr, MutVoidPtr, SafeRead};
 use crate::Environment;
 use crate::environment::ThreadBlock::Suspended;
 use crate::environment::ThreadId;
@@ -18,6 +18,8 @@ use crate::environment::ThreadId;
 type kern_return_t = i32;
 type mach_msg_return_t = kern_return_t;
 const KERN_SUCCESS: kern_return_t = 0;
+const KERN_INVALID_ARGUMENT: kern_return_t = 4;
+const KERN_RESOURCE_SHORTAGE: kern_return_t = 6;
 
 type mach_port_t = u32;
 
@@ -32,10 +34,6 @@ type ipc_space_t = mach_port_t;
 type mach_port_name_t = natural_t;
 type mach_port_right_t = natural_t;
 
-type vm_map_t = mach_port_t;
-type mach_vm_address_t = u32;
-type mach_vm_size_t = u32;
-
 type thread_inspect_t = mach_port_t;
 type thread_flavor_t = natural_t;
 type thread_info_t = MutPtr<integer_t>;
@@ -174,6 +172,185 @@ fn thread_info(
     KERN_SUCCESS
 }
 
+type task_name_t = mach_port_t;
+type task_flavor_t = natural_t;
+type task_info_t = MutPtr<integer_t>;
+
+const TASK_BASIC_INFO_32: task_flavor_t = 4;
+const TASK_BASIC_INFO_64: task_flavor_t = 5;
+/// Newer SDKs use this value for `TASK_BASIC_INFO_64` on Arm.
+const TASK_BASIC_INFO_64_2: task_flavor_t = 18;
+
+#[repr(C, packed)]
+struct task_basic_info_32 {
+    suspend_count: integer_t,
+    virtual_size: natural_t,
+    resident_size: natural_t,
+    user_time: time_value_t,
+    system_time: time_value_t,
+    policy: policy_t,
+}
+unsafe impl SafeRead for task_basic_info_32 {}
+
+#[repr(C, packed)]
+struct task_basic_info_64 {
+    suspend_count: integer_t,
+    virtual_size: u64,
+    resident_size: u64,
+    user_time: time_value_t,
+    system_time: time_value_t,
+    policy: policy_t,
+}
+unsafe impl SafeRead for task_basic_info_64 {}
+
+/// The same as what `sysctlbyname("hw.physmem")` reports.
+const PHYSICAL_MEMORY_SIZE: u64 = 121634816;
+const PAGE_SIZE: u64 = 4096;
+
+/// Returns the task's user time and resident size. The guest heap usage is
+/// the closest thing touchHLE has to a resident size.
+fn task_usage(env: &Environment) -> (time_value_t, u64) {
+    let elapsed = std::time::Instant::now().duration_since(env.startup_time);
+    let user_time = time_value_t {
+        seconds: elapsed.as_secs() as integer_t,
+        microseconds: elapsed.subsec_micros() as integer_t,
+    };
+    (user_time, env.mem.used_bytes())
+}
+
+fn task_info(
+    env: &mut Environment,
+    target_task: task_name_t,
+    flavor: task_flavor_t,
+    task_info_out: task_info_t,
+    task_info_out_count: MutPtr<mach_msg_type_number_t>,
+) -> kern_return_t {
+    assert_eq!(target_task, 0); // mach_task_self_
+
+    let out_size_available = env.mem.read(task_info_out_count);
+    let (user_time, resident_size) = task_usage(env);
+    let system_time = time_value_t {
+        seconds: 0,
+        microseconds: 0,
+    };
+
+    let out_size_expected = match flavor {
+        TASK_BASIC_INFO_32 => {
+            let out_size_expected =
+                guest_size_of::<task_basic_info_32>() / guest_size_of::<integer_t>();
+            if out_size_available < out_size_expected {
+                return KERN_INVALID_ARGUMENT;
+            }
+            let resident_size = resident_size.min(natural_t::MAX.into()) as natural_t;
+            env.mem.write(
+                task_info_out.cast(),
+                task_basic_info_32 {
+                    suspend_count: 0,
+                    virtual_size: resident_size,
+                    resident_size,
+                    user_time,
+                    system_time,
+                    policy: POLICY_TIMESHARE,
+                },
+            );
+            out_size_expected
+        }
+        TASK_BASIC_INFO_64 | TASK_BASIC_INFO_64_2 => {
+            let out_size_expected =
+                guest_size_of::<task_basic_info_64>() / guest_size_of::<integer_t>();
+            if out_size_available < out_size_expected {
+                return KERN_INVALID_ARGUMENT;
+            }
+            env.mem.write(
+                task_info_out.cast(),
+                task_basic_info_64 {
+                    suspend_count: 0,
+                    virtual_size: resident_size,
+                    resident_size,
+                    user_time,
+                    system_time,
+                    policy: POLICY_TIMESHARE,
+                },
+            );
+            out_size_expected
+        }
+        _ => {
+            log!("TODO: task_info flavor {}", flavor);
+            return KERN_INVALID_ARGUMENT;
+        }
+    };
+    env.mem.write(task_info_out_count, out_size_expected);
+
+    KERN_SUCCESS
+}
+
+type host_t = mach_port_t;
+type host_flavor_t = integer_t;
+type host_info_t = MutPtr<integer_t>;
+
+const HOST_VM_INFO: host_flavor_t = 2;
+
+/// Number of `natural_t` fields in the current revision of `vm_statistics`.
+const HOST_VM_INFO_COUNT: mach_msg_type_number_t = 15;
+
+fn host_statistics(
+    env: &mut Environment,
+    _host_priv: host_t,
+    flavor: host_flavor_t,
+    host_info_out: host_info_t,
+    host_info_out_count: MutPtr<mach_msg_type_number_t>,
+) -> kern_return_t {
+    if flavor != HOST_VM_INFO {
+        log!("TODO: host_statistics flavor {}", flavor);
+        return KERN_INVALID_ARGUMENT;
+    }
+
+    // Pretend the system uses a fixed amount of memory, the app uses whatever
+    // is allocated in guest memory, and the rest is free.
+    let total_pages = PHYSICAL_MEMORY_SIZE / PAGE_SIZE;
+    let wire_count = (32 * 1024 * 1024) / PAGE_SIZE;
+    let (_, resident_size) = task_usage(env);
+    let active_count = resident_size.div_ceil(PAGE_SIZE).min(total_pages - wire_count);
+    let free_count = total_pages - wire_count - active_count;
+
+    // struct vm_statistics. Older revisions of the struct are prefixes of
+    // newer ones, so only as many fields as the app asks for are written.
+    let fields: [natural_t; HOST_VM_INFO_COUNT as usize] = [
+        free_count as natural_t,   // free_count
+        active_count as natural_t, // active_count
+        0,                         // inactive_count
+        wire_count as natural_t,   // wire_count
+        0,                         // zero_fill_count
+        0,                         // reactivations
+        0,                         // pageins
+        0,                         // pageouts
+        0,                         // faults
+        0,                         // cow_faults
+        0,                         // lookups
+        0,                         // hits
+        0,                         // purgeable_count
+        0,                         // purges
+        0,                         // speculative_count
+    ];
+    let count = env.mem.read(host_info_out_count).min(HOST_VM_INFO_COUNT);
+    for i in 0..count {
+        env.mem
+            .write(host_info_out.cast::<natural_t>() + i, fields[i as usize]);
+    }
+    env.mem.write(host_info_out_count, count);
+
+    KERN_SUCCESS
+}
+
+fn mach_task_self(_env: &mut Environment) -> task_t {
+    0 // same as mach_task_self_
+}
+
+fn mach_host_self(_env: &mut Environment) -> host_t {
+    // Not a real port, but it's not used for anything.
+    1
+}
+
 type thread_t = mach_port_t;
 type thread_policy_flavor_t = natural_t;
 type thread_policy_t = MutPtr<integer_t>;
@@ -209,7 +386,11 @@ fn task_threads(
 ) -> kern_return_t {
     assert_eq!(task, 0); // mach_task_self_
     let thread_count = env.threads.len() as GuestUSize;
-    let arr: MutPtr<thread_act_t> = env.mem.alloc(thread_count * guest_size_of::<thread_act_t>()).cast();
+    // The app frees this with vm_deallocate(), see mach_vm.rs.
+    let Some(arr) = env.mem.alloc_pages(thread_count * guest_size_of::<thread_act_t>()) else {
+        return KERN_RESOURCE_SHORTAGE;
+    };
+    let arr: MutPtr<thread_act_t> = arr.cast();
     for i in 0..thread_count {
         env.mem.write(arr + i, i);
     }
@@ -262,18 +443,6 @@ fn mach_port_insert_right(
     KERN_SUCCESS
 }
 
-fn vm_deallocate(
-    env: &mut Environment,
-    target_task: vm_map_t,
-    address: mach_vm_address_t,
-    size: mach_vm_size_t
-) -> kern_return_t {
-    // Is it OK? vm_deallocate() can be called to free a list created by task_threads()
-    // But in general there is no guarantee what memory was previously allocated by malloc!
-    env.mem.free(Ptr::from_bits(address));
-    KERN_SUCCESS
-}
-
 fn exc_server(
     env: &mut Environment,
     request_msg: MutVoidPtr, // MutPtr<mach_msg_header_t>,
@@ -341,13 +510,16 @@ fn thread_get_state(
 pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(thread_info(_, _, _, _)),
     export_c_func!(thread_policy_set(_, _, _, _)),
+    export_c_func!(task_info(_, _, _, _)),
+    export_c_func!(host_statistics(_, _, _, _)),
+    export_c_func!(mach_task_self()),
+    export_c_func!(mach_host_self()),
     export_c_func!(mach_thread_self()),
     export_c_func!(task_threads(_, _, _)),
     export_c_func!(mach_msg(_, _, _, _, _, _, _)),
     export_c_func!(mach_port_allocate(_, _, _)),
     export_c_func!(mach_port_deallocate(_, _)),
     export_c_func!(mach_port_insert_right(_, _, _, _)),
-    export_c_func!(vm_deallocate(_, _, _)),
     export_c_func!(exc_server(_, _)),
     export_c_func!(task_set_exception_ports(_, _, _, _, _)),
     export_c_func!(thread_suspend(_)),
@@ -0,0 +1,190 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! The virtual memory subset of the Mach API (`mach/vm_map.h`,
+//! `mach/mach_host.h` and friends).
+//!
+//! touchHLE has no real notion of pages, so these are implemented on top of the
+//! guest memory allocator, with allocations rounded to page boundaries.
+
+#![allow(non_camel_case_types)]
+
+use crate::dyld::{export_c_func, ConstantExports, FunctionExports, HostConstant};
+use crate::mem::{GuestUSize, Mem, MutPtr, VAddr};
+use crate::Environment;
+
+type kern_return_t = i32;
+const KERN_SUCCESS: kern_return_t = 0;
+const KERN_INVALID_ADDRESS: kern_return_t = 1;
+const KERN_NO_SPACE: kern_return_t = 3;
+const KERN_INVALID_ARGUMENT: kern_return_t = 4;
+
+type mach_port_t = u32;
+type vm_map_t = mach_port_t;
+type host_t = mach_port_t;
+type vm_address_t = VAddr;
+type vm_size_t = GuestUSize;
+type vm_prot_t = i32;
+type vm_inherit_t = u32;
+type boolean_t = i32;
+
+const PAGE_SIZE: vm_size_t = 4096;
+
+/// If this isn't set, `vm_allocate` uses the address it was given.
+const VM_FLAGS_ANYWHERE: i32 = 0x1;
+
+const VM_PROT_ALL: vm_prot_t = 0x1 | 0x2 | 0x4; // read, write, execute
+
+/// `VM_INHERIT_SHARE`, `VM_INHERIT_COPY`, `VM_INHERIT_NONE` and the obsolete
+/// `VM_INHERIT_DONATE_COPY`.
+const VM_INHERIT_LAST_VALID: vm_inherit_t = 3;
+
+/// Check the task is `mach_task_self()`, which is the only one we have.
+fn is_own_task(target_task: vm_map_t) -> bool {
+    target_task == 0 // mach_task_self_
+}
+
+/// Get the page-aligned range covering `size` bytes from `address`, or [None]
+/// if it would go past the end of the address space.
+fn page_range(address: vm_address_t, size: vm_size_t) -> Option<(VAddr, GuestUSize)> {
+    let base = address - address % PAGE_SIZE;
+    let end = address
+        .checked_add(size)?
+        .checked_next_multiple_of(PAGE_SIZE)?;
+    Some((base, end - base))
+}
+
+fn vm_allocate(
+    env: &mut Environment,
+    target_task: vm_map_t,
+    address: MutPtr<vm_address_t>,
+    size: vm_size_t,
+    flags: i32,
+) -> kern_return_t {
+    if !is_own_task(target_task) || address.is_null() {
+        return KERN_INVALID_ARGUMENT;
+    }
+    if size == 0 {
+        env.mem.write(address, 0);
+        return KERN_SUCCESS;
+    }
+
+    let result = if flags & VM_FLAGS_ANYWHERE != 0 {
+        env.mem.alloc_pages(size).map(|ptr| ptr.to_bits())
+    } else {
+        let requested = env.mem.read(address);
+        page_range(requested, size)
+            .filter(|&(base, size)| env.mem.try_reserve(base, size))
+            .map(|(base, _)| base)
+    };
+    log_dbg!(
+        "vm_allocate({:#x} bytes, flags {:#x}) => {:#x?}",
+        size,
+        flags,
+        result
+    );
+
+    // Unused guest memory is always zeroed, so there's nothing else to do.
+    let Some(base) = result else {
+        return KERN_NO_SPACE;
+    };
+    env.mem.write(address, base);
+    KERN_SUCCESS
+}
+
+fn vm_deallocate(
+    env: &mut Environment,
+    target_task: vm_map_t,
+    address: vm_address_t,
+    size: vm_size_t,
+) -> kern_return_t {
+    if !is_own_task(target_task) {
+        return KERN_INVALID_ARGUMENT;
+    }
+    if size == 0 {
+        return KERN_SUCCESS;
+    }
+    let Some((base, size)) = page_range(address, size) else {
+        return KERN_INVALID_ADDRESS;
+    };
+    log_dbg!("vm_deallocate({:#x}, {:#x} bytes)", base, size);
+    // Freeing a thread's stack would let it get reused while still in use.
+    let overlaps_stack = env.threads.iter().any(|thread| {
+        thread.stack.as_ref().is_some_and(|stack| {
+            u64::from(base) <= u64::from(*stack.end())
+                && u64::from(*stack.start()) < u64::from(base) + u64::from(size)
+        })
+    });
+    if overlaps_stack || !env.mem.can_free_range(base, size) {
+        log!(
+            "Warning: vm_deallocate({:#x}, {:#x} bytes) on memory that wasn't allocated, ignoring",
+            base,
+            size
+        );
+        return KERN_INVALID_ADDRESS;
+    }
+    env.mem.free_range(base, size);
+    KERN_SUCCESS
+}
+
+fn vm_protect(
+    _env: &mut Environment,
+    target_task: vm_map_t,
+    address: vm_address_t,
+    size: vm_size_t,
+    _set_maximum: boolean_t,
+    new_protection: vm_prot_t,
+) -> kern_return_t {
+    // touchHLE doesn't enforce memory protection, so this only validates.
+    if !is_own_task(target_task) || new_protection & !VM_PROT_ALL != 0 {
+        return KERN_INVALID_ARGUMENT;
+    }
+    if page_range(address, size).is_none() {
+        return KERN_INVALID_ADDRESS;
+    }
+    KERN_SUCCESS
+}
+
+fn vm_inherit(
+    _env: &mut Environment,
+    target_task: vm_map_t,
+    address: vm_address_t,
+    size: vm_size_t,
+    new_inheritance: vm_inherit_t,
+) -> kern_return_t {
+    // There's no fork(), so inheritance can't matter.
+    if !is_own_task(target_task) || new_inheritance > VM_INHERIT_LAST_VALID {
+        return KERN_INVALID_ARGUMENT;
+    }
+    if page_range(address, size).is_none() {
+        return KERN_INVALID_ADDRESS;
+    }
+    KERN_SUCCESS
+}
+
+fn host_page_size(
+    env: &mut Environment,
+    _host: host_t,
+    out_page_size: MutPtr<vm_size_t>,
+) -> kern_return_t {
+    if out_page_size.is_null() {
+        return KERN_INVALID_ARGUMENT;
+    }
+    env.mem.write(out_page_size, PAGE_SIZE);
+    KERN_SUCCESS
+}
+
+pub const CONSTANTS: ConstantExports = &[(
+    "_vm_page_size",
+    HostConstant::Custom(|mem: &mut Mem| mem.alloc_and_write(PAGE_SIZE).cast().cast_const()),
+)];
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(vm_allocate(_, _, _, _)),
+    export_c_func!(vm_deallocate(_, _, _)),
+    export_c_func!(vm_protect(_, _, _, _, _)),
+    export_c_func!(vm_inherit(_, _, _, _)),
+    export_c_func!(host_page_size(_, _)),
+];
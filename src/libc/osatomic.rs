@@ -0,0 +1,298 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `libkern/OSAtomic.h`
+//!
+//! All guest threads run on a single host thread. The scheduler only switches
+//! between them after some number of emulated instructions, and host functions
+//! like these always run to completion before that can happen. That means
+//! plain reads and writes of guest memory are already atomic with respect to
+//! other guest threads, and every barrier is a no-op.
+
+use crate::dyld::{export_c_func, FunctionExports};
+use crate::mem::{GuestUSize, MutPtr, MutVoidPtr, SafeRead};
+use crate::Environment;
+
+/// Apply `f` to the value at `ptr`, returning the old and new values.
+fn modify<T: SafeRead + Copy>(
+    env: &mut Environment,
+    ptr: MutPtr<T>,
+    f: impl FnOnce(T) -> T,
+) -> (T, T) {
+    let old = env.mem.read(ptr);
+    let new = f(old);
+    env.mem.write(ptr, new);
+    (old, new)
+}
+
+fn compare_and_swap<T: SafeRead + Copy + PartialEq>(
+    env: &mut Environment,
+    old: T,
+    new: T,
+    ptr: MutPtr<T>,
+) -> bool {
+    if env.mem.read(ptr) != old {
+        return false;
+    }
+    env.mem.write(ptr, new);
+    true
+}
+
+// Arithmetic. These return the new value.
+
+fn OSAtomicAdd32(env: &mut Environment, amount: i32, value: MutPtr<i32>) -> i32 {
+    modify(env, value, |v| v.wrapping_add(amount)).1
+}
+fn OSAtomicAdd32Barrier(env: &mut Environment, amount: i32, value: MutPtr<i32>) -> i32 {
+    OSAtomicAdd32(env, amount, value)
+}
+fn OSAtomicIncrement32(env: &mut Environment, value: MutPtr<i32>) -> i32 {
+    OSAtomicAdd32(env, 1, value)
+}
+fn OSAtomicIncrement32Barrier(env: &mut Environment, value: MutPtr<i32>) -> i32 {
+    OSAtomicAdd32(env, 1, value)
+}
+fn OSAtomicDecrement32(env: &mut Environment, value: MutPtr<i32>) -> i32 {
+    OSAtomicAdd32(env, -1, value)
+}
+fn OSAtomicDecrement32Barrier(env: &mut Environment, value: MutPtr<i32>) -> i32 {
+    OSAtomicAdd32(env, -1, value)
+}
+
+fn OSAtomicAdd64(env: &mut Environment, amount: i64, value: MutPtr<i64>) -> i64 {
+    modify(env, value, |v| v.wrapping_add(amount)).1
+}
+fn OSAtomicAdd64Barrier(env: &mut Environment, amount: i64, value: MutPtr<i64>) -> i64 {
+    OSAtomicAdd64(env, amount, value)
+}
+fn OSAtomicIncrement64(env: &mut Environment, value: MutPtr<i64>) -> i64 {
+    OSAtomicAdd64(env, 1, value)
+}
+fn OSAtomicIncrement64Barrier(env: &mut Environment, value: MutPtr<i64>) -> i64 {
+    OSAtomicAdd64(env, 1, value)
+}
+fn OSAtomicDecrement64(env: &mut Environment, value: MutPtr<i64>) -> i64 {
+    OSAtomicAdd64(env, -1, value)
+}
+fn OSAtomicDecrement64Barrier(env: &mut Environment, value: MutPtr<i64>) -> i64 {
+    OSAtomicAdd64(env, -1, value)
+}
+
+// Bitwise operations. The plain versions return the new value, the `Orig`
+// versions return the old one.
+
+fn OSAtomicOr32(env: &mut Environment, mask: u32, value: MutPtr<u32>) -> u32 {
+    modify(env, value, |v| v | mask).1
+}
+fn OSAtomicOr32Barrier(env: &mut Environment, mask: u32, value: MutPtr<u32>) -> u32 {
+    OSAtomicOr32(env, mask, value)
+}
+fn OSAtomicOr32Orig(env: &mut Environment, mask: u32, value: MutPtr<u32>) -> u32 {
+    modify(env, value, |v| v | mask).0
+}
+fn OSAtomicOr32OrigBarrier(env: &mut Environment, mask: u32, value: MutPtr<u32>) -> u32 {
+    OSAtomicOr32Orig(env, mask, value)
+}
+fn OSAtomicAnd32(env: &mut Environment, mask: u32, value: MutPtr<u32>) -> u32 {
+    modify(env, value, |v| v & mask).1
+}
+fn OSAtomicAnd32Barrier(env: &mut Environment, mask: u32, value: MutPtr<u32>) -> u32 {
+    OSAtomicAnd32(env, mask, value)
+}
+fn OSAtomicAnd32Orig(env: &mut Environment, mask: u32, value: MutPtr<u32>) -> u32 {
+    modify(env, value, |v| v & mask).0
+}
+fn OSAtomicAnd32OrigBarrier(env: &mut Environment, mask: u32, value: MutPtr<u32>) -> u32 {
+    OSAtomicAnd32Orig(env, mask, value)
+}
+fn OSAtomicXor32(env: &mut Environment, mask: u32, value: MutPtr<u32>) -> u32 {
+    modify(env, value, |v| v ^ mask).1
+}
+fn OSAtomicXor32Barrier(env: &mut Environment, mask: u32, value: MutPtr<u32>) -> u32 {
+    OSAtomicXor32(env, mask, value)
+}
+fn OSAtomicXor32Orig(env: &mut Environment, mask: u32, value: MutPtr<u32>) -> u32 {
+    modify(env, value, |v| v ^ mask).0
+}
+fn OSAtomicXor32OrigBarrier(env: &mut Environment, mask: u32, value: MutPtr<u32>) -> u32 {
+    OSAtomicXor32Orig(env, mask, value)
+}
+
+// Compare-and-swap. These return whether the swap happened. `int` and `long`
+// are both 32-bit, like pointers.
+
+fn OSAtomicCompareAndSwap32(env: &mut Environment, old: i32, new: i32, value: MutPtr<i32>) -> bool {
+    compare_and_swap(env, old, new, value)
+}
+fn OSAtomicCompareAndSwap32Barrier(
+    env: &mut Environment,
+    old: i32,
+    new: i32,
+    value: MutPtr<i32>,
+) -> bool {
+    compare_and_swap(env, old, new, value)
+}
+fn OSAtomicCompareAndSwapInt(
+    env: &mut Environment,
+    old: i32,
+    new: i32,
+    value: MutPtr<i32>,
+) -> bool {
+    compare_and_swap(env, old, new, value)
+}
+fn OSAtomicCompareAndSwapIntBarrier(
+    env: &mut Environment,
+    old: i32,
+    new: i32,
+    value: MutPtr<i32>,
+) -> bool {
+    compare_and_swap(env, old, new, value)
+}
+fn OSAtomicCompareAndSwapLong(
+    env: &mut Environment,
+    old: i32,
+    new: i32,
+    value: MutPtr<i32>,
+) -> bool {
+    compare_and_swap(env, old, new, value)
+}
+fn OSAtomicCompareAndSwapLongBarrier(
+    env: &mut Environment,
+    old: i32,
+    new: i32,
+    value: MutPtr<i32>,
+) -> bool {
+    compare_and_swap(env, old, new, value)
+}
+fn OSAtomicCompareAndSwapPtr(
+    env: &mut Environment,
+    old: MutVoidPtr,
+    new: MutVoidPtr,
+    value: MutPtr<MutVoidPtr>,
+) -> bool {
+    compare_and_swap(env, old, new, value)
+}
+fn OSAtomicCompareAndSwapPtrBarrier(
+    env: &mut Environment,
+    old: MutVoidPtr,
+    new: MutVoidPtr,
+    value: MutPtr<MutVoidPtr>,
+) -> bool {
+    compare_and_swap(env, old, new, value)
+}
+fn OSAtomicCompareAndSwap64(env: &mut Environment, old: i64, new: i64, value: MutPtr<i64>) -> bool {
+    compare_and_swap(env, old, new, value)
+}
+fn OSAtomicCompareAndSwap64Barrier(
+    env: &mut Environment,
+    old: i64,
+    new: i64,
+    value: MutPtr<i64>,
+) -> bool {
+    compare_and_swap(env, old, new, value)
+}
+
+// Bit test-and-set. Bit `n` is `0x80 >> (n & 7)` in byte `n >> 3`, i.e. the
+// bits are numbered from the most significant end. These return the bit's
+// previous value.
+
+fn test_and_modify_bit(env: &mut Environment, n: u32, address: MutVoidPtr, set: bool) -> bool {
+    let byte_ptr = address.cast::<u8>() + (n >> 3);
+    let bit = 0x80u8 >> (n & 7);
+    let (old, _) = modify(
+        env,
+        byte_ptr,
+        |byte| if set { byte | bit } else { byte & !bit },
+    );
+    old & bit != 0
+}
+
+fn OSAtomicTestAndSet(env: &mut Environment, n: u32, address: MutVoidPtr) -> bool {
+    test_and_modify_bit(env, n, address, true)
+}
+fn OSAtomicTestAndSetBarrier(env: &mut Environment, n: u32, address: MutVoidPtr) -> bool {
+    test_and_modify_bit(env, n, address, true)
+}
+fn OSAtomicTestAndClear(env: &mut Environment, n: u32, address: MutVoidPtr) -> bool {
+    test_and_modify_bit(env, n, address, false)
+}
+fn OSAtomicTestAndClearBarrier(env: &mut Environment, n: u32, address: MutVoidPtr) -> bool {
+    test_and_modify_bit(env, n, address, false)
+}
+
+fn OSMemoryBarrier(_env: &mut Environment) {}
+
+// LIFO queue. `OSQueueHead` is `{ void *opaque1; long opaque2; }`, where the
+// first field points to the most recently enqueued element (the second is a
+// generation count used to avoid the ABA problem, which we don't have). Each
+// element stores the pointer to the next one at `offset` bytes into itself.
+
+fn OSAtomicEnqueue(
+    env: &mut Environment,
+    list: MutPtr<MutVoidPtr>,
+    new: MutVoidPtr,
+    offset: GuestUSize,
+) {
+    let head = env.mem.read(list);
+    env.mem.write((new.cast::<u8>() + offset).cast(), head);
+    env.mem.write(list, new);
+}
+
+fn OSAtomicDequeue(
+    env: &mut Environment,
+    list: MutPtr<MutVoidPtr>,
+    offset: GuestUSize,
+) -> MutVoidPtr {
+    let head = env.mem.read(list);
+    if !head.is_null() {
+        let next = env.mem.read((head.cast::<u8>() + offset).cast());
+        env.mem.write(list, next);
+    }
+    head
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(OSAtomicAdd32(_, _)),
+    export_c_func!(OSAtomicAdd32Barrier(_, _)),
+    export_c_func!(OSAtomicIncrement32(_)),
+    export_c_func!(OSAtomicIncrement32Barrier(_)),
+    export_c_func!(OSAtomicDecrement32(_)),
+    export_c_func!(OSAtomicDecrement32Barrier(_)),
+    export_c_func!(OSAtomicAdd64(_, _)),
+    export_c_func!(OSAtomicAdd64Barrier(_, _)),
+    export_c_func!(OSAtomicIncrement64(_)),
+    export_c_func!(OSAtomicIncrement64Barrier(_)),
+    export_c_func!(OSAtomicDecrement64(_)),
+    export_c_func!(OSAtomicDecrement64Barrier(_)),
+    export_c_func!(OSAtomicOr32(_, _)),
+    export_c_func!(OSAtomicOr32Barrier(_, _)),
+    export_c_func!(OSAtomicOr32Orig(_, _)),
+    export_c_func!(OSAtomicOr32OrigBarrier(_, _)),
+    export_c_func!(OSAtomicAnd32(_, _)),
+    export_c_func!(OSAtomicAnd32Barrier(_, _)),
+    export_c_func!(OSAtomicAnd32Orig(_, _)),
+    export_c_func!(OSAtomicAnd32OrigBarrier(_, _)),
+    export_c_func!(OSAtomicXor32(_, _)),
+    export_c_func!(OSAtomicXor32Barrier(_, _)),
+    export_c_func!(OSAtomicXor32Orig(_, _)),
+    export_c_func!(OSAtomicXor32OrigBarrier(_, _)),
+    export_c_func!(OSAtomicCompareAndSwap32(_, _, _)),
+    export_c_func!(OSAtomicCompareAndSwap32Barrier(_, _, _)),
+    export_c_func!(OSAtomicCompareAndSwapInt(_, _, _)),
+    export_c_func!(OSAtomicCompareAndSwapIntBarrier(_, _, _)),
+    export_c_func!(OSAtomicCompareAndSwapLong(_, _, _)),
+    export_c_func!(OSAtomicCompareAndSwapLongBarrier(_, _, _)),
+    export_c_func!(OSAtomicCompareAndSwapPtr(_, _, _)),
+    export_c_func!(OSAtomicCompareAndSwapPtrBarrier(_, _, _)),
+    export_c_func!(OSAtomicCompareAndSwap64(_, _, _)),
+    export_c_func!(OSAtomicCompareAndSwap64Barrier(_, _, _)),
+    export_c_func!(OSAtomicTestAndSet(_, _)),
+    export_c_func!(OSAtomicTestAndSetBarrier(_, _)),
+    export_c_func!(OSAtomicTestAndClear(_, _)),
+    export_c_func!(OSAtomicTestAndClearBarrier(_, _)),
+    export_c_func!(OSMemoryBarrier()),
+    export_c_func!(OSAtomicEnqueue(_, _, _)),
+    export_c_func!(OSAtomicDequeue(_, _)),
+];
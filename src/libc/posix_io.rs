@@ -5,22 +5,38 @@
  */
 //! POSIX I/O functions (`fcntl.h`, parts of `unistd.h`, etc)
 
+pub mod pipe;
 pub mod stat;
 
 use std::cell::{RefCell, RefMut};
 use crate::abi::DotDotDot;
 use crate::dyld::{export_c_func, FunctionExports};
-use crate::fs::{GuestFile, GuestOpenOptions, GuestPath};
-use crate::mem::{ConstPtr, ConstVoidPtr, GuestISize, GuestUSize, MutPtr, MutVoidPtr, Ptr, SafeRead};
+use crate::fs::{
+    resolve_guest_path, GuestFile, GuestOpenOptions, GuestPath, GuestPathBuf, PathIntent,
+    ResolveError,
+};
+use crate::libc::errno::{
+    set_errno, EBADF, EEXIST, EILSEQ, EINVAL, EISDIR, EMFILE, ENOENT, ENOTDIR, ERANGE, EROFS,
+};
+use crate::mem::{
+    ConstPtr, ConstVoidPtr, GuestISize, GuestUSize, MutPtr, MutVoidPtr, Ptr, SafeRead, VAddr,
+};
 use crate::Environment;
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::rc::Rc;
-use crate::libc::string::strcat;
+use crate::libc::socket;
 
 #[derive(Default)]
 pub struct State {
     /// File descriptors _other than stdin, stdout, and stderr_
     files: Vec<Option<Rc<RefCell<PosixFileHostObject>>>>,
+    /// The soft `RLIMIT_NOFILE`, if the app has changed it from the default
+    /// (the `--fd-limit=` option). See [fd_limit].
+    limit: Option<u32>,
+    /// Whether the descriptor table has already been dumped because the limit
+    /// was reached, so a leaking app retrying every frame doesn't flood the
+    /// log. See `--dump-open-files`.
+    dumped_at_limit: bool,
 }
 impl State {
     pub fn file_for_fd(&mut self, fd: FileDescriptor) -> Option<RefMut<PosixFileHostObject>> {
@@ -34,6 +50,13 @@ impl State {
 pub struct PosixFileHostObject {
     pub file: GuestFile,
     reached_eof: bool,
+    /// The path the file was opened with, if it was opened by path.
+    path: Option<GuestPathBuf>,
+    /// The flags the file was opened with.
+    flags: OpenFlag,
+    /// Guest backtrace from when the file was opened, only captured if call
+    /// tracing is enabled, for attributing descriptor leaks.
+    opened_at: Option<Vec<(VAddr, Option<String>)>>,
 }
 
 // TODO: stdin/stdout/stderr handling somehow
@@ -54,6 +77,9 @@ pub const STDOUT_FILENO: FileDescriptor = 1;
 pub const STDERR_FILENO: FileDescriptor = 2;
 const NORMAL_FILENO_BASE: FileDescriptor = STDERR_FILENO + 1;
 
+/// The highest value the descriptor limit can be set to (`OPEN_MAX`).
+pub const OPEN_MAX: u32 = 10240;
+
 /// Flags bitfield for `open`. This alias is for readability, POSIX just uses
 /// `int`.
 pub type OpenFlag = i32;
@@ -69,6 +95,7 @@ pub const O_NOFOLLOW: OpenFlag = 0x100;
 pub const O_CREAT: OpenFlag = 0x200;
 pub const O_TRUNC: OpenFlag = 0x400;
 pub const O_EXCL: OpenFlag = 0x800;
+pub const O_DIRECTORY: OpenFlag = 0x100000;
 
 pub type FLockFlag = i32;
 pub const LOCK_SH: FLockFlag = 1;
@@ -79,6 +106,8 @@ pub const LOCK_NB: FLockFlag = 4;
 #[allow(dead_code)]
 pub const LOCK_UN: FLockFlag = 8;
 
+pub const F_GETFL: i32 = 3;
+pub const F_SETFL: i32 = 4;
 pub const F_GETLK: i32 = 7;
 pub const F_SETLK: i32 = 8;
 pub const F_NOCACHE: i32 = 48;
@@ -96,6 +125,41 @@ struct FLockInfo {
 
 unsafe impl SafeRead for FLockInfo {}
 
+/// Read a path argument and resolve it with [resolve_guest_path]. On failure,
+/// this sets errno and returns [None].
+pub fn resolve_path_at(
+    env: &mut Environment,
+    path: ConstPtr<u8>,
+    intent: PathIntent,
+) -> Option<GuestPathBuf> {
+    let path_string = path_at(env, path)?;
+    match resolve_guest_path(env, GuestPath::new(&path_string), intent) {
+        Ok(resolved) => Some(resolved),
+        Err(err) => {
+            let errno = match err {
+                ResolveError::NotFound => ENOENT,
+                ResolveError::NotADirectory => ENOTDIR,
+                ResolveError::ReadOnly => EROFS,
+            };
+            set_errno(env, errno);
+            None
+        }
+    }
+}
+
+/// Read a path argument. touchHLE's guest filesystem, like HFS+, only allows
+/// UTF-8 names, so for anything else this sets errno to `EILSEQ` and returns
+/// [None].
+pub fn path_at(env: &mut Environment, path: ConstPtr<u8>) -> Option<String> {
+    let path = match env.mem.cstr_at_utf8(path) {
+        Ok(path) => return Some(path.to_owned()),
+        Err(bytes) => String::from_utf8_lossy(bytes).into_owned(),
+    };
+    log!("Warning: path {:?} is not valid UTF-8, failing with EILSEQ", path);
+    set_errno(env, EILSEQ);
+    None
+}
+
 fn open(env: &mut Environment, path: ConstPtr<u8>, flags: i32, _args: DotDotDot) -> FileDescriptor {
     // TODO: parse variadic arguments and pass them on (file creation mode)
     self::open_direct(env, path, flags)
@@ -103,22 +167,6 @@ fn open(env: &mut Environment, path: ConstPtr<u8>, flags: i32, _args: DotDotDot)
 
 /// Special extension for host code: [open] without the [DotDotDot].
 pub fn open_direct(env: &mut Environment, path: ConstPtr<u8>, flags: i32) -> FileDescriptor {
-    let res = open_direct2(env, path, flags);
-    if res == -1 {
-        let buf: MutPtr<u8> = env.mem.alloc(1024).cast();
-        _ = env.mem
-            .bytes_at_mut(buf, 1024)
-            .write(env.bundle.bundle_path().join("").as_str().as_bytes());
-        strcat(env, buf, path);
-        let res = open_direct2(env, buf.cast_const(), flags);
-        env.mem.free(buf.cast());
-        return res;
-    }
-    res
-}
-
-/// Special extension for host code: [open] without the [DotDotDot].
-fn open_direct2(env: &mut Environment, path: ConstPtr<u8>, flags: i32) -> FileDescriptor {
     // TODO: support more flags, this list is not complete
     assert!(
         flags
@@ -129,14 +177,13 @@ fn open_direct2(env: &mut Environment, path: ConstPtr<u8>, flags: i32) -> FileDe
             | O_NOFOLLOW
             | O_CREAT
             | O_TRUNC
-            | O_EXCL)
+            | O_EXCL
+            | O_DIRECTORY)
             == 0
     );
     // TODO: symlinks don't exist in the FS yet, so we can't "not follow" them.
     // (Should we just ignore this?)
     assert!(flags & O_NOFOLLOW == 0);
-    // TODO: exclusive mode not implemented yet
-    assert!(flags & O_EXCL == 0);
 
     // TODO: respect the mode (in the variadic arguments) when creating a file
     // Note: NONBLOCK flag is ignored, assumption is all file I/O is fast
@@ -157,37 +204,54 @@ fn open_direct2(env: &mut Environment, path: ConstPtr<u8>, flags: i32) -> FileDe
         options.truncate();
     }
 
-    let y = env.mem.cstr_at_utf8(path);
-    if y.is_err() {
+    // Opening an existing file with O_CREAT but without writing to it is fine
+    // even if it's read-only.
+    let writes = (flags & O_ACCMODE) != O_RDONLY || (flags & (O_TRUNC | O_APPEND)) != 0;
+    let intent = if writes {
+        PathIntent::Write
+    } else {
+        PathIntent::Read
+    };
+    let resolved = match resolve_path_at(env, path, intent) {
+        None if !writes && (flags & O_CREAT) != 0 => resolve_path_at(env, path, PathIntent::Write),
+        resolved => resolved,
+    };
+    let Some(path_string) = resolved else {
+        log!(
+            "Warning: open({:?}, {:#x}) failed, could not resolve path, returning -1",
+            path,
+            flags
+        );
         return -1;
-    }
-    let path_string = y.unwrap().to_owned();
-    let res = match env
-        .fs
-        .open_with_options(GuestPath::new(&path_string), options)
-    {
-        Ok(file) => {
-            let host_object = PosixFileHostObject {
-                file,
-                reached_eof: false,
-            };
-
-            let idx = if let Some(free_idx) = env
-                .libc_state
-                .posix_io
-                .files
-                .iter()
-                .position(|f| f.is_none())
-            {
-                env.libc_state.posix_io.files[free_idx] = Some(Rc::new(RefCell::new(host_object)));
-                free_idx
-            } else {
-                let idx = env.libc_state.posix_io.files.len();
-                env.libc_state.posix_io.files.push(Some(Rc::new(RefCell::new(host_object))));
-                idx
-            };
-            file_idx_to_fd(idx)
+    };
+    let guest_path: &GuestPath = &path_string;
+    // Checking and creating happen in one call, so this is atomic as far as the
+    // guest is concerned.
+    let exclusive = (flags & (O_CREAT | O_EXCL)) == (O_CREAT | O_EXCL);
+    let open_result = if exclusive && env.fs.exists(guest_path) {
+        set_errno(env, EEXIST);
+        Err(())
+    } else if env.fs.is_dir(guest_path) {
+        // Directories can be opened read-only, e.g. for use with fchdir().
+        if (flags & O_ACCMODE) != O_RDONLY {
+            set_errno(env, EISDIR);
+            Err(())
+        } else {
+            env.fs.open_dir(guest_path)
         }
+    } else if (flags & O_DIRECTORY) != 0 {
+        let errno = if env.fs.exists(guest_path) {
+            ENOTDIR
+        } else {
+            ENOENT
+        };
+        set_errno(env, errno);
+        Err(())
+    } else {
+        env.fs.open_with_options(guest_path, options)
+    };
+    let res = match open_result {
+        Ok(file) => add_file_with_path(env, file, path_string.clone(), flags),
         Err(()) => {
             // TODO: set errno
             -1
@@ -207,26 +271,145 @@ fn open_direct2(env: &mut Environment, path: ConstPtr<u8>, flags: i32) -> FileDe
     res
 }
 
-fn dup(env: &mut Environment, fd: FileDescriptor) -> FileDescriptor {
-    let Some(file) = env.libc_state.posix_io.files[fd_to_file_idx(fd)].as_ref() else {
-        return -1; // TODO: set errno
-    };
+/// Add an open file (or socket) to the descriptor table and return its new
+/// file descriptor, which is the lowest one available. If the descriptor limit
+/// has been reached, this sets errno to `EMFILE` and returns -1.
+pub fn add_file(env: &mut Environment, file: GuestFile) -> FileDescriptor {
+    add_host_object(env, file, None, O_RDWR)
+}
 
-    let idx = if let Some(free_idx) = env
-        .libc_state
-        .posix_io
-        .files
+fn add_file_with_path(
+    env: &mut Environment,
+    file: GuestFile,
+    path: GuestPathBuf,
+    flags: OpenFlag,
+) -> FileDescriptor {
+    add_host_object(env, file, Some(path), flags)
+}
+
+fn add_host_object(
+    env: &mut Environment,
+    file: GuestFile,
+    path: Option<GuestPathBuf>,
+    flags: OpenFlag,
+) -> FileDescriptor {
+    let opened_at = env
+        .options
+        .trace_calls
+        .is_some()
+        .then(|| crate::backtrace::guest_backtrace(env));
+    let host_object = Rc::new(RefCell::new(PosixFileHostObject {
+        file,
+        reached_eof: false,
+        path,
+        flags,
+        opened_at,
+    }));
+    insert_host_object(env, host_object)
+}
+
+/// Put a host object in the lowest free slot of the descriptor table, or set
+/// errno to `EMFILE` and return -1 if that would exceed the limit.
+fn insert_host_object(
+    env: &mut Environment,
+    host_object: Rc<RefCell<PosixFileHostObject>>,
+) -> FileDescriptor {
+    let limit = fd_limit(env);
+    let files = &mut env.libc_state.posix_io.files;
+    let idx = files
         .iter()
         .position(|f| f.is_none())
-    {
-        env.libc_state.posix_io.files[free_idx] = Some(file.clone());
-        free_idx
+        .unwrap_or(files.len());
+    let fd = file_idx_to_fd(idx);
+    if fd as u32 >= limit {
+        log!(
+            "Warning: Reached the limit of {} file descriptors, returning -1 and setting errno to EMFILE",
+            limit
+        );
+        set_errno(env, EMFILE);
+        if env.options.dump_open_files && !env.libc_state.posix_io.dumped_at_limit {
+            env.libc_state.posix_io.dumped_at_limit = true;
+            dump_open_files(env);
+        }
+        return -1;
+    }
+    if idx == files.len() {
+        files.push(Some(host_object));
     } else {
-        let idx = env.libc_state.posix_io.files.len();
-        env.libc_state.posix_io.files.push(Some(file.clone()));
-        idx
+        files[idx] = Some(host_object);
+    }
+    fd
+}
+
+/// Get the maximum number of file descriptors the app can have open, counting
+/// stdin, stdout and stderr. This is the soft `RLIMIT_NOFILE`.
+pub fn fd_limit(env: &Environment) -> u32 {
+    env.libc_state
+        .posix_io
+        .limit
+        .unwrap_or(env.options.fd_limit)
+}
+
+/// Change the limit returned by [fd_limit]. Descriptors that are already open
+/// above the new limit stay open.
+pub fn set_fd_limit(env: &mut Environment, limit: u32) {
+    assert!(limit <= OPEN_MAX);
+    env.libc_state.posix_io.limit = Some(limit);
+}
+
+/// Print the descriptor table: each open descriptor with its path (or what
+/// kind of file it is), open flags, current offset, and the backtrace from
+/// when it was opened, if one was captured.
+pub fn dump_open_files(env: &mut Environment) {
+    let files = &env.libc_state.posix_io.files;
+    let open_count = files.iter().flatten().count();
+    echo!(
+        "Open file descriptors ({} of {}, not counting stdin, stdout and stderr):",
+        open_count,
+        fd_limit(env)
+    );
+    for (idx, file) in files.iter().enumerate() {
+        let Some(file) = file else {
+            continue;
+        };
+        let mut file = file.borrow_mut();
+        let description = match (&file.path, &file.file) {
+            (Some(path), _) => format!("{:?}", path.as_str()),
+            (None, GuestFile::Socket(_)) => "socket".to_string(),
+            (None, GuestFile::Pipe(_)) => "pipe".to_string(),
+            (None, GuestFile::Directory(path)) => format!("{:?}", path.as_str()),
+            (None, _) => "file".to_string(),
+        };
+        let offset = match file.file {
+            GuestFile::Socket(_) | GuestFile::Pipe(_) | GuestFile::Directory(_) => None,
+            _ => file.file.stream_position().ok(),
+        };
+        let offset = offset.map_or_else(|| "-".to_string(), |offset| offset.to_string());
+        echo!(
+            "{:4}: {} (flags {:#x}, offset {})",
+            file_idx_to_fd(idx),
+            description,
+            file.flags,
+            offset
+        );
+        if let Some(ref opened_at) = file.opened_at {
+            for (addr, symbol) in opened_at {
+                match symbol {
+                    Some(symbol) => echo!("        {:#010x} {}", addr, symbol),
+                    None => echo!("        {:#010x}", addr),
+                }
+            }
+        }
+    }
+}
+
+fn dup(env: &mut Environment, fd: FileDescriptor) -> FileDescriptor {
+    let Some(file) = env.libc_state.posix_io.files[fd_to_file_idx(fd)].clone() else {
+        return -1; // TODO: set errno
     };
-    file_idx_to_fd(idx)
+    let res = insert_host_object(env, file);
+    log_dbg!("dup({:?}) => {:?}", fd, res);
+    res
 }
 
 pub fn read(
@@ -268,7 +451,6 @@ pub fn read(
             bytes_read.try_into().unwrap()
         }
         Err(e) => {
-            // TODO: set errno
             log!(
                 "Warning: read({:?}, {:?}, {:#x}) encountered error {:?}, returning -1",
                 fd,
@@ -276,6 +458,8 @@ pub fn read(
                 size,
                 e,
             );
+            drop(file);
+            set_errno(env, socket::errno_for_io_error(&e));
             -1
         }
     }
@@ -374,7 +558,6 @@ pub fn write(
             bytes_written.try_into().unwrap()
         }
         Err(e) => {
-            // TODO: set errno
             log!(
                 "Warning: write({:?}, {:?}, {:#x}) encountered error {:?}, returning -1",
                 fd,
@@ -382,6 +565,8 @@ pub fn write(
                 size,
                 e,
             );
+            drop(file);
+            set_errno(env, socket::errno_for_io_error(&e));
             -1
         }
     }
@@ -454,48 +639,73 @@ pub fn close(env: &mut Environment, fd: FileDescriptor) -> i32 {
     }
 }
 
+/// Close every open file descriptor, as happens when the process exits.
+pub fn close_all(env: &mut Environment) {
+    for idx in 0..env.libc_state.posix_io.files.len() {
+        if env.libc_state.posix_io.files[idx].is_some() {
+            close(env, file_idx_to_fd(idx));
+        }
+    }
+}
+
 pub fn getcwd(env: &mut Environment, buf_ptr: MutPtr<u8>, buf_size: GuestUSize) -> MutPtr<u8> {
     let working_directory = env.fs.working_directory();
     if !env.fs.is_dir(working_directory) {
-        // TODO: set errno to ENOENT
         log!(
-            "Warning: getcwd({:?}, {:#x}) failed, returning NULL",
+            "Warning: getcwd({:?}, {:#x}) failed, working directory no longer exists, returning NULL",
             buf_ptr,
             buf_size
         );
+        set_errno(env, ENOENT);
         return Ptr::null();
     }
 
-    let working_directory = env.fs.working_directory().as_str().as_bytes();
-
-    if buf_ptr.is_null() {
-        // The buffer size argument is presumably ignored in this mode.
-        // This mode is an extension, which might explain the strange API.
-        let res = env.mem.alloc_and_write_cstr(working_directory);
-        log_dbg!("getcwd(NULL, _) => {:?} ({:?})", res, working_directory);
-        return res;
-    }
+    let working_directory = env.fs.working_directory().as_str().as_bytes().to_vec();
 
     // Includes space for null terminator
     let res_size: GuestUSize = u32::try_from(working_directory.len()).unwrap() + 1;
 
+    if buf_ptr.is_null() && buf_size == 0 {
+        // This mode is an extension: the buffer is allocated with the size
+        // needed and must be freed by the caller.
+        let res = env.mem.alloc_and_write_cstr(&working_directory);
+        log_dbg!("getcwd(NULL, 0) => {:?} ({:?})", res, working_directory);
+        return res;
+    }
+
+    if buf_size == 0 {
+        log!(
+            "Warning: getcwd({:?}, {:#x}) failed, buffer size is zero, returning NULL",
+            buf_ptr,
+            buf_size
+        );
+        set_errno(env, EINVAL);
+        return Ptr::null();
+    }
     if buf_size < res_size {
-        // TODO: set errno to EINVAL or ERANGE as appropriate
         log!(
-            "Warning: getcwd({:?}, {:#x}) failed, returning NULL",
+            "Warning: getcwd({:?}, {:#x}) failed, buffer is too small, returning NULL",
             buf_ptr,
             buf_size
         );
+        set_errno(env, ERANGE);
         return Ptr::null();
     }
 
+    // With a NULL buffer and a non-zero size, a buffer of that size is
+    // allocated, which is another extension.
+    let buf_ptr = if buf_ptr.is_null() {
+        env.mem.alloc(buf_size).cast()
+    } else {
+        buf_ptr
+    };
+
     let buf = env.mem.bytes_at_mut(buf_ptr, res_size);
-    buf[..(res_size - 1) as usize].copy_from_slice(working_directory);
+    buf[..(res_size - 1) as usize].copy_from_slice(&working_directory);
     buf[(res_size - 1) as usize] = b'\0';
 
     log_dbg!(
-        "getcwd({:?}, {:#x}) => {:?}, wrote {:?} ({:#x} bytes)",
-        buf_ptr,
+        "getcwd(_, {:#x}) => {:?}, wrote {:?} ({:#x} bytes)",
         buf_size,
         buf_ptr,
         working_directory,
@@ -505,7 +715,10 @@ pub fn getcwd(env: &mut Environment, buf_ptr: MutPtr<u8>, buf_size: GuestUSize)
 }
 
 fn chdir(env: &mut Environment, path_ptr: ConstPtr<u8>) -> i32 {
-    let path = GuestPath::new(env.mem.cstr_at_utf8(path_ptr).unwrap());
+    let Some(path_string) = path_at(env, path_ptr) else {
+        return -1;
+    };
+    let path = GuestPath::new(&path_string);
     match env.fs.change_working_directory(path) {
         Ok(new) => {
             log_dbg!(
@@ -517,12 +730,46 @@ fn chdir(env: &mut Environment, path_ptr: ConstPtr<u8>) -> i32 {
         }
         Err(()) => {
             log!("Warning: chdir({:?}) failed, could not change working directory to {:?}, returning -1", path_ptr, path);
-            // TODO: set errno
+            let errno = if env.fs.exists(path) { ENOTDIR } else { ENOENT };
+            set_errno(env, errno);
+            -1
+        }
+    }
+}
+
+fn fchdir(env: &mut Environment, fd: FileDescriptor) -> i32 {
+    let path = match env.libc_state.posix_io.file_for_fd(fd) {
+        Some(file) => match file.file {
+            GuestFile::Directory(ref path) => Ok(path.clone()),
+            _ => Err(ENOTDIR),
+        },
+        None => Err(EBADF),
+    };
+    let path = match path {
+        Ok(path) => path,
+        Err(errno) => {
+            log!("Warning: fchdir({:?}) failed, returning -1", fd);
+            set_errno(env, errno);
+            return -1;
+        }
+    };
+    match env.fs.change_working_directory(&path) {
+        Ok(new) => {
+            log_dbg!("fchdir({:?}) => 0, new working directory: {:?}", fd, new);
+            0
+        }
+        Err(()) => {
+            // The directory was removed after it was opened.
+            log!(
+                "Warning: fchdir({:?}) failed, {:?} no longer exists, returning -1",
+                fd,
+                path
+            );
+            set_errno(env, ENOENT);
             -1
         }
     }
 }
-// TODO: fchdir(), once open() on a directory is supported.
 
 fn flock(_env: &mut Environment, fd: FileDescriptor, operation: FLockFlag) -> i32 {
     log!("TODO: flock({:?}, {:?})", fd, operation);
@@ -552,6 +799,21 @@ fn fcntl(env: &mut Environment, fd: FileDescriptor, operation: i32, args: DotDot
             log!("TODO: fcntl({:?}, {:?}, {:?})", fd, operation, data);
             0
         }
+        // Only sockets have flags that can be changed after opening.
+        F_GETFL if socket::socket_is_nonblocking(env, fd).is_some() => {
+            let nonblocking = socket::socket_is_nonblocking(env, fd).unwrap();
+            O_RDWR | if nonblocking { O_NONBLOCK } else { 0 }
+        }
+        F_SETFL if socket::socket_is_nonblocking(env, fd).is_some() => {
+            let flags = args.start().next::<i32>(env);
+            match socket::set_socket_nonblocking(env, fd, flags & O_NONBLOCK != 0) {
+                Ok(()) => 0,
+                Err(errno) => {
+                    set_errno(env, errno);
+                    -1
+                }
+            }
+        }
         F_NOCACHE => {
             log!("Ignoring F_NOCACHE for {} fd", fd);
             0
@@ -571,6 +833,7 @@ pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(close(_)),
     export_c_func!(getcwd(_, _)),
     export_c_func!(chdir(_)),
+    export_c_func!(fchdir(_)),
     export_c_func!(flock(_, _)),
     export_c_func!(ftruncate(_, _)),
     export_c_func!(fcntl(_, _, _)),
@@ -0,0 +1,159 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! POSIX `pipe()`.
+//!
+//! Both ends of a pipe live in the guest descriptor table and share a buffer
+//! on the host side. touchHLE only simulates one process and there is no
+//! other thread that could be woken to fill the pipe, so reading from an empty
+//! pipe whose write end is still open fails with `EAGAIN` rather than blocking.
+
+use super::{add_file, close, FileDescriptor};
+use crate::dyld::{export_c_func, FunctionExports};
+use crate::fs::GuestFile;
+use crate::libc::errno::{set_errno, EMFILE};
+use crate::mem::MutPtr;
+use crate::Environment;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind, Read, Write};
+use std::rc::Rc;
+
+#[derive(Debug, Default)]
+struct PipeBuffer {
+    data: VecDeque<u8>,
+    read_end_closed: bool,
+    write_end_closed: bool,
+}
+
+/// One end of a pipe in the descriptor table. See [GuestFile::Pipe].
+#[derive(Debug)]
+pub struct PipeEnd {
+    buffer: Rc<RefCell<PipeBuffer>>,
+    is_write_end: bool,
+}
+
+impl PipeEnd {
+    /// Create a new pipe, returning its read and write ends.
+    pub fn new_pair() -> (PipeEnd, PipeEnd) {
+        let buffer = Rc::new(RefCell::new(PipeBuffer::default()));
+        (
+            PipeEnd {
+                buffer: buffer.clone(),
+                is_write_end: false,
+            },
+            PipeEnd {
+                buffer,
+                is_write_end: true,
+            },
+        )
+    }
+}
+
+impl Drop for PipeEnd {
+    fn drop(&mut self) {
+        let mut buffer = self.buffer.borrow_mut();
+        if self.is_write_end {
+            buffer.write_end_closed = true;
+        } else {
+            buffer.read_end_closed = true;
+        }
+    }
+}
+
+impl Read for PipeEnd {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.is_write_end {
+            return Err(io::Error::other("Bad file descriptor"));
+        }
+        let mut buffer = self.buffer.borrow_mut();
+        if buffer.data.is_empty() && !buffer.write_end_closed && !buf.is_empty() {
+            return Err(ErrorKind::WouldBlock.into());
+        }
+        buffer.data.read(buf)
+    }
+}
+
+impl Write for PipeEnd {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.is_write_end {
+            return Err(io::Error::other("Bad file descriptor"));
+        }
+        let mut buffer = self.buffer.borrow_mut();
+        if buffer.read_end_closed {
+            return Err(ErrorKind::BrokenPipe.into());
+        }
+        buffer.data.extend(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Create a pipe, for host code. Returns the read and write descriptors, or
+/// [None] (with errno set) if there aren't enough descriptors left.
+pub fn new_pipe(env: &mut Environment) -> Option<(FileDescriptor, FileDescriptor)> {
+    let (read_end, write_end) = PipeEnd::new_pair();
+    let read_fd = add_file(env, GuestFile::Pipe(read_end));
+    if read_fd == -1 {
+        return None;
+    }
+    let write_fd = add_file(env, GuestFile::Pipe(write_end));
+    if write_fd == -1 {
+        close(env, read_fd);
+        set_errno(env, EMFILE);
+        return None;
+    }
+    Some((read_fd, write_fd))
+}
+
+fn pipe(env: &mut Environment, fildes: MutPtr<FileDescriptor>) -> i32 {
+    let Some((read_fd, write_fd)) = new_pipe(env) else {
+        log!("Warning: pipe({:?}) failed, returning -1", fildes);
+        return -1;
+    };
+    env.mem.write(fildes, read_fd);
+    env.mem.write(fildes + 1, write_fd);
+    log_dbg!("pipe({:?}) => 0, [{}, {}]", fildes, read_fd, write_fd);
+    0
+}
+
+pub const FUNCTIONS: FunctionExports = &[export_c_func!(pipe(_))];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_and_write() {
+        let (mut read_end, mut write_end) = PipeEnd::new_pair();
+        let mut buf = [0u8; 8];
+        assert_eq!(
+            read_end.read(&mut buf).unwrap_err().kind(),
+            ErrorKind::WouldBlock
+        );
+        assert_eq!(write_end.write(b"hello").unwrap(), 5);
+        assert_eq!(read_end.read(&mut buf[..3]).unwrap(), 3);
+        assert_eq!(&buf[..3], b"hel");
+        assert!(read_end.write(b"x").is_err());
+        assert!(write_end.read(&mut buf).is_err());
+        drop(write_end);
+        assert_eq!(read_end.read(&mut buf).unwrap(), 2);
+        assert_eq!(&buf[..2], b"lo");
+        assert_eq!(read_end.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn write_after_read_end_closed() {
+        let (read_end, mut write_end) = PipeEnd::new_pair();
+        drop(read_end);
+        assert_eq!(
+            write_end.write(b"x").unwrap_err().kind(),
+            ErrorKind::BrokenPipe
+        );
+    }
+}
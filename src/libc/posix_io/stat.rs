@@ -3,30 +3,168 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
-//! POSIX `sys/stat.h`
+//! POSIX `sys/stat.h`, plus `utimes()` and `futimes()` from `sys/time.h` and
+//! `utime()` from `utime.h`, which also change file attributes.
 
-use super::{off_t, FileDescriptor};
+use super::{off_t, resolve_path_at, FileDescriptor, STDERR_FILENO, STDIN_FILENO};
 use crate::dyld::{export_c_func, FunctionExports};
-use crate::fs::GuestPath;
-use crate::mem::{ConstPtr, MutVoidPtr};
+use crate::fs::{GuestFile, GuestMetadata, GuestPathBuf, PathIntent};
+use crate::libc::errno::{set_errno, EBADF, EEXIST, EINVAL, EROFS};
+use crate::libc::time::{current_time, time_t, timespec, timeval};
+use crate::libc::unistd::{MOBILE_GID, MOBILE_UID};
+use crate::mem::{ConstPtr, MutVoidPtr, SafeRead};
 use crate::Environment;
-use std::io::{Seek, SeekFrom};
+use std::time::{Duration, SystemTime};
 
 #[allow(non_camel_case_types)]
 pub type mode_t = u16;
 
+pub const S_IFCHR: mode_t = 0o020000;
+pub const S_IFDIR: mode_t = 0o040000;
+pub const S_IFREG: mode_t = 0o100000;
+
+/// `struct stat`, in the variant with 64-bit inode numbers that is the default
+/// on iPhone OS.
+#[allow(non_camel_case_types)]
+#[repr(C, packed)]
+pub struct stat {
+    st_dev: i32,
+    st_mode: mode_t,
+    st_nlink: u16,
+    st_ino: u64,
+    st_uid: u32,
+    st_gid: u32,
+    st_rdev: i32,
+    st_atimespec: timespec,
+    st_mtimespec: timespec,
+    st_ctimespec: timespec,
+    st_birthtimespec: timespec,
+    st_size: off_t,
+    st_blocks: i64,
+    st_blksize: i32,
+    st_flags: u32,
+    st_gen: u32,
+    st_lspare: i32,
+    st_qspare: [i64; 2],
+}
+unsafe impl SafeRead for stat {}
+
+#[allow(non_camel_case_types)]
+#[repr(C, packed)]
+pub struct utimbuf {
+    actime: time_t,
+    modtime: time_t,
+}
+unsafe impl SafeRead for utimbuf {}
+
+fn system_time_to_timespec(time: Option<SystemTime>) -> timespec {
+    // Times before 1970 or after 2038 are clamped, they can't be represented.
+    let since_epoch = time
+        .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    timespec {
+        tv_sec: since_epoch.as_secs().try_into().unwrap_or(time_t::MAX),
+        tv_nsec: since_epoch.subsec_nanos() as i32,
+    }
+}
+
+fn timestamp_to_system_time(secs: time_t, nanos: u32) -> SystemTime {
+    let time = if secs >= 0 {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs as u64)
+    } else {
+        SystemTime::UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs().into())
+    };
+    time + Duration::from_nanos(nanos.into())
+}
+
+fn write_stat(env: &mut Environment, buf: MutVoidPtr, mode: mode_t, metadata: &GuestMetadata) {
+    let GuestMetadata {
+        size,
+        permissions,
+        accessed,
+        modified,
+        ..
+    } = *metadata;
+    let modified = system_time_to_timespec(modified);
+    env.mem.write(
+        buf.cast(),
+        stat {
+            st_dev: 0,
+            st_mode: mode | permissions,
+            st_nlink: 1,
+            st_ino: 0,
+            st_uid: MOBILE_UID,
+            st_gid: MOBILE_GID,
+            st_rdev: 0,
+            st_atimespec: system_time_to_timespec(accessed),
+            // There's no portable way to get the host's status change or
+            // creation time, so the modification time stands in for both.
+            st_ctimespec: modified,
+            st_birthtimespec: modified,
+            st_mtimespec: modified,
+            st_size: size.try_into().unwrap_or(off_t::MAX),
+            st_blocks: size.div_ceil(512).try_into().unwrap_or(i64::MAX),
+            st_blksize: 4096,
+            st_flags: 0,
+            st_gen: 0,
+            st_lspare: 0,
+            st_qspare: [0; 2],
+        },
+    );
+}
+
+fn file_type(metadata: &GuestMetadata) -> mode_t {
+    if metadata.is_dir {
+        S_IFDIR
+    } else {
+        S_IFREG
+    }
+}
+
+/// The outcome of looking up a file descriptor for the functions here.
+enum FdTarget {
+    File,
+    /// Directories can only be handled by path.
+    Directory(GuestPathBuf),
+    /// stdin, stdout and stderr.
+    Terminal,
+    Invalid,
+}
+
+fn fd_target(env: &mut Environment, fd: FileDescriptor) -> FdTarget {
+    if (STDIN_FILENO..=STDERR_FILENO).contains(&fd) {
+        return FdTarget::Terminal;
+    }
+    if fd < 0 {
+        return FdTarget::Invalid;
+    }
+    match env.libc_state.posix_io.file_for_fd(fd).as_deref() {
+        Some(super::PosixFileHostObject {
+            file: GuestFile::Directory(path),
+            ..
+        }) => FdTarget::Directory(path.clone()),
+        Some(_) => FdTarget::File,
+        None => FdTarget::Invalid,
+    }
+}
+
 fn mkdir(env: &mut Environment, path: ConstPtr<u8>, mode: mode_t) -> i32 {
     // TODO: respect the mode
-    match env
-        .fs
-        .create_dir(GuestPath::new(&env.mem.cstr_at_utf8(path).unwrap()))
-    {
+    let Some(guest_path) = resolve_path_at(env, path, PathIntent::Write) else {
+        log!("Warning: mkdir({:?}, {:#x}) failed, returning -1", path, mode);
+        return -1;
+    };
+    // A missing parent directory was already reported as ENOENT above, which
+    // is what apps doing their own `mkdir -p` rely on.
+    match env.fs.create_dir(guest_path) {
         Ok(()) => {
             log_dbg!("mkdir({:?}, {:#x}) => 0", path, mode);
             0
         }
         Err(()) => {
-            // TODO: set errno
+            // The parent directory exists and is writeable, so the only way
+            // this can fail is if something is already there.
+            set_errno(env, EEXIST);
             log!(
                 "Warning: mkdir({:?}, {:#x}) failed, returning -1",
                 path,
@@ -37,46 +175,182 @@ fn mkdir(env: &mut Environment, path: ConstPtr<u8>, mode: mode_t) -> i32 {
     }
 }
 
+pub fn stat(env: &mut Environment, path: ConstPtr<u8>, buf: MutVoidPtr) -> i32 {
+    let Some(guest_path) = resolve_path_at(env, path, PathIntent::Read) else {
+        log_dbg!("stat({:?}) => -1, path does not exist", path);
+        return -1;
+    };
+    let Ok(metadata) = env.fs.metadata(&guest_path) else {
+        log!(
+            "Warning: stat({:?}) failed to get metadata, returning -1",
+            path
+        );
+        set_errno(env, EINVAL);
+        return -1;
+    };
+    log_dbg!("stat({:?}) => 0, {:?}", path, metadata);
+    write_stat(env, buf, file_type(&metadata), &metadata);
+    0
+}
 
-fn stat(env: &mut Environment, path: ConstPtr<u8>, buf: MutVoidPtr) -> i32 {
-    //log!("stat {}", env.mem.cstr_at_utf8(path).unwrap());
+fn fstat(env: &mut Environment, fd: FileDescriptor, buf: MutVoidPtr) -> i32 {
+    let metadata = match fd_target(env, fd) {
+        FdTarget::File => {
+            let mut file = env.libc_state.posix_io.file_for_fd(fd).unwrap();
+            file.file.metadata().map_err(|_| ())
+        }
+        FdTarget::Directory(path) => env.fs.metadata(path),
+        FdTarget::Terminal => {
+            let metadata = GuestMetadata {
+                is_dir: false,
+                size: 0,
+                permissions: 0o620,
+                accessed: None,
+                modified: None,
+            };
+            write_stat(env, buf, S_IFCHR, &metadata);
+            return 0;
+        }
+        FdTarget::Invalid => {
+            set_errno(env, EBADF);
+            return -1;
+        }
+    };
+    let Ok(metadata) = metadata else {
+        log!(
+            "Warning: fstat({:?}) is unsupported for this kind of file, returning -1",
+            fd
+        );
+        set_errno(env, EINVAL);
+        return -1;
+    };
+    log_dbg!("fstat({:?}) => 0, {:?}", fd, metadata);
+    write_stat(env, buf, file_type(&metadata), &metadata);
+    0
+}
 
-    let path_string = env.mem.cstr_at_utf8(path).unwrap().to_owned();
-    let guest_path = GuestPath::new(&path_string);
-    let is_dir = env.fs.is_dir(guest_path);
+fn chmod(env: &mut Environment, path: ConstPtr<u8>, mode: mode_t) -> i32 {
+    let Some(guest_path) = resolve_path_at(env, path, PathIntent::Read) else {
+        log_dbg!("chmod({:?}, {:#o}) => -1, path does not exist", path, mode);
+        return -1;
+    };
+    match env.fs.set_permissions(guest_path, mode) {
+        Ok(()) => {
+            log_dbg!("chmod({:?}, {:#o}) => 0", path, mode);
+            0
+        }
+        Err(()) => {
+            set_errno(env, EROFS);
+            -1
+        }
+    }
+}
 
-    let st_mode_ptr = (buf + 0x4).cast::<mode_t>();
-    let mode: mode_t = if is_dir {
-        0x4000
-    } else {
-        0x8000
+fn fchmod(env: &mut Environment, fd: FileDescriptor, mode: mode_t) -> i32 {
+    let res = match fd_target(env, fd) {
+        FdTarget::File => {
+            let file = env.libc_state.posix_io.file_for_fd(fd).unwrap();
+            file.file.set_permissions(mode).map_err(|_| ())
+        }
+        FdTarget::Directory(path) => env.fs.set_permissions(path, mode),
+        FdTarget::Terminal | FdTarget::Invalid => {
+            set_errno(env, EBADF);
+            return -1;
+        }
     };
-    env.mem.write(st_mode_ptr, mode.try_into().unwrap());
-    0
+    log_dbg!("fchmod({:?}, {:#o}) => {:?}", fd, mode, res);
+    match res {
+        Ok(()) => 0,
+        Err(()) => {
+            set_errno(env, EROFS);
+            -1
+        }
+    }
 }
 
-fn fstat(env: &mut Environment, fd: FileDescriptor, buf: MutVoidPtr) -> i32 {
-    // TODO: error handling for unknown fd?
-    let mut file = env.libc_state.posix_io.file_for_fd(fd).unwrap();
+/// Read the `times` argument of `utimes()` or `futimes()`.
+fn read_timevals(env: &mut Environment, times: ConstPtr<timeval>) -> (SystemTime, SystemTime) {
+    if times.is_null() {
+        let now = current_time(env);
+        return (now, now);
+    }
+    let [accessed, modified] = [env.mem.read(times), env.mem.read(times + 1)].map(|time| {
+        let timeval { tv_sec, tv_usec } = time;
+        timestamp_to_system_time(tv_sec, (tv_usec as u32).min(999_999) * 1000)
+    });
+    (accessed, modified)
+}
 
-    log!("Warning: fstat() call, this function is mostly unimplemented");
-    // FIXME: This implementation is highly incomplete. fstat() returns a huge
-    // struct with many kinds of data in it. This code is assuming the caller
-    // only wants the file size.
-    let st_size_ptr = (buf + 0x3c).cast::<off_t>();
+fn set_times_at(
+    env: &mut Environment,
+    path: ConstPtr<u8>,
+    (accessed, modified): (SystemTime, SystemTime),
+) -> i32 {
+    let Some(guest_path) = resolve_path_at(env, path, PathIntent::Read) else {
+        return -1;
+    };
+    match env.fs.set_times(guest_path, accessed, modified) {
+        Ok(()) => 0,
+        Err(()) => {
+            set_errno(env, EROFS);
+            -1
+        }
+    }
+}
 
-    // TODO: Use the stream_len() method if that ever gets stabilized.
-    let old_pos = file.file.stream_position().unwrap();
-    let full_size = file.file.seek(SeekFrom::End(0)).unwrap();
-    file.file.seek(SeekFrom::Start(old_pos)).unwrap();
+fn utimes(env: &mut Environment, path: ConstPtr<u8>, times: ConstPtr<timeval>) -> i32 {
+    let times = read_timevals(env, times);
+    let res = set_times_at(env, path, times);
+    log_dbg!("utimes({:?}, {:?}) => {}", path, times, res);
+    res
+}
 
-    env.mem.write(st_size_ptr, full_size.try_into().unwrap());
+fn utime(env: &mut Environment, path: ConstPtr<u8>, times: ConstPtr<utimbuf>) -> i32 {
+    let times = if times.is_null() {
+        let now = current_time(env);
+        (now, now)
+    } else {
+        let utimbuf { actime, modtime } = env.mem.read(times);
+        (
+            timestamp_to_system_time(actime, 0),
+            timestamp_to_system_time(modtime, 0),
+        )
+    };
+    let res = set_times_at(env, path, times);
+    log_dbg!("utime({:?}, {:?}) => {}", path, times, res);
+    res
+}
 
-    0 // success
+fn futimes(env: &mut Environment, fd: FileDescriptor, times: ConstPtr<timeval>) -> i32 {
+    let (accessed, modified) = read_timevals(env, times);
+    let res = match fd_target(env, fd) {
+        FdTarget::File => {
+            let file = env.libc_state.posix_io.file_for_fd(fd).unwrap();
+            file.file.set_times(accessed, modified).map_err(|_| ())
+        }
+        FdTarget::Directory(path) => env.fs.set_times(path, accessed, modified),
+        FdTarget::Terminal | FdTarget::Invalid => {
+            set_errno(env, EBADF);
+            return -1;
+        }
+    };
+    log_dbg!("futimes({:?}, {:?}) => {:?}", fd, (accessed, modified), res);
+    match res {
+        Ok(()) => 0,
+        Err(()) => {
+            set_errno(env, EROFS);
+            -1
+        }
+    }
 }
 
 pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(mkdir(_, _)),
     export_c_func!(stat(_, _)),
-    export_c_func!(fstat(_, _))
+    export_c_func!(fstat(_, _)),
+    export_c_func!(chmod(_, _)),
+    export_c_func!(fchmod(_, _)),
+    export_c_func!(utimes(_, _)),
+    export_c_func!(utime(_, _)),
+    export_c_func!(futimes(_, _)),
 ];
@@ -0,0 +1,120 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Process creation: `fork()` and `execve()` from `unistd.h`, `posix_spawn()`
+//! from `spawn.h` and `system()` from `stdlib.h`.
+//!
+//! touchHLE only simulates a single process, and apps on a stock iPhone OS
+//! device aren't allowed to create new ones anyway. Apps mostly call these as
+//! part of a jailbreak check, so they all fail the way the sandbox would make
+//! them fail, which is the "not jailbroken" answer.
+
+use crate::dyld::{export_c_func, FunctionExports};
+use crate::fs::GuestPath;
+use crate::libc::errno::{set_errno, EACCES, EAGAIN, EINVAL, ENOENT, EPERM};
+use crate::libc::posix_io::path_at;
+use crate::libc::unistd::pid_t;
+use crate::mem::{ConstPtr, ConstVoidPtr, MutPtr};
+use crate::Environment;
+
+/// Read a null-terminated `argv`-style array, for logging.
+fn read_string_array(env: &Environment, mut array: ConstPtr<ConstPtr<u8>>) -> Vec<String> {
+    let mut strings = Vec::new();
+    if array.is_null() {
+        return strings;
+    }
+    loop {
+        let string = env.mem.read(array);
+        if string.is_null() {
+            return strings;
+        }
+        strings.push(String::from_utf8_lossy(env.mem.cstr_at(string)).into_owned());
+        array += 1;
+    }
+}
+
+fn fork(env: &mut Environment) -> pid_t {
+    log!("App tried to fork(), failing with EAGAIN");
+    set_errno(env, EAGAIN);
+    -1
+}
+
+fn vfork(env: &mut Environment) -> pid_t {
+    log!("App tried to vfork(), failing with EAGAIN");
+    set_errno(env, EAGAIN);
+    -1
+}
+
+fn execve(
+    env: &mut Environment,
+    path: ConstPtr<u8>,
+    argv: ConstPtr<ConstPtr<u8>>,
+    _envp: ConstPtr<ConstPtr<u8>>,
+) -> i32 {
+    let Some(path_string) = path_at(env, path) else {
+        return -1;
+    };
+    let args = read_string_array(env, argv);
+    // A missing program is the more likely answer for the paths jailbreak
+    // checks try, but anything that does exist still can't be executed.
+    let errno = if env.fs.exists(GuestPath::new(&path_string)) {
+        EACCES
+    } else {
+        ENOENT
+    };
+    log!(
+        "App tried to execve({:?}, {:?}), failing with errno {}",
+        path_string,
+        args,
+        errno
+    );
+    set_errno(env, errno);
+    -1
+}
+
+fn posix_spawn(
+    env: &mut Environment,
+    pid: MutPtr<pid_t>,
+    path: ConstPtr<u8>,
+    _file_actions: ConstVoidPtr,
+    _attrp: ConstVoidPtr,
+    argv: ConstPtr<ConstPtr<u8>>,
+    _envp: ConstPtr<ConstPtr<u8>>,
+) -> i32 {
+    if path.is_null() {
+        log!("App tried to posix_spawn() with a NULL path, returning EINVAL");
+        return EINVAL;
+    }
+    let path_string = String::from_utf8_lossy(env.mem.cstr_at(path)).into_owned();
+    let args = read_string_array(env, argv);
+    log!(
+        "App tried to posix_spawn({:?}, {:?}, {:?}), returning EPERM",
+        pid,
+        path_string,
+        args
+    );
+    // posix_spawn() returns the error number rather than setting errno.
+    EPERM
+}
+
+fn system(env: &mut Environment, command: ConstPtr<u8>) -> i32 {
+    if command.is_null() {
+        // This asks whether a command processor is available.
+        log_dbg!("system(NULL) => 0");
+        return 0;
+    }
+    let command = String::from_utf8_lossy(env.mem.cstr_at(command)).into_owned();
+    log!("App tried to system({:?}), failing with EAGAIN", command);
+    set_errno(env, EAGAIN);
+    -1
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(fork()),
+    export_c_func!(vfork()),
+    export_c_func!(execve(_, _, _)),
+    export_c_func!(posix_spawn(_, _, _, _, _, _)),
+    export_c_func!(system(_)),
+];
@@ -31,16 +31,21 @@ pub struct pthread_attr_t {
     /// Magic number (must be [MAGIC_ATTR])
     magic: u32,
     detachstate: i32,
-    _unused: [u32; 8],
+    stacksize: GuestUSize,
+    _unused: [u32; 7],
 }
 unsafe impl SafeRead for pthread_attr_t {}
 
 const DEFAULT_ATTR: pthread_attr_t = pthread_attr_t {
     magic: MAGIC_ATTR,
     detachstate: PTHREAD_CREATE_JOINABLE,
-    _unused: [0; 8],
+    stacksize: Mem::SECONDARY_THREAD_STACK_SIZE,
+    _unused: [0; 7],
 };
 
+/// Smallest stack size `pthread_attr_setstacksize` accepts, as on iPhone OS.
+const PTHREAD_STACK_MIN: GuestUSize = 0x2000;
+
 /// Apple's implementation is a 4-byte magic number followed by a massive
 /// (>4KiB) opaque region. We will store the actual data on the host instead.
 #[repr(C, packed)]
@@ -100,6 +105,7 @@ fn pthread_attr_destroy(env: &mut Environment, attr: MutPtr<pthread_attr_t>) ->
         pthread_attr_t {
             magic: 0,
             detachstate: 0,
+            stacksize: 0,
             _unused: Default::default(),
         },
     );
@@ -108,26 +114,27 @@ fn pthread_attr_destroy(env: &mut Environment, attr: MutPtr<pthread_attr_t>) ->
 
 pub fn pthread_attr_getstacksize(
     env: &mut Environment,
-    attr: MutPtr<pthread_attr_t>,
+    attr: ConstPtr<pthread_attr_t>,
     stacksize: MutPtr<GuestUSize>,
 ) -> i32 {
     check_magic!(env, attr, MAGIC_ATTR);
-    let stack_size = env.mem
-        .secondary_thread_stack_size_override
-        .or_else(|| Some(Mem::SECONDARY_THREAD_STACK_SIZE))
-        .unwrap();
-    env.mem.write(stacksize, stack_size);
+    let attr_val = env.mem.read(attr);
+    env.mem.write(stacksize, attr_val.stacksize);
     0 // success
 }
 fn pthread_attr_setstacksize(
     env: &mut Environment,
     attr: MutPtr<pthread_attr_t>,
-    stacksize: MutPtr<GuestUSize>,
+    stacksize: GuestUSize,
 ) -> i32 {
     check_magic!(env, attr, MAGIC_ATTR);
-    let val = env.mem.read(stacksize);
-    log!("pthread_attr_setstacksize: {}", val);
-    env.mem.secondary_thread_stack_size_override = Some(val);
+    // Apple's implementation also requires a whole number of pages.
+    if stacksize < PTHREAD_STACK_MIN || stacksize % 0x1000 != 0 {
+        return EINVAL;
+    }
+    let mut attr_copy = env.mem.read(attr);
+    attr_copy.stacksize = stacksize;
+    env.mem.write(attr, attr_copy);
     0 // success
 }
 
@@ -145,7 +152,7 @@ pub fn pthread_create(
         DEFAULT_ATTR
     };
 
-    let thread_id = env.new_thread(start_routine, user_data);
+    let thread_id = env.new_thread(start_routine, user_data, attr.stacksize);
 
     let opaque = env.mem.alloc_and_write(OpaqueThread {
         magic: MAGIC_THREAD,
@@ -289,10 +296,9 @@ fn pthread_get_stackaddr_np(env: &mut Environment, thread: pthread_t) -> MutVoid
 }
 
 fn pthread_get_stacksize_np(env: &mut Environment, thread: pthread_t) -> GuestUSize {
-    env.mem
-        .secondary_thread_stack_size_override
-        .or_else(|| Some(Mem::SECONDARY_THREAD_STACK_SIZE))
-        .unwrap()
+    let thread_id = State::get(env).threads.get(&thread).unwrap().thread_id;
+    let stack = env.threads[thread_id].stack.clone().unwrap();
+    stack.end() - stack.start() + 1
 }
 
 fn pthread_detach(env: &mut Environment, thread: pthread_t) -> i32 {
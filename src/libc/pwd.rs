@@ -0,0 +1,94 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `pwd.h`
+//!
+//! iPhone OS apps run as the "mobile" user. The only user database entry is
+//! for that user, and its home directory is the app's sandbox home directory.
+
+use crate::dyld::{export_c_func, FunctionExports};
+use crate::libc::time::time_t;
+use crate::libc::unistd::{gid_t, uid_t, MOBILE_GID, MOBILE_UID};
+use crate::mem::{ConstPtr, MutPtr, Ptr, SafeRead};
+use crate::Environment;
+
+pub const MOBILE_USER_NAME: &[u8] = b"mobile";
+pub const MOBILE_USER_FULL_NAME: &[u8] = b"Mobile User";
+
+#[allow(non_camel_case_types)]
+#[repr(C, packed)]
+pub struct passwd {
+    pw_name: MutPtr<u8>,
+    pw_passwd: MutPtr<u8>,
+    pw_uid: uid_t,
+    pw_gid: gid_t,
+    pw_change: time_t,
+    pw_class: MutPtr<u8>,
+    pw_gecos: MutPtr<u8>,
+    pw_dir: MutPtr<u8>,
+    pw_shell: MutPtr<u8>,
+    pw_expire: time_t,
+}
+unsafe impl SafeRead for passwd {}
+
+#[derive(Default)]
+pub struct State {
+    /// The "mobile" user's record. This is allocated on first use and never
+    /// freed, so pointers into it stay valid across calls.
+    mobile_passwd: Option<MutPtr<passwd>>,
+}
+
+/// Get the "mobile" user's record, allocating it if needed.
+pub fn mobile_passwd(env: &mut Environment) -> MutPtr<passwd> {
+    if let Some(existing) = env.libc_state.pwd.mobile_passwd {
+        return existing;
+    }
+    let home = env.fs.home_directory().as_str().as_bytes().to_vec();
+    let record = passwd {
+        pw_name: env.mem.alloc_and_write_cstr(MOBILE_USER_NAME),
+        pw_passwd: env.mem.alloc_and_write_cstr(b"*"),
+        pw_uid: MOBILE_UID,
+        pw_gid: MOBILE_GID,
+        pw_change: 0,
+        pw_class: env.mem.alloc_and_write_cstr(b""),
+        pw_gecos: env.mem.alloc_and_write_cstr(MOBILE_USER_FULL_NAME),
+        pw_dir: env.mem.alloc_and_write_cstr(&home),
+        pw_shell: env.mem.alloc_and_write_cstr(b"/bin/sh"),
+        pw_expire: 0,
+    };
+    let record = env.mem.alloc_and_write(record);
+    env.libc_state.pwd.mobile_passwd = Some(record);
+    record
+}
+
+/// Get the name of the "mobile" user as a guest string with stable storage.
+pub fn mobile_user_name(env: &mut Environment) -> MutPtr<u8> {
+    let record = mobile_passwd(env);
+    env.mem.read(record).pw_name
+}
+
+fn getpwuid(env: &mut Environment, uid: uid_t) -> MutPtr<passwd> {
+    if uid != MOBILE_UID {
+        log!(
+            "Warning: getpwuid({}) for unknown user, returning NULL",
+            uid
+        );
+        return Ptr::null();
+    }
+    mobile_passwd(env)
+}
+
+fn getpwnam(env: &mut Environment, name: ConstPtr<u8>) -> MutPtr<passwd> {
+    if env.mem.cstr_at(name) != MOBILE_USER_NAME {
+        log!(
+            "Warning: getpwnam({:?}) for unknown user, returning NULL",
+            env.mem.cstr_at_utf8(name)
+        );
+        return Ptr::null();
+    }
+    mobile_passwd(env)
+}
+
+pub const FUNCTIONS: FunctionExports = &[export_c_func!(getpwuid(_)), export_c_func!(getpwnam(_))];
@@ -0,0 +1,694 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `regex.h`
+//!
+//! POSIX regular expressions are translated to the syntax of the
+//! [regex_lite] crate and compiled on the host. The compiled pattern is kept
+//! in [State], keyed by the guest's `regex_t` pointer, so the guest-side
+//! struct only holds the fields apps are allowed to look at.
+//!
+//! Apple's implementation works on bytes in the C locale, so patterns and
+//! strings are decoded as Latin-1: every byte becomes one character, and
+//! offsets can be mapped back to bytes by counting characters. Case folding
+//! is ASCII-only, which also matches the C locale.
+//!
+//! Known differences from Apple's implementation:
+//! - When alternatives could match different lengths, the first alternative
+//!   that matches is used rather than the longest (e.g. `a|ab` on `ab`).
+//! - Backreferences (`\1` etc) are not supported.
+
+use crate::dyld::{export_c_func, FunctionExports};
+use crate::mem::{ConstPtr, GuestUSize, MutPtr, MutVoidPtr, Ptr, SafeRead};
+use crate::Environment;
+use regex_lite::Regex;
+use std::collections::HashMap;
+
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone)]
+#[repr(C, packed)]
+pub struct regex_t {
+    re_magic: i32,
+    re_nsub: GuestUSize,
+    re_endp: ConstPtr<u8>,
+    /// Always NULL, the compiled pattern lives in [State] instead.
+    re_g: MutVoidPtr,
+}
+unsafe impl SafeRead for regex_t {}
+
+#[allow(non_camel_case_types)]
+type regoff_t = i64;
+
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone)]
+#[repr(C, packed)]
+pub struct regmatch_t {
+    rm_so: regoff_t,
+    rm_eo: regoff_t,
+}
+unsafe impl SafeRead for regmatch_t {}
+
+/// Value of `re_magic` for a compiled pattern (same as Apple's).
+const MAGIC: i32 = 0xf265;
+
+// regcomp() flags
+const REG_EXTENDED: i32 = 0o1;
+const REG_ICASE: i32 = 0o2;
+const REG_NOSUB: i32 = 0o4;
+const REG_NEWLINE: i32 = 0o10;
+const REG_NOSPEC: i32 = 0o20;
+const REG_PEND: i32 = 0o40;
+
+// regexec() flags
+const REG_NOTBOL: i32 = 0o1;
+const REG_NOTEOL: i32 = 0o2;
+const REG_STARTEND: i32 = 0o4;
+
+// Error codes
+const REG_NOMATCH: i32 = 1;
+const REG_BADPAT: i32 = 2;
+const REG_ECOLLATE: i32 = 3;
+const REG_ECTYPE: i32 = 4;
+const REG_EESCAPE: i32 = 5;
+const REG_ESUBREG: i32 = 6;
+const REG_EBRACK: i32 = 7;
+const REG_EPAREN: i32 = 8;
+const REG_EBRACE: i32 = 9;
+const REG_BADBR: i32 = 10;
+const REG_ERANGE: i32 = 11;
+const REG_ESPACE: i32 = 12;
+const REG_BADRPT: i32 = 13;
+const REG_EMPTY: i32 = 14;
+const REG_ASSERT: i32 = 15;
+const REG_INVARG: i32 = 16;
+
+// regerror() flags
+const REG_ATOI: i32 = 255;
+const REG_ITOA: i32 = 0o400;
+
+/// Error codes, their names and the messages Apple's `regerror()` gives.
+const ERRORS: &[(i32, &str, &str)] = &[
+    (REG_NOMATCH, "REG_NOMATCH", "regexec() failed to match"),
+    (REG_BADPAT, "REG_BADPAT", "invalid regular expression"),
+    (REG_ECOLLATE, "REG_ECOLLATE", "invalid collating element"),
+    (REG_ECTYPE, "REG_ECTYPE", "invalid character class"),
+    (REG_EESCAPE, "REG_EESCAPE", "trailing backslash (\\)"),
+    (REG_ESUBREG, "REG_ESUBREG", "invalid backreference number"),
+    (REG_EBRACK, "REG_EBRACK", "brackets ([ ]) not balanced"),
+    (REG_EPAREN, "REG_EPAREN", "parentheses not balanced"),
+    (REG_EBRACE, "REG_EBRACE", "braces not balanced"),
+    (REG_BADBR, "REG_BADBR", "invalid repetition count(s)"),
+    (REG_ERANGE, "REG_ERANGE", "invalid character range"),
+    (REG_ESPACE, "REG_ESPACE", "out of memory"),
+    (
+        REG_BADRPT,
+        "REG_BADRPT",
+        "repetition-operator operand invalid",
+    ),
+    (REG_EMPTY, "REG_EMPTY", "empty (sub)expression"),
+    (
+        REG_ASSERT,
+        "REG_ASSERT",
+        "\"can't happen\" -- you found a bug",
+    ),
+    (
+        REG_INVARG,
+        "REG_INVARG",
+        "invalid argument to regex routine",
+    ),
+];
+
+/// Largest count allowed in a bound (`RE_DUP_MAX`).
+const DUP_MAX: u32 = 255;
+
+/// Character classes that can be used as `[:name:]` in a bracket expression.
+/// These are all supported by [regex_lite] too.
+const CLASS_NAMES: &[&str] = &[
+    "alnum", "alpha", "blank", "cntrl", "digit", "graph", "lower", "print", "punct", "space",
+    "upper", "xdigit",
+];
+
+#[derive(Default)]
+pub struct State {
+    compiled: HashMap<MutPtr<regex_t>, Compiled>,
+}
+
+struct Compiled {
+    /// The original pattern, needed to recompile it for `REG_NOTEOL`.
+    pattern: String,
+    cflags: i32,
+    nsub: GuestUSize,
+    regex: Regex,
+}
+
+fn latin1_decode(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| char::from(b)).collect()
+}
+
+fn push_literal(out: &mut String, c: char) {
+    // Characters regex_lite treats specially somewhere in a pattern.
+    if "\\.+*?()|[]{}^$#&-~".contains(c) {
+        out.push('\\');
+    }
+    out.push(c);
+}
+
+/// Translate a bracket expression, starting after the `[`. Returns the index
+/// after the closing `]`.
+fn translate_bracket(
+    chars: &[char],
+    mut i: usize,
+    newline: bool,
+    out: &mut String,
+) -> Result<usize, i32> {
+    out.push('[');
+    if chars.get(i) == Some(&'^') {
+        out.push('^');
+        // A non-matching list never matches a newline with REG_NEWLINE.
+        if newline {
+            out.push_str("\\n");
+        }
+        i += 1;
+    }
+    let mut first = true;
+    loop {
+        let c = *chars.get(i).ok_or(REG_EBRACK)?;
+        if c == ']' && !first {
+            out.push(']');
+            return Ok(i + 1);
+        }
+        first = false;
+
+        // A single element: a character, a collating symbol or an equivalence
+        // class. The latter two can only be single characters here.
+        let lo = match (c, chars.get(i + 1)) {
+            ('[', Some(&delim @ (':' | '.' | '='))) => {
+                let start = i + 2;
+                let len = chars[start..]
+                    .windows(2)
+                    .position(|w| w == [delim, ']'])
+                    .ok_or(REG_EBRACK)?;
+                let name: String = chars[start..start + len].iter().collect();
+                i = start + len + 2;
+                if delim == ':' {
+                    if !CLASS_NAMES.contains(&name.as_str()) {
+                        return Err(REG_ECTYPE);
+                    }
+                    out.push_str("[:");
+                    out.push_str(&name);
+                    out.push_str(":]");
+                    continue;
+                }
+                let mut name_chars = name.chars();
+                match (name_chars.next(), name_chars.next()) {
+                    (Some(c), None) => c,
+                    _ => return Err(REG_ECOLLATE),
+                }
+            }
+            _ => {
+                i += 1;
+                c
+            }
+        };
+
+        // A range, unless the `-` is the last thing in the list.
+        if chars.get(i) == Some(&'-') && chars.get(i + 1).is_some_and(|&c| c != ']') {
+            let hi = chars[i + 1];
+            i += 2;
+            if hi < lo {
+                return Err(REG_ERANGE);
+            }
+            push_literal(out, lo);
+            out.push('-');
+            push_literal(out, hi);
+        } else {
+            push_literal(out, lo);
+        }
+    }
+}
+
+/// Translate a bound, starting after the `{` (ERE) or `\{` (BRE). Returns the
+/// index after the closing `}` or `\}`.
+fn translate_bound(
+    chars: &[char],
+    mut i: usize,
+    extended: bool,
+    out: &mut String,
+) -> Result<usize, i32> {
+    let number = |i: &mut usize| -> Result<Option<u32>, i32> {
+        let start = *i;
+        while chars.get(*i).is_some_and(|c| c.is_ascii_digit()) {
+            *i += 1;
+        }
+        if *i == start {
+            return Ok(None);
+        }
+        let n: String = chars[start..*i].iter().collect();
+        match n.parse() {
+            Ok(n) if n <= DUP_MAX => Ok(Some(n)),
+            _ => Err(REG_BADBR),
+        }
+    };
+    let min = number(&mut i)?.ok_or(REG_BADBR)?;
+    let max = if chars.get(i) == Some(&',') {
+        i += 1;
+        number(&mut i)?
+    } else {
+        Some(min)
+    };
+    let closed = if extended {
+        chars.get(i) == Some(&'}')
+    } else {
+        chars.get(i) == Some(&'\\') && chars.get(i + 1) == Some(&'}')
+    };
+    if !closed {
+        return Err(if chars.get(i).is_none() {
+            REG_EBRACE
+        } else {
+            REG_BADBR
+        });
+    }
+    match max {
+        Some(max) if max < min => return Err(REG_BADBR),
+        Some(max) if max == min => out.push_str(&format!("{{{}}}", min)),
+        Some(max) => out.push_str(&format!("{{{},{}}}", min, max)),
+        None => out.push_str(&format!("{{{},}}", min)),
+    }
+    Ok(if extended { i + 1 } else { i + 2 })
+}
+
+/// Translate a POSIX basic (BRE) or extended (ERE) regular expression to
+/// [regex_lite] syntax. Returns the translation and the number of
+/// subexpressions, or a `REG_*` error code.
+fn translate(pattern: &str, cflags: i32, not_eol: bool) -> Result<(String, GuestUSize), i32> {
+    let extended = cflags & REG_EXTENDED != 0;
+    let newline = cflags & REG_NEWLINE != 0;
+    let chars: Vec<char> = pattern.chars().collect();
+
+    let mut out = String::new();
+    // Without REG_NEWLINE, a newline is an ordinary character. With it, `.`
+    // doesn't match a newline and `^` and `$` match at line boundaries.
+    out.push_str(if newline { "(?m)" } else { "(?s)" });
+    if cflags & REG_ICASE != 0 {
+        out.push_str("(?i)");
+    }
+    // `$` must never match at the end of the string with REG_NOTEOL.
+    let eol = if not_eol { "\\b\\B" } else { "$" };
+
+    let mut nsub = 0;
+    let mut depth = 0;
+    // Whether the preceding item can have a repetition operator applied.
+    let mut can_repeat = false;
+    // Whether this is the start of the pattern or a subexpression (for BREs).
+    let mut at_start = true;
+    let mut i = 0;
+    while let Some(&c) = chars.get(i) {
+        i += 1;
+        let was_at_start = std::mem::replace(&mut at_start, false);
+        let escaped = if c == '\\' {
+            let &e = chars.get(i).ok_or(REG_EESCAPE)?;
+            i += 1;
+            if e.is_ascii_digit() && e != '0' {
+                log!("TODO: backreference in regex {:?}", pattern);
+                return Err(REG_ESUBREG);
+            }
+            Some(e)
+        } else {
+            None
+        };
+
+        match (escaped, c) {
+            (None, '[') => {
+                i = translate_bracket(&chars, i, newline, &mut out)?;
+                can_repeat = true;
+            }
+            (None, '.') => {
+                out.push('.');
+                can_repeat = true;
+            }
+            (None, '^') if extended || was_at_start => {
+                out.push('^');
+                can_repeat = false;
+            }
+            (None, '$')
+                if extended
+                    || i == chars.len()
+                    || (chars.get(i) == Some(&'\\') && chars.get(i + 1) == Some(&')')) =>
+            {
+                out.push_str(eol);
+                can_repeat = false;
+            }
+            (Some('('), _) if !extended => {
+                nsub += 1;
+                depth += 1;
+                out.push('(');
+                can_repeat = false;
+                at_start = true;
+            }
+            (None, '(') if extended => {
+                nsub += 1;
+                depth += 1;
+                out.push('(');
+                can_repeat = false;
+            }
+            (Some(')'), _) if !extended => {
+                if depth == 0 {
+                    return Err(REG_EPAREN);
+                }
+                depth -= 1;
+                out.push(')');
+                can_repeat = true;
+            }
+            (None, ')') if extended => {
+                if depth == 0 {
+                    return Err(REG_EPAREN);
+                }
+                depth -= 1;
+                out.push(')');
+                can_repeat = true;
+            }
+            (None, '|') if extended => {
+                out.push('|');
+                can_repeat = false;
+            }
+            (None, '*') if can_repeat || extended => {
+                if !can_repeat {
+                    return Err(REG_BADRPT);
+                }
+                out.push('*');
+                // A second operator would mean something else to regex_lite,
+                // e.g. `*?` is a lazy repetition.
+                can_repeat = false;
+            }
+            (None, '+' | '?') if extended => {
+                if !can_repeat {
+                    return Err(REG_BADRPT);
+                }
+                out.push(c);
+                can_repeat = false;
+            }
+            (None, '{') if extended && chars.get(i).is_some_and(|c| c.is_ascii_digit()) => {
+                if !can_repeat {
+                    return Err(REG_BADRPT);
+                }
+                i = translate_bound(&chars, i, true, &mut out)?;
+                can_repeat = false;
+            }
+            (Some('{'), _) if !extended => {
+                if !can_repeat {
+                    return Err(REG_BADRPT);
+                }
+                i = translate_bound(&chars, i, false, &mut out)?;
+                can_repeat = false;
+            }
+            (Some(e), _) => {
+                push_literal(&mut out, e);
+                can_repeat = true;
+            }
+            (None, c) => {
+                push_literal(&mut out, c);
+                can_repeat = true;
+            }
+        }
+    }
+    if depth != 0 {
+        return Err(REG_EPAREN);
+    }
+    Ok((out, nsub))
+}
+
+impl Compiled {
+    fn new(pattern: String, cflags: i32) -> Result<Compiled, i32> {
+        let (translated, nsub) = translate(&pattern, cflags, false)?;
+        let regex = Regex::new(&translated).map_err(|e| {
+            log!(
+                "Couldn't compile regex {:?} (translated to {:?}): {}",
+                pattern,
+                translated,
+                e
+            );
+            REG_BADPAT
+        })?;
+        Ok(Compiled {
+            pattern,
+            cflags,
+            nsub,
+            regex,
+        })
+    }
+
+    /// Match against a string. On success, returns the byte offsets of the
+    /// match and of each subexpression that participated in it.
+    fn exec(&self, string: &[u8], eflags: i32) -> Option<Vec<Option<(usize, usize)>>> {
+        let not_eol_regex;
+        let regex = if eflags & REG_NOTEOL != 0 {
+            if self.cflags & REG_NEWLINE != 0 {
+                log!("TODO: REG_NOTEOL with REG_NEWLINE, `$` won't match at line ends");
+            }
+            let (translated, _) = translate(&self.pattern, self.cflags, true).unwrap();
+            not_eol_regex = Regex::new(&translated).unwrap();
+            &not_eol_regex
+        } else {
+            &self.regex
+        };
+
+        // With REG_NOTBOL, a dummy character is put in front of the string so
+        // `^` can't match at the start. It must not be a newline or a word
+        // character, so that it doesn't affect `^` with REG_NEWLINE or `\b`.
+        let prefix = if eflags & REG_NOTBOL != 0 { "\0" } else { "" };
+        let haystack = format!("{}{}", prefix, latin1_decode(string));
+        let captures = regex.captures_at(&haystack, prefix.len())?;
+
+        let to_byte_offset = |pos: usize| haystack[prefix.len()..pos].chars().count();
+        Some(
+            captures
+                .iter()
+                .map(|m| m.map(|m| (to_byte_offset(m.start()), to_byte_offset(m.end()))))
+                .collect(),
+        )
+    }
+}
+
+fn regcomp(
+    env: &mut Environment,
+    preg: MutPtr<regex_t>,
+    pattern: ConstPtr<u8>,
+    cflags: i32,
+) -> i32 {
+    if cflags & (REG_NOSPEC | REG_PEND) != 0 {
+        log!(
+            "TODO: regcomp() flags {:#x}",
+            cflags & (REG_NOSPEC | REG_PEND)
+        );
+        return REG_INVARG;
+    }
+    let pattern_str = latin1_decode(env.mem.cstr_at(pattern));
+    let compiled = match Compiled::new(pattern_str.clone(), cflags) {
+        Ok(compiled) => compiled,
+        Err(err) => {
+            log_dbg!("regcomp({:?}, {:#x}) => error {}", pattern_str, cflags, err);
+            return err;
+        }
+    };
+    log_dbg!(
+        "regcomp({:?}, {:#x}) => {:?}, {} subexpression(s)",
+        pattern_str,
+        cflags,
+        compiled.regex.as_str(),
+        compiled.nsub
+    );
+    env.mem.write(
+        preg,
+        regex_t {
+            re_magic: MAGIC,
+            re_nsub: compiled.nsub,
+            re_endp: Ptr::null(),
+            re_g: Ptr::null(),
+        },
+    );
+    env.libc_state.regex.compiled.insert(preg, compiled);
+    0 // success
+}
+
+fn regexec(
+    env: &mut Environment,
+    preg: ConstPtr<regex_t>,
+    string: ConstPtr<u8>,
+    nmatch: GuestUSize,
+    pmatch: MutPtr<regmatch_t>,
+    eflags: i32,
+) -> i32 {
+    let Some(compiled) = env.libc_state.regex.compiled.get(&preg.cast_mut()) else {
+        log!("regexec() with {:?} which was not compiled", preg);
+        return REG_BADPAT;
+    };
+
+    let (start, bytes) = if eflags & REG_STARTEND != 0 {
+        let range = env.mem.read(pmatch);
+        let start: GuestUSize = range.rm_so.try_into().unwrap();
+        let end: GuestUSize = range.rm_eo.try_into().unwrap();
+        (start, env.mem.bytes_at(string + start, end - start))
+    } else {
+        (0, env.mem.cstr_at(string))
+    };
+    let Some(matches) = compiled.exec(bytes, eflags) else {
+        return REG_NOMATCH;
+    };
+    if compiled.cflags & REG_NOSUB != 0 {
+        return 0; // success
+    }
+
+    for i in 0..nmatch {
+        let m = matches
+            .get(usize::try_from(i).unwrap())
+            .copied()
+            .flatten()
+            .map_or(
+                regmatch_t {
+                    rm_so: -1,
+                    rm_eo: -1,
+                },
+                |(so, eo)| regmatch_t {
+                    rm_so: regoff_t::from(start) + regoff_t::try_from(so).unwrap(),
+                    rm_eo: regoff_t::from(start) + regoff_t::try_from(eo).unwrap(),
+                },
+            );
+        env.mem.write(pmatch + i, m);
+    }
+    0 // success
+}
+
+fn regerror(
+    env: &mut Environment,
+    errcode: i32,
+    preg: ConstPtr<regex_t>,
+    errbuf: MutPtr<u8>,
+    errbuf_size: GuestUSize,
+) -> GuestUSize {
+    let message = if errcode == REG_ATOI {
+        // Look up the code for the name in re_endp.
+        let name = env.mem.cstr_at(env.mem.read(preg).re_endp);
+        let code = ERRORS
+            .iter()
+            .find(|&&(_, n, _)| n.as_bytes() == name)
+            .map_or(0, |&(code, _, _)| code);
+        code.to_string()
+    } else {
+        let entry = ERRORS
+            .iter()
+            .find(|&&(code, _, _)| code == errcode & !REG_ITOA);
+        match (entry, errcode & REG_ITOA != 0) {
+            (Some(&(_, name, _)), true) => name.to_string(),
+            (Some(&(_, _, message)), false) => message.to_string(),
+            (None, true) => format!("REG_0x{:x}", errcode & !REG_ITOA),
+            (None, false) => "*** unknown regexp error code ***".to_string(),
+        }
+    };
+
+    let len: GuestUSize = message.len().try_into().unwrap();
+    if errbuf_size > 0 {
+        let copy_len = len.min(errbuf_size - 1);
+        env.mem
+            .bytes_at_mut(errbuf, copy_len)
+            .copy_from_slice(&message.as_bytes()[..copy_len as usize]);
+        env.mem.write(errbuf + copy_len, b'\0');
+    }
+    len + 1
+}
+
+fn regfree(env: &mut Environment, preg: MutPtr<regex_t>) {
+    if env.libc_state.regex.compiled.remove(&preg).is_none() {
+        log!("regfree() with {:?} which was not compiled", preg);
+        return;
+    }
+    let mut regex = env.mem.read(preg);
+    regex.re_magic = 0;
+    env.mem.write(preg, regex);
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(regcomp(_, _, _)),
+    export_c_func!(regexec(_, _, _, _, _)),
+    export_c_func!(regerror(_, _, _, _)),
+    export_c_func!(regfree(_)),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exec(
+        pattern: &str,
+        cflags: i32,
+        string: &str,
+        eflags: i32,
+    ) -> Option<Vec<Option<(usize, usize)>>> {
+        Compiled::new(pattern.to_string(), cflags)
+            .unwrap()
+            .exec(string.as_bytes(), eflags)
+    }
+
+    #[test]
+    fn test_translate() {
+        let ere = |p| translate(p, REG_EXTENDED, false).map(|(t, n)| (t[4..].to_string(), n));
+        let bre = |p| translate(p, 0, false).map(|(t, n)| (t[4..].to_string(), n));
+
+        assert_eq!(ere("^(a|b)+c{2,3}$"), Ok(("^(a|b)+c{2,3}$".into(), 1)));
+        assert_eq!(
+            bre("^(a|b)+c{2,3}$"),
+            Ok(("^\\(a\\|b\\)\\+c\\{2,3\\}$".into(), 0))
+        );
+        assert_eq!(bre("\\(ab\\)*x\\{2\\}"), Ok(("(ab)*x{2}".into(), 1)));
+        assert_eq!(ere("\\(ab\\)"), Ok(("\\(ab\\)".into(), 0)));
+        // Special characters that are only special in some places
+        assert_eq!(bre("*a^$b$"), Ok(("\\*a\\^\\$b$".into(), 0)));
+        assert_eq!(bre("\\(^*a\\)"), Ok(("(^\\*a)".into(), 1)));
+        assert_eq!(ere("a{b"), Ok(("a\\{b".into(), 0)));
+        // Bracket expressions
+        assert_eq!(
+            ere("[]a-c[:digit:]\\-]"),
+            Ok(("[\\]a-c[:digit:]\\\\\\-]".into(), 0))
+        );
+        assert_eq!(ere("[^[.-.]x]"), Ok(("[^\\-x]".into(), 0)));
+
+        assert_eq!(ere("(a"), Err(REG_EPAREN));
+        assert_eq!(bre("a\\)"), Err(REG_EPAREN));
+        assert_eq!(ere("[a"), Err(REG_EBRACK));
+        assert_eq!(ere("[[:foo:]]"), Err(REG_ECTYPE));
+        assert_eq!(ere("[z-a]"), Err(REG_ERANGE));
+        assert_eq!(ere("a{3,2}"), Err(REG_BADBR));
+        assert_eq!(bre("a\\{1"), Err(REG_EBRACE));
+        assert_eq!(ere("*a"), Err(REG_BADRPT));
+        assert_eq!(ere("a\\"), Err(REG_EESCAPE));
+        assert_eq!(bre("\\(a\\)\\1"), Err(REG_ESUBREG));
+    }
+
+    #[test]
+    fn test_exec() {
+        assert_eq!(
+            exec("([a-z]+)-([0-9]+)", REG_EXTENDED, "LEVEL: forest-12!", 0),
+            Some(vec![Some((7, 16)), Some((7, 13)), Some((14, 16))])
+        );
+        assert_eq!(
+            exec("(a)|(b)", REG_EXTENDED, "xb", 0),
+            Some(vec![Some((1, 2)), None, Some((1, 2))])
+        );
+        assert_eq!(
+            exec("^level", REG_EXTENDED | REG_ICASE, "LEVEL", 0),
+            Some(vec![Some((0, 5))])
+        );
+        assert_eq!(exec("^level", REG_EXTENDED, "level", REG_NOTBOL), None);
+        assert_eq!(
+            exec("^b", REG_EXTENDED | REG_NEWLINE, "a\nb", REG_NOTBOL),
+            Some(vec![Some((2, 3))])
+        );
+        assert_eq!(exec("a$", REG_EXTENDED, "a", REG_NOTEOL), None);
+        // Offsets are in bytes even for non-ASCII strings.
+        assert_eq!(
+            exec("b.", REG_EXTENDED, "\u{e9}b\u{e9}", 0),
+            Some(vec![Some((2, 4))])
+        );
+    }
+}
@@ -0,0 +1,161 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `sys/resource.h`
+
+use crate::dyld::{export_c_func, FunctionExports};
+use crate::libc::errno::{set_errno, EINVAL};
+use crate::libc::posix_io::{self, OPEN_MAX};
+use crate::libc::time::{suseconds_t, time_t, timeval};
+use crate::mem::{ConstPtr, MutPtr, SafeRead};
+use crate::Environment;
+use std::time::Instant;
+
+const RUSAGE_SELF: i32 = 0;
+const RUSAGE_CHILDREN: i32 = -1;
+
+#[allow(non_camel_case_types)]
+#[repr(C, packed)]
+struct rusage {
+    ru_utime: timeval,
+    ru_stime: timeval,
+    /// Maximum resident set size, in bytes (not kilobytes, unlike Linux).
+    ru_maxrss: i32,
+    ru_ixrss: i32,
+    ru_idrss: i32,
+    ru_isrss: i32,
+    ru_minflt: i32,
+    ru_majflt: i32,
+    ru_nswap: i32,
+    ru_inblock: i32,
+    ru_oublock: i32,
+    ru_msgsnd: i32,
+    ru_msgrcv: i32,
+    ru_nsignals: i32,
+    ru_nvcsw: i32,
+    ru_nivcsw: i32,
+}
+unsafe impl SafeRead for rusage {}
+
+fn getrusage(env: &mut Environment, who: i32, r_usage: MutPtr<rusage>) -> i32 {
+    let (user_time, maxrss) = match who {
+        RUSAGE_SELF => {
+            // There's no portable way to get the host's CPU time for the
+            // emulator thread, so the time since startup has to do. It is at
+            // least monotonic.
+            let elapsed = Instant::now().duration_since(env.startup_time);
+            let maxrss = env.mem.peak_used_bytes().min(i32::MAX as u64) as i32;
+            (elapsed, maxrss)
+        }
+        // touchHLE only simulates a single process.
+        RUSAGE_CHILDREN => (Default::default(), 0),
+        _ => {
+            set_errno(env, EINVAL);
+            return -1;
+        }
+    };
+    env.mem.write(
+        r_usage,
+        rusage {
+            ru_utime: timeval {
+                tv_sec: user_time.as_secs() as time_t,
+                tv_usec: user_time.subsec_micros() as suseconds_t,
+            },
+            ru_stime: timeval {
+                tv_sec: 0,
+                tv_usec: 0,
+            },
+            ru_maxrss: maxrss,
+            ru_ixrss: 0,
+            ru_idrss: 0,
+            ru_isrss: 0,
+            ru_minflt: 0,
+            ru_majflt: 0,
+            ru_nswap: 0,
+            ru_inblock: 0,
+            ru_oublock: 0,
+            ru_msgsnd: 0,
+            ru_msgrcv: 0,
+            ru_nsignals: 0,
+            ru_nvcsw: 0,
+            ru_nivcsw: 0,
+        },
+    );
+    0 // success
+}
+
+#[allow(non_camel_case_types)]
+type rlim_t = u64;
+
+const RLIM_INFINITY: rlim_t = (1 << 63) - 1;
+
+const RLIMIT_NOFILE: i32 = 8;
+
+#[allow(non_camel_case_types)]
+#[repr(C, packed)]
+struct rlimit {
+    rlim_cur: rlim_t,
+    rlim_max: rlim_t,
+}
+unsafe impl SafeRead for rlimit {}
+
+fn getrlimit(env: &mut Environment, resource: i32, rlp: MutPtr<rlimit>) -> i32 {
+    let rlim_cur = match resource {
+        RLIMIT_NOFILE => posix_io::fd_limit(env).into(),
+        _ => {
+            log!(
+                "TODO: getrlimit({}, {:?}), reporting no limit",
+                resource,
+                rlp
+            );
+            RLIM_INFINITY
+        }
+    };
+    env.mem.write(
+        rlp,
+        rlimit {
+            rlim_cur,
+            rlim_max: RLIM_INFINITY,
+        },
+    );
+    0 // success
+}
+
+fn setrlimit(env: &mut Environment, resource: i32, rlp: ConstPtr<rlimit>) -> i32 {
+    let rlimit { rlim_cur, rlim_max } = env.mem.read(rlp);
+    if rlim_cur > rlim_max {
+        set_errno(env, EINVAL);
+        return -1;
+    }
+    match resource {
+        RLIMIT_NOFILE => {
+            // Like on Darwin, the limit can't be raised beyond OPEN_MAX.
+            let Some(limit) = u32::try_from(rlim_cur)
+                .ok()
+                .filter(|&limit| limit <= OPEN_MAX)
+            else {
+                set_errno(env, EINVAL);
+                return -1;
+            };
+            log_dbg!("setrlimit(RLIMIT_NOFILE, {})", limit);
+            posix_io::set_fd_limit(env, limit);
+        }
+        _ => {
+            log!(
+                "TODO: setrlimit({}, {}, {}), ignoring",
+                resource,
+                rlim_cur,
+                rlim_max
+            );
+        }
+    }
+    0 // success
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(getrusage(_, _)),
+    export_c_func!(getrlimit(_, _)),
+    export_c_func!(setrlimit(_, _)),
+];
@@ -19,7 +19,8 @@ const SEM_FAILED: MutPtr<sem_t> = MutPtr::from_bits(u32::MAX);
 
 #[derive(Default)]
 pub struct State {
-    named_semaphores: HashMap<String, Rc<RefCell<SemaphoreHostObject>>>,
+    /// Names are kept as bytes since they needn't be valid UTF-8.
+    named_semaphores: HashMap<Vec<u8>, Rc<RefCell<SemaphoreHostObject>>>,
     pub open_semaphores: HashMap<MutPtr<sem_t>, Rc<RefCell<SemaphoreHostObject>>>,
 }
 impl State {
@@ -47,10 +48,9 @@ fn sem_open(
     _mode: mode_t,
     value: u32,
 ) -> MutPtr<sem_t> {
-    let sem_name = env.mem.cstr_at_utf8(name).unwrap();
-    let sem_name_str = sem_name.to_string();
+    let sem_name = env.mem.cstr_at(name).to_vec();
     let host_sem_rc =
-        if let Some(existing_host_sem_rc) = State::get(env).named_semaphores.get(sem_name) {
+        if let Some(existing_host_sem_rc) = State::get(env).named_semaphores.get(&sem_name) {
             if (oflag & O_EXCL) != 0 {
                 // TODO: set errno
                 return SEM_FAILED;
@@ -72,7 +72,7 @@ fn sem_open(
             }));
             State::get_mut(env)
                 .named_semaphores
-                .insert(sem_name_str, Rc::clone(&host_sem_rc));
+                .insert(sem_name, Rc::clone(&host_sem_rc));
             host_sem_rc
         };
 
@@ -115,7 +115,7 @@ fn sem_close(env: &mut Environment, sem: MutPtr<sem_t>) -> i32 {
 }
 
 fn sem_unlink(env: &mut Environment, name: ConstPtr<u8>) -> i32 {
-    let sem_name = env.mem.cstr_at_utf8(name).unwrap();
+    let sem_name = env.mem.cstr_at(name);
     env.libc_state.semaphore.named_semaphores.remove(sem_name);
     0 // success
 }
@@ -8,16 +8,17 @@
 //! We don't have a real implementation for this right now. It could be quite
 //! tricky to write one, considering that we would need to unwind through host
 //! code, and somehow do so selectively since we have a mix of stack frames from
-//! different guest threads. For the moment, we simply pray the app never throws
-//! exceptions.
+//! different guest threads. Host code that needs to jump back to a `setjmp()`,
+//! like `objc_exception_throw`, can use [crate::Environment::schedule_longjmp]
+//! to do so once it has returned to the guest.
 //!
 //! Note that `setjmp` and `longjmp` are defined as macros in the C standard,
 //! but it seems like the implementation of these on iPhone OS uses real
 //! functions, at least for the former.
 
 use crate::abi::GuestFunction;
-use crate::dyld::{export_c_func, FunctionExports};
-use crate::mem::{MutPtr, SafeRead};
+use crate::dyld::{export_c_func, export_c_func2, FunctionExports};
+use crate::mem::{MutPtr, MutVoidPtr, SafeRead};
 use crate::{abi, Environment};
 
 #[repr(C, packed)]
@@ -69,6 +70,13 @@ fn longjmp(env: &mut Environment, jmp_buf: MutPtr<JmpBuf>, status: u32) {
             other_stack
         );
     }
+    restore_jmp_buf(env, jmp_buf.cast(), status);
+}
+
+/// Restore the guest state saved by `setjmp()`, so that it returns again with
+/// `status` once the guest resumes.
+pub fn restore_jmp_buf(env: &mut Environment, jmp_buf: MutVoidPtr, status: u32) {
+    let buf = env.mem.read(jmp_buf.cast::<JmpBuf>());
     env.cpu.regs_mut()[0] = status;
     env.cpu.regs_mut()[4] = buf.r4;
     env.cpu.regs_mut()[5] = buf.r5;
@@ -83,4 +91,11 @@ fn longjmp(env: &mut Environment, jmp_buf: MutPtr<JmpBuf>, status: u32) {
         .branch(GuestFunction::from_addr_with_thumb_bit(buf.lr));
 }
 
-pub const FUNCTIONS: FunctionExports = &[export_c_func!(setjmp(_)), export_c_func!(longjmp(_, _))];
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(setjmp(_)),
+    export_c_func!(longjmp(_, _)),
+    // These don't save and restore the signal mask, which touchHLE doesn't
+    // have anyway. `@try` uses them.
+    export_c_func2!("__setjmp", setjmp(_)),
+    export_c_func2!("__longjmp", longjmp(_, _)),
+];
@@ -0,0 +1,378 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `signal.h`
+//!
+//! touchHLE never delivers asynchronous signals, so this is mostly bookkeeping
+//! of dispositions. Signals are only delivered synchronously, either by
+//! `raise()`, by `abort()` (see [handle_abort]) or when the emulator detects a
+//! memory access violation (see [handle_memory_error]).
+
+use crate::abi::{CallFromHost, GuestFunction};
+use crate::dyld::{export_c_func, FunctionExports};
+use crate::libc::errno::{set_errno, EINVAL};
+use crate::mem::{ConstPtr, ConstVoidPtr, MutPtr, MutVoidPtr, Ptr, SafeRead};
+use crate::Environment;
+use std::collections::HashMap;
+
+#[allow(non_camel_case_types)]
+pub type sigset_t = u32;
+
+pub const SIGABRT: i32 = 6;
+pub const SIGKILL: i32 = 9;
+pub const SIGBUS: i32 = 10;
+pub const SIGSEGV: i32 = 11;
+pub const SIGURG: i32 = 16;
+pub const SIGSTOP: i32 = 17;
+pub const SIGCONT: i32 = 19;
+pub const SIGCHLD: i32 = 20;
+pub const SIGWINCH: i32 = 28;
+pub const SIGINFO: i32 = 29;
+const NSIG: i32 = 32;
+
+/// Special handler values. These aren't real function pointers.
+const SIG_DFL: u32 = 0;
+const SIG_IGN: u32 = 1;
+const SIG_ERR: u32 = u32::MAX;
+
+const SA_RESETHAND: i32 = 0x4;
+const SA_NODEFER: i32 = 0x10;
+const SA_SIGINFO: i32 = 0x40;
+
+const SIG_BLOCK: i32 = 1;
+const SIG_UNBLOCK: i32 = 2;
+const SIG_SETMASK: i32 = 3;
+
+/// `si_code` for a signal sent by `raise()`/`kill()`.
+const SI_USER: i32 = 0x10001;
+/// `si_code` for `SIGSEGV`: address not mapped to object.
+const SEGV_MAPERR: i32 = 1;
+
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone)]
+#[repr(C, packed)]
+pub struct sigaction {
+    /// Either `void (*)(int)`, or `void (*)(int, siginfo_t *, void *)` if
+    /// `SA_SIGINFO` is set, or one of the special values `SIG_DFL`/`SIG_IGN`.
+    sa_handler: GuestFunction,
+    sa_mask: sigset_t,
+    sa_flags: i32,
+}
+unsafe impl SafeRead for sigaction {}
+
+impl Default for sigaction {
+    fn default() -> Self {
+        sigaction {
+            sa_handler: GuestFunction::from_addr_with_thumb_bit(SIG_DFL),
+            sa_mask: 0,
+            sa_flags: 0,
+        }
+    }
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone, Default)]
+#[repr(C, packed)]
+struct siginfo_t {
+    si_signo: i32,
+    si_errno: i32,
+    si_code: i32,
+    si_pid: i32,
+    si_uid: u32,
+    si_status: i32,
+    si_addr: MutVoidPtr,
+    si_value: u32,
+    si_band: i32,
+    __pad: [u32; 7],
+}
+unsafe impl SafeRead for siginfo_t {}
+
+#[derive(Default)]
+pub struct State {
+    /// Signals without an entry have the default disposition.
+    actions: HashMap<i32, sigaction>,
+    /// touchHLE doesn't bother with per-thread signal masks.
+    blocked: sigset_t,
+    pending: sigset_t,
+    /// The app's fault handler is only given one chance, so that a handler
+    /// that faults itself doesn't loop forever.
+    fault_handler_invoked: bool,
+    /// Likewise for `SIGABRT`, in case the handler itself calls `abort()`.
+    abort_handler_invoked: bool,
+}
+
+fn sig_bit(sig: i32) -> sigset_t {
+    1 << (sig - 1)
+}
+
+fn is_valid_signal(sig: i32) -> bool {
+    (1..NSIG).contains(&sig)
+}
+
+/// Signals whose default action is to do nothing, rather than terminate.
+fn is_ignored_by_default(sig: i32) -> bool {
+    matches!(sig, SIGURG | SIGCONT | SIGCHLD | SIGWINCH | SIGINFO)
+}
+
+fn get_action(env: &Environment, sig: i32) -> sigaction {
+    env.libc_state
+        .signal
+        .actions
+        .get(&sig)
+        .copied()
+        .unwrap_or_default()
+}
+
+/// Run the guest's handler for `sig` (or the default action). `si_code` and
+/// `si_addr` are used to build the `siginfo_t` for `SA_SIGINFO` handlers.
+fn deliver_signal(env: &mut Environment, sig: i32, si_code: i32, si_addr: MutVoidPtr) {
+    let action = get_action(env, sig);
+    let handler = action.sa_handler;
+    let flags = action.sa_flags;
+    match handler.addr_with_thumb_bit() {
+        SIG_IGN => {
+            log_dbg!("Signal {} is ignored", sig);
+        }
+        SIG_DFL if is_ignored_by_default(sig) => {
+            log_dbg!("Signal {} is ignored by default", sig);
+        }
+        SIG_DFL => {
+            panic!("App received signal {} with no handler installed", sig);
+        }
+        _ => {
+            log_dbg!("Calling handler {:?} for signal {}", handler, sig);
+            if (flags & SA_RESETHAND) != 0 {
+                env.libc_state.signal.actions.remove(&sig);
+            }
+            let old_blocked = env.libc_state.signal.blocked;
+            let mut blocked = old_blocked | action.sa_mask;
+            if (flags & SA_NODEFER) == 0 {
+                blocked |= sig_bit(sig);
+            }
+            env.libc_state.signal.blocked = blocked;
+            if (flags & SA_SIGINFO) != 0 {
+                let info = env.mem.alloc_and_write(siginfo_t {
+                    si_signo: sig,
+                    si_code,
+                    // Same as getpid()
+                    si_pid: 1,
+                    si_addr,
+                    ..Default::default()
+                });
+                let context: MutVoidPtr = Ptr::null();
+                let _: () = handler.call_from_host(env, (sig, info, context));
+                env.mem.free(info.cast());
+            } else {
+                let _: () = handler.call_from_host(env, (sig,));
+            }
+            env.libc_state.signal.blocked = old_blocked;
+        }
+    }
+}
+
+/// Deliver any pending signals that are no longer blocked.
+fn deliver_pending_signals(env: &mut Environment) {
+    for sig in 1..NSIG {
+        let state = &mut env.libc_state.signal;
+        if (state.pending & !state.blocked & sig_bit(sig)) != 0 {
+            state.pending &= !sig_bit(sig);
+            deliver_signal(env, sig, SI_USER, Ptr::null());
+        }
+    }
+}
+
+/// Called by the emulator when the guest performs a memory access violation
+/// that isn't being handled by a debugger. If the app has installed a handler
+/// for `SIGSEGV` (or failing that, `SIGBUS`), it is called once, so that e.g. a
+/// crash reporter can write its log. The emulator aborts afterwards regardless.
+///
+/// The CPU registers are restored afterwards so that the state of the faulting
+/// thread can still be inspected.
+pub fn handle_memory_error(env: &mut Environment) {
+    if env.libc_state.signal.fault_handler_invoked {
+        return;
+    }
+    let Some(sig) = [SIGSEGV, SIGBUS].into_iter().find(|&sig| {
+        let handler = get_action(env, sig).sa_handler.addr_with_thumb_bit();
+        handler != SIG_DFL && handler != SIG_IGN
+    }) else {
+        return;
+    };
+    env.libc_state.signal.fault_handler_invoked = true;
+
+    echo!(
+        "Memory error on thread {}, calling the app's handler for signal {}.",
+        env.current_thread,
+        sig
+    );
+    let regs = *env.cpu.regs();
+    let cpsr = env.cpu.cpsr();
+    // The faulting address isn't known, so the PC has to do.
+    let pc: MutVoidPtr = Ptr::from_bits(regs[crate::cpu::Cpu::PC]);
+    deliver_signal(env, sig, SEGV_MAPERR, pc);
+    *env.cpu.regs_mut() = regs;
+    env.cpu.set_cpsr(cpsr);
+}
+
+/// Called by `abort()` before it terminates the app. If the app has installed
+/// a handler for `SIGABRT`, it is called once, regardless of the signal mask.
+pub fn handle_abort(env: &mut Environment) {
+    if env.libc_state.signal.abort_handler_invoked {
+        return;
+    }
+    let handler = get_action(env, SIGABRT).sa_handler.addr_with_thumb_bit();
+    if handler == SIG_DFL || handler == SIG_IGN {
+        return;
+    }
+    env.libc_state.signal.abort_handler_invoked = true;
+
+    echo!("Calling the app's handler for SIGABRT.");
+    deliver_signal(env, SIGABRT, SI_USER, Ptr::null());
+}
+
+fn sigemptyset(env: &mut Environment, set: MutPtr<sigset_t>) -> i32 {
+    env.mem.write(set, 0);
+    0 // success
+}
+
+fn sigfillset(env: &mut Environment, set: MutPtr<sigset_t>) -> i32 {
+    env.mem.write(set, !0);
+    0 // success
+}
+
+fn sigaddset(env: &mut Environment, set: MutPtr<sigset_t>, sig: i32) -> i32 {
+    if !is_valid_signal(sig) {
+        set_errno(env, EINVAL);
+        return -1;
+    }
+    let value = env.mem.read(set);
+    env.mem.write(set, value | sig_bit(sig));
+    0 // success
+}
+
+fn sigdelset(env: &mut Environment, set: MutPtr<sigset_t>, sig: i32) -> i32 {
+    if !is_valid_signal(sig) {
+        set_errno(env, EINVAL);
+        return -1;
+    }
+    let value = env.mem.read(set);
+    env.mem.write(set, value & !sig_bit(sig));
+    0 // success
+}
+
+fn sigismember(env: &mut Environment, set: ConstPtr<sigset_t>, sig: i32) -> i32 {
+    if !is_valid_signal(sig) {
+        set_errno(env, EINVAL);
+        return -1;
+    }
+    ((env.mem.read(set) & sig_bit(sig)) != 0).into()
+}
+
+fn sigaction(
+    env: &mut Environment,
+    sig: i32,
+    act: ConstPtr<sigaction>,
+    oact: MutPtr<sigaction>,
+) -> i32 {
+    if !is_valid_signal(sig) || (!act.is_null() && (sig == SIGKILL || sig == SIGSTOP)) {
+        set_errno(env, EINVAL);
+        return -1;
+    }
+    if !oact.is_null() {
+        let old = get_action(env, sig);
+        env.mem.write(oact, old);
+    }
+    if !act.is_null() {
+        let new = env.mem.read(act);
+        let handler = new.sa_handler;
+        log_dbg!("sigaction({}): new handler {:?}", sig, handler);
+        env.libc_state.signal.actions.insert(sig, new);
+    }
+    0 // success
+}
+
+fn signal(env: &mut Environment, sig: i32, func: GuestFunction) -> ConstVoidPtr {
+    if !is_valid_signal(sig) || sig == SIGKILL || sig == SIGSTOP {
+        set_errno(env, EINVAL);
+        return Ptr::from_bits(SIG_ERR);
+    }
+    log_dbg!("signal({}, {:?})", sig, func);
+    let old = get_action(env, sig).sa_handler;
+    env.libc_state.signal.actions.insert(
+        sig,
+        sigaction {
+            sa_handler: func,
+            ..Default::default()
+        },
+    );
+    old.to_ptr()
+}
+
+fn sigprocmask(
+    env: &mut Environment,
+    how: i32,
+    set: ConstPtr<sigset_t>,
+    oset: MutPtr<sigset_t>,
+) -> i32 {
+    let old = env.libc_state.signal.blocked;
+    if !set.is_null() {
+        let set = env.mem.read(set);
+        let new = match how {
+            SIG_BLOCK => old | set,
+            SIG_UNBLOCK => old & !set,
+            SIG_SETMASK => set,
+            _ => {
+                set_errno(env, EINVAL);
+                return -1;
+            }
+        };
+        // SIGKILL and SIGSTOP can't be blocked.
+        env.libc_state.signal.blocked = new & !(sig_bit(SIGKILL) | sig_bit(SIGSTOP));
+    }
+    if !oset.is_null() {
+        env.mem.write(oset, old);
+    }
+    deliver_pending_signals(env);
+    0 // success
+}
+
+fn pthread_sigmask(
+    env: &mut Environment,
+    how: i32,
+    set: ConstPtr<sigset_t>,
+    oset: MutPtr<sigset_t>,
+) -> i32 {
+    match sigprocmask(env, how, set, oset) {
+        0 => 0,
+        _ => EINVAL,
+    }
+}
+
+fn raise(env: &mut Environment, sig: i32) -> i32 {
+    if !is_valid_signal(sig) {
+        set_errno(env, EINVAL);
+        return -1;
+    }
+    log_dbg!("raise({})", sig);
+    if (env.libc_state.signal.blocked & sig_bit(sig)) != 0 {
+        env.libc_state.signal.pending |= sig_bit(sig);
+    } else {
+        deliver_signal(env, sig, SI_USER, Ptr::null());
+    }
+    0 // success
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(sigemptyset(_)),
+    export_c_func!(sigfillset(_)),
+    export_c_func!(sigaddset(_, _)),
+    export_c_func!(sigdelset(_, _)),
+    export_c_func!(sigismember(_, _)),
+    export_c_func!(sigaction(_, _, _)),
+    export_c_func!(signal(_, _)),
+    export_c_func!(sigprocmask(_, _, _)),
+    export_c_func!(pthread_sigmask(_, _, _)),
+    export_c_func!(raise(_)),
+];
@@ -0,0 +1,955 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `sys/socket.h` and the parts of `netinet/in.h` it needs.
+//!
+//! Sockets are backed by host sockets from [std::net] and live in the same
+//! descriptor table as files (see [super::posix_io]), so `read()`, `write()`,
+//! `close()` and `fcntl()` work on them too. Only IPv4 stream (TCP) sockets
+//! are supported.
+//!
+//! [std::net] creates, binds and connects (or listens) a socket in one step,
+//! so there is no host socket until the guest calls `connect()` or `listen()`.
+//! Connecting is done on a host thread, since [std::net] can only connect
+//! synchronously.
+//!
+//! Blocking calls never block the host: they sleep the current guest thread
+//! until the host socket is ready, so other guest threads keep running. This
+//! module also provides `select()` and `poll()` (`sys/select.h` and `poll.h`).
+
+use super::errno::{
+    set_errno, EACCES, EADDRINUSE, EADDRNOTAVAIL, EAFNOSUPPORT, EAGAIN, EALREADY, EBADF,
+    ECONNREFUSED, ECONNRESET, EINPROGRESS, EINVAL, EIO, EISCONN, EMFILE, ENOENT, ENOTCONN,
+    ENOTSOCK, EPIPE, EPROTONOSUPPORT, ETIMEDOUT,
+};
+use super::posix_io::{add_file, FileDescriptor};
+use super::time::timeval;
+use crate::dyld::{export_c_func, FunctionExports};
+use crate::fs::GuestFile;
+use crate::mem::{ConstPtr, ConstVoidPtr, GuestISize, GuestUSize, MutPtr, MutVoidPtr, SafeRead};
+use crate::Environment;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::time::{Duration, Instant};
+
+#[allow(non_camel_case_types)]
+pub type sa_family_t = u8;
+#[allow(non_camel_case_types)]
+pub type socklen_t = u32;
+#[allow(non_camel_case_types)]
+pub type in_port_t = u16;
+#[allow(non_camel_case_types)]
+pub type in_addr_t = u32;
+
+pub const AF_INET: i32 = 2;
+pub const SOCK_STREAM: i32 = 1;
+pub const IPPROTO_TCP: i32 = 6;
+
+/// How often a blocking call checks whether its socket is ready.
+const BLOCKING_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone)]
+#[repr(C, packed)]
+pub struct sockaddr_in {
+    pub sin_len: u8,
+    pub sin_family: sa_family_t,
+    /// In network byte order.
+    pub sin_port: in_port_t,
+    /// In network byte order.
+    pub sin_addr: in_addr_t,
+    pub sin_zero: u64,
+}
+unsafe impl SafeRead for sockaddr_in {}
+
+impl sockaddr_in {
+    pub fn from_host(addr: SocketAddrV4) -> sockaddr_in {
+        sockaddr_in {
+            sin_len: std::mem::size_of::<sockaddr_in>() as u8,
+            sin_family: AF_INET as sa_family_t,
+            sin_port: addr.port().to_be(),
+            sin_addr: u32::from(*addr.ip()).to_be(),
+            sin_zero: 0,
+        }
+    }
+
+    pub fn to_host(self) -> SocketAddrV4 {
+        SocketAddrV4::new(
+            Ipv4Addr::from(u32::from_be(self.sin_addr)),
+            u16::from_be(self.sin_port),
+        )
+    }
+}
+
+/// Result of [Socket::poll_readable].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReadReadiness {
+    /// Reading would block.
+    NotReady,
+    /// There is data or a connection to accept, or an error to report.
+    Ready,
+    /// The peer has closed the connection, so reading will return 0.
+    AtEnd,
+}
+
+/// A socket in the descriptor table. See [GuestFile::Socket].
+#[derive(Debug)]
+pub struct Socket {
+    state: SocketState,
+    nonblocking: bool,
+}
+
+#[derive(Debug)]
+enum SocketState {
+    /// Created by `socket()`, maybe with an address from `bind()`.
+    Unconnected {
+        bound_to: Option<SocketAddrV4>,
+        /// The `errno` value for the last connection attempt, if it failed.
+        connect_error: Option<i32>,
+    },
+    /// Waiting for the host thread started by [start_connect].
+    Connecting(Receiver<Result<TcpStream, i32>>),
+    Listening {
+        listener: TcpListener,
+        /// A connection accepted early by [Socket::poll_readable], to be
+        /// returned by the next `accept()`.
+        pending: Option<(TcpStream, SocketAddr)>,
+    },
+    Connected(TcpStream),
+}
+
+impl Socket {
+    fn new() -> Socket {
+        Socket {
+            state: SocketState::Unconnected {
+                bound_to: None,
+                connect_error: None,
+            },
+            nonblocking: false,
+        }
+    }
+
+    fn from_stream(stream: TcpStream) -> io::Result<Socket> {
+        stream.set_nonblocking(false)?;
+        Ok(Socket {
+            state: SocketState::Connected(stream),
+            nonblocking: false,
+        })
+    }
+
+    /// Check without blocking whether a read from the socket (or `accept()`,
+    /// for a listening socket) would return immediately.
+    pub fn poll_readable(&mut self) -> ReadReadiness {
+        // A socket that is still connecting isn't readable yet.
+        let _ = self.poll_connect();
+        match self.state {
+            SocketState::Unconnected { .. } | SocketState::Connecting(_) => ReadReadiness::NotReady,
+            SocketState::Listening {
+                ref listener,
+                ref mut pending,
+            } => {
+                if pending.is_none() {
+                    if listener.set_nonblocking(true).is_err() {
+                        return ReadReadiness::Ready;
+                    }
+                    let res = listener.accept();
+                    let _ = listener.set_nonblocking(self.nonblocking);
+                    match res {
+                        Ok(accepted) => *pending = Some(accepted),
+                        Err(e) if e.kind() == ErrorKind::WouldBlock => (),
+                        // Let accept() report the error.
+                        Err(_) => return ReadReadiness::Ready,
+                    }
+                }
+                if pending.is_some() {
+                    ReadReadiness::Ready
+                } else {
+                    ReadReadiness::NotReady
+                }
+            }
+            SocketState::Connected(ref stream) => {
+                if stream.set_nonblocking(true).is_err() {
+                    return ReadReadiness::Ready;
+                }
+                let res = stream.peek(&mut [0u8]);
+                let _ = stream.set_nonblocking(self.nonblocking);
+                match res {
+                    Ok(0) => ReadReadiness::AtEnd,
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => ReadReadiness::NotReady,
+                    // An error counts as ready so that the read can report it.
+                    _ => ReadReadiness::Ready,
+                }
+            }
+        }
+    }
+
+    /// Check without blocking whether a write to the socket would return
+    /// immediately. The host's send buffer can't be inspected with [std::net],
+    /// so a connected socket is always considered writeable. Like on a real
+    /// system, a socket also becomes writeable when a connection attempt
+    /// fails.
+    pub fn poll_writable(&mut self) -> bool {
+        let _ = self.poll_connect();
+        matches!(
+            self.state,
+            SocketState::Connected(_)
+                | SocketState::Unconnected {
+                    connect_error: Some(_),
+                    ..
+                }
+        )
+    }
+
+    /// Check without blocking whether a connection attempt has finished.
+    /// Returns [None] while still connecting, and otherwise the result of the
+    /// last attempt (an `errno` value if it failed). A socket that never tried
+    /// to connect reports [ENOTCONN], and a listening one [EISCONN].
+    pub fn poll_connect(&mut self) -> Option<Result<(), i32>> {
+        match self.state {
+            SocketState::Unconnected { connect_error, .. } => {
+                Some(Err(connect_error.unwrap_or(ENOTCONN)))
+            }
+            SocketState::Listening { .. } => Some(Err(EISCONN)),
+            SocketState::Connected(_) => Some(Ok(())),
+            SocketState::Connecting(ref receiver) => {
+                let res = match receiver.try_recv() {
+                    Err(TryRecvError::Empty) => return None,
+                    Err(TryRecvError::Disconnected) => Err(EIO),
+                    Ok(res) => res.and_then(|stream| {
+                        stream
+                            .set_nonblocking(self.nonblocking)
+                            .map_err(|e| errno_for_io_error(&e))?;
+                        Ok(stream)
+                    }),
+                };
+                match res {
+                    Ok(stream) => {
+                        self.state = SocketState::Connected(stream);
+                        Some(Ok(()))
+                    }
+                    Err(errno) => {
+                        self.state = SocketState::Unconnected {
+                            bound_to: None,
+                            connect_error: Some(errno),
+                        };
+                        Some(Err(errno))
+                    }
+                }
+            }
+        }
+    }
+
+    /// Get the address of the peer of a connected socket.
+    pub fn peer_address(&self) -> Option<SocketAddrV4> {
+        match self.state {
+            SocketState::Connected(ref stream) => match stream.peer_addr() {
+                Ok(SocketAddr::V4(peer)) => Some(peer),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn set_nonblocking(&mut self, nonblocking: bool) -> io::Result<()> {
+        self.nonblocking = nonblocking;
+        match self.state {
+            // A connecting socket's mode is applied once it's connected.
+            SocketState::Unconnected { .. } | SocketState::Connecting(_) => Ok(()),
+            SocketState::Listening { ref listener, .. } => listener.set_nonblocking(nonblocking),
+            SocketState::Connected(ref stream) => stream.set_nonblocking(nonblocking),
+        }
+    }
+}
+
+impl Read for Socket {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.state {
+            SocketState::Connected(ref mut stream) => stream.read(buf),
+            _ => Err(ErrorKind::NotConnected.into()),
+        }
+    }
+}
+
+impl Write for Socket {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.state {
+            SocketState::Connected(ref mut stream) => stream.write(buf),
+            _ => Err(ErrorKind::NotConnected.into()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Map a host I/O error to the closest `errno` value.
+pub fn errno_for_io_error(err: &io::Error) -> i32 {
+    match err.kind() {
+        ErrorKind::NotFound => ENOENT,
+        ErrorKind::PermissionDenied => EACCES,
+        ErrorKind::WouldBlock => EAGAIN,
+        ErrorKind::ConnectionRefused => ECONNREFUSED,
+        ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted => ECONNRESET,
+        ErrorKind::NotConnected => ENOTCONN,
+        ErrorKind::AddrInUse => EADDRINUSE,
+        ErrorKind::AddrNotAvailable => EADDRNOTAVAIL,
+        ErrorKind::BrokenPipe => EPIPE,
+        ErrorKind::TimedOut => ETIMEDOUT,
+        _ => EIO,
+    }
+}
+
+/// Run `f` on the socket for a file descriptor. The error is an `errno` value.
+pub fn with_socket<T>(
+    env: &mut Environment,
+    fd: FileDescriptor,
+    f: impl FnOnce(&mut Socket) -> Result<T, i32>,
+) -> Result<T, i32> {
+    if fd < 0 {
+        return Err(EBADF);
+    }
+    let Some(mut file) = env.libc_state.posix_io.file_for_fd(fd) else {
+        return Err(EBADF);
+    };
+    match file.file {
+        GuestFile::Socket(ref mut socket) => f(socket),
+        _ => Err(ENOTSOCK),
+    }
+}
+
+/// Create an unconnected IPv4 stream socket, for host code. Returns -1 and sets
+/// errno if there are no descriptors left.
+pub fn new_socket(env: &mut Environment) -> FileDescriptor {
+    add_file(env, GuestFile::Socket(Socket::new()))
+}
+
+/// Wait until `ready` returns `true` for a blocking socket. The current guest
+/// thread sleeps in the meantime, so other threads keep running. Returns
+/// immediately for a non-blocking socket, or if `fd` isn't a socket.
+fn wait_for_socket(
+    env: &mut Environment,
+    fd: FileDescriptor,
+    mut ready: impl FnMut(&mut Socket) -> bool,
+) {
+    while let Ok(false) = with_socket(env, fd, |socket| Ok(socket.nonblocking || ready(socket))) {
+        env.sleep(BLOCKING_POLL_INTERVAL, /* tail_call: */ false);
+    }
+}
+
+/// Start connecting a socket to the first of `addrs` that accepts the
+/// connection, on a host thread, for host code. There is no timeout if
+/// `timeout` is [None]. Use [Socket::poll_connect] to get the result.
+pub fn start_connect(
+    env: &mut Environment,
+    fd: FileDescriptor,
+    addrs: Vec<SocketAddr>,
+    timeout: Option<Duration>,
+) -> Result<(), i32> {
+    with_socket(env, fd, |socket| {
+        match socket.state {
+            SocketState::Unconnected { .. } => (),
+            SocketState::Connecting(_) => return Err(EALREADY),
+            SocketState::Listening { .. } | SocketState::Connected(_) => return Err(EISCONN),
+        }
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut last_err = None;
+            for addr in &addrs {
+                let res = match timeout {
+                    Some(timeout) => TcpStream::connect_timeout(addr, timeout),
+                    None => TcpStream::connect(addr),
+                };
+                match res {
+                    Ok(stream) => {
+                        // The socket might have been closed in the meantime.
+                        let _ = sender.send(Ok(stream));
+                        return;
+                    }
+                    Err(e) => last_err = Some(e),
+                }
+            }
+            let errno = last_err.map_or(EADDRNOTAVAIL, |e| errno_for_io_error(&e));
+            let _ = sender.send(Err(errno));
+        });
+        socket.state = SocketState::Connecting(receiver);
+        Ok(())
+    })
+}
+
+/// Connect a socket to the first of `addrs` that accepts the connection, for
+/// host code. There is no timeout if `timeout` is [None]. A non-blocking socket
+/// fails with [EINPROGRESS] and finishes connecting in the background.
+pub fn connect_socket(
+    env: &mut Environment,
+    fd: FileDescriptor,
+    addrs: &[SocketAddr],
+    timeout: Option<Duration>,
+) -> Result<(), i32> {
+    start_connect(env, fd, addrs.to_vec(), timeout)?;
+    wait_for_socket(env, fd, |socket| socket.poll_connect().is_some());
+    with_socket(env, fd, |socket| {
+        socket.poll_connect().unwrap_or(Err(EINPROGRESS))
+    })
+}
+
+/// Accept a connection on a listening socket, for host code. Returns the new
+/// socket's descriptor and the peer's address.
+pub fn accept_socket(
+    env: &mut Environment,
+    fd: FileDescriptor,
+) -> Result<(FileDescriptor, SocketAddrV4), i32> {
+    wait_for_socket(env, fd, |socket| {
+        socket.poll_readable() != ReadReadiness::NotReady
+    });
+    let (stream, addr) = with_socket(env, fd, |socket| match socket.state {
+        SocketState::Listening {
+            ref listener,
+            ref mut pending,
+        } => match pending.take() {
+            Some(accepted) => Ok(accepted),
+            None => listener.accept().map_err(|e| errno_for_io_error(&e)),
+        },
+        _ => Err(EINVAL),
+    })?;
+    let SocketAddr::V4(addr) = addr else {
+        return Err(EAFNOSUPPORT);
+    };
+    let socket = Socket::from_stream(stream).map_err(|e| errno_for_io_error(&e))?;
+    match add_file(env, GuestFile::Socket(socket)) {
+        -1 => Err(EMFILE),
+        new_fd => Ok((new_fd, addr)),
+    }
+}
+
+/// Get whether a socket is in non-blocking mode, or [None] if `fd` is not a
+/// socket.
+pub fn socket_is_nonblocking(env: &mut Environment, fd: FileDescriptor) -> Option<bool> {
+    with_socket(env, fd, |socket| Ok(socket.nonblocking)).ok()
+}
+
+/// Set whether a socket is in non-blocking mode.
+pub fn set_socket_nonblocking(
+    env: &mut Environment,
+    fd: FileDescriptor,
+    nonblocking: bool,
+) -> Result<(), i32> {
+    with_socket(env, fd, |socket| {
+        socket
+            .set_nonblocking(nonblocking)
+            .map_err(|e| errno_for_io_error(&e))
+    })
+}
+
+fn read_sockaddr_in(
+    env: &mut Environment,
+    addr: ConstPtr<sockaddr_in>,
+    addr_len: socklen_t,
+) -> Result<SocketAddrV4, i32> {
+    if addr.is_null() || (addr_len as usize) < std::mem::size_of::<sockaddr_in>() {
+        return Err(EINVAL);
+    }
+    let addr = env.mem.read(addr);
+    if addr.sin_family as i32 != AF_INET {
+        return Err(EAFNOSUPPORT);
+    }
+    Ok(addr.to_host())
+}
+
+/// Write an address for functions like `accept()`, which truncate it to the
+/// buffer size given by the guest.
+fn write_sockaddr_in(
+    env: &mut Environment,
+    addr: SocketAddrV4,
+    addr_ptr: MutPtr<sockaddr_in>,
+    addr_len_ptr: MutPtr<socklen_t>,
+) {
+    if addr_ptr.is_null() || addr_len_ptr.is_null() {
+        return;
+    }
+    let size = std::mem::size_of::<sockaddr_in>() as socklen_t;
+    let len = env.mem.read(addr_len_ptr).min(size);
+    let addr = sockaddr_in::from_host(addr);
+    let addr_bytes: ConstPtr<u8> = env.mem.alloc_and_write(addr).cast_const().cast();
+    env.mem.memmove(addr_ptr.cast(), addr_bytes.cast(), len);
+    env.mem.free(addr_bytes.cast_mut().cast());
+    env.mem.write(addr_len_ptr, size);
+}
+
+fn socket(env: &mut Environment, domain: i32, type_: i32, protocol: i32) -> FileDescriptor {
+    let errno = if domain != AF_INET {
+        EAFNOSUPPORT
+    } else if type_ != SOCK_STREAM || (protocol != 0 && protocol != IPPROTO_TCP) {
+        EPROTONOSUPPORT
+    } else {
+        let fd = new_socket(env);
+        log_dbg!("socket({}, {}, {}) => {:?}", domain, type_, protocol, fd);
+        return fd;
+    };
+    log!(
+        "Warning: socket({}, {}, {}) is unsupported, returning -1",
+        domain,
+        type_,
+        protocol
+    );
+    set_errno(env, errno);
+    -1
+}
+
+pub fn bind(
+    env: &mut Environment,
+    fd: FileDescriptor,
+    addr: ConstPtr<sockaddr_in>,
+    addr_len: socklen_t,
+) -> i32 {
+    let res = read_sockaddr_in(env, addr, addr_len).and_then(|addr| {
+        with_socket(env, fd, |socket| match socket.state {
+            SocketState::Unconnected {
+                ref mut bound_to, ..
+            } if bound_to.is_none() => {
+                *bound_to = Some(addr);
+                Ok(())
+            }
+            _ => Err(EINVAL),
+        })
+    });
+    match res {
+        Ok(()) => 0,
+        Err(errno) => {
+            log!("Warning: bind({:?}, {:?}) failed, returning -1", fd, addr);
+            set_errno(env, errno);
+            -1
+        }
+    }
+}
+
+pub fn listen(env: &mut Environment, fd: FileDescriptor, backlog: i32) -> i32 {
+    // The host's default backlog is used.
+    let res = with_socket(env, fd, |socket| {
+        let bound_to = match socket.state {
+            SocketState::Unconnected { bound_to, .. } => bound_to,
+            SocketState::Listening { .. } => return Ok(()),
+            SocketState::Connecting(_) | SocketState::Connected(_) => return Err(EISCONN),
+        };
+        let bound_to = bound_to.unwrap_or(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0));
+        let listener = TcpListener::bind(bound_to).map_err(|e| errno_for_io_error(&e))?;
+        listener
+            .set_nonblocking(socket.nonblocking)
+            .map_err(|e| errno_for_io_error(&e))?;
+        socket.state = SocketState::Listening {
+            listener,
+            pending: None,
+        };
+        Ok(())
+    });
+    match res {
+        Ok(()) => {
+            log_dbg!("listen({:?}, {}) => 0", fd, backlog);
+            0
+        }
+        Err(errno) => {
+            log!(
+                "Warning: listen({:?}, {}) failed, returning -1",
+                fd,
+                backlog
+            );
+            set_errno(env, errno);
+            -1
+        }
+    }
+}
+
+fn accept(
+    env: &mut Environment,
+    fd: FileDescriptor,
+    addr: MutPtr<sockaddr_in>,
+    addr_len: MutPtr<socklen_t>,
+) -> FileDescriptor {
+    match accept_socket(env, fd) {
+        Ok((new_fd, peer)) => {
+            log_dbg!("accept({:?}) => {:?} (peer {})", fd, new_fd, peer);
+            write_sockaddr_in(env, peer, addr, addr_len);
+            new_fd
+        }
+        Err(errno) => {
+            if errno != EAGAIN {
+                log!("Warning: accept({:?}) failed, returning -1", fd);
+            }
+            set_errno(env, errno);
+            -1
+        }
+    }
+}
+
+fn connect(
+    env: &mut Environment,
+    fd: FileDescriptor,
+    addr: ConstPtr<sockaddr_in>,
+    addr_len: socklen_t,
+) -> i32 {
+    let res = read_sockaddr_in(env, addr, addr_len)
+        .and_then(|addr| connect_socket(env, fd, &[SocketAddr::V4(addr)], None));
+    match res {
+        Ok(()) => {
+            log_dbg!("connect({:?}, {:?}) => 0", fd, addr);
+            0
+        }
+        Err(errno) => {
+            if errno != EINPROGRESS {
+                log!(
+                    "Warning: connect({:?}, {:?}) failed, returning -1",
+                    fd,
+                    addr
+                );
+            }
+            set_errno(env, errno);
+            -1
+        }
+    }
+}
+
+fn getsockname(
+    env: &mut Environment,
+    fd: FileDescriptor,
+    addr: MutPtr<sockaddr_in>,
+    addr_len: MutPtr<socklen_t>,
+) -> i32 {
+    let res = with_socket(env, fd, |socket| {
+        let local = match socket.state {
+            SocketState::Unconnected { bound_to, .. } => {
+                return Ok(bound_to.unwrap_or(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0)))
+            }
+            SocketState::Connecting(_) => return Ok(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0)),
+            SocketState::Listening { ref listener, .. } => listener.local_addr(),
+            SocketState::Connected(ref stream) => stream.local_addr(),
+        };
+        match local {
+            Ok(SocketAddr::V4(local)) => Ok(local),
+            Ok(SocketAddr::V6(_)) => Err(EAFNOSUPPORT),
+            Err(e) => Err(errno_for_io_error(&e)),
+        }
+    });
+    match res {
+        Ok(local) => {
+            write_sockaddr_in(env, local, addr, addr_len);
+            0
+        }
+        Err(errno) => {
+            set_errno(env, errno);
+            -1
+        }
+    }
+}
+
+fn getpeername(
+    env: &mut Environment,
+    fd: FileDescriptor,
+    addr: MutPtr<sockaddr_in>,
+    addr_len: MutPtr<socklen_t>,
+) -> i32 {
+    let res = with_socket(env, fd, |socket| socket.peer_address().ok_or(ENOTCONN));
+    match res {
+        Ok(peer) => {
+            write_sockaddr_in(env, peer, addr, addr_len);
+            0
+        }
+        Err(errno) => {
+            set_errno(env, errno);
+            -1
+        }
+    }
+}
+
+pub fn send(
+    env: &mut Environment,
+    fd: FileDescriptor,
+    buffer: ConstVoidPtr,
+    length: GuestUSize,
+    flags: i32,
+) -> GuestISize {
+    if flags != 0 {
+        log!("TODO: send() flags {:#x}", flags);
+    }
+    let data = env.mem.bytes_at(buffer.cast(), length).to_vec();
+    match with_socket(env, fd, |socket| {
+        socket.write(&data).map_err(|e| errno_for_io_error(&e))
+    }) {
+        Ok(sent) => {
+            log_dbg!("send({:?}, {:?}, {:#x}) => {:#x}", fd, buffer, length, sent);
+            sent as GuestISize
+        }
+        Err(errno) => {
+            if errno != EAGAIN {
+                log!(
+                    "Warning: send({:?}, {:?}, {:#x}) failed, returning -1",
+                    fd,
+                    buffer,
+                    length
+                );
+            }
+            set_errno(env, errno);
+            -1
+        }
+    }
+}
+
+pub fn recv(
+    env: &mut Environment,
+    fd: FileDescriptor,
+    buffer: MutVoidPtr,
+    length: GuestUSize,
+    flags: i32,
+) -> GuestISize {
+    if flags != 0 {
+        log!("TODO: recv() flags {:#x}", flags);
+    }
+    wait_for_socket(env, fd, |socket| {
+        socket.poll_readable() != ReadReadiness::NotReady
+    });
+    let mut data = vec![0u8; length as usize];
+    match with_socket(env, fd, |socket| {
+        socket.read(&mut data).map_err(|e| errno_for_io_error(&e))
+    }) {
+        Ok(received) => {
+            env.mem
+                .bytes_at_mut(buffer.cast(), received as GuestUSize)
+                .copy_from_slice(&data[..received]);
+            log_dbg!(
+                "recv({:?}, {:?}, {:#x}) => {:#x}",
+                fd,
+                buffer,
+                length,
+                received
+            );
+            received as GuestISize
+        }
+        Err(errno) => {
+            if errno != EAGAIN {
+                log!(
+                    "Warning: recv({:?}, {:?}, {:#x}) failed, returning -1",
+                    fd,
+                    buffer,
+                    length
+                );
+            }
+            set_errno(env, errno);
+            -1
+        }
+    }
+}
+
+fn setsockopt(
+    env: &mut Environment,
+    fd: FileDescriptor,
+    level: i32,
+    name: i32,
+    _value: ConstVoidPtr,
+    _value_len: socklen_t,
+) -> i32 {
+    log!(
+        "TODO: setsockopt({:?}, {:#x}, {:#x}) (ignored)",
+        fd,
+        level,
+        name
+    );
+    match with_socket(env, fd, |_| Ok(())) {
+        Ok(()) => 0,
+        Err(errno) => {
+            set_errno(env, errno);
+            -1
+        }
+    }
+}
+
+/// Check without blocking whether a read from (or, if `write` is `true`, a
+/// write to) a descriptor would return immediately. Descriptors that aren't
+/// sockets are always considered ready.
+fn poll_fd(env: &mut Environment, fd: FileDescriptor, write: bool) -> Result<ReadReadiness, i32> {
+    let res = with_socket(env, fd, |socket| {
+        Ok(if !write {
+            socket.poll_readable()
+        } else if socket.poll_writable() {
+            ReadReadiness::Ready
+        } else {
+            ReadReadiness::NotReady
+        })
+    });
+    match res {
+        Err(ENOTSOCK) => Ok(ReadReadiness::Ready),
+        res => res,
+    }
+}
+
+// sys/select.h
+
+/// The number of descriptors in an `fd_set`, which is a bitmap stored as
+/// 32-bit words.
+const FD_SETSIZE: i32 = 1024;
+
+fn read_fd_set(env: &mut Environment, set: MutPtr<u32>, words: GuestUSize) -> Vec<u32> {
+    if set.is_null() {
+        vec![0; words as usize]
+    } else {
+        (0..words).map(|i| env.mem.read(set + i)).collect()
+    }
+}
+
+fn write_fd_set(env: &mut Environment, set: MutPtr<u32>, bits: &[u32]) {
+    if set.is_null() {
+        return;
+    }
+    for (i, &word) in bits.iter().enumerate() {
+        env.mem.write(set + i as GuestUSize, word);
+    }
+}
+
+fn select(
+    env: &mut Environment,
+    nfds: i32,
+    read_fds: MutPtr<u32>,
+    write_fds: MutPtr<u32>,
+    error_fds: MutPtr<u32>,
+    timeout: MutPtr<timeval>,
+) -> i32 {
+    let deadline = if timeout.is_null() {
+        None
+    } else {
+        let timeval { tv_sec, tv_usec } = env.mem.read(timeout);
+        if tv_sec < 0 || !(0..1_000_000).contains(&tv_usec) {
+            set_errno(env, EINVAL);
+            return -1;
+        }
+        let duration = Duration::from_secs(tv_sec as u64) + Duration::from_micros(tv_usec as u64);
+        Some(Instant::now() + duration)
+    };
+    if !(0..=FD_SETSIZE).contains(&nfds) {
+        set_errno(env, EINVAL);
+        return -1;
+    }
+    let words = (nfds as GuestUSize).div_ceil(32);
+    let read_in = read_fd_set(env, read_fds, words);
+    let write_in = read_fd_set(env, write_fds, words);
+
+    loop {
+        let mut read_out = vec![0; words as usize];
+        let mut write_out = vec![0; words as usize];
+        let mut count = 0;
+        for fd in 0..nfds {
+            let (word, bit) = (fd as usize / 32, 1 << (fd % 32));
+            for (set_in, set_out, write) in [
+                (&read_in, &mut read_out, false),
+                (&write_in, &mut write_out, true),
+            ] {
+                if set_in[word] & bit == 0 {
+                    continue;
+                }
+                match poll_fd(env, fd, write) {
+                    Ok(ReadReadiness::NotReady) => (),
+                    Ok(_) => {
+                        set_out[word] |= bit;
+                        count += 1;
+                    }
+                    Err(errno) => {
+                        set_errno(env, errno);
+                        return -1;
+                    }
+                }
+            }
+        }
+        if count > 0 || deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            write_fd_set(env, read_fds, &read_out);
+            write_fd_set(env, write_fds, &write_out);
+            // Exceptional conditions (out-of-band data) are never reported.
+            write_fd_set(env, error_fds, &vec![0; words as usize]);
+            log_dbg!("select({}, ...) => {}", nfds, count);
+            return count;
+        }
+        env.sleep(BLOCKING_POLL_INTERVAL, /* tail_call: */ false);
+    }
+}
+
+// poll.h
+
+#[allow(non_camel_case_types)]
+#[repr(C, packed)]
+pub struct pollfd {
+    pub fd: FileDescriptor,
+    pub events: i16,
+    pub revents: i16,
+}
+unsafe impl SafeRead for pollfd {}
+
+#[allow(non_camel_case_types)]
+pub type nfds_t = u32;
+
+const POLLIN: i16 = 0x1;
+const POLLOUT: i16 = 0x4;
+const POLLHUP: i16 = 0x10;
+const POLLNVAL: i16 = 0x20;
+const POLLRDNORM: i16 = 0x40;
+
+/// Get the `revents` for one descriptor passed to `poll()`.
+fn poll_events(env: &mut Environment, fd: FileDescriptor, events: i16) -> i16 {
+    let mut revents = match poll_fd(env, fd, false) {
+        Ok(ReadReadiness::NotReady) => 0,
+        Ok(ReadReadiness::Ready) => events & (POLLIN | POLLRDNORM),
+        // POLLHUP is reported even if it wasn't requested.
+        Ok(ReadReadiness::AtEnd) => (events & (POLLIN | POLLRDNORM)) | POLLHUP,
+        Err(_) => return POLLNVAL,
+    };
+    if events & POLLOUT != 0 && poll_fd(env, fd, true) == Ok(ReadReadiness::Ready) {
+        revents |= POLLOUT;
+    }
+    revents
+}
+
+fn poll(env: &mut Environment, fds: MutPtr<pollfd>, nfds: nfds_t, timeout: i32) -> i32 {
+    // A negative timeout means waiting forever.
+    let deadline = (timeout >= 0).then(|| Instant::now() + Duration::from_millis(timeout as u64));
+    loop {
+        let mut count = 0;
+        for i in 0..nfds {
+            let pollfd { fd, events, .. } = env.mem.read(fds + i);
+            // Negative descriptors are ignored.
+            let revents = if fd < 0 {
+                0
+            } else {
+                poll_events(env, fd, events)
+            };
+            env.mem.write(
+                fds + i,
+                pollfd {
+                    fd,
+                    events,
+                    revents,
+                },
+            );
+            if revents != 0 {
+                count += 1;
+            }
+        }
+        if count > 0 || deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            log_dbg!("poll({:?}, {}, {}) => {}", fds, nfds, timeout, count);
+            return count;
+        }
+        env.sleep(BLOCKING_POLL_INTERVAL, /* tail_call: */ false);
+    }
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(socket(_, _, _)),
+    export_c_func!(bind(_, _, _)),
+    export_c_func!(listen(_, _)),
+    export_c_func!(accept(_, _, _)),
+    export_c_func!(connect(_, _, _)),
+    export_c_func!(getsockname(_, _, _)),
+    export_c_func!(getpeername(_, _, _)),
+    export_c_func!(send(_, _, _, _)),
+    export_c_func!(recv(_, _, _, _)),
+    export_c_func!(setsockopt(_, _, _, _, _)),
+    export_c_func!(select(_, _, _, _, _)),
+    export_c_func!(poll(_, _, _)),
+];
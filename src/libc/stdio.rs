@@ -5,8 +5,9 @@
  */
 //! `stdio.h`
 
+use super::errno::set_errno;
 use super::posix_io::{
-    self, off_t, O_APPEND, O_CREAT, O_RDONLY, O_RDWR, O_TRUNC, O_WRONLY, STDERR_FILENO,
+    self, off_t, path_at, O_APPEND, O_CREAT, O_RDONLY, O_RDWR, O_TRUNC, O_WRONLY, STDERR_FILENO,
     STDIN_FILENO, STDOUT_FILENO,
 };
 use crate::dyld::{export_c_func, ConstantExports, FunctionExports, HostConstant};
@@ -14,6 +15,7 @@ use crate::fs::GuestPath;
 use crate::libc::string::strlen;
 use crate::mem::{ConstPtr, ConstVoidPtr, GuestUSize, Mem, MutPtr, MutVoidPtr, Ptr, SafeRead};
 use crate::Environment;
+use std::collections::HashMap;
 use std::io::Write;
 use crate::abi::{CallFromHost, GuestFunction};
 
@@ -23,6 +25,83 @@ pub mod printf;
 
 const EOF: i32 = -1;
 
+#[derive(Default)]
+pub struct State {
+    /// Streams opened with `fopen` and not yet closed, so `exit()` can close
+    /// them. See [close_all_streams].
+    open_streams: Vec<MutPtr<FILE>>,
+    /// Orientation of each stream that has been used. Streams not in here are
+    /// unoriented. See [orient].
+    orientations: HashMap<MutPtr<FILE>, Orientation>,
+}
+
+/// Whether a stream is used for bytes or wide characters (see `fwide()`).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(super) enum Orientation {
+    Byte,
+    /// The encoding is [None] until the first wide character is read or
+    /// written, at which point it's decided by [super::wchar].
+    Wide(Option<WideEncoding>),
+}
+
+/// Encoding of the wide characters in a wide-oriented stream. This is UTF-8,
+/// like the locale, unless the stream starts with a UTF-16 byte order mark.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(super) enum WideEncoding {
+    Utf8,
+    Utf16LE,
+    Utf16BE,
+}
+
+/// Get the orientation of a stream, or [None] if it's unoriented.
+pub(super) fn orientation(env: &Environment, stream: MutPtr<FILE>) -> Option<Orientation> {
+    env.libc_state.stdio.orientations.get(&stream).copied()
+}
+
+pub(super) fn set_orientation(
+    env: &mut Environment,
+    stream: MutPtr<FILE>,
+    orientation: Orientation,
+) {
+    env.libc_state
+        .stdio
+        .orientations
+        .insert(stream, orientation);
+}
+
+/// Apply the "first operation wins" rule for a byte (`wide == false`) or wide
+/// character operation on a stream: an unoriented stream takes on the
+/// orientation of the operation. Returns `false` if the stream already has the
+/// other orientation, in which case the operation should fail, since mixing
+/// the two would garble the data.
+pub(super) fn orient(env: &mut Environment, stream: MutPtr<FILE>, wide: bool) -> bool {
+    let orientation = *env
+        .libc_state
+        .stdio
+        .orientations
+        .entry(stream)
+        .or_insert(if wide {
+            Orientation::Wide(None)
+        } else {
+            Orientation::Byte
+        });
+    if matches!(orientation, Orientation::Wide(_)) == wide {
+        return true;
+    }
+    let (operation, other) = if wide {
+        ("Wide character", "byte")
+    } else {
+        ("Byte", "wide")
+    };
+    log!(
+        "Warning: {} operation on {}-oriented stream {:?}, failing",
+        operation,
+        other,
+        stream
+    );
+    false
+}
+
 #[allow(clippy::upper_case_acronyms)]
 /// C `FILE` struct. This is an opaque type in C, so the definition here is our
 /// own.
@@ -40,6 +119,10 @@ fn fopen(env: &mut Environment, filename: ConstPtr<u8>, mode: ConstPtr<u8>) -> M
     // rely on this, e.g. using "wt" to mean open for writing in text mode,
     // even though that's not a real flag. The one thing that is required is for
     // a known basic mode (r/w/a) to come first.
+    //
+    // The path goes through the same resolution as open() (see
+    // [crate::fs::resolve_guest_path]), so e.g. paths into the app bundle
+    // work, and opening them for writing fails with EROFS.
 
     let mode = env.mem.cstr_at(mode);
     let [basic_mode @ (b'r' | b'w' | b'a'), flags @ ..] = mode else {
@@ -53,6 +136,9 @@ fn fopen(env: &mut Environment, filename: ConstPtr<u8>, mode: ConstPtr<u8>) -> M
         match flag {
             // binary flag does nothing on UNIX
             b'b' => (),
+            // Windows' text mode flag, which Apple's implementation accepts
+            // as a no-op too
+            b't' => (),
             b'+' => plus = true,
             other => {
                 log!("Tolerating unrecognized fopen() mode flag: {:?}", other);
@@ -72,7 +158,11 @@ fn fopen(env: &mut Environment, filename: ConstPtr<u8>, mode: ConstPtr<u8>) -> M
 
     match posix_io::open_direct(env, filename, flags) {
         -1 => Ptr::null(),
-        fd => env.mem.alloc_and_write(FILE { fd }),
+        fd => {
+            let file_ptr = env.mem.alloc_and_write(FILE { fd });
+            env.libc_state.stdio.open_streams.push(file_ptr);
+            file_ptr
+        }
     }
 }
 
@@ -82,6 +172,21 @@ pub fn fread(
     item_size: GuestUSize,
     n_items: GuestUSize,
     file_ptr: MutPtr<FILE>,
+) -> GuestUSize {
+    if !orient(env, file_ptr, /* wide: */ false) {
+        return 0;
+    }
+    fread_unoriented(env, buffer, item_size, n_items, file_ptr)
+}
+
+/// [fread] without the orientation check, for use by the wide character
+/// functions.
+pub(super) fn fread_unoriented(
+    env: &mut Environment,
+    buffer: MutVoidPtr,
+    item_size: GuestUSize,
+    n_items: GuestUSize,
+    file_ptr: MutPtr<FILE>,
 ) -> GuestUSize {
     if item_size == 0 {
         return 0;
@@ -104,21 +209,26 @@ pub fn fread(
 }
 
 fn fgetc(env: &mut Environment, file_ptr: MutPtr<FILE>) -> i32 {
+    if !orient(env, file_ptr, /* wide: */ false) {
+        return EOF;
+    }
     let FILE { fd } = env.mem.read(file_ptr);
     let buffer = env.mem.alloc(1);
 
-    match posix_io::read(env, fd, buffer, 1) {
+    let res = match posix_io::read(env, fd, buffer, 1) {
         -1 => EOF,
         bytes_read => {
             let bytes_read: GuestUSize = bytes_read.try_into().unwrap();
             if bytes_read < 1 {
                 EOF
             } else {
-                let buf: MutPtr<i32> = buffer.cast();
-                env.mem.read(buf)
+                let buf: MutPtr<u8> = buffer.cast();
+                env.mem.read(buf).into()
             }
         }
-    }
+    };
+    env.mem.free(buffer);
+    res
 }
 
 fn ungetc(env: &mut Environment, c: u8, file_ptr: MutPtr<FILE>) -> i32 {
@@ -179,6 +289,21 @@ fn fwrite(
     item_size: GuestUSize,
     n_items: GuestUSize,
     file_ptr: MutPtr<FILE>,
+) -> GuestUSize {
+    if !orient(env, file_ptr, /* wide: */ false) {
+        return 0;
+    }
+    fwrite_unoriented(env, buffer, item_size, n_items, file_ptr)
+}
+
+/// [fwrite] without the orientation check, for use by the wide character
+/// functions.
+pub(super) fn fwrite_unoriented(
+    env: &mut Environment,
+    buffer: ConstVoidPtr,
+    item_size: GuestUSize,
+    n_items: GuestUSize,
+    file_ptr: MutPtr<FILE>,
 ) -> GuestUSize {
     if item_size == 0 {
         return 0;
@@ -250,6 +375,11 @@ fn rewind(env: &mut Environment, file_ptr: MutPtr<FILE>)  {
 fn fclose(env: &mut Environment, file_ptr: MutPtr<FILE>) -> i32 {
     let FILE { fd } = env.mem.read(file_ptr);
 
+    env.libc_state
+        .stdio
+        .open_streams
+        .retain(|&stream| stream != file_ptr);
+    env.libc_state.stdio.orientations.remove(&file_ptr);
     env.mem.free(file_ptr.cast());
 
     match posix_io::close(env, fd) {
@@ -311,10 +441,10 @@ fn remove(env: &mut Environment, path: ConstPtr<u8>) -> i32 {
         return -1;
     }
 
-    match env
-        .fs
-        .remove(GuestPath::new(&env.mem.cstr_at_utf8(path).unwrap()))
-    {
+    let Some(path_string) = path_at(env, path) else {
+        return -1;
+    };
+    match env.fs.remove(GuestPath::new(&path_string)) {
         Ok(()) => {
             log_dbg!("remove({:?}) => 0", path);
             0
@@ -327,6 +457,31 @@ fn remove(env: &mut Environment, path: ConstPtr<u8>) -> i32 {
     }
 }
 
+fn rename(env: &mut Environment, old: ConstPtr<u8>, new: ConstPtr<u8>) -> i32 {
+    let (Some(old_path), Some(new_path)) = (path_at(env, old), path_at(env, new)) else {
+        return -1;
+    };
+    match env
+        .fs
+        .rename(GuestPath::new(&old_path), GuestPath::new(&new_path))
+    {
+        Ok(()) => {
+            log_dbg!("rename({:?}, {:?}) => 0", old_path, new_path);
+            0
+        }
+        Err(errno) => {
+            log!(
+                "Warning: rename({:?}, {:?}) failed with errno {}, returning -1",
+                old_path,
+                new_path,
+                errno
+            );
+            set_errno(env, errno);
+            -1
+        }
+    }
+}
+
 fn setbuf(_env: &mut Environment, stream: MutPtr<FILE>, buf: ConstPtr<u8>) {
     assert!(buf.is_null());
     log!(
@@ -352,6 +507,17 @@ fn _ZNK4Body18isIslandGeneratingEv(env: &mut Environment, ptr: MutVoidPtr) {
     func.call_from_host(env, (ptr,))
 }
 
+/// Close every stream opened with `fopen` and flush the standard streams, as
+/// `exit()` does. Writes to streams aren't buffered on the guest side, but
+/// writes to stdout are buffered by the host until the end of a line.
+pub fn close_all_streams(env: &mut Environment) {
+    for stream in std::mem::take(&mut env.libc_state.stdio.open_streams) {
+        fclose(env, stream);
+    }
+    let _ = std::io::stdout().flush();
+    let _ = std::io::stderr().flush();
+}
+
 pub const CONSTANTS: ConstantExports = &[
     (
         "___stdinp",
@@ -398,6 +564,7 @@ pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(puts(_)),
     export_c_func!(putchar(_)),
     export_c_func!(remove(_)),
+    export_c_func!(rename(_, _)),
     export_c_func!(setbuf(_, _)),
     // POSIX-specific functions
     export_c_func!(fileno(_)),
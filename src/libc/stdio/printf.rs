@@ -5,22 +5,323 @@
  */
 //! `printf` function family. The implementation is also used by `NSLog` etc.
 
-use crate::abi::{DotDotDot, VaList};
+use crate::abi::{DotDotDot, GuestArg, VaList};
 use crate::dyld::{export_c_func, FunctionExports};
-use crate::frameworks::foundation::{ns_string, unichar};
-use crate::libc::posix_io::{STDERR_FILENO, STDOUT_FILENO};
-use crate::libc::stdio::{EOF, fgetc, FILE, fputc, fwrite, ungetc};
+use crate::frameworks::foundation::{ns_object, unichar};
+use crate::libc::posix_io::{STDERR_FILENO, STDIN_FILENO, STDOUT_FILENO};
+use crate::libc::stdio::{EOF, fgetc, FILE, fputc, fwrite, orient, ungetc};
 use crate::mem::{ConstPtr, guest_size_of, GuestUSize, Mem, MutPtr, MutVoidPtr, Ptr};
-use crate::objc::{id, msg};
+use crate::objc::id;
 use crate::Environment;
 use std::collections::HashSet;
 use std::io::Write;
-use crate::libc::stdlib::{atof_inner, strtoul};
+use crate::libc::stdlib::strtoul;
 use crate::libc::string::strlen;
 use crate::libc::wchar::{wchar_t, wmemcpy};
 
 const INTEGER_SPECIFIERS: [u8; 6] = [b'd', b'i', b'o', b'u', b'x', b'X'];
-const FLOAT_SPECIFIERS: [u8; 2] = [b'f', b'g'];
+const FLOAT_SPECIFIERS: [u8; 6] = [b'f', b'F', b'e', b'E', b'g', b'G'];
+
+/// Length modifiers for integer specifiers. Longer ones must come before their
+/// prefixes.
+const LENGTH_MODIFIERS: [&[u8]; 4] = [b"hh", b"h", b"ll", b"l"];
+
+/// The arguments for [printf_inner].
+///
+/// Usually they are read in order straight from the [VaList]. Positional
+/// specifiers (`%2$d`) can use them in any order though, and where an argument
+/// is in a [VaList] depends on the types of the ones before it, so a format
+/// string containing those is scanned in advance to find where every argument
+/// starts (see [find_positional_args]). As on BSD, a specifier without a
+/// position then uses the argument after the previous specifier's.
+struct PrintfArgs {
+    va_list: VaList,
+    /// Start of each argument, if the format string has positional specifiers.
+    positions: Option<Vec<VaList>>,
+    /// Index of the next argument to use, if `positions` is [Some].
+    next_index: usize,
+}
+impl PrintfArgs {
+    fn next<T: GuestArg>(&mut self, env: &mut Environment) -> T {
+        let Some(positions) = &self.positions else {
+            return self.va_list.next(env);
+        };
+        let mut arg = positions[self.next_index];
+        self.next_index += 1;
+        arg.next(env)
+    }
+
+    /// Get the argument at a (1-based) position, without affecting which
+    /// argument [Self::next] returns. For `*N$` widths.
+    fn get<T: GuestArg>(&self, env: &mut Environment, position: usize) -> T {
+        let mut arg = self.positions.as_ref().unwrap()[position - 1];
+        arg.next(env)
+    }
+
+    /// Make [Self::next] continue from a (1-based) position.
+    fn set_next_position(&mut self, position: usize) {
+        self.next_index = position - 1;
+    }
+}
+
+/// Parse a `N$` argument position at `idx`, if there is one, and advance past
+/// it.
+fn parse_position<F: Fn(&Mem, GuestUSize) -> u8>(
+    mem: &Mem,
+    get_format_char: &F,
+    idx: &mut GuestUSize,
+) -> Option<usize> {
+    let mut end = *idx;
+    let mut position: usize = 0;
+    while let c @ b'0'..=b'9' = get_format_char(mem, end) {
+        position = position
+            .saturating_mul(10)
+            .saturating_add((c - b'0').into());
+        end += 1;
+    }
+    if position == 0 || get_format_char(mem, end) != b'$' {
+        return None;
+    }
+    *idx = end + 1;
+    Some(position)
+}
+
+/// Parse a length modifier at `idx`, if there is one, and advance past it.
+fn parse_length_modifier<F: Fn(&Mem, GuestUSize) -> u8>(
+    mem: &Mem,
+    get_format_char: &F,
+    idx: &mut GuestUSize,
+) -> Option<&'static [u8]> {
+    let length_modifier = LENGTH_MODIFIERS.into_iter().find(|modifier| {
+        (0..)
+            .zip(modifier.iter())
+            .all(|(i, &c)| get_format_char(mem, *idx + i) == c)
+    });
+    if let Some(modifier) = length_modifier {
+        *idx += modifier.len() as GuestUSize;
+    }
+    length_modifier
+}
+
+/// If the format string contains positional specifiers, find where each
+/// argument starts in `va_list`. See [PrintfArgs].
+///
+/// Only the sizes of the arguments matter for this: on 32-bit ARM everything
+/// is passed as one word, except for `long long` and `double`, which take two.
+fn find_positional_args<F: Fn(&Mem, GuestUSize) -> u8>(
+    env: &mut Environment,
+    get_format_char: &F,
+    mut va_list: VaList,
+) -> Option<Vec<VaList>> {
+    let mut has_positions = false;
+    // Whether each argument is two words, if any specifier uses it.
+    let mut arg_is_wide: Vec<Option<bool>> = Vec::new();
+    let mut use_arg = |index: usize, is_wide: bool| {
+        if arg_is_wide.len() <= index {
+            arg_is_wide.resize(index + 1, None);
+        }
+        arg_is_wide[index] = Some(is_wide);
+    };
+    let mut next_index = 0;
+
+    // This must parse specifiers the same way as printf_inner.
+    let mut idx = 0;
+    loop {
+        let c = get_format_char(&env.mem, idx);
+        idx += 1;
+        if c == b'\0' {
+            break;
+        }
+        if c != b'%' {
+            continue;
+        }
+
+        if let Some(position) = parse_position(&env.mem, get_format_char, &mut idx) {
+            has_positions = true;
+            next_index = position - 1;
+        }
+        while let b'0' | b'#' | b'-' = get_format_char(&env.mem, idx) {
+            idx += 1;
+        }
+        if get_format_char(&env.mem, idx) == b'*' {
+            idx += 1;
+            if let Some(position) = parse_position(&env.mem, get_format_char, &mut idx) {
+                has_positions = true;
+                use_arg(position - 1, false);
+            } else {
+                use_arg(next_index, false);
+                next_index += 1;
+            }
+        }
+        while let b'0'..=b'9' | b'.' = get_format_char(&env.mem, idx) {
+            idx += 1;
+        }
+        let length_modifier = parse_length_modifier(&env.mem, get_format_char, &mut idx);
+
+        let specifier = get_format_char(&env.mem, idx);
+        idx += 1;
+        match specifier {
+            // printf_inner will complain about this.
+            b'\0' => break,
+            b'%' => (),
+            _ => {
+                let is_wide = FLOAT_SPECIFIERS.contains(&specifier)
+                    || (INTEGER_SPECIFIERS.contains(&specifier) && length_modifier == Some(b"ll"));
+                use_arg(next_index, is_wide);
+                next_index += 1;
+            }
+        }
+    }
+
+    if !has_positions {
+        return None;
+    }
+
+    let positions = arg_is_wide
+        .into_iter()
+        .enumerate()
+        .map(|(index, is_wide)| {
+            let start = va_list;
+            if is_wide.unwrap_or_else(|| {
+                log!(
+                    "Warning: printf argument {} is unused, assuming it is one word",
+                    index + 1
+                );
+                false
+            }) {
+                va_list.next::<u64>(env);
+            } else {
+                va_list.next::<u32>(env);
+            }
+            start
+        })
+        .collect();
+    Some(positions)
+}
+
+/// Read the argument for an integer specifier with the given length modifier,
+/// sign- or zero-extending it. `char` and `short` arguments are promoted to
+/// `int` when passed, so they are read as one and then narrowed.
+fn next_integer_arg(
+    env: &mut Environment,
+    args: &mut PrintfArgs,
+    length_modifier: Option<&[u8]>,
+    signed: bool,
+) -> i128 {
+    match (length_modifier, signed) {
+        (Some(b"hh"), true) => (args.next::<i32>(env) as i8).into(),
+        (Some(b"hh"), false) => (args.next::<u32>(env) as u8).into(),
+        (Some(b"h"), true) => (args.next::<i32>(env) as i16).into(),
+        (Some(b"h"), false) => (args.next::<u32>(env) as u16).into(),
+        (Some(b"ll"), true) => args.next::<i64>(env).into(),
+        (Some(b"ll"), false) => args.next::<u64>(env).into(),
+        // On a 32-bit system, int and long are the same size.
+        (_, true) => args.next::<i32>(env).into(),
+        (_, false) => args.next::<u32>(env).into(),
+    }
+}
+
+/// Write a formatted number, padded to `width` with zeros after the sign like
+/// the `0` flag does.
+fn write_zero_padded(res: &mut Vec<u8>, number: &str, width: usize) {
+    let (sign, digits) = match number.strip_prefix('-') {
+        Some(digits) => ("-", digits),
+        None => ("", number),
+    };
+    let width = width.saturating_sub(sign.len());
+    write!(res, "{}{:0>2$}", sign, digits, width).unwrap();
+}
+
+/// Format an infinity or NaN, in uppercase for `%F`, `%E` and `%G`. Like
+/// Apple's libc, the sign of a NaN isn't shown.
+fn format_non_finite(float: f64, uppercase: bool) -> String {
+    let string = match (float.is_nan(), float.is_sign_negative()) {
+        (true, _) => "nan",
+        (false, false) => "inf",
+        (false, true) => "-inf",
+    };
+    if uppercase {
+        string.to_ascii_uppercase()
+    } else {
+        string.to_string()
+    }
+}
+
+/// Format a float for `%f`/`%F`, without any padding. `alternate_form` is the
+/// `#` flag.
+fn format_float_fixed(float: f64, specifier: u8, precision: usize, alternate_form: bool) -> String {
+    if !float.is_finite() {
+        return format_non_finite(float, specifier == b'F');
+    }
+    // Rust's formatting is exact, like C's: huge values are printed in full,
+    // halfway cases round to even and negative zero keeps its sign.
+    let mut formatted = format!("{:.1$}", float, precision);
+    if alternate_form && precision == 0 {
+        formatted.push('.');
+    }
+    formatted
+}
+
+/// Format a float for `%e`/`%E` (if `specifier` is `e` or `E`) or `%g`/`%G`,
+/// without any padding. `alternate_form` is the `#` flag.
+fn format_float_exponent_or_general(
+    float: f64,
+    specifier: u8,
+    precision: usize,
+    alternate_form: bool,
+) -> String {
+    let uppercase = specifier.is_ascii_uppercase();
+    if !float.is_finite() {
+        return format_non_finite(float, uppercase);
+    }
+
+    // Rust's exponent formatting has the right digits, but the exponent
+    // doesn't have C's sign and minimum width, so it's split off.
+    let exponent_form = |precision: usize| -> (String, i32) {
+        let formatted = format!("{:.1$e}", float, precision);
+        let (mantissa, exponent) = formatted.split_once('e').unwrap();
+        (mantissa.to_string(), exponent.parse().unwrap())
+    };
+    let with_exponent = |mantissa: &str, exponent: i32| -> String {
+        let e = if uppercase { 'E' } else { 'e' };
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!("{}{}{}{:02}", mantissa, e, sign, exponent.unsigned_abs())
+    };
+    let add_point = |mut mantissa: String| -> String {
+        if alternate_form && !mantissa.contains('.') {
+            mantissa.push('.');
+        }
+        mantissa
+    };
+
+    if matches!(specifier, b'e' | b'E') {
+        let (mantissa, exponent) = exponent_form(precision);
+        return with_exponent(&add_point(mantissa), exponent);
+    }
+
+    // %g uses %e or %f style depending on the exponent, with the precision
+    // being the number of significant digits.
+    let precision = precision.max(1);
+    let (mantissa, exponent) = exponent_form(precision - 1);
+    let trim = |string: String| -> String {
+        if alternate_form {
+            add_point(string)
+        } else if string.contains('.') {
+            string
+                .trim_end_matches('0')
+                .trim_end_matches('.')
+                .to_string()
+        } else {
+            string
+        }
+    };
+    if exponent < -4 || exponent >= precision as i32 {
+        with_exponent(&trim(mantissa), exponent)
+    } else {
+        let decimals = (precision as i32 - 1 - exponent) as usize;
+        trim(format!("{:.1$}", float, decimals))
+    }
+}
 
 /// String formatting implementation for `printf` and `NSLog` function families.
 ///
@@ -32,10 +333,16 @@ const FLOAT_SPECIFIERS: [u8; 2] = [b'f', b'g'];
 pub fn printf_inner<const NS_LOG: bool, F: Fn(&Mem, GuestUSize) -> u8>(
     env: &mut Environment,
     get_format_char: F,
-    mut args: VaList,
+    va_list: VaList,
 ) -> Vec<u8> {
     let mut res = Vec::<u8>::new();
 
+    let mut args = PrintfArgs {
+        va_list,
+        positions: find_positional_args(env, &get_format_char, va_list),
+        next_index: 0,
+    };
+
     let mut format_char_idx = 0;
 
     loop {
@@ -50,18 +357,35 @@ pub fn printf_inner<const NS_LOG: bool, F: Fn(&Mem, GuestUSize) -> u8>(
             continue;
         }
 
-        let pad_char = if get_format_char(&env.mem, format_char_idx) == b'0' {
+        if let Some(position) = parse_position(&env.mem, &get_format_char, &mut format_char_idx) {
+            args.set_next_position(position);
+        }
+
+        let mut pad_char = ' ';
+        let mut alternate_form = false;
+        let mut left_justify = false;
+        loop {
+            match get_format_char(&env.mem, format_char_idx) {
+                b'0' => pad_char = '0',
+                b'#' => alternate_form = true,
+                b'-' => left_justify = true,
+                _ => break,
+            }
             format_char_idx += 1;
-            '0'
-        } else {
-            ' '
-        };
+        }
 
-        let pad_width = if get_format_char(&env.mem, format_char_idx) == b'*' {
-            let pad_width = args.next::<i32>(env);
-            assert!(pad_width >= 0); // TODO: Implement right-padding
+        let mut pad_width = if get_format_char(&env.mem, format_char_idx) == b'*' {
             format_char_idx += 1;
-            pad_width
+            let pad_width: i32 =
+                match parse_position(&env.mem, &get_format_char, &mut format_char_idx) {
+                    Some(position) => args.get(env, position),
+                    None => args.next(env),
+                };
+            // A negative width argument is taken as the '-' flag.
+            if pad_width < 0 {
+                left_justify = true;
+            }
+            pad_width.saturating_abs()
         } else {
             let mut pad_width: i32 = 0;
             while let c @ b'0'..=b'9' = get_format_char(&env.mem, format_char_idx) {
@@ -83,12 +407,8 @@ pub fn printf_inner<const NS_LOG: bool, F: Fn(&Mem, GuestUSize) -> u8>(
             None
         };
 
-        let length_modifier = if get_format_char(&env.mem, format_char_idx) == b'l' {
-            format_char_idx += 1;
-            Some(b'l')
-        } else {
-            None
-        };
+        let length_modifier =
+            parse_length_modifier(&env.mem, &get_format_char, &mut format_char_idx);
 
         let specifier = get_format_char(&env.mem, format_char_idx);
         format_char_idx += 1;
@@ -101,16 +421,29 @@ pub fn printf_inner<const NS_LOG: bool, F: Fn(&Mem, GuestUSize) -> u8>(
 
         if precision.is_some() {
             assert!(
-                INTEGER_SPECIFIERS.contains(&specifier) || FLOAT_SPECIFIERS.contains(&specifier)
+                INTEGER_SPECIFIERS.contains(&specifier)
+                    || FLOAT_SPECIFIERS.contains(&specifier)
+                    || specifier == b's'
             )
         }
+        // TODO: alternate forms of other specifiers
+        assert!(!alternate_form || FLOAT_SPECIFIERS.contains(&specifier) || specifier == b'o');
+
+        // When left-justifying, the value is formatted without padding and
+        // spaces are added after it. The '0' flag is ignored in that case.
+        let left_justify_width = if left_justify {
+            std::mem::replace(&mut pad_width, 0) as usize
+        } else {
+            0
+        };
+        let value_start = res.len();
 
         match specifier {
             b'c' => {
                 // TODO: support length modifier
                 assert!(length_modifier.is_none());
                 let c: u8 = args.next(env);
-                assert!(pad_char == ' ' && pad_width == 0); // TODO
+                res.resize(res.len() + (pad_width as usize).saturating_sub(1), b' ');
                 res.push(c);
             }
             // Apple extension? Seemingly works in both NSLog and printf.
@@ -128,23 +461,28 @@ pub fn printf_inner<const NS_LOG: bool, F: Fn(&Mem, GuestUSize) -> u8>(
                 // TODO: support length modifier
                 assert!(length_modifier.is_none());
                 let c_string: ConstPtr<u8> = args.next(env);
-                assert!(pad_char == ' ' && pad_width == 0); // TODO
-                if !c_string.is_null() {
-                    res.extend_from_slice(env.mem.cstr_at(c_string));
+                let string = if c_string.is_null() {
+                    b"(null)"
+                } else if let Some(precision) = precision {
+                    // The precision is the maximum number of bytes to write,
+                    // and the string needn't be terminated if it's longer.
+                    let max_len = precision.try_into().unwrap_or(GuestUSize::MAX);
+                    let len = env
+                        .mem
+                        .find_byte(c_string, b'\0', max_len)
+                        .unwrap_or(max_len);
+                    env.mem.bytes_at(c_string, len)
                 } else {
-                    res.extend_from_slice("(null)".as_bytes());
-                }
+                    env.mem.cstr_at(c_string)
+                };
+                // The '0' flag is meaningless for strings, so spaces are always
+                // used.
+                let padding = (pad_width as usize).saturating_sub(string.len());
+                res.resize(res.len() + padding, b' ');
+                res.extend_from_slice(string);
             }
             b'd' | b'i' | b'u' => {
-                // Note: on 32-bit system int and long are i32,
-                // so length_modifier is ignored
-                let int: i64 = if specifier == b'u' {
-                    let uint: u32 = args.next(env);
-                    uint.into()
-                } else {
-                    let int: i32 = args.next(env);
-                    int.into()
-                };
+                let int = next_integer_arg(env, &mut args, length_modifier, specifier != b'u');
 
                 let int_with_precision = if precision.is_some_and(|value| value > 0) {
                     format!("{:01$}", int, precision.unwrap())
@@ -163,37 +501,53 @@ pub fn printf_inner<const NS_LOG: bool, F: Fn(&Mem, GuestUSize) -> u8>(
                     res.extend_from_slice(int_with_precision.as_bytes());
                 }
             }
-            b'g' | b'f' => {
+            b'f' | b'F' => {
                 // TODO: support length modifier
                 assert!(length_modifier.is_none());
                 let float: f64 = args.next(env);
-                let precision_value = precision.unwrap_or(6);
+                let formatted =
+                    format_float_fixed(float, specifier, precision.unwrap_or(6), alternate_form);
                 if pad_width > 0 {
                     let pad_width = pad_width as usize;
-                    if pad_char == '0' {
-                        write!(&mut res, "{:01$.2$}", float, pad_width, precision_value).unwrap();
+                    if pad_char == '0' && float.is_finite() {
+                        write_zero_padded(&mut res, &formatted, pad_width);
                     } else {
-                        write!(&mut res, "{:1$.2$}", float, pad_width, precision_value).unwrap();
+                        write!(&mut res, "{:>1$}", formatted, pad_width).unwrap();
                     }
                 } else {
-                    write!(&mut res, "{:.1$}", float, precision_value).unwrap();
+                    res.extend_from_slice(formatted.as_bytes());
+                }
+            }
+            b'e' | b'E' | b'g' | b'G' => {
+                // TODO: support length modifier
+                assert!(length_modifier.is_none());
+                let float: f64 = args.next(env);
+                let formatted = format_float_exponent_or_general(
+                    float,
+                    specifier,
+                    precision.unwrap_or(6),
+                    alternate_form,
+                );
+                if pad_width > 0 {
+                    let pad_width = pad_width as usize;
+                    if pad_char == '0' && float.is_finite() {
+                        write_zero_padded(&mut res, &formatted, pad_width);
+                    } else {
+                        write!(&mut res, "{:>1$}", formatted, pad_width).unwrap();
+                    }
+                } else {
+                    res.extend_from_slice(formatted.as_bytes());
                 }
             }
             b'@' if NS_LOG => {
                 assert!(length_modifier.is_none());
                 let object: id = args.next(env);
-                // TODO: use localized description if available?
-                let description: id = msg![env; object description];
-                // TODO: avoid copy
-                // TODO: what if the description isn't valid UTF-16?
-                let description = ns_string::to_rust_string(env, description);
-                write!(&mut res, "{}", description).unwrap();
+                let description = ns_object::description_string(env, object);
+                res.extend_from_slice(description.as_bytes());
             }
             b'x' => {
                 assert!(precision.is_none());
-                // Note: on 32-bit system unsigned int and unsigned long
-                // are u32, so length_modifier is ignored
-                let uint: u32 = args.next(env);
+                let uint = next_integer_arg(env, &mut args, length_modifier, false);
                 if pad_width > 0 {
                     let pad_width = pad_width as usize;
                     if pad_char == '0' && precision.is_none() {
@@ -207,9 +561,7 @@ pub fn printf_inner<const NS_LOG: bool, F: Fn(&Mem, GuestUSize) -> u8>(
             }
             b'X' => {
                 assert!(precision.is_none());
-                // Note: on 32-bit system unsigned int and unsigned long
-                // are u32, so length_modifier is ignored
-                let uint: u32 = args.next(env);
+                let uint = next_integer_arg(env, &mut args, length_modifier, false);
                 if pad_width > 0 {
                     let pad_width = pad_width as usize;
                     if pad_char == '0' && precision.is_none() {
@@ -221,10 +573,32 @@ pub fn printf_inner<const NS_LOG: bool, F: Fn(&Mem, GuestUSize) -> u8>(
                     res.extend_from_slice(format!("{:X}", uint).as_bytes());
                 }
             }
+            b'o' => {
+                let uint = next_integer_arg(env, &mut args, length_modifier, false);
+                // A precision of 0 means zero is printed as no digits at all.
+                let mut digits = if precision == Some(0) && uint == 0 {
+                    String::new()
+                } else {
+                    format!("{:01$o}", uint, precision.unwrap_or(0))
+                };
+                // The alternate form always has a leading zero.
+                if alternate_form && !digits.starts_with('0') {
+                    digits.insert(0, '0');
+                }
+                let pad_width = pad_width as usize;
+                if pad_char == '0' && precision.is_none() {
+                    write!(&mut res, "{:0>1$}", digits, pad_width).unwrap();
+                } else {
+                    write!(&mut res, "{:>1$}", digits, pad_width).unwrap();
+                }
+            }
             b'p' => {
                 assert!(length_modifier.is_none());
                 let ptr: MutVoidPtr = args.next(env);
-                res.extend_from_slice(format!("{:?}", ptr).as_bytes());
+                // Like iOS, this is always lowercase hexadecimal with a 0x
+                // prefix, including for NULL ("0x0").
+                let formatted = format!("{:#x}", ptr.to_bits());
+                write!(&mut res, "{:>1$}", formatted, pad_width as usize).unwrap();
             }
             // TODO: more specifiers
             _ => unimplemented!(
@@ -233,6 +607,10 @@ pub fn printf_inner<const NS_LOG: bool, F: Fn(&Mem, GuestUSize) -> u8>(
                 format_char_idx
             ),
         }
+
+        if res.len() - value_start < left_justify_width {
+            res.resize(value_start + left_justify_width, b' ');
+        }
     }
 
     log_dbg!("=> {:?}", std::str::from_utf8(&res));
@@ -278,6 +656,11 @@ fn vsnprintf(
     );
 
     let res = printf_inner::<false, _>(env, |mem, idx| mem.read(format + idx), arg);
+    // With a zero size, nothing (not even the null terminator) is written.
+    if n == 0 {
+        return res.len().try_into().unwrap();
+    }
+
     let middle = if ((n - 1) as usize) < res.len() {
         &res[..(n - 1) as usize]
     } else {
@@ -415,118 +798,176 @@ fn printf(env: &mut Environment, format: ConstPtr<u8>, args: DotDotDot) -> i32 {
 
 // TODO: more printf variants
 
-fn fscanf(env: &mut Environment, stream: MutPtr<FILE>, format: ConstPtr<u8>, args: DotDotDot) -> i32 {
-    log_dbg!(
-        "fscanf({:?}, {:?} ({:?}), ...)",
-        stream,
-        format,
-        env.mem.cstr_at_utf8(format)
-    );
-
-    let mut args = args.start();
+/// Source of input characters for [scanf_inner].
+trait ScanfSource {
+    /// Get the next character without consuming it, or [None] at the end of
+    /// the input.
+    fn peek(&mut self, env: &mut Environment) -> Option<u8>;
+    /// Consume the character returned by the last [ScanfSource::peek].
+    fn advance(&mut self, env: &mut Environment);
+}
 
-    let mut format_char_idx = 0;
+/// Input from a null-terminated string (`sscanf` etc).
+struct StringSource(ConstPtr<u8>);
 
-    let mut matched_args = 0;
+impl ScanfSource for StringSource {
+    fn peek(&mut self, env: &mut Environment) -> Option<u8> {
+        match env.mem.read(self.0) {
+            b'\0' => None,
+            c => Some(c),
+        }
+    }
+    fn advance(&mut self, _env: &mut Environment) {
+        self.0 += 1;
+    }
+}
 
-    loop {
-        let c = env.mem.read(format + format_char_idx);
-        format_char_idx += 1;
+/// Input from a `FILE` (`fscanf` etc). Peeking is done by reading a character
+/// and pushing it back with `ungetc`.
+struct FileSource(MutPtr<FILE>);
 
-        if c == b'\0' {
-            break;
-        }
-        if c != b'%' {
-            //let cc = env.mem.read(src_ptr);
-            let cc = fgetc(env, stream);
-            if (cc == EOF) {
-                panic!("EOF");
-            }
-            let cc: u8 = cc.try_into().unwrap();
-            if c != cc {
-                log_dbg!("fscanf c '{}' cc '{}'", c as char, cc as char);
-                return matched_args - 1;
-            }
-            //src_ptr += 1;
-            continue;
+impl ScanfSource for FileSource {
+    fn peek(&mut self, env: &mut Environment) -> Option<u8> {
+        let c = fgetc(env, self.0);
+        if c == EOF {
+            return None;
         }
+        let c: u8 = c.try_into().unwrap();
+        ungetc(env, c, self.0);
+        Some(c)
+    }
+    fn advance(&mut self, env: &mut Environment) {
+        fgetc(env, self.0);
+    }
+}
 
-        let length_modifier = if env.mem.read(format + format_char_idx) == b'h' {
-            format_char_idx += 1;
-            Some(b'h')
-        } else {
-            None
-        };
+/// Wrapper for another [ScanfSource] that ends the input after a conversion's
+/// maximum field width.
+struct WidthLimitedSource<'a, S: ScanfSource> {
+    source: &'a mut S,
+    remaining: GuestUSize,
+}
 
-        let specifier = env.mem.read(format + format_char_idx);
-        format_char_idx += 1;
+impl<S: ScanfSource> ScanfSource for WidthLimitedSource<'_, S> {
+    fn peek(&mut self, env: &mut Environment) -> Option<u8> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.source.peek(env)
+    }
+    fn advance(&mut self, env: &mut Environment) {
+        self.remaining -= 1;
+        self.source.advance(env);
+    }
+}
 
-        match specifier {
-            b'd' | b'i' => {
-                if specifier == b'i' {
-                    // TODO: hexs and octals
-                    //assert_ne!(env.mem.read(src_ptr), b'0');
-                    //assert_ne!(fgetc(env, stream) as u8, b'0');
-                }
+fn scanf_skip_whitespace<S: ScanfSource>(env: &mut Environment, source: &mut S) {
+    while source.peek(env).is_some_and(|c| c.is_ascii_whitespace()) {
+        source.advance(env);
+    }
+}
 
-                match length_modifier {
-                    Some(lm) => {
-                        match lm {
-                            b'h' => {
-                                // signed short* or unsigned short*
-                                let mut val: i16 = 0;
-                                while let c @ b'0'..=b'9' = fgetc(env, stream).try_into().unwrap() {
-                                    val = val * 10 + (c - b'0') as i16;
-                                    //src_ptr += 1;
-                                }
-                                let c_short_ptr: ConstPtr<i16> = args.next(env);
-                                env.mem.write(c_short_ptr.cast_mut(), val);
-                            }
-                            _ => unimplemented!(),
-                        }
-                    }
-                    _ => {
-                        let mut val: i32 = 0;
-                        let mut sign = 1;
-                        if let c = fgetc(env, stream).try_into().unwrap() {
-                            if c == b'-' {
-                                sign = -1;
-                            } else {
-                                ungetc(env, c, stream);
-                            }
-                        }
-                        while let c @ b'0'..=b'9' = fgetc(env, stream).try_into().unwrap() {
-                            val = val * 10 + (c - b'0') as i32;
-                        }
-                        val *= sign;
-                        log_dbg!("fscanf i32 '{}'", val);
-                        let c_int_ptr: ConstPtr<i32> = args.next(env);
-                        env.mem.write(c_int_ptr.cast_mut(), val);
-                    }
-                }
+/// Expand the characters between the brackets of a `%[...]` conversion into a
+/// set, e.g. `a-z0-9_`. A `-` at either end, or followed by a character lower
+/// than the one before it, stands for itself.
+fn scanf_set(chars: &[u8]) -> HashSet<u8> {
+    let mut set = HashSet::new();
+    let mut prev = None;
+    let mut chars = chars.iter().copied().peekable();
+    while let Some(c) = chars.next() {
+        match (c, prev, chars.peek()) {
+            (b'-', Some(start), Some(&end)) if end >= start => {
+                set.extend(start..=end);
+                prev = Some(end);
+                chars.next();
+            }
+            _ => {
+                set.insert(c);
+                prev = Some(c);
             }
-            // TODO: more specifiers
-            _ => unimplemented!("Format character '{}'", specifier as char),
         }
-
-        matched_args += 1;
     }
+    set
+}
 
-    matched_args
+/// Consume an integer with an optional sign. `base` 0 means the base is
+/// detected from the prefix, like for `%i`. Returns [None] if there were no
+/// digits.
+fn scanf_integer<S: ScanfSource>(env: &mut Environment, source: &mut S, base: u32) -> Option<i64> {
+    let mut negative = false;
+    if let Some(c @ (b'+' | b'-')) = source.peek(env) {
+        negative = c == b'-';
+        source.advance(env);
+    }
+    let mut base = base;
+    let mut any_digits = false;
+    if base == 0 || base == 16 {
+        if source.peek(env) == Some(b'0') {
+            source.advance(env);
+            any_digits = true;
+            if let Some(b'x' | b'X') = source.peek(env) {
+                source.advance(env);
+                base = 16;
+            } else if base == 0 {
+                base = 8;
+            }
+        } else if base == 0 {
+            base = 10;
+        }
+    }
+    let mut val: i64 = 0;
+    while let Some(digit) = source.peek(env).and_then(|c| (c as char).to_digit(base)) {
+        source.advance(env);
+        val = val.wrapping_mul(base.into()).wrapping_add(digit.into());
+        any_digits = true;
+    }
+    any_digits.then_some(if negative { val.wrapping_neg() } else { val })
 }
 
-fn sscanf(env: &mut Environment, src: ConstPtr<u8>, format: ConstPtr<u8>, args: DotDotDot) -> i32 {
-    log_dbg!(
-        "sscanf({:?} ({:?}), {:?} ({:?}), ...)",
-        src,
-        env.mem.cstr_at_utf8(src),
-        format,
-        env.mem.cstr_at_utf8(format)
-    );
+/// Consume a floating-point number in the same syntax `atof` accepts.
+/// Returns [None] if it could not be parsed.
+fn scanf_float<S: ScanfSource>(env: &mut Environment, source: &mut S) -> Option<f64> {
+    // FIXME: no C99 hexfloat, INF, NAN support
+    fn take_digits<S: ScanfSource>(env: &mut Environment, source: &mut S, number: &mut String) {
+        while let Some(c) = source.peek(env).filter(u8::is_ascii_digit) {
+            source.advance(env);
+            number.push(c as char);
+        }
+    }
 
-    let mut args = args.start();
+    let mut number = String::new();
+    if let Some(c @ (b'+' | b'-')) = source.peek(env) {
+        source.advance(env);
+        number.push(c as char);
+    }
+    take_digits(env, source, &mut number);
+    if source.peek(env) == Some(b'.') {
+        source.advance(env);
+        number.push('.');
+        take_digits(env, source, &mut number);
+    }
+    if let Some(c @ (b'e' | b'E')) = source.peek(env) {
+        source.advance(env);
+        number.push(c as char);
+        if let Some(c @ (b'+' | b'-')) = source.peek(env) {
+            source.advance(env);
+            number.push(c as char);
+        }
+        take_digits(env, source, &mut number);
+    }
+    number.parse().ok()
+}
 
-    let mut src_ptr = src.cast_mut();
+/// Input parsing implementation for the `scanf` function family.
+///
+/// Returns the number of assigned conversions, or [EOF] if the input ended
+/// before the first conversion.
+fn scanf_inner<S: ScanfSource>(
+    env: &mut Environment,
+    source: &mut S,
+    format: ConstPtr<u8>,
+    mut args: VaList,
+) -> i32 {
     let mut format_char_idx = 0;
 
     let mut matched_args = 0;
@@ -538,130 +979,159 @@ fn sscanf(env: &mut Environment, src: ConstPtr<u8>, format: ConstPtr<u8>, args:
         if c == b'\0' {
             break;
         }
-        if c != b'%' {
-            let cc = env.mem.read(src_ptr);
-            if c != cc {
-                return matched_args - 1;
+        if c.is_ascii_whitespace() {
+            // Whitespace in the format matches any amount of whitespace,
+            // including none.
+            scanf_skip_whitespace(env, source);
+            continue;
+        }
+        if c != b'%' || env.mem.read(format + format_char_idx) == b'%' {
+            if c == b'%' {
+                format_char_idx += 1;
+                scanf_skip_whitespace(env, source);
+            }
+            match source.peek(env) {
+                Some(cc) if cc == c => source.advance(env),
+                None if matched_args == 0 => return EOF,
+                _ => break,
             }
-            src_ptr += 1;
             continue;
         }
 
-        let length_modifier = if env.mem.read(format + format_char_idx) == b'h' {
+        let mut width = None;
+        while let c @ b'0'..=b'9' = env.mem.read(format + format_char_idx) {
             format_char_idx += 1;
-            Some(b'h')
-        } else {
-            None
+            width = Some(width.unwrap_or(0) * 10 + GuestUSize::from(c - b'0'));
+        }
+
+        let length_modifier = match env.mem.read(format + format_char_idx) {
+            lm @ (b'h' | b'l') => {
+                format_char_idx += 1;
+                Some(lm)
+            }
+            _ => None,
         };
 
         let specifier = env.mem.read(format + format_char_idx);
         format_char_idx += 1;
 
-        match specifier {
-            b'd' | b'i' => {
-                if specifier == b'i' {
-                    // TODO: hexs and octals
-                    assert_ne!(env.mem.read(src_ptr), b'0');
-                }
+        // All conversions except sets and characters skip leading whitespace.
+        if specifier != b'[' && specifier != b'c' {
+            scanf_skip_whitespace(env, source);
+        }
+        if source.peek(env).is_none() {
+            if matched_args == 0 {
+                return EOF;
+            }
+            break;
+        }
 
+        // %c reads exactly one character by default, other conversions are
+        // unlimited.
+        let default_width = if specifier == b'c' {
+            1
+        } else {
+            GuestUSize::MAX
+        };
+        let source = &mut WidthLimitedSource {
+            source: &mut *source,
+            remaining: width.unwrap_or(default_width),
+        };
+
+        match specifier {
+            b'd' | b'i' | b'u' | b'o' | b'x' | b'X' => {
+                let base = match specifier {
+                    b'd' | b'u' => 10,
+                    b'i' => 0,
+                    b'o' => 8,
+                    _ => 16,
+                };
+                let Some(val) = scanf_integer(env, source, base) else {
+                    break;
+                };
+                log_dbg!("scanf integer '{}'", val);
                 match length_modifier {
-                    Some(lm) => {
-                        match lm {
-                            b'h' => {
-                                // signed short* or unsigned short*
-                                let mut val: i16 = 0;
-                                while let c @ b'0'..=b'9' = env.mem.read(src_ptr) {
-                                    val = val * 10 + (c - b'0') as i16;
-                                    src_ptr += 1;
-                                }
-                                let c_short_ptr: ConstPtr<i16> = args.next(env);
-                                env.mem.write(c_short_ptr.cast_mut(), val);
-                            }
-                            _ => unimplemented!(),
-                        }
+                    Some(b'h') => {
+                        // signed short* or unsigned short*
+                        let c_short_ptr: MutPtr<i16> = args.next(env);
+                        env.mem.write(c_short_ptr, val as i16);
                     }
                     _ => {
-                        let mut val: i32 = 0;
-                        let mut sign = 1;
-                        if env.mem.read(src_ptr) == b'-' {
-                            sign = -1;
-                            src_ptr += 1;
-                        }
-                        while let c @ b'0'..=b'9' = env.mem.read(src_ptr) {
-                            val = val * 10 + (c - b'0') as i32;
-                            src_ptr += 1;
-                        }
-                        val *= sign;
-                        log_dbg!("sscanf i32 '{}'", val);
-                        let c_int_ptr: ConstPtr<i32> = args.next(env);
-                        env.mem.write(c_int_ptr.cast_mut(), val);
+                        // signed int* or unsigned int* (long is the same size)
+                        let c_int_ptr: MutPtr<i32> = args.next(env);
+                        env.mem.write(c_int_ptr, val as i32);
                     }
                 }
             }
-            b'u' => {
-                assert!(length_modifier.is_none());
-                if !env.mem.read(src_ptr).is_ascii_digit() {
+            b'e' | b'f' | b'g' | b'E' | b'G' => {
+                let Some(number) = scanf_float(env, source) else {
                     break;
+                };
+                log_dbg!("scanf float '{}'", number);
+                if length_modifier == Some(b'l') {
+                    let c_f64_ptr: MutPtr<f64> = args.next(env);
+                    env.mem.write(c_f64_ptr, number);
+                } else {
+                    let c_f32_ptr: MutPtr<f32> = args.next(env);
+                    env.mem.write(c_f32_ptr, number as f32);
                 }
-                let mut val: u32 = 0;
-                while let c @ b'0'..=b'9' = env.mem.read(src_ptr) {
-                    val = val * 10 + (c - b'0') as u32;
-                    src_ptr += 1;
-                }
-                log_dbg!("sscanf u32 '{}'", val);
-                let c_int_ptr: ConstPtr<u32> = args.next(env);
-                env.mem.write(c_int_ptr.cast_mut(), val);
-            }
-            b'f' => {
-                let (number, length) = atof_inner(env, src_ptr.cast_const()).unwrap();
-                log_dbg!("sscanf float '{}' len '{}'", number, length);
-                src_ptr += length;
-                let c_f32_ptr: ConstPtr<f32> = args.next(env);
-                env.mem.write(c_f32_ptr.cast_mut(), number as f32);
             }
-            // b'u' => {
-            //     //let x = strtoul(env, src_ptr.cast_const(), Ptr::null(), 10);
-            //     // TODO: do not load whole string!!
-            //     let s = env.mem.cstr_at_utf8(src_ptr).unwrap();
-            //     let s = s.split_whitespace().next().unwrap();
-            //     log!("sscanf u '{}'", s);
-            //     let res = u32::from_str_radix(s, 10).unwrap_or(u32::MAX);
-            //     src_ptr += s.len().try_into().unwrap();
-            //     let c_u32_ptr: ConstPtr<u32> = args.next(env);
-            //     env.mem.write(c_u32_ptr.cast_mut(), res);
-            // }
             b'[' => {
                 assert!(length_modifier.is_none());
-                // TODO: support ranges like [0-9]
-                // [set] case
                 let mut c = env.mem.read(format + format_char_idx);
                 format_char_idx += 1;
-                // TODO: only `not in the set` for a moment
-                assert_eq!(c, b'^');
-                // Build set
-                let mut set: HashSet<u8> = HashSet::new();
-                // TODO: set can contain ']' as well
-                c = env.mem.read(format + format_char_idx);
-                format_char_idx += 1;
-                while c != b']' {
-                    set.insert(c);
+                let negated = c == b'^';
+                if negated {
+                    c = env.mem.read(format + format_char_idx);
+                    format_char_idx += 1;
+                }
+                // A ']' straight after the opening is part of the set.
+                let mut set_chars = Vec::new();
+                loop {
+                    assert_ne!(c, b'\0', "Unterminated scanf set");
+                    set_chars.push(c);
                     c = env.mem.read(format + format_char_idx);
                     format_char_idx += 1;
+                    if c == b']' {
+                        break;
+                    }
                 }
+                let set = scanf_set(&set_chars);
                 let mut dst_ptr: MutPtr<u8> = args.next(env);
-                // Consume `src` while chars are not in the set
-                let mut cc = env.mem.read(src_ptr);
-                src_ptr += 1;
-                // TODO: handle end of src string
-                while !set.contains(&cc) {
+                let start = dst_ptr;
+                // Consume input while chars match the set
+                while let Some(cc) = source.peek(env).filter(|cc| set.contains(cc) != negated) {
+                    source.advance(env);
                     env.mem.write(dst_ptr, cc);
                     dst_ptr += 1;
-                    cc = env.mem.read(src_ptr);
-                    src_ptr += 1;
                 }
-                // we need to backtrack one position
-                src_ptr -= 1;
                 env.mem.write(dst_ptr, b'\0');
+                if dst_ptr == start {
+                    break;
+                }
+            }
+            b's' => {
+                assert!(length_modifier.is_none());
+                let mut dst_ptr: MutPtr<u8> = args.next(env);
+                while let Some(cc) = source.peek(env).filter(|cc| !cc.is_ascii_whitespace()) {
+                    source.advance(env);
+                    env.mem.write(dst_ptr, cc);
+                    dst_ptr += 1;
+                }
+                env.mem.write(dst_ptr, b'\0');
+            }
+            b'c' => {
+                assert!(length_modifier.is_none());
+                // No null terminator is written.
+                let mut dst_ptr: MutPtr<u8> = args.next(env);
+                while let Some(cc) = source.peek(env) {
+                    source.advance(env);
+                    env.mem.write(dst_ptr, cc);
+                    dst_ptr += 1;
+                }
+                if source.remaining != 0 {
+                    break;
+                }
             }
             // TODO: more specifiers
             _ => unimplemented!("Format character '{}'", specifier as char),
@@ -673,64 +1143,44 @@ fn sscanf(env: &mut Environment, src: ConstPtr<u8>, format: ConstPtr<u8>, args:
     matched_args
 }
 
-fn vsscanf(env: &mut Environment, src: ConstPtr<u8>, format: ConstPtr<u8>, arg: VaList) -> i32 {
+fn fscanf(env: &mut Environment, stream: MutPtr<FILE>, format: ConstPtr<u8>, args: DotDotDot) -> i32 {
+    vfscanf(env, stream, format, args.start())
+}
+
+fn vfscanf(env: &mut Environment, stream: MutPtr<FILE>, format: ConstPtr<u8>, arg: VaList) -> i32 {
     log_dbg!(
-        "vsscanf({:?}, {:?} ({:?}), ...)",
-        src,
+        "vfscanf({:?}, {:?} ({:?}), ...)",
+        stream,
         format,
         env.mem.cstr_at_utf8(format)
     );
+    scanf_inner(env, &mut FileSource(stream), format, arg)
+}
 
-    let mut args = arg;
-
-    let mut src_ptr = src.cast_mut();
-    let mut format_char_idx = 0;
-
-    let mut matched_args = 0;
-
-    loop {
-        let c = env.mem.read(format + format_char_idx);
-        format_char_idx += 1;
-
-        if c == b'\0' {
-            break;
-        }
-        if c != b'%' {
-            let cc = env.mem.read(src_ptr);
-            if c != cc {
-                return matched_args - 1;
-            }
-            src_ptr += 1;
-            continue;
-        }
-
-        let specifier = env.mem.read(format + format_char_idx);
-        format_char_idx += 1;
+fn scanf(env: &mut Environment, format: ConstPtr<u8>, args: DotDotDot) -> i32 {
+    vscanf(env, format, args.start())
+}
 
-        match specifier {
-            b'd' => {
-                let mut val: i32 = 0;
-                while let c @ b'0'..=b'9' = env.mem.read(src_ptr) {
-                    val = val * 10 + (c - b'0') as i32;
-                    src_ptr += 1;
-                }
-                let c_int_ptr: ConstPtr<i32> = args.next(env);
-                env.mem.write(c_int_ptr.cast_mut(), val);
-            }
-            b'g' | b'f' => {
-                let (number, length) = atof_inner(env, src_ptr.cast_const()).unwrap();
-                src_ptr += length;
-                let c_f32_ptr: ConstPtr<f32> = args.next(env);
-                env.mem.write(c_f32_ptr.cast_mut(), number as f32);
-            }
-            // TODO: more specifiers
-            _ => unimplemented!("Format character '{}'", specifier as char),
-        }
+fn vscanf(env: &mut Environment, format: ConstPtr<u8>, arg: VaList) -> i32 {
+    let stdin = env.mem.alloc_and_write(FILE { fd: STDIN_FILENO });
+    let res = vfscanf(env, stdin, format, arg);
+    env.mem.free(stdin.cast());
+    res
+}
 
-        matched_args += 1;
-    }
+fn sscanf(env: &mut Environment, src: ConstPtr<u8>, format: ConstPtr<u8>, args: DotDotDot) -> i32 {
+    vsscanf(env, src, format, args.start())
+}
 
-    matched_args
+fn vsscanf(env: &mut Environment, src: ConstPtr<u8>, format: ConstPtr<u8>, arg: VaList) -> i32 {
+    log_dbg!(
+        "vsscanf({:?} ({:?}), {:?} ({:?}), ...)",
+        src,
+        env.mem.cstr_at_utf8(src),
+        format,
+        env.mem.cstr_at_utf8(format)
+    );
+    scanf_inner(env, &mut StringSource(src), format, arg)
 }
 
 fn fprintf(
@@ -746,6 +1196,9 @@ fn fprintf(
         env.mem.cstr_at_utf8(format)
     );
 
+    if !orient(env, stream, /* wide: */ false) {
+        return -1;
+    }
     let res = printf_inner::<false, _>(env, |mem, idx| mem.read(format + idx), args.start());
     // TODO: I/O error handling
     match env.mem.read(stream).fd {
@@ -774,6 +1227,9 @@ fn vfprintf(
         env.mem.cstr_at_utf8(format)
     );
 
+    if !orient(env, stream, /* wide: */ false) {
+        return -1;
+    }
     let res = printf_inner::<false, _>(env, |mem, idx| mem.read(format + idx), arg);
     // TODO: I/O error handling
     match env.mem.read(stream).fd {
@@ -786,6 +1242,9 @@ fn vfprintf(
 
 pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(fscanf(_, _, _)),
+    export_c_func!(vfscanf(_, _, _)),
+    export_c_func!(scanf(_, _)),
+    export_c_func!(vscanf(_, _)),
     export_c_func!(sscanf(_, _, _)),
     export_c_func!(vsscanf(_, _, _)),
     export_c_func!(snprintf(_, _, _, _)),
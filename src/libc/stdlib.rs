@@ -7,13 +7,22 @@
 
 use crate::abi::{CallFromHost, GuestFunction};
 use crate::dyld::{export_c_func, FunctionExports};
-use crate::mem::{ConstPtr, ConstVoidPtr, GuestUSize, MutPtr, MutVoidPtr, Ptr};
-use crate::{Environment, export_c_func2};
+use crate::mem::{
+    guest_size_of, ConstPtr, ConstVoidPtr, GuestUSize, MutPtr, MutVoidPtr, PointerStatus, Ptr,
+    SafeRead,
+};
+use crate::{export_c_func2, impl_GuestRet_for_large_struct, Environment};
 use std::collections::HashMap;
-use std::io::Write;
 use std::str::FromStr;
-use crate::libc::posix_io::getcwd;
-use crate::libc::string::{strlen, strcpy};
+use std::time::Duration;
+use crate::frameworks::foundation::ns_locale::get_preferred_languages;
+use crate::fs::{GuestPathBuf, PathIntent};
+use crate::libc::errno::{set_errno, EEXIST, EILSEQ, EINVAL, ENOMEM, ERANGE};
+use crate::libc::posix_io::{
+    open_direct, resolve_path_at, FileDescriptor, O_CREAT, O_EXCL, O_RDWR,
+};
+use crate::libc::pwd::MOBILE_USER_NAME;
+use crate::libc::unistd::{CS_PATH, MOBILE_UID};
 use crate::libc::wchar::{wchar_t, wmemcpy};
 
 pub mod qsort;
@@ -23,9 +32,70 @@ pub struct State {
     rand: u32,
     random: u32,
     arc4random: u32,
-    env: HashMap<Vec<u8>, MutPtr<u8>>,
+    /// Environment variables, see [populate_default_env].
+    env: HashMap<Vec<u8>, EnvVar>,
+    env_populated: bool,
+    /// Functions registered with `atexit` or `__cxa_atexit`, in the order they
+    /// were registered.
+    atexit_handlers: Vec<AtExitHandler>,
+    locale: LocaleState,
 }
 
+/// The value of an environment variable.
+#[derive(Copy, Clone)]
+struct EnvVar {
+    value: MutPtr<u8>,
+    /// Whether `value` is a heap allocation belonging to the environment, as
+    /// opposed to part of a string the app passed to `putenv`, which the app
+    /// still owns.
+    owned: bool,
+}
+
+/// A function to call when the app exits.
+#[derive(Copy, Clone)]
+pub struct AtExitHandler {
+    /// `void (*func)(void)` if `arg` is [None], `void (*func)(void *)`
+    /// otherwise.
+    pub func: GuestFunction,
+    pub arg: Option<MutVoidPtr>,
+    /// The `__dso_handle` of the shared object that registered the function,
+    /// for `__cxa_finalize`. Null for `atexit`.
+    pub dso: MutVoidPtr,
+}
+
+/// Register a function to be called by [run_atexit_handlers].
+pub fn register_atexit_handler(env: &mut Environment, handler: AtExitHandler) {
+    env.libc_state.stdlib.atexit_handlers.push(handler);
+}
+
+/// Call and unregister the `atexit` and `__cxa_atexit` handlers in reverse
+/// order of registration, either all of them or only those for one shared
+/// object.
+///
+/// Each handler is unregistered before it is called, so every handler runs
+/// exactly once, even if a handler calls `exit()` (which then carries on with
+/// the remaining handlers) or registers more handlers (which are run too).
+pub fn run_atexit_handlers(env: &mut Environment, dso: Option<MutVoidPtr>) {
+    loop {
+        let handlers = &mut env.libc_state.stdlib.atexit_handlers;
+        let Some(idx) = handlers
+            .iter()
+            .rposition(|handler| dso.is_none_or(|dso| dso == handler.dso))
+        else {
+            break;
+        };
+        let AtExitHandler { func, arg, .. } = handlers.remove(idx);
+        log_dbg!("Calling atexit handler {:?} with {:?}", func, arg);
+        match arg {
+            None => () = func.call_from_host(env, ()),
+            Some(arg) => () = func.call_from_host(env, (arg,)),
+        }
+    }
+}
+
+/// Alignment used by `valloc()`.
+const PAGE_SIZE: GuestUSize = 4096;
+
 // Sizes of zero are implementation-defined. macOS will happily give you back
 // an allocation for any of these, so presumably iPhone OS does too.
 // (touchHLE's allocator will round up allocations to at least 16 bytes.)
@@ -35,14 +105,45 @@ fn malloc(env: &mut Environment, size: GuestUSize) -> MutVoidPtr {
 }
 
 fn calloc(env: &mut Environment, count: GuestUSize, size: GuestUSize) -> MutVoidPtr {
-    let total = size.checked_mul(count).unwrap();
-    env.mem.alloc(total)
+    let Some(total) = size.checked_mul(count) else {
+        set_errno(env, ENOMEM);
+        return Ptr::null();
+    };
+    let ptr = env.mem.alloc(total);
+    // Freed memory is zeroed, but apps can write past the end of their
+    // allocations, so a reused chunk might not be clean.
+    env.mem.bytes_at_mut(ptr.cast(), total).fill(0);
+    ptr
+}
+
+fn posix_memalign(
+    env: &mut Environment,
+    memptr: MutPtr<MutVoidPtr>,
+    alignment: GuestUSize,
+    size: GuestUSize,
+) -> i32 {
+    // errno isn't set, the error is only returned.
+    if !alignment.is_power_of_two() || alignment % guest_size_of::<MutVoidPtr>() != 0 {
+        return EINVAL;
+    }
+    let ptr = env.mem.alloc_aligned(size, alignment);
+    env.mem.write(memptr, ptr);
+    0
+}
+
+fn valloc(env: &mut Environment, size: GuestUSize) -> MutVoidPtr {
+    env.mem.alloc_aligned(size, PAGE_SIZE)
 }
 
 fn realloc(env: &mut Environment, ptr: MutVoidPtr, size: GuestUSize) -> MutVoidPtr {
     if ptr.is_null() {
         return malloc(env, size);
     }
+    if !check_heap_pointer(env, "realloc", ptr) {
+        // The old contents can't be copied without knowing their size.
+        log!("Warning: treating realloc() of {:?} like malloc()", ptr);
+        return malloc(env, size);
+    }
     env.mem.realloc(ptr, size)
 }
 
@@ -51,16 +152,39 @@ fn free(env: &mut Environment, ptr: MutVoidPtr) {
         // "If ptr is a NULL pointer, no operation is performed."
         return;
     }
+    if !check_heap_pointer(env, "free", ptr) {
+        return;
+    }
     env.mem.free(ptr);
 }
 
+/// Check that a pointer passed to `free()` or `realloc()` is the start of an
+/// allocation. If it isn't, that's reported and [false] is returned.
+fn check_heap_pointer(env: &mut Environment, function: &str, ptr: MutVoidPtr) -> bool {
+    let problem = match env.mem.validate(ptr) {
+        PointerStatus::Exact => return true,
+        PointerStatus::Interior(base) => format!("points inside the allocation at {:#x}", base),
+        PointerStatus::Unknown => "is not part of any allocation".to_string(),
+    };
+    crate::backtrace::report_invalid_pointer(
+        env,
+        format_args!("{}() called with {:?}, which {}", function, ptr, problem),
+    );
+    false
+}
+
 fn atexit(
-    _env: &mut Environment,
+    env: &mut Environment,
     func: GuestFunction, // void (*func)(void)
 ) -> i32 {
-    // TODO: when this is implemented, make sure it's properly compatible with
-    // __cxa_atexit.
-    log!("TODO: atexit({:?}) (unimplemented)", func);
+    register_atexit_handler(
+        env,
+        AtExitHandler {
+            func,
+            arg: None,
+            dso: Ptr::null(),
+        },
+    );
     0 // success
 }
 
@@ -78,27 +202,114 @@ fn skip_whitespace(env: &mut Environment, s: ConstPtr<u8>) -> ConstPtr<u8> {
     start
 }
 
-fn atoi(env: &mut Environment, s: ConstPtr<u8>) -> i32 {
-    // atoi() doesn't work with a null-terminated string, instead it stops
-    // once it hits something that's not a digit, so we have to do some parsing
-    // ourselves.
-    let start = skip_whitespace(env, s);
-    let mut len = 0;
-    let maybe_sign = env.mem.read(start + len);
-    if maybe_sign == b'+' || maybe_sign == b'-' || maybe_sign.is_ascii_digit() {
-        len += 1;
+/// Parsing shared by [atoi], [atol] and [atoll], which only differ in the
+/// width of the result.
+fn parse_decimal(env: &mut Environment, s: ConstPtr<u8>) -> i64 {
+    // These functions don't need a null-terminated string, instead they stop
+    // once they hit something that's not a digit.
+    let mut s = skip_whitespace(env, s);
+    let negative = match env.mem.read(s) {
+        b'-' => {
+            s += 1;
+            true
+        }
+        b'+' => {
+            s += 1;
+            false
+        }
+        _ => false,
+    };
+    // Conveniently, overflow is undefined, so wrapping is as valid a result as
+    // any.
+    let mut res: i64 = 0;
+    loop {
+        let c = env.mem.read(s);
+        if !c.is_ascii_digit() {
+            break;
+        }
+        res = res.wrapping_mul(10).wrapping_add((c - b'0').into());
+        s += 1;
     }
-    while env.mem.read(start + len).is_ascii_digit() {
-        len += 1;
+    if negative {
+        res.wrapping_neg()
+    } else {
+        res
     }
+}
 
-    let s = std::str::from_utf8(env.mem.bytes_at(start, len)).unwrap();
-    // conveniently, overflow is undefined, so 0 is as valid a result as any
-    s.parse().unwrap_or(0)
+fn atoi(env: &mut Environment, s: ConstPtr<u8>) -> i32 {
+    parse_decimal(env, s) as i32
 }
 
 fn atol(env: &mut Environment, s: ConstPtr<u8>) -> i32 {
-    atoi(env, s)
+    parse_decimal(env, s) as i32
+}
+
+fn atoll(env: &mut Environment, s: ConstPtr<u8>) -> i64 {
+    parse_decimal(env, s)
+}
+
+// The absolute value of the most negative integer is undefined behaviour in C.
+// Apple's implementation returns it unchanged, so wrap rather than panicking.
+
+fn abs(_env: &mut Environment, i: i32) -> i32 {
+    i.wrapping_abs()
+}
+
+fn labs(_env: &mut Environment, i: i32) -> i32 {
+    i.wrapping_abs()
+}
+
+fn llabs(_env: &mut Environment, i: i64) -> i64 {
+    i.wrapping_abs()
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Debug)]
+#[repr(C, packed)]
+struct div_t {
+    quot: i32,
+    rem: i32,
+}
+unsafe impl SafeRead for div_t {}
+impl_GuestRet_for_large_struct!(div_t);
+
+#[allow(non_camel_case_types)]
+#[derive(Debug)]
+#[repr(C, packed)]
+struct lldiv_t {
+    quot: i64,
+    rem: i64,
+}
+unsafe impl SafeRead for lldiv_t {}
+impl_GuestRet_for_large_struct!(lldiv_t);
+
+/// Rust's `/` and `%` truncate toward zero like C's. Division by zero and
+/// overflow are undefined in C, so rather than panicking, this does what the
+/// ARM `sdiv` instruction does: a zero divisor gives a zero quotient, and
+/// overflow wraps.
+macro_rules! div_impl {
+    ($numer:expr, $denom:expr) => {
+        match ($numer, $denom) {
+            (numer, 0) => (0, numer),
+            (numer, denom) => (numer.wrapping_div(denom), numer.wrapping_rem(denom)),
+        }
+    };
+}
+
+fn div(_env: &mut Environment, numer: i32, denom: i32) -> div_t {
+    let (quot, rem) = div_impl!(numer, denom);
+    div_t { quot, rem }
+}
+
+fn ldiv(_env: &mut Environment, numer: i32, denom: i32) -> div_t {
+    let (quot, rem) = div_impl!(numer, denom);
+    div_t { quot, rem }
+}
+
+fn lldiv(_env: &mut Environment, numer: i64, denom: i64) -> lldiv_t {
+    let (quot, rem) = div_impl!(numer, denom);
+    lldiv_t { quot, rem }
 }
 
 fn atof(env: &mut Environment, s: ConstPtr<u8>) -> f64 {
@@ -120,6 +331,11 @@ fn prng(state: u32) -> u32 {
 
 const RAND_MAX: i32 = i32::MAX;
 const ULONG_MAX: u32 = u32::MAX;
+const LONG_MAX: i32 = i32::MAX;
+const LONG_MIN: i32 = i32::MIN;
+const ULLONG_MAX: u64 = u64::MAX;
+const LLONG_MAX: i64 = i64::MAX;
+const LLONG_MIN: i64 = i64::MIN;
 
 fn srand(env: &mut Environment, seed: u32) {
     env.libc_state.stdlib.rand = seed;
@@ -128,6 +344,13 @@ fn rand(env: &mut Environment) -> i32 {
     env.libc_state.stdlib.rand = prng(env.libc_state.stdlib.rand);
     (env.libc_state.stdlib.rand as i32) & RAND_MAX
 }
+/// Reentrant `rand()`: the state lives in guest memory rather than being
+/// global, so each caller gets its own sequence.
+fn rand_r(env: &mut Environment, seedp: MutPtr<u32>) -> i32 {
+    let state = prng(env.mem.read(seedp));
+    env.mem.write(seedp, state);
+    (state as i32) & RAND_MAX
+}
 
 // BSD's "better" random number generator, with an implementation that is not
 // actually better.
@@ -144,15 +367,65 @@ fn arc4random(env: &mut Environment) -> u32 {
     env.libc_state.stdlib.arc4random
 }
 
+/// Fill in the environment variables that iPhone OS gives every app process,
+/// if that hasn't happened yet. Like the ones set with `setenv`, their values
+/// are heap allocations owned by the environment, so they stay put until
+/// replaced or removed.
+fn populate_default_env(env: &mut Environment) {
+    if env.libc_state.stdlib.env_populated {
+        return;
+    }
+    env.libc_state.stdlib.env_populated = true;
+
+    // The home directory is the same as in the user database (see pwd.rs).
+    let home = env.fs.home_directory();
+    let home_str = home.as_str().as_bytes().to_vec();
+    let tmp_dir = format!("{}/", env.fs.temporary_directory().as_str()).into_bytes();
+    let text_encoding = format!("{:#X}:0:0", MOBILE_UID).into_bytes();
+    let defaults: [(&[u8], Vec<u8>); 8] = [
+        (b"HOME", home_str.clone()),
+        (b"CFFIXED_USER_HOME", home_str),
+        (b"TMPDIR", tmp_dir),
+        (b"PATH", CS_PATH.to_vec()),
+        (b"USER", MOBILE_USER_NAME.to_vec()),
+        (b"LOGNAME", MOBILE_USER_NAME.to_vec()),
+        (b"SHELL", b"/bin/sh".to_vec()),
+        (b"__CF_USER_TEXT_ENCODING", text_encoding),
+    ];
+    for (name, value) in defaults {
+        let value = env.mem.alloc_and_write_cstr(&value);
+        let var = EnvVar { value, owned: true };
+        env.libc_state.stdlib.env.insert(name.to_vec(), var);
+    }
+
+    // Variables from the --env= option, which can replace the defaults.
+    for (name, value) in env.options.guest_env.clone() {
+        let value = env.mem.alloc_and_write_cstr(value.as_bytes());
+        let var = EnvVar { value, owned: true };
+        replace_env_var(env, name.into_bytes(), Some(var));
+    }
+}
+
+/// Replace or remove an environment variable, freeing the old value if the
+/// environment owns it.
+fn replace_env_var(env: &mut Environment, name: Vec<u8>, new: Option<EnvVar>) {
+    let old = match new {
+        Some(new) => env.libc_state.stdlib.env.insert(name, new),
+        None => env.libc_state.stdlib.env.remove(&name),
+    };
+    if let Some(EnvVar { value, owned: true }) = old {
+        env.mem.free(value.cast());
+    }
+}
+
 fn getenv(env: &mut Environment, name: ConstPtr<u8>) -> MutPtr<u8> {
     let name_cstr = env.mem.cstr_at(name);
     if name_cstr == b"MONO_LOG_LEVEL" {
         return env.mem.alloc_and_write_cstr(b"debug");
     }
-    // TODO: Provide all the system environment variables an app might expect to
-    // find. Currently the only environment variables that can be found are
-    // those put there by the app (Crash Bandicoot Nitro Kart 3D uses this).
-    let Some(&value) = env.libc_state.stdlib.env.get(name_cstr) else {
+    populate_default_env(env);
+    let name_cstr = env.mem.cstr_at(name); // reborrow
+    let Some(&EnvVar { value, .. }) = env.libc_state.stdlib.env.get(name_cstr) else {
         log!(
             "Warning: getenv() for {:?} ({:?}) unhandled",
             name,
@@ -171,35 +444,96 @@ fn getenv(env: &mut Environment, name: ConstPtr<u8>) -> MutPtr<u8> {
     value
 }
 fn setenv(env: &mut Environment, name: ConstPtr<u8>, value: ConstPtr<u8>, overwrite: i32) -> i32 {
+    populate_default_env(env);
     let name_cstr = env.mem.cstr_at(name);
-    if let Some(&existing) = env.libc_state.stdlib.env.get(name_cstr) {
-        if overwrite == 0 {
-            return 0; // success
-        }
-        env.mem.free(existing.cast());
-    };
+    if overwrite == 0 && env.libc_state.stdlib.env.contains_key(name_cstr) {
+        return 0; // success
+    }
+    let name_cstr = name_cstr.to_vec();
     let value = super::string::strdup(env, value);
-    let name_cstr = env.mem.cstr_at(name); // reborrow
-    env.libc_state.stdlib.env.insert(name_cstr.to_vec(), value);
     log_dbg!(
         "Stored new value {:?} ({:?}) for environment variable {:?}",
         value,
         env.mem.cstr_at_utf8(value),
-        std::str::from_utf8(name_cstr),
+        std::str::from_utf8(&name_cstr),
     );
+    replace_env_var(env, name_cstr, Some(EnvVar { value, owned: true }));
     0 // success
 }
 fn unsetenv(env: &mut Environment, name: ConstPtr<u8>) -> i32 {
-    let name_cstr = env.mem.cstr_at(name);
-    assert!(env.libc_state.stdlib.env.get(name_cstr).is_none());
+    populate_default_env(env);
+    let name_cstr = env.mem.cstr_at(name).to_vec();
+    replace_env_var(env, name_cstr, None);
+    0 // success
+}
+/// Unlike `setenv`, this doesn't copy anything: the string becomes part of the
+/// environment, so the app can change the value by modifying it.
+fn putenv(env: &mut Environment, string: MutPtr<u8>) -> i32 {
+    populate_default_env(env);
+    let string_cstr = env.mem.cstr_at(string);
+    let Some(name_len) = string_cstr.iter().position(|&c| c == b'=') else {
+        // Darwin treats this like unsetenv().
+        let name = string_cstr.to_vec();
+        replace_env_var(env, name, None);
+        return 0; // success
+    };
+    if name_len == 0 {
+        set_errno(env, EINVAL);
+        return -1;
+    }
+    let name = string_cstr[..name_len].to_vec();
+    let value = string + (name_len as GuestUSize + 1);
+    log_dbg!(
+        "putenv({:?}): {:?} = {:?}",
+        string,
+        std::str::from_utf8(&name),
+        env.mem.cstr_at_utf8(value),
+    );
+    replace_env_var(env, name, Some(EnvVar { value, owned: false }));
     0 // success
 }
 
-fn exit(_env: &mut Environment, exit_code: i32) {
+fn exit(env: &mut Environment, exit_code: i32) {
     echo!("App called exit(), exiting.");
+    run_atexit_handlers(env, None);
+    exit_process(env, exit_code);
+}
+
+/// The part of `exit()` after the `atexit` handlers have run: close the app's
+/// streams and files, so nothing written to them is lost, close the window,
+/// then terminate.
+pub fn exit_process(env: &mut Environment, exit_code: i32) -> ! {
+    if env.options.dump_open_files {
+        crate::libc::posix_io::dump_open_files(env);
+    }
+    crate::libc::stdio::close_all_streams(env);
+    crate::libc::posix_io::close_all(env);
+    // Destroying the window now, rather than leaving it to the OS, avoids it
+    // lingering on some platforms.
+    env.window = None;
     std::process::exit(exit_code);
 }
 
+/// `_exit()` (from `unistd.h`) and `_Exit()`: terminate immediately, without
+/// running `atexit` handlers or closing streams.
+fn _exit(_env: &mut Environment, exit_code: i32) {
+    echo!("App called _exit(), exiting.");
+    std::process::exit(exit_code);
+}
+fn _Exit(env: &mut Environment, exit_code: i32) {
+    _exit(env, exit_code)
+}
+
+/// `abort()`. This is also where failed assertions and uncaught exceptions end
+/// up. It never returns.
+pub fn abort(env: &mut Environment) {
+    echo!("App called abort(), exiting.");
+    crate::backtrace::print_guest_backtrace(env);
+    crate::libc::signal::handle_abort(env);
+    // Same exit status a shell would report for a process killed by SIGABRT.
+    std::process::exit(128 + crate::libc::signal::SIGABRT);
+}
+
 fn bsearch(
     env: &mut Environment,
     key: ConstVoidPtr,
@@ -237,7 +571,7 @@ fn bsearch(
 }
 
 fn strtod(env: &mut Environment, nptr: ConstPtr<u8>, endptr: MutPtr<MutPtr<u8>>) -> f64 {
-    log!("strtod nptr {}", env.mem.cstr_at_utf8(nptr).unwrap());
+    log!("strtod nptr {}", String::from_utf8_lossy(env.mem.cstr_at(nptr)));
     let (d, len) = atof_inner(env, nptr).unwrap_or((0.0, 0));
     if !endptr.is_null() {
         env.mem.write(endptr, (nptr + len).cast_mut());
@@ -253,123 +587,601 @@ fn strtof(env: &mut Environment, nptr: ConstPtr<u8>, endptr: MutPtr<ConstPtr<u8>
     number as f32
 }
 
-fn realpath(env: &mut Environment, file_name: ConstPtr<u8>, resolve_name: MutPtr<u8>) -> MutPtr<u8> {
-    assert!(!resolve_name.is_null());
+fn realpath(
+    env: &mut Environment,
+    file_name: ConstPtr<u8>,
+    resolve_name: MutPtr<u8>,
+) -> MutPtr<u8> {
+    if file_name.is_null() {
+        set_errno(env, EINVAL);
+        return Ptr::null();
+    }
+    // The guest filesystem has no symlinks, so this only has to make the path
+    // absolute, collapse `.`, `..` and empty components, and check that the
+    // result exists.
+    let Some(resolved) = resolve_path_at(env, file_name, PathIntent::Read) else {
+        log_dbg!("realpath({:?}) => NULL", file_name);
+        return Ptr::null();
+    };
+    log_dbg!("realpath({:?}) => {:?}", file_name, resolved);
 
-    let file_name_str = env.mem.cstr_at_utf8(file_name).unwrap();
-    log_dbg!("realpath file name {}", file_name_str);
-    // assert!(!file_name_str.contains("/.") && file_name_str.as_bytes()[0] != b'.');
-    if file_name_str.as_bytes()[0] == b'/' {
-        strcpy(env, resolve_name, file_name);
-        return resolve_name;
+    if resolve_name.is_null() {
+        // Darwin extension: allocate the result.
+        return env.mem.alloc_and_write_cstr(resolved.as_str().as_bytes());
     }
+    let resolved = resolved.as_str().as_bytes();
+    let len: GuestUSize = resolved.len().try_into().unwrap();
+    env.mem
+        .bytes_at_mut(resolve_name, len)
+        .copy_from_slice(resolved);
+    env.mem.write(resolve_name + len, b'\0');
+    resolve_name
+}
 
-    let cwd_ptr = getcwd(env, Ptr::null(), 0);
-    let cwd_len = strlen(env, cwd_ptr.cast_const());
+/// Characters used to fill in the `X`s of a `mktemp()` template.
+const TEMP_NAME_CHARS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
 
-    strcpy(env, resolve_name, cwd_ptr.cast_const());
-    env.mem.write(resolve_name + cwd_len, b'/');
-    strcpy(env, resolve_name + cwd_len + 1, file_name);
+/// Replace the trailing `X`s of a `mktemp()`-style template in-place with
+/// random characters, until it names a file that doesn't exist yet. Returns the
+/// resolved path of that file, or sets errno and returns [None].
+fn fill_temp_template(env: &mut Environment, template: MutPtr<u8>) -> Option<GuestPathBuf> {
+    if template.is_null() {
+        set_errno(env, EINVAL);
+        return None;
+    }
+    let bytes = env.mem.cstr_at(template);
+    let x_count = bytes.iter().rev().take_while(|&&c| c == b'X').count();
+    let x_start: GuestUSize = (bytes.len() - x_count).try_into().unwrap();
+    if x_count < 6 {
+        log!(
+            "Warning: template {:?} doesn't end with at least six X characters, failing with EINVAL",
+            String::from_utf8_lossy(bytes)
+        );
+        set_errno(env, EINVAL);
+        return None;
+    }
 
-    let resolve_name_str = env.mem.cstr_at_utf8(resolve_name).unwrap();
-    log_dbg!("realpath resolve name {}", resolve_name_str);
+    // With six or more random characters, running out of attempts means
+    // something else is wrong.
+    for _ in 0..100 {
+        for i in 0..x_count as GuestUSize {
+            let c = TEMP_NAME_CHARS[arc4random(env) as usize % TEMP_NAME_CHARS.len()];
+            env.mem.write(template + x_start + i, c);
+        }
+        let path = resolve_path_at(env, template.cast_const(), PathIntent::Write)?;
+        if !env.fs.exists(&path) {
+            return Some(path);
+        }
+    }
+    set_errno(env, EEXIST);
+    None
+}
 
-    resolve_name
+fn mktemp(env: &mut Environment, template: MutPtr<u8>) -> MutPtr<u8> {
+    let res = match fill_temp_template(env, template) {
+        Some(_) => template,
+        None => Ptr::null(),
+    };
+    log_dbg!("mktemp({:?}) => {:?}", template, res);
+    res
+}
+
+fn mkstemp(env: &mut Environment, template: MutPtr<u8>) -> FileDescriptor {
+    if fill_temp_template(env, template).is_none() {
+        return -1;
+    }
+    // TODO: the file should be created with mode 0600
+    open_direct(env, template.cast_const(), O_RDWR | O_CREAT | O_EXCL)
 }
 
 fn sched_yield(env: &mut Environment) -> i32 {
+    // Sleeping for zero time gives every other runnable thread a turn before
+    // this one is resumed.
+    env.sleep(Duration::ZERO, true);
     0
 }
 
-fn mbstowcs(env: &mut Environment, pwcs: MutPtr<wchar_t>, s: ConstPtr<u8>, n: GuestUSize) -> GuestUSize {
-    // TODO: assert C locale
-    let size = strlen(env, s);
-    let to_write = size.min(n);
-    for i in 0..to_write {
-        let c = env.mem.read(s + i);
-        env.mem.write(pwcs + i, c as wchar_t);
-    }
-    if to_write < n {
-        env.mem.write(pwcs + to_write, wchar_t::default());
+/// Length of the UTF-8 sequence that starts with the byte `first`, or [None]
+/// if no valid sequence can start with it.
+pub(super) fn mb_char_len(first: u8) -> Option<GuestUSize> {
+    match first {
+        0x00..=0x7f => Some(1),
+        0xc2..=0xdf => Some(2),
+        0xe0..=0xef => Some(3),
+        0xf0..=0xf4 => Some(4),
+        _ => None,
     }
-    to_write
 }
 
-// size_t
-//      wcstombs(char *restrict s, const wchar_t *restrict pwcs, size_t n);
-fn wcstombs(env: &mut Environment, s: ConstPtr<u8>, pwcs: MutPtr<wchar_t>, n: GuestUSize) -> GuestUSize {
+/// Decode one multibyte character from at most `n` bytes at `s`. The locale is
+/// treated as UTF-8, like on iPhone OS. Returns the character and the number of
+/// bytes it took (0 for the null character), or [None] if the sequence is
+/// invalid or incomplete. Shared by [mbtowc], [mbstowcs] and the wide
+/// character stream functions in [super::wchar].
+pub(super) fn decode_mb_char(
+    env: &Environment,
+    s: ConstPtr<u8>,
+    n: GuestUSize,
+) -> Option<(wchar_t, GuestUSize)> {
     if n == 0 {
+        return None;
+    }
+    let first = env.mem.read(s);
+    if first == 0 {
+        return Some((0, 0));
+    }
+    let len = mb_char_len(first)?;
+    if len > n {
+        return None;
+    }
+    let mut bytes = [first, 0, 0, 0];
+    for i in 1..len {
+        let byte = env.mem.read(s + i);
+        // Stop at anything that isn't a continuation byte, including the null
+        // terminator, so nothing past the end of the string is read.
+        if byte & 0xc0 != 0x80 {
+            return None;
+        }
+        bytes[i as usize] = byte;
+    }
+    // This rejects overlong encodings, surrogates and values above U+10FFFF.
+    let c = std::str::from_utf8(&bytes[..len as usize])
+        .ok()?
+        .chars()
+        .next()?;
+    Some((c as wchar_t, len))
+}
+
+fn mbtowc(env: &mut Environment, pwc: MutPtr<wchar_t>, s: ConstPtr<u8>, n: GuestUSize) -> i32 {
+    if s.is_null() {
+        // UTF-8 has no shift states.
         return 0;
     }
-    let x = env.mem.wcstr_at(pwcs);
-    let len: GuestUSize = x.bytes().len() as GuestUSize;
-    log!("wcstombs '{}', len {}, n {}", x, len, n);
-    env.mem.bytes_at_mut(s.cast_mut(), n).write(x.as_bytes()).unwrap();
-    if len < n {
-        env.mem.write((s + len).cast_mut(), b'\0');
+    let Some((wc, len)) = decode_mb_char(env, s, n) else {
+        set_errno(env, EILSEQ);
+        return -1;
+    };
+    if !pwc.is_null() {
+        env.mem.write(pwc, wc);
+    }
+    len as i32
+}
+
+fn mblen(env: &mut Environment, s: ConstPtr<u8>, n: GuestUSize) -> i32 {
+    mbtowc(env, Ptr::null(), s, n)
+}
+
+/// Encode a wide character as a UTF-8 multibyte character in `buf`, or return
+/// [None] if it isn't a Unicode scalar value. Shared by [wctomb] and
+/// [wcstombs].
+fn encode_mb_char(wc: wchar_t, buf: &mut [u8; 4]) -> Option<&[u8]> {
+    let c = u32::try_from(wc).ok().and_then(char::from_u32)?;
+    Some(c.encode_utf8(buf).as_bytes())
+}
+
+fn wctomb(env: &mut Environment, s: MutPtr<u8>, wc: wchar_t) -> i32 {
+    if s.is_null() {
+        // UTF-8 has no shift states.
+        return 0;
+    }
+    let mut buf = [0u8; 4];
+    let Some(bytes) = encode_mb_char(wc, &mut buf) else {
+        set_errno(env, EILSEQ);
+        return -1;
+    };
+    let len: GuestUSize = bytes.len().try_into().unwrap();
+    env.mem.bytes_at_mut(s, len).copy_from_slice(bytes);
+    len as i32
+}
+
+/// The longest UTF-8 sequence, see [decode_mb_char].
+const MB_LEN_MAX: GuestUSize = 4;
+
+fn mbstowcs(
+    env: &mut Environment,
+    pwcs: MutPtr<wchar_t>,
+    s: ConstPtr<u8>,
+    n: GuestUSize,
+) -> GuestUSize {
+    // If there's no output buffer, the length of the result is calculated.
+    let mut count = 0;
+    let mut offset = 0;
+    while pwcs.is_null() || count < n {
+        let Some((wc, len)) = decode_mb_char(env, s + offset, MB_LEN_MAX) else {
+            set_errno(env, EILSEQ);
+            return GuestUSize::MAX; // (size_t)-1
+        };
+        if !pwcs.is_null() {
+            env.mem.write(pwcs + count, wc);
+        }
+        if wc == 0 {
+            break;
+        }
+        count += 1;
+        offset += len;
+    }
+    count
+}
+
+fn wcstombs(
+    env: &mut Environment,
+    s: MutPtr<u8>,
+    pwcs: ConstPtr<wchar_t>,
+    n: GuestUSize,
+) -> GuestUSize {
+    // If there's no output buffer, the length of the result is calculated.
+    let mut written = 0;
+    let mut i = 0;
+    loop {
+        let wc = env.mem.read(pwcs + i);
+        if wc == 0 {
+            if !s.is_null() && written < n {
+                env.mem.write(s + written, b'\0');
+            }
+            return written;
+        }
+        let mut buf = [0u8; 4];
+        let Some(bytes) = encode_mb_char(wc, &mut buf) else {
+            set_errno(env, EILSEQ);
+            return GuestUSize::MAX; // (size_t)-1
+        };
+        let len: GuestUSize = bytes.len().try_into().unwrap();
+        if !s.is_null() {
+            // A character that doesn't fit is not written partially.
+            if len > n - written {
+                return written;
+            }
+            env.mem
+                .bytes_at_mut(s + written, len)
+                .copy_from_slice(bytes);
+        }
+        written += len;
+        i += 1;
     }
-    len
 }
 
-fn setlocale(env: &mut Environment, _category: i32, locale: ConstPtr<u8>) -> MutPtr<u8> {
-    // assert_eq!(category, 0); // LC_ALL
+const LC_ALL: i32 = 0;
+const LC_COLLATE: i32 = 1;
+const LC_CTYPE: i32 = 2;
+const LC_MONETARY: i32 = 3;
+const LC_NUMERIC: i32 = 4;
+const LC_TIME: i32 = 5;
+const LC_MESSAGES: i32 = 6;
+
+/// The categories other than `LC_ALL`, in the order used when querying
+/// `LC_ALL` gives a name for each one.
+const LOCALE_CATEGORIES: [i32; 6] = [
+    LC_COLLATE,
+    LC_CTYPE,
+    LC_MONETARY,
+    LC_NUMERIC,
+    LC_TIME,
+    LC_MESSAGES,
+];
+const LOCALE_CATEGORY_COUNT: usize = LOCALE_CATEGORIES.len();
+
+/// Locale names set with [setlocale], and the guest strings it has returned.
+#[derive(Default)]
+pub struct LocaleState {
+    /// Name for each category from `LC_COLLATE` to `LC_MESSAGES`. Empty means
+    /// the initial C locale.
+    names: [Vec<u8>; LOCALE_CATEGORY_COUNT],
+    /// Every name is only allocated once, so that repeated queries return the
+    /// same pointer and nothing is leaked.
+    strings: HashMap<Vec<u8>, MutPtr<u8>>,
+}
+impl LocaleState {
+    fn name(&self, index: usize) -> &[u8] {
+        match &self.names[index][..] {
+            b"" => b"C",
+            name => name,
+        }
+    }
+}
+
+/// Whether `name` is a locale [setlocale] can switch to: C, or a language
+/// with an optional country and UTF-8 codeset, like `en_US.UTF-8`. Nothing
+/// actually behaves differently in the other locales, but apps usually only
+/// check that they were accepted.
+fn is_supported_locale(name: &[u8]) -> bool {
+    if name == b"C" || name == b"POSIX" {
+        return true;
+    }
+    let (name, codeset) = match name.iter().position(|&c| c == b'.') {
+        Some(dot) => (&name[..dot], Some(&name[dot + 1..])),
+        None => (name, None),
+    };
+    if codeset.is_some_and(|codeset| !codeset.eq_ignore_ascii_case(b"UTF-8")) {
+        return false;
+    }
+    let (language, country) = match name.iter().position(|&c| c == b'_') {
+        Some(underscore) => (&name[..underscore], Some(&name[underscore + 1..])),
+        None => (name, None),
+    };
+    (2..=3).contains(&language.len())
+        && language.iter().all(u8::is_ascii_lowercase)
+        && country
+            .is_none_or(|country| country.len() == 2 && country.iter().all(u8::is_ascii_uppercase))
+}
+
+/// The locale name `""` stands for, which is based on the user's first
+/// preferred language like `NSLocale preferredLanguages`.
+fn preferred_locale_name(env: &Environment) -> Vec<u8> {
+    let languages = get_preferred_languages(&env.options);
+    let Some(language) = languages.first() else {
+        return b"C".to_vec();
+    };
+    // Language identifiers like "pt-BR" or "zh-Hans" use hyphens, and may have
+    // a script rather than a country after the language.
+    let mut parts = language.split(['-', '_']);
+    let mut name = parts.next().unwrap_or("").to_ascii_lowercase();
+    if let Some(country) = parts.next().filter(|part| part.len() == 2) {
+        name.push('_');
+        name.push_str(&country.to_ascii_uppercase());
+    }
+    let name = name.into_bytes();
+    if is_supported_locale(&name) {
+        name
+    } else {
+        b"C".to_vec()
+    }
+}
+
+fn setlocale(env: &mut Environment, category: i32, locale: ConstPtr<u8>) -> MutPtr<u8> {
+    let categories = if category == LC_ALL {
+        0..LOCALE_CATEGORY_COUNT
+    } else if let Some(index) = LOCALE_CATEGORIES.iter().position(|&c| c == category) {
+        index..index + 1
+    } else {
+        log!("setlocale(): invalid category {}, returning NULL", category);
+        set_errno(env, EINVAL);
+        return Ptr::null();
+    };
+
     if !locale.is_null() {
-        assert_eq!("C", env.mem.cstr_at_utf8(locale).unwrap());
-        locale.cast_mut()
+        let requested = env.mem.cstr_at(locale).to_vec();
+        let names = if requested.is_empty() {
+            vec![preferred_locale_name(env); categories.len()]
+        } else if category == LC_ALL && requested.contains(&b'/') {
+            // A name for each category, as returned when querying LC_ALL
+            // after setting the categories to different locales.
+            requested
+                .split(|&c| c == b'/')
+                .map(<[u8]>::to_vec)
+                .collect()
+        } else {
+            vec![requested.clone(); categories.len()]
+        };
+        if names.len() != categories.len() || !names.iter().all(|name| is_supported_locale(name)) {
+            log!(
+                "setlocale(): unsupported locale {:?}, returning NULL",
+                String::from_utf8_lossy(&requested)
+            );
+            return Ptr::null();
+        }
+        let state = &mut env.libc_state.stdlib.locale;
+        for (index, name) in categories.clone().zip(names) {
+            state.names[index] = name;
+        }
+    }
+
+    let state = &mut env.libc_state.stdlib.locale;
+    let name = if category == LC_ALL
+        && (1..LOCALE_CATEGORY_COUNT).any(|index| state.name(index) != state.name(0))
+    {
+        (0..LOCALE_CATEGORY_COUNT)
+            .map(|index| state.name(index))
+            .collect::<Vec<_>>()
+            .join(&b'/')
     } else {
-        env.mem.alloc_and_write_cstr(b"C")
+        state.name(categories.start).to_vec()
+    };
+    if let Some(&string) = state.strings.get(&name) {
+        return string;
     }
+    let string = env.mem.alloc_and_write_cstr(&name);
+    env.libc_state.stdlib.locale.strings.insert(name, string);
+    string
 }
 
-pub fn strtoul(env: &mut Environment, str: ConstPtr<u8>, endptr: MutPtr<MutPtr<u8>>, base: i32) -> u32 {
-    let s = env.mem.cstr_at_utf8(str).unwrap();
-    log_dbg!("strtoul '{}'", s);
-    assert_eq!(base, 16);
-    let without_prefix = s.trim_start_matches("0x");
-    let res = u32::from_str_radix(without_prefix, 16).unwrap_or(ULONG_MAX);
+/// Result of [parse_integer].
+struct ParsedInteger {
+    negative: bool,
+    magnitude: u64,
+    /// Whether the magnitude didn't fit in a [u64].
+    overflow: bool,
+}
+
+/// Parsing shared by [strtol], [strtoul], [strtoll] and [strtoull]:
+/// whitespace, an optional sign, a
+/// prefix if the base is 0 (or 16), then as many digits as possible. `endptr`
+/// is written like those functions do. Returns [None] and sets `errno` if the
+/// base is invalid.
+fn parse_integer(
+    env: &mut Environment,
+    str: ConstPtr<u8>,
+    endptr: MutPtr<MutPtr<u8>>,
+    base: i32,
+) -> Option<ParsedInteger> {
+    if base != 0 && !(2..=36).contains(&base) {
+        set_errno(env, EINVAL);
+        return None;
+    }
+
+    let mut s = skip_whitespace(env, str);
+    let negative = match env.mem.read(s) {
+        b'-' => {
+            s += 1;
+            true
+        }
+        b'+' => {
+            s += 1;
+            false
+        }
+        _ => false,
+    };
+    // The 0x prefix only counts if a hex digit follows it, otherwise the 0 is
+    // parsed on its own.
+    let hex_prefix = env.mem.read(s) == b'0'
+        && env.mem.read(s + 1).eq_ignore_ascii_case(&b'x')
+        && env.mem.read(s + 2).is_ascii_hexdigit();
+    let base = match base {
+        0 if hex_prefix => 16,
+        0 if env.mem.read(s) == b'0' => 8,
+        0 => 10,
+        _ => base as u32,
+    };
+    if base == 16 && hex_prefix {
+        s += 2;
+    }
+
+    let digits_start = s;
+    let mut res: u64 = 0;
+    let mut overflow = false;
+    while let Some(digit) = (env.mem.read(s) as char).to_digit(base) {
+        match res
+            .checked_mul(base.into())
+            .and_then(|res| res.checked_add(digit.into()))
+        {
+            Some(new_res) => res = new_res,
+            None => overflow = true,
+        }
+        s += 1;
+    }
     if !endptr.is_null() {
-        let len: GuestUSize = s.len().try_into().unwrap();
-        env.mem.write(endptr, (str + len).cast_mut());
+        // If there were no digits, nothing was consumed.
+        let end = if s == digits_start { str } else { s };
+        env.mem.write(endptr, end.cast_mut());
+    }
+    Some(ParsedInteger {
+        negative,
+        magnitude: res,
+        overflow,
+    })
+}
+
+fn strtol(env: &mut Environment, str: ConstPtr<u8>, endptr: MutPtr<MutPtr<u8>>, base: i32) -> i32 {
+    log_dbg!("strtol '{}'", String::from_utf8_lossy(env.mem.cstr_at(str)));
+    let Some(ParsedInteger { negative, magnitude, overflow }) = parse_integer(env, str, endptr, base) else {
+        return 0;
+    };
+    let limit = if negative { LONG_MIN.unsigned_abs() } else { LONG_MAX as u32 };
+    if overflow || magnitude > limit.into() {
+        set_errno(env, ERANGE);
+        if negative { LONG_MIN } else { LONG_MAX }
+    } else if negative {
+        (magnitude as i32).wrapping_neg()
+    } else {
+        magnitude as i32
+    }
+}
+
+pub fn strtoul(env: &mut Environment, str: ConstPtr<u8>, endptr: MutPtr<MutPtr<u8>>, base: i32) -> u32 {
+    log_dbg!("strtoul '{}'", String::from_utf8_lossy(env.mem.cstr_at(str)));
+    let Some(ParsedInteger { negative, magnitude, overflow }) = parse_integer(env, str, endptr, base) else {
+        return 0;
+    };
+    if overflow || magnitude > ULONG_MAX.into() {
+        set_errno(env, ERANGE);
+        ULONG_MAX
+    } else if negative {
+        (magnitude as u32).wrapping_neg()
+    } else {
+        magnitude as u32
+    }
+}
+
+fn strtoll(env: &mut Environment, str: ConstPtr<u8>, endptr: MutPtr<MutPtr<u8>>, base: i32) -> i64 {
+    log_dbg!("strtoll '{}'", String::from_utf8_lossy(env.mem.cstr_at(str)));
+    let Some(ParsedInteger { negative, magnitude, overflow }) = parse_integer(env, str, endptr, base) else {
+        return 0;
+    };
+    let limit = if negative { LLONG_MIN.unsigned_abs() } else { LLONG_MAX as u64 };
+    if overflow || magnitude > limit {
+        set_errno(env, ERANGE);
+        if negative { LLONG_MIN } else { LLONG_MAX }
+    } else if negative {
+        (magnitude as i64).wrapping_neg()
+    } else {
+        magnitude as i64
+    }
+}
+
+fn strtoull(env: &mut Environment, str: ConstPtr<u8>, endptr: MutPtr<MutPtr<u8>>, base: i32) -> u64 {
+    log_dbg!("strtoull '{}'", String::from_utf8_lossy(env.mem.cstr_at(str)));
+    let Some(ParsedInteger { negative, magnitude, overflow }) = parse_integer(env, str, endptr, base) else {
+        return 0;
+    };
+    if overflow {
+        set_errno(env, ERANGE);
+        ULLONG_MAX
+    } else if negative {
+        magnitude.wrapping_neg()
+    } else {
+        magnitude
     }
-    res
 }
 
 pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(malloc(_)),
     export_c_func!(calloc(_, _)),
+    export_c_func!(posix_memalign(_, _, _)),
+    export_c_func!(valloc(_)),
     export_c_func!(realloc(_, _)),
     export_c_func!(free(_)),
     export_c_func!(atexit(_)),
     export_c_func!(atoi(_)),
     export_c_func!(atol(_)),
+    export_c_func!(atoll(_)),
+    export_c_func!(abs(_)),
+    export_c_func!(labs(_)),
+    export_c_func!(llabs(_)),
+    export_c_func!(div(_, _)),
+    export_c_func!(ldiv(_, _)),
+    export_c_func!(lldiv(_, _)),
     export_c_func!(atof(_)),
     export_c_func!(srand(_)),
     export_c_func!(rand()),
+    export_c_func!(rand_r(_)),
     export_c_func!(srandom(_)),
     export_c_func!(random()),
     export_c_func!(arc4random()),
     export_c_func!(getenv(_)),
     export_c_func!(setenv(_, _, _)),
     export_c_func!(unsetenv(_)),
+    export_c_func!(putenv(_)),
     export_c_func!(exit(_)),
+    export_c_func!(_exit(_)),
+    export_c_func!(_Exit(_)),
+    export_c_func!(abort()),
     export_c_func!(bsearch(_, _, _, _, _)),
     export_c_func!(strtof(_, _)),
     export_c_func!(strtod(_, _)),
     export_c_func2!("_realpath$DARWIN_EXTSN", realpath(_, _)),
+    export_c_func!(mktemp(_)),
+    export_c_func!(mkstemp(_)),
     export_c_func!(sched_yield()),
+    export_c_func!(mbtowc(_, _, _)),
+    export_c_func!(mblen(_, _)),
+    export_c_func!(wctomb(_, _)),
     export_c_func!(mbstowcs(_, _, _)),
     export_c_func!(wcstombs(_, _, _)),
     export_c_func!(setlocale(_, _)),
+    export_c_func!(strtol(_, _, _)),
     export_c_func!(strtoul(_, _, _)),
+    export_c_func!(strtoll(_, _, _)),
+    export_c_func!(strtoull(_, _, _)),
 ];
 
 /// Returns a tuple containing the parsed number and the length of the number in
 /// the string
 pub fn atof_inner(env: &mut Environment, s: ConstPtr<u8>) -> Result<(f64, u32), <f64 as FromStr>::Err> {
     // atof() is similar to atoi().
-    // FIXME: no C99 hexfloat, INF, NAN support
     let start = skip_whitespace(env, s);
     let whitespace_len = Ptr::to_bits(start) - Ptr::to_bits(s);
+    if let Some((result, len)) = atof_hex_inf_nan(env, start) {
+        return Ok((result, whitespace_len + len));
+    }
     let mut len = 0;
     let maybe_sign = env.mem.read(start + len);
     if maybe_sign == b'+' || maybe_sign == b'-' || maybe_sign.is_ascii_digit() {
@@ -398,3 +1210,110 @@ pub fn atof_inner(env: &mut Environment, s: ConstPtr<u8>) -> Result<(f64, u32),
     let s = std::str::from_utf8(env.mem.bytes_at(start, len)).unwrap();
     s.parse().map(|result| (result, whitespace_len + len))
 }
+
+/// Part of [atof_inner]: parse a C99 hexadecimal float (e.g. `0x1.8p+3`),
+/// infinity (`inf` or `infinity`) or NaN (`nan` or `nan(...)`) at `start`, if
+/// there is one. Returns the number and its length.
+fn atof_hex_inf_nan(env: &mut Environment, start: ConstPtr<u8>) -> Option<(f64, u32)> {
+    let at = |len: u32| env.mem.read(start + len);
+    let starts_with_ignore_case = |len: u32, word: &[u8]| {
+        (0..)
+            .zip(word)
+            .all(|(i, &c)| at(len + i).to_ascii_lowercase() == c)
+    };
+
+    let mut len = 0;
+    let negative = at(len) == b'-';
+    if negative || at(len) == b'+' {
+        len += 1;
+    }
+    let sign = if negative { -1.0 } else { 1.0 };
+
+    if starts_with_ignore_case(len, b"infinity") {
+        return Some((sign * f64::INFINITY, len + 8));
+    }
+    if starts_with_ignore_case(len, b"inf") {
+        return Some((sign * f64::INFINITY, len + 3));
+    }
+    if starts_with_ignore_case(len, b"nan") {
+        len += 3;
+        // The characters in the parentheses are implementation-defined, and
+        // are ignored here.
+        if at(len) == b'(' {
+            let mut end = len + 1;
+            while at(end).is_ascii_alphanumeric() || at(end) == b'_' {
+                end += 1;
+            }
+            if at(end) == b')' {
+                len = end + 1;
+            }
+        }
+        return Some((sign * f64::NAN, len));
+    }
+
+    if !starts_with_ignore_case(len, b"0x") {
+        return None;
+    }
+    len += 2;
+    // Up to 15 significant digits fit in the integer without any risk of
+    // overflow, the rest only affect the exponent.
+    let mut mantissa: u64 = 0;
+    let mut significant_digits = 0;
+    let mut exponent: i32 = 0;
+    let mut any_digits = false;
+    let mut seen_point = false;
+    loop {
+        let c = at(len);
+        if c == b'.' && !seen_point {
+            seen_point = true;
+        } else if let Some(digit) = (c as char).to_digit(16) {
+            any_digits = true;
+            if mantissa != 0 || digit != 0 {
+                significant_digits += 1;
+            }
+            if significant_digits <= 15 {
+                mantissa = mantissa * 16 + u64::from(digit);
+                if seen_point {
+                    exponent -= 4;
+                }
+            } else if !seen_point {
+                exponent += 4;
+            }
+        } else {
+            break;
+        }
+        len += 1;
+    }
+    if !any_digits {
+        // Just "0" followed by an 'x', which the decimal parsing handles.
+        return None;
+    }
+
+    // The binary exponent is only part of the number if it has digits.
+    if at(len).to_ascii_lowercase() == b'p' {
+        let mut end = len + 1;
+        let exponent_negative = at(end) == b'-';
+        if exponent_negative || at(end) == b'+' {
+            end += 1;
+        }
+        if at(end).is_ascii_digit() {
+            let mut binary_exponent: i32 = 0;
+            while at(end).is_ascii_digit() {
+                let digit = i32::from(at(end) - b'0');
+                binary_exponent = binary_exponent.saturating_mul(10).saturating_add(digit);
+                end += 1;
+            }
+            if exponent_negative {
+                binary_exponent = -binary_exponent;
+            }
+            exponent = exponent.saturating_add(binary_exponent);
+            len = end;
+        }
+    }
+
+    // Scaling in two steps avoids overflowing to infinity or underflowing to
+    // zero in the intermediate power of two for results near the limits.
+    let half_exponent = exponent / 2;
+    let result = mantissa as f64 * 2f64.powi(half_exponent) * 2f64.powi(exponent - half_exponent);
+    Some((sign * result, len))
+}
@@ -3,13 +3,24 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
-//! stdlib's qsort
+//! stdlib's qsort, plus Darwin's `qsort_r` and BSD `heapsort` and `mergesort`.
 
 use crate::abi::{CallFromHost, GuestFunction};
 use crate::dyld::{export_c_func, FunctionExports};
-use crate::mem::MutPtr;
+use crate::libc::errno::{set_errno, EINVAL};
+use crate::mem::{guest_size_of, MutPtr, MutVoidPtr};
 use crate::Environment;
 
+/// The guest's comparison function.
+#[derive(Copy, Clone)]
+enum Comparator {
+    /// `int (*compar)(const void *, const void *)`
+    Plain(GuestFunction),
+    /// `int (*compar)(void *thunk, const void *, const void *)`. Note that
+    /// Darwin passes the thunk first, unlike glibc.
+    WithThunk(GuestFunction, MutVoidPtr),
+}
+
 fn qsort(
     env: &mut Environment,
     base: MutPtr<u8>,
@@ -20,15 +31,159 @@ fn qsort(
     if nitems < 2 {
         return;
     }
+    let compar = Comparator::Plain(compar);
     qsort_rec(env, base, nitems, size, compar, 0, nitems - 1);
 }
 
+fn qsort_r(
+    env: &mut Environment,
+    base: MutPtr<u8>,
+    nitems: u32,
+    size: u32,
+    thunk: MutVoidPtr,
+    compar: GuestFunction, // int (*compar)(void *, const void *, const void*))
+) {
+    if nitems < 2 {
+        return;
+    }
+    let compar = Comparator::WithThunk(compar, thunk);
+    qsort_rec(env, base, nitems, size, compar, 0, nitems - 1);
+}
+
+/// Like [qsort], but stable.
+fn mergesort(
+    env: &mut Environment,
+    base: MutPtr<u8>,
+    nitems: u32,
+    size: u32,
+    compar: GuestFunction, // int (*compar)(const void *, const void*))
+) -> i32 {
+    // BSD's implementation needs room for a pointer in each pair of elements.
+    if size < guest_size_of::<MutVoidPtr>() / 2 {
+        set_errno(env, EINVAL);
+        return -1;
+    }
+    if nitems < 2 {
+        return 0;
+    }
+    let compar = Comparator::Plain(compar);
+
+    // Sort the indices of the elements rather than the elements themselves, so
+    // the comparator always sees the original elements, then move them.
+    let mut order: Vec<u32> = (0..nitems).collect();
+    let mut scratch = order.clone();
+    merge_sort_rec(env, base, size, compar, &mut order, &mut scratch);
+
+    let total_size = nitems * size;
+    let original = env.mem.bytes_at(base.cast_const(), total_size).to_vec();
+    let sorted = env.mem.bytes_at_mut(base, total_size);
+    for (dest, &src) in sorted.chunks_exact_mut(size as usize).zip(order.iter()) {
+        let src = (src * size) as usize;
+        dest.copy_from_slice(&original[src..][..size as usize]);
+    }
+    0
+}
+
+/// Like [qsort], but guaranteed not to recurse. Also not stable.
+fn heapsort(
+    env: &mut Environment,
+    base: MutPtr<u8>,
+    nitems: u32,
+    size: u32,
+    compar: GuestFunction, // int (*compar)(const void *, const void*))
+) -> i32 {
+    if size == 0 {
+        set_errno(env, EINVAL);
+        return -1;
+    }
+    if nitems < 2 {
+        return 0;
+    }
+    let compar = Comparator::Plain(compar);
+
+    // Build a max-heap, then repeatedly move its root to the end.
+    for root in (0..nitems / 2).rev() {
+        sift_down(env, base, nitems, size, compar, root, nitems);
+    }
+    for end in (1..nitems).rev() {
+        swap_slices(env, base, nitems, size, 0, end);
+        sift_down(env, base, nitems, size, compar, 0, end);
+    }
+    0
+}
+
+/// Restore the heap property for the subtree at `root`, considering only the
+/// first `heap_len` elements.
+fn sift_down(
+    env: &mut Environment,
+    base: MutPtr<u8>,
+    nitems: u32,
+    size: u32,
+    compar: Comparator,
+    mut root: u32,
+    heap_len: u32,
+) {
+    loop {
+        let left = 2 * root + 1;
+        if left >= heap_len {
+            return;
+        }
+        let right = left + 1;
+        let child = if right < heap_len && compare(env, base, size, compar, right, left) > 0 {
+            right
+        } else {
+            left
+        };
+        if compare(env, base, size, compar, child, root) <= 0 {
+            return;
+        }
+        swap_slices(env, base, nitems, size, root, child);
+        root = child;
+    }
+}
+
+fn merge_sort_rec(
+    env: &mut Environment,
+    base: MutPtr<u8>,
+    size: u32,
+    compar: Comparator,
+    order: &mut [u32],
+    scratch: &mut [u32],
+) {
+    if order.len() < 2 {
+        return;
+    }
+    let mid = order.len() / 2;
+    {
+        let (left, right) = order.split_at_mut(mid);
+        let (left_scratch, right_scratch) = scratch.split_at_mut(mid);
+        merge_sort_rec(env, base, size, compar, left, left_scratch);
+        merge_sort_rec(env, base, size, compar, right, right_scratch);
+    }
+
+    scratch.copy_from_slice(order);
+    let (left, right) = scratch.split_at(mid);
+    let (mut i, mut j) = (0, 0);
+    for dest in order.iter_mut() {
+        // Taking from the left on ties is what makes this stable.
+        let take_left = j == right.len()
+            || (i < left.len() && compare(env, base, size, compar, left[i], right[j]) <= 0);
+        if take_left {
+            *dest = left[i];
+            i += 1;
+        } else {
+            *dest = right[j];
+            j += 1;
+        }
+    }
+}
+
 fn qsort_rec(
     env: &mut Environment,
     base: MutPtr<u8>,
     nitems: u32,
     size: u32,
-    compar: GuestFunction,
+    compar: Comparator,
     low: u32,
     hi: u32,
 ) {
@@ -55,13 +210,20 @@ fn compare(
     env: &mut Environment,
     base: MutPtr<u8>,
     size: u32,
-    compar: GuestFunction,
+    compar: Comparator,
     i: u32,
     j: u32,
 ) -> i32 {
     let i_ptr = base + i * size;
     let j_ptr = base + j * size;
-    compar.call_from_host(env, (i_ptr.cast_const(), j_ptr.cast_const()))
+    match compar {
+        Comparator::Plain(compar) => {
+            compar.call_from_host(env, (i_ptr.cast_const(), j_ptr.cast_const()))
+        }
+        Comparator::WithThunk(compar, thunk) => {
+            compar.call_from_host(env, (thunk, i_ptr.cast_const(), j_ptr.cast_const()))
+        }
+    }
 }
 
 fn swap_slices(env: &mut Environment, base: MutPtr<u8>, nitems: u32, size: u32, i: u32, j: u32) {
@@ -79,4 +241,9 @@ fn swap_slices(env: &mut Environment, base: MutPtr<u8>, nitems: u32, size: u32,
     left[..size as usize].swap_with_slice(&mut right[..size as usize]);
 }
 
-pub const FUNCTIONS: FunctionExports = &[export_c_func!(qsort(_, _, _, _))];
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(qsort(_, _, _, _)),
+    export_c_func!(qsort_r(_, _, _, _, _)),
+    export_c_func!(mergesort(_, _, _, _)),
+    export_c_func!(heapsort(_, _, _, _)),
+];
@@ -14,52 +14,21 @@ use super::generic_char::GenericChar;
 
 #[derive(Default)]
 pub struct State {
-    strtok: Option<MutPtr<u8>>,
+    /// Where `strtok()` continues from when passed a null pointer. This is in
+    /// guest memory so it can be passed to `strtok_r()`.
+    strtok: Option<MutPtr<MutPtr<u8>>>,
 }
 
 fn strtok(env: &mut Environment, s: MutPtr<u8>, sep: ConstPtr<u8>) -> MutPtr<u8> {
-    let s = if s.is_null() {
-        let state = env.libc_state.string.strtok.unwrap();
-        if state.is_null() {
-            env.libc_state.string.strtok = None;
-            return Ptr::null();
+    let last = match env.libc_state.string.strtok {
+        Some(last) => last,
+        None => {
+            let last = env.mem.alloc_and_write(Ptr::null());
+            env.libc_state.string.strtok = Some(last);
+            last
         }
-        state
-    } else {
-        s
     };
-
-    let sep = env.mem.cstr_at(sep);
-
-    let mut token_start = s;
-    loop {
-        let c = env.mem.read(token_start);
-        if c == b'\0' {
-            env.libc_state.string.strtok = None;
-            return Ptr::null();
-        } else if sep.contains(&c) {
-            token_start += 1;
-        } else {
-            break;
-        }
-    }
-
-    let mut token_end = token_start;
-    let next_token = loop {
-        let c = env.mem.read(token_end);
-        if sep.contains(&c) {
-            env.mem.write(token_end, b'\0');
-            break token_end + 1;
-        } else if c == b'\0' {
-            break Ptr::null();
-        } else {
-            token_end += 1;
-        }
-    };
-
-    env.libc_state.string.strtok = Some(next_token);
-
-    token_start
+    strtok_r(env, s, sep, last)
 }
 
 // Functions shared with wchar.rs
@@ -83,18 +52,36 @@ fn memmove(
 ) -> MutVoidPtr {
     GenericChar::<u8>::memmove(env, dest.cast(), src.cast(), size).cast()
 }
+fn memccpy(
+    env: &mut Environment,
+    dest: MutVoidPtr,
+    src: ConstVoidPtr,
+    c: i32,
+    size: GuestUSize,
+) -> MutVoidPtr {
+    GenericChar::<u8>::memccpy(env, dest.cast(), src.cast(), c as u8, size).cast()
+}
 fn memchr(env: &mut Environment, string: ConstVoidPtr, c: i32, size: GuestUSize) -> ConstVoidPtr {
     GenericChar::<u8>::memchr(env, string.cast(), c as u8, size).cast()
 }
+fn memrchr(env: &mut Environment, string: ConstVoidPtr, c: i32, size: GuestUSize) -> ConstVoidPtr {
+    GenericChar::<u8>::memrchr(env, string.cast(), c as u8, size).cast()
+}
 fn memcmp(env: &mut Environment, a: ConstVoidPtr, b: ConstVoidPtr, size: GuestUSize) -> i32 {
     GenericChar::<u8>::memcmp(env, a.cast(), b.cast(), size)
 }
 pub(super) fn strlen(env: &mut Environment, s: ConstPtr<u8>) -> GuestUSize {
     GenericChar::<u8>::strlen(env, s)
 }
+fn strnlen(env: &mut Environment, s: ConstPtr<u8>, maxlen: GuestUSize) -> GuestUSize {
+    GenericChar::<u8>::strnlen(env, s, maxlen)
+}
 pub(super) fn strcpy(env: &mut Environment, dest: MutPtr<u8>, src: ConstPtr<u8>) -> MutPtr<u8> {
     GenericChar::<u8>::strcpy(env, dest, src, GuestUSize::MAX)
 }
+fn stpcpy(env: &mut Environment, dest: MutPtr<u8>, src: ConstPtr<u8>) -> MutPtr<u8> {
+    GenericChar::<u8>::stpcpy(env, dest, src, GuestUSize::MAX)
+}
 fn __strcpy_chk(
     env: &mut Environment,
     dest: MutPtr<u8>,
@@ -115,9 +102,27 @@ fn __strcat_chk(
     GenericChar::<u8>::strcat(env, dest, src, size)
 }
 fn strcspn(env: &mut Environment, s: ConstPtr<u8>, charset: ConstPtr<u8>) -> GuestUSize {
-    log!("strcspn: '{}' '{}'", env.mem.cstr_at_utf8(s).unwrap(), env.mem.cstr_at_utf8(charset).unwrap());
+    log!(
+        "strcspn: '{}' '{}'",
+        String::from_utf8_lossy(env.mem.cstr_at(s)),
+        String::from_utf8_lossy(env.mem.cstr_at(charset))
+    );
     GenericChar::<u8>::strcspn(env, s, charset)
 }
+fn strspn(env: &mut Environment, s: ConstPtr<u8>, charset: ConstPtr<u8>) -> GuestUSize {
+    GenericChar::<u8>::strspn(env, s, charset)
+}
+fn strpbrk(env: &mut Environment, s: ConstPtr<u8>, charset: ConstPtr<u8>) -> ConstPtr<u8> {
+    GenericChar::<u8>::strpbrk(env, s, charset)
+}
+fn strtok_r(
+    env: &mut Environment,
+    s: MutPtr<u8>,
+    sep: ConstPtr<u8>,
+    last: MutPtr<MutPtr<u8>>,
+) -> MutPtr<u8> {
+    GenericChar::<u8>::strtok_r(env, s, sep, last)
+}
 fn strncpy(
     env: &mut Environment,
     dest: MutPtr<u8>,
@@ -126,35 +131,16 @@ fn strncpy(
 ) -> MutPtr<u8> {
     GenericChar::<u8>::strncpy(env, dest, src, size)
 }
+fn stpncpy(
+    env: &mut Environment,
+    dest: MutPtr<u8>,
+    src: ConstPtr<u8>,
+    size: GuestUSize,
+) -> MutPtr<u8> {
+    GenericChar::<u8>::stpncpy(env, dest, src, size)
+}
 fn strsep(env: &mut Environment, stringp: MutPtr<MutPtr<u8>>, delim: ConstPtr<u8>) -> MutPtr<u8> {
-    let orig = env.mem.read(stringp);
-    if orig.is_null() {
-        return Ptr::null();
-    }
-    let tmp = orig;
-    let mut i = 0;
-    loop {
-        let c = env.mem.read(tmp + i);
-        if c == b'\0' {
-            env.mem.write(stringp, Ptr::null());
-            break;
-        }
-        let mut j = 0;
-        loop {
-            let cc = env.mem.read(delim + j);
-            if c == cc {
-                env.mem.write(tmp + i, b'\0');
-                env.mem.write(stringp, tmp + i + 1);
-                return orig;
-            }
-            if cc == b'\0' {
-                break;
-            }
-            j += 1;
-        }
-        i += 1;
-    }
-    orig
+    GenericChar::<u8>::strsep(env, stringp, delim)
 }
 pub(super) fn strdup(env: &mut Environment, src: ConstPtr<u8>) -> MutPtr<u8> {
     GenericChar::<u8>::strdup(env, src)
@@ -166,25 +152,7 @@ fn strncmp(env: &mut Environment, a: ConstPtr<u8>, b: ConstPtr<u8>, n: GuestUSiz
     GenericChar::<u8>::strncmp(env, a, b, n)
 }
 fn strcasecmp(env: &mut Environment, a: ConstPtr<u8>, b: ConstPtr<u8>) -> i32 {
-    // TODO: generalize to wide chars
-    let mut offset = 0;
-    loop {
-        let char_a = env.mem.read(a + offset).to_ascii_lowercase();
-        let char_b = env.mem.read(b + offset).to_ascii_lowercase();
-        offset += 1;
-
-        match char_a.cmp(&char_b) {
-            Ordering::Less => return -1,
-            Ordering::Greater => return 1,
-            Ordering::Equal => {
-                if char_a == u8::default() {
-                    return 0;
-                } else {
-                    continue;
-                }
-            }
-        }
-    }
+    strncasecmp(env, a, b, GuestUSize::MAX)
 }
 fn strncasecmp(env: &mut Environment, a: ConstPtr<u8>, b: ConstPtr<u8>, n: GuestUSize) -> i32 {
     // TODO: generalize to wide chars
@@ -234,19 +202,27 @@ fn strlcpy(
 
 pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(strtok(_, _)),
+    export_c_func!(strtok_r(_, _, _)),
     // Functions shared with wchar.rs
     export_c_func!(memset(_, _, _)),
     export_c_func!(memcpy(_, _, _)),
     export_c_func!(memmove(_, _, _)),
+    export_c_func!(memccpy(_, _, _, _)),
     export_c_func!(memchr(_, _, _)),
+    export_c_func!(memrchr(_, _, _)),
     export_c_func!(memcmp(_, _, _)),
     export_c_func!(strlen(_)),
+    export_c_func!(strnlen(_, _)),
     export_c_func!(strcpy(_, _)),
+    export_c_func!(stpcpy(_, _)),
     export_c_func!(__strcpy_chk(_, _, _)),
     export_c_func!(strcat(_, _)),
     export_c_func!(strcspn(_, _)),
+    export_c_func!(strspn(_, _)),
+    export_c_func!(strpbrk(_, _)),
     export_c_func!(__strcat_chk(_, _, _)),
     export_c_func!(strncpy(_, _, _)),
+    export_c_func!(stpncpy(_, _, _)),
     export_c_func!(strsep(_, _)),
     export_c_func!(strdup(_)),
     export_c_func!(strcmp(_, _)),
@@ -8,7 +8,7 @@
 use crate::dyld::{export_c_func, FunctionExports};
 use crate::mem::{ConstPtr, GuestUSize, MutPtr, MutVoidPtr};
 use crate::Environment;
-use crate::libc::errno::ENOENT;
+use crate::libc::errno::{set_errno, ENOENT};
 
 fn sysctl(
     env: &mut Environment,
@@ -42,7 +42,14 @@ fn sysctlbyname(
     newp: MutVoidPtr,
     newlen: GuestUSize,
 ) -> i32 {
-    let name_str = env.mem.cstr_at_utf8(name).unwrap();
+    let Ok(name_str) = env.mem.cstr_at_utf8(name) else {
+        log!(
+            "sysctlbyname({:?}) => -1, name is not valid UTF-8",
+            String::from_utf8_lossy(env.mem.cstr_at(name))
+        );
+        set_errno(env, ENOENT);
+        return -1;
+    };
     log_dbg!(
         "TODO: sysctlbyname({:?}, {:?}, {:?}, {:?}, {:x})",
         name_str,
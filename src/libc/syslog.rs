@@ -0,0 +1,121 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `syslog.h`, and `asl_log()` from `asl.h`, which works the same way.
+//!
+//! There is no system log to write to, so messages go to touchHLE's log.
+
+use crate::abi::{DotDotDot, VaList};
+use crate::dyld::{export_c_func, FunctionExports};
+use crate::libc::err::program_name;
+use crate::libc::errno::{error_message, get_errno};
+use crate::libc::stdio::printf::printf_inner;
+use crate::mem::{ConstPtr, MutVoidPtr};
+use crate::Environment;
+use std::io::Write;
+
+const LOG_PRIMASK: i32 = 0x07;
+/// `openlog()` option to also write messages to stderr.
+const LOG_PERROR: i32 = 0x20;
+
+#[derive(Default, Copy, Clone)]
+pub struct State {
+    /// The `ident` passed to `openlog()`, which the app must keep alive.
+    ident: Option<ConstPtr<u8>>,
+    options: i32,
+}
+
+/// Names of the priority levels, which are the same for ASL.
+fn priority_name(priority: i32) -> &'static str {
+    const NAMES: [&str; 8] = [
+        "emerg", "alert", "crit", "err", "warning", "notice", "info", "debug",
+    ];
+    NAMES[(priority & LOG_PRIMASK) as usize]
+}
+
+/// Read `format`, replacing `%m` with the message for `errnum` like `syslog()`
+/// does. Other conversions are left for [printf_inner].
+fn expand_format(env: &Environment, format: ConstPtr<u8>, errnum: i32) -> Vec<u8> {
+    let format = env.mem.cstr_at(format);
+    let mut expanded = Vec::with_capacity(format.len());
+    let mut i = 0;
+    while i < format.len() {
+        match &format[i..] {
+            [b'%', b'm', ..] => expanded.extend_from_slice(error_message(errnum).as_bytes()),
+            // Copying both characters means "%%m" isn't mistaken for "%m".
+            [b'%', c, ..] => expanded.extend_from_slice(&[b'%', *c]),
+            [c, ..] => {
+                expanded.push(*c);
+                i += 1;
+                continue;
+            }
+            [] => unreachable!(),
+        }
+        i += 2;
+    }
+    expanded
+}
+
+fn log_message(env: &mut Environment, priority: i32, format: ConstPtr<u8>, args: VaList) {
+    let errnum = get_errno(env);
+    let format = expand_format(env, format, errnum);
+    let message = printf_inner::<false, _>(
+        env,
+        |_, idx| format.get(idx as usize).copied().unwrap_or(b'\0'),
+        args,
+    );
+    let message = String::from_utf8_lossy(&message);
+    let message = message.trim_end_matches('\n');
+
+    let State { ident, options } = env.libc_state.syslog;
+    let ident = match ident {
+        Some(ident) => String::from_utf8_lossy(env.mem.cstr_at(ident)).into_owned(),
+        None => program_name(env),
+    };
+    log!("{} <{}>: {}", ident, priority_name(priority), message);
+    if options & LOG_PERROR != 0 {
+        let _ = writeln!(std::io::stderr(), "{}: {}", ident, message);
+    }
+}
+
+fn openlog(env: &mut Environment, ident: ConstPtr<u8>, options: i32, _facility: i32) {
+    env.libc_state.syslog = State {
+        ident: (!ident.is_null()).then_some(ident),
+        options,
+    };
+}
+
+fn closelog(env: &mut Environment) {
+    env.libc_state.syslog = State::default();
+}
+
+fn syslog(env: &mut Environment, priority: i32, format: ConstPtr<u8>, args: DotDotDot) {
+    vsyslog(env, priority, format, args.start())
+}
+fn vsyslog(env: &mut Environment, priority: i32, format: ConstPtr<u8>, args: VaList) {
+    log_message(env, priority, format, args)
+}
+
+/// `asl_log()`. The client and message objects are ignored, so this works
+/// even if the app never created them.
+fn asl_log(
+    env: &mut Environment,
+    _client: MutVoidPtr,
+    _msg: MutVoidPtr,
+    level: i32,
+    format: ConstPtr<u8>,
+    args: DotDotDot,
+) -> i32 {
+    log_message(env, level, format, args.start());
+    0 // success
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(openlog(_, _, _)),
+    export_c_func!(closelog()),
+    export_c_func!(syslog(_, _, _)),
+    export_c_func!(vsyslog(_, _, _)),
+    export_c_func!(asl_log(_, _, _, _, _)),
+];
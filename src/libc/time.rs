@@ -6,6 +6,7 @@
 //! `time.h` (C) and `sys/time.h` (POSIX)
 
 use crate::dyld::{export_c_func, FunctionExports};
+use crate::libc::errno::{set_errno, EINVAL};
 use crate::mem::{guest_size_of, ConstPtr, MutPtr, Ptr, SafeRead, GuestUSize};
 use crate::Environment;
 use std::time::{Duration, Instant, SystemTime};
@@ -16,6 +17,38 @@ pub struct State {
     /// Temporary static storage for the return value of `gmtime` or
     /// `localtime`. The standard allows calls to either to overwrite it.
     gmtime_tmp: Option<MutPtr<tm>>,
+    /// Number of frames presented by the app, see [current_time].
+    frames_presented: u64,
+}
+impl State {
+    /// The time for `--deterministic-time=`, see [current_time].
+    fn deterministic_time(&self, start: u64) -> SystemTime {
+        let frames = self.frames_presented;
+        let elapsed = Duration::from_nanos(frames.saturating_mul(1_000_000_000) / 60);
+        SystemTime::UNIX_EPOCH + Duration::from_secs(start) + elapsed
+    }
+}
+
+/// Get the current date and time as the app should see it.
+///
+/// This is the host's time, unless the `--deterministic-time=` option is used.
+/// In that case, time starts at the given point and only advances by 1/60th of
+/// a second (one iPhone OS display refresh) for each frame the app presents,
+/// so apps that seed their random number generator with the time etc. behave
+/// the same way every time. Monotonic clocks like `mach_absolute_time()` are
+/// unaffected.
+pub fn current_time(env: &Environment) -> SystemTime {
+    let Some(start) = env.options.deterministic_time else {
+        return SystemTime::now();
+    };
+    env.libc_state.time.deterministic_time(start)
+}
+
+/// Tell [current_time] that a frame has been displayed. This should be called
+/// once per frame: by the compositor, or by `EAGLContext` when it presents
+/// directly and composition is skipped.
+pub fn count_presented_frame(env: &mut Environment) {
+    env.libc_state.time.frames_presented += 1;
 }
 
 // time.h (C)
@@ -25,19 +58,20 @@ pub struct State {
 pub type time_t = i32;
 
 #[allow(non_camel_case_types)]
-type clock_t = u64;
+type clock_t = u32; // unsigned long
 
 const CLOCKS_PER_SEC: clock_t = 1000000;
 
+/// The app runs on a single host thread, so the time since startup is a fair
+/// approximation of the processor time used. Like on a real system, this wraps
+/// around after a while (about 72 minutes).
 fn clock(env: &mut Environment) -> clock_t {
-    Instant::now()
-        .duration_since(env.startup_time)
-        .as_secs()
-        .wrapping_mul(CLOCKS_PER_SEC)
+    let elapsed = Instant::now().duration_since(env.startup_time);
+    (elapsed.as_micros() * u128::from(CLOCKS_PER_SEC) / 1_000_000) as clock_t
 }
 
 fn time(env: &mut Environment, out: MutPtr<time_t>) -> time_t {
-    let time64 = SystemTime::now()
+    let time64 = current_time(env)
         .duration_since(SystemTime::UNIX_EPOCH)
         .unwrap()
         .as_secs();
@@ -52,6 +86,10 @@ fn time(env: &mut Environment, out: MutPtr<time_t>) -> time_t {
     time
 }
 
+fn difftime(_env: &mut Environment, time1: time_t, time0: time_t) -> f64 {
+    f64::from(time1) - f64::from(time0)
+}
+
 fn ftime(env: &mut Environment, tb: MutPtr<timeb>) -> i32 {
     let time = time(env, Ptr::null());
     env.mem.write(tb, timeb {
@@ -208,6 +246,26 @@ pub fn timestamp_to_calendar_date(timestamp: time_t) -> tm {
         tm_zone: Ptr::null(),
     }
 }
+#[cfg(test)]
+#[test]
+fn test_deterministic_time() {
+    // headless mode can't present frames, so the test app can only check that
+    // the time stays put.
+    let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1234567890);
+    let mut state = State::default();
+    assert_eq!(state.deterministic_time(1234567890), start);
+    state.frames_presented += 1;
+    assert_eq!(
+        state.deterministic_time(1234567890),
+        start + Duration::from_nanos(1_000_000_000 / 60)
+    );
+    state.frames_presented += 59;
+    assert_eq!(
+        state.deterministic_time(1234567890),
+        start + Duration::from_secs(1)
+    );
+}
+
 #[cfg(test)]
 #[test]
 fn test_timestamp_to_calendar_date() {
@@ -339,21 +397,22 @@ fn mktime(env: &mut Environment, tm: MutPtr<tm>) -> time_t {
 // sys/time.h (POSIX)
 
 #[allow(non_camel_case_types)]
-type suseconds_t = i32;
+pub type suseconds_t = i32;
 
 #[allow(non_camel_case_types)]
 #[repr(C, packed)]
-struct timeval {
-    tv_sec: time_t,
-    tv_usec: suseconds_t,
+pub struct timeval {
+    pub tv_sec: time_t,
+    pub tv_usec: suseconds_t,
 }
 unsafe impl SafeRead for timeval {}
 
 #[allow(non_camel_case_types)]
+#[derive(Copy, Clone)]
 #[repr(C, packed)]
-struct timespec {
-    tv_sec: time_t,
-    tv_nsec: i32,
+pub struct timespec {
+    pub tv_sec: time_t,
+    pub tv_nsec: i32,
 }
 unsafe impl SafeRead for timespec {}
 
@@ -384,7 +443,7 @@ fn gettimeofday(
         return 0; // success
     }
 
-    let time = SystemTime::now()
+    let time = current_time(env)
         .duration_since(SystemTime::UNIX_EPOCH)
         .unwrap();
 
@@ -401,13 +460,30 @@ fn gettimeofday(
     0 // success
 }
 
-fn nanosleep(env: &mut Environment, rqtp: ConstPtr<timespec>, _rmtp: MutPtr<timespec>) -> i32 {
+fn nanosleep(env: &mut Environment, rqtp: ConstPtr<timespec>, rmtp: MutPtr<timespec>) -> i32 {
     let t = env.mem.read(rqtp);
     let tv_sec = t.tv_sec;
     let tv_nsec = t.tv_nsec;
     log_dbg!("nanosleep {} {}", tv_sec, tv_nsec);
-    let total_sleep = Duration::from_secs(tv_sec.try_into().unwrap())
-        + Duration::from_nanos(tv_nsec.try_into().unwrap());
+    if tv_sec < 0 || !(0..1_000_000_000).contains(&tv_nsec) {
+        set_errno(env, EINVAL);
+        return -1;
+    }
+    let total_sleep = Duration::new(tv_sec as u64, tv_nsec as u32);
+    // nanosleep() writes the time remaining to rmtp if the thread was woken up
+    // early by a signal. touchHLE never does that currently, so the remainder
+    // is always zero.
+    if !rmtp.is_null() {
+        env.mem.write(
+            rmtp,
+            timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            },
+        );
+    }
+    // A zero-length sleep still goes through the scheduler, which makes it
+    // behave like sched_yield().
     env.sleep(total_sleep, true);
     0 // success
 }
@@ -418,16 +494,37 @@ fn strftime(
     maxsize: GuestUSize,
     format: ConstPtr<u8>,
     tm: ConstPtr<tm>) -> GuestUSize {
-    let fmt = env.mem.cstr_at_utf8(format).unwrap();
-    log!("strftime fmt {}", fmt);
-    assert_eq!(fmt, "%Z");
-    env.mem.write(s, b'\0');
-    0
+    let format = env.mem.cstr_at(format).to_vec();
+    log_dbg!("strftime fmt {:?}", String::from_utf8_lossy(&format));
+    // The format is processed as bytes: anything but a conversion is copied
+    // as-is, whatever the encoding.
+    let mut result = Vec::new();
+    let mut bytes = format.iter();
+    while let Some(&c) = bytes.next() {
+        if c != b'%' {
+            result.push(c);
+            continue;
+        }
+        match bytes.next() {
+            Some(b'%') => result.push(b'%'),
+            // TODO: time zone name
+            Some(b'Z') => (),
+            other => unimplemented!("strftime() conversion {:?}", other.map(|&c| c as char)),
+        }
+    }
+    let len: GuestUSize = result.len().try_into().unwrap();
+    if len >= maxsize {
+        return 0;
+    }
+    env.mem.bytes_at_mut(s, len).copy_from_slice(&result);
+    env.mem.write(s + len, b'\0');
+    len
 }
 
 pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(clock()),
     export_c_func!(time(_)),
+    export_c_func!(difftime(_, _)),
     export_c_func!(ftime(_)),
     export_c_func!(gmtime_r(_, _)),
     export_c_func!(gmtime(_)),
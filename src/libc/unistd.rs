@@ -7,11 +7,13 @@
 
 use crate::dyld::{export_c_func, FunctionExports};
 use crate::fs::GuestPath;
-use crate::libc::posix_io::{FileDescriptor, O_RDONLY, STDERR_FILENO, STDIN_FILENO, STDOUT_FILENO};
-use crate::mem::{ConstPtr, ConstVoidPtr, GuestISize, GuestUSize, MutPtr, MutVoidPtr, Ptr};
+use crate::libc::posix_io::{path_at, FileDescriptor, O_RDONLY, STDERR_FILENO, STDIN_FILENO, STDOUT_FILENO};
+use crate::mem::{ConstPtr, GuestISize, GuestUSize, MutPtr, MutVoidPtr};
 use crate::Environment;
 use std::time::Duration;
+use crate::libc::errno::{set_errno, EACCES, EBADF, EINVAL, ENOENT, EPERM};
 use crate::libc::posix_io;
+use crate::libc::pwd;
 use crate::libc::stdio::{FILE, fread};
 
 #[allow(non_camel_case_types)]
@@ -34,7 +36,7 @@ fn usleep(env: &mut Environment, useconds: useconds_t) -> i32 {
 }
 
 #[allow(non_camel_case_types)]
-type pid_t = i32;
+pub type pid_t = i32;
 
 fn getpid(_env: &mut Environment) -> pid_t {
     // Not a real value, since touchHLE only simulates a single process.
@@ -46,6 +48,34 @@ fn getppid(_env: &mut Environment) -> pid_t {
     0
 }
 
+#[allow(non_camel_case_types)]
+pub type uid_t = u32;
+#[allow(non_camel_case_types)]
+pub type gid_t = u32;
+
+/// User ID of the "mobile" user that iPhone OS apps run as.
+pub const MOBILE_UID: uid_t = 501;
+/// Group ID of the "mobile" group.
+pub const MOBILE_GID: gid_t = 501;
+
+fn getuid(_env: &mut Environment) -> uid_t {
+    MOBILE_UID
+}
+fn geteuid(_env: &mut Environment) -> uid_t {
+    MOBILE_UID
+}
+fn getgid(_env: &mut Environment) -> gid_t {
+    MOBILE_GID
+}
+fn getegid(_env: &mut Environment) -> gid_t {
+    MOBILE_GID
+}
+
+fn getlogin(env: &mut Environment) -> MutPtr<u8> {
+    // Caller should not modify the result
+    pwd::mobile_user_name(env)
+}
+
 fn isatty(_env: &mut Environment, fd: FileDescriptor) -> i32 {
     if [STDIN_FILENO, STDOUT_FILENO, STDERR_FILENO].contains(&fd) {
         1
@@ -55,8 +85,10 @@ fn isatty(_env: &mut Environment, fd: FileDescriptor) -> i32 {
 }
 
 fn access(env: &mut Environment, path: ConstPtr<u8>, mode: i32) -> i32 {
-    let binding = env.mem.cstr_at_utf8(path).unwrap();
-    let guest_path = GuestPath::new(&binding);
+    let Some(path_string) = path_at(env, path) else {
+        return -1;
+    };
+    let guest_path = GuestPath::new(&path_string);
     let (exists, r, _, _) = env.fs.access(guest_path);
     // TODO: set errno
     match mode {
@@ -78,6 +110,37 @@ fn access(env: &mut Environment, path: ConstPtr<u8>, mode: i32) -> i32 {
     }
 }
 
+fn unlink(env: &mut Environment, path: ConstPtr<u8>) -> i32 {
+    let Some(path_string) = path_at(env, path) else {
+        return -1;
+    };
+    let guest_path = GuestPath::new(&path_string);
+    if env.fs.is_dir(guest_path) {
+        log!(
+            "Warning: unlink({:?}) failed, path is a directory",
+            path_string
+        );
+        set_errno(env, EPERM);
+        return -1;
+    }
+    match env.fs.remove(guest_path) {
+        Ok(()) => {
+            log_dbg!("unlink({:?}) => 0", path_string);
+            0
+        }
+        Err(()) => {
+            log!("Warning: unlink({:?}) failed, returning -1", path_string);
+            let errno = if env.fs.exists(guest_path) {
+                EACCES
+            } else {
+                ENOENT
+            };
+            set_errno(env, errno);
+            -1
+        }
+    }
+}
+
 fn uname(_env: &mut Environment, name: MutVoidPtr) -> i32 {
     -1
 }
@@ -94,24 +157,9 @@ fn get_end(env: &mut Environment) -> u32 {
     927506432
 }
 
-// int sigaction(int sig, const struct sigaction *restrict act, struct sigaction *restrict oact);
-fn sigaction(env: &mut Environment, sig: i32, act: ConstVoidPtr, oact: MutVoidPtr) -> i32 {
-    0
-}
-
-// int sigprocmask(int how, const sigset_t *restrict set, sigset_t *restrict oset);
-fn sigprocmask(env: &mut Environment, how: i32, set: ConstVoidPtr, oact: MutVoidPtr) -> i32 {
-    0
-}
-
-// sig_t signal(int sig, sig_t func);
-fn signal(env: &mut Environment, sig: i32, func: MutVoidPtr) -> MutVoidPtr {
-    Ptr::null()
-}
-
 // ssize_t readlink(const char *restrict path, char *restrict buf, size_t bufsize)
 fn readlink(env: &mut Environment, path: ConstPtr<u8>, buf: MutPtr<u8>, bufsize: GuestISize) -> GuestISize {
-    log!("Failing readlink() for {}", env.mem.cstr_at_utf8(path).unwrap());
+    log!("Failing readlink() for {}", String::from_utf8_lossy(env.mem.cstr_at(path)));
     // TODO: set errno
     -1
     // let file: MutPtr<FILE> = match posix_io::open_direct(env, path, O_RDONLY) {
@@ -126,7 +174,7 @@ fn readlink(env: &mut Environment, path: ConstPtr<u8>, buf: MutPtr<u8>, bufsize:
 }
 
 fn getdtablesize(env: &mut Environment) -> i32 {
-    2
+    posix_io::fd_limit(env) as i32
 }
 
 fn gethostname(env: &mut Environment, name: ConstPtr<u8>, namelen: GuestUSize) -> i32 {
@@ -144,6 +192,100 @@ fn sysconf(env: &mut Environment, name: i32) -> i32 {
     }
 }
 
+const _PC_LINK_MAX: i32 = 1;
+const _PC_MAX_CANON: i32 = 2;
+const _PC_MAX_INPUT: i32 = 3;
+const _PC_NAME_MAX: i32 = 4;
+const _PC_PATH_MAX: i32 = 5;
+const _PC_PIPE_BUF: i32 = 6;
+const _PC_CHOWN_RESTRICTED: i32 = 7;
+const _PC_NO_TRUNC: i32 = 8;
+
+/// Shared part of [pathconf] and [fpathconf]. The limits are the same for every
+/// file, and are the ones HFS+ has on Darwin.
+fn path_limit(env: &mut Environment, name: i32) -> GuestISize {
+    match name {
+        _PC_LINK_MAX => 32767,
+        _PC_MAX_CANON | _PC_MAX_INPUT | _PC_PATH_MAX => 1024,
+        _PC_NAME_MAX => 255,
+        _PC_PIPE_BUF => 512,
+        // _POSIX_VERSION, meaning the option is in effect.
+        _PC_CHOWN_RESTRICTED | _PC_NO_TRUNC => 200112,
+        _ => {
+            log!("Warning: unknown pathconf() name {}, returning -1", name);
+            set_errno(env, EINVAL);
+            -1
+        }
+    }
+}
+
+fn pathconf(env: &mut Environment, path: ConstPtr<u8>, name: i32) -> GuestISize {
+    let Some(path_string) = path_at(env, path) else {
+        return -1;
+    };
+    if !env.fs.exists(GuestPath::new(&path_string)) {
+        set_errno(env, ENOENT);
+        return -1;
+    }
+    path_limit(env, name)
+}
+
+fn fpathconf(env: &mut Environment, fd: FileDescriptor, name: i32) -> GuestISize {
+    let is_open = match fd {
+        STDIN_FILENO | STDOUT_FILENO | STDERR_FILENO => true,
+        ..0 => false,
+        _ => env.libc_state.posix_io.file_for_fd(fd).is_some(),
+    };
+    if !is_open {
+        set_errno(env, EBADF);
+        return -1;
+    }
+    path_limit(env, name)
+}
+
+const _CS_PATH: i32 = 1;
+const _CS_DARWIN_USER_DIR: i32 = 65536;
+const _CS_DARWIN_USER_TEMP_DIR: i32 = 65537;
+const _CS_DARWIN_USER_CACHE_DIR: i32 = 65538;
+
+/// The value of `confstr(_CS_PATH)`, which is also the default `PATH`.
+pub const CS_PATH: &[u8] = b"/usr/bin:/bin:/usr/sbin:/sbin";
+
+/// `confstr()`: returns the size of the buffer the whole value needs, and
+/// writes as much of it as fits into `buf`.
+fn confstr(env: &mut Environment, name: i32, buf: MutPtr<u8>, len: GuestUSize) -> GuestUSize {
+    // The Darwin per-user directories are all in the app's sandbox, so they
+    // agree with NSTemporaryDirectory(), TMPDIR etc. They end in a slash.
+    let value = match name {
+        _CS_PATH => CS_PATH.to_vec(),
+        _CS_DARWIN_USER_DIR => format!("{}/", env.fs.home_directory().as_str()).into_bytes(),
+        _CS_DARWIN_USER_TEMP_DIR => {
+            format!("{}/", env.fs.temporary_directory().as_str()).into_bytes()
+        }
+        _CS_DARWIN_USER_CACHE_DIR => {
+            let dir = env.fs.home_directory().join("Library/Caches");
+            if env.fs.create_dir_all(&dir).is_err() {
+                log!("Warning: couldn't create directory {:?}", dir);
+            }
+            format!("{}/", dir.as_str()).into_bytes()
+        }
+        _ => {
+            log!("Warning: unknown confstr() name {}, returning 0", name);
+            set_errno(env, EINVAL);
+            return 0;
+        }
+    };
+    let size: GuestUSize = (value.len() + 1).try_into().unwrap();
+    if !buf.is_null() && len > 0 {
+        let copied = (size - 1).min(len - 1);
+        env.mem
+            .bytes_at_mut(buf, copied)
+            .copy_from_slice(&value[..copied as usize]);
+        env.mem.write(buf + copied, b'\0');
+    }
+    size
+}
+
 fn waitpid(env: &mut Environment, pid: pid_t, stat_loc: MutPtr<i32>, options: i32) -> i32 {
     log!("waitpid pid {}, options {}", pid, options);
     // we do not have any other processes really
@@ -161,15 +303,21 @@ pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(usleep(_)),
     export_c_func!(getpid()),
     export_c_func!(getppid()),
+    export_c_func!(getuid()),
+    export_c_func!(geteuid()),
+    export_c_func!(getgid()),
+    export_c_func!(getegid()),
+    export_c_func!(getlogin()),
     export_c_func!(isatty(_)),
     export_c_func!(access(_, _)),
+    export_c_func!(unlink(_)),
     export_c_func!(uname(_)),
-    export_c_func!(sigaction(_, _, _)),
-    export_c_func!(sigprocmask(_, _, _)),
-    export_c_func!(signal(_, _)),
     export_c_func!(readlink(_, _, _)),
     export_c_func!(getdtablesize()),
     export_c_func!(gethostname(_, _)),
     export_c_func!(sysconf(_)),
+    export_c_func!(pathconf(_, _)),
+    export_c_func!(fpathconf(_, _)),
+    export_c_func!(confstr(_, _, _)),
     export_c_func!(waitpid(_, _, _)),
 ];
@@ -6,7 +6,14 @@
 //! `wchar.h`
 
 use crate::dyld::{export_c_func, FunctionExports};
-use crate::mem::{ConstPtr, GuestUSize, MutPtr};
+use crate::libc::errno::{set_errno, EILSEQ};
+use crate::libc::posix_io::{self, SEEK_CUR, SEEK_SET, STDERR_FILENO};
+use crate::libc::stdio::{
+    fread_unoriented, fwrite_unoriented, orient, orientation, set_orientation, Orientation,
+    WideEncoding, FILE,
+};
+use crate::libc::stdlib::{decode_mb_char, mb_char_len};
+use crate::mem::{ConstPtr, GuestUSize, MutPtr, Ptr};
 use crate::Environment;
 
 use super::generic_char::GenericChar;
@@ -42,6 +49,215 @@ fn wctob(_env: &mut Environment, c: wint_t) -> i32 {
     }
 }
 
+// Wide character I/O. See also [crate::libc::stdio] for the orientation of
+// streams.
+
+/// Get the encoding of a wide-oriented stream, deciding it if this is the first
+/// wide character operation on it. When reading from the start of a file, a
+/// byte order mark is looked for (and skipped), so UTF-16 files can be read.
+fn stream_encoding(env: &mut Environment, stream: MutPtr<FILE>, reading: bool) -> WideEncoding {
+    if let Some(Orientation::Wide(Some(encoding))) = orientation(env, stream) {
+        return encoding;
+    }
+    let mut encoding = WideEncoding::Utf8;
+    let FILE { fd } = env.mem.read(stream);
+    // The standard streams can't be seeked.
+    if reading && fd > STDERR_FILENO && posix_io::lseek(env, fd, 0, SEEK_CUR) == 0 {
+        let buf: MutPtr<u8> = env.mem.alloc(3).cast();
+        let read = fread_unoriented(env, buf.cast(), 1, 3, stream);
+        let bom_len = match env.mem.bytes_at(buf, read) {
+            [0xff, 0xfe, ..] => {
+                encoding = WideEncoding::Utf16LE;
+                2
+            }
+            [0xfe, 0xff, ..] => {
+                encoding = WideEncoding::Utf16BE;
+                2
+            }
+            [0xef, 0xbb, 0xbf] => 3,
+            _ => 0,
+        };
+        env.mem.free(buf.cast());
+        posix_io::lseek(env, fd, bom_len, SEEK_SET);
+    }
+    log_dbg!(
+        "Stream {:?} has wide character encoding {:?}",
+        stream,
+        encoding
+    );
+    set_orientation(env, stream, Orientation::Wide(Some(encoding)));
+    encoding
+}
+
+/// Read one wide character from a wide-oriented stream. Returns [None] at the
+/// end of the file, or `Some(Err(()))` for an invalid or truncated sequence.
+fn read_wide_char(env: &mut Environment, stream: MutPtr<FILE>) -> Option<Result<wchar_t, ()>> {
+    let encoding = stream_encoding(env, stream, /* reading: */ true);
+    let buf: MutPtr<u8> = env.mem.alloc(4).cast();
+    let read = |env: &mut Environment, offset: GuestUSize, count: GuestUSize| {
+        fread_unoriented(env, (buf + offset).cast(), 1, count, stream) == count
+    };
+    let res = match encoding {
+        WideEncoding::Utf8 => {
+            if !read(env, 0, 1) {
+                None
+            } else if let Some(len) = mb_char_len(env.mem.read(buf)) {
+                if len > 1 && !read(env, 1, len - 1) {
+                    Some(Err(()))
+                } else {
+                    decode_mb_char(env, buf.cast_const(), len)
+                        .map(|(wc, _)| wc)
+                        .ok_or(())
+                        .into()
+                }
+            } else {
+                Some(Err(()))
+            }
+        }
+        WideEncoding::Utf16LE | WideEncoding::Utf16BE => {
+            let unit = |env: &Environment, offset: GuestUSize| {
+                let bytes = [env.mem.read(buf + offset), env.mem.read(buf + offset + 1)];
+                if encoding == WideEncoding::Utf16LE {
+                    u16::from_le_bytes(bytes)
+                } else {
+                    u16::from_be_bytes(bytes)
+                }
+            };
+            // A trailing odd byte is ignored.
+            if !read(env, 0, 2) {
+                None
+            } else {
+                let mut units = vec![unit(env, 0)];
+                // A high surrogate needs the low surrogate that follows it.
+                if (0xd800..0xdc00).contains(&units[0]) && read(env, 2, 2) {
+                    units.push(unit(env, 2));
+                }
+                Some(match char::decode_utf16(units).next() {
+                    Some(Ok(c)) => Ok(c as wchar_t),
+                    _ => Err(()),
+                })
+            }
+        }
+    };
+    env.mem.free(buf.cast());
+    res
+}
+
+/// Write wide characters to a wide-oriented stream. Returns `false` on failure,
+/// setting `errno` to `EILSEQ` if a character can't be encoded.
+fn write_wide_chars(env: &mut Environment, stream: MutPtr<FILE>, chars: &[wchar_t]) -> bool {
+    let encoding = stream_encoding(env, stream, /* reading: */ false);
+    let mut bytes = Vec::new();
+    for &wc in chars {
+        let Some(c) = u32::try_from(wc).ok().and_then(char::from_u32) else {
+            set_errno(env, EILSEQ);
+            return false;
+        };
+        match encoding {
+            WideEncoding::Utf8 => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+            WideEncoding::Utf16LE => {
+                for unit in c.encode_utf16(&mut [0; 2]) {
+                    bytes.extend_from_slice(&unit.to_le_bytes());
+                }
+            }
+            WideEncoding::Utf16BE => {
+                for unit in c.encode_utf16(&mut [0; 2]) {
+                    bytes.extend_from_slice(&unit.to_be_bytes());
+                }
+            }
+        }
+    }
+    let len: GuestUSize = bytes.len().try_into().unwrap();
+    let buf: MutPtr<u8> = env.mem.alloc(len).cast();
+    env.mem.bytes_at_mut(buf, len).copy_from_slice(&bytes);
+    let written = fwrite_unoriented(env, buf.cast_const().cast(), 1, len, stream);
+    env.mem.free(buf.cast());
+    written == len
+}
+
+fn fwide(env: &mut Environment, stream: MutPtr<FILE>, mode: i32) -> i32 {
+    // Only an unoriented stream can have its orientation set.
+    if mode != 0 && orientation(env, stream).is_none() {
+        let new = if mode > 0 {
+            Orientation::Wide(None)
+        } else {
+            Orientation::Byte
+        };
+        set_orientation(env, stream, new);
+    }
+    match orientation(env, stream) {
+        None => 0,
+        Some(Orientation::Byte) => -1,
+        Some(Orientation::Wide(_)) => 1,
+    }
+}
+
+fn fgetwc(env: &mut Environment, stream: MutPtr<FILE>) -> wint_t {
+    if !orient(env, stream, /* wide: */ true) {
+        return WEOF;
+    }
+    match read_wide_char(env, stream) {
+        Some(Ok(wc)) => wc,
+        Some(Err(())) => {
+            set_errno(env, EILSEQ);
+            WEOF
+        }
+        None => WEOF,
+    }
+}
+
+fn fgetws(
+    env: &mut Environment,
+    ws: MutPtr<wchar_t>,
+    n: i32,
+    stream: MutPtr<FILE>,
+) -> MutPtr<wchar_t> {
+    if n <= 0 || !orient(env, stream, /* wide: */ true) {
+        return Ptr::null();
+    }
+    let max: GuestUSize = (n - 1).try_into().unwrap();
+    let mut count = 0;
+    while count < max {
+        match read_wide_char(env, stream) {
+            Some(Ok(wc)) => {
+                env.mem.write(ws + count, wc);
+                count += 1;
+                if wc == wchar_t::from(b'\n') {
+                    break;
+                }
+            }
+            Some(Err(())) => {
+                set_errno(env, EILSEQ);
+                return Ptr::null();
+            }
+            None if count == 0 => return Ptr::null(),
+            None => break,
+        }
+    }
+    env.mem.write(ws + count, 0);
+    ws
+}
+
+fn fputwc(env: &mut Environment, wc: wchar_t, stream: MutPtr<FILE>) -> wint_t {
+    if !orient(env, stream, /* wide: */ true) || !write_wide_chars(env, stream, &[wc]) {
+        return WEOF;
+    }
+    wc
+}
+
+fn fputws(env: &mut Environment, ws: ConstPtr<wchar_t>, stream: MutPtr<FILE>) -> i32 {
+    if !orient(env, stream, /* wide: */ true) {
+        return -1;
+    }
+    let len = wcslen(env, ws);
+    let chars: Vec<wchar_t> = (0..len).map(|i| env.mem.read(ws + i)).collect();
+    if write_wide_chars(env, stream, &chars) {
+        0
+    } else {
+        -1
+    }
+}
+
 // Functions shared with string.rs
 
 fn wmemset(
@@ -157,6 +373,11 @@ fn wcstol(env: &mut Environment, nptr: ConstPtr<wchar_t>, endptr: MutPtr<MutPtr<
 pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(btowc(_)),
     export_c_func!(wctob(_)),
+    export_c_func!(fwide(_, _)),
+    export_c_func!(fgetwc(_)),
+    export_c_func!(fgetws(_, _, _)),
+    export_c_func!(fputwc(_, _)),
+    export_c_func!(fputws(_, _)),
     // Functions shared with string.rs
     export_c_func!(wmemset(_, _, _)),
     export_c_func!(wmemcpy(_, _, _)),
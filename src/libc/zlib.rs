@@ -0,0 +1,1270 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `zlib.h`
+//!
+//! iPhone OS has zlib as a system library, so apps link against it rather
+//! than bundling their own copy.
+//!
+//! The DEFLATE format itself is handled by [flate2], but the zlib and gzip
+//! wrappers and their checksums are done here, so that all the `windowBits`
+//! variants can share one implementation. Streams are always consumed in full
+//! and the output is buffered host-side until the app provides enough space
+//! for it, so the compressed output won't be identical to what the real zlib
+//! produces, but it is interchangeable.
+//!
+//! The host state for a `z_stream` is keyed by its guest address, so apps
+//! must not move one between calls (zlib doesn't allow this either).
+
+use crate::abi::GuestFunction;
+use crate::dyld::{export_c_func, FunctionExports};
+use crate::libc::posix_io::{self, FileDescriptor, O_APPEND, O_CREAT, O_RDONLY, O_TRUNC, O_WRONLY};
+use crate::mem::{guest_size_of, ConstPtr, ConstVoidPtr, MutPtr, MutVoidPtr, Ptr, SafeRead};
+use crate::Environment;
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone)]
+#[repr(C, packed)]
+pub struct z_stream {
+    next_in: ConstPtr<u8>,
+    avail_in: u32,
+    total_in: u32,
+    next_out: MutPtr<u8>,
+    avail_out: u32,
+    total_out: u32,
+    msg: ConstPtr<u8>,
+    /// Always NULL, the host state lives in [State] instead.
+    state: MutVoidPtr,
+    // The allocator functions are ignored, since nothing is allocated in guest
+    // memory on the stream's behalf.
+    zalloc: GuestFunction,
+    zfree: GuestFunction,
+    opaque: MutVoidPtr,
+    data_type: i32,
+    adler: u32,
+    reserved: u32,
+}
+unsafe impl SafeRead for z_stream {}
+impl z_stream {
+    /// Update the fields for the consumed input and produced output.
+    fn advance(&mut self, consumed: u32, produced: u32) {
+        let (next_in, next_out) = (self.next_in, self.next_out);
+        self.next_in = next_in + consumed;
+        self.avail_in -= consumed;
+        self.total_in = self.total_in.wrapping_add(consumed);
+        self.next_out = next_out + produced;
+        self.avail_out -= produced;
+        self.total_out = self.total_out.wrapping_add(produced);
+    }
+}
+
+/// The version apps have been built against must have the same major version.
+const ZLIB_VERSION: &str = "1.2.3";
+
+// Flush values
+const Z_NO_FLUSH: i32 = 0;
+const Z_PARTIAL_FLUSH: i32 = 1;
+const Z_SYNC_FLUSH: i32 = 2;
+const Z_FULL_FLUSH: i32 = 3;
+const Z_FINISH: i32 = 4;
+const Z_BLOCK: i32 = 5;
+
+// Return codes
+const Z_OK: i32 = 0;
+const Z_STREAM_END: i32 = 1;
+const Z_NEED_DICT: i32 = 2;
+const Z_ERRNO: i32 = -1;
+const Z_STREAM_ERROR: i32 = -2;
+const Z_DATA_ERROR: i32 = -3;
+const Z_BUF_ERROR: i32 = -5;
+const Z_VERSION_ERROR: i32 = -6;
+
+const Z_DEFAULT_COMPRESSION: i32 = -1;
+const Z_DEFLATED: i32 = 8;
+/// Values above this for `strategy` are invalid.
+const Z_FIXED: i32 = 4;
+const Z_UNKNOWN: i32 = 2;
+
+/// `OS_CODE` for Unix, used in gzip headers.
+const OS_CODE: u8 = 3;
+
+/// Amount of output space to add at a time while (de)compressing, and amount
+/// of a gzip file to read at a time.
+const CHUNK_SIZE: usize = 16 * 1024;
+
+#[derive(Default)]
+pub struct State {
+    streams: HashMap<MutPtr<z_stream>, Stream>,
+    gz_files: HashMap<MutVoidPtr, GzFile>,
+    /// Guest copies of the strings returned by `zlibVersion()` or put in the
+    /// `msg` field of `z_stream`, which must stay valid forever.
+    strings: HashMap<&'static str, ConstPtr<u8>>,
+}
+
+fn get_string(env: &mut Environment, string: &'static str) -> ConstPtr<u8> {
+    if let Some(&ptr) = env.libc_state.zlib.strings.get(string) {
+        return ptr;
+    }
+    let ptr = env.mem.alloc_and_write_cstr(string.as_bytes()).cast_const();
+    env.libc_state.zlib.strings.insert(string, ptr);
+    ptr
+}
+
+enum Stream {
+    Inflate(Inflater),
+    Deflate(Deflater),
+}
+
+/// The format around the DEFLATE data, chosen by `windowBits`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Wrapper {
+    /// No header or trailer (negative `windowBits`)
+    Raw,
+    /// zlib header and Adler-32 trailer (`windowBits` of 8 to 15)
+    Zlib,
+    /// gzip header and CRC-32 trailer (16 added to `windowBits`)
+    Gzip,
+    /// zlib or gzip, detected from the header (32 added to `windowBits`)
+    Auto,
+}
+
+/// Determine the wrapper and the window size in bits from a `windowBits`
+/// argument, or return [None] if it is invalid.
+fn parse_window_bits(window_bits: i32, inflate: bool) -> Option<(Wrapper, u8)> {
+    let (wrapper, bits) = match window_bits {
+        // For inflate, 0 means to use the window size from the zlib header.
+        0 if inflate => (Wrapper::Zlib, 15),
+        -15..=-8 => (Wrapper::Raw, -window_bits),
+        8..=15 => (Wrapper::Zlib, window_bits),
+        24..=31 => (Wrapper::Gzip, window_bits - 16),
+        40..=47 if inflate => (Wrapper::Auto, window_bits - 32),
+        _ => return None,
+    };
+    Some((wrapper, bits.try_into().unwrap()))
+}
+
+pub fn adler32_update(adler: u32, data: &[u8]) -> u32 {
+    const BASE: u32 = 65521;
+    // The largest number of bytes that can be summed before `b` could
+    // overflow, from zlib.
+    const NMAX: usize = 5552;
+    let (mut a, mut b) = (adler & 0xffff, adler >> 16);
+    for chunk in data.chunks(NMAX) {
+        for &byte in chunk {
+            a += u32::from(byte);
+            b += a;
+        }
+        a %= BASE;
+        b %= BASE;
+    }
+    (b << 16) | a
+}
+
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xedb88320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+};
+
+pub fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    let mut c = !crc;
+    for &byte in data {
+        c = CRC32_TABLE[((c ^ u32::from(byte)) & 0xff) as usize] ^ (c >> 8);
+    }
+    !c
+}
+
+/// Output that hasn't been copied to the guest yet.
+#[derive(Default)]
+struct Pending {
+    data: Vec<u8>,
+    pos: usize,
+}
+impl Pending {
+    fn is_empty(&self) -> bool {
+        self.pos == self.data.len()
+    }
+    fn len(&self) -> usize {
+        self.data.len() - self.pos
+    }
+    fn copy_to(&mut self, out: &mut [u8]) -> usize {
+        let count = out.len().min(self.len());
+        out[..count].copy_from_slice(&self.data[self.pos..][..count]);
+        self.pos += count;
+        if self.is_empty() {
+            self.data.clear();
+            self.pos = 0;
+        }
+        count
+    }
+}
+
+/// Errors from [Inflater::feed], corresponding to `Z_NEED_DICT` and
+/// `Z_DATA_ERROR`.
+#[derive(Debug, PartialEq, Eq)]
+enum InflateError {
+    NeedDict,
+    Data(&'static str),
+}
+impl InflateError {
+    fn code(&self) -> i32 {
+        match self {
+            InflateError::NeedDict => Z_NEED_DICT,
+            InflateError::Data(_) => Z_DATA_ERROR,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Phase {
+    Header,
+    Body,
+    Trailer,
+    Done,
+}
+
+struct Inflater {
+    wrapper: Wrapper,
+    window_bits: u8,
+    decompress: Decompress,
+    phase: Phase,
+    /// Header or trailer bytes seen so far.
+    partial: Vec<u8>,
+    /// Adler-32 or CRC-32 of the output so far.
+    check: u32,
+    /// Length of the output so far, modulo 2^32 like gzip's `ISIZE`.
+    output_len: u32,
+    pending: Pending,
+}
+impl Inflater {
+    fn new(wrapper: Wrapper, window_bits: u8) -> Inflater {
+        Inflater {
+            wrapper,
+            window_bits,
+            decompress: Decompress::new(false),
+            phase: Phase::Header,
+            partial: Vec::new(),
+            check: Self::initial_check(wrapper),
+            output_len: 0,
+            pending: Pending::default(),
+        }
+    }
+
+    fn initial_check(wrapper: Wrapper) -> u32 {
+        match wrapper {
+            Wrapper::Gzip => crc32_update(0, &[]),
+            _ => adler32_update(1, &[]),
+        }
+    }
+
+    fn reset(&mut self, wrapper: Wrapper) {
+        let window_bits = self.window_bits;
+        let pending = std::mem::take(&mut self.pending);
+        *self = Inflater::new(wrapper, window_bits);
+        self.pending = pending;
+    }
+
+    fn is_done(&self) -> bool {
+        self.phase == Phase::Done && self.pending.is_empty()
+    }
+
+    /// Check the header bytes collected so far. Returns `true` once the header
+    /// is complete.
+    fn check_header(&mut self) -> Result<bool, InflateError> {
+        let header = &self.partial[..];
+        if self.wrapper == Wrapper::Auto {
+            if header.len() < 2 {
+                return Ok(false);
+            }
+            self.wrapper = if header[..2] == [0x1f, 0x8b] {
+                Wrapper::Gzip
+            } else {
+                Wrapper::Zlib
+            };
+            self.check = Self::initial_check(self.wrapper);
+        }
+        match self.wrapper {
+            Wrapper::Zlib => {
+                let &[cmf, flg] = header else {
+                    return Ok(false);
+                };
+                if (u16::from(cmf) << 8 | u16::from(flg)) % 31 != 0 {
+                    return Err(InflateError::Data("incorrect header check"));
+                }
+                if i32::from(cmf & 0xf) != Z_DEFLATED {
+                    return Err(InflateError::Data("unknown compression method"));
+                }
+                if (cmf >> 4) + 8 > self.window_bits {
+                    return Err(InflateError::Data("invalid window size"));
+                }
+                if flg & 0x20 != 0 {
+                    log!("TODO: zlib stream with a preset dictionary");
+                    return Err(InflateError::NeedDict);
+                }
+                Ok(true)
+            }
+            Wrapper::Gzip => Ok(gzip_header_len(header)?.is_some()),
+            Wrapper::Raw | Wrapper::Auto => unreachable!(),
+        }
+    }
+
+    fn check_trailer(&self) -> Result<(), InflateError> {
+        let trailer = &self.partial[..];
+        match self.wrapper {
+            Wrapper::Zlib => {
+                if u32::from_be_bytes(trailer.try_into().unwrap()) != self.check {
+                    return Err(InflateError::Data("incorrect data check"));
+                }
+            }
+            Wrapper::Gzip => {
+                if u32::from_le_bytes(trailer[..4].try_into().unwrap()) != self.check {
+                    return Err(InflateError::Data("incorrect data check"));
+                }
+                if u32::from_le_bytes(trailer[4..].try_into().unwrap()) != self.output_len {
+                    return Err(InflateError::Data("incorrect length check"));
+                }
+            }
+            Wrapper::Raw | Wrapper::Auto => unreachable!(),
+        }
+        Ok(())
+    }
+
+    /// Decompress as much of `input` as possible, returning how many bytes
+    /// were consumed. Input after the end of the stream isn't consumed.
+    fn feed(&mut self, input: &[u8]) -> Result<usize, InflateError> {
+        let mut pos = 0;
+        loop {
+            match self.phase {
+                Phase::Header => {
+                    if self.wrapper == Wrapper::Raw {
+                        self.phase = Phase::Body;
+                        continue;
+                    }
+                    // Headers are short, so going byte-by-byte is fine.
+                    let mut complete = false;
+                    while !complete && pos < input.len() {
+                        self.partial.push(input[pos]);
+                        pos += 1;
+                        complete = self.check_header()?;
+                    }
+                    if !complete {
+                        return Ok(pos);
+                    }
+                    self.partial.clear();
+                    self.phase = Phase::Body;
+                }
+                Phase::Body => {
+                    let old_len = self.pending.data.len();
+                    let (consumed, status) = decompress_into(
+                        &mut self.decompress,
+                        &input[pos..],
+                        &mut self.pending.data,
+                    )
+                    .map_err(|_| InflateError::Data("invalid deflate data"))?;
+                    pos += consumed;
+                    let output = &self.pending.data[old_len..];
+                    self.check = match self.wrapper {
+                        Wrapper::Gzip => crc32_update(self.check, output),
+                        _ => adler32_update(self.check, output),
+                    };
+                    self.output_len = self.output_len.wrapping_add(output.len() as u32);
+                    if status != Status::StreamEnd {
+                        return Ok(pos);
+                    }
+                    self.phase = match self.wrapper {
+                        Wrapper::Raw => Phase::Done,
+                        _ => Phase::Trailer,
+                    };
+                }
+                Phase::Trailer => {
+                    let trailer_len = match self.wrapper {
+                        Wrapper::Gzip => 8,
+                        _ => 4,
+                    };
+                    let count = (trailer_len - self.partial.len()).min(input.len() - pos);
+                    self.partial.extend_from_slice(&input[pos..][..count]);
+                    pos += count;
+                    if self.partial.len() < trailer_len {
+                        return Ok(pos);
+                    }
+                    self.check_trailer()?;
+                    self.phase = Phase::Done;
+                }
+                Phase::Done => return Ok(pos),
+            }
+        }
+    }
+}
+
+/// Get the length of a gzip header, or [None] if it is incomplete.
+fn gzip_header_len(header: &[u8]) -> Result<Option<usize>, InflateError> {
+    const FHCRC: u8 = 0x02;
+    const FEXTRA: u8 = 0x04;
+    const FNAME: u8 = 0x08;
+    const FCOMMENT: u8 = 0x10;
+
+    if header.iter().zip([0x1f, 0x8b]).any(|(&a, b)| a != b) {
+        return Err(InflateError::Data("incorrect header check"));
+    }
+    if header
+        .get(2)
+        .is_some_and(|&method| i32::from(method) != Z_DEFLATED)
+    {
+        return Err(InflateError::Data("unknown compression method"));
+    }
+    if header.get(3).is_some_and(|&flags| flags & 0xe0 != 0) {
+        return Err(InflateError::Data("unknown header flags set"));
+    }
+    if header.len() < 10 {
+        return Ok(None);
+    }
+
+    let flags = header[3];
+    let mut len = 10;
+    if flags & FEXTRA != 0 {
+        let Some(&[lo, hi]) = header.get(len..len + 2) else {
+            return Ok(None);
+        };
+        len += 2 + usize::from(u16::from_le_bytes([lo, hi]));
+    }
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            // Null-terminated string
+            let Some(nul) = header
+                .get(len..)
+                .and_then(|s| s.iter().position(|&b| b == 0))
+            else {
+                return Ok(None);
+            };
+            len += nul + 1;
+        }
+    }
+    if flags & FHCRC != 0 {
+        len += 2;
+    }
+    Ok((header.len() >= len).then_some(len))
+}
+
+/// Run `decompress` on `input`, appending to `output`, until it needs more
+/// input or the stream ends. Returns the number of bytes consumed.
+fn decompress_into(
+    decompress: &mut Decompress,
+    input: &[u8],
+    output: &mut Vec<u8>,
+) -> Result<(usize, Status), flate2::DecompressError> {
+    let start = decompress.total_in();
+    loop {
+        output.reserve(CHUNK_SIZE);
+        let consumed = (decompress.total_in() - start) as usize;
+        let status =
+            decompress.decompress_vec(&input[consumed..], output, FlushDecompress::None)?;
+        let consumed = (decompress.total_in() - start) as usize;
+        // If there was output space to spare, it must be waiting for input.
+        if status == Status::StreamEnd || output.len() < output.capacity() {
+            return Ok((consumed, status));
+        }
+    }
+}
+
+struct Deflater {
+    wrapper: Wrapper,
+    level: i32,
+    window_bits: u8,
+    compress: Compress,
+    header_written: bool,
+    finished: bool,
+    /// Adler-32 or CRC-32 of the input so far.
+    check: u32,
+    /// Length of the input so far, modulo 2^32 like gzip's `ISIZE`.
+    input_len: u32,
+    pending: Pending,
+}
+impl Deflater {
+    fn new(wrapper: Wrapper, level: i32, window_bits: u8) -> Deflater {
+        let level = if level == Z_DEFAULT_COMPRESSION {
+            6
+        } else {
+            level
+        };
+        Deflater {
+            wrapper,
+            level,
+            window_bits,
+            compress: Compress::new(Compression::new(level.try_into().unwrap()), false),
+            header_written: false,
+            finished: false,
+            check: Inflater::initial_check(wrapper),
+            input_len: 0,
+            pending: Pending::default(),
+        }
+    }
+
+    fn reset(&mut self) {
+        *self = Deflater::new(self.wrapper, self.level, self.window_bits);
+    }
+
+    fn write_header(&mut self) {
+        let out = &mut self.pending.data;
+        match self.wrapper {
+            Wrapper::Raw => (),
+            Wrapper::Zlib => {
+                // Same as what zlib's deflate() writes.
+                let level_flags = match self.level {
+                    0..=1 => 0,
+                    2..=5 => 1,
+                    6 => 2,
+                    _ => 3,
+                };
+                let mut header = (Z_DEFLATED as u16 | u16::from(self.window_bits - 8) << 4) << 8;
+                header |= level_flags << 6;
+                header += 31 - (header % 31);
+                out.extend_from_slice(&header.to_be_bytes());
+            }
+            Wrapper::Gzip => {
+                let xfl = match self.level {
+                    9 => 2,
+                    0..=1 => 4,
+                    _ => 0,
+                };
+                // No flags, no modification time.
+                out.extend_from_slice(&[0x1f, 0x8b, Z_DEFLATED as u8, 0, 0, 0, 0, 0, xfl, OS_CODE]);
+            }
+            Wrapper::Auto => unreachable!(),
+        }
+        self.header_written = true;
+    }
+
+    /// Compress all of `input`.
+    fn feed(&mut self, input: &[u8], flush: FlushCompress) {
+        if !self.header_written {
+            self.write_header();
+        }
+        self.check = match self.wrapper {
+            Wrapper::Gzip => crc32_update(self.check, input),
+            _ => adler32_update(self.check, input),
+        };
+        self.input_len = self.input_len.wrapping_add(input.len() as u32);
+
+        let start = self.compress.total_in();
+        let output = &mut self.pending.data;
+        let status = loop {
+            output.reserve(CHUNK_SIZE);
+            let consumed = (self.compress.total_in() - start) as usize;
+            let status = self
+                .compress
+                .compress_vec(&input[consumed..], output, flush)
+                .unwrap();
+            let consumed = (self.compress.total_in() - start) as usize;
+            // If there was output space to spare, everything that this flush
+            // mode requires has been written.
+            if status == Status::StreamEnd
+                || (consumed == input.len() && output.len() < output.capacity())
+            {
+                break status;
+            }
+        };
+
+        if status == Status::StreamEnd {
+            match self.wrapper {
+                Wrapper::Raw => (),
+                Wrapper::Zlib => output.extend_from_slice(&self.check.to_be_bytes()),
+                Wrapper::Gzip => {
+                    output.extend_from_slice(&self.check.to_le_bytes());
+                    output.extend_from_slice(&self.input_len.to_le_bytes());
+                }
+                Wrapper::Auto => unreachable!(),
+            }
+            self.finished = true;
+        }
+    }
+}
+
+fn check_version(env: &Environment, version: ConstPtr<u8>, stream_size: i32) -> bool {
+    // Like zlib, only the major version has to match.
+    !version.is_null()
+        && env.mem.read(version) == ZLIB_VERSION.as_bytes()[0]
+        && stream_size == guest_size_of::<z_stream>() as i32
+}
+
+/// Reset the fields of a `z_stream` that zlib resets when initializing it.
+fn init_stream(env: &mut Environment, strm: MutPtr<z_stream>, stream: Stream, adler: u32) {
+    let mut s = env.mem.read(strm);
+    s.total_in = 0;
+    s.total_out = 0;
+    s.msg = Ptr::null();
+    s.data_type = Z_UNKNOWN;
+    s.adler = adler;
+    env.mem.write(strm, s);
+    env.libc_state.zlib.streams.insert(strm, stream);
+}
+
+fn inflateInit_(
+    env: &mut Environment,
+    strm: MutPtr<z_stream>,
+    version: ConstPtr<u8>,
+    stream_size: i32,
+) -> i32 {
+    inflateInit2_(env, strm, 15, version, stream_size)
+}
+
+fn inflateInit2_(
+    env: &mut Environment,
+    strm: MutPtr<z_stream>,
+    window_bits: i32,
+    version: ConstPtr<u8>,
+    stream_size: i32,
+) -> i32 {
+    if !check_version(env, version, stream_size) {
+        return Z_VERSION_ERROR;
+    }
+    if strm.is_null() {
+        return Z_STREAM_ERROR;
+    }
+    let Some((wrapper, bits)) = parse_window_bits(window_bits, true) else {
+        return Z_STREAM_ERROR;
+    };
+    log_dbg!("inflateInit2_({:?}, {})", strm, window_bits);
+    let inflater = Inflater::new(wrapper, bits);
+    let adler = inflater.check;
+    init_stream(env, strm, Stream::Inflate(inflater), adler);
+    Z_OK
+}
+
+fn inflate(env: &mut Environment, strm: MutPtr<z_stream>, flush: i32) -> i32 {
+    let Some(Stream::Inflate(inflater)) = env.libc_state.zlib.streams.get_mut(&strm) else {
+        return Z_STREAM_ERROR;
+    };
+    let mut s = env.mem.read(strm);
+    if s.next_out.is_null() || (s.next_in.is_null() && s.avail_in != 0) {
+        return Z_STREAM_ERROR;
+    }
+
+    // next_in may be NULL if there's no input (e.g. a final Z_FINISH call).
+    let input: &[u8] = if s.avail_in == 0 {
+        &[]
+    } else {
+        env.mem.bytes_at(s.next_in, s.avail_in)
+    };
+    let result = inflater.feed(input);
+    let consumed: u32 = *result.as_ref().unwrap_or(&0) as u32;
+    let produced = inflater
+        .pending
+        .copy_to(env.mem.bytes_at_mut(s.next_out, s.avail_out)) as u32;
+    let done = inflater.is_done();
+    s.adler = inflater.check;
+
+    s.advance(consumed, produced);
+    let res = match result {
+        Err(err) => {
+            log!("inflate({:?}) failed: {:?}", strm, err);
+            if let InflateError::Data(msg) = err {
+                s.msg = get_string(env, msg);
+            }
+            err.code()
+        }
+        Ok(_) if done => Z_STREAM_END,
+        Ok(_) if (consumed == 0 && produced == 0) || flush == Z_FINISH => Z_BUF_ERROR,
+        Ok(_) => Z_OK,
+    };
+    env.mem.write(strm, s);
+    res
+}
+
+fn inflateReset(env: &mut Environment, strm: MutPtr<z_stream>) -> i32 {
+    let Some(Stream::Inflate(inflater)) = env.libc_state.zlib.streams.get_mut(&strm) else {
+        return Z_STREAM_ERROR;
+    };
+    // The wrapper may have been detected, so this can't use reset().
+    *inflater = Inflater::new(inflater.wrapper, inflater.window_bits);
+    let adler = inflater.check;
+    let Some(stream) = env.libc_state.zlib.streams.remove(&strm) else {
+        unreachable!()
+    };
+    init_stream(env, strm, stream, adler);
+    Z_OK
+}
+
+fn inflateEnd(env: &mut Environment, strm: MutPtr<z_stream>) -> i32 {
+    match env.libc_state.zlib.streams.remove(&strm) {
+        Some(Stream::Inflate(_)) => Z_OK,
+        _ => Z_STREAM_ERROR,
+    }
+}
+
+fn deflateInit_(
+    env: &mut Environment,
+    strm: MutPtr<z_stream>,
+    level: i32,
+    version: ConstPtr<u8>,
+    stream_size: i32,
+) -> i32 {
+    deflateInit2_(env, strm, level, Z_DEFLATED, 15, 8, 0, version, stream_size)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn deflateInit2_(
+    env: &mut Environment,
+    strm: MutPtr<z_stream>,
+    level: i32,
+    method: i32,
+    window_bits: i32,
+    mem_level: i32,
+    strategy: i32,
+    version: ConstPtr<u8>,
+    stream_size: i32,
+) -> i32 {
+    if !check_version(env, version, stream_size) {
+        return Z_VERSION_ERROR;
+    }
+    if strm.is_null() {
+        return Z_STREAM_ERROR;
+    }
+    let Some((wrapper, bits)) = parse_window_bits(window_bits, false) else {
+        return Z_STREAM_ERROR;
+    };
+    if !(level == Z_DEFAULT_COMPRESSION || (0..=9).contains(&level))
+        || method != Z_DEFLATED
+        || !(1..=9).contains(&mem_level)
+        || !(0..=Z_FIXED).contains(&strategy)
+    {
+        return Z_STREAM_ERROR;
+    }
+    if strategy != 0 {
+        log!("TODO: deflate strategy {}, using the default", strategy);
+    }
+    log_dbg!("deflateInit2_({:?}, {}, {})", strm, level, window_bits);
+    let deflater = Deflater::new(wrapper, level, bits);
+    let adler = deflater.check;
+    init_stream(env, strm, Stream::Deflate(deflater), adler);
+    Z_OK
+}
+
+fn deflate(env: &mut Environment, strm: MutPtr<z_stream>, flush: i32) -> i32 {
+    let Some(Stream::Deflate(deflater)) = env.libc_state.zlib.streams.get_mut(&strm) else {
+        return Z_STREAM_ERROR;
+    };
+    let mut s = env.mem.read(strm);
+    let flush_mode = match flush {
+        Z_NO_FLUSH => FlushCompress::None,
+        Z_PARTIAL_FLUSH | Z_BLOCK => FlushCompress::Partial,
+        Z_SYNC_FLUSH => FlushCompress::Sync,
+        Z_FULL_FLUSH => FlushCompress::Full,
+        Z_FINISH => FlushCompress::Finish,
+        _ => return Z_STREAM_ERROR,
+    };
+    if s.next_out.is_null()
+        || (s.next_in.is_null() && s.avail_in != 0)
+        || (deflater.finished && flush != Z_FINISH)
+    {
+        return Z_STREAM_ERROR;
+    }
+    if s.avail_out == 0 {
+        return Z_BUF_ERROR;
+    }
+
+    let consumed = s.avail_in;
+    if !deflater.finished {
+        // next_in may be NULL if there's no input (e.g. a final Z_FINISH call).
+        let input: &[u8] = if consumed == 0 {
+            &[]
+        } else {
+            env.mem.bytes_at(s.next_in, consumed)
+        };
+        deflater.feed(input, flush_mode);
+    }
+    let produced = deflater
+        .pending
+        .copy_to(env.mem.bytes_at_mut(s.next_out, s.avail_out)) as u32;
+    let done = deflater.finished && deflater.pending.is_empty();
+    s.adler = deflater.check;
+
+    s.advance(consumed, produced);
+    env.mem.write(strm, s);
+    if done {
+        Z_STREAM_END
+    } else if consumed == 0 && produced == 0 {
+        Z_BUF_ERROR
+    } else {
+        Z_OK
+    }
+}
+
+fn deflateReset(env: &mut Environment, strm: MutPtr<z_stream>) -> i32 {
+    let Some(Stream::Deflate(deflater)) = env.libc_state.zlib.streams.get_mut(&strm) else {
+        return Z_STREAM_ERROR;
+    };
+    deflater.reset();
+    let adler = deflater.check;
+    let Some(stream) = env.libc_state.zlib.streams.remove(&strm) else {
+        unreachable!()
+    };
+    init_stream(env, strm, stream, adler);
+    Z_OK
+}
+
+fn deflateEnd(env: &mut Environment, strm: MutPtr<z_stream>) -> i32 {
+    match env.libc_state.zlib.streams.remove(&strm) {
+        Some(Stream::Deflate(_)) => Z_OK,
+        _ => Z_STREAM_ERROR,
+    }
+}
+
+fn compress_bound(source_len: u32) -> u32 {
+    // Same formula as zlib. The extra 13 bytes are for the zlib wrapper and
+    // the end of the last block.
+    source_len + (source_len >> 12) + (source_len >> 14) + (source_len >> 25) + 13
+}
+
+fn compressBound(_env: &mut Environment, source_len: u32) -> u32 {
+    compress_bound(source_len)
+}
+
+fn deflateBound(_env: &mut Environment, _strm: MutPtr<z_stream>, source_len: u32) -> u32 {
+    // Enough for a gzip wrapper too.
+    compress_bound(source_len) + 12
+}
+
+fn compress(
+    env: &mut Environment,
+    dest: MutPtr<u8>,
+    dest_len: MutPtr<u32>,
+    source: ConstPtr<u8>,
+    source_len: u32,
+) -> i32 {
+    compress2(
+        env,
+        dest,
+        dest_len,
+        source,
+        source_len,
+        Z_DEFAULT_COMPRESSION,
+    )
+}
+
+fn compress2(
+    env: &mut Environment,
+    dest: MutPtr<u8>,
+    dest_len: MutPtr<u32>,
+    source: ConstPtr<u8>,
+    source_len: u32,
+    level: i32,
+) -> i32 {
+    if !(level == Z_DEFAULT_COMPRESSION || (0..=9).contains(&level)) {
+        return Z_STREAM_ERROR;
+    }
+    let mut deflater = Deflater::new(Wrapper::Zlib, level, 15);
+    deflater.feed(env.mem.bytes_at(source, source_len), FlushCompress::Finish);
+    let out_len: u32 = deflater.pending.len().try_into().unwrap();
+    log_dbg!(
+        "compress2({:#x} bytes, {}) => {:#x} bytes",
+        source_len,
+        level,
+        out_len
+    );
+    if out_len > env.mem.read(dest_len) {
+        return Z_BUF_ERROR;
+    }
+    deflater
+        .pending
+        .copy_to(env.mem.bytes_at_mut(dest, out_len));
+    env.mem.write(dest_len, out_len);
+    Z_OK
+}
+
+fn uncompress(
+    env: &mut Environment,
+    dest: MutPtr<u8>,
+    dest_len: MutPtr<u32>,
+    source: ConstPtr<u8>,
+    source_len: u32,
+) -> i32 {
+    let mut inflater = Inflater::new(Wrapper::Zlib, 15);
+    let res = inflater.feed(env.mem.bytes_at(source, source_len));
+    let out_len: u32 = inflater.pending.len().try_into().unwrap();
+    log_dbg!(
+        "uncompress({:#x} bytes) => {:?}, {:#x} bytes",
+        source_len,
+        res,
+        out_len
+    );
+    if res.is_err() {
+        // zlib's uncompress() turns Z_NEED_DICT into this too.
+        return Z_DATA_ERROR;
+    }
+    let avail = env.mem.read(dest_len);
+    let res = if out_len > avail {
+        Z_BUF_ERROR
+    } else if inflater.phase != Phase::Done {
+        // Truncated input
+        Z_DATA_ERROR
+    } else {
+        Z_OK
+    };
+    let copied = inflater
+        .pending
+        .copy_to(env.mem.bytes_at_mut(dest, out_len.min(avail)));
+    env.mem.write(dest_len, copied as u32);
+    res
+}
+
+fn crc32(env: &mut Environment, crc: u32, buf: ConstPtr<u8>, len: u32) -> u32 {
+    if buf.is_null() {
+        return 0; // initial value
+    }
+    crc32_update(crc, env.mem.bytes_at(buf, len))
+}
+
+fn adler32(env: &mut Environment, adler: u32, buf: ConstPtr<u8>, len: u32) -> u32 {
+    if buf.is_null() {
+        return 1; // initial value
+    }
+    adler32_update(adler, env.mem.bytes_at(buf, len))
+}
+
+fn zlibVersion(env: &mut Environment) -> ConstPtr<u8> {
+    get_string(env, ZLIB_VERSION)
+}
+
+/// Host state for a `gzFile`, which is opaque to the app.
+struct GzFile {
+    fd: FileDescriptor,
+    kind: GzFileKind,
+}
+
+enum GzFileKind {
+    Read {
+        /// Data read from the file but not yet consumed.
+        input: Vec<u8>,
+        reached_eof: bool,
+        /// [None] if the file isn't compressed, in which case gzread() reads
+        /// it as-is, or if that isn't known yet.
+        inflater: Option<Inflater>,
+        transparent: bool,
+    },
+    Write(Deflater),
+}
+
+fn gzopen(env: &mut Environment, path: ConstPtr<u8>, mode: ConstPtr<u8>) -> MutVoidPtr {
+    let mode_str = String::from_utf8_lossy(env.mem.cstr_at(mode)).into_owned();
+    let mut level = Z_DEFAULT_COMPRESSION;
+    let mut flags = None;
+    for c in mode_str.bytes() {
+        match c {
+            b'r' => flags = Some(O_RDONLY),
+            b'w' => flags = Some(O_WRONLY | O_CREAT | O_TRUNC),
+            b'a' => flags = Some(O_WRONLY | O_CREAT | O_APPEND),
+            b'0'..=b'9' => level = i32::from(c - b'0'),
+            // binary flag does nothing on UNIX, strategies aren't supported
+            _ => (),
+        }
+    }
+    let Some(flags) = flags else {
+        return Ptr::null();
+    };
+
+    let fd = posix_io::open_direct(env, path, flags);
+    if fd == -1 {
+        return Ptr::null();
+    }
+    let kind = if flags == O_RDONLY {
+        GzFileKind::Read {
+            input: Vec::new(),
+            reached_eof: false,
+            inflater: None,
+            transparent: false,
+        }
+    } else {
+        GzFileKind::Write(Deflater::new(Wrapper::Gzip, level, 15))
+    };
+    // The contents of this don't matter, it just needs a unique address.
+    let file = env.mem.alloc(guest_size_of::<FileDescriptor>());
+    log_dbg!("gzopen({:?}, {:?}) => {:?}", path, mode_str, file);
+    env.libc_state
+        .zlib
+        .gz_files
+        .insert(file, GzFile { fd, kind });
+    file
+}
+
+fn gzread(env: &mut Environment, file: MutVoidPtr, buf: MutVoidPtr, len: u32) -> i32 {
+    let Some(gz_file) = env.libc_state.zlib.gz_files.get_mut(&file) else {
+        return -1;
+    };
+    let GzFileKind::Read {
+        input,
+        reached_eof,
+        inflater,
+        transparent,
+    } = &mut gz_file.kind
+    else {
+        return -1;
+    };
+    let out = env.mem.bytes_at_mut(buf.cast(), len);
+    let mut written = 0;
+    while written < out.len() {
+        if let Some(inflater) = inflater.as_mut() {
+            written += inflater.pending.copy_to(&mut out[written..]);
+            if written == out.len() {
+                break;
+            }
+            if inflater.is_done() {
+                // There may be another gzip member after this one.
+                if input.len() < 2 && !*reached_eof {
+                    // Need more input to tell.
+                } else if input.starts_with(&[0x1f, 0x8b]) {
+                    inflater.reset(Wrapper::Gzip);
+                } else {
+                    break;
+                }
+            }
+        } else if *transparent {
+            let count = input.len().min(out.len() - written);
+            out[written..][..count].copy_from_slice(&input[..count]);
+            input.drain(..count);
+            written += count;
+            if written == out.len() {
+                break;
+            }
+        } else if input.len() >= 2 || *reached_eof {
+            if input.starts_with(&[0x1f, 0x8b]) {
+                *inflater = Some(Inflater::new(Wrapper::Gzip, 15));
+            } else {
+                *transparent = true;
+            }
+            continue;
+        }
+
+        if let Some(inflater) = inflater.as_mut() {
+            if !input.is_empty() && !inflater.is_done() {
+                match inflater.feed(input) {
+                    Ok(consumed) => {
+                        input.drain(..consumed);
+                        continue;
+                    }
+                    Err(err) => {
+                        log!("gzread({:?}) failed: {:?}", file, err);
+                        return -1;
+                    }
+                }
+            }
+        }
+        if *reached_eof {
+            break;
+        }
+        let mut host_file = env.libc_state.posix_io.file_for_fd(gz_file.fd).unwrap();
+        let old_len = input.len();
+        input.resize(old_len + CHUNK_SIZE, 0);
+        match host_file.file.read(&mut input[old_len..]) {
+            Ok(count) => {
+                input.truncate(old_len + count);
+                *reached_eof = count == 0;
+            }
+            Err(e) => {
+                log!("gzread({:?}) encountered error {:?}", file, e);
+                return -1;
+            }
+        }
+    }
+    log_dbg!(
+        "gzread({:?}, {:?}, {:#x}) => {:#x}",
+        file,
+        buf,
+        len,
+        written
+    );
+    written.try_into().unwrap()
+}
+
+/// Write any pending output of a gzip file being written.
+fn gz_flush_pending(env: &mut Environment, gz_file: &mut GzFile) -> bool {
+    let GzFileKind::Write(deflater) = &mut gz_file.kind else {
+        unreachable!()
+    };
+    let pending = &mut deflater.pending;
+    let mut host_file = env.libc_state.posix_io.file_for_fd(gz_file.fd).unwrap();
+    let res = host_file.file.write_all(&pending.data[pending.pos..]);
+    *pending = Pending::default();
+    res.is_ok()
+}
+
+fn gzwrite(env: &mut Environment, file: MutVoidPtr, buf: ConstVoidPtr, len: u32) -> i32 {
+    let Some(mut gz_file) = env.libc_state.zlib.gz_files.remove(&file) else {
+        return 0;
+    };
+    let res = if let GzFileKind::Write(deflater) = &mut gz_file.kind {
+        deflater.feed(env.mem.bytes_at(buf.cast(), len), FlushCompress::None);
+        if gz_flush_pending(env, &mut gz_file) {
+            len.try_into().unwrap()
+        } else {
+            0
+        }
+    } else {
+        0
+    };
+    env.libc_state.zlib.gz_files.insert(file, gz_file);
+    res
+}
+
+fn gzeof(env: &mut Environment, file: MutVoidPtr) -> i32 {
+    let Some(GzFile {
+        kind:
+            GzFileKind::Read {
+                input,
+                reached_eof,
+                inflater,
+                ..
+            },
+        ..
+    }) = env.libc_state.zlib.gz_files.get(&file)
+    else {
+        return 0;
+    };
+    let no_more_output = match inflater {
+        Some(inflater) => inflater.pending.is_empty(),
+        None => input.is_empty(),
+    };
+    (*reached_eof && no_more_output).into()
+}
+
+fn gzclose(env: &mut Environment, file: MutVoidPtr) -> i32 {
+    let Some(mut gz_file) = env.libc_state.zlib.gz_files.remove(&file) else {
+        return Z_STREAM_ERROR;
+    };
+    let mut res = Z_OK;
+    if let GzFileKind::Write(deflater) = &mut gz_file.kind {
+        deflater.feed(&[], FlushCompress::Finish);
+        if !gz_flush_pending(env, &mut gz_file) {
+            res = Z_ERRNO;
+        }
+    }
+    posix_io::close(env, gz_file.fd);
+    env.mem.free(file);
+    res
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(zlibVersion()),
+    export_c_func!(inflateInit_(_, _, _)),
+    export_c_func!(inflateInit2_(_, _, _, _)),
+    export_c_func!(inflate(_, _)),
+    export_c_func!(inflateReset(_)),
+    export_c_func!(inflateEnd(_)),
+    export_c_func!(deflateInit_(_, _, _, _)),
+    export_c_func!(deflateInit2_(_, _, _, _, _, _, _, _)),
+    export_c_func!(deflate(_, _)),
+    export_c_func!(deflateReset(_)),
+    export_c_func!(deflateEnd(_)),
+    export_c_func!(deflateBound(_, _)),
+    export_c_func!(compressBound(_)),
+    export_c_func!(compress(_, _, _, _)),
+    export_c_func!(compress2(_, _, _, _, _)),
+    export_c_func!(uncompress(_, _, _, _)),
+    export_c_func!(crc32(_, _, _)),
+    export_c_func!(adler32(_, _, _)),
+    export_c_func!(gzopen(_, _)),
+    export_c_func!(gzread(_, _, _)),
+    export_c_func!(gzwrite(_, _, _)),
+    export_c_func!(gzeof(_)),
+    export_c_func!(gzclose(_)),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checksums() {
+        // Well-known check values
+        assert_eq!(crc32_update(0, b"123456789"), 0xcbf43926);
+        assert_eq!(adler32_update(1, b"Wikipedia"), 0x11e60398);
+        // Incremental use gives the same result.
+        assert_eq!(crc32_update(crc32_update(0, b"1234"), b"56789"), 0xcbf43926);
+        let big = vec![0xffu8; 100_000];
+        assert_eq!(
+            adler32_update(adler32_update(1, &big[..12345]), &big[12345..]),
+            adler32_update(1, &big)
+        );
+    }
+
+    fn round_trip(wrapper: Wrapper, window_bits: u8, data: &[u8]) {
+        let mut deflater = Deflater::new(wrapper, Z_DEFAULT_COMPRESSION, window_bits);
+        deflater.feed(&data[..data.len() / 2], FlushCompress::None);
+        deflater.feed(&data[data.len() / 2..], FlushCompress::Finish);
+        assert!(deflater.finished);
+        let compressed = &deflater.pending.data;
+
+        let inflate_wrapper = if wrapper == Wrapper::Raw {
+            Wrapper::Raw
+        } else {
+            Wrapper::Auto
+        };
+        let mut inflater = Inflater::new(inflate_wrapper, window_bits);
+        // Feed one byte at a time to exercise the header and trailer handling.
+        for &byte in compressed.iter() {
+            assert_eq!(inflater.feed(&[byte]), Ok(1));
+        }
+        assert_eq!(inflater.feed(b"trailing garbage"), Ok(0));
+        assert!(inflater.phase == Phase::Done);
+        assert_eq!(inflater.pending.data, data);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let data: Vec<u8> = (0..50_000u32).map(|i| (i * i % 251) as u8).collect();
+        round_trip(Wrapper::Raw, 15, &data);
+        round_trip(Wrapper::Zlib, 15, &data);
+        round_trip(Wrapper::Gzip, 15, &data);
+        round_trip(Wrapper::Zlib, 15, b"");
+    }
+
+    #[test]
+    fn test_known_vectors() {
+        // Produced by the real zlib: compress("hello, hello, hello!") and the
+        // same with gzip, with a file name in the header.
+        let zlib = [
+            0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0xd7, 0x51, 0xc8, 0x40, 0xa2, 0x14, 0x01,
+            0x4b, 0x1e, 0x06, 0xf6,
+        ];
+        let mut inflater = Inflater::new(Wrapper::Zlib, 15);
+        assert_eq!(inflater.feed(&zlib), Ok(zlib.len()));
+        assert_eq!(inflater.pending.data, b"hello, hello, hello!");
+
+        let mut corrupt = zlib;
+        corrupt[zlib.len() - 1] ^= 1;
+        let mut inflater = Inflater::new(Wrapper::Zlib, 15);
+        assert_eq!(
+            inflater.feed(&corrupt),
+            Err(InflateError::Data("incorrect data check"))
+        );
+
+        let gzip = [
+            0x1f, 0x8b, 0x08, 0x08, 0x00, 0x00, 0x00, 0x00, 0x02, 0xff, 0x61, 0x2e, 0x74, 0x78,
+            0x74, 0x00, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0xd7, 0x51, 0xc8, 0x40, 0xa2, 0x14, 0x01,
+            0xa7, 0xbb, 0xd2, 0xfe, 0x14, 0x00, 0x00, 0x00,
+        ];
+        assert_eq!(gzip_header_len(&gzip[..14]), Ok(None));
+        assert_eq!(gzip_header_len(&gzip), Ok(Some(16)));
+        let mut inflater = Inflater::new(Wrapper::Auto, 15);
+        assert_eq!(inflater.feed(&gzip), Ok(gzip.len()));
+        assert_eq!(inflater.pending.data, b"hello, hello, hello!");
+    }
+}
@@ -15,9 +15,13 @@
 //! * [Memory Usage Performance Guidelines](https://developer.apple.com/library/archive/documentation/Performance/Conceptual/ManagingMemory/ManagingMemory.html)
 
 use crate::libc::wchar::wchar_t;
+use crate::ThreadId;
+use std::ops::RangeInclusive;
 
 mod allocator;
 
+pub use allocator::PointerStatus;
+
 /// Equivalent of `usize` for guest memory.
 pub type GuestUSize = u32;
 
@@ -30,8 +34,8 @@ pub const fn guest_size_of<T: Sized>() -> GuestUSize {
     std::mem::size_of::<T>() as u32
 }
 
-/// Internal type for representing an untyped virtual address.
-type VAddr = GuestUSize;
+/// Type for representing an untyped virtual address.
+pub type VAddr = GuestUSize;
 
 /// Pointer type for guest memory, or the "guest pointer" type.
 ///
@@ -235,7 +239,31 @@ pub struct Mem {
 
     allocator: allocator::Allocator,
 
-    pub secondary_thread_stack_size_override: Option<GuestUSize>,
+    /// Size of the main thread's stack, see [Self::main_thread_stack].
+    main_thread_stack_size: GuestUSize,
+
+    /// Guard pages below each thread's stack, which are checked like the null
+    /// segment so that a stack overflow can't silently corrupt other memory.
+    stack_guards: Vec<StackGuard>,
+
+    /// Set when an access to a stack guard page is caught, so the cause of the
+    /// resulting CPU memory error can be reported properly.
+    stack_guard_hit: std::cell::Cell<Option<ThreadId>>,
+}
+
+/// See [Mem::add_stack_guard].
+#[derive(Copy, Clone)]
+struct StackGuard {
+    base: VAddr,
+    thread: ThreadId,
+}
+impl StackGuard {
+    fn overlaps(&self, at: VAddr, size: GuestUSize) -> bool {
+        // Avoid overflow at the top of the address space.
+        let (at, size) = (u64::from(at), u64::from(size.max(1)));
+        let base = u64::from(self.base);
+        at < base + u64::from(Mem::STACK_GUARD_SIZE) && base < at + size
+    }
 }
 
 impl Drop for Mem {
@@ -249,32 +277,37 @@ impl Drop for Mem {
 
 impl Mem {
     /// [According to Apple](https://developer.apple.com/library/archive/documentation/Cocoa/Conceptual/Multithreading/CreatingThreads/CreatingThreads.html)
-    /// among others, the iPhone OS main thread stack size is 1MiB.
-    pub const MAIN_THREAD_STACK_SIZE: GuestUSize = 1024 * 1024;
-
-    /// Address of the lowest byte (not the base) of the main thread's stack.
-    ///
-    /// We are arbitrarily putting the stack at the top of the virtual address
-    /// space (see also: stack.rs), I have no idea if this matches iPhone OS.
-    pub const MAIN_THREAD_STACK_LOW_END: VAddr = 0u32.wrapping_sub(Self::MAIN_THREAD_STACK_SIZE);
+    /// among others, the iPhone OS main thread stack size is 1MiB. This can be
+    /// overridden with the `--main-thread-stack-size=` option.
+    pub const DEFAULT_MAIN_THREAD_STACK_SIZE: GuestUSize = 1024 * 1024;
 
-    /// iPhone OS secondary thread stack size.
+    /// iPhone OS secondary thread stack size, unless the app requests another
+    /// size with `pthread_attr_setstacksize`.
     pub const SECONDARY_THREAD_STACK_SIZE: GuestUSize = 512 * 1024;
 
-    /// Create a fresh instance of guest memory.
-    pub fn new() -> Mem {
+    /// Size of the guard region below each thread's stack. Stacks and their
+    /// guards are page-aligned so the CPU can trap accesses to the guard.
+    pub const STACK_GUARD_SIZE: GuestUSize = 0x1000;
+
+    /// Create a fresh instance of guest memory. The main thread stack size must
+    /// be page-aligned.
+    pub fn new(main_thread_stack_size: GuestUSize) -> Mem {
         // This will hopefully get the host OS to lazily allocate the memory.
         let layout = std::alloc::Layout::new::<Bytes>().align_to(4096).unwrap();
         let bytes = unsafe { std::alloc::alloc_zeroed(layout) as *mut Bytes };
 
-        let allocator = allocator::Allocator::new();
+        let allocator = allocator::Allocator::new(main_thread_stack_size);
 
-        Mem {
+        let mut mem = Mem {
             bytes,
             null_segment_size: 0,
             allocator,
-            secondary_thread_stack_size_override: None
-        }
+            main_thread_stack_size,
+            stack_guards: Vec::new(),
+            stack_guard_hit: Default::default(),
+        };
+        mem.add_main_thread_stack_guard();
+        mem
     }
 
     /// Take an existing instance of [Mem], but free and zero all the
@@ -282,21 +315,97 @@ impl Mem {
     ///
     /// Note that, since there is no protection against writing outside an
     /// allocation, there might be stray bytes preserved in the result.
-    pub fn refurbish(mut mem: Mem) -> Mem {
+    pub fn refurbish(mut mem: Mem, main_thread_stack_size: GuestUSize) -> Mem {
         let Mem {
             bytes: _,
             null_segment_size: _,
             ref mut allocator,
             ..
         } = mem;
-        let used_chunks = allocator.reset_and_drain_used_chunks();
+        let used_chunks = allocator.reset_and_drain_used_chunks(main_thread_stack_size);
         for allocator::Chunk { base, size } in used_chunks {
             mem.bytes_mut()[base as usize..][..size.get() as usize].fill(0);
         }
         mem.null_segment_size = 0;
+        mem.main_thread_stack_size = main_thread_stack_size;
+        mem.stack_guards.clear();
+        mem.stack_guard_hit.set(None);
+        mem.add_main_thread_stack_guard();
         mem
     }
 
+    /// The range of addresses occupied by the main thread's stack.
+    ///
+    /// We are arbitrarily putting the stack at the top of the virtual address
+    /// space (see also: stack.rs), I have no idea if this matches iPhone OS.
+    pub fn main_thread_stack(&self) -> RangeInclusive<VAddr> {
+        0u32.wrapping_sub(self.main_thread_stack_size)..=0u32.wrapping_sub(1)
+    }
+
+    pub fn main_thread_stack_size(&self) -> GuestUSize {
+        self.main_thread_stack_size
+    }
+
+    fn add_main_thread_stack_guard(&mut self) {
+        let guard_base = self.main_thread_stack().start() - Self::STACK_GUARD_SIZE;
+        self.add_stack_guard(guard_base, 0);
+    }
+
+    /// Reserve a guard page at `base` for the stack of `thread`, which should
+    /// be directly above it. Accesses to it will panic, and if they came from
+    /// the CPU, [Self::take_stack_guard_hit] will say which thread overflowed.
+    fn add_stack_guard(&mut self, base: VAddr, thread: ThreadId) {
+        assert!(base % 0x1000 == 0);
+        self.allocator
+            .reserve(allocator::Chunk::new(base, Self::STACK_GUARD_SIZE));
+        self.stack_guards.push(StackGuard { base, thread });
+    }
+
+    /// Allocate a page-aligned stack of at least `size` bytes for `thread`,
+    /// with a guard page below it. Returns the range of the stack itself.
+    pub fn alloc_thread_stack(
+        &mut self,
+        size: GuestUSize,
+        thread: ThreadId,
+    ) -> RangeInclusive<VAddr> {
+        let size = size.checked_next_multiple_of(0x1000).unwrap();
+        let total_size = Self::STACK_GUARD_SIZE + size;
+        let guard_base = self.allocator.find_page_aligned_gap(total_size).unwrap();
+        self.add_stack_guard(guard_base, thread);
+        let stack_base = guard_base + Self::STACK_GUARD_SIZE;
+        self.allocator
+            .reserve(allocator::Chunk::new(stack_base, size));
+        stack_base..=(stack_base + size - 1)
+    }
+
+    /// Un-reserve the guard page below the stack of `thread`, which must have
+    /// been allocated with [Self::alloc_thread_stack]. Call this when freeing
+    /// that stack. The CPU will still trap accesses to the page, but that only
+    /// makes them slower.
+    pub fn remove_stack_guard(&mut self, thread: ThreadId) {
+        let index = self
+            .stack_guards
+            .iter()
+            .position(|guard| guard.thread == thread)
+            .unwrap();
+        let guard = self.stack_guards.swap_remove(index);
+        let _ = self.allocator.free(guard.base);
+    }
+
+    /// The ranges of all the stack guard pages. Only for use by
+    /// [crate::cpu::Cpu].
+    pub fn stack_guards(&self) -> impl Iterator<Item = (VAddr, GuestUSize)> + '_ {
+        self.stack_guards
+            .iter()
+            .map(|guard| (guard.base, Self::STACK_GUARD_SIZE))
+    }
+
+    /// If the last memory error was an access to a stack guard page, returns
+    /// the thread whose stack overflowed, and clears it.
+    pub fn take_stack_guard_hit(&self) -> Option<ThreadId> {
+        self.stack_guard_hit.take()
+    }
+
     /// Sets up the null segment of the given size. There's no reason to call
     /// this outside of binary loading, and it won't be respected even if you
     /// do. The size must not have been set already, and must be page aligned.
@@ -344,10 +453,39 @@ impl Mem {
         )
     }
 
+    #[cold]
+    fn stack_guard_fail(&self, at: VAddr, size: GuestUSize, guard: StackGuard) {
+        self.stack_guard_hit.set(Some(guard.thread));
+        panic!(
+            "Attempted access to the stack guard page of thread {} at {:#x} ({:#x} bytes)",
+            guard.thread, at, size
+        )
+    }
+
+    /// Panic if an access would touch the null segment or a stack guard page.
+    #[inline]
+    fn check_access(&self, at: VAddr, size: GuestUSize) {
+        if at < self.null_segment_size {
+            Self::null_check_fail(at, size)
+        }
+        if let Some(guard) = self.find_stack_guard(at, size) {
+            self.stack_guard_fail(at, size, guard)
+        }
+    }
+
+    fn find_stack_guard(&self, at: VAddr, size: GuestUSize) -> Option<StackGuard> {
+        self.stack_guards
+            .iter()
+            .find(|guard| guard.overlaps(at, size))
+            .copied()
+    }
+
     /// Special version of [Self::bytes_at] that returns [None] rather than
     /// panicking on failure. Only for use by [crate::gdb::GdbServer].
     pub fn get_bytes_fallible(&self, addr: ConstVoidPtr, count: GuestUSize) -> Option<&[u8]> {
-        if addr.to_bits() < self.null_segment_size {
+        if addr.to_bits() < self.null_segment_size
+            || self.find_stack_guard(addr.to_bits(), count).is_some()
+        {
             return None;
         }
         self.bytes()
@@ -361,7 +499,9 @@ impl Mem {
         addr: ConstVoidPtr,
         count: GuestUSize,
     ) -> Option<&mut [u8]> {
-        if addr.to_bits() < self.null_segment_size {
+        if addr.to_bits() < self.null_segment_size
+            || self.find_stack_guard(addr.to_bits(), count).is_some()
+        {
             return None;
         }
         self.bytes_mut()
@@ -372,27 +512,23 @@ impl Mem {
     /// Get a slice for reading `count` bytes. This is the basic primitive for
     /// safe read-only memory access.
     ///
-    /// This will panic when `ptr` is within the null page, even if `count` is
-    /// 0. This may be inconvenient in some cases, but it makes the behavior
-    /// when deriving a pointer from the slice consistent (though you should use
-    /// [Self::ptr_at] for that).
+    /// This will panic when `ptr` is within the null page or a stack guard
+    /// page, even if `count` is 0. This may be inconvenient in some cases, but
+    /// it makes the behavior when deriving a pointer from the slice consistent
+    /// (though you should use [Self::ptr_at] for that).
     pub fn bytes_at<const MUT: bool>(&self, ptr: Ptr<u8, MUT>, count: GuestUSize) -> &[u8] {
-        if ptr.to_bits() < self.null_segment_size {
-            Self::null_check_fail(ptr.to_bits(), count)
-        }
+        self.check_access(ptr.to_bits(), count);
         &self.bytes()[ptr.to_bits() as usize..][..count as usize]
     }
     /// Get a slice for reading or writing `count` bytes. This is the basic
     /// primitive for safe read-write memory access.
     ///
-    /// This will panic when `ptr` is within the null page, even if `count` is
-    /// 0. This may be inconvenient in some cases, but it makes the behavior
-    /// when deriving a pointer from the slice consistent (though you should use
-    /// [Self::ptr_at_mut] for that).
+    /// This will panic when `ptr` is within the null page or a stack guard
+    /// page, even if `count` is 0. This may be inconvenient in some cases, but
+    /// it makes the behavior when deriving a pointer from the slice consistent
+    /// (though you should use [Self::ptr_at_mut] for that).
     pub fn bytes_at_mut(&mut self, ptr: MutPtr<u8>, count: GuestUSize) -> &mut [u8] {
-        if ptr.to_bits() < self.null_segment_size {
-            Self::null_check_fail(ptr.to_bits(), count)
-        }
+        self.check_access(ptr.to_bits(), count);
         &mut self.bytes_mut()[ptr.to_bits() as usize..][..count as usize]
     }
 
@@ -469,6 +605,45 @@ impl Mem {
             .copy_within(src..src.checked_add(size).unwrap(), dest)
     }
 
+    /// Like [Self::memmove], but with the same checks as [Self::bytes_at] and
+    /// [Self::bytes_at_mut] for both ranges. Copying a string this way rather
+    /// than [Self::read]ing and [Self::write]ing each byte is much faster.
+    pub fn copy_within_guest(&mut self, dest: MutPtr<u8>, src: ConstPtr<u8>, size: GuestUSize) {
+        self.check_access(src.to_bits(), size);
+        self.check_access(dest.to_bits(), size);
+        let src = src.to_bits() as usize;
+        let end = src.checked_add(size as usize).unwrap();
+        self.bytes_mut()
+            .copy_within(src..end, dest.to_bits() as usize)
+    }
+
+    /// Find the offset of the first occurrence of `byte` within the `max_len`
+    /// bytes starting at `start`, if any.
+    ///
+    /// This is a faster equivalent of [Self::read]ing each byte in turn: the
+    /// memory is searched directly, and only the bytes up to and including the
+    /// one found (or all `max_len` bytes, if there is none) are checked, so
+    /// this panics in exactly the cases the loop would.
+    pub fn find_byte<const MUT: bool>(
+        &self,
+        start: Ptr<u8, MUT>,
+        byte: u8,
+        max_len: GuestUSize,
+    ) -> Option<GuestUSize> {
+        let at = start.to_bits();
+        let bytes = &self.bytes()[at as usize..];
+        let bytes = &bytes[..bytes.len().min(max_len as usize)];
+        let Some(offset) = bytes.iter().position(|&b| b == byte) else {
+            if !bytes.is_empty() {
+                self.check_access(at, bytes.len() as GuestUSize);
+            }
+            return None;
+        };
+        let offset = offset as GuestUSize;
+        self.check_access(at, offset + 1);
+        Some(offset)
+    }
+
     /// Allocate `size` bytes.
     pub fn alloc(&mut self, size: GuestUSize) -> MutVoidPtr {
         let ptr = Ptr::from_bits(self.allocator.alloc(size));
@@ -476,6 +651,20 @@ impl Mem {
         ptr
     }
 
+    /// Allocate `size` bytes at an address that is a multiple of `align`,
+    /// which must be a power of two. The result can be freed like any other
+    /// allocation.
+    pub fn alloc_aligned(&mut self, size: GuestUSize, align: GuestUSize) -> MutVoidPtr {
+        let ptr = Ptr::from_bits(self.allocator.alloc_aligned(size, align));
+        log_dbg!(
+            "Allocated {:?} ({:#x} bytes, aligned to {:#x})",
+            ptr,
+            size,
+            align
+        );
+        ptr
+    }
+
     pub fn realloc(&mut self, old_ptr: MutVoidPtr, size: GuestUSize) -> MutVoidPtr {
         // TODO: for a moment we always assume that we do not have enough size
         //       to realloc inplace
@@ -490,6 +679,24 @@ impl Mem {
         new_ptr
     }
 
+    /// Allocate `size` bytes at a page-aligned address, or return [None] if
+    /// there isn't enough free address space. The size is rounded up to a
+    /// multiple of the page size.
+    pub fn alloc_pages(&mut self, size: GuestUSize) -> Option<MutVoidPtr> {
+        let size = size.checked_next_multiple_of(allocator::PAGE_SIZE)?;
+        let base = self.allocator.find_page_aligned_gap(size)?;
+        self.allocator.reserve(allocator::Chunk::new(base, size));
+        let ptr = Ptr::from_bits(base);
+        log_dbg!("Allocated {:?} ({:#x} bytes, page-aligned)", ptr, size);
+        Some(ptr)
+    }
+
+    /// Get the size of an allocation made with one of the `alloc` methods on
+    /// this type.
+    pub fn allocation_size(&mut self, ptr: MutVoidPtr) -> GuestUSize {
+        self.allocator.find_allocated_size(ptr.to_bits())
+    }
+
     /// Free an allocation made with one of the `alloc` methods on this type.
     pub fn free(&mut self, ptr: MutVoidPtr) {
         let size = self.allocator.free(ptr.to_bits());
@@ -497,6 +704,55 @@ impl Mem {
         log_dbg!("Freed {:?} ({:#x} bytes)", ptr, size);
     }
 
+    /// Free every allocated or reserved byte in the `size` bytes starting at
+    /// `base`, even if that means freeing only part of an allocation. This is
+    /// meant for `vm_deallocate` and similar, not for `free()`.
+    pub fn free_range(&mut self, base: VAddr, size: GuestUSize) {
+        for allocator::Chunk { base, size } in
+            self.allocator.free_range(allocator::Chunk::new(base, size))
+        {
+            self.bytes_at_mut(Ptr::from_bits(base), size.get()).fill(0);
+            log_dbg!("Freed {:#x} ({:#x} bytes)", base, size);
+        }
+    }
+
+    /// Check whether [Self::free_range] can be used on the `size` bytes
+    /// starting at `base`: they must all be allocated or reserved, and none of
+    /// them can be in the null segment or a stack guard page.
+    pub fn can_free_range(&self, base: VAddr, size: GuestUSize) -> bool {
+        if size == 0 {
+            return true;
+        }
+        if base < self.null_segment_size
+            || self
+                .stack_guards
+                .iter()
+                .any(|guard| guard.overlaps(base, size))
+        {
+            return false;
+        }
+        self.allocator
+            .is_range_used(allocator::Chunk::new(base, size))
+    }
+
+    /// Check whether a pointer is the start of, or inside, an allocation made
+    /// with one of the `alloc` methods on this type or a reserved region (such
+    /// as the app binary or the main thread's stack).
+    pub fn validate<T, const MUT: bool>(&self, ptr: Ptr<T, MUT>) -> PointerStatus {
+        self.allocator.validate(ptr.to_bits())
+    }
+
+    /// Number of bytes of guest memory currently allocated or reserved. This is
+    /// a rough stand-in for the app's resident memory size.
+    pub fn used_bytes(&self) -> u64 {
+        self.allocator.used_bytes()
+    }
+
+    /// The largest value [Self::used_bytes] has had so far.
+    pub fn peak_used_bytes(&self) -> u64 {
+        self.allocator.peak_used_bytes()
+    }
+
     /// Allocate memory large enough for a value of type `T` and write the value
     /// to it. Equivalent to [Self::alloc] + [Self::write].
     pub fn alloc_and_write<T>(&mut self, value: T) -> MutPtr<T>
@@ -539,10 +795,9 @@ impl Mem {
     /// Get a C string (null-terminated) as a slice. The null terminator is not
     /// included in the slice.
     pub fn cstr_at<const MUT: bool>(&self, ptr: Ptr<u8, MUT>) -> &[u8] {
-        let mut len = 0;
-        while self.read(ptr + len) != b'\0' {
-            len += 1;
-        }
+        let len = self
+            .find_byte(ptr, b'\0', GuestUSize::MAX)
+            .expect("Unterminated C string");
         self.bytes_at(ptr, len)
     }
 
@@ -559,4 +814,86 @@ impl Mem {
     pub fn reserve(&mut self, base: VAddr, size: GuestUSize) {
         self.allocator.reserve(allocator::Chunk::new(base, size));
     }
+
+    /// Like [Self::reserve], but returns [false] rather than panicking if any
+    /// part of the region is already in use.
+    pub fn try_reserve(&mut self, base: VAddr, size: GuestUSize) -> bool {
+        self.allocator
+            .try_reserve(allocator::Chunk::new(base, size))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConstPtr, Mem, MutPtr, Ptr};
+
+    /// Allocate two chunks next to each other and write a string straddling
+    /// the boundary between them.
+    fn straddling_string(mem: &mut Mem) -> MutPtr<u8> {
+        let a: MutPtr<u8> = mem.alloc(16).cast();
+        let b: MutPtr<u8> = mem.alloc(16).cast();
+        assert_eq!(a + 16, b);
+        let s = a + 10;
+        mem.bytes_at_mut(s, 12).copy_from_slice(b"hello world\0");
+        s
+    }
+
+    #[test]
+    fn test_find_byte() {
+        let mut mem = Mem::new(Mem::DEFAULT_MAIN_THREAD_STACK_SIZE);
+        let s = straddling_string(&mut mem);
+        assert_eq!(mem.find_byte(s, b'\0', u32::MAX), Some(11));
+        assert_eq!(mem.find_byte(s, b'w', u32::MAX), Some(6));
+        assert_eq!(mem.find_byte(s, b'w', 6), None);
+        assert_eq!(mem.find_byte(s, b'w', 7), Some(6));
+        assert_eq!(mem.find_byte(s, b'h', 0), None);
+        assert_eq!(mem.cstr_at(s), b"hello world");
+        assert_eq!(mem.cstr_at(s + 11), b"");
+    }
+
+    #[test]
+    fn test_find_byte_stops_before_stack_guard() {
+        let mut mem = Mem::new(Mem::DEFAULT_MAIN_THREAD_STACK_SIZE);
+        let (guard, _) = mem.stack_guards().next().unwrap();
+        let s: MutPtr<u8> = Ptr::from_bits(guard - 4);
+        mem.bytes_at_mut(s, 4).copy_from_slice(b"abc\0");
+        assert_eq!(mem.cstr_at(s), b"abc");
+        assert_eq!(mem.find_byte(s, b'x', 4), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "stack guard page")]
+    fn test_find_byte_into_stack_guard() {
+        let mut mem = Mem::new(Mem::DEFAULT_MAIN_THREAD_STACK_SIZE);
+        let (guard, _) = mem.stack_guards().next().unwrap();
+        let s: MutPtr<u8> = Ptr::from_bits(guard - 4);
+        mem.bytes_at_mut(s, 4).copy_from_slice(b"abcd");
+        mem.cstr_at(s);
+    }
+
+    #[test]
+    fn test_free_thread_stack() {
+        let mut mem = Mem::new(Mem::DEFAULT_MAIN_THREAD_STACK_SIZE);
+        let used_bytes = mem.used_bytes();
+        let stack = mem.alloc_thread_stack(Mem::SECONDARY_THREAD_STACK_SIZE, 1);
+        assert_eq!(mem.stack_guards().count(), 2);
+        mem.free(Ptr::from_bits(*stack.start()));
+        mem.remove_stack_guard(1);
+        assert_eq!(mem.stack_guards().count(), 1);
+        assert_eq!(mem.used_bytes(), used_bytes);
+    }
+
+    #[test]
+    fn test_copy_within_guest() {
+        let mut mem = Mem::new(Mem::DEFAULT_MAIN_THREAD_STACK_SIZE);
+        let s = straddling_string(&mut mem);
+        let dest: MutPtr<u8> = mem.alloc(12).cast();
+        mem.copy_within_guest(dest, s.cast_const(), 12);
+        assert_eq!(mem.cstr_at(dest), b"hello world");
+
+        // Overlapping copies behave like memmove.
+        let src: ConstPtr<u8> = (s + 6).cast_const();
+        mem.copy_within_guest(s, src, 6);
+        assert_eq!(mem.cstr_at(s), b"world");
+    }
 }
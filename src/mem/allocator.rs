@@ -3,7 +3,7 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
-use super::{GuestUSize, Mem, VAddr};
+use super::{GuestUSize, VAddr};
 use std::collections::BTreeMap;
 use std::num::NonZeroU32;
 
@@ -12,6 +12,9 @@ use std::num::NonZeroU32;
 /// TODO: also do the 4096-byte alignment.
 pub const MIN_CHUNK_SIZE: GuestUSize = 16;
 
+/// See [Allocator::find_page_aligned_gap].
+pub const PAGE_SIZE: GuestUSize = 0x1000;
+
 /// A non-empty range of bytes in virtual address space.
 ///
 /// Similar to [`RangeInclusive<u32>`][std::ops::RangeInclusive] but with a
@@ -134,6 +137,21 @@ mod collections {
         pub fn get_size_with_base(&self, base: VAddr) -> Option<NonZeroU32> {
             self.chunks.get(&base).copied()
         }
+        #[inline(always)]
+        pub fn find_containing(&self, addr: VAddr) -> Option<Chunk> {
+            let (&base, &size) = self.chunks.range(..=addr).next_back()?;
+            let chunk = Chunk { base, size };
+            chunk.contains(addr).then_some(chunk)
+        }
+        pub fn find_overlapping(&self, range: Chunk) -> Vec<Chunk> {
+            let first = self
+                .find_containing(range.base)
+                .map_or(range.base, |c| c.base);
+            self.chunks
+                .range(first..=range.last_byte())
+                .map(|(&base, &size)| Chunk { base, size })
+                .collect()
+        }
     }
 
     #[derive(Default, Debug)]
@@ -179,6 +197,10 @@ mod collections {
             Some(chunk)
         }
 
+        pub fn find_containing(&self, addr: VAddr) -> Option<Chunk> {
+            self.chunks.find_containing(addr)
+        }
+
         fn allocate_in_bucket(&mut self, size: GuestUSize, bucket: usize) -> Option<Chunk> {
             let (idx, _) = {
                 let mut best_chunk: Option<(usize, GuestUSize)> = None;
@@ -239,29 +261,38 @@ mod collections {
                 + 1;
             self.allocate_in_bucket(size, bucket)
         }
-
-        pub fn iter(&self) -> impl Iterator<Item = Chunk> + '_ {
-            self.chunks_by_log2_size
-                .iter()
-                .flat_map(|chunks| chunks.iter())
-                .copied()
-        }
     }
 }
 use collections::{ChunkMap, SizeBucketedChunkMap};
 
+/// Result of [Allocator::validate].
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum PointerStatus {
+    /// The address is the start of an allocation or reserved region.
+    Exact,
+    /// The address is inside the allocation or reserved region that starts at
+    /// the contained address.
+    Interior(VAddr),
+    /// The address is not in use.
+    Unknown,
+}
+
 /// Tracks which memory is in use and makes allocations from it.
 #[derive(Debug)]
 pub struct Allocator {
     used_chunks: ChunkMap,
     unused_chunks: SizeBucketedChunkMap,
+    /// Total size of all the used chunks, in bytes.
+    used_bytes: u64,
+    /// The highest value `used_bytes` has reached.
+    peak_used_bytes: u64,
 }
 
 impl Allocator {
-    pub fn new() -> Allocator {
-        let main_thread_stack =
-            Chunk::new(Mem::MAIN_THREAD_STACK_LOW_END, Mem::MAIN_THREAD_STACK_SIZE);
-        let rest = Chunk::new(0, Mem::MAIN_THREAD_STACK_LOW_END);
+    pub fn new(main_thread_stack_size: GuestUSize) -> Allocator {
+        let main_thread_stack_low_end = 0u32.wrapping_sub(main_thread_stack_size);
+        let main_thread_stack = Chunk::new(main_thread_stack_low_end, main_thread_stack_size);
+        let rest = Chunk::new(0, main_thread_stack_low_end);
 
         let mut used_chunks: ChunkMap = Default::default();
         used_chunks.insert(main_thread_stack);
@@ -272,23 +303,42 @@ impl Allocator {
         Allocator {
             used_chunks,
             unused_chunks,
+            used_bytes: main_thread_stack_size.into(),
+            peak_used_bytes: main_thread_stack_size.into(),
         }
     }
 
+    fn track_used(&mut self, size: GuestUSize) {
+        self.used_bytes += u64::from(size);
+        self.peak_used_bytes = self.peak_used_bytes.max(self.used_bytes);
+    }
+
+    /// Number of bytes currently in use, including reserved regions.
+    pub fn used_bytes(&self) -> u64 {
+        self.used_bytes
+    }
+
+    /// The largest number of bytes that has been in use at any one time.
+    pub fn peak_used_bytes(&self) -> u64 {
+        self.peak_used_bytes
+    }
+
     pub fn reserve(&mut self, chunk: Chunk) {
-        let mut to_trisect = None;
-        for unused_chunk in self.unused_chunks.iter() {
-            if unused_chunk.trisect_by(chunk).is_some() {
-                to_trisect = Some(unused_chunk);
-                break;
-            }
+        if !self.try_reserve(chunk) {
+            panic!("Could not reserve chunk {:?}!", chunk);
         }
+    }
 
-        let Some(to_trisect) = to_trisect else {
-            panic!("Could not reserve chunk {:?}!", chunk);
+    /// Like [Self::reserve], but returns [false] rather than panicking if some
+    /// of the chunk is already in use.
+    pub fn try_reserve(&mut self, chunk: Chunk) -> bool {
+        let Some(to_trisect) = self.unused_chunks.find_containing(chunk.base) else {
+            return false;
+        };
+        let Some((before, after)) = to_trisect.trisect_by(chunk) else {
+            return false;
         };
 
-        let (before, after) = to_trisect.trisect_by(chunk).unwrap();
         self.unused_chunks.remove_with_base(to_trisect.base);
         if let Some(before) = before {
             self.unused_chunks.insert(before);
@@ -297,25 +347,84 @@ impl Allocator {
             self.unused_chunks.insert(after);
         }
         self.used_chunks.insert(chunk);
+        self.track_used(chunk.size.get());
+        true
     }
 
     pub fn alloc(&mut self, size: GuestUSize) -> VAddr {
-        let size = size.max(MIN_CHUNK_SIZE);
-        let size = if size % MIN_CHUNK_SIZE != 0 {
-            size + MIN_CHUNK_SIZE - (size % MIN_CHUNK_SIZE)
-        } else {
-            size
-        };
-
-        let Some(alloc) = self.unused_chunks.allocate(size) else {
+        let Some(base) = self.try_alloc(size) else {
             panic!(
                 "Could not find large enough chunk to allocate {:#x} bytes",
                 size
             );
         };
+        base
+    }
+
+    /// Like [Self::alloc], but returns [None] rather than panicking if there
+    /// is no free chunk large enough.
+    pub fn try_alloc(&mut self, size: GuestUSize) -> Option<VAddr> {
+        let size = Self::chunk_size_for(size)?;
+
+        let alloc = self.unused_chunks.allocate(size)?;
+        self.used_chunks.insert(alloc);
+        self.track_used(size);
+
+        Some(alloc.base)
+    }
+
+    /// Round an allocation size up to a valid chunk size.
+    fn chunk_size_for(size: GuestUSize) -> Option<GuestUSize> {
+        size.max(MIN_CHUNK_SIZE)
+            .checked_next_multiple_of(MIN_CHUNK_SIZE)
+    }
+
+    /// Like [Self::alloc], but the result is a multiple of `align`, which must
+    /// be a power of two.
+    pub fn alloc_aligned(&mut self, size: GuestUSize, align: GuestUSize) -> VAddr {
+        let Some(base) = self.try_alloc_aligned(size, align) else {
+            panic!(
+                "Could not find large enough chunk to allocate {:#x} bytes aligned to {:#x}",
+                size, align
+            );
+        };
+        base
+    }
+
+    /// Like [Self::alloc_aligned], but returns [None] rather than panicking if
+    /// there is no free chunk large enough.
+    pub fn try_alloc_aligned(&mut self, size: GuestUSize, align: GuestUSize) -> Option<VAddr> {
+        assert!(align.is_power_of_two());
+        if align <= MIN_CHUNK_SIZE {
+            return self.try_alloc(size);
+        }
+        let size = Self::chunk_size_for(size)?;
+
+        // Chunks are always aligned to MIN_CHUNK_SIZE, so this much extra space
+        // is enough to find an aligned base somewhere within the chunk. The
+        // slack on either side is then given back.
+        let padded = self
+            .unused_chunks
+            .allocate(size.checked_add(align - MIN_CHUNK_SIZE)?)?;
+        let alloc = Chunk::new(padded.base.next_multiple_of(align), size);
+        let (before, after) = padded.trisect_by(alloc).unwrap();
+        for slack in [before, after].into_iter().flatten() {
+            self.add_unused(slack);
+        }
         self.used_chunks.insert(alloc);
+        self.track_used(size);
+
+        Some(alloc.base)
+    }
 
-        alloc.base
+    /// Find a page-aligned address with at least `size` unused bytes after it,
+    /// without allocating anything. Use [Self::reserve] to claim it.
+    pub fn find_page_aligned_gap(&mut self, size: GuestUSize) -> Option<VAddr> {
+        // The chunks aren't page-aligned, so take a big enough one and then
+        // give it back.
+        let gap = self.try_alloc(size.checked_add(PAGE_SIZE - 1)?)?;
+        let _ = self.free(gap);
+        Some(gap.next_multiple_of(PAGE_SIZE))
     }
 
     /// This is used for realloc
@@ -326,6 +435,16 @@ impl Allocator {
         size.get()
     }
 
+    /// Find out whether an address is part of an allocation or a reserved
+    /// region, so bad pointers from the app can be caught before using them.
+    pub fn validate(&self, addr: VAddr) -> PointerStatus {
+        match self.used_chunks.find_containing(addr) {
+            Some(chunk) if chunk.base == addr => PointerStatus::Exact,
+            Some(chunk) => PointerStatus::Interior(chunk.base),
+            None => PointerStatus::Unknown,
+        }
+    }
+
     /// Returns the size of the freed chunk so it can be zeroed if desired
     #[must_use]
     pub fn free(&mut self, base: VAddr) -> GuestUSize {
@@ -333,27 +452,188 @@ impl Allocator {
             log!("Can't free {:#x}, unknown allocation!", base);
             return 0;
         };
+        self.used_bytes -= u64::from(freed.size.get());
+        self.add_unused(freed);
 
-        if let Some(adjacent) = self
-            .unused_chunks
-            .remove_with_base(freed.last_byte() + 1)
-            .or_else(|| self.unused_chunks.remove_with_end(freed.base))
-        {
-            let combined = Chunk::new(
-                freed.base.min(adjacent.base),
-                freed.size.get() + adjacent.size.get(),
-            );
-            self.unused_chunks.insert(combined);
-        } else {
-            self.unused_chunks.insert(freed);
+        freed.size.get()
+    }
+
+    /// Free every used byte within `range`, regardless of how it was allocated
+    /// or reserved, splitting any chunks that are only partly within it. This
+    /// is what `vm_deallocate` needs. Returns the freed chunks so they can be
+    /// zeroed if desired.
+    #[must_use]
+    pub fn free_range(&mut self, range: Chunk) -> Vec<Chunk> {
+        let mut freed_chunks = Vec::new();
+        for chunk in self.used_chunks.find_overlapping(range) {
+            self.used_chunks.remove_with_base(chunk.base);
+            let freed_base = chunk.base.max(range.base);
+            let freed_last_byte = chunk.last_byte().min(range.last_byte());
+            let freed = Chunk::new(freed_base, freed_last_byte - freed_base + 1);
+            let (before, after) = chunk.trisect_by(freed).unwrap();
+            for kept in [before, after].into_iter().flatten() {
+                self.used_chunks.insert(kept);
+            }
+            self.used_bytes -= u64::from(freed.size.get());
+            self.add_unused(freed);
+            freed_chunks.push(freed);
         }
+        freed_chunks
+    }
 
-        freed.size.get()
+    /// Whether every byte within `range` is part of a used chunk.
+    pub fn is_range_used(&self, range: Chunk) -> bool {
+        // This is a u64 so that it can't overflow at the top of the address
+        // space.
+        let mut next = u64::from(range.base);
+        for chunk in self.used_chunks.find_overlapping(range) {
+            if u64::from(chunk.base) > next {
+                return false;
+            }
+            next = u64::from(chunk.last_byte()) + 1;
+        }
+        next > u64::from(range.last_byte())
     }
 
-    pub(super) fn reset_and_drain_used_chunks(&mut self) -> impl Iterator<Item = Chunk> {
+    /// Return a chunk to the unused pool, merging it with its neighbours.
+    fn add_unused(&mut self, chunk: Chunk) {
+        let mut combined = chunk;
+        if let Some(after) = chunk
+            .last_byte()
+            .checked_add(1)
+            .and_then(|end| self.unused_chunks.remove_with_base(end))
+        {
+            combined = Chunk::new(combined.base, combined.size.get() + after.size.get());
+        }
+        if let Some(before) = self.unused_chunks.remove_with_end(combined.base) {
+            combined = Chunk::new(before.base, before.size.get() + combined.size.get());
+        }
+        self.unused_chunks.insert(combined);
+    }
+
+    pub(super) fn reset_and_drain_used_chunks(
+        &mut self,
+        main_thread_stack_size: GuestUSize,
+    ) -> impl Iterator<Item = Chunk> {
         let chunks = std::mem::take(&mut self.used_chunks);
-        *self = Allocator::new();
+        *self = Allocator::new(main_thread_stack_size);
         chunks.drain()
     }
 }
+
+#[cfg(test)]
+mod allocator_tests {
+    use super::{Allocator, Chunk, PointerStatus, PAGE_SIZE};
+    use crate::mem::Mem;
+    #[test]
+    fn test_used_bytes() {
+        let mut allocator = Allocator::new(Mem::DEFAULT_MAIN_THREAD_STACK_SIZE);
+        let initial = allocator.used_bytes();
+        assert_eq!(initial, Mem::DEFAULT_MAIN_THREAD_STACK_SIZE.into());
+
+        let a = allocator.alloc(100); // rounded up to 112
+        let b = allocator.alloc(16);
+        assert_eq!(allocator.used_bytes(), initial + 112 + 16);
+
+        let _ = allocator.free(a);
+        assert_eq!(allocator.used_bytes(), initial + 16);
+        assert_eq!(allocator.peak_used_bytes(), initial + 112 + 16);
+
+        let _ = allocator.free(b);
+        assert_eq!(allocator.used_bytes(), initial);
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut allocator = Allocator::new(Mem::DEFAULT_MAIN_THREAD_STACK_SIZE);
+        let a = allocator.alloc(32);
+        let b = allocator.alloc(16);
+        assert_eq!(allocator.validate(a), PointerStatus::Exact);
+        assert_eq!(allocator.validate(a + 31), PointerStatus::Interior(a));
+        assert_eq!(allocator.validate(b), PointerStatus::Exact);
+
+        // Reserved regions count as allocations.
+        let stack_low_end = 0u32.wrapping_sub(Mem::DEFAULT_MAIN_THREAD_STACK_SIZE);
+        assert_eq!(
+            allocator.validate(stack_low_end + 0x100),
+            PointerStatus::Interior(stack_low_end)
+        );
+
+        let _ = allocator.free(a);
+        assert_eq!(allocator.validate(a), PointerStatus::Unknown);
+        assert_eq!(allocator.validate(a + 31), PointerStatus::Unknown);
+        assert_eq!(allocator.validate(b), PointerStatus::Exact);
+    }
+
+    #[test]
+    fn test_free_range() {
+        let mut allocator = Allocator::new(Mem::DEFAULT_MAIN_THREAD_STACK_SIZE);
+        let initial = allocator.used_bytes();
+        let base = allocator.find_page_aligned_gap(0x3000).unwrap();
+        assert_eq!(base % PAGE_SIZE, 0);
+        allocator.reserve(Chunk::new(base, 0x1000));
+        allocator.reserve(Chunk::new(base + 0x1000, 0x2000));
+
+        // Free the middle page, which spans both reservations.
+        let freed = allocator.free_range(Chunk::new(base + 0x800, 0x1000));
+        assert_eq!(
+            freed,
+            [
+                Chunk::new(base + 0x800, 0x800),
+                Chunk::new(base + 0x1000, 0x800)
+            ]
+        );
+        assert_eq!(allocator.used_bytes(), initial + 0x2000);
+        assert_eq!(allocator.validate(base), PointerStatus::Exact);
+        assert_eq!(allocator.validate(base + 0x800), PointerStatus::Unknown);
+        assert_eq!(allocator.validate(base + 0x17ff), PointerStatus::Unknown);
+        assert_eq!(allocator.validate(base + 0x1800), PointerStatus::Exact);
+        assert!(!allocator.is_range_used(Chunk::new(base, 0x1000)));
+        assert!(!allocator.is_range_used(Chunk::new(base + 0x1000, 0x1000)));
+
+        // The hole can be reused.
+        assert!(allocator.try_reserve(Chunk::new(base + 0x800, 0x1000)));
+        assert!(!allocator.try_reserve(Chunk::new(base + 0x800, 0x1000)));
+
+        assert!(allocator.is_range_used(Chunk::new(base + 0x800, 0x1000)));
+        assert!(allocator.is_range_used(Chunk::new(base, 0x3000)));
+        assert!(!allocator.is_range_used(Chunk::new(base, 0x4000)));
+        assert!(!allocator.is_range_used(Chunk::new(base + 0x2000, 0x2000)));
+
+        let freed = allocator.free_range(Chunk::new(base, 0x3000));
+        assert_eq!(freed.len(), 3);
+        assert!(!allocator.is_range_used(Chunk::new(base + 0x800, 0x10)));
+        assert_eq!(allocator.used_bytes(), initial);
+        assert!(allocator.try_reserve(Chunk::new(base, 0x3000)));
+    }
+
+    #[test]
+    fn test_alloc_aligned() {
+        let mut allocator = Allocator::new(Mem::DEFAULT_MAIN_THREAD_STACK_SIZE);
+        let initial = allocator.used_bytes();
+        // Make sure the next free chunk isn't already well-aligned.
+        let unaligned = allocator.alloc(16);
+
+        for align in [16, 64, PAGE_SIZE] {
+            let a = allocator.alloc_aligned(100, align);
+            assert_eq!(a % align, 0);
+            assert_eq!(allocator.validate(a), PointerStatus::Exact);
+            assert_eq!(allocator.find_allocated_size(a), 112);
+            // The slack before the allocation is reused.
+            let b = allocator.alloc(16);
+            if align > 16 {
+                assert!(b < a);
+            }
+            let _ = allocator.free(b);
+            let _ = allocator.free(a);
+        }
+        let _ = allocator.free(unaligned);
+        assert_eq!(allocator.used_bytes(), initial);
+
+        // Freed aligned allocations can be reused.
+        let a = allocator.alloc_aligned(PAGE_SIZE, PAGE_SIZE);
+        let _ = allocator.free(a);
+        assert_eq!(allocator.alloc_aligned(PAGE_SIZE, PAGE_SIZE), a);
+        assert_eq!(allocator.used_bytes(), initial + u64::from(PAGE_SIZE));
+    }
+}
@@ -23,32 +23,38 @@ use crate::MutexId;
 use std::collections::HashMap;
 
 mod classes;
+mod ivars;
 mod messages;
 mod methods;
 mod objects;
 mod properties;
 mod selectors;
 mod synchronization;
+mod zombies;
 
 pub use classes::{objc_classes, Class, ClassExports, ClassTemplate};
 pub use messages::{
     autorelease, msg, msg_class, msg_send, msg_send_super2, msg_super, objc_super, release, retain,
 };
+pub use ivars::Ivar;
 pub use methods::{GuestIMP, HostIMP, IMP};
 pub use objects::{
     id, impl_HostObject_with_superclass, nil, AnyHostObject, HostObject, TrivialHostObject,
 };
 pub use selectors::{selector, SEL};
+pub use zombies::ZombieMode;
 
 use classes::{ClassHostObject, FakeClass, UnimplementedClass, CLASS_LISTS};
 use messages::{
     objc_msgSend, objc_msgSendSuper2, objc_msgSend_stret, MsgSendSignature, MsgSendSuperSignature,
 };
+use ivars::ivar_list_t;
 use methods::method_list_t;
 use objects::{objc_object, HostObjectEntry};
 use properties::{objc_copyStruct, objc_setProperty};
 use selectors::sel_registerName;
 use synchronization::{objc_sync_enter, objc_sync_exit};
+use zombies::{ZombieClass, Zombies};
 
 /// Typedef for `NSZone *`. This is a [fossil type] found in the signature of
 /// `allocWithZone:` and similar methods. Its value is always ignored.
@@ -78,6 +84,13 @@ pub struct ObjC {
     /// Type information isn't part of the `objc_msgSend` ABI, so an alternative
     /// channel is needed.
     message_type_info: Option<(std::any::TypeId, &'static str)>,
+
+    /// The `release` selector, which gets extra checking in `objc_msgSend`.
+    /// Set by [ObjC::register_host_selectors].
+    release_selector: Option<SEL>,
+
+    /// Deallocated objects kept around for debugging, see [zombies].
+    zombies: Zombies,
 }
 
 impl ObjC {
@@ -88,6 +101,8 @@ impl ObjC {
             classes: HashMap::new(),
             sync_mutexes: HashMap::new(),
             message_type_info: None,
+            release_selector: None,
+            zombies: Default::default(),
         }
     }
 }
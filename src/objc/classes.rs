@@ -14,7 +14,8 @@ mod class_lists;
 pub(super) use class_lists::CLASS_LISTS;
 
 use super::{
-    id, method_list_t, nil, objc_object, AnyHostObject, HostIMP, HostObject, ObjC, IMP, SEL,
+    id, ivar_list_t, method_list_t, nil, objc_object, AnyHostObject, HostIMP, HostObject, Ivar,
+    ObjC, ZombieClass, IMP, SEL,
 };
 use crate::mach_o::MachO;
 use crate::mem::{guest_size_of, ConstPtr, ConstVoidPtr, GuestUSize, Mem, Ptr, SafeRead};
@@ -45,6 +46,8 @@ pub(super) struct ClassHostObject {
     /// Size of the allocated memory for instances of this class or metaclass.
     /// This is always >= the value in the superclass.
     pub(super) instance_size: GuestUSize,
+    /// Instance variables declared by this class (not its superclasses).
+    pub(super) ivars: HashMap<String, Ivar>,
 }
 impl HostObject for ClassHostObject {}
 
@@ -94,7 +97,7 @@ struct class_rw_t {
     name: ConstPtr<u8>,
     base_methods: ConstPtr<method_list_t>,
     _base_protocols: ConstVoidPtr, // protocol list (TODO)
-    _ivars: ConstVoidPtr,          // ivar list (TODO)
+    ivars: ConstPtr<ivar_list_t>,
     _weak_ivar_layout: u32,
     _base_properties: ConstVoidPtr, // property list (TODO)
 }
@@ -360,6 +363,7 @@ impl ClassHostObject {
             // maybe this should be 0 for NSObject? does it matter?
             _instance_start: size,
             instance_size: size,
+            ivars: HashMap::new(),
         }
     }
 
@@ -372,6 +376,7 @@ impl ClassHostObject {
             instance_size,
             name,
             base_methods,
+            ivars,
             ..
         } = mem.read(data);
 
@@ -384,11 +389,16 @@ impl ClassHostObject {
             methods: HashMap::new(),
             _instance_start: instance_start,
             instance_size,
+            ivars: HashMap::new(),
         };
 
         if !base_methods.is_null() {
             host_object.add_methods_from_bin(base_methods, mem, objc);
         }
+        // Metaclasses' "ivars" would be class variables, which don't exist.
+        if !is_metaclass && !ivars.is_null() {
+            host_object.add_ivars_from_bin(ivars, mem);
+        }
 
         host_object
     }
@@ -474,6 +484,18 @@ impl ObjC {
         self.link_class_inner(name, /* is_metaclass: */ false, mem, false)
     }
 
+    /// For use by host functions: like [Self::get_known_class], but returns
+    /// [None] rather than panicking if neither the app nor the host has an
+    /// implementation of the class.
+    pub fn get_implemented_class(&mut self, name: &str, mem: &mut Mem) -> Option<Class> {
+        if let Some(class) = self.get_class(name, /* is_metaclass: */ false, mem) {
+            let any = self.get_host_object(class).unwrap().as_any();
+            return (!any.is::<UnimplementedClass>()).then_some(class);
+        }
+        Self::find_template(name)?;
+        Some(self.get_known_class(name, mem))
+    }
+
     fn link_class_inner(
         &mut self,
         name: &str,
@@ -657,6 +679,7 @@ impl ObjC {
                         methods: Default::default(),
                         _instance_start: Default::default(),
                         instance_size: Default::default(),
+                        ivars: Default::default(),
                     },
                 );
                 log_dbg!(
@@ -710,6 +733,8 @@ impl ObjC {
             name
         } else if let Some(FakeClass { name, .. }) = host_object.as_any().downcast_ref() {
             name
+        } else if host_object.as_any().is::<ZombieClass>() {
+            super::zombies::ZOMBIE_CLASS_NAME
         } else {
             panic!();
         }
@@ -7,13 +7,14 @@
 //! very long and frequently-updated list.
 
 use crate::frameworks::{
-    av_audio, core_animation, core_foundation, core_graphics, foundation, media_player, opengles,
-    store_kit, uikit,
+    audio_toolbox, av_audio, core_animation, core_foundation, core_graphics, foundation,
+    media_player, opengles, store_kit, uikit,
 };
 
 /// All the lists of classes that the runtime should search through.
 pub const CLASS_LISTS: &[super::ClassExports] = &[
     crate::app_picker::CLASSES, // Not a framework! Special internal classes.
+    audio_toolbox::audio_services::CLASSES,
     core_animation::ca_eagl_layer::CLASSES,
     core_animation::ca_layer::CLASSES,
     core_graphics::cg_data_provider::CLASSES,
@@ -21,31 +22,48 @@ pub const CLASS_LISTS: &[super::ClassExports] = &[
     core_graphics::cg_context::CLASSES,
     core_graphics::cg_image::CLASSES,
     core_foundation::cf_run_loop_timer::CLASSES, // Special internal classes.
+    core_foundation::cf_socket::CLASSES,
+    core_foundation::cf_stream::CLASSES,
     foundation::ns_array::CLASSES,
     foundation::ns_autorelease_pool::CLASSES,
     foundation::ns_bundle::CLASSES,
+    foundation::ns_cache::CLASSES,
     foundation::ns_character_set::CLASSES,
     foundation::ns_coder::CLASSES,
     foundation::ns_data::CLASSES,
     foundation::ns_date::CLASSES,
     foundation::ns_date_formatter::CLASSES,
+    foundation::ns_decimal_number::CLASSES,
     foundation::ns_dictionary::CLASSES,
     foundation::ns_enumerator::CLASSES,
+    foundation::ns_error::CLASSES,
+    foundation::ns_exception::CLASSES,
     foundation::ns_file_manager::CLASSES,
     foundation::ns_file_handle::CLASSES,
+    foundation::ns_http_cookie::CLASSES,
+    foundation::ns_http_cookie_storage::CLASSES,
     foundation::ns_keyed_unarchiver::CLASSES,
     foundation::ns_locale::CLASSES,
     foundation::ns_notification::CLASSES,
     foundation::ns_notification_center::CLASSES,
     foundation::ns_null::CLASSES,
+    foundation::ns_number_formatter::CLASSES,
     foundation::ns_object::CLASSES,
+    foundation::ns_pipe::CLASSES,
     foundation::ns_process_info::CLASSES,
+    foundation::ns_property_list_serialization::CLASSES,
     foundation::ns_run_loop::CLASSES,
     foundation::ns_set::CLASSES,
     foundation::ns_string::CLASSES,
+    foundation::ns_task::CLASSES,
     foundation::ns_thread::CLASSES,
     foundation::ns_timer::CLASSES,
+    foundation::ns_unarchiver::CLASSES,
     foundation::ns_url::CLASSES,
+    foundation::ns_url_cache::CLASSES,
+    foundation::ns_url_connection::CLASSES,
+    foundation::ns_url_request::CLASSES,
+    foundation::ns_url_response::CLASSES,
     foundation::ns_user_defaults::CLASSES,
     foundation::ns_value::CLASSES,
     av_audio::av_audio_player::CLASSES,
@@ -0,0 +1,100 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Parsing of instance variable (ivar) lists from the app binary, and looking
+//! up ivars by name (e.g. for key-value coding).
+//!
+//! Host classes keep their state in host objects, so they have no ivars.
+
+use super::{Class, ClassHostObject, ObjC};
+use crate::mem::{guest_size_of, ConstPtr, GuestUSize, Mem, Ptr, SafeRead};
+
+/// The layout of an ivar list in an app binary.
+///
+/// The name, field names and field layout are based on what Ghidra outputs.
+#[repr(C, packed)]
+pub(super) struct ivar_list_t {
+    entsize: GuestUSize,
+    count: GuestUSize,
+    // entries follow the struct
+}
+unsafe impl SafeRead for ivar_list_t {}
+
+/// The layout of an ivar in an app binary.
+///
+/// The name, field names and field layout are based on what Ghidra outputs.
+#[repr(C, packed)]
+struct ivar_t {
+    offset: ConstPtr<GuestUSize>,
+    name: ConstPtr<u8>,
+    type_: ConstPtr<u8>,
+    _alignment: u32,
+    _size: u32,
+}
+unsafe impl SafeRead for ivar_t {}
+
+/// An instance variable of a class from the app binary.
+#[derive(Clone, Debug)]
+pub struct Ivar {
+    /// Offset of the ivar from the start of the object.
+    pub offset: GuestUSize,
+    /// Objective-C type encoding, e.g. `@"UILabel"` for a `UILabel *`.
+    pub type_: String,
+}
+impl Ivar {
+    /// Whether this ivar holds an object pointer (`id` or a class type).
+    pub fn is_object(&self) -> bool {
+        self.type_.starts_with('@')
+    }
+}
+
+impl ClassHostObject {
+    pub(super) fn add_ivars_from_bin(&mut self, ivar_list_ptr: ConstPtr<ivar_list_t>, mem: &Mem) {
+        let ivar_list_t { entsize, count } = mem.read(ivar_list_ptr);
+        assert!(entsize >= guest_size_of::<ivar_t>());
+
+        let ivars_base_ptr: ConstPtr<ivar_t> = (ivar_list_ptr + 1).cast();
+
+        for i in 0..count {
+            let ivar_ptr: ConstPtr<ivar_t> = Ptr::from_bits(ivars_base_ptr.to_bits() + i * entsize);
+            let ivar_t {
+                offset,
+                name,
+                type_,
+                ..
+            } = mem.read(ivar_ptr);
+            // Anonymous ivars (e.g. for bitfield padding) can't be looked up.
+            if offset.is_null() || name.is_null() {
+                continue;
+            }
+
+            let name = String::from_utf8_lossy(mem.cstr_at(name)).into_owned();
+            let type_ = if type_.is_null() {
+                String::new()
+            } else {
+                String::from_utf8_lossy(mem.cstr_at(type_)).into_owned()
+            };
+            let offset = mem.read(offset);
+            self.ivars.insert(name, Ivar { offset, type_ });
+        }
+    }
+}
+
+impl ObjC {
+    /// Look up an instance variable by name in a class or its superclasses.
+    pub fn lookup_ivar(&self, class: Class, name: &str) -> Option<&Ivar> {
+        let mut class = class;
+        loop {
+            let host_object = self.get_host_object(class)?;
+            let ClassHostObject {
+                superclass, ivars, ..
+            } = host_object.as_any().downcast_ref()?;
+            if let Some(ivar) = ivars.get(name) {
+                return Some(ivar);
+            }
+            class = *superclass;
+        }
+    }
+}
@@ -13,7 +13,7 @@
 
 use super::{id, nil, Class, ObjC, IMP, SEL};
 use crate::abi::{CallFromHost, GuestRet};
-use crate::mem::{ConstPtr, MutVoidPtr, SafeRead};
+use crate::mem::{ConstPtr, MutVoidPtr, PointerStatus, SafeRead};
 use crate::Environment;
 use std::any::TypeId;
 
@@ -40,6 +40,23 @@ fn objc_msgSend_inner(env: &mut Environment, receiver: id, selector: SEL, super2
         return;
     }
 
+    // Over-releasing objects is a common app bug, and reading the isa of an
+    // object that no longer exists would give nonsense, so check first.
+    if super2.is_none()
+        && Some(selector) == env.objc.release_selector
+        && (receiver.to_bits() < env.mem.null_segment_size()
+            || env.mem.validate(receiver) == PointerStatus::Unknown)
+    {
+        crate::backtrace::report_invalid_pointer(
+            env,
+            format_args!(
+                "{:?} was sent release, but it is not part of any allocation",
+                receiver
+            ),
+        );
+        return;
+    }
+
     let orig_class = super2.unwrap_or_else(|| ObjC::read_isa(receiver, &env.mem));
     if orig_class == nil {
         return;
@@ -151,6 +168,9 @@ Type mismatch when sending message {} to {:?}!
             );
             env.cpu.regs_mut()[0..2].fill(0);
             return;
+        } else if host_object.as_any().is::<super::ZombieClass>() {
+            super::zombies::message_zombie(env, receiver, selector);
+            return;
         } else {
             panic!(
                 "Item {:?} in superclass chain of object {:?}'s class {:?} has an unexpected host object type.",
@@ -275,6 +275,16 @@ impl super::ObjC {
         }
     }
 
+    /// Get the refcount of an object, or [None] if it has a static lifetime.
+    /// This is for `retainCount`, which apps shouldn't rely on, but tests do.
+    pub fn get_refcount(&self, object: id) -> Option<u32> {
+        self.objects
+            .get(&object)
+            .unwrap()
+            .refcount
+            .map(NonZeroU32::get)
+    }
+
     /// Increase the refcount of a reference-counted object. Do not call this
     /// directly unless you're implementing `release` on `NSObject`. That method
     /// may be overridden.
@@ -346,6 +356,8 @@ impl super::ObjC {
 
         std::mem::drop(host_object);
 
-        mem.free(object.cast());
+        if !self.zombify(object, mem) {
+            mem.free(object.cast());
+        }
     }
 }
@@ -105,6 +105,7 @@ impl ObjC {
                 }
             }
         }
+        self.release_selector = self.lookup_selector("release");
     }
 
     /// Register a selector from the application binary. Must be a
@@ -0,0 +1,143 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Zombie objects, a debugging aid for use-after-free bugs (see the
+//! `--zombie-objects` option).
+//!
+//! Normally, a use-after-free of an Objective-C object only shows up when its
+//! memory has been reused and something is later sent a message it doesn't
+//! expect, or the app crashes in some unrelated place. Like Apple's
+//! `NSZombieEnabled`, zombie mode instead keeps the memory of deallocated
+//! objects and changes their class to `_NSZombie_`, so that any message sent
+//! to one is reported along with the original class and the selector.
+//!
+//! Resources:
+//! - Apple's [Technical Note TN2239](https://developer.apple.com/library/archive/technotes/tn2239/_index.html#//apple_ref/doc/uid/DTS40010638-CH1-SUBSECTION36)
+
+use super::{id, objc_object, Class, HostObject, ObjC, SEL};
+use crate::mem::{GuestUSize, Mem};
+use crate::Environment;
+use std::collections::{HashMap, VecDeque};
+
+/// What happens when a message is sent to a zombie.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ZombieMode {
+    /// Report the message and panic.
+    Abort,
+    /// Report the message and carry on as if it was sent to nil.
+    Continue,
+}
+
+/// Host object for the `_NSZombie_` class. Every message sent to an instance
+/// is intercepted in `objc_msgSend`, so it has no methods.
+pub(super) struct ZombieClass;
+impl HostObject for ZombieClass {}
+
+pub(super) const ZOMBIE_CLASS_NAME: &str = "_NSZombie_";
+
+#[derive(Default)]
+pub(super) struct Zombies {
+    /// [None] if zombie mode is disabled.
+    mode: Option<ZombieMode>,
+    /// How many zombies to keep before the oldest one is really freed.
+    limit: usize,
+    /// The `_NSZombie_` class, created when the first zombie is.
+    class: Option<Class>,
+    /// Original class name of each zombie.
+    class_names: HashMap<id, String>,
+    /// Zombies in the order they were created, with their allocation sizes.
+    queue: VecDeque<(id, GuestUSize)>,
+    /// Total size of the allocations kept alive by zombies.
+    bytes: u64,
+    limit_reached: bool,
+}
+
+impl ObjC {
+    /// Turn on zombie mode, keeping up to `limit` zombies at once.
+    pub fn enable_zombies(&mut self, mode: ZombieMode, limit: usize) {
+        log!(
+            "Zombie objects are enabled ({:?}), up to {} deallocated objects will be kept",
+            mode,
+            limit
+        );
+        self.zombies.mode = Some(mode);
+        self.zombies.limit = limit;
+    }
+
+    /// If zombie mode is enabled, turn an object that is being deallocated
+    /// (and no longer has a host object) into a zombie and return `true`.
+    /// Otherwise, its memory should be freed as usual.
+    pub(super) fn zombify(&mut self, object: id, mem: &mut Mem) -> bool {
+        if self.zombies.mode.is_none() {
+            return false;
+        }
+        let class_name = self.get_class_name(Self::read_isa(object, mem)).to_string();
+        let zombie_class = match self.zombies.class {
+            Some(class) => class,
+            None => {
+                let class = self.alloc_static_object(super::nil, Box::new(ZombieClass), mem);
+                self.zombies.class = Some(class);
+                class
+            }
+        };
+        mem.write(object, objc_object { isa: zombie_class });
+        let size = mem.allocation_size(object.cast());
+        log_dbg!("{:?} ({}) is now a zombie", object, class_name);
+
+        let zombies = &mut self.zombies;
+        zombies.class_names.insert(object, class_name);
+        zombies.queue.push_back((object, size));
+        zombies.bytes += u64::from(size);
+        if zombies.queue.len() > zombies.limit {
+            if !zombies.limit_reached {
+                zombies.limit_reached = true;
+                log!(
+                    "Warning: Reached the limit of {} zombie objects ({} bytes), the oldest ones will now be freed",
+                    zombies.limit,
+                    zombies.bytes
+                );
+            }
+            let (oldest, oldest_size) = zombies.queue.pop_front().unwrap();
+            zombies.class_names.remove(&oldest);
+            zombies.bytes -= u64::from(oldest_size);
+            mem.free(oldest.cast());
+        }
+        true
+    }
+
+    /// The number of zombies currently kept and the total size of their
+    /// allocations, which count towards the app's memory usage.
+    pub fn zombie_stats(&self) -> (usize, u64) {
+        (self.zombies.queue.len(), self.zombies.bytes)
+    }
+}
+
+/// Called by `objc_msgSend` when the receiver is a zombie.
+pub(super) fn message_zombie(env: &mut Environment, receiver: id, selector: SEL) {
+    let class_name = env
+        .objc
+        .zombies
+        .class_names
+        .get(&receiver)
+        .map_or("?", String::as_str);
+    // This is the same format as Apple's runtime uses.
+    let message = format!(
+        "*** -[{} {}]: message sent to deallocated instance {:?}",
+        class_name,
+        selector.as_str(&env.mem),
+        receiver
+    );
+    match env.objc.zombies.mode.unwrap() {
+        ZombieMode::Abort => {
+            crate::backtrace::print_guest_backtrace(env);
+            panic!("{}", message);
+        }
+        ZombieMode::Continue => {
+            log!("Warning: {}", message);
+            crate::backtrace::print_guest_backtrace(env);
+            env.cpu.regs_mut()[0..2].fill(0);
+        }
+    }
+}
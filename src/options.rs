@@ -5,7 +5,10 @@
  */
 //! Parsing and management of user-configurable options, e.g. for input methods.
 
+use crate::dyld::CallTraceFilter;
 use crate::gles::GLESImplementation;
+use crate::mem::{GuestUSize, Mem};
+use crate::objc::ZombieMode;
 use crate::window::DeviceOrientation;
 use std::collections::HashMap;
 use std::io::{BufRead, BufReader, Read};
@@ -32,7 +35,8 @@ pub enum Button {
 /// Struct containing all user-configurable options.
 pub struct Options {
     pub fullscreen: bool,
-    pub initial_orientation: DeviceOrientation,
+    /// [None] means the app's `Info.plist` decides.
+    pub initial_orientation: Option<DeviceOrientation>,
     pub scale_hack: NonZeroU32,
     pub deadzone: f32,
     pub x_tilt_range: f32,
@@ -44,17 +48,35 @@ pub struct Options {
     pub gles1_implementation: Option<GLESImplementation>,
     pub direct_memory_access: bool,
     pub gdb_listen_addrs: Option<Vec<SocketAddr>>,
+    pub trace_calls: Option<CallTraceFilter>,
+    pub fatal_invalid_pointers: bool,
     pub preferred_languages: Option<Vec<String>>,
     pub headless: bool,
     pub print_fps: bool,
     pub fps_limit: Option<f64>,
+    /// In bytes.
+    pub memory_warning_threshold: Option<u64>,
+    /// In bytes.
+    pub main_thread_stack_size: GuestUSize,
+    pub case_insensitive_paths: bool,
+    /// Unix time in seconds, see [crate::libc::time::current_time].
+    pub deterministic_time: Option<u64>,
+    /// Default soft `RLIMIT_NOFILE`, see [crate::libc::posix_io::fd_limit].
+    pub fd_limit: u32,
+    pub dump_open_files: bool,
+    pub zombie_objects: Option<ZombieMode>,
+    /// Maximum number of zombies kept at once, see [crate::objc::ZombieMode].
+    pub zombie_limit: usize,
+    /// Extra environment variables for the app, which can override the
+    /// default ones. See [crate::libc::stdlib].
+    pub guest_env: Vec<(String, String)>,
 }
 
 impl Default for Options {
     fn default() -> Self {
         Options {
             fullscreen: false,
-            initial_orientation: DeviceOrientation::Portrait,
+            initial_orientation: None,
             scale_hack: NonZeroU32::new(1).unwrap(),
             deadzone: 0.1,
             x_tilt_range: 60.0,
@@ -66,10 +88,21 @@ impl Default for Options {
             gles1_implementation: None,
             direct_memory_access: true,
             gdb_listen_addrs: None,
+            trace_calls: None,
+            fatal_invalid_pointers: false,
             preferred_languages: None,
             headless: false,
             print_fps: false,
             fps_limit: Some(60.0), // Original iPhone is 60Hz and uses v-sync
+            memory_warning_threshold: None,
+            main_thread_stack_size: Mem::DEFAULT_MAIN_THREAD_STACK_SIZE,
+            case_insensitive_paths: true,
+            deterministic_time: None,
+            fd_limit: 256, // Same as iPhone OS
+            dump_open_files: false,
+            zombie_objects: None,
+            zombie_limit: 10000,
+            guest_env: Vec::new(),
         }
     }
 }
@@ -92,9 +125,9 @@ impl Options {
         if arg == "--fullscreen" {
             self.fullscreen = true;
         } else if arg == "--landscape-left" {
-            self.initial_orientation = DeviceOrientation::LandscapeLeft;
+            self.initial_orientation = Some(DeviceOrientation::LandscapeLeft);
         } else if arg == "--landscape-right" {
-            self.initial_orientation = DeviceOrientation::LandscapeRight;
+            self.initial_orientation = Some(DeviceOrientation::LandscapeRight);
         } else if let Some(value) = arg.strip_prefix("--scale-hack=") {
             self.scale_hack = value
                 .parse()
@@ -167,6 +200,10 @@ impl Options {
                 .map_err(|e| format!("Could not resolve GDB server listen address: {}", e))?
                 .collect();
             self.gdb_listen_addrs = Some(addrs);
+        } else if let Some(value) = arg.strip_prefix("--trace-calls=") {
+            self.trace_calls = Some(CallTraceFilter::parse(value)?);
+        } else if arg == "--fatal-invalid-pointers" {
+            self.fatal_invalid_pointers = true;
         } else if let Some(value) = arg.strip_prefix("--preferred-languages=") {
             self.preferred_languages = Some(value.split(',').map(ToOwned::to_owned).collect());
         } else if arg == "--headless" {
@@ -184,6 +221,53 @@ impl Options {
                     .ok_or_else(|| "Invalid value for --fps-limit=".to_string())?;
                 self.fps_limit = Some(limit);
             }
+        } else if let Some(value) = arg.strip_prefix("--memory-warning-threshold=") {
+            let mebibytes: u64 = value
+                .parse()
+                .ok()
+                .and_then(|v| if v == 0 { None } else { Some(v) })
+                .ok_or_else(|| "Invalid value for --memory-warning-threshold=".to_string())?;
+            self.memory_warning_threshold = Some(mebibytes * 1024 * 1024);
+        } else if let Some(value) = arg.strip_prefix("--main-thread-stack-size=") {
+            let kibibytes: GuestUSize = value
+                .parse()
+                .ok()
+                .filter(|&v| (16..=1024 * 1024).contains(&v) && v % 4 == 0)
+                .ok_or_else(|| "Invalid value for --main-thread-stack-size=".to_string())?;
+            self.main_thread_stack_size = kibibytes * 1024;
+        } else if arg == "--case-sensitive-paths" {
+            self.case_insensitive_paths = false;
+        } else if let Some(value) = arg.strip_prefix("--deterministic-time=") {
+            let time: u64 = value
+                .parse()
+                .ok()
+                .filter(|&v| v <= i32::MAX as u64)
+                .ok_or_else(|| "Invalid value for --deterministic-time=".to_string())?;
+            self.deterministic_time = Some(time);
+        } else if let Some(value) = arg.strip_prefix("--fd-limit=") {
+            self.fd_limit = value
+                .parse()
+                .ok()
+                .filter(|&v| (16..=crate::libc::posix_io::OPEN_MAX).contains(&v))
+                .ok_or_else(|| "Invalid value for --fd-limit=".to_string())?;
+        } else if arg == "--dump-open-files" {
+            self.dump_open_files = true;
+        } else if arg == "--zombie-objects" {
+            self.zombie_objects = Some(ZombieMode::Abort);
+        } else if arg == "--zombie-objects=continue" {
+            self.zombie_objects = Some(ZombieMode::Continue);
+        } else if let Some(value) = arg.strip_prefix("--zombie-limit=") {
+            self.zombie_limit = value
+                .parse()
+                .ok()
+                .filter(|&v| v > 0)
+                .ok_or_else(|| "Invalid value for --zombie-limit=".to_string())?;
+        } else if let Some(value) = arg.strip_prefix("--env=") {
+            let (name, value) = value
+                .split_once('=')
+                .filter(|(name, _)| !name.is_empty())
+                .ok_or_else(|| "Value for --env= must be NAME=VALUE".to_string())?;
+            self.guest_env.push((name.to_string(), value.to_string()));
         } else {
             return Ok(false);
         };
@@ -28,7 +28,7 @@ pub fn prep_stack_for_start(
     let argc: i32 = argv.len().try_into().unwrap();
 
     // We are arbitrarily putting the main thread's stack at the top of the
-    // address space (see also: mem::Mem::MAIN_THREAD_STACK_LOW_END).
+    // address space (see also: mem::Mem::main_thread_stack).
     // Since the stack grows downwards, its first byte would be 0xffffffff.
     let stack_base: usize = 1 << 32;
 
@@ -76,7 +76,7 @@ pub fn prep_stack_for_start(
         Ptr::from_bits((stack_base - reversed_data.len()).try_into().unwrap());
     let stack_height: GuestUSize = reversed_data.len().try_into().unwrap();
 
-    assert!(stack_height < Mem::MAIN_THREAD_STACK_SIZE);
+    assert!(stack_height < mem.main_thread_stack_size());
     // FIXME: make stack always be 4-byte aligned. it is currently aligned only
     // by accident
     assert!(stack_height % 4 == 0);
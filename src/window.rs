@@ -93,6 +93,9 @@ pub enum Event {
     /// User pressed F12, requesting that execution be paused and the debugger
     /// take over.
     EnterDebugger,
+    /// User pressed F11, or the OS has informed touchHLE that memory is low.
+    /// (iOS `applicationDidReceiveMemoryWarning:`, Android `onLowMemory()`)
+    MemoryWarning,
 }
 
 pub enum GLVersion {
@@ -199,9 +202,9 @@ impl Window {
         video_ctx.enable_screen_saver();
 
         let scale_hack = options.scale_hack;
-        // TODO: some apps specify their orientation in Info.plist, we could use
-        // that here.
-        let device_orientation = options.initial_orientation;
+        let device_orientation = options
+            .initial_orientation
+            .unwrap_or(DeviceOrientation::Portrait);
         let fullscreen = options.fullscreen;
 
         let mut window = if Self::rotatable_fullscreen() {
@@ -471,6 +474,10 @@ impl Window {
                     self.enable_event_polling = false;
                     continue;
                 }
+                E::AppLowMemory { .. } => {
+                    log!("Received low-memory event.");
+                    Event::MemoryWarning
+                }
                 E::AppTerminating { .. } => {
                     log!("Received app-will-terminate event.");
                     assert!(self.high_priority_event.is_none());
@@ -572,6 +579,13 @@ impl Window {
                     echo!("F12 pressed, EnterDebugger event queued.");
                     Event::EnterDebugger
                 }
+                E::KeyDown {
+                    keycode: Some(sdl2::keyboard::Keycode::F11),
+                    ..
+                } => {
+                    echo!("F11 pressed, MemoryWarning event queued.");
+                    Event::MemoryWarning
+                }
                 _ => continue,
             })
         }
@@ -73,6 +73,23 @@ void dump_transform(CGAffineTransform t) {
 
 // === Main code ===
 
+// For results of rotations, which are imprecise.
+bool approx_equal(CGFloat a, CGFloat b) {
+  CGFloat difference = a - b;
+  return difference < 0.0001 && difference > -0.0001;
+}
+bool approx_equal_rect(CGRect a, CGRect b) {
+  return approx_equal(a.origin.x, b.origin.x) &&
+         approx_equal(a.origin.y, b.origin.y) &&
+         approx_equal(a.size.width, b.size.width) &&
+         approx_equal(a.size.height, b.size.height);
+}
+bool approx_equal_transform(CGAffineTransform a, CGAffineTransform b) {
+  return approx_equal(a.a, b.a) && approx_equal(a.b, b.b) &&
+         approx_equal(a.c, b.c) && approx_equal(a.d, b.d) &&
+         approx_equal(a.tx, b.tx) && approx_equal(a.ty, b.ty);
+}
+
 int test_CGAffineTransform(void) {
   bool success = 1;
 
@@ -282,6 +299,38 @@ int test_CGAffineTransform(void) {
                                    CGRectApplyAffineTransform(
                                        (CGRect){2.0, 3.0, 1.0, 2.0},
                                        CGAffineTransformMakeScale(-2.0, -2.0)));
+  // Rotation by 90 degrees maps (x, y) to (-y, x), so the result is the
+  // bounding box of the rotated corners.
+  const CGFloat half_pi = 1.57079632679489661923;
+  success = success && approx_equal_rect((CGRect){-6.0, 1.0, 4.0, 3.0},
+                                         CGRectApplyAffineTransform(
+                                             (CGRect){1.0, 2.0, 3.0, 4.0},
+                                             CGAffineTransformMakeRotation(
+                                                 half_pi)));
+  // Rotation by 45 degrees doesn't preserve rectangles, the result is larger.
+  success = success &&
+            approx_equal_rect(
+                (CGRect){-0.70710678, 0.0, 1.41421356, 1.41421356},
+                CGRectApplyAffineTransform(
+                    (CGRect){0.0, 0.0, 1.0, 1.0},
+                    CGAffineTransformMakeRotation(half_pi / 2.0)));
+
+  // Inversion round-trips.
+  {
+    CGAffineTransform t = CGAffineTransformTranslate(
+        CGAffineTransformScale(CGAffineTransformMakeRotation(0.5), 2.0, 3.0),
+        5.0, -7.0);
+    CGAffineTransform inverse = CGAffineTransformInvert(t);
+    success = success && approx_equal_transform(
+                             CGAffineTransformConcat(t, inverse),
+                             CGAffineTransformMake(1.0, 0.0, 0.0, 1.0, 0.0,
+                                                   0.0));
+    success = success && approx_equal_transform(
+                             CGAffineTransformInvert(inverse), t);
+    CGPoint p = CGPointApplyAffineTransform(
+        CGPointApplyAffineTransform((CGPoint){3.0, -4.0}, t), inverse);
+    success = success && approx_equal(p.x, 3.0) && approx_equal(p.y, -4.0);
+  }
 
   return !success;
 }
@@ -23,9 +23,35 @@
 #define NULL ((void *)0)
 typedef unsigned long size_t;
 
+// <ctype.h>
+#define EOF (-1)
+int isalnum(int);
+int isalpha(int);
+int isdigit(int);
+int islower(int);
+int isprint(int);
+int ispunct(int);
+int isspace(int);
+int isupper(int);
+int isxdigit(int);
+int tolower(int);
+int toupper(int);
+
 // <errno.h>
 int *__error(void);
 #define errno (*__error())
+#define EPERM 1
+#define ENOENT 2
+#define EBADF 9
+#define ENOMEM 12
+#define EEXIST 17
+#define ENOTDIR 20
+#define EINVAL 22
+#define EMFILE 24
+#define EROFS 30
+#define ERANGE 34
+#define EAGAIN 35
+#define EILSEQ 92
 
 // <stdarg.h>
 typedef __builtin_va_list va_list;
@@ -33,53 +59,404 @@ typedef __builtin_va_list va_list;
 #define va_arg(a, b) __builtin_va_arg(a, b)
 #define va_end(a) __builtin_va_end(a)
 
+// <err.h>
+void warn(const char *, ...);
+void warnx(const char *, ...);
+
+// <syslog.h>
+#define LOG_WARNING 4
+#define LOG_USER (1 << 3)
+#define LOG_PERROR 0x20
+void openlog(const char *, int, int);
+void syslog(int, const char *, ...);
+void closelog(void);
+
 // <stdio.h>
 typedef struct FILE FILE;
 FILE *fopen(const char *, const char *);
 int fclose(FILE *);
+size_t fread(void *, size_t, size_t, FILE *);
+size_t fwrite(const void *, size_t, size_t, FILE *);
+int fgetc(FILE *);
+int remove(const char *);
+int rename(const char *, const char *);
 int sscanf(const char *, const char *, ...);
+int vsscanf(const char *, const char *, va_list);
+int fscanf(FILE *, const char *, ...);
+int vfscanf(FILE *, const char *, va_list);
 int printf(const char *, ...);
+int fprintf(FILE *, const char *, ...);
+int snprintf(char *, size_t, const char *, ...);
 int vsnprintf(char *, size_t, const char *, va_list);
 
+// <assert.h>
+void __assert_rtn(const char *, const char *, int, const char *);
+#define assert(e)                                                              \
+  ((e) ? (void)0 : __assert_rtn(__func__, __FILE__, __LINE__, #e))
+
 // <stdlib.h>
 #define EXIT_SUCCESS 0
 #define EXIT_FAILURE 1
 void exit(int);
+int atexit(void (*)(void));
+int rand(void);
+int rand_r(unsigned *);
+void srand(unsigned);
 void free(void *);
 void *malloc(size_t);
+void *calloc(size_t, size_t);
+int posix_memalign(void **, size_t, size_t);
+void *valloc(size_t);
 void qsort(void *, size_t, size_t, int (*)(const void *, const void *));
+void qsort_r(void *, size_t, size_t, void *,
+             int (*)(void *, const void *, const void *));
+int mergesort(void *, size_t, size_t, int (*)(const void *, const void *));
+int heapsort(void *, size_t, size_t, int (*)(const void *, const void *));
 void *realloc(void *, size_t);
 double atof(const char *);
+int atoi(const char *);
+long atol(const char *);
+long long atoll(const char *);
+int abs(int);
+long labs(long);
+long long llabs(long long);
+typedef struct {
+  int quot;
+  int rem;
+} div_t;
+typedef struct {
+  long quot;
+  long rem;
+} ldiv_t;
+typedef struct {
+  long long quot;
+  long long rem;
+} lldiv_t;
+div_t div(int, int);
+ldiv_t ldiv(long, long);
+lldiv_t lldiv(long long, long long);
+char *getenv(const char *);
+int setenv(const char *, const char *, int);
+int unsetenv(const char *);
+int putenv(char *);
 float strtof(const char *, char **);
+double strtod(const char *, char **);
+long strtol(const char *, char **, int);
+unsigned long strtoul(const char *, char **, int);
+long long strtoll(const char *, char **, int);
+unsigned long long strtoull(const char *, char **, int);
+int system(const char *);
+// This is the symbol Apple's headers use for realpath().
+char *realpath(const char *, char *) __asm("_realpath$DARWIN_EXTSN");
+typedef int wchar_t;
+int mblen(const char *, size_t);
+int mbtowc(wchar_t *, const char *, size_t);
+int wctomb(char *, wchar_t);
+size_t mbstowcs(wchar_t *, const char *, size_t);
+size_t wcstombs(char *, const wchar_t *, size_t);
+char *mktemp(char *);
+int mkstemp(char *);
 
 // <string.h>
 void *memset(void *, int, size_t);
 int memcmp(const void *, const void *, size_t);
 void *memmove(void *, const void *, size_t);
+void *memccpy(void *, const void *, int, size_t);
 int strcmp(const char *, const char *);
+int strcasecmp(const char *, const char *);
+int strncasecmp(const char *, const char *, size_t);
+char *strcpy(char *, const char *);
 char *strncpy(char *, const char *, size_t);
+char *stpcpy(char *, const char *);
+char *stpncpy(char *, const char *, size_t);
 char *strncat(char *, const char *, size_t);
 size_t strlcpy(char *, const char *, size_t);
 char *strchr(const char *s, int c);
 char *strrchr(const char *s, int c);
+size_t strcspn(const char *, const char *);
+size_t strspn(const char *, const char *);
+char *strpbrk(const char *, const char *);
+char *strtok(char *, const char *);
+char *strtok_r(char *, const char *, char **);
+char *strsep(char **, const char *);
+size_t strnlen(const char *, size_t);
+void *memrchr(const void *, int, size_t);
+
+// <wchar.h>
+typedef int wint_t;
+#define WEOF ((wint_t)-1)
+int fwide(FILE *, int);
+wint_t fgetwc(FILE *);
+wchar_t *fgetws(wchar_t *, int, FILE *);
+wint_t fputwc(wchar_t, FILE *);
+int fputws(const wchar_t *, FILE *);
+int wcscmp(const wchar_t *, const wchar_t *);
 
 // <unistd.h>
 typedef unsigned int __uint32_t;
 typedef __uint32_t useconds_t;
+typedef long ssize_t;
+typedef __uint32_t uid_t;
+typedef __uint32_t gid_t;
+typedef int pid_t;
+#define F_OK 0
+int access(const char *, int);
 int chdir(const char *);
+int close(int);
+#define _CS_PATH 1
+#define _CS_DARWIN_USER_TEMP_DIR 65537
+size_t confstr(int, char *, size_t);
+int dup(int);
+int execve(const char *, char *const[], char *const[]);
+int fchdir(int);
+pid_t fork(void);
+#define _PC_NAME_MAX 4
+#define _PC_PATH_MAX 5
+#define _PC_PIPE_BUF 6
+long fpathconf(int, int);
+long pathconf(const char *, int);
 char *getcwd(char *, size_t);
+int getdtablesize(void);
+gid_t getegid(void);
+uid_t geteuid(void);
+gid_t getgid(void);
+char *getlogin(void);
+int getopt(int, char *const[], const char *);
+extern char *optarg;
+extern int optind, opterr, optopt;
+pid_t getpid(void);
+uid_t getuid(void);
+typedef long long off_t;
+#define SEEK_SET 0
+off_t lseek(int, off_t, int);
+int pipe(int[2]);
+ssize_t read(int, void *, size_t);
+ssize_t readlink(const char *, char *, size_t);
+int unlink(const char *);
+ssize_t write(int, const void *, size_t);
 int usleep(useconds_t);
+unsigned int sleep(unsigned int);
+
+// <time.h>
+typedef long time_t;
+struct timespec {
+  time_t tv_sec;
+  long tv_nsec;
+};
+int nanosleep(const struct timespec *, struct timespec *);
+struct tm {
+  int tm_sec;
+  int tm_min;
+  int tm_hour;
+  int tm_mday;
+  int tm_mon;
+  int tm_year;
+  int tm_wday;
+  int tm_yday;
+  int tm_isdst;
+  long tm_gmtoff;
+  char *tm_zone;
+};
+size_t strftime(char *, size_t, const char *, const struct tm *);
+time_t time(time_t *);
+double difftime(time_t, time_t);
+typedef unsigned long clock_t;
+clock_t clock(void);
+
+// <locale.h>
+#define LC_ALL 0
+#define LC_CTYPE 2
+#define LC_NUMERIC 4
+char *setlocale(int, const char *);
+
+// <getopt.h>
+struct option {
+  const char *name;
+  int has_arg;
+  int *flag;
+  int val;
+};
+#define no_argument 0
+#define required_argument 1
+#define optional_argument 2
+int getopt_long(int, char *const[], const char *, const struct option *,
+                int *);
+
+// <dirent.h>
+typedef struct DIR DIR;
+DIR *opendir(const char *);
+
+// <dlfcn.h>
+#define RTLD_LAZY 0x1
+void *dlopen(const char *, int);
+void *dlsym(void *, const char *);
+int dlclose(void *);
+
+// <sys/sysctl.h>
+int sysctlbyname(const char *, void *, size_t *, void *, size_t);
+
+// <spawn.h>
+int posix_spawn(pid_t *, const char *, const void *, const void *,
+                char *const[], char *const[]);
+
+// <pwd.h>
+struct passwd {
+  char *pw_name;
+  char *pw_passwd;
+  uid_t pw_uid;
+  gid_t pw_gid;
+  time_t pw_change;
+  char *pw_class;
+  char *pw_gecos;
+  char *pw_dir;
+  char *pw_shell;
+  time_t pw_expire;
+};
+struct passwd *getpwnam(const char *);
+struct passwd *getpwuid(uid_t);
 
 // <fcntl.h>
+#define O_RDONLY 0x00000000
+#define O_WRONLY 0x00000001
 #define O_CREAT 0x00000200
+#define O_TRUNC 0x00000400
+int open(const char *, int, ...);
+
+// <sys/stat.h>
+#define S_IFMT 0170000
+#define S_IFREG 0100000
+struct stat {
+  int st_dev;
+  unsigned short st_mode;
+  unsigned short st_nlink;
+  unsigned long long st_ino;
+  uid_t st_uid;
+  gid_t st_gid;
+  int st_rdev;
+  struct timespec st_atimespec;
+  struct timespec st_mtimespec;
+  struct timespec st_ctimespec;
+  struct timespec st_birthtimespec;
+  long long st_size;
+  long long st_blocks;
+  int st_blksize;
+  unsigned int st_flags;
+  unsigned int st_gen;
+  int st_lspare;
+  long long st_qspare[2];
+};
+#define st_atime st_atimespec.tv_sec
+#define st_mtime st_mtimespec.tv_sec
+int stat(const char *, struct stat *);
+int fstat(int, struct stat *);
+int chmod(const char *, unsigned short);
+int fchmod(int, unsigned short);
+int mkdir(const char *, unsigned short);
+
+// <glob.h>
+typedef struct {
+  size_t gl_pathc;
+  int gl_matchc;
+  size_t gl_offs;
+  int gl_flags;
+  char **gl_pathv;
+  void *gl_funcs[6]; // only used with GLOB_ALTDIRFUNC
+} glob_t;
+#define GLOB_MARK 0x0008
+#define GLOB_NOCHECK 0x0010
+#define GLOB_NOMATCH (-3)
+int glob(const char *, int, int (*)(const char *, int), glob_t *);
+void globfree(glob_t *);
+
+// <libgen.h>
+char *basename(char *);
+char *basename_r(const char *, char *);
+char *dirname(char *);
+char *dirname_r(const char *, char *);
+
+// <ftw.h>
+struct FTW {
+  int base;
+  int level;
+};
+#define FTW_F 0
+#define FTW_D 1
+#define FTW_DP 3
+#define FTW_DEPTH 0x04
+int nftw(const char *,
+         int (*)(const char *, const struct stat *, int, struct FTW *), int,
+         int);
+
+// <regex.h>
+typedef struct {
+  int re_magic;
+  size_t re_nsub;
+  const char *re_endp;
+  void *re_g;
+} regex_t;
+typedef long long regoff_t;
+typedef struct {
+  regoff_t rm_so;
+  regoff_t rm_eo;
+} regmatch_t;
+#define REG_BASIC 0
+#define REG_EXTENDED 1
+#define REG_ICASE 2
+#define REG_NOTBOL 1
+#define REG_NOMATCH 1
+#define REG_EPAREN 8
+int regcomp(regex_t *, const char *, int);
+int regexec(const regex_t *, const char *, size_t, regmatch_t[], int);
+size_t regerror(int, const regex_t *, char *, size_t);
+void regfree(regex_t *);
+
+// <zlib.h>
+typedef struct {
+  const unsigned char *next_in;
+  unsigned int avail_in;
+  unsigned long total_in;
+  unsigned char *next_out;
+  unsigned int avail_out;
+  unsigned long total_out;
+  const char *msg;
+  void *state;
+  void *zalloc;
+  void *zfree;
+  void *opaque;
+  int data_type;
+  unsigned long adler;
+  unsigned long reserved;
+} z_stream;
+#define Z_OK 0
+#define Z_STREAM_END 1
+#define Z_DATA_ERROR (-3)
+#define Z_BUF_ERROR (-5)
+#define Z_VERSION_ERROR (-6)
+#define Z_NO_FLUSH 0
+#define Z_FINISH 4
+#define Z_DEFLATED 8
+int compress2(unsigned char *, unsigned long *, const unsigned char *,
+              unsigned long, int);
+unsigned long compressBound(unsigned long);
+int uncompress(unsigned char *, unsigned long *, const unsigned char *,
+               unsigned long);
+int inflateInit_(z_stream *, const char *, int);
+int inflateInit2_(z_stream *, int, const char *, int);
+int inflate(z_stream *, int);
+int inflateEnd(z_stream *);
+int deflateInit2_(z_stream *, int, int, int, int, int, const char *, int);
+int deflate(z_stream *, int);
+int deflateEnd(z_stream *);
+unsigned long crc32(unsigned long, const unsigned char *, unsigned int);
+unsigned long adler32(unsigned long, const unsigned char *, unsigned int);
 
 // <pthread.h>
 typedef struct opaque_pthread_t opaque_pthread_t;
 typedef struct opaque_pthread_t *__pthread_t;
 typedef __pthread_t pthread_t;
 
-typedef struct opaque_pthread_attr_t opaque_pthread_attr_t;
-typedef struct opaque_pthread_attr_t *__pthread_attr_t;
+struct _opaque_pthread_attr_t { long __sig; char __opaque[36]; };
+typedef struct _opaque_pthread_attr_t __pthread_attr_t;
 typedef __pthread_attr_t pthread_attr_t;
 
 struct _opaque_pthread_mutex_t { long __sig; char __opaque[40]; };
@@ -98,8 +475,15 @@ typedef struct opaque_pthread_condattr_t opaque_pthread_condattr_t;
 typedef struct opaque_pthread_condattr_t *__pthread_condattr_t;
 typedef __pthread_condattr_t pthread_condattr_t;
 
+int pthread_attr_init(pthread_attr_t *);
+int pthread_attr_destroy(pthread_attr_t *);
+int pthread_attr_getstacksize(const pthread_attr_t *, size_t *);
+int pthread_attr_setstacksize(pthread_attr_t *, size_t);
 int pthread_create(pthread_t *, const pthread_attr_t *, void *(*)(void *),
                    void *);
+int pthread_join(pthread_t, void **);
+pthread_t pthread_self(void);
+size_t pthread_get_stacksize_np(pthread_t);
 
 int pthread_cond_init(pthread_cond_t *, const pthread_condattr_t *);
 int pthread_cond_signal(pthread_cond_t *);
@@ -125,12 +509,365 @@ typedef long _register_t; // 64-bit definition
 typedef int _register_t;
 #endif
 
+// <signal.h>
+#define SIGPIPE 13
+#define SIGUSR1 30
+#define SIG_IGN ((void (*)(int))1)
+#define SA_SIGINFO 0x40
+typedef unsigned int sigset_t;
+typedef struct __siginfo {
+  int si_signo;
+  int si_errno;
+  int si_code;
+  int si_pid;
+  unsigned int si_uid;
+  int si_status;
+  void *si_addr;
+  int si_value;
+  long si_band;
+  unsigned long __pad[7];
+} siginfo_t;
+struct sigaction {
+  union {
+    void (*__sa_handler)(int);
+    void (*__sa_sigaction)(int, siginfo_t *, void *);
+  } __sigaction_u;
+  sigset_t sa_mask;
+  int sa_flags;
+};
+#define sa_handler __sigaction_u.__sa_handler
+#define sa_sigaction __sigaction_u.__sa_sigaction
+void (*signal(int, void (*)(int)))(int);
+int sigaction(int, const struct sigaction *, struct sigaction *);
+int sigemptyset(sigset_t *);
+int raise(int);
+
+// <sys/resource.h>
+#define RUSAGE_SELF 0
+struct timeval {
+  time_t tv_sec;
+  int tv_usec;
+};
+struct rusage {
+  struct timeval ru_utime;
+  struct timeval ru_stime;
+  long ru_maxrss;
+  long ru_other[13];
+};
+int getrusage(int, struct rusage *);
+typedef unsigned long long rlim_t;
+struct rlimit {
+  rlim_t rlim_cur;
+  rlim_t rlim_max;
+};
+#define RLIMIT_NOFILE 8
+int getrlimit(int, struct rlimit *);
+int setrlimit(int, const struct rlimit *);
+
+// <sys/time.h>
+int gettimeofday(struct timeval *, void *);
+int utimes(const char *, const struct timeval *);
+int futimes(int, const struct timeval *);
+
+// <utime.h>
+struct utimbuf {
+  time_t actime;
+  time_t modtime;
+};
+int utime(const char *, const struct utimbuf *);
+
+// <mach/task_info.h>
+typedef unsigned int mach_port_t;
+typedef int kern_return_t;
+typedef unsigned int mach_msg_type_number_t;
+#define KERN_SUCCESS 0
+#define KERN_INVALID_ADDRESS 1
+#define TASK_BASIC_INFO 4
+struct task_basic_info {
+  int suspend_count;
+  unsigned int virtual_size;
+  unsigned int resident_size;
+  int user_time[2];
+  int system_time[2];
+  int policy;
+};
+#define TASK_BASIC_INFO_COUNT                                                  \
+  (sizeof(struct task_basic_info) / sizeof(unsigned int))
+mach_port_t mach_task_self(void);
+kern_return_t task_info(mach_port_t, unsigned int, int *,
+                        mach_msg_type_number_t *);
+
+// <mach/vm_map.h>
+typedef unsigned int vm_address_t;
+typedef unsigned int vm_size_t;
+#define KERN_NO_SPACE 3
+#define KERN_INVALID_ARGUMENT 4
+#define VM_FLAGS_FIXED 0
+#define VM_FLAGS_ANYWHERE 1
+#define VM_PROT_READ 1
+#define VM_PROT_WRITE 2
+extern vm_size_t vm_page_size;
+mach_port_t mach_host_self(void);
+kern_return_t host_page_size(mach_port_t, vm_size_t *);
+kern_return_t vm_allocate(mach_port_t, vm_address_t *, vm_size_t, int);
+kern_return_t vm_deallocate(mach_port_t, vm_address_t, vm_size_t);
+kern_return_t vm_protect(mach_port_t, vm_address_t, vm_size_t, int, int);
+
+// <libkern/OSAtomic.h>
+int OSAtomicAdd32(int, volatile int *);
+int OSAtomicIncrement32Barrier(volatile int *);
+int OSAtomicDecrement32(volatile int *);
+long long OSAtomicAdd64(long long, volatile long long *);
+unsigned int OSAtomicOr32Orig(unsigned int, volatile unsigned int *);
+bool OSAtomicCompareAndSwap32(int, int, volatile int *);
+bool OSAtomicCompareAndSwap32Barrier(int, int, volatile int *);
+bool OSAtomicCompareAndSwapPtr(void *, void *, void *volatile *);
+bool OSAtomicTestAndSet(unsigned int, volatile void *);
+bool OSAtomicTestAndClear(unsigned int, volatile void *);
+void OSMemoryBarrier(void);
+typedef struct {
+  void *opaque1;
+  long opaque2;
+} OSQueueHead;
+#define OS_ATOMIC_QUEUE_INIT {NULL, 0}
+void OSAtomicEnqueue(OSQueueHead *, void *, size_t);
+void *OSAtomicDequeue(OSQueueHead *, size_t);
+
+// <cxxabi.h>
+int __cxa_atexit(void (*)(void *), void *, void *);
+
 // <setjmp.h>
 #define _JBLEN (10 + 16 + 2)
 typedef _register_t jmp_buf[_JBLEN];
 int setjmp(jmp_buf env);
 void longjmp(jmp_buf env, int val);
 
+// <sys/socket.h>
+typedef __uint32_t socklen_t;
+#define AF_INET 2
+#define SOCK_STREAM 1
+int socket(int, int, int);
+int bind(int, const void *, socklen_t);
+int listen(int, int);
+int accept(int, void *, socklen_t *);
+int connect(int, const void *, socklen_t);
+int getsockname(int, void *, socklen_t *);
+ssize_t send(int, const void *, size_t, int);
+ssize_t recv(int, void *, size_t, int);
+
+// <sys/select.h>
+typedef struct {
+  int fds_bits[32];
+} fd_set;
+#define FD_ZERO(p) memset((p), 0, sizeof(*(p)))
+#define FD_SET(n, p) ((p)->fds_bits[(n) / 32] |= (1 << ((n) % 32)))
+#define FD_ISSET(n, p) ((p)->fds_bits[(n) / 32] & (1 << ((n) % 32)))
+int select(int, fd_set *, fd_set *, fd_set *, struct timeval *);
+
+// <poll.h>
+#define POLLIN 0x0001
+#define POLLOUT 0x0004
+#define POLLHUP 0x0010
+#define POLLNVAL 0x0020
+struct pollfd {
+  int fd;
+  short events;
+  short revents;
+};
+int poll(struct pollfd *, unsigned int, int);
+
+// <netinet/in.h>
+struct sockaddr_in {
+  unsigned char sin_len;
+  unsigned char sin_family;
+  unsigned short sin_port;
+  unsigned int sin_addr;
+  char sin_zero[8];
+};
+
+// <objc/objc.h>
+typedef struct objc_object *id;
+typedef struct objc_selector *SEL;
+typedef signed char BOOL;
+id objc_msgSend(id, SEL, ...);
+void objc_msgSend_stret(void *, id, SEL, ...);
+SEL sel_registerName(const char *);
+
+// <objc/objc-exception.h>
+// This is what @try/@catch uses on armv6 and armv7: objc_exception_try_enter()
+// registers the data, then the caller does _setjmp() on it.
+typedef struct {
+  jmp_buf buf;
+  void *pointers[4];
+} objc_exception_data;
+int _setjmp(jmp_buf);
+void objc_exception_throw(id);
+void objc_exception_try_enter(objc_exception_data *);
+void objc_exception_try_exit(objc_exception_data *);
+id objc_exception_extract(objc_exception_data *);
+int objc_exception_match(id, id);
+
+// <Foundation/Foundation.h>
+typedef unsigned long NSUInteger;
+#define NSLibraryDirectory 5
+#define NSDocumentDirectory 9
+#define NSCachesDirectory 13
+#define NSItemReplacementDirectory 99
+#define NSUserDomainMask 1
+id NSClassFromString(id);
+id NSHomeDirectory(void);
+id NSTemporaryDirectory(void);
+id NSSearchPathForDirectoriesInDomains(NSUInteger, NSUInteger, BOOL);
+id NSUserName(void);
+typedef struct {
+  NSUInteger location;
+  NSUInteger length;
+} NSRange;
+#define NSCaseInsensitiveSearch 1
+#define NSLiteralSearch 2
+#define NSNumericSearch 64
+#define NSDiacriticInsensitiveSearch 128
+#define NSWidthInsensitiveSearch 256
+#define NSForcedOrderingSearch 512
+typedef void NSUncaughtExceptionHandler(id);
+void NSSetUncaughtExceptionHandler(NSUncaughtExceptionHandler *);
+NSUncaughtExceptionHandler *NSGetUncaughtExceptionHandler(void);
+
+// <CoreGraphics/CGGeometry.h>
+// (The types are declared in CGAffineTransform.c.)
+bool CGRectIsNull(CGRect);
+bool CGRectIsEmpty(CGRect);
+CGFloat CGRectGetMinX(CGRect);
+CGFloat CGRectGetMidY(CGRect);
+CGFloat CGRectGetMaxX(CGRect);
+CGFloat CGRectGetWidth(CGRect);
+bool CGRectContainsPoint(CGRect, CGPoint);
+bool CGRectContainsRect(CGRect, CGRect);
+bool CGRectIntersectsRect(CGRect, CGRect);
+CGRect CGRectIntersection(CGRect, CGRect);
+CGRect CGRectUnion(CGRect, CGRect);
+CGRect CGRectInset(CGRect, CGFloat, CGFloat);
+CGRect CGRectOffset(CGRect, CGFloat, CGFloat);
+CGRect CGRectIntegral(CGRect);
+
+// <UIKit/UIGeometry.h>
+id NSStringFromCGPoint(CGPoint);
+id NSStringFromCGSize(CGSize);
+id NSStringFromCGRect(CGRect);
+CGPoint CGPointFromString(id);
+CGSize CGSizeFromString(id);
+CGRect CGRectFromString(id);
+
+// <CoreFoundation/CoreFoundation.h>
+typedef long CFIndex;
+typedef unsigned long CFOptionFlags;
+typedef double CFTimeInterval;
+typedef const void *CFTypeRef;
+typedef const void *CFDataRef;
+typedef const void *CFStringRef;
+typedef void *CFRunLoopRef;
+typedef void *CFRunLoopSourceRef;
+typedef void *CFSocketRef;
+void CFRelease(CFTypeRef);
+CFDataRef CFDataCreate(const void *, const unsigned char *, CFIndex);
+CFIndex CFDataGetLength(CFDataRef);
+const unsigned char *CFDataGetBytePtr(CFDataRef);
+#define kCFCompareCaseInsensitive 1
+#define kCFCompareNonliteral 16
+#define kCFCompareNumerically 64
+CFIndex CFStringCompare(CFStringRef, CFStringRef, CFOptionFlags);
+extern const CFStringRef kCFRunLoopDefaultMode;
+#define kCFRunLoopRunHandledSource 4
+CFRunLoopRef CFRunLoopGetCurrent(void);
+int CFRunLoopRunInMode(CFStringRef, CFTimeInterval, BOOL);
+void CFRunLoopAddSource(CFRunLoopRef, CFRunLoopSourceRef, CFStringRef);
+typedef double CFAbsoluteTime;
+CFAbsoluteTime CFAbsoluteTimeGetCurrent(void);
+typedef void *CFRunLoopTimerRef;
+typedef void (*CFRunLoopTimerCallBack)(CFRunLoopTimerRef, void *);
+typedef struct {
+  CFIndex version;
+  void *info;
+  const void *(*retain)(const void *);
+  void (*release)(const void *);
+  CFStringRef (*copyDescription)(const void *);
+} CFRunLoopTimerContext;
+CFRunLoopTimerRef CFRunLoopTimerCreate(const void *, CFAbsoluteTime,
+                                       CFTimeInterval, CFOptionFlags, CFIndex,
+                                       CFRunLoopTimerCallBack,
+                                       CFRunLoopTimerContext *);
+void CFRunLoopAddTimer(CFRunLoopRef, CFRunLoopTimerRef, CFStringRef);
+void CFRunLoopTimerInvalidate(CFRunLoopTimerRef);
+#define kCFSocketDataCallBack 3
+#define kCFSocketConnectCallBack 4
+typedef void (*CFSocketCallBack)(CFSocketRef, CFOptionFlags, CFDataRef,
+                                 const void *, void *);
+typedef struct {
+  CFIndex version;
+  void *info;
+  const void *(*retain)(const void *);
+  void (*release)(const void *);
+  CFStringRef (*copyDescription)(const void *);
+} CFSocketContext;
+CFSocketRef CFSocketCreate(const void *, int, int, int, CFOptionFlags,
+                           CFSocketCallBack, const CFSocketContext *);
+CFIndex CFSocketConnectToAddress(CFSocketRef, CFDataRef, CFTimeInterval);
+CFIndex CFSocketSendData(CFSocketRef, CFDataRef, CFDataRef, CFTimeInterval);
+CFRunLoopSourceRef CFSocketCreateRunLoopSource(const void *, CFSocketRef,
+                                               CFIndex);
+void CFSocketInvalidate(CFSocketRef);
+typedef void *CFReadStreamRef;
+typedef void *CFWriteStreamRef;
+void CFStreamCreatePairWithSocketToHost(const void *, CFStringRef, unsigned int,
+                                        CFReadStreamRef *, CFWriteStreamRef *);
+BOOL CFReadStreamOpen(CFReadStreamRef);
+BOOL CFWriteStreamOpen(CFWriteStreamRef);
+void CFReadStreamClose(CFReadStreamRef);
+void CFWriteStreamClose(CFWriteStreamRef);
+CFIndex CFReadStreamRead(CFReadStreamRef, unsigned char *, CFIndex);
+CFIndex CFWriteStreamWrite(CFWriteStreamRef, const unsigned char *, CFIndex);
+typedef unsigned int CFStringEncoding;
+#define kCFStringEncodingASCII 0x0600
+#define kCFStringEncodingUTF8 0x08000100
+CFStringRef CFStringCreateWithCString(const void *, const char *,
+                                      CFStringEncoding);
+BOOL CFStringGetCString(CFStringRef, char *, CFIndex, CFStringEncoding);
+CFIndex CFStringGetMaximumSizeOfFileSystemRepresentation(CFStringRef);
+BOOL CFStringGetFileSystemRepresentation(CFStringRef, char *, CFIndex);
+typedef unsigned long CFTypeID;
+extern const CFTypeRef kCFNull;
+CFTypeID CFNullGetTypeID(void);
+typedef const void *CFDictionaryRef;
+typedef void *CFNotificationCenterRef;
+typedef void (*CFNotificationCallback)(CFNotificationCenterRef, void *,
+                                       CFStringRef, const void *,
+                                       CFDictionaryRef);
+#define CFNotificationSuspensionBehaviorDeliverImmediately 4
+CFNotificationCenterRef CFNotificationCenterGetLocalCenter(void);
+CFNotificationCenterRef CFNotificationCenterGetDarwinNotifyCenter(void);
+void CFNotificationCenterAddObserver(CFNotificationCenterRef, const void *,
+                                     CFNotificationCallback, CFStringRef,
+                                     const void *, CFIndex);
+void CFNotificationCenterRemoveObserver(CFNotificationCenterRef, const void *,
+                                        CFStringRef, const void *);
+void CFNotificationCenterRemoveEveryObserver(CFNotificationCenterRef,
+                                             const void *);
+void CFNotificationCenterPostNotification(CFNotificationCenterRef, CFStringRef,
+                                          const void *, CFDictionaryRef, BOOL);
+
+// <AudioToolbox/AudioServices.h>
+typedef int OSStatus;
+typedef unsigned int SystemSoundID;
+#define kSystemSoundID_Vibrate 0x00000FFF
+typedef void (*AudioServicesSystemSoundCompletionProc)(SystemSoundID, void *);
+OSStatus AudioServicesCreateSystemSoundID(const void *, SystemSoundID *);
+OSStatus AudioServicesDisposeSystemSoundID(SystemSoundID);
+void AudioServicesPlaySystemSound(SystemSoundID);
+OSStatus AudioServicesAddSystemSoundCompletion(
+    SystemSoundID, CFRunLoopRef, CFStringRef,
+    AudioServicesSystemSoundCompletionProc, void *);
+void AudioServicesRemoveSystemSoundCompletion(SystemSoundID);
+
 // === Main code ===
 
 int int_compar(const void *a, const void *b) { return *(int *)a - *(int *)b; }
@@ -161,6 +898,73 @@ int test_qsort() {
   return 0;
 }
 
+// Sorts in ascending or descending order depending on the thunk, and counts
+// the calls.
+struct qsort_r_thunk {
+  int descending;
+  int calls;
+};
+int int_compar_r(void *thunk, const void *a, const void *b) {
+  struct qsort_r_thunk *t = thunk;
+  t->calls++;
+  int diff = *(int *)a - *(int *)b;
+  return t->descending ? -diff : diff;
+}
+
+int test_qsort_r() {
+  int arr[] = {3, -7, 12, 0, 5};
+  struct qsort_r_thunk thunk = {1, 0};
+  qsort_r(arr, 5, sizeof(int), &thunk, &int_compar_r);
+  if (memcmp(arr, (int[]){12, 5, 3, 0, -7}, sizeof(arr)) || thunk.calls == 0)
+    return -1;
+  thunk.descending = 0;
+  qsort_r(arr, 5, sizeof(int), &thunk, &int_compar_r);
+  if (memcmp(arr, (int[]){-7, 0, 3, 5, 12}, sizeof(arr)))
+    return -2;
+  return 0;
+}
+
+struct score {
+  int points;
+  char name;
+};
+int score_compar(const void *a, const void *b) {
+  return ((struct score *)b)->points - ((struct score *)a)->points;
+}
+
+int test_mergesort() {
+  // Entries with equal scores must stay in their original order.
+  struct score scores[] = {{10, 'a'}, {30, 'b'}, {10, 'c'}, {20, 'd'},
+                           {30, 'e'}, {10, 'f'}, {20, 'g'}};
+  if (mergesort(scores, 7, sizeof(struct score), &score_compar) != 0)
+    return -1;
+  const char *expected = "bedgacf";
+  for (int i = 0; i < 7; i++) {
+    if (scores[i].name != expected[i])
+      return -2;
+  }
+  errno = 0;
+  if (mergesort(scores, 7, 1, &score_compar) != -1 || errno != EINVAL)
+    return -3;
+  return 0;
+}
+
+int test_heapsort() {
+  int arr[] = {9, -3, 27, 0, 4, 4, -100, 81, 2};
+  if (heapsort(arr, 9, sizeof(int), &int_compar) != 0 ||
+      memcmp(arr, (int[]){-100, -3, 0, 2, 4, 4, 9, 27, 81}, sizeof(arr)))
+    return -1;
+  // Elements wider than a word, sorted by their first field.
+  struct score scores[] = {{3, 'a'}, {1, 'b'}, {2, 'c'}};
+  if (heapsort(scores, 3, sizeof(struct score), &score_compar) != 0 ||
+      scores[0].name != 'a' || scores[1].name != 'c' || scores[2].name != 'b')
+    return -2;
+  errno = 0;
+  if (heapsort(arr, 9, 0, &int_compar) != -1 || errno != EINVAL)
+    return -3;
+  return 0;
+}
+
 char *str_format(const char *format, ...) {
   char *str = malloc(256);
   if (str == NULL) {
@@ -203,6 +1007,80 @@ int test_vsnprintf() {
   res += !!strcmp(str, "10.123450|10.123450|10.123450|10|      10|10.123|  "
                        "10.123|0010.123|10.123450|10.123450");
   free(str);
+  // Test %f rounding, which is exact and rounds halfway cases to even
+  str = str_format("%.0f|%.0f|%.0f|%.0f|%.2f|%.2f|%.1f|%.3f|%#.0f", 2.5, 3.5,
+                   0.5, -0.5, 0.125, 1.005, 0.25, -1e-10, 1.0);
+  res += !!strcmp(str, "2|4|0|-0|0.12|1.00|0.2|-0.000|1.");
+  free(str);
+  // Test %f with negative zero and large magnitudes
+  str = str_format("%f|%f|%.0f", -0.0, 1e22, 1e300);
+  res += !!strcmp(
+      str, "-0.000000|10000000000000000000000.000000|"
+           "100000000000000005250476025520442024870446858110815915491585411551"
+           "180245798890819578637137508044786404370444383288387817694252323536"
+           "043057564479218478670698284838720092657580373783023379478809005936"
+           "895323497079994508111903896764088007465274278014249457925878882005"
+           "6842838115669472196386865459400540160");
+  free(str);
+  // Test %f and %F with infinity and NaN, which are never zero-padded
+  str = str_format("%f|%F|%f|%F|%5.1f|%06f|%#.0f|%-5f|", __builtin_inf(),
+                   __builtin_inf(), -__builtin_inf(), __builtin_nan(""),
+                   __builtin_inf(), __builtin_inf(), __builtin_inf(),
+                   -__builtin_nan(""));
+  res += !!strcmp(str, "inf|INF|-inf|NAN|  inf|   inf|inf|nan  |");
+  free(str);
+  // Test %e
+  str = str_format("%e|%.3E|%12.2e|%012.2e|%.0e|%#.0e|%e", 1234.5678, 1234.5678,
+                   -0.000123, -0.000123, 5.0, 5.0, 1e100);
+  res += !!strcmp(str, "1.234568e+03|1.235E+03|   -1.23e-04|-0001.23e-04|5e+00|"
+                       "5.e+00|1.000000e+100");
+  free(str);
+  // Test %g
+  str = str_format("%g|%.3g|%g|%g|%G|%#g|%.0g|%08.3g|%g", 100000.0, 3.14159,
+                   1234567.0, 0.0001, 0.00001, 1.5, 123.0, -0.5, 0.0);
+  res += !!strcmp(str, "100000|3.14|1.23457e+06|0.0001|1E-05|1.50000|1e+02|"
+                       "-00000.5|0");
+  free(str);
+  // Test the '-' flag
+  str = str_format("%-8d|%-10s|%-6.2f|%-08x|%*d|%-3c|%5s", -42, "left", 3.14159,
+                   255, -4, 7, 'c', "abc");
+  res += !!strcmp(str, "-42     |left      |3.14  |ff      |7   |c  |  abc");
+  free(str);
+  // Test widths and precisions for %c and %s
+  char unterminated[3] = {'x', 'y', 'z'};
+  str = str_format("%3c|%20s|%.2s|%-6.3s|%.3s|%.10s|%.0s|", 'a', "menu item",
+                   "abc", "abcdef", unterminated, "short", "gone");
+  res += !!strcmp(str, "  a|           menu item|ab|abc   |xyz|short||");
+  free(str);
+  // Test length modifiers
+  str = str_format("%lld|%llu|%hhd|%hhu|%hd|%llx|%ld|%d", -1234567890123ll,
+                   18446744073709551615ull, 300, -1, 40000, 0x123456789abcull,
+                   -5l, 42);
+  res += !!strcmp(str, "-1234567890123|18446744073709551615|44|255|-25536|"
+                       "123456789abc|-5|42");
+  free(str);
+  // Test %o and %p
+  str = str_format("%o|%#o|%#o|%5o|%-5o|%05o|%.4o|%#.0o|%p|%p", 0755, 0644, 0,
+                   8, 8, 8, 8, 0, (void *)0x1abc, NULL);
+  res += !!strcmp(str, "755|0644|0|   10|10   |00010|0010|0|0x1abc|0x0");
+  free(str);
+  // Test positional arguments
+  str = str_format("%2$s, %1$s!", "world", "Hello");
+  res += !!strcmp(str, "Hello, world!");
+  free(str);
+  str = str_format("%3$f|%1$lld|%2$d|%1$llx", 1234567890123ll, 7, 0.5);
+  res += !!strcmp(str, "0.500000|1234567890123|7|11f71fb04cb");
+  free(str);
+  str = str_format("%1$*2$d|%1$-*2$d|%2$03d", 42, 5);
+  res += !!strcmp(str, "   42|42   |005");
+  free(str);
+  // Test a zero size: nothing is written, but the length is still returned
+  char buf[2] = {'x', 'y'};
+  res += snprintf(buf, 0, "%d", 5) != 1;
+  res += buf[0] != 'x' || buf[1] != 'y';
+  // Test truncation
+  res += snprintf(buf, 2, "%d", 123) != 3;
+  res += !!strcmp(buf, "1");
 
   return res;
 }
@@ -226,9 +1104,122 @@ int test_sscanf() {
   matched = sscanf("9,10", "%hi,%i", &c, &a);
   if (!(c == 9 && a == 10))
     return -1;
+  matched = sscanf("ab]12", "%[]ab]%d", str, &a);
+  if (!(matched == 2 && strcmp(str, "ab]") == 0 && a == 12))
+    return -1;
+  matched = sscanf("  -12 \t 34", "%d %d", &a, &b);
+  if (!(matched == 2 && a == -12 && b == 34))
+    return -1;
+  matched = sscanf("0x1f 010", "%i%i", &a, &b);
+  if (!(matched == 2 && a == 31 && b == 8))
+    return -1;
+  unsigned int u;
+  matched = sscanf("42abc", "%u%d", &u, &b);
+  if (!(matched == 1 && u == 42))
+    return -1;
+  float f;
+  double d;
+  matched = sscanf("1.5,-2.25e1", "%f,%lf", &f, &d);
+  if (!(matched == 2 && f == 1.5 && d == -22.5))
+    return -1;
+  matched = sscanf("", "%d", &a);
+  if (matched != -1)
+    return -1;
+  char name[16];
+  unsigned flags;
+  matched = sscanf("  player_1 \t 1F\n", "%s %x", name, &flags);
+  if (!(matched == 2 && strcmp(name, "player_1") == 0 && flags == 0x1f))
+    return -1;
+  matched = sscanf("abcdefgh 12345", "%3s%s %2d%X", str, name, &a, &u);
+  if (!(matched == 4 && strcmp(str, "abc") == 0 &&
+        strcmp(name, "defgh") == 0 && a == 12 && u == 0x345))
+    return -1;
+  char chars[4] = {'x', 'x', 'x', 'x'};
+  matched = sscanf(" ab c", "%c%c%2c", &chars[0], &chars[1], &chars[2]);
+  if (!(matched == 3 && memcmp(chars, " ab ", 4) == 0))
+    return -1;
+  matched = sscanf("x", "%c%c", &chars[0], &chars[1]);
+  if (!(matched == 1 && chars[0] == 'x'))
+    return -1;
+  // Sets with ranges, a literal '-' at either end, and the input ending
+  // within a set.
+  char word[16];
+  matched = sscanf("2010,Level_9-B", "%[0-9],%[a-zA-Z_]", name, word);
+  if (!(matched == 2 && strcmp(name, "2010") == 0 &&
+        strcmp(word, "Level_") == 0))
+    return -1;
+  matched = sscanf("9-B,x", "%[-9]%[A-C-]%[^,]", str, name, word);
+  if (!(matched == 2 && strcmp(str, "9-") == 0 && strcmp(name, "B") == 0))
+    return -1;
+  matched = sscanf("edcba-", "%[a-c-e]", name);
+  if (!(matched == 1 && strcmp(name, "edcba") == 0))
+    return -1;
+  matched = sscanf("z-a", "%[z-a]%d", str, &a);
+  if (!(matched == 1 && strcmp(str, "z-a") == 0))
+    return -1;
+  return 0;
+}
+
+int vsscanf_wrapper(const char *src, const char *format, ...) {
+  va_list args;
+  va_start(args, format);
+  int matched = vsscanf(src, format, args);
+  va_end(args);
+  return matched;
+}
+
+int test_vsscanf() {
+  int a;
+  short b;
+  char str[8];
+  int matched = vsscanf_wrapper("7,-8 rest", "%d,%hd %[^\n]", &a, &b, str);
+  if (!(matched == 3 && a == 7 && b == -8 && strcmp(str, "rest") == 0))
+    return -1;
   return 0;
 }
 
+int vfscanf_wrapper(FILE *f, const char *format, ...) {
+  va_list args;
+  va_start(args, format);
+  int matched = vfscanf(f, format, args);
+  va_end(args);
+  return matched;
+}
+
+int test_fscanf() {
+  const char *path = "/tmp/fscanf_test.txt";
+  FILE *f = fopen(path, "w");
+  if (!f)
+    return -1;
+  const char *contents = "12 abc,7\n-3 45";
+  fwrite(contents, 1, strlen(contents), f);
+  fclose(f);
+
+  int res = 0;
+  int a = 0, b = 0, c = 0;
+  char str[8] = {0};
+  f = fopen(path, "r");
+  if (!f)
+    return -2;
+  // A conversion stops before the character that doesn't match, so the
+  // next one starts from it.
+  if (fscanf(f, "%d %[^,],", &a, str) != 2 || a != 12 || strcmp(str, "abc"))
+    res = -3;
+  if (!res && (vfscanf_wrapper(f, "%d", &a) != 1 || a != 7 ||
+               fgetc(f) != '\n'))
+    res = -4;
+  // The end of the file cuts the last field short, which still matches.
+  if (!res && (fscanf(f, "%d %3d%d", &a, &b, &c) != 2 || a != -3 || b != 45))
+    res = -5;
+  // Nothing is left.
+  if (!res &&
+      (fscanf(f, "%d", &a) != -1 || vfscanf_wrapper(f, " %d", &a) != -1))
+    res = -6;
+  fclose(f);
+  remove(path);
+  return res;
+}
+
 int test_errno() { return (errno == 0) ? 0 : -1; }
 
 int test_realloc() {
@@ -240,6 +1231,23 @@ int test_realloc() {
   return res == 0 ? 0 : -1;
 }
 
+int test_aligned_alloc() {
+  void *ptr = (void *)1;
+  if (posix_memalign(&ptr, 24, 16) != EINVAL || ptr != (void *)1)
+    return -1;
+  if (posix_memalign(&ptr, 2, 16) != EINVAL)
+    return -2;
+  if (posix_memalign(&ptr, 64, 100) != 0 || (size_t)ptr % 64 != 0)
+    return -3;
+  memset(ptr, 0xff, 100);
+  free(ptr);
+  ptr = valloc(10);
+  if (!ptr || (size_t)ptr % 4096 != 0)
+    return -4;
+  free(ptr);
+  return 0;
+}
+
 int test_atof() {
   if (atof("1") != 1)
     return -1;
@@ -379,6 +1387,56 @@ int test_strtof() {
   text = "1.5";
   if (strtof(text, NULL) != 1.5)
     return -27;
+  text = "0x1.8p+3";
+  if (strtof(text, &endptr) != 12.0 || endptr != text + 8)
+    return -28;
+  return 0;
+}
+
+int test_strtod() {
+  char *text = "0x1.8p+3";
+  char *endptr;
+  double inf = 1.0 / 0.0;
+  double d;
+  if (strtod(text, &endptr) != 12.0 || endptr != text + 8)
+    return -1;
+  text = "  -0X10";
+  if (strtod(text, &endptr) != -16.0 || endptr != text + 7)
+    return -2;
+  // The exponent is optional, and is only consumed if it has digits.
+  text = "0x.8p";
+  if (strtod(text, &endptr) != 0.5 || endptr != text + 4)
+    return -3;
+  text = "0x1p-2xyz";
+  if (strtod(text, &endptr) != 0.25 || endptr != text + 6)
+    return -4;
+  // Without any hex digits, only the 0 is a number.
+  text = "0xg";
+  if (strtod(text, &endptr) != 0.0 || endptr != text + 1)
+    return -5;
+  text = "0x8p-1077";
+  if (strtod(text, &endptr) != 4.9406564584124654e-324 || endptr != text + 9)
+    return -6;
+  text = "-inf";
+  if (strtod(text, &endptr) != -inf || endptr != text + 4)
+    return -7;
+  text = "INFINITY!";
+  if (strtod(text, &endptr) != inf || endptr != text + 8)
+    return -8;
+  text = "infin";
+  if (strtod(text, &endptr) != inf || endptr != text + 3)
+    return -9;
+  text = "nan(123)x";
+  d = strtod(text, &endptr);
+  if (d == d || endptr != text + 8)
+    return -10;
+  text = "NaN(";
+  d = strtod(text, &endptr);
+  if (d == d || endptr != text + 3)
+    return -11;
+  text = "  +123.456e7with text right after";
+  if (strtod(text, &endptr) != 1234560000.0 || endptr != text + 12)
+    return -12;
   return 0;
 }
 
@@ -436,99 +1494,487 @@ int test_getcwd_chdir() {
   return 0;
 }
 
-sem_t *semaphore;
-int shared_int = 0;
+int test_cwd_relative_paths() {
+  char buf[256];
+  const char *documents =
+      "/var/mobile/Applications/00000000-0000-0000-0000-000000000000/Documents";
+  struct stat st;
 
-void sem_thread_func() {
-  while (1) {
-    if (sem_trywait(semaphore) == -1) {
-      return;
-    }
-    shared_int = -1;
-    sem_post(semaphore);
-    usleep(100);
+  if (chdir(documents))
+    return -1;
+
+  // Relative paths resolve against the working directory.
+  int fd = open("cwd_test.txt", O_WRONLY | O_CREAT | O_TRUNC, 0644);
+  if (fd == -1)
+    return -1;
+  int written = write(fd, "test", 4);
+  close(fd);
+  if (written != 4)
+    return -1;
+  char *abs_path = str_format("%s/cwd_test.txt", documents);
+  int res = stat(abs_path, &st);
+  if (res == 0 && rename("cwd_test.txt", "./cwd_test2.txt") == 0) {
+    errno = 0;
+    if (stat(abs_path, &st) != -1 || errno != ENOENT)
+      res = -1;
+    errno = 0;
+    if (rename("cwd_test.txt", "cwd_test3.txt") != -1 || errno != ENOENT)
+      res = -1;
+    free(abs_path);
+    abs_path = str_format("%s/cwd_test2.txt", documents);
+    if (stat(abs_path, &st) != 0)
+      res = -1;
+  } else {
+    res = -1;
   }
-}
+  if (unlink("cwd_test2.txt") && unlink("cwd_test.txt"))
+    res = -1;
+  if (stat(abs_path, &st) != -1)
+    res = -1;
+  free(abs_path);
+  if (res)
+    return -1;
 
-int test_sem() {
-  semaphore = sem_open("sem_test", O_CREAT, 0644, 1);
-  if (semaphore == SEM_FAILED) {
-    printf("Error opening semaphore\n");
+  // getcwd() error handling and the allocating extension.
+  errno = 0;
+  if (getcwd(buf, 4) || errno != ERANGE)
+    return -1;
+  errno = 0;
+  if (getcwd(buf, 0) || errno != EINVAL)
+    return -1;
+  char *allocated = getcwd(NULL, sizeof buf);
+  if (!allocated || strcmp(documents, allocated))
+    return -1;
+  free(allocated);
+
+  // fchdir() returns to a directory opened earlier.
+  int dir_fd = open("..", O_RDONLY);
+  if (dir_fd == -1)
+    return -1;
+  if (chdir("/") || fchdir(dir_fd)) {
+    close(dir_fd);
     return -1;
   }
+  close(dir_fd);
+  char *cwd = getcwd(buf, sizeof buf);
+  if (!cwd ||
+      strcmp("/var/mobile/Applications/00000000-0000-0000-0000-000000000000",
+             cwd))
+    return -1;
 
-  pthread_t *my_thread = (pthread_t *)malloc(sizeof(pthread_t));
-  pthread_create(my_thread, NULL, (void *)sem_thread_func, NULL);
-  usleep(200);
+  if (chdir("/"))
+    return -1;
 
-  sem_wait(semaphore);
+  return 0;
+}
 
-  shared_int = 1;
-  usleep(200);
+int test_user_identity() {
+  if (getuid() != 501 || geteuid() != 501 || getgid() != 501 ||
+      getegid() != 501)
+    return -1;
+  if (getpid() <= 0 || getpid() != getpid())
+    return -1;
 
-  sem_close(semaphore);
-  sem_unlink("sem_test");
+  struct passwd *pw = getpwuid(getuid());
+  if (!pw || strcmp(pw->pw_name, "mobile") || pw->pw_uid != 501 ||
+      pw->pw_gid != 501)
+    return -1;
+  // The record is stable across calls and shared with getpwnam().
+  if (getpwnam("mobile") != pw || getpwuid(501) != pw)
+    return -1;
+  if (getpwnam("root") || getpwuid(0))
+    return -1;
 
-  return shared_int == 1 ? 0 : -1;
-}
+  char *login = getlogin();
+  if (!login || strcmp(login, "mobile"))
+    return -1;
 
-int done = 0;
-pthread_mutex_t m;
-pthread_cond_t c;
+  char *home = getenv("HOME");
+  if (!home || strcmp(home, pw->pw_dir) ||
+      strcmp(home,
+             "/var/mobile/Applications/00000000-0000-0000-0000-000000000000"))
+    return -1;
 
-void thr_exit() {
-  pthread_mutex_lock(&m);
-  done = 1;
-  pthread_cond_signal(&c);
-  pthread_mutex_unlock(&m);
+  return 0;
 }
 
-void *child(void *arg) {
-  thr_exit();
-  return NULL;
+const char *first_search_path(NSUInteger directory, BOOL expand_tilde) {
+  id paths = NSSearchPathForDirectoriesInDomains(directory, NSUserDomainMask,
+                                                 expand_tilde);
+  id path = objc_msgSend(paths, sel_registerName("objectAtIndex:"), 0);
+  return (const char *)objc_msgSend(path, sel_registerName("UTF8String"));
 }
 
-void thr_join() {
-  pthread_mutex_lock(&m);
-  while (done == 0) {
-    pthread_cond_wait(&c, &m);
+int test_file_attributes() {
+  const char *documents = first_search_path(NSDocumentDirectory, 1);
+  char *path = str_format("%s/attributes_test.txt", documents);
+  struct stat st;
+  int res = 0;
+
+  // Times set with futimes() and utimes() are seen by fstat() and stat().
+  int fd = open(path, O_WRONLY | O_CREAT | O_TRUNC, 0644);
+  if (fd == -1) {
+    free(path);
+    return -1;
   }
-  pthread_mutex_unlock(&m);
-}
+  struct timeval times[2] = {{1000000000, 0}, {1234567890, 0}};
+  if (write(fd, "hello", 5) != 5 || futimes(fd, times) || fstat(fd, &st) ||
+      st.st_mtime != 1234567890 || st.st_atime != 1000000000 ||
+      st.st_size != 5 || (st.st_mode & S_IFMT) != S_IFREG)
+    res = -2;
+  close(fd);
+  times[1].tv_sec = 1300000000;
+  if (!res && (utimes(path, times) || stat(path, &st) ||
+               st.st_mtime != 1300000000 || st.st_size != 5))
+    res = -3;
+  struct utimbuf utimbuf = {1000000000, 1350000000};
+  if (!res && (utime(path, &utimbuf) || stat(path, &st) ||
+               st.st_mtime != 1350000000))
+    res = -4;
 
-int test_cond_var() {
-  pthread_t p;
+  // Permissions can be changed inside the sandbox.
+  if (!res && (chmod(path, 0444) || stat(path, &st) ||
+               (st.st_mode & 0777) != 0444 || chmod(path, 0644) ||
+               stat(path, &st) || (st.st_mode & 0777) != 0644))
+    res = -5;
+  errno = 0;
+  if (!res && (chmod("/nonexistent", 0644) != -1 || errno != ENOENT))
+    res = -6;
 
-  pthread_mutex_init(&m, NULL);
-  pthread_cond_init(&c, NULL);
+  // NSFileManager sees the same attributes, and can set them too.
+  id string_class = objc_msgSend(NSHomeDirectory(), sel_registerName("class"));
+  SEL with_utf8 = sel_registerName("stringWithUTF8String:");
+  SEL object_for_key = sel_registerName("objectForKey:");
+  SEL since_reference_date = sel_registerName("timeIntervalSinceReferenceDate");
+  id ns_path = objc_msgSend(string_class, with_utf8, path);
+  id manager = objc_msgSend(
+      NSClassFromString(objc_msgSend(string_class, with_utf8, "NSFileManager")),
+      sel_registerName("defaultManager"));
+  id date_key = objc_msgSend(string_class, with_utf8, "NSFileModificationDate");
+  id size_key = objc_msgSend(string_class, with_utf8, "NSFileSize");
+  id error = NULL;
+  id attributes =
+      objc_msgSend(manager, sel_registerName("attributesOfItemAtPath:error:"),
+                   ns_path, &error);
+  // 978307200 is the reference date (2001-01-01) in UNIX time.
+  if (!res &&
+      (!attributes || error ||
+       (int)objc_msgSend(objc_msgSend(attributes, object_for_key, size_key),
+                         sel_registerName("intValue")) != 5 ||
+       ((double (*)(id, SEL))objc_msgSend)(
+           objc_msgSend(attributes, object_for_key, date_key),
+           since_reference_date) != 1350000000.0 - 978307200.0))
+    res = -7;
 
-  pthread_create(&p, NULL, child, NULL);
-  thr_join();
+  id date_class =
+      NSClassFromString(objc_msgSend(string_class, with_utf8, "NSDate"));
+  id date = ((id (*)(id, SEL, double))objc_msgSend)(
+      date_class, sel_registerName("dateWithTimeIntervalSinceReferenceDate:"),
+      1400000000.0 - 978307200.0);
+  id new_attributes = objc_msgSend(
+      NSClassFromString(objc_msgSend(string_class, with_utf8, "NSDictionary")),
+      sel_registerName("dictionaryWithObjectsAndKeys:"), date, date_key, NULL);
+  SEL set_attributes = sel_registerName("setAttributes:ofItemAtPath:error:");
+  if (!res && (!objc_msgSend(manager, set_attributes, new_attributes, ns_path,
+                             &error) ||
+               error || stat(path, &st) || st.st_mtime != 1400000000))
+    res = -8;
 
-  return done == 1 ? 0 : -1;
+  // Errors are reported with NSError rather than crashing.
+  id missing_path = objc_msgSend(string_class, with_utf8, "/nonexistent");
+  if (!res &&
+      (objc_msgSend(manager, set_attributes, new_attributes, missing_path,
+                    &error) ||
+       !error || (int)objc_msgSend(error, sel_registerName("code")) != 4))
+    res = -9;
+
+  if (unlink(path))
+    res = -10;
+  free(path);
+  return res;
 }
 
-int test_strncpy() {
-  char *src = "test\0abcd";
-  char dst[10];
-  char *retval;
+int test_mkdir_p() {
+  const char *documents =
+      "/var/mobile/Applications/00000000-0000-0000-0000-000000000000/Documents";
+  if (chdir(documents))
+    return -1;
+  // mkdir() reports a missing parent with ENOENT, so apps can create the
+  // parents themselves, and an existing directory with EEXIST.
+  errno = 0;
+  if (mkdir("mkdir_test/a", 0755) != -1 || errno != ENOENT)
+    return -2;
+  if (mkdir("mkdir_test", 0755) || mkdir("mkdir_test/a", 0755))
+    return -3;
+  errno = 0;
+  if (mkdir("mkdir_test/a", 0755) != -1 || errno != EEXIST)
+    return -4;
+  int fd = open("mkdir_test/file", O_WRONLY | O_CREAT | O_TRUNC, 0644);
+  if (fd == -1)
+    return -5;
+  close(fd);
+  errno = 0;
+  if (mkdir("mkdir_test/file/a", 0755) != -1 || errno != ENOTDIR)
+    return -6;
 
-  char expected1[] = "test\x00\x7F\x7F\x7F\x7F\x7F";
-  memset(dst, 0x7F, 10);
-  retval = strncpy(dst, src, 5);
-  if (retval != dst || memcmp(retval, expected1, 10))
-    return 1;
+  // NSFileManager can create the intermediate directories itself.
+  id string_class = objc_msgSend(NSHomeDirectory(), sel_registerName("class"));
+  SEL with_utf8 = sel_registerName("stringWithUTF8String:");
+  SEL create = sel_registerName(
+      "createDirectoryAtPath:withIntermediateDirectories:attributes:error:");
+  id manager = objc_msgSend(
+      NSClassFromString(objc_msgSend(string_class, with_utf8, "NSFileManager")),
+      sel_registerName("defaultManager"));
+  id nested = objc_msgSend(string_class, with_utf8, "mkdir_test/b/c");
+  id existing = objc_msgSend(string_class, with_utf8, "mkdir_test/a");
+  id under_file = objc_msgSend(string_class, with_utf8, "mkdir_test/file/a");
+  id error = NULL;
+  int res = 0;
+  if (objc_msgSend(manager, create, nested, 0, NULL, &error) || !error ||
+      (int)objc_msgSend(error, sel_registerName("code")) != 4)
+    res = -7;
+  error = NULL;
+  if (!res && (!objc_msgSend(manager, create, nested, 1, NULL, &error) ||
+               error || mkdir("mkdir_test/b/c", 0755) != -1 || errno != EEXIST))
+    res = -8;
+  if (!res && (!objc_msgSend(manager, create, existing, 1, NULL, &error) ||
+               objc_msgSend(manager, create, existing, 0, NULL, NULL)))
+    res = -9;
+  if (!res && objc_msgSend(manager, create, under_file, 1, NULL, NULL))
+    res = -10;
 
-  char expected2[] = "te\x7F\x7F\x7F\x7F\x7F\x7F\x7F\x7F";
-  memset(dst, 0x7F, 10);
-  retval = strncpy(dst, src, 2);
-  if (retval != dst || memcmp(retval, expected2, 10))
-    return 2;
+  if (remove("mkdir_test/b/c") || remove("mkdir_test/b") ||
+      remove("mkdir_test/a") || remove("mkdir_test/file") ||
+      remove("mkdir_test") || chdir("/"))
+    res = -11;
+  return res;
+}
 
-  char expected3[] = "test\x00\x00\x00\x00\x00\x00";
-  memset(dst, 0x7F, 10);
-  retval = strncpy(dst, src, 10);
-  if (retval != dst || memcmp(retval, expected3, 10))
+int test_time() {
+  time_t t = 0;
+  time_t now = time(&t);
+  // iPhone OS 2.0 came out in July 2008.
+  if (now != t || now < 1214870400)
+    return -1;
+  if (difftime(now + 90, now) != 90.0 || difftime(now, now + 1) != -1.0)
+    return -2;
+
+  // Keep the processor busy for a bit, so clock() has to advance too.
+  struct timeval start, end;
+  clock_t clock_start = clock();
+  if (gettimeofday(&start, NULL) || start.tv_sec < now)
+    return -3;
+  long long elapsed_us;
+  do {
+    if (gettimeofday(&end, NULL))
+      return -4;
+    elapsed_us = (end.tv_sec - start.tv_sec) * 1000000LL +
+                 (end.tv_usec - start.tv_usec);
+  } while (elapsed_us < 20000);
+  if (clock() <= clock_start)
+    return -5;
+
+  // CFAbsoluteTime counts from 2001 rather than 1970.
+  double unix_time = CFAbsoluteTimeGetCurrent() + 978307200.0;
+  if (unix_time < (double)now || unix_time > (double)now + 60.0)
+    return -6;
+  return 0;
+}
+
+int test_getenv() {
+  char *home = getenv("HOME");
+  if (!home || strcmp(getenv("CFFIXED_USER_HOME"), home))
+    return -1;
+  char *tmp_dir = str_format("%s/tmp/", home);
+  int res = strcmp(getenv("TMPDIR"), tmp_dir);
+  free(tmp_dir);
+  if (res)
+    return -2;
+  if (strcmp(getenv("USER"), "mobile") || strcmp(getenv("LOGNAME"), "mobile"))
+    return -3;
+  if (!getenv("PATH") || !getenv("SHELL"))
+    return -4;
+  // The values are stable across calls.
+  if (getenv("HOME") != home)
+    return -5;
+
+  if (setenv("HOME", "/elsewhere", 0) || getenv("HOME") != home)
+    return -6;
+  if (setenv("TOUCHHLE_TEST", "1", 0) || strcmp(getenv("TOUCHHLE_TEST"), "1"))
+    return -7;
+  if (unsetenv("TOUCHHLE_TEST") || getenv("TOUCHHLE_TEST"))
+    return -8;
+
+  // putenv() uses the string itself rather than a copy.
+  char string[] = "TOUCHHLE_TEST=abc";
+  if (putenv(string) || strcmp(getenv("TOUCHHLE_TEST"), "abc"))
+    return -9;
+  string[14] = 'x';
+  if (strcmp(getenv("TOUCHHLE_TEST"), "xbc"))
+    return -10;
+  // Replacing it mustn't try to free the app's string.
+  if (setenv("TOUCHHLE_TEST", "def", 1) ||
+      strcmp(getenv("TOUCHHLE_TEST"), "def"))
+    return -11;
+  if (putenv(string) || getenv("TOUCHHLE_TEST") != string + 14)
+    return -12;
+  // A string without '=' removes the variable.
+  char name[] = "TOUCHHLE_TEST";
+  if (putenv(name) || getenv("TOUCHHLE_TEST"))
+    return -13;
+  return 0;
+}
+
+int test_search_paths() {
+  const char *home = (const char *)objc_msgSend(
+      NSHomeDirectory(), sel_registerName("UTF8String"));
+  const char *documents = first_search_path(NSDocumentDirectory, 1);
+  char *expected = str_format("%s/Documents", home);
+  int res = strcmp(documents, expected);
+  free(expected);
+  if (res)
+    return -1;
+
+  if (strcmp(first_search_path(NSCachesDirectory, 0), "~/Library/Caches"))
+    return -2;
+  // Caches is created on demand.
+  struct stat st;
+  char *caches = str_format("%s/Library/Caches", home);
+  res = stat(caches, &st);
+  free(caches);
+  if (res)
+    return -3;
+
+  if (strcmp((const char *)objc_msgSend(NSUserName(),
+                                        sel_registerName("UTF8String")),
+             "mobile"))
+    return -4;
+
+  // Directories without a fixed location give an empty array.
+  id no_paths = NSSearchPathForDirectoriesInDomains(NSItemReplacementDirectory,
+                                                    NSUserDomainMask, 1);
+  if (!no_paths || objc_msgSend(no_paths, sel_registerName("count")))
+    return -5;
+
+  // A file written with stdio can be read back with Foundation.
+  char *path = str_format("%s/search_paths_test.txt", documents);
+  FILE *file = fopen(path, "w");
+  if (!file) {
+    free(path);
+    return -6;
+  }
+  fwrite("hello", 1, 5, file);
+  fclose(file);
+  id string_class = objc_msgSend(NSHomeDirectory(), sel_registerName("class"));
+  id ns_path = objc_msgSend(string_class,
+                            sel_registerName("stringWithUTF8String:"), path);
+  id ns_data_name = objc_msgSend(
+      string_class, sel_registerName("stringWithUTF8String:"), "NSData");
+  id data = objc_msgSend(NSClassFromString(ns_data_name),
+                         sel_registerName("dataWithContentsOfFile:"), ns_path);
+  res = -7;
+  if (data &&
+      (NSUInteger)objc_msgSend(data, sel_registerName("length")) == 5 &&
+      !memcmp(objc_msgSend(data, sel_registerName("bytes")), "hello", 5))
+    res = 0;
+  remove(path);
+  free(path);
+  return res;
+}
+
+sem_t *semaphore;
+int shared_int = 0;
+
+void sem_thread_func() {
+  while (1) {
+    if (sem_trywait(semaphore) == -1) {
+      return;
+    }
+    shared_int = -1;
+    sem_post(semaphore);
+    usleep(100);
+  }
+}
+
+int test_sem() {
+  semaphore = sem_open("sem_test", O_CREAT, 0644, 1);
+  if (semaphore == SEM_FAILED) {
+    printf("Error opening semaphore\n");
+    return -1;
+  }
+
+  pthread_t *my_thread = (pthread_t *)malloc(sizeof(pthread_t));
+  pthread_create(my_thread, NULL, (void *)sem_thread_func, NULL);
+  usleep(200);
+
+  sem_wait(semaphore);
+
+  shared_int = 1;
+  usleep(200);
+
+  sem_close(semaphore);
+  sem_unlink("sem_test");
+
+  return shared_int == 1 ? 0 : -1;
+}
+
+int done = 0;
+pthread_mutex_t m;
+pthread_cond_t c;
+
+void thr_exit() {
+  pthread_mutex_lock(&m);
+  done = 1;
+  pthread_cond_signal(&c);
+  pthread_mutex_unlock(&m);
+}
+
+void *child(void *arg) {
+  thr_exit();
+  return NULL;
+}
+
+void thr_join() {
+  pthread_mutex_lock(&m);
+  while (done == 0) {
+    pthread_cond_wait(&c, &m);
+  }
+  pthread_mutex_unlock(&m);
+}
+
+int test_cond_var() {
+  pthread_t p;
+
+  pthread_mutex_init(&m, NULL);
+  pthread_cond_init(&c, NULL);
+
+  pthread_create(&p, NULL, child, NULL);
+  thr_join();
+
+  return done == 1 ? 0 : -1;
+}
+
+int test_strncpy() {
+  char *src = "test\0abcd";
+  char dst[10];
+  char *retval;
+
+  char expected1[] = "test\x00\x7F\x7F\x7F\x7F\x7F";
+  memset(dst, 0x7F, 10);
+  retval = strncpy(dst, src, 5);
+  if (retval != dst || memcmp(retval, expected1, 10))
+    return 1;
+
+  char expected2[] = "te\x7F\x7F\x7F\x7F\x7F\x7F\x7F\x7F";
+  memset(dst, 0x7F, 10);
+  retval = strncpy(dst, src, 2);
+  if (retval != dst || memcmp(retval, expected2, 10))
+    return 2;
+
+  char expected3[] = "test\x00\x00\x00\x00\x00\x00";
+  memset(dst, 0x7F, 10);
+  retval = strncpy(dst, src, 10);
+  if (retval != dst || memcmp(retval, expected3, 10))
     return 3;
 
   return 0;
@@ -635,31 +2081,3729 @@ int test_strchr() {
     return -4;
   if (strchr(src, 'd') != NULL || strrchr(src, 'd') != NULL)
     return -5;
+  // Repeated characters: the first and last match, and the terminator rather
+  // than anything after it.
+  char *path = "dir/sub/file";
+  if (strchr(path, '/') != path + 3 || strrchr(path, '/') != path + 7 ||
+      strchr(path, '\0') != path + 12 || strrchr(path, '\0') != path + 12)
+    return -6;
+  char buf[4] = {'x', '\0', 'y', '\0'};
+  if (strchr(buf, 'y') != NULL || strrchr(buf, 'y') != NULL)
+    return -7;
   return 0;
 }
 
-#define FUNC_DEF(func)                                                         \
-  { &func, #func }
-struct {
-  int (*func)();
-  const char *name;
-} test_func_array[] = {
-    FUNC_DEF(test_qsort),   FUNC_DEF(test_vsnprintf),
-    FUNC_DEF(test_sscanf),  FUNC_DEF(test_errno),
-    FUNC_DEF(test_realloc), FUNC_DEF(test_atof),
-    FUNC_DEF(test_strtof),  FUNC_DEF(test_getcwd_chdir),
-    FUNC_DEF(test_sem),     FUNC_DEF(test_CGAffineTransform),
-    FUNC_DEF(test_strncpy), FUNC_DEF(test_strncat),
-    FUNC_DEF(test_setjmp), FUNC_DEF(test_cond_var),
-    FUNC_DEF(test_strlcpy), FUNC_DEF(test_strchr),
-};
+int test_strspn_strpbrk() {
+  const char *line = "  12,34;end";
+  if (strspn(line, " ") != 2 || strspn(line + 2, "0123456789") != 2 ||
+      strspn(line, "") != 0 || strspn(line, " ,;0123456789dne") != 11)
+    return -1;
+  if (strcspn(line, ",;") != 4 || strcspn(line, "") != 11)
+    return -2;
+  if (strpbrk(line, ";,") != line + 4 || strpbrk(line, "xyz") != NULL ||
+      strpbrk(line, "") != NULL || strpbrk("", "a") != NULL)
+    return -3;
+  return 0;
+}
+
+int test_strtok() {
+  // Leading and consecutive delimiters don't produce empty tokens.
+  char str[] = ",a,,b;c";
+  const char *expected[] = {"a", "b", "c"};
+  char *token = strtok(str, ",;");
+  for (int i = 0; i < 3; i++) {
+    if (!token || strcmp(token, expected[i]))
+      return -1;
+    token = strtok(NULL, ",;");
+  }
+  if (token || strtok(NULL, ",;"))
+    return -2;
+  // Delimiters are overwritten in-place.
+  if (memcmp(str, ",a\0,b\0c", 8))
+    return -3;
+
+  // strtok_r() keeps its state in the caller's pointer, so two strings can be
+  // tokenized at once.
+  char str1[] = "a,,b;c", str2[] = ";x;y";
+  char *last1, *last2;
+  char *a = strtok_r(str1, ",;", &last1);
+  char *x = strtok_r(str2, ",;", &last2);
+  char *b = strtok_r(NULL, ",;", &last1);
+  char *y = strtok_r(NULL, ",;", &last2);
+  char *c = strtok_r(NULL, ",;", &last1);
+  if (!a || !b || !c || !x || !y || strcmp(a, "a") || strcmp(b, "b") ||
+      strcmp(c, "c") || strcmp(x, "x") || strcmp(y, "y"))
+    return -4;
+  if (strtok_r(NULL, ",;", &last1) || strtok_r(NULL, ",;", &last2))
+    return -5;
+  char empty[] = ",;";
+  if (strtok_r(empty, ",;", &last1))
+    return -6;
+  return 0;
+}
+
+int test_strsep() {
+  // Unlike strtok(), every delimiter ends a token, even an empty one.
+  char str[] = "a::b";
+  char *rest = str;
+  const char *expected[] = {"a", "", "b"};
+  for (int i = 0; i < 3; i++) {
+    char *token = strsep(&rest, ":");
+    if (!token || strcmp(token, expected[i]))
+      return -1;
+  }
+  if (rest != NULL || strsep(&rest, ":") != NULL)
+    return -2;
+  if (memcmp(str, "a\0\0b", 5))
+    return -3;
+
+  // Any of the delimiters can end a token, and a trailing one gives a final
+  // empty token.
+  char str2[] = ";x,";
+  rest = str2;
+  char *empty = strsep(&rest, ",;");
+  char *x = strsep(&rest, ",;");
+  char *last = strsep(&rest, ",;");
+  if (empty != str2 || *empty || x != str2 + 1 || strcmp(x, "x") ||
+      last != str2 + 3 || *last || rest != NULL)
+    return -4;
+  return 0;
+}
+
+int test_strcasecmp() {
+  if (strcasecmp("PNG", "png") || strcasecmp("", "") ||
+      strcasecmp("image.PNG", "image.png"))
+    return -1;
+  // Only ASCII letters are folded, like in the C locale.
+  if (strcasecmp("png", "pnh") >= 0 || strcasecmp("PNH", "png") <= 0 ||
+      strcasecmp("png", "PNG2") >= 0 || strcasecmp("\xc9", "\xe9") == 0)
+    return -2;
+  if (strncasecmp("PNGfile", "pngFILE", 3) || strncasecmp("abc", "ABD", 2) ||
+      strncasecmp("abc", "xyz", 0) || strncasecmp("abc", "ABD", 3) >= 0 ||
+      strncasecmp("Png", "pNG", 100))
+    return -3;
+  return 0;
+}
+
+int test_multibyte() {
+  // The locale is UTF-8 and has no shift state.
+  if (mblen(NULL, 0) || mbtowc(NULL, NULL, 0) || wctomb(NULL, 0))
+    return -1;
+  wchar_t wc = 0;
+  if (mbtowc(&wc, "A", 1) != 1 || wc != 'A' || mbtowc(&wc, "", 1) != 0 ||
+      wc != 0 || mbtowc(&wc, "\xc3\xa9", 2) != 2 || wc != 0xe9 ||
+      mbtowc(&wc, "\xe2\x82\xac!", 4) != 3 || wc != 0x20ac ||
+      mblen("\xf0\x9f\x98\x80", 4) != 4)
+    return -2;
+  // Incomplete, truncated and invalid sequences.
+  errno = 0;
+  if (mbtowc(&wc, "\xe2\x82", 3) != -1 || errno != EILSEQ ||
+      mblen("\xe2\x82\xac", 2) != -1 || mblen("\x80", 1) != -1 ||
+      mblen("\xc0\x80", 2) != -1 || mblen("\xed\xa0\x80", 3) != -1)
+    return -3;
+
+  char buf[8] = {0};
+  if (wctomb(buf, 'z') != 1 || buf[0] != 'z' || wctomb(buf, 0x20ac) != 3 ||
+      memcmp(buf, "\xe2\x82\xac", 3) || wctomb(buf, 0x1f600) != 4 ||
+      memcmp(buf, "\xf0\x9f\x98\x80", 4))
+    return -4;
+  errno = 0;
+  if (wctomb(buf, 0xd800) != -1 || errno != EILSEQ ||
+      wctomb(buf, 0x110000) != -1)
+    return -5;
+
+  // mbstowcs() decodes the same way.
+  const char *str = "a\xc3\xa9\xe2\x82\xac";
+  wchar_t wcs[4] = {-1, -1, -1, -1};
+  if (mbstowcs(NULL, str, 0) != 3 || mbstowcs(wcs, str, 4) != 3 ||
+      wcs[0] != 'a' || wcs[1] != 0xe9 || wcs[2] != 0x20ac || wcs[3] != 0)
+    return -6;
+  wcs[1] = -1;
+  if (mbstowcs(wcs, str, 1) != 1 || wcs[0] != 'a' || wcs[1] != -1)
+    return -7;
+  if (mbstowcs(wcs, "a\xff", 4) != (size_t)-1)
+    return -8;
+  return 0;
+}
+
+int test_wcstombs() {
+  // ASCII, 2-byte, 3-byte and ASCII characters.
+  const wchar_t wcs[] = {'a', 0xe9, 0x20ac, 'b', 0};
+  const char *mbs = "a\xc3\xa9\xe2\x82\xac" "b";
+  char buf[10];
+  memset(buf, '#', sizeof(buf));
+  if (wcstombs(NULL, wcs, 0) != 7 || wcstombs(buf, wcs, 10) != 7 ||
+      strcmp(buf, mbs))
+    return -1;
+  // An exact fit isn't terminated, and a character that doesn't fit isn't
+  // written partially.
+  memset(buf, '#', sizeof(buf));
+  if (wcstombs(buf, wcs, 7) != 7 || memcmp(buf, mbs, 7) || buf[7] != '#')
+    return -2;
+  memset(buf, '#', sizeof(buf));
+  if (wcstombs(buf, wcs, 5) != 3 || memcmp(buf, mbs, 3) || buf[3] != '#')
+    return -3;
+  // A short buffer gets only what fits, with no terminator, and the result is
+  // the number of bytes stored, not the length of the whole conversion.
+  const wchar_t ten[] = {'0', '1', '2', '3', '4', '5', '6', '7', '8', '9', 0};
+  memset(buf, '#', sizeof(buf));
+  if (wcstombs(buf, ten, 4) != 4 || memcmp(buf, "0123#", 5))
+    return -4;
+  const wchar_t bad[] = {'a', 0xd800, 0};
+  if (wcstombs(buf, bad, 10) != (size_t)-1 ||
+      wcstombs(NULL, bad, 0) != (size_t)-1)
+    return -5;
+
+  // The same, the other way around.
+  wchar_t out[5] = {-1, -1, -1, -1, -1};
+  if (mbstowcs(out, mbs, 4) != 4 || out[0] != 'a' || out[1] != 0xe9 ||
+      out[2] != 0x20ac || out[3] != 'b' || out[4] != -1)
+    return -6;
+  if (mbstowcs(out, mbs, 5) != 4 || out[4] != 0)
+    return -7;
+  return 0;
+}
+
+int test_wide_stdio() {
+  // The fixture is UTF-16LE with a byte order mark.
+  FILE *f = fopen("TestScript.txt", "r");
+  if (!f)
+    return -1;
+  int res = 0;
+  if (fwide(f, 0) != 0)
+    res = -2;
+  const wchar_t line1[] = {'H', 'e', 'l', 'l', 'o', '\n', 0};
+  const wchar_t line2a[] = {'C', 'a', 0};
+  const wchar_t line2b[] = {'f', 0xe9, '\n', 0};
+  const wchar_t line3[] = {0x65e5, 0x672c, 0x8a9e, '\n', 0};
+  const wchar_t line4[] = {0x1f600, ' ', 'e', 'n', 'd', 0};
+  wchar_t buf[16];
+  if (!res && (!fgetws(buf, 16, f) || wcscmp(buf, line1)))
+    res = -3;
+  // A short buffer stops early, and the rest of the line comes next time.
+  if (!res && (!fgetws(buf, 3, f) || wcscmp(buf, line2a) ||
+               !fgetws(buf, 16, f) || wcscmp(buf, line2b)))
+    res = -4;
+  if (!res && (fgetwc(f) != 0x65e5 || !fgetws(buf + 1, 15, f)))
+    res = -5;
+  buf[0] = 0x65e5;
+  if (!res && wcscmp(buf, line3))
+    res = -6;
+  // The first operation decides the orientation, and byte functions fail on a
+  // wide-oriented stream.
+  if (!res && (fwide(f, -1) != 1 || fgetc(f) != EOF))
+    res = -7;
+  if (!res && (!fgetws(buf, 16, f) || wcscmp(buf, line4) || fgetws(buf, 16, f)))
+    res = -8;
+  fclose(f);
+  if (res)
+    return res;
+
+  // Writing without a byte order mark uses UTF-8, like the locale.
+  const char *path = "/tmp/wide_stdio_test.txt";
+  const wchar_t text[] = {'C', 'a', 'f', 0xe9, 0};
+  f = fopen(path, "w");
+  if (!f)
+    return -9;
+  if (fputws(text, f) < 0 || fputwc(0x20ac, f) != 0x20ac ||
+      fwrite("x", 1, 1, f) != 0 || fprintf(f, "%d", 1) != -1)
+    res = -10;
+  fclose(f);
+  f = fopen(path, "r");
+  if (!f)
+    return -11;
+  char bytes[16];
+  if (!res && (fread(bytes, 1, 16, f) != 8 ||
+               memcmp(bytes, "Caf\xc3\xa9\xe2\x82\xac", 8)))
+    res = -12;
+  fclose(f);
+  // Byte-oriented streams can't be used for wide characters.
+  f = fopen(path, "r");
+  if (!f)
+    return -13;
+  if (!res && (fwide(f, -1) != -1 || fgetwc(f) != WEOF || fwide(f, 1) != -1))
+    res = -14;
+  fclose(f);
+  remove(path);
+  return res;
+}
+
+int test_strnlen_memrchr() {
+  // A fixed-size field with no terminator must not be read past its end.
+  char field[4] = {'a', 'b', 'c', 'd'};
+  if (strnlen(field, 4) != 4 || strnlen(field, 2) != 2 ||
+      strnlen(field, 0) != 0 || strnlen("ab", 10) != 2 || strnlen("", 1) != 0)
+    return -1;
+  const char buf[] = "one\ntwo\nthree\xff";
+  if (memrchr(buf, '\n', 14) != buf + 7 || memrchr(buf, '\n', 7) != buf + 3 ||
+      memrchr(buf, '\n', 3) != NULL || memrchr(buf, 'o', 0) != NULL ||
+      memrchr(buf, 'o', 1) != buf || memrchr(buf, 0x1ff, 14) != buf + 13)
+    return -2;
+  return 0;
+}
+
+int test_stpcpy() {
+  // Chained copies should each start at the previous terminator.
+  char buf[16];
+  char *end = stpcpy(buf, "foo");
+  if (end != buf + 3 || *end != '\0')
+    return -1;
+  end = stpcpy(stpcpy(end, ""), "/bar");
+  if (end != buf + 7 || strcmp(buf, "foo/bar") != 0)
+    return -2;
+
+  // stpncpy() pads like strncpy() and returns the first padding byte.
+  memset(buf, 0x7F, sizeof(buf));
+  end = stpncpy(buf, "ab", 5);
+  if (end != buf + 2 || memcmp(buf, "ab\0\0\0\x7F", 6) != 0)
+    return -3;
+  // Without room for the terminator it returns the end of the buffer.
+  memset(buf, 0x7F, sizeof(buf));
+  end = stpncpy(buf, "abcdef", 4);
+  if (end != buf + 4 || memcmp(buf, "abcd\x7F", 5) != 0)
+    return -4;
+  end = stpncpy(buf, "abcd", 4);
+  if (end != buf + 4 || stpncpy(buf, "x", 0) != buf)
+    return -5;
+  return 0;
+}
+
+int test_setlocale() {
+  if (strcmp(setlocale(LC_ALL, "C"), "C"))
+    return -1;
+
+  // The user's locale is remembered, and queries return the same string.
+  char *user = setlocale(LC_NUMERIC, "");
+  if (!user || setlocale(LC_NUMERIC, NULL) != user ||
+      setlocale(LC_NUMERIC, NULL) != user)
+    return -2;
+  if (strcmp(setlocale(LC_CTYPE, NULL), "C"))
+    return -3;
+
+  // Names are returned exactly as they were set.
+  if (strcmp(setlocale(LC_NUMERIC, "en_US"), "en_US") ||
+      strcmp(setlocale(LC_NUMERIC, NULL), "en_US"))
+    return -4;
+
+  // LC_ALL names each category if they differ, and accepts that back.
+  char all[64];
+  strcpy(all, setlocale(LC_ALL, NULL));
+  if (strcmp(all, "C/C/C/en_US/C/C"))
+    return -5;
+  if (strcmp(setlocale(LC_ALL, "C"), "C") ||
+      strcmp(setlocale(LC_NUMERIC, NULL), "C"))
+    return -6;
+  if (strcmp(setlocale(LC_ALL, all), all) ||
+      strcmp(setlocale(LC_NUMERIC, NULL), "en_US"))
+    return -7;
+
+  // Locales that can't be honored fail without changing anything.
+  if (setlocale(LC_NUMERIC, "en_US.ISO8859-1") ||
+      setlocale(LC_NUMERIC, "english") || setlocale(LC_ALL, "C/C") ||
+      setlocale(99, "C") || strcmp(setlocale(LC_NUMERIC, NULL), "en_US"))
+    return -8;
+
+  if (strcmp(setlocale(LC_ALL, "fr_FR.UTF-8"), "fr_FR.UTF-8") ||
+      strcmp(setlocale(LC_CTYPE, NULL), "fr_FR.UTF-8"))
+    return -9;
+  setlocale(LC_ALL, "C");
+  return 0;
+}
+
+int test_err_warn() {
+  // The messages are checked by integration.rs.
+  const char *path = "/tmp/no_such_dir/warn_test";
+  errno = 0;
+  if (open(path, O_RDONLY) != -1 || errno != ENOENT)
+    return -1;
+  warn("open %s", path);
+  warnx("warnx %d", 42);
+
+  // LOG_PERROR copies the message to stderr, and %m is the errno message.
+  openlog("TestApp-syslog", LOG_PERROR, LOG_USER);
+  errno = EEXIST;
+  syslog(LOG_WARNING, "syslog %s: %m", "test");
+  closelog();
+  return 0;
+}
+
+int test_confstr_pathconf() {
+  const char *home = getenv("HOME");
+  if (pathconf(home, _PC_PATH_MAX) != 1024 ||
+      pathconf(home, _PC_NAME_MAX) != 255 ||
+      fpathconf(1, _PC_PIPE_BUF) != 512)
+    return -1;
+  errno = 0;
+  if (pathconf(home, 999) != -1 || errno != EINVAL)
+    return -2;
+  errno = 0;
+  if (pathconf("/tmp/no_such_file", _PC_NAME_MAX) != -1 || errno != ENOENT)
+    return -3;
+  errno = 0;
+  if (fpathconf(-1, _PC_NAME_MAX) != -1 || errno != EBADF)
+    return -4;
+
+  // The temporary directory is the same whichever way it's asked for.
+  char buf[256];
+  size_t size = confstr(_CS_DARWIN_USER_TEMP_DIR, NULL, 0);
+  if (size == 0 || size > sizeof(buf) ||
+      confstr(_CS_DARWIN_USER_TEMP_DIR, buf, sizeof(buf)) != size ||
+      strlen(buf) + 1 != size)
+    return -5;
+  const char *ns_tmp = (const char *)objc_msgSend(
+      NSTemporaryDirectory(), sel_registerName("UTF8String"));
+  if (strcmp(buf, getenv("TMPDIR")) || strcmp(buf, ns_tmp) ||
+      buf[size - 2] != '/')
+    return -6;
+
+  // A short buffer gets as much as fits, and the full size is returned.
+  memset(buf, 0x7F, sizeof(buf));
+  if (confstr(_CS_PATH, buf, 5) != 30 || memcmp(buf, "/usr\0\x7F", 6))
+    return -7;
+  errno = 0;
+  if (confstr(12345, buf, sizeof(buf)) != 0 || errno != EINVAL)
+    return -8;
+  return 0;
+}
+
+int test_memccpy() {
+  // Copying stops after the first sentinel, which is included.
+  const char src[] = "key=value;rest";
+  char dst[16];
+  memset(dst, 0x7F, sizeof(dst));
+  char *end = memccpy(dst, src, ';', sizeof(src));
+  if (end != dst + 10 || memcmp(dst, "key=value;\x7F", 11))
+    return -1;
+  // The sentinel can be any byte, not just ASCII.
+  const char high[] = {'a', '\xff', 'b'};
+  memset(dst, 0x7F, sizeof(dst));
+  if (memccpy(dst, high, 0x1ff, 3) != dst + 2 || memcmp(dst, "a\xff\x7F", 3))
+    return -2;
+
+  // Without the sentinel in the first n bytes, all n are copied.
+  memset(dst, 0x7F, sizeof(dst));
+  if (memccpy(dst, src, ';', 5) != NULL || memcmp(dst, "key=v\x7F", 6))
+    return -3;
+  if (memccpy(dst, src, 'k', 0) != NULL)
+    return -4;
+  return 0;
+}
+
+int test_url_cache_cookies() {
+  int res = 0;
+  id string_class = objc_msgSend(NSHomeDirectory(), sel_registerName("class"));
+  SEL with_utf8 = sel_registerName("stringWithUTF8String:");
+  SEL utf8 = sel_registerName("UTF8String");
+  SEL count = sel_registerName("count");
+  SEL url_with_string = sel_registerName("URLWithString:");
+  SEL request_with_url = sel_registerName("requestWithURL:");
+  SEL cookies_for_url = sel_registerName("cookiesForURL:");
+  SEL header_fields_with_cookies =
+      sel_registerName("requestHeaderFieldsWithCookies:");
+  SEL set_all_header_fields = sel_registerName("setAllHTTPHeaderFields:");
+  SEL value_for_header_field = sel_registerName("valueForHTTPHeaderField:");
+  id url_class =
+      NSClassFromString(objc_msgSend(string_class, with_utf8, "NSURL"));
+  id request_class = NSClassFromString(
+      objc_msgSend(string_class, with_utf8, "NSMutableURLRequest"));
+  id cookie_class =
+      NSClassFromString(objc_msgSend(string_class, with_utf8, "NSHTTPCookie"));
+  id cookie_name = objc_msgSend(string_class, with_utf8, "cookie");
+
+  // A response sets a cookie for the whole domain, and one for the host.
+  id url = objc_msgSend(
+      url_class, url_with_string,
+      objc_msgSend(string_class, with_utf8,
+                   "http://www.example.com/account/login"));
+  id headers = objc_msgSend(
+      NSClassFromString(objc_msgSend(string_class, with_utf8, "NSDictionary")),
+      sel_registerName("dictionaryWithObjectsAndKeys:"),
+      objc_msgSend(string_class, with_utf8,
+                   "sid=abc123; Path=/; Domain=example.com, "
+                   "theme=dark; Expires=Wed, 21 Oct 2099 07:28:00 GMT"),
+      objc_msgSend(string_class, with_utf8, "Set-Cookie"), NULL);
+  id response = objc_msgSend(
+      NSClassFromString(
+          objc_msgSend(string_class, with_utf8, "NSHTTPURLResponse")),
+      sel_registerName("alloc"));
+  response = objc_msgSend(
+      response,
+      sel_registerName("initWithURL:statusCode:HTTPVersion:headerFields:"),
+      url, 200, objc_msgSend(string_class, with_utf8, "HTTP/1.1"), headers);
+  id cookies = objc_msgSend(
+      cookie_class,
+      sel_registerName("cookiesWithResponseHeaderFields:forURL:"),
+      objc_msgSend(response, sel_registerName("allHeaderFields")), url);
+  if ((int)objc_msgSend(cookies, count) != 2)
+    res = -1;
+
+  id storage = objc_msgSend(
+      NSClassFromString(
+          objc_msgSend(string_class, with_utf8, "NSHTTPCookieStorage")),
+      sel_registerName("sharedHTTPCookieStorage"));
+  objc_msgSend(storage, sel_registerName("setCookies:forURL:mainDocumentURL:"),
+               cookies, url, NULL);
+
+  // Only the domain cookie is sent to another host, and it ends up in the
+  // next request's Cookie header.
+  id next_url = objc_msgSend(
+      url_class, url_with_string,
+      objc_msgSend(string_class, with_utf8,
+                   "http://images.example.com/logo.png"));
+  id next_cookies = objc_msgSend(storage, cookies_for_url, next_url);
+  id request = objc_msgSend(request_class, request_with_url, next_url);
+  objc_msgSend(request, set_all_header_fields,
+               objc_msgSend(cookie_class, header_fields_with_cookies,
+                            next_cookies));
+  id header = objc_msgSend(request, value_for_header_field, cookie_name);
+  if (!res && (!header || strcmp((const char *)objc_msgSend(header, utf8),
+                                 "sid=abc123")))
+    res = -2;
+
+  // Back on the original host, both are sent, longest path first.
+  id account_url = objc_msgSend(
+      url_class, url_with_string,
+      objc_msgSend(string_class, with_utf8,
+                   "http://www.example.com/account/settings"));
+  id account_cookies = objc_msgSend(storage, cookies_for_url, account_url);
+  id account_request =
+      objc_msgSend(request_class, request_with_url, account_url);
+  objc_msgSend(account_request, set_all_header_fields,
+               objc_msgSend(cookie_class, header_fields_with_cookies,
+                            account_cookies));
+  header = objc_msgSend(account_request, value_for_header_field, cookie_name);
+  if (!res && (!header || strcmp((const char *)objc_msgSend(header, utf8),
+                                 "theme=dark; sid=abc123")))
+    res = -3;
+
+  // Don't leave the cookies saved for the next run.
+  for (int i = 0; i < (int)objc_msgSend(cookies, count); i++)
+    objc_msgSend(storage, sel_registerName("deleteCookie:"),
+                 objc_msgSend(cookies, sel_registerName("objectAtIndex:"), i));
+  if (!res &&
+      (int)objc_msgSend(objc_msgSend(storage, cookies_for_url, account_url),
+                        count) != 0)
+    res = -4;
+
+  // The URL cache evicts the least recently used response when it's over
+  // capacity, and NSURLConnection answers requests from it.
+  id cache_class =
+      NSClassFromString(objc_msgSend(string_class, with_utf8, "NSURLCache"));
+  id cache = objc_msgSend(
+      objc_msgSend(cache_class, sel_registerName("alloc")),
+      sel_registerName("initWithMemoryCapacity:diskCapacity:diskPath:"), 10, 0,
+      NULL);
+  objc_msgSend(cache_class, sel_registerName("setSharedURLCache:"), cache);
+  objc_msgSend(cache, sel_registerName("release"));
+  cache = objc_msgSend(cache_class, sel_registerName("sharedURLCache"));
+
+  id data_class =
+      NSClassFromString(objc_msgSend(string_class, with_utf8, "NSData"));
+  id cached_response_class = NSClassFromString(
+      objc_msgSend(string_class, with_utf8, "NSCachedURLResponse"));
+  SEL data_with_bytes = sel_registerName("dataWithBytes:length:");
+  SEL init_with_response = sel_registerName("initWithResponse:data:");
+  SEL store = sel_registerName("storeCachedResponse:forRequest:");
+  SEL cached_response_for_request =
+      sel_registerName("cachedResponseForRequest:");
+  SEL send =
+      sel_registerName("sendSynchronousRequest:returningResponse:error:");
+  id connection_class = NSClassFromString(
+      objc_msgSend(string_class, with_utf8, "NSURLConnection"));
+
+  id cached = objc_msgSend(
+      objc_msgSend(cached_response_class, sel_registerName("alloc")),
+      init_with_response, response,
+      objc_msgSend(data_class, data_with_bytes, "hello", 5));
+  objc_msgSend(cache, store, cached, request);
+  objc_msgSend(cached, sel_registerName("release"));
+  id returned_response = NULL;
+  id error = NULL;
+  id data = objc_msgSend(connection_class, send, request, &returned_response,
+                         &error);
+  if (!res &&
+      (!data || (int)objc_msgSend(data, sel_registerName("length")) != 5 ||
+       returned_response != response || error))
+    res = -5;
+
+  // The cached response's cookies were stored again.
+  cookies = objc_msgSend(storage, cookies_for_url, account_url);
+  if (!res && (int)objc_msgSend(cookies, count) != 2)
+    res = -8;
+  for (int i = 0; i < (int)objc_msgSend(cookies, count); i++)
+    objc_msgSend(storage, sel_registerName("deleteCookie:"),
+                 objc_msgSend(cookies, sel_registerName("objectAtIndex:"), i));
+
+  cached = objc_msgSend(
+      objc_msgSend(cached_response_class, sel_registerName("alloc")),
+      init_with_response, response,
+      objc_msgSend(data_class, data_with_bytes, "world!", 6));
+  objc_msgSend(cache, store, cached, account_request);
+  objc_msgSend(cached, sel_registerName("release"));
+  if (!res &&
+      (objc_msgSend(cache, cached_response_for_request, request) ||
+       !objc_msgSend(cache, cached_response_for_request, account_request) ||
+       (int)objc_msgSend(cache, sel_registerName("currentMemoryUsage")) != 6))
+    res = -6;
+
+  // Without a cached response, the request fails like it would offline.
+  data = objc_msgSend(connection_class, send, request, &returned_response,
+                      &error);
+  if (!res && (data || returned_response || !error ||
+               (int)objc_msgSend(error, sel_registerName("code")) != -1009))
+    res = -7;
+
+  objc_msgSend(response, sel_registerName("release"));
+  return res;
+}
+
+int test_mkstemp() {
+  // The template must end with at least six X characters.
+  char short_template[] = "/tmp/levelXXXXX";
+  errno = 0;
+  if (mkstemp(short_template) != -1 || errno != EINVAL)
+    return -1;
+
+  // /tmp is redirected into the app's sandboxed tmp directory.
+  const char *home = getenv("HOME");
+  char names[3][32];
+  int fds[3];
+  int count = 0;
+  int res = 0;
+  struct stat st;
+  char buf[8];
+  for (; count < 3; count++) {
+    strcpy(names[count], "/tmp/levelXXXXXX");
+    fds[count] = mkstemp(names[count]);
+    if (fds[count] == -1) {
+      res = -2;
+      break;
+    }
+    if (memcmp(names[count], "/tmp/level", 10) ||
+        !strcmp(names[count], "/tmp/levelXXXXXX"))
+      res = -3;
+    for (int i = 0; i < count; i++) {
+      if (!strcmp(names[i], names[count]))
+        res = -4;
+    }
+    char *sandboxed = str_format("%s/tmp/%s", home, names[count] + 5);
+    if (!res && stat(sandboxed, &st))
+      res = -5;
+    free(sandboxed);
+    if (!res && (write(fds[count], "level", 5) != 5 ||
+                 lseek(fds[count], 0, SEEK_SET) != 0 ||
+                 read(fds[count], buf, 5) != 5 || memcmp(buf, "level", 5)))
+      res = -6;
+  }
+
+  // mktemp() only picks a name that doesn't exist.
+  char template[] = "/tmp/levelXXXXXX";
+  if (!res && (mktemp(template) != template ||
+               !strcmp(template, "/tmp/levelXXXXXX") ||
+               access(template, F_OK) != -1))
+    res = -7;
+
+  for (int i = 0; i < count; i++) {
+    close(fds[i]);
+    if (unlink(names[i]))
+      res = -8;
+  }
+  return res;
+}
+
+volatile int sleep_counter = 0;
+volatile int sleep_counter_stop = 0;
+
+void *sleep_counter_thread(void *arg) {
+  while (!sleep_counter_stop) {
+    sleep_counter++;
+  }
+  return NULL;
+}
+
+int test_sleep() {
+  pthread_t thread;
+  pthread_create(&thread, NULL, sleep_counter_thread, NULL);
+
+  // The other thread must keep running while this one sleeps.
+  int i;
+  for (i = 0; i < 3; i++) {
+    int before = sleep_counter;
+    if (usleep(50000) != 0)
+      return -1;
+    if (sleep_counter == before)
+      return -2;
+  }
+
+  struct timespec req = {0, 50000000};
+  struct timespec rem = {1, 1};
+  int before = sleep_counter;
+  if (nanosleep(&req, &rem) != 0)
+    return -3;
+  if (sleep_counter == before || rem.tv_sec != 0 || rem.tv_nsec != 0)
+    return -4;
+
+  // Zero-length sleeps should just yield.
+  if (usleep(0) != 0 || sleep(0) != 0)
+    return -5;
+
+  struct timespec bad = {0, 1000000000};
+  if (nanosleep(&bad, NULL) != -1)
+    return -6;
+
+  sleep_counter_stop = 1;
+  return 0;
+}
+
+int signal_received = 0;
+
+void sigusr1_handler(int sig) { signal_received = sig; }
+
+void sigusr1_siginfo_handler(int sig, siginfo_t *info, void *context) {
+  signal_received = info->si_signo == sig ? sig : -1;
+}
+
+int test_signal() {
+  // Ignored signals should do nothing.
+  signal(SIGPIPE, SIG_IGN);
+  if (raise(SIGPIPE) != 0)
+    return -1;
+
+  struct sigaction act;
+  act.sa_handler = sigusr1_handler;
+  sigemptyset(&act.sa_mask);
+  act.sa_flags = 0;
+  if (sigaction(SIGUSR1, &act, NULL) != 0)
+    return -2;
+  if (raise(SIGUSR1) != 0 || signal_received != SIGUSR1)
+    return -3;
+
+  struct sigaction old;
+  signal_received = 0;
+  act.sa_sigaction = sigusr1_siginfo_handler;
+  act.sa_flags = SA_SIGINFO;
+  if (sigaction(SIGUSR1, &act, &old) != 0 ||
+      old.sa_handler != sigusr1_handler)
+    return -4;
+  if (raise(SIGUSR1) != 0 || signal_received != SIGUSR1)
+    return -5;
+
+  // Restore SIGUSR1 to the previous handler.
+  if (signal(SIGUSR1, sigusr1_handler) !=
+      (void (*)(int))sigusr1_siginfo_handler)
+    return -6;
+  return 0;
+}
+
+int resident_size(unsigned int *size) {
+  struct task_basic_info info;
+  mach_msg_type_number_t count = TASK_BASIC_INFO_COUNT;
+  if (task_info(mach_task_self(), TASK_BASIC_INFO, (int *)&info, &count) !=
+      KERN_SUCCESS)
+    return -1;
+  if (count != TASK_BASIC_INFO_COUNT)
+    return -1;
+  *size = info.resident_size;
+  return 0;
+}
+
+int test_memory_usage() {
+  unsigned int before, after;
+  if (resident_size(&before) != 0)
+    return -1;
+  void *big = malloc(10 * 1024 * 1024);
+  if (resident_size(&after) != 0)
+    return -2;
+  if (after < before + 10 * 1024 * 1024)
+    return -3;
+
+  struct rusage usage;
+  if (getrusage(RUSAGE_SELF, &usage) != 0)
+    return -4;
+  if (usage.ru_maxrss < (long)after)
+    return -5;
+
+  free(big);
+  if (resident_size(&after) != 0)
+    return -6;
+  return after < before + 10 * 1024 * 1024 ? 0 : -7;
+}
+
+int test_ctype() {
+  int c;
+  for (c = EOF; c < 256; c++) {
+    int upper = c >= 'A' && c <= 'Z';
+    int lower = c >= 'a' && c <= 'z';
+    int digit = c >= '0' && c <= '9';
+    int xdigit = digit || (c >= 'a' && c <= 'f') || (c >= 'A' && c <= 'F');
+    int space = c == ' ' || (c >= '\t' && c <= '\r');
+    int print = c >= ' ' && c <= '~';
+    int punct = print && c != ' ' && !upper && !lower && !digit;
+    if (!!isupper(c) != upper || !!islower(c) != lower)
+      return -1;
+    if (!!isalpha(c) != (upper || lower) ||
+        !!isalnum(c) != (upper || lower || digit))
+      return -2;
+    if (!!isdigit(c) != digit || !!isxdigit(c) != xdigit)
+      return -3;
+    if (!!isspace(c) != space || !!isprint(c) != print ||
+        !!ispunct(c) != punct)
+      return -4;
+    if (tolower(c) != (upper ? c + 32 : c) ||
+        toupper(c) != (lower ? c - 32 : c))
+      return -5;
+  }
+  return 0;
+}
+
+struct loopback_state {
+  int connect_result;
+  char received[16];
+  CFIndex received_len;
+  int closed;
+};
+
+void loopback_callback(CFSocketRef s, CFOptionFlags type, CFDataRef address,
+                       const void *data, void *info) {
+  struct loopback_state *state = info;
+  if (type == kCFSocketConnectCallBack) {
+    // NULL data means the connection succeeded.
+    state->connect_result = data == NULL ? 1 : -1;
+  } else if (type == kCFSocketDataCallBack) {
+    CFIndex len = CFDataGetLength(data);
+    if (len == 0) {
+      state->closed = 1;
+    } else if (state->received_len + len <= sizeof(state->received)) {
+      memcpy(state->received + state->received_len, CFDataGetBytePtr(data),
+             len);
+      state->received_len += len;
+    }
+  }
+}
+
+// Create a socket listening on a free port of 127.0.0.1, and get its address.
+// Returns -1 on failure.
+int open_loopback_listener(struct sockaddr_in *addr) {
+  memset(addr, 0, sizeof(*addr));
+  addr->sin_len = sizeof(*addr);
+  addr->sin_family = AF_INET;
+  addr->sin_addr = 0x0100007f; // 127.0.0.1 in network byte order
+  socklen_t addr_len = sizeof(*addr);
+  int listener = socket(AF_INET, SOCK_STREAM, 0);
+  if (listener < 0 || bind(listener, addr, addr_len) != 0 ||
+      listen(listener, 1) != 0 ||
+      getsockname(listener, addr, &addr_len) != 0 || addr->sin_port == 0)
+    return -1;
+  return listener;
+}
+
+int test_cfsocket_loopback() {
+  // Server side, using BSD sockets directly.
+  struct sockaddr_in addr;
+  int listener = open_loopback_listener(&addr);
+  if (listener < 0)
+    return -1;
+
+  // Client side, a CFSocket connecting in the background.
+  struct loopback_state state;
+  memset(&state, 0, sizeof(state));
+  CFSocketContext context = {0, &state, NULL, NULL, NULL};
+  CFSocketRef sock =
+      CFSocketCreate(NULL, 0, 0, 0,
+                     kCFSocketDataCallBack | kCFSocketConnectCallBack,
+                     &loopback_callback, &context);
+  CFDataRef address =
+      CFDataCreate(NULL, (const unsigned char *)&addr, sizeof(addr));
+  CFIndex connect_res = CFSocketConnectToAddress(sock, address, -1);
+  CFRelease(address);
+  int server = accept(listener, NULL, NULL);
+  if (sock == NULL || connect_res != 0 || server < 0)
+    return -2;
+
+  CFRunLoopSourceRef source = CFSocketCreateRunLoopSource(NULL, sock, 0);
+  CFRunLoopAddSource(CFRunLoopGetCurrent(), source, kCFRunLoopDefaultMode);
+
+  // The connect callback is only called from the run loop.
+  if (CFRunLoopRunInMode(kCFRunLoopDefaultMode, 1.0, 1) !=
+          kCFRunLoopRunHandledSource ||
+      state.connect_result != 1)
+    return -3;
+
+  CFDataRef ping = CFDataCreate(NULL, (const unsigned char *)"ping", 4);
+  CFIndex send_res = CFSocketSendData(sock, NULL, ping, 1.0);
+  CFRelease(ping);
+  char buf[4];
+  if (send_res != 0 || recv(server, buf, 4, 0) != 4 ||
+      memcmp(buf, "ping", 4) != 0)
+    return -4;
+
+  if (send(server, "pong", 4, 0) != 4)
+    return -5;
+  int i;
+  for (i = 0; i < 20 && state.received_len < 4; i++)
+    CFRunLoopRunInMode(kCFRunLoopDefaultMode, 0.1, 1);
+  if (state.received_len != 4 || memcmp(state.received, "pong", 4) != 0)
+    return -6;
+
+  // Closing the connection is reported with empty data.
+  close(server);
+  for (i = 0; i < 20 && !state.closed; i++)
+    CFRunLoopRunInMode(kCFRunLoopDefaultMode, 0.1, 1);
+  if (!state.closed)
+    return -7;
+
+  CFSocketInvalidate(sock);
+  CFRelease(source);
+  CFRelease(sock);
+  close(listener);
+  return 0;
+}
+
+int test_cfstream_loopback() {
+  struct sockaddr_in addr;
+  int listener = open_loopback_listener(&addr);
+  if (listener < 0)
+    return -1;
+  unsigned int port = (addr.sin_port >> 8) | ((addr.sin_port & 0xff) << 8);
+
+  // Ports that don't fit in 16 bits are rejected.
+  CFStringRef host =
+      CFStringCreateWithCString(NULL, "127.0.0.1", kCFStringEncodingASCII);
+  CFReadStreamRef read_stream = (CFReadStreamRef)1;
+  CFWriteStreamRef write_stream = (CFWriteStreamRef)1;
+  CFStreamCreatePairWithSocketToHost(NULL, host, 0x10000 | port, &read_stream,
+                                     &write_stream);
+  if (read_stream != NULL || write_stream != NULL)
+    return -2;
+
+  CFStreamCreatePairWithSocketToHost(NULL, host, port, &read_stream,
+                                     &write_stream);
+  CFRelease(host);
+  if (read_stream == NULL || write_stream == NULL ||
+      !CFReadStreamOpen(read_stream) || !CFWriteStreamOpen(write_stream))
+    return -3;
+  int server = accept(listener, NULL, NULL);
+  if (server < 0)
+    return -4;
+
+  char buf[4];
+  if (CFWriteStreamWrite(write_stream, (const unsigned char *)"ping", 4) != 4 ||
+      recv(server, buf, 4, 0) != 4 || memcmp(buf, "ping", 4) != 0)
+    return -5;
+
+  // Reading waits for the data to arrive.
+  if (send(server, "pong", 4, 0) != 4 ||
+      CFReadStreamRead(read_stream, (unsigned char *)buf, 4) != 4 ||
+      memcmp(buf, "pong", 4) != 0)
+    return -6;
+
+  close(server);
+  if (CFReadStreamRead(read_stream, (unsigned char *)buf, 4) != 0)
+    return -7;
+
+  CFReadStreamClose(read_stream);
+  CFWriteStreamClose(write_stream);
+  CFRelease(read_stream);
+  CFRelease(write_stream);
+  close(listener);
+  return 0;
+}
+
+int test_select_poll() {
+  struct sockaddr_in addr;
+  int listener = open_loopback_listener(&addr);
+  if (listener < 0)
+    return -1;
+
+  // Nothing to accept yet.
+  fd_set fds;
+  FD_ZERO(&fds);
+  FD_SET(listener, &fds);
+  struct timeval no_wait = {0, 0};
+  if (select(listener + 1, &fds, NULL, NULL, &no_wait) != 0 ||
+      FD_ISSET(listener, &fds))
+    return -2;
+
+  int client = socket(AF_INET, SOCK_STREAM, 0);
+  if (client < 0 || connect(client, &addr, sizeof(addr)) != 0)
+    return -3;
+  FD_ZERO(&fds);
+  FD_SET(listener, &fds);
+  struct timeval one_second = {1, 0};
+  if (select(listener + 1, &fds, NULL, NULL, &one_second) != 1 ||
+      !FD_ISSET(listener, &fds))
+    return -4;
+  int server = accept(listener, NULL, NULL);
+  if (server < 0)
+    return -5;
+
+  // A new connection can be written to, but has nothing to read.
+  struct pollfd pfd = {server, POLLIN | POLLOUT, 0};
+  if (poll(&pfd, 1, 0) != 1 || pfd.revents != POLLOUT)
+    return -6;
+  pfd.events = POLLIN;
+  if (send(client, "x", 1, 0) != 1 || poll(&pfd, 1, 1000) != 1 ||
+      pfd.revents != POLLIN)
+    return -7;
+
+  // The end of the connection is readable, and is also a hangup.
+  char buf[1];
+  close(client);
+  if (recv(server, buf, 1, 0) != 1 || poll(&pfd, 1, 1000) != 1 ||
+      pfd.revents != (POLLIN | POLLHUP) || recv(server, buf, 1, 0) != 0)
+    return -8;
+
+  // Closed descriptors are reported, negative ones ignored.
+  close(server);
+  struct pollfd pfds[2] = {{server, POLLIN, 0}, {-1, POLLIN, 0}};
+  if (poll(pfds, 2, 0) != 1 || pfds[0].revents != POLLNVAL ||
+      pfds[1].revents != 0)
+    return -9;
+
+  close(listener);
+  return 0;
+}
+
+void count_sound_completion(SystemSoundID sound, void *count) {
+  (void)sound;
+  (*(int *)count)++;
+}
+
+int test_system_sounds() {
+  id string_class = objc_msgSend(NSHomeDirectory(), sel_registerName("class"));
+  SEL with_utf8 = sel_registerName("stringWithUTF8String:");
+  id bundle = objc_msgSend(
+      NSClassFromString(objc_msgSend(string_class, with_utf8, "NSBundle")),
+      sel_registerName("mainBundle"));
+  id url =
+      objc_msgSend(bundle, sel_registerName("URLForResource:withExtension:"),
+                   objc_msgSend(string_class, with_utf8, "TestSound"),
+                   objc_msgSend(string_class, with_utf8, "wav"));
+  SystemSoundID sound = 0;
+  if (!url || AudioServicesCreateSystemSoundID(url, &sound) != 0 || !sound)
+    return -1;
+
+  // The completion is called from the run loop once the sound (50ms) ends.
+  int count = 0;
+  if (AudioServicesAddSystemSoundCompletion(sound, NULL, NULL,
+                                            count_sound_completion, &count))
+    return -2;
+  AudioServicesPlaySystemSound(sound);
+  if (count != 0)
+    return -3;
+  int i;
+  for (i = 0; i < 20 && !count; i++)
+    CFRunLoopRunInMode(kCFRunLoopDefaultMode, 0.1, 1);
+  CFRunLoopRunInMode(kCFRunLoopDefaultMode, 0.1, 1);
+  if (count != 1)
+    return -4;
+
+  // Vibration does nothing, but still completes on the next run loop pass.
+  int vibrate_count = 0;
+  AudioServicesAddSystemSoundCompletion(kSystemSoundID_Vibrate, NULL, NULL,
+                                        count_sound_completion, &vibrate_count);
+  AudioServicesPlaySystemSound(kSystemSoundID_Vibrate);
+  if (CFRunLoopRunInMode(kCFRunLoopDefaultMode, 1.0, 1) !=
+          kCFRunLoopRunHandledSource ||
+      vibrate_count != 1)
+    return -5;
+  AudioServicesRemoveSystemSoundCompletion(kSystemSoundID_Vibrate);
+
+  // Disposing of a playing sound stops it, and its completion is never called.
+  count = 0;
+  AudioServicesPlaySystemSound(sound);
+  if (AudioServicesDisposeSystemSoundID(sound) != 0)
+    return -6;
+  CFRunLoopRunInMode(kCFRunLoopDefaultMode, 0.2, 0);
+  if (count != 0 || AudioServicesDisposeSystemSoundID(sound) == 0)
+    return -7;
+  return 0;
+}
+
+// Check that CFStringGetCString() succeeds for exactly the buffer sizes that
+// can hold expected_len bytes plus the null terminator, and that it never
+// writes past the size it was given. A negative expected_len means the string
+// can't be represented at all.
+int check_cfstring_get_cstring(CFStringRef str, CFStringEncoding encoding,
+                               const char *expected, int expected_len) {
+  char buf[16];
+  for (int size = 0; size < (int)sizeof(buf); size++) {
+    memset(buf, 0x7F, sizeof(buf));
+    BOOL res = CFStringGetCString(str, size ? buf : NULL, size, encoding);
+    BOOL should_fit = expected_len >= 0 && size > expected_len;
+    if (res != should_fit)
+      return -1;
+    for (int i = size; i < (int)sizeof(buf); i++) {
+      if (buf[i] != 0x7F)
+        return -2;
+    }
+    if (res && memcmp(buf, expected, expected_len + 1) != 0)
+      return -3;
+  }
+  return 0;
+}
+
+int test_cfstring_get_cstring() {
+  // "café" in UTF-8: five bytes for four characters
+  const char *cafe = "caf\xC3\xA9";
+  CFStringRef ascii =
+      CFStringCreateWithCString(NULL, "abc", kCFStringEncodingUTF8);
+  CFStringRef non_ascii =
+      CFStringCreateWithCString(NULL, cafe, kCFStringEncodingUTF8);
+  int res = 0;
+
+  if (check_cfstring_get_cstring(ascii, kCFStringEncodingUTF8, "abc", 3))
+    res = -1;
+  else if (check_cfstring_get_cstring(ascii, kCFStringEncodingASCII, "abc",
+                                      3))
+    res = -2;
+  else if (check_cfstring_get_cstring(non_ascii, kCFStringEncodingUTF8, cafe,
+                                      5))
+    res = -3;
+  else if (check_cfstring_get_cstring(non_ascii, kCFStringEncodingASCII,
+                                      NULL, -1))
+    res = -4;
+  else if (CFStringGetCString(ascii, NULL, -1, kCFStringEncodingUTF8))
+    res = -5;
+
+  if (res == 0) {
+    CFIndex max_size =
+        CFStringGetMaximumSizeOfFileSystemRepresentation(non_ascii);
+    char buf[32];
+    if (max_size < 6 || max_size > (CFIndex)sizeof(buf))
+      res = -6;
+    else if (!CFStringGetFileSystemRepresentation(non_ascii, buf, max_size) ||
+             strcmp(buf, cafe) != 0)
+      res = -7;
+    else if (CFStringGetFileSystemRepresentation(non_ascii, buf, 5))
+      res = -8;
+  }
+
+  CFRelease(ascii);
+  CFRelease(non_ascii);
+  return res;
+}
+
+// What [NSArchiver archiveRootObject:toFile:] writes for the array
+// (@"hello", <the same string>, @42, <NSData "abc">, {k = v}, @2.5).
+static const unsigned char typedstream_fixture[] = {
+    0x04, 0x0b, 0x73, 0x74, 0x72, 0x65, 0x61, 0x6d, 0x74, 0x79, 0x70, 0x65,
+    0x64, 0x81, 0xe8, 0x03, 0x84, 0x01, 0x40, 0x84, 0x84, 0x84, 0x07, 0x4e,
+    0x53, 0x41, 0x72, 0x72, 0x61, 0x79, 0x00, 0x84, 0x84, 0x08, 0x4e, 0x53,
+    0x4f, 0x62, 0x6a, 0x65, 0x63, 0x74, 0x00, 0x85, 0x84, 0x01, 0x69, 0x06,
+    0x92, 0x84, 0x84, 0x84, 0x08, 0x4e, 0x53, 0x53, 0x74, 0x72, 0x69, 0x6e,
+    0x67, 0x01, 0x94, 0x84, 0x01, 0x2b, 0x05, 0x68, 0x65, 0x6c, 0x6c, 0x6f,
+    0x86, 0x92, 0x95, 0x92, 0x84, 0x84, 0x84, 0x08, 0x4e, 0x53, 0x4e, 0x75,
+    0x6d, 0x62, 0x65, 0x72, 0x00, 0x84, 0x84, 0x07, 0x4e, 0x53, 0x56, 0x61,
+    0x6c, 0x75, 0x65, 0x00, 0x94, 0x84, 0x01, 0x2a, 0x84, 0x95, 0x95, 0x2a,
+    0x86, 0x92, 0x84, 0x84, 0x84, 0x06, 0x4e, 0x53, 0x44, 0x61, 0x74, 0x61,
+    0x00, 0x94, 0x95, 0x03, 0x84, 0x04, 0x5b, 0x33, 0x63, 0x5d, 0x61, 0x62,
+    0x63, 0x86, 0x92, 0x84, 0x84, 0x84, 0x0c, 0x4e, 0x53, 0x44, 0x69, 0x63,
+    0x74, 0x69, 0x6f, 0x6e, 0x61, 0x72, 0x79, 0x00, 0x94, 0x95, 0x01, 0x92,
+    0x84, 0x96, 0x97, 0x01, 0x6b, 0x86, 0x92, 0x84, 0x96, 0x97, 0x01, 0x76,
+    0x86, 0x86, 0x92, 0x84, 0x98, 0x9a, 0x84, 0x84, 0x01, 0x64, 0x9e, 0x83,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x40, 0x86, 0x86,
+};
+
+int test_ns_unarchiver() {
+  id string_class = objc_msgSend(NSHomeDirectory(), sel_registerName("class"));
+  SEL with_utf8 = sel_registerName("stringWithUTF8String:");
+  id unarchiver_class =
+      NSClassFromString(objc_msgSend(string_class, with_utf8, "NSUnarchiver"));
+  CFDataRef data =
+      CFDataCreate(NULL, typedstream_fixture, sizeof(typedstream_fixture));
+  id unarchiver = objc_msgSend(unarchiver_class, sel_registerName("alloc"));
+  unarchiver = objc_msgSend(
+      unarchiver, sel_registerName("initForReadingWithData:"), data);
+  CFRelease(data);
+  if (!unarchiver)
+    return -1;
+
+  int res = 0;
+  id array = objc_msgSend(unarchiver, sel_registerName("decodeObject"));
+  SEL at_index = sel_registerName("objectAtIndex:");
+  SEL utf8_string = sel_registerName("UTF8String");
+  if (!array || (NSUInteger)objc_msgSend(array, sel_registerName("count")) != 6)
+    res = -2;
+  else if (!objc_msgSend(unarchiver, sel_registerName("isAtEnd")))
+    res = -3;
+  else if ((long)objc_msgSend(
+               unarchiver, sel_registerName("versionForClassName:"),
+               objc_msgSend(string_class, with_utf8, "NSString")) != 1)
+    res = -4;
+
+  if (res) {
+    objc_msgSend(unarchiver, sel_registerName("release"));
+    return res;
+  }
+
+  id hello = objc_msgSend(array, at_index, 0);
+  id number = objc_msgSend(array, at_index, 2);
+  id abc = objc_msgSend(array, at_index, 3);
+  id dict = objc_msgSend(array, at_index, 4);
+  id real = objc_msgSend(array, at_index, 5);
+  id v = objc_msgSend(dict, sel_registerName("objectForKey:"),
+                      objc_msgSend(string_class, with_utf8, "k"));
+  if (strcmp((const char *)objc_msgSend(hello, utf8_string), "hello"))
+    res = -5;
+  else if (objc_msgSend(array, at_index, 1) != hello)
+    res = -6;
+  else if ((int)objc_msgSend(number, sel_registerName("intValue")) != 42)
+    res = -7;
+  else if ((NSUInteger)objc_msgSend(abc, sel_registerName("length")) != 3 ||
+           memcmp(objc_msgSend(abc, sel_registerName("bytes")), "abc", 3))
+    res = -8;
+  else if (!v || strcmp((const char *)objc_msgSend(v, utf8_string), "v"))
+    res = -9;
+  else if (((float (*)(id, SEL))objc_msgSend)(
+               real, sel_registerName("floatValue")) != 2.5f)
+    res = -10;
+
+  objc_msgSend(unarchiver, sel_registerName("release"));
+  return res;
+}
+
+int test_objc_exceptions() {
+  id string_class = objc_msgSend(NSHomeDirectory(), sel_registerName("class"));
+  SEL with_utf8 = sel_registerName("stringWithUTF8String:");
+  SEL utf8_string = sel_registerName("UTF8String");
+  id handler = objc_msgSend(
+      NSClassFromString(
+          objc_msgSend(string_class, with_utf8, "NSAssertionHandler")),
+      sel_registerName("currentHandler"));
+  id exception_class =
+      NSClassFromString(objc_msgSend(string_class, with_utf8, "NSException"));
+
+  // @try { NSAssert(2 == 3, @"%d is not %d", 2, 3); }
+  // @catch (NSException *e) { ... }
+  objc_exception_data outer;
+  objc_exception_try_enter(&outer);
+  if (!_setjmp(outer.buf)) {
+    objc_msgSend(
+        handler,
+        sel_registerName(
+            "handleFailureInMethod:object:file:lineNumber:description:"),
+        sel_registerName("testObjcExceptions"), handler,
+        objc_msgSend(string_class, with_utf8, "main.c"), 42,
+        objc_msgSend(string_class, with_utf8, "%d is not %d"), 2, 3);
+    objc_exception_try_exit(&outer);
+    return -1;
+  }
+  id exception = objc_exception_extract(&outer);
+  if (!exception || !objc_exception_match(exception_class, exception))
+    return -2;
+  if (strcmp((const char *)objc_msgSend(
+                 objc_msgSend(exception, sel_registerName("name")),
+                 utf8_string),
+             "NSInternalInconsistencyException") ||
+      strcmp((const char *)objc_msgSend(
+                 objc_msgSend(exception, sel_registerName("reason")),
+                 utf8_string),
+             "2 is not 3"))
+    return -3;
+
+  // The innermost @try catches the exception, and rethrowing it from the
+  // @catch reaches the outer one.
+  volatile int inner_caught = 0;
+  objc_exception_try_enter(&outer);
+  if (!_setjmp(outer.buf)) {
+    objc_exception_data inner;
+    objc_exception_try_enter(&inner);
+    if (!_setjmp(inner.buf)) {
+      objc_msgSend(exception, sel_registerName("raise"));
+      objc_exception_try_exit(&inner);
+      objc_exception_try_exit(&outer);
+      return -4;
+    }
+    inner_caught = 1;
+    objc_exception_throw(objc_exception_extract(&inner));
+    objc_exception_try_exit(&outer);
+    return -5;
+  }
+  if (!inner_caught || objc_exception_extract(&outer) != exception)
+    return -6;
+
+  return 0;
+}
+
+int test_ns_null() {
+  id string_class = objc_msgSend(NSHomeDirectory(), sel_registerName("class"));
+  SEL with_utf8 = sel_registerName("stringWithUTF8String:");
+  id null_class =
+      NSClassFromString(objc_msgSend(string_class, with_utf8, "NSNull"));
+  id null = objc_msgSend(null_class, sel_registerName("null"));
+  id allocated = objc_msgSend(null_class, sel_registerName("alloc"));
+  allocated = objc_msgSend(allocated, sel_registerName("init"));
+  if (!null || allocated != null || (CFTypeRef)null != kCFNull ||
+      objc_msgSend(null, sel_registerName("copy")) != null)
+    return -1;
+  if (!objc_msgSend(null, sel_registerName("isEqual:"), allocated) ||
+      !objc_msgSend(null, sel_registerName("isKindOfClass:"), null_class))
+    return -2;
+  id description = objc_msgSend(null, sel_registerName("description"));
+  if (strcmp((const char *)objc_msgSend(description,
+                                        sel_registerName("UTF8String")),
+             "<null>"))
+    return -3;
+  if (CFNullGetTypeID() != CFNullGetTypeID())
+    return -4;
+
+  // NSNull can be stored in and retrieved from collections.
+  id key = objc_msgSend(string_class, with_utf8, "key");
+  id array_class =
+      NSClassFromString(objc_msgSend(string_class, with_utf8, "NSArray"));
+  id dict_class =
+      NSClassFromString(objc_msgSend(string_class, with_utf8, "NSDictionary"));
+  id array = objc_msgSend(array_class, sel_registerName("arrayWithObjects:"),
+                          null, key, NULL);
+  id dict = objc_msgSend(dict_class,
+                         sel_registerName("dictionaryWithObjectsAndKeys:"),
+                         null, key, NULL);
+  if (objc_msgSend(array, sel_registerName("objectAtIndex:"), 0) != null ||
+      objc_msgSend(dict, sel_registerName("objectForKey:"), key) != null)
+    return -5;
+
+  // Property lists can't contain it, so writing one fails cleanly.
+  id serialization_class = NSClassFromString(
+      objc_msgSend(string_class, with_utf8, "NSPropertyListSerialization"));
+  id error = NULL;
+  id data = objc_msgSend(
+      serialization_class,
+      sel_registerName("dataFromPropertyList:format:errorDescription:"), dict,
+      (NSUInteger)100, &error);
+  if (data || !error)
+    return -6;
+  const char *documents = first_search_path(NSDocumentDirectory, 1);
+  char *path = str_format("%s/ns_null_test.plist", documents);
+  id ns_path = objc_msgSend(string_class, with_utf8, path);
+  SEL write = sel_registerName("writeToFile:atomically:");
+  int res = 0;
+  if (objc_msgSend(array, write, ns_path, (BOOL)1))
+    res = -7;
+
+  // Without it, the same array round-trips.
+  array = objc_msgSend(array_class, sel_registerName("arrayWithObjects:"), key,
+                       NULL);
+  id read_back = NULL;
+  if (res == 0 && !objc_msgSend(array, write, ns_path, (BOOL)1))
+    res = -8;
+  else if (res == 0)
+    read_back = objc_msgSend(
+        array_class, sel_registerName("arrayWithContentsOfFile:"), ns_path);
+  if (res == 0 &&
+      (!read_back ||
+       (NSUInteger)objc_msgSend(read_back, sel_registerName("count")) != 1 ||
+       !objc_msgSend(
+           objc_msgSend(read_back, sel_registerName("objectAtIndex:"), 0),
+           sel_registerName("isEqual:"), key)))
+    res = -9;
+  remove(path);
+  free(path);
+  return res;
+}
+
+int test_ns_copying() {
+  id string_class = objc_msgSend(NSHomeDirectory(), sel_registerName("class"));
+  SEL with_utf8 = sel_registerName("stringWithUTF8String:");
+  SEL copy = sel_registerName("copy");
+  SEL mutable_copy = sel_registerName("mutableCopy");
+  SEL is_equal = sel_registerName("isEqual:");
+  SEL object_for_key = sel_registerName("objectForKey:");
+
+  // Copying a mutable string takes a snapshot of it.
+  id string = objc_msgSend(string_class, with_utf8, "key");
+  id mutable_string = objc_msgSend(string, mutable_copy);
+  id snapshot = objc_msgSend(mutable_string, copy);
+  if (mutable_string == string || snapshot == mutable_string ||
+      !objc_msgSend(snapshot, is_equal, string))
+    return -1;
+
+  // Mutable dictionaries copy their keys, so mutating the original key
+  // afterwards doesn't affect the dictionary.
+  id dict_class = NSClassFromString(
+      objc_msgSend(string_class, with_utf8, "NSMutableDictionary"));
+  id dict = objc_msgSend(dict_class, sel_registerName("dictionary"));
+  objc_msgSend(dict, sel_registerName("setObject:forKey:"), string,
+               mutable_string);
+  objc_msgSend(mutable_string, sel_registerName("appendString:"), string);
+  if (objc_msgSend(snapshot, is_equal, mutable_string) ||
+      objc_msgSend(dict, object_for_key, string) != string ||
+      objc_msgSend(dict, object_for_key, mutable_string))
+    return -2;
+
+  // Copies of collections are shallow.
+  id dict_copy = objc_msgSend(dict, copy);
+  id dict_mutable_copy = objc_msgSend(dict_copy, mutable_copy);
+  if (dict_copy == dict || dict_mutable_copy == dict_copy ||
+      objc_msgSend(dict_copy, object_for_key, string) != string ||
+      objc_msgSend(dict_mutable_copy, object_for_key, string) != string)
+    return -3;
+  id array_class =
+      NSClassFromString(objc_msgSend(string_class, with_utf8, "NSArray"));
+  id array = objc_msgSend(array_class, sel_registerName("arrayWithObjects:"),
+                          mutable_string, NULL);
+  id array_mutable_copy = objc_msgSend(array, mutable_copy);
+  objc_msgSend(array_mutable_copy, sel_registerName("addObject:"), string);
+  id array_copy = objc_msgSend(array_mutable_copy, copy);
+  SEL object_at_index = sel_registerName("objectAtIndex:");
+  SEL count = sel_registerName("count");
+  if ((NSUInteger)objc_msgSend(array, count) != 1 ||
+      (NSUInteger)objc_msgSend(array_copy, count) != 2 ||
+      objc_msgSend(array_copy, object_at_index, 0) != mutable_string)
+    return -4;
+
+  // Immutable objects can just be retained.
+  id date_class =
+      NSClassFromString(objc_msgSend(string_class, with_utf8, "NSDate"));
+  id date = objc_msgSend(date_class, sel_registerName("date"));
+  id number_class =
+      NSClassFromString(objc_msgSend(string_class, with_utf8, "NSNumber"));
+  id number = objc_msgSend(number_class, sel_registerName("numberWithInt:"), 1);
+  if (objc_msgSend(string, copy) != string ||
+      objc_msgSend(date, copy) != date || objc_msgSend(number, copy) != number)
+    return -5;
+
+  return 0;
+}
+
+int test_ns_description() {
+  id string_class = objc_msgSend(NSHomeDirectory(), sel_registerName("class"));
+  SEL with_utf8 = sel_registerName("stringWithUTF8String:");
+  SEL description = sel_registerName("description");
+  SEL utf8_string = sel_registerName("UTF8String");
+  id name = objc_msgSend(string_class, with_utf8, "hello world");
+  id two = objc_msgSend(string_class, with_utf8, "two");
+  id x = objc_msgSend(string_class, with_utf8, "x");
+
+  id number_class =
+      NSClassFromString(objc_msgSend(string_class, with_utf8, "NSNumber"));
+  id one = objc_msgSend(number_class, sel_registerName("numberWithInt:"), 1);
+  id set_class =
+      NSClassFromString(objc_msgSend(string_class, with_utf8, "NSSet"));
+  id set = objc_msgSend(set_class, sel_registerName("setWithObject:"), x);
+  id array_class =
+      NSClassFromString(objc_msgSend(string_class, with_utf8, "NSArray"));
+  id list = objc_msgSend(array_class, sel_registerName("arrayWithObjects:"),
+                         one, two, set, NULL);
+  id data_class =
+      NSClassFromString(objc_msgSend(string_class, with_utf8, "NSData"));
+  id data = objc_msgSend(data_class, sel_registerName("dataWithBytes:length:"),
+                         "hello", (NSUInteger)5);
+  id dict_class =
+      NSClassFromString(objc_msgSend(string_class, with_utf8, "NSDictionary"));
+  id dict = objc_msgSend(
+      dict_class, sel_registerName("dictionaryWithObjectsAndKeys:"), name,
+      objc_msgSend(string_class, with_utf8, "name"), list,
+      objc_msgSend(string_class, with_utf8, "list"), data,
+      objc_msgSend(string_class, with_utf8, "data"), NULL);
+
+  // Nested collections are indented like Apple's output, keys are sorted.
+  const char *expected = "{\n"
+                         "    data = <68656c6c 6f>;\n"
+                         "    list =     (\n"
+                         "        1,\n"
+                         "        two,\n"
+                         "                {(\n"
+                         "            x\n"
+                         "        )}\n"
+                         "    );\n"
+                         "    name = \"hello world\";\n"
+                         "}";
+  const char *actual = (const char *)objc_msgSend(
+      objc_msgSend(dict, description), utf8_string);
+  if (strcmp(actual, expected))
+    return -1;
+
+  // Dates are shown in UTC.
+  id date_class =
+      NSClassFromString(objc_msgSend(string_class, with_utf8, "NSDate"));
+  id date = objc_msgSend(
+      date_class, sel_registerName("dateWithTimeIntervalSinceReferenceDate:"),
+      86400.5);
+  actual = (const char *)objc_msgSend(objc_msgSend(date, description),
+                                      utf8_string);
+  if (strcmp(actual, "2001-01-02 00:00:00 +0000"))
+    return -2;
+
+  // Objects without their own description show the class and address.
+  id object_class =
+      NSClassFromString(objc_msgSend(string_class, with_utf8, "NSObject"));
+  id object = objc_msgSend(object_class, sel_registerName("new"));
+  char *object_expected = str_format("<NSObject: %p>", object);
+  actual = (const char *)objc_msgSend(objc_msgSend(object, description),
+                                      utf8_string);
+  int res = strcmp(actual, object_expected) ? -3 : 0;
+  free(object_expected);
+  objc_msgSend(object, sel_registerName("release"));
+  if (res)
+    return res;
+
+  // A nil object is formatted as "(null)".
+  id format = objc_msgSend(string_class, with_utf8, "[%@]");
+  id formatted =
+      objc_msgSend(string_class, sel_registerName("stringWithFormat:"), format,
+                   (id)NULL);
+  if (strcmp((const char *)objc_msgSend(formatted, utf8_string), "[(null)]"))
+    return -4;
+
+  // Localized format strings often reorder their arguments.
+  format = objc_msgSend(string_class, with_utf8, "%2$@ %1$@ (%3$d)");
+  formatted = objc_msgSend(string_class, sel_registerName("stringWithFormat:"),
+                           format, objc_msgSend(string_class, with_utf8, "b"),
+                           objc_msgSend(string_class, with_utf8, "a"), 3);
+  if (strcmp((const char *)objc_msgSend(formatted, utf8_string), "a b (3)"))
+    return -5;
+
+  // An array that contains itself is cut short instead of recursing forever,
+  // and the same output is produced each time.
+  id mutable_array_class = NSClassFromString(
+      objc_msgSend(string_class, with_utf8, "NSMutableArray"));
+  id cycle = objc_msgSend(mutable_array_class, sel_registerName("new"));
+  objc_msgSend(cycle, sel_registerName("addObject:"), one);
+  objc_msgSend(cycle, sel_registerName("addObject:"), cycle);
+  format = objc_msgSend(string_class, with_utf8, "%@");
+  for (int i = 0; i < 2 && !res; i++) {
+    formatted = objc_msgSend(
+        string_class, sel_registerName("stringWithFormat:"), format, cycle);
+    if (strcmp((const char *)objc_msgSend(formatted, utf8_string),
+               "(\n    1,\n    (...)\n)"))
+      res = -6;
+  }
+  objc_msgSend(cycle, sel_registerName("removeLastObject"));
+  objc_msgSend(cycle, sel_registerName("release"));
+
+  return res;
+}
+
+int test_ns_cache() {
+  id string_class = objc_msgSend(NSHomeDirectory(), sel_registerName("class"));
+  SEL with_utf8 = sel_registerName("stringWithUTF8String:");
+  SEL set_with_cost = sel_registerName("setObject:forKey:cost:");
+  SEL object_for_key = sel_registerName("objectForKey:");
+  id a = objc_msgSend(string_class, with_utf8, "a");
+  id b = objc_msgSend(string_class, with_utf8, "b");
+  id c = objc_msgSend(string_class, with_utf8, "c");
+
+  id cache_class =
+      NSClassFromString(objc_msgSend(string_class, with_utf8, "NSCache"));
+  id cache = objc_msgSend(cache_class, sel_registerName("new"));
+  objc_msgSend(cache, sel_registerName("setTotalCostLimit:"), (NSUInteger)10);
+  objc_msgSend(cache, set_with_cost, a, a, (NSUInteger)4);
+  objc_msgSend(cache, set_with_cost, b, b, (NSUInteger)4);
+  // Using "a" makes "b" the least recently used, so it's evicted first.
+  objc_msgSend(cache, object_for_key, a);
+  objc_msgSend(cache, set_with_cost, c, c, (NSUInteger)4);
+  if (objc_msgSend(cache, object_for_key, b) ||
+      objc_msgSend(cache, object_for_key, a) != a ||
+      objc_msgSend(cache, object_for_key, c) != c)
+    return -1;
+  objc_msgSend(cache, sel_registerName("setCountLimit:"), (NSUInteger)1);
+  if (objc_msgSend(cache, object_for_key, a) ||
+      objc_msgSend(cache, object_for_key, c) != c)
+    return -2;
+
+  // A memory warning empties caches and posts a notification.
+  id app_class =
+      NSClassFromString(objc_msgSend(string_class, with_utf8, "UIApplication"));
+  id app = objc_msgSend(app_class, sel_registerName("sharedApplication"));
+  if (!app)
+    app = objc_msgSend(objc_msgSend(app_class, sel_registerName("alloc")),
+                       sel_registerName("init"));
+  id array_class = NSClassFromString(
+      objc_msgSend(string_class, with_utf8, "NSMutableArray"));
+  id notifications = objc_msgSend(array_class, sel_registerName("new"));
+  id center_class = NSClassFromString(
+      objc_msgSend(string_class, with_utf8, "NSNotificationCenter"));
+  id center = objc_msgSend(center_class, sel_registerName("defaultCenter"));
+  id name = objc_msgSend(string_class, with_utf8,
+                         "UIApplicationDidReceiveMemoryWarningNotification");
+  objc_msgSend(center, sel_registerName("addObserver:selector:name:object:"),
+               notifications, sel_registerName("addObject:"), name, NULL);
+  objc_msgSend(app, sel_registerName("_performMemoryWarning"));
+  objc_msgSend(center, sel_registerName("removeObserver:name:object:"),
+               notifications, name, NULL);
+  int res = 0;
+  if (objc_msgSend(cache, object_for_key, c))
+    res = -3;
+  else if ((NSUInteger)objc_msgSend(notifications, sel_registerName("count")) !=
+           1)
+    res = -4;
+  objc_msgSend(notifications, sel_registerName("release"));
+  objc_msgSend(cache, sel_registerName("release"));
+  return res;
+}
+
+void ns_exception_test_handler(id exception) {}
+
+// Raising an exception or failing an assertion aborts the app, so only the
+// parts that don't can be tested here.
+int test_ns_exception() {
+  id string_class = objc_msgSend(NSHomeDirectory(), sel_registerName("class"));
+  SEL with_utf8 = sel_registerName("stringWithUTF8String:");
+  SEL is_equal = sel_registerName("isEqualToString:");
+  id name = objc_msgSend(string_class, with_utf8, "TestException");
+  id reason = objc_msgSend(string_class, with_utf8, "something broke");
+
+  id exception_class =
+      NSClassFromString(objc_msgSend(string_class, with_utf8, "NSException"));
+  id exception = objc_msgSend(exception_class,
+                              sel_registerName("exceptionWithName:reason:"
+                                               "userInfo:"),
+                              name, reason, NULL);
+  if (!objc_msgSend(objc_msgSend(exception, sel_registerName("name")),
+                    is_equal, name) ||
+      !objc_msgSend(objc_msgSend(exception, sel_registerName("reason")),
+                    is_equal, reason) ||
+      objc_msgSend(exception, sel_registerName("userInfo")))
+    return -1;
+  if (!objc_msgSend(objc_msgSend(exception, sel_registerName("description")),
+                    is_equal, reason))
+    return -2;
+
+  id handler_class = NSClassFromString(
+      objc_msgSend(string_class, with_utf8, "NSAssertionHandler"));
+  id handler = objc_msgSend(handler_class, sel_registerName("currentHandler"));
+  if (!handler ||
+      objc_msgSend(handler_class, sel_registerName("currentHandler")) !=
+          handler)
+    return -3;
+
+  if (NSGetUncaughtExceptionHandler())
+    return -4;
+  NSSetUncaughtExceptionHandler(&ns_exception_test_handler);
+  NSUncaughtExceptionHandler *got = NSGetUncaughtExceptionHandler();
+  NSSetUncaughtExceptionHandler(NULL);
+  if (got != &ns_exception_test_handler || NSGetUncaughtExceptionHandler())
+    return -5;
+
+  return 0;
+}
+
+#define TIMER_COUNT 1000
+#define TIMER_FIRINGS 3
+int timer_firings[TIMER_COUNT];
+
+void count_timer_firing(CFRunLoopTimerRef timer, void *info) {
+  int *firings = info;
+  if (++*firings == TIMER_FIRINGS)
+    CFRunLoopTimerInvalidate(timer);
+}
+
+int test_run_loop_timers() {
+  CFRunLoopTimerContext context = {0, NULL, NULL, NULL, NULL};
+  int i;
+  for (i = 0; i < TIMER_COUNT; i++) {
+    timer_firings[i] = 0;
+    context.info = &timer_firings[i];
+    // Varying the intervals makes the timers fire in an interleaved order.
+    CFTimeInterval interval = 0.01 + (i % 10) * 0.001;
+    CFRunLoopTimerRef timer = CFRunLoopTimerCreate(
+        NULL, 0, interval, 0, 0, &count_timer_firing, &context);
+    CFRunLoopAddTimer(CFRunLoopGetCurrent(), timer, kCFRunLoopDefaultMode);
+  }
+
+  id string_class = objc_msgSend(NSHomeDirectory(), sel_registerName("class"));
+  SEL with_utf8 = sel_registerName("stringWithUTF8String:");
+  id array_class = NSClassFromString(
+      objc_msgSend(string_class, with_utf8, "NSMutableArray"));
+  id array = objc_msgSend(array_class, sel_registerName("new"));
+  ((void (*)(id, SEL, SEL, id, double))objc_msgSend)(
+      array, sel_registerName("performSelector:withObject:afterDelay:"),
+      sel_registerName("addObject:"), array_class, 0.02);
+
+  int done = 0;
+  int tries;
+  for (tries = 0; tries < 50 && !done; tries++) {
+    CFRunLoopRunInMode(kCFRunLoopDefaultMode, 0.1, 0);
+    done = 1;
+    for (i = 0; i < TIMER_COUNT; i++)
+      if (timer_firings[i] < TIMER_FIRINGS)
+        done = 0;
+  }
+  // Each timer must fire exactly as many times as it was allowed to, even
+  // though many of them are due at the same time.
+  for (i = 0; i < TIMER_COUNT; i++)
+    if (timer_firings[i] != TIMER_FIRINGS)
+      return -1;
+
+  // The delayed message should have been sent exactly once by now.
+  int res = 0;
+  if ((NSUInteger)objc_msgSend(array, sel_registerName("count")) != 1 ||
+      objc_msgSend(array, sel_registerName("lastObject")) != array_class)
+    res = -2;
+  objc_msgSend(array, sel_registerName("release"));
+  return res;
+}
+
+int nftw_files, nftw_dirs, nftw_post_dirs, nftw_max_level, nftw_last_level;
+
+int count_nftw_visit(const char *path, const struct stat *st, int flag,
+                     struct FTW *ftw) {
+  if (flag == FTW_F)
+    nftw_files++;
+  else if (flag == FTW_D)
+    nftw_dirs++;
+  else if (flag == FTW_DP)
+    nftw_post_dirs++;
+  // The base is the offset of the last path component.
+  if (ftw->level > 0 && path[ftw->base - 1] != '/')
+    return -1;
+  if (ftw->level > nftw_max_level)
+    nftw_max_level = ftw->level;
+  nftw_last_level = ftw->level;
+  return 0;
+}
+
+int test_glob_ftw() {
+  const char *documents =
+      "/var/mobile/Applications/00000000-0000-0000-0000-000000000000/Documents";
+  const char *files[] = {"glob_test/b.lvl", "glob_test/a.lvl",
+                         "glob_test/c.txt", "glob_test/sub/d.lvl"};
+  int i;
+  if (chdir(documents) || mkdir("glob_test", 0755) ||
+      mkdir("glob_test/sub", 0755))
+    return -1;
+  for (i = 0; i < 4; i++) {
+    int fd = open(files[i], O_WRONLY | O_CREAT | O_TRUNC, 0644);
+    if (fd == -1)
+      return -1;
+    close(fd);
+  }
+
+  int res = 0;
+  glob_t g;
+  // Results are sorted by default, and only the given directory is searched.
+  if (glob("glob_test/*.lvl", 0, NULL, &g) != 0 || g.gl_pathc != 2 ||
+      strcmp(g.gl_pathv[0], "glob_test/a.lvl") ||
+      strcmp(g.gl_pathv[1], "glob_test/b.lvl") || g.gl_pathv[2])
+    res = -2;
+  globfree(&g);
+  if (!res && (glob("glob_test/*", GLOB_MARK, NULL, &g) != 0 ||
+               g.gl_pathc != 4 || strcmp(g.gl_pathv[2], "glob_test/c.txt") ||
+               strcmp(g.gl_pathv[3], "glob_test/sub/")))
+    res = -3;
+  globfree(&g);
+  if (!res && (glob("glob_test/[!a]*/?.lvl", 0, NULL, &g) != 0 ||
+               g.gl_pathc != 1 ||
+               strcmp(g.gl_pathv[0], "glob_test/sub/d.lvl")))
+    res = -4;
+  globfree(&g);
+  if (!res && glob("glob_test/*.dat", 0, NULL, &g) != GLOB_NOMATCH)
+    res = -5;
+  globfree(&g);
+  if (!res && (glob("glob_test/*.dat", GLOB_NOCHECK, NULL, &g) != 0 ||
+               g.gl_pathc != 1 || strcmp(g.gl_pathv[0], "glob_test/*.dat")))
+    res = -6;
+  globfree(&g);
+
+  // Directories are visited before their contents by default.
+  nftw_files = nftw_dirs = nftw_post_dirs = nftw_max_level = 0;
+  if (!res && (nftw("glob_test", &count_nftw_visit, 4, 0) != 0 ||
+               nftw_files != 4 || nftw_dirs != 2 || nftw_post_dirs != 0 ||
+               nftw_max_level != 2 || nftw_last_level == 0))
+    res = -7;
+  // With FTW_DEPTH, they are visited afterwards, so the root is last.
+  nftw_files = nftw_dirs = nftw_post_dirs = nftw_max_level = 0;
+  if (!res && (nftw("glob_test", &count_nftw_visit, 4, FTW_DEPTH) != 0 ||
+               nftw_files != 4 || nftw_dirs != 0 || nftw_post_dirs != 2 ||
+               nftw_last_level != 0))
+    res = -8;
+
+  for (i = 0; i < 4; i++)
+    if (remove(files[i]))
+      res = -9;
+  if (remove("glob_test/sub") || remove("glob_test"))
+    res = -9;
+  return res;
+}
+
+int test_libgen() {
+  // From the table in the basename(3) and dirname(3) man pages.
+  const char *cases[][3] = {
+      {"/usr/lib", "/usr", "lib"}, {"/usr/", "/", "usr"}, {"usr", ".", "usr"},
+      {"/", "/", "/"},             {".", ".", "."},       {"..", ".", ".."},
+      {"", ".", "."},              {"a/", ".", "a"},      {"//foo", "/", "foo"},
+  };
+  char path[16];
+  char buf[1024];
+  int i;
+  for (i = 0; i < sizeof(cases) / sizeof(cases[0]); i++) {
+    strcpy(path, cases[i][0]);
+    if (strcmp(dirname(path), cases[i][1]) ||
+        strcmp(basename(path), cases[i][2]))
+      return -(i + 1);
+    // Unlike glibc, Darwin doesn't modify the input.
+    if (strcmp(path, cases[i][0]))
+      return -(i + 1);
+    if (dirname_r(path, buf) != buf || strcmp(buf, cases[i][1]) ||
+        basename_r(path, buf) != buf || strcmp(buf, cases[i][2]))
+      return -(i + 1);
+  }
+  if (strcmp(basename(NULL), ".") || strcmp(dirname(NULL), "."))
+    return -20;
+
+  // NSString's path methods agree for paths with a directory part.
+  id string_class = objc_msgSend(NSHomeDirectory(), sel_registerName("class"));
+  SEL with_utf8 = sel_registerName("stringWithUTF8String:");
+  SEL utf8 = sel_registerName("UTF8String");
+  for (i = 0; i < 2; i++) {
+    id string = objc_msgSend(string_class, with_utf8, cases[i][0]);
+    id dir = objc_msgSend(
+        string, sel_registerName("stringByDeletingLastPathComponent"));
+    id base = objc_msgSend(string, sel_registerName("lastPathComponent"));
+    if (strcmp((const char *)objc_msgSend(dir, utf8), cases[i][1]) ||
+        strcmp((const char *)objc_msgSend(base, utf8), cases[i][2]))
+      return -21;
+  }
+  return 0;
+}
+
+int test_regex() {
+  regex_t re;
+  regmatch_t m[4];
+  char buf[32];
+
+  // Capture group offsets, and unused groups.
+  if (regcomp(&re, "([a-z]+)-([0-9]+)(x)?", REG_EXTENDED | REG_ICASE) ||
+      re.re_nsub != 3)
+    return -1;
+  if (regexec(&re, "Level: Forest-12!", 4, m, 0) || m[0].rm_so != 7 ||
+      m[0].rm_eo != 16 || m[1].rm_so != 7 || m[1].rm_eo != 13 ||
+      m[2].rm_so != 14 || m[2].rm_eo != 16 || m[3].rm_so != -1 ||
+      m[3].rm_eo != -1)
+    return -2;
+  if (regexec(&re, "no level here", 4, m, 0) != REG_NOMATCH)
+    return -3;
+  regfree(&re);
+
+  // REG_NOTBOL stops ^ from matching at the start of the string.
+  if (regcomp(&re, "^cheat", REG_EXTENDED))
+    return -4;
+  if (regexec(&re, "cheat", 0, NULL, 0) ||
+      regexec(&re, "cheat", 0, NULL, REG_NOTBOL) != REG_NOMATCH)
+    return -5;
+  regfree(&re);
+
+  // In a basic RE, \( and \{ are special, but ( and { are not.
+  if (regcomp(&re, "\\(a*\\)b\\{2\\}(c{1})", REG_BASIC) || re.re_nsub != 1)
+    return -6;
+  if (regexec(&re, "xaabb(c{1})", 2, m, 0) || m[0].rm_so != 1 ||
+      m[0].rm_eo != 11 || m[1].rm_so != 1 || m[1].rm_eo != 3)
+    return -7;
+  regfree(&re);
+
+  int err = regcomp(&re, "(unbalanced", REG_EXTENDED);
+  if (err != REG_EPAREN)
+    return -8;
+  if (regerror(err, &re, buf, sizeof(buf)) != 25 ||
+      strcmp(buf, "parentheses not balanced"))
+    return -9;
+  return 0;
+}
+
+int test_zlib() {
+  const char *text = "hello, hello, hello!";
+  // zlib.compress(b"hello, hello, hello!") from the real zlib.
+  const unsigned char zlib_data[] = {0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9,
+                                     0xc9, 0xd7, 0x51, 0xc8, 0x40, 0xa2,
+                                     0x14, 0x01, 0x4b, 0x1e, 0x06, 0xf6};
+  // The same, as a gzip file with a file name.
+  const unsigned char gzip_data[] = {
+      0x1f, 0x8b, 0x08, 0x08, 0x00, 0x00, 0x00, 0x00, 0x02, 0xff, 0x61, 0x2e,
+      0x74, 0x78, 0x74, 0x00, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0xd7, 0x51, 0xc8,
+      0x40, 0xa2, 0x14, 0x01, 0xa7, 0xbb, 0xd2, 0xfe, 0x14, 0x00, 0x00, 0x00};
+  unsigned char buf[512];
+  unsigned char buf2[256];
+  unsigned long len;
+  int i;
+
+  if (crc32(0, (const unsigned char *)"123456789", 9) != 0xcbf43926 ||
+      adler32(1, (const unsigned char *)"Wikipedia", 9) != 0x11e60398 ||
+      crc32(0, NULL, 0) != 0 || adler32(0, NULL, 0) != 1)
+    return -1;
+
+  // Decompress a known vector.
+  len = sizeof(buf);
+  if (uncompress(buf, &len, zlib_data, sizeof(zlib_data)) != Z_OK ||
+      len != strlen(text) || memcmp(buf, text, len))
+    return -2;
+  len = 4;
+  if (uncompress(buf, &len, zlib_data, sizeof(zlib_data)) != Z_BUF_ERROR)
+    return -3;
+  len = sizeof(buf);
+  if (uncompress(buf, &len, zlib_data, sizeof(zlib_data) - 1) != Z_DATA_ERROR)
+    return -4;
+
+  // Round trip through the one-shot functions.
+  for (i = 0; i < sizeof(buf2); i++)
+    buf2[i] = i % 7;
+  len = sizeof(buf);
+  if (compressBound(sizeof(buf2)) > len ||
+      compress2(buf, &len, buf2, sizeof(buf2), 9) != Z_OK)
+    return -5;
+  unsigned long compressed_len = len;
+  unsigned char out[256];
+  len = sizeof(out);
+  if (uncompress(out, &len, buf, compressed_len) != Z_OK ||
+      len != sizeof(buf2) || memcmp(out, buf2, len))
+    return -6;
+
+  // Stream a gzip file through a small output buffer, with zlib/gzip
+  // detection.
+  z_stream strm;
+  memset(&strm, 0, sizeof(strm));
+  if (inflateInit2_(&strm, 15 + 32, "1.2.3", 48) != Z_VERSION_ERROR ||
+      inflateInit2_(&strm, 15 + 32, "1.2.3", sizeof(strm)) != Z_OK)
+    return -7;
+  strm.next_in = gzip_data;
+  strm.avail_in = sizeof(gzip_data);
+  int res = Z_OK;
+  for (i = 0; res == Z_OK && i < 100; i++) {
+    strm.next_out = buf + strm.total_out;
+    strm.avail_out = 3;
+    res = inflate(&strm, Z_NO_FLUSH);
+  }
+  if (res != Z_STREAM_END || strm.total_out != strlen(text) ||
+      memcmp(buf, text, strm.total_out) || strm.avail_in != 0 ||
+      strm.total_in != sizeof(gzip_data) || inflateEnd(&strm) != Z_OK)
+    return -8;
+
+  // Raw deflate round trip.
+  memset(&strm, 0, sizeof(strm));
+  if (deflateInit2_(&strm, 6, Z_DEFLATED, -15, 8, 0, "1.2.3",
+                    sizeof(strm)) != Z_OK)
+    return -9;
+  strm.next_in = buf2;
+  strm.avail_in = sizeof(buf2);
+  strm.next_out = buf;
+  strm.avail_out = sizeof(buf);
+  if (deflate(&strm, Z_FINISH) != Z_STREAM_END || deflateEnd(&strm) != Z_OK)
+    return -10;
+  compressed_len = strm.total_out;
+  memset(&strm, 0, sizeof(strm));
+  if (inflateInit2_(&strm, -15, "1.2.3", sizeof(strm)) != Z_OK)
+    return -11;
+  strm.next_in = buf;
+  strm.avail_in = compressed_len;
+  strm.next_out = out;
+  strm.avail_out = sizeof(out);
+  if (inflate(&strm, Z_FINISH) != Z_STREAM_END ||
+      strm.total_out != sizeof(buf2) || memcmp(out, buf2, sizeof(buf2)) ||
+      inflateEnd(&strm) != Z_OK)
+    return -12;
+
+  // Corrupt data is reported.
+  memcpy(buf, zlib_data, sizeof(zlib_data));
+  buf[sizeof(zlib_data) - 1] ^= 1;
+  memset(&strm, 0, sizeof(strm));
+  inflateInit_(&strm, "1.2.3", sizeof(strm));
+  strm.next_in = buf;
+  strm.avail_in = sizeof(zlib_data);
+  strm.next_out = out;
+  strm.avail_out = sizeof(out);
+  res = inflate(&strm, Z_FINISH);
+  inflateEnd(&strm);
+  if (res != Z_DATA_ERROR || strm.msg == NULL ||
+      strcmp(strm.msg, "incorrect data check"))
+    return -13;
+
+  // The final Z_FINISH call may have no input at all.
+  memset(&strm, 0, sizeof(strm));
+  deflateInit2_(&strm, 6, Z_DEFLATED, -15, 8, 0, "1.2.3", sizeof(strm));
+  strm.next_in = buf2;
+  strm.avail_in = sizeof(buf2);
+  strm.next_out = buf;
+  strm.avail_out = sizeof(buf);
+  res = deflate(&strm, Z_NO_FLUSH);
+  strm.next_in = NULL;
+  strm.avail_in = 0;
+  if (res != Z_OK || deflate(&strm, Z_FINISH) != Z_STREAM_END ||
+      deflateEnd(&strm) != Z_OK)
+    return -14;
+  compressed_len = strm.total_out;
+  memset(&strm, 0, sizeof(strm));
+  inflateInit2_(&strm, -15, "1.2.3", sizeof(strm));
+  strm.next_in = buf;
+  strm.avail_in = compressed_len;
+  strm.next_out = out;
+  strm.avail_out = 16;
+  res = inflate(&strm, Z_NO_FLUSH);
+  strm.next_in = NULL;
+  strm.avail_in = 0;
+  strm.avail_out = sizeof(out) - 16;
+  if (res != Z_OK || inflate(&strm, Z_FINISH) != Z_STREAM_END ||
+      strm.total_out != sizeof(buf2) || memcmp(out, buf2, sizeof(buf2)) ||
+      inflateEnd(&strm) != Z_OK)
+    return -15;
+
+  return 0;
+}
+
+int test_number_formatter() {
+  id string_class = objc_msgSend(NSHomeDirectory(), sel_registerName("class"));
+  SEL with_utf8 = sel_registerName("stringWithUTF8String:");
+  SEL utf8_string = sel_registerName("UTF8String");
+  SEL from_number = sel_registerName("stringFromNumber:");
+  SEL from_string = sel_registerName("numberFromString:");
+  SEL set_style = sel_registerName("setNumberStyle:");
+  double (*double_value)(id, SEL) = (double (*)(id, SEL))objc_msgSend;
+  id number_class =
+      NSClassFromString(objc_msgSend(string_class, with_utf8, "NSNumber"));
+  id decimal_class = NSClassFromString(
+      objc_msgSend(string_class, with_utf8, "NSDecimalNumber"));
+
+  id locale = objc_msgSend(
+      NSClassFromString(objc_msgSend(string_class, with_utf8, "NSLocale")),
+      sel_registerName("alloc"));
+  locale = objc_msgSend(locale, sel_registerName("initWithLocaleIdentifier:"),
+                        objc_msgSend(string_class, with_utf8, "en_US"));
+  id formatter = objc_msgSend(
+      NSClassFromString(
+          objc_msgSend(string_class, with_utf8, "NSNumberFormatter")),
+      sel_registerName("new"));
+  objc_msgSend(formatter, sel_registerName("setLocale:"), locale);
+  objc_msgSend(locale, sel_registerName("release"));
+
+  id number = ((id(*)(id, SEL, double))objc_msgSend)(
+      number_class, sel_registerName("numberWithDouble:"), 1234567.891);
+
+  objc_msgSend(formatter, set_style, (NSUInteger)1); // decimal
+  id decimal = objc_msgSend(formatter, from_number, number);
+  id decimal_parsed = objc_msgSend(formatter, from_string, decimal);
+
+  objc_msgSend(formatter, set_style, (NSUInteger)2); // currency
+  id currency = objc_msgSend(formatter, from_number, number);
+  id currency_parsed = objc_msgSend(formatter, from_string, currency);
+
+  objc_msgSend(formatter, set_style, (NSUInteger)1);
+  objc_msgSend(formatter, sel_registerName("setMinimumFractionDigits:"),
+               (NSUInteger)2);
+  objc_msgSend(formatter, sel_registerName("setGroupingSeparator:"),
+               objc_msgSend(string_class, with_utf8, "'"));
+  id five = objc_msgSend(
+      formatter, from_number,
+      objc_msgSend(number_class, sel_registerName("numberWithInt:"), 5));
+  id grouped = objc_msgSend(formatter, from_number, number);
+  id invalid = objc_msgSend(formatter, from_string,
+                            objc_msgSend(string_class, with_utf8, "12abc"));
+  objc_msgSend(formatter, sel_registerName("release"));
+
+  SEL value = sel_registerName("doubleValue");
+  if (strcmp((const char *)objc_msgSend(decimal, utf8_string),
+             "1,234,567.891"))
+    return -1;
+  if (!decimal_parsed || double_value(decimal_parsed, value) != 1234567.891)
+    return -2;
+  if (strcmp((const char *)objc_msgSend(currency, utf8_string),
+             "$1,234,567.89"))
+    return -3;
+  if (!currency_parsed || double_value(currency_parsed, value) != 1234567.89)
+    return -4;
+  if (strcmp((const char *)objc_msgSend(five, utf8_string), "5.00"))
+    return -5;
+  if (strcmp((const char *)objc_msgSend(grouped, utf8_string),
+             "1'234'567.891"))
+    return -6;
+  if (invalid)
+    return -7;
+
+  SEL with_string = sel_registerName("decimalNumberWithString:");
+  id a = objc_msgSend(decimal_class, with_string,
+                      objc_msgSend(string_class, with_utf8, "0.1"));
+  id b = objc_msgSend(decimal_class, with_string,
+                      objc_msgSend(string_class, with_utf8, "0.2"));
+  id c = objc_msgSend(decimal_class, with_string,
+                      objc_msgSend(string_class, with_utf8, "0.3"));
+  id sum = objc_msgSend(a, sel_registerName("decimalNumberByAdding:"), b);
+  id eighth = objc_msgSend(
+      objc_msgSend(decimal_class, sel_registerName("one")),
+      sel_registerName("decimalNumberByDividingBy:"),
+      objc_msgSend(decimal_class, with_string,
+                   objc_msgSend(string_class, with_utf8, "8")));
+  id nan = objc_msgSend(decimal_class, sel_registerName("notANumber"));
+  SEL description = sel_registerName("description");
+  if ((long)objc_msgSend(sum, sel_registerName("compare:"), c) != 0)
+    return -8;
+  if (strcmp((const char *)objc_msgSend(objc_msgSend(eighth, description),
+                                        utf8_string),
+             "0.125"))
+    return -9;
+  if (strcmp((const char *)objc_msgSend(objc_msgSend(nan, description),
+                                        utf8_string),
+             "NaN"))
+    return -10;
+
+  // Overflow and underflow are reported to the behavior.
+  id quiet = ((id(*)(id, SEL, NSUInteger, short, BOOL, BOOL, BOOL, BOOL))
+                  objc_msgSend)(
+      NSClassFromString(
+          objc_msgSend(string_class, with_utf8, "NSDecimalNumberHandler")),
+      sel_registerName("decimalNumberHandlerWithRoundingMode:scale:"
+                       "raiseOnExactness:raiseOnOverflow:raiseOnUnderflow:"
+                       "raiseOnDivideByZero:"),
+      0, 32767, 0, 0, 0, 0);
+  id (*by_power)(id, SEL, short, id) = (id(*)(id, SEL, short, id))objc_msgSend;
+  SEL by_power_sel =
+      sel_registerName("decimalNumberByMultiplyingByPowerOf10:withBehavior:");
+  id thousandth = by_power(a, by_power_sel, -3, quiet);
+  id huge = by_power(a, by_power_sel, 200, quiet);
+  id tiny = by_power(a, by_power_sel, -200, quiet);
+  if (strcmp((const char *)objc_msgSend(objc_msgSend(thousandth, description),
+                                        utf8_string),
+             "0.0001"))
+    return -11;
+  if (strcmp((const char *)objc_msgSend(objc_msgSend(huge, description),
+                                        utf8_string),
+             "NaN"))
+    return -12;
+  if ((long)objc_msgSend(tiny, sel_registerName("compare:"),
+                         objc_msgSend(decimal_class, sel_registerName("zero"))))
+    return -13;
+
+  // NSDecimalNumber can be used wherever an NSNumber can.
+  id decimal_true = ((id(*)(id, SEL, BOOL))objc_msgSend)(
+      decimal_class, sel_registerName("numberWithBool:"), 1);
+  id decimal_big = ((id(*)(id, SEL, unsigned long long))objc_msgSend)(
+      decimal_class, sel_registerName("numberWithUnsignedLongLong:"),
+      10000000000ULL);
+  if (!objc_msgSend(decimal_true, sel_registerName("boolValue")) ||
+      ((unsigned long long (*)(id, SEL))objc_msgSend)(
+          decimal_big, sel_registerName("unsignedLongLongValue")) !=
+          10000000000ULL)
+    return -14;
+  id one = objc_msgSend(number_class, sel_registerName("numberWithInt:"), 1);
+  SEL is_equal = sel_registerName("isEqual:");
+  SEL hash = sel_registerName("hash");
+  if (!objc_msgSend(one, is_equal, decimal_true) ||
+      !objc_msgSend(decimal_true, is_equal, one) ||
+      objc_msgSend(one, hash) != objc_msgSend(decimal_true, hash) ||
+      objc_msgSend(one, is_equal, eighth))
+    return -15;
+  id plist = objc_msgSend(
+      NSClassFromString(
+          objc_msgSend(string_class, with_utf8, "NSPropertyListSerialization")),
+      sel_registerName("dataFromPropertyList:format:errorDescription:"),
+      eighth, (NSUInteger)100, NULL);
+  if (!plist)
+    return -16;
+
+  return 0;
+}
+
+int test_non_utf8_strings() {
+  // "café" in Latin-1, which isn't valid UTF-8
+  const char *latin1 = "caf\xe9";
+  char buf[16];
+  char *end;
+  struct stat st;
+
+  // Paths that aren't UTF-8 can't exist, like on HFS+.
+  errno = 0;
+  if (open(latin1, O_RDONLY) != -1 || errno != EILSEQ)
+    return -1;
+  errno = 0;
+  if (stat(latin1, &st) != -1 || errno != EILSEQ)
+    return -2;
+  if (mkdir(latin1, 0755) != -1 || access(latin1, F_OK) != -1)
+    return -3;
+  if (opendir(latin1) || chdir(latin1) != -1 || unlink(latin1) != -1)
+    return -4;
+  if (remove(latin1) != -1 || rename(latin1, "other") != -1)
+    return -5;
+  if (realpath(latin1, NULL) || readlink(latin1, buf, sizeof(buf)) != -1)
+    return -6;
+
+  // Parsing stops at the first byte that doesn't belong to the number.
+  if (strtoul("1f\xe9", &end, 16) != 0x1f || *end != '\xe9')
+    return -7;
+  if (strtoul("\xe9", &end, 10) != 0 || *end != '\xe9')
+    return -8;
+  if (strtoul(" 0x1F", NULL, 0) != 0x1f || strtoul("017", NULL, 0) != 017 ||
+      strtoul("-1", NULL, 10) != 0xffffffff)
+    return -9;
+  errno = 0;
+  if (strtoul("4294967296", NULL, 10) != 0xffffffff || errno != ERANGE)
+    return -10;
+  if (strtod("2.5\xe9", &end) != 2.5 || *end != '\xe9')
+    return -11;
+  if (strcspn("ab\xe9!", "\xe9") != 2)
+    return -12;
+
+  if (setlocale(LC_ALL, latin1))
+    return -13;
+  if (!setlocale(LC_ALL, "") || strcmp(setlocale(LC_ALL, "C"), "C"))
+    return -14;
+
+  sem_t *sem = sem_open(latin1, O_CREAT, 0644, 1);
+  if (sem == SEM_FAILED || sem_close(sem) || sem_unlink(latin1))
+    return -15;
+
+  size_t len = sizeof(buf);
+  if (sysctlbyname(latin1, buf, &len, NULL, 0) != -1 || errno != ENOENT)
+    return -16;
+
+  void *handle = dlopen("/usr/lib/libSystem.B.dylib", RTLD_LAZY);
+  if (!handle || dlsym(handle, latin1))
+    return -17;
+  dlclose(handle);
+
+  struct tm tm = {0};
+  if (strftime(buf, sizeof(buf), "%%\xe9", &tm) != 2 || strcmp(buf, "%\xe9"))
+    return -18;
+  if (strftime(buf, 2, "%%\xe9", &tm) != 0)
+    return -19;
+
+  return 0;
+}
+
+int test_cg_geometry_strings() {
+  id string_class = objc_msgSend(NSHomeDirectory(), sel_registerName("class"));
+  SEL with_utf8 = sel_registerName("stringWithUTF8String:");
+  SEL utf8_string = sel_registerName("UTF8String");
+  CGRect rects[] = {
+      {{0, 0}, {0, 0}},
+      {{-10, 20.5}, {320, 480}},
+      {{0.125, -0.75}, {1024, 768}},
+      {{-123.456, 0.001}, {99999, 0.5}},
+  };
+  const char *expected[] = {
+      "{{0, 0}, {0, 0}}",
+      "{{-10, 20.5}, {320, 480}}",
+      "{{0.125, -0.75}, {1024, 768}}",
+      "{{-123.456, 0.001}, {99999, 0.5}}",
+  };
+  for (int i = 0; i < sizeof(rects) / sizeof(rects[0]); i++) {
+    CGRect rect = rects[i];
+    id string = NSStringFromCGRect(rect);
+    if (strcmp(objc_msgSend(string, utf8_string), expected[i]))
+      return -1 - i * 10;
+    if (!CGRectEqualToRect(CGRectFromString(string), rect))
+      return -2 - i * 10;
+    string = NSStringFromCGPoint(rect.origin);
+    if (!CGPointEqualToPoint(CGPointFromString(string), rect.origin))
+      return -3 - i * 10;
+    string = NSStringFromCGSize(rect.size);
+    if (!CGSizeEqualToSize(CGSizeFromString(string), rect.size))
+      return -4 - i * 10;
+  }
+
+  // Whitespace is ignored when parsing.
+  CGRect parsed = CGRectFromString(
+      objc_msgSend(string_class, with_utf8, " { {1,2} ,{ 3 , 4} } "));
+  CGRect expected_rect = {{1, 2}, {3, 4}};
+  if (!CGRectEqualToRect(parsed, expected_rect))
+    return -100;
+
+  // Malformed input and nil give zeroes rather than crashing.
+  CGRect zero_rect = {{0, 0}, {0, 0}};
+  CGPoint zero_point = {0, 0};
+  CGSize zero_size = {0, 0};
+  id malformed = objc_msgSend(string_class, with_utf8, "{1, oops}");
+  if (!CGRectEqualToRect(CGRectFromString(malformed), zero_rect) ||
+      !CGPointEqualToPoint(CGPointFromString(malformed), zero_point) ||
+      !CGSizeEqualToSize(CGSizeFromString(malformed), zero_size))
+    return -101;
+  if (!CGRectEqualToRect(CGRectFromString(NULL), zero_rect) ||
+      !CGPointEqualToPoint(CGPointFromString(NULL), zero_point))
+    return -102;
+
+  return 0;
+}
+
+int test_process_stubs() {
+  // Jailbreak checks try to run commands. These must fail cleanly.
+  errno = 0;
+  if (system("id") != -1 || errno != EAGAIN)
+    return -1;
+  if (system(NULL) != 0)
+    return -2;
+  errno = 0;
+  if (fork() != -1 || errno != EAGAIN)
+    return -3;
+  char *argv[] = {"/bin/sh", "-c", "id", NULL};
+  char *envp[] = {NULL};
+  errno = 0;
+  if (execve("/bin/sh", argv, envp) != -1 || errno != ENOENT)
+    return -4;
+  pid_t pid = 0;
+  if (posix_spawn(&pid, "/bin/sh", NULL, NULL, argv, envp) != EPERM ||
+      pid != 0)
+    return -5;
+  if (posix_spawn(&pid, NULL, NULL, NULL, argv, envp) != EINVAL)
+    return -5;
+
+  // pipe() works within the process.
+  int fds[2];
+  char buf[8] = {0};
+  if (pipe(fds) != 0)
+    return -6;
+  if (write(fds[1], "hello", 5) != 5)
+    return -7;
+  if (read(fds[0], buf, 3) != 3 || memcmp(buf, "hel", 3))
+    return -8;
+  // Nothing more is available, but the write end is open.
+  errno = 0;
+  if (read(fds[0], buf, sizeof(buf)) != 2 ||
+      read(fds[0], buf, sizeof(buf)) != -1 || errno != EAGAIN)
+    return -9;
+  close(fds[1]);
+  if (read(fds[0], buf, sizeof(buf)) != 0)
+    return -10;
+  close(fds[0]);
+
+  // NSPipe is built on the same thing.
+  id string_class = objc_msgSend(NSHomeDirectory(), sel_registerName("class"));
+  id pipe_class = NSClassFromString(
+      objc_msgSend(string_class, sel_registerName("stringWithUTF8String:"),
+                   "NSPipe"));
+  id ns_pipe = objc_msgSend(pipe_class, sel_registerName("pipe"));
+  id writing = objc_msgSend(ns_pipe, sel_registerName("fileHandleForWriting"));
+  id reading = objc_msgSend(ns_pipe, sel_registerName("fileHandleForReading"));
+  int write_fd = (int)objc_msgSend(writing, sel_registerName("fileDescriptor"));
+  if (write(write_fd, "abc", 3) != 3)
+    return -11;
+  objc_msgSend(writing, sel_registerName("closeFile"));
+  id data = objc_msgSend(reading, sel_registerName("readDataToEndOfFile"));
+  if ((NSUInteger)objc_msgSend(data, sel_registerName("length")) != 3 ||
+      memcmp(objc_msgSend(data, sel_registerName("bytes")), "abc", 3))
+    return -12;
+
+  return 0;
+}
+
+int test_path_resolution() {
+  id string_class = objc_msgSend(NSHomeDirectory(), sel_registerName("class"));
+  SEL with_utf8 = sel_registerName("stringWithUTF8String:");
+  SEL utf8_string = sel_registerName("UTF8String");
+  id bundle = objc_msgSend(
+      NSClassFromString(objc_msgSend(string_class, with_utf8, "NSBundle")),
+      sel_registerName("mainBundle"));
+  const char *bundle_path =
+      objc_msgSend(objc_msgSend(bundle, sel_registerName("bundlePath")),
+                   utf8_string);
+  int res = 0;
+
+  // Paths are case-insensitive, like on HFS+.
+  char *path = str_format("%s/info.PLIST", bundle_path);
+  int fd = open(path, O_RDONLY);
+  if (fd == -1)
+    res = -1;
+  else
+    close(fd);
+  // Relative paths can also be found in the bundle.
+  fd = open("./pkginfo", O_RDONLY);
+  if (fd == -1)
+    res = -2;
+  else
+    close(fd);
+
+  // The bundle is read-only.
+  errno = 0;
+  if (!res && (open(path, O_WRONLY) != -1 || errno != EROFS))
+    res = -3;
+  free(path);
+  path = str_format("%s/new_file", bundle_path);
+  errno = 0;
+  if (!res && (open(path, O_WRONLY | O_CREAT, 0644) != -1 || errno != EROFS))
+    res = -4;
+  errno = 0;
+  if (!res && (mkdir(path, 0755) != -1 || errno != EROFS))
+    res = -5;
+  free(path);
+
+  // NSBundle finds resources with the wrong case, and gives the real name.
+  id resource_path = objc_msgSend(
+      bundle, sel_registerName("pathForResource:ofType:"),
+      objc_msgSend(string_class, with_utf8, "INFO"),
+      objc_msgSend(string_class, with_utf8, "Plist"));
+  path = str_format("%s/Info.plist", bundle_path);
+  if (!res && (!resource_path ||
+               strcmp(objc_msgSend(resource_path, utf8_string), path)))
+    res = -6;
+  free(path);
+
+  // stdio uses the same path handling, whatever the mode string looks like.
+  path = str_format("%s/PkgInfo", bundle_path);
+  FILE *file = fopen(path, "rt");
+  if (!file)
+    res = res ? res : -7;
+  else
+    fclose(file);
+  errno = 0;
+  file = fopen(path, "wt");
+  if (file) {
+    fclose(file);
+    res = res ? res : -8;
+  } else if (errno != EROFS) {
+    res = res ? res : -8;
+  }
+  free(path);
+
+  // realpath() collapses ".", ".." and extra slashes.
+  char *expected = str_format("%s/PkgInfo", bundle_path);
+  path = str_format("%s/..//%s/./PkgInfo", bundle_path,
+                    strrchr(bundle_path, '/') + 1);
+  char *resolved = realpath(path, NULL);
+  if (!resolved || strcmp(resolved, expected))
+    res = res ? res : -9;
+  free(resolved);
+  free(path);
+  char buf[1024];
+  path = str_format("%s/", bundle_path);
+  if (realpath(path, buf) != buf || strcmp(buf, bundle_path))
+    res = res ? res : -10;
+  free(path);
+  if (!realpath("/../..//.", buf) || strcmp(buf, "/"))
+    res = res ? res : -11;
+  // The result must exist.
+  errno = 0;
+  if (realpath(expected, buf) != buf || realpath("Missing", NULL) ||
+      errno != ENOENT)
+    res = res ? res : -12;
+  free(expected);
+
+  return res;
+}
+
+int test_nib_loading() {
+  id string_class = objc_msgSend(NSHomeDirectory(), sel_registerName("class"));
+  SEL with_utf8 = sel_registerName("stringWithUTF8String:");
+  SEL utf8_string = sel_registerName("UTF8String");
+  SEL object_for_key = sel_registerName("objectForKey:");
+  SEL tag = sel_registerName("tag");
+  SEL superview = sel_registerName("superview");
+  SEL view_with_tag = sel_registerName("viewWithTag:");
+  id bundle = objc_msgSend(
+      NSClassFromString(objc_msgSend(string_class, with_utf8, "NSBundle")),
+      sel_registerName("mainBundle"));
+  // Outlets are connected with setValue:forKey:, so a dictionary can stand in
+  // for the owner's ivars.
+  id owner = objc_msgSend(
+      NSClassFromString(
+          objc_msgSend(string_class, with_utf8, "NSMutableDictionary")),
+      sel_registerName("new"));
+
+  id objects = objc_msgSend(
+      bundle, sel_registerName("loadNibNamed:owner:options:"),
+      objc_msgSend(string_class, with_utf8, "TestNib"), owner, NULL);
+  if (!objects ||
+      (NSUInteger)objc_msgSend(objects, sel_registerName("count")) != 1)
+    return -1;
+  id root = objc_msgSend(objects, sel_registerName("objectAtIndex:"), 0);
+  if (objc_msgSend(owner, object_for_key,
+                   objc_msgSend(string_class, with_utf8, "rootView")) != root)
+    return -2;
+  if ((int)objc_msgSend(root, tag) != 1 ||
+      (NSUInteger)objc_msgSend(root, sel_registerName("autoresizingMask")) !=
+          18 ||
+      (NSUInteger)objc_msgSend(objc_msgSend(root, sel_registerName("subviews")),
+                               sel_registerName("count")) != 2)
+    return -3;
+
+  id label = objc_msgSend(owner, object_for_key,
+                          objc_msgSend(string_class, with_utf8, "titleLabel"));
+  if (!label || objc_msgSend(root, view_with_tag, 2) != label ||
+      objc_msgSend(label, superview) != root)
+    return -4;
+  if (strcmp(objc_msgSend(objc_msgSend(label, sel_registerName("text")),
+                          utf8_string),
+             "Hello, nib!") ||
+      (int)objc_msgSend(label, sel_registerName("textAlignment")) != 1 ||
+      (int)objc_msgSend(label, sel_registerName("numberOfLines")) != 2)
+    return -5;
+  CGRect frame;
+  objc_msgSend_stret(&frame, label, sel_registerName("frame"));
+  if (frame.origin.x != 20 || frame.origin.y != 40 ||
+      frame.size.width != 280 || frame.size.height != 30)
+    return -6;
+
+  // The container's class isn't implemented, so it should be a plain UIView.
+  id container = objc_msgSend(root, view_with_tag, 3);
+  if (!container || objc_msgSend(container, superview) != root ||
+      !objc_msgSend(container, sel_registerName("isKindOfClass:"),
+                    NSClassFromString(
+                        objc_msgSend(string_class, with_utf8, "UIView"))))
+    return -7;
+
+  id button = objc_msgSend(owner, object_for_key,
+                           objc_msgSend(string_class, with_utf8, "playButton"));
+  if (!button || objc_msgSend(root, view_with_tag, 4) != button ||
+      objc_msgSend(button, superview) != container)
+    return -8;
+  id title = objc_msgSend(button, sel_registerName("titleForState:"), 0);
+  if (!title || strcmp(objc_msgSend(title, utf8_string), "Play"))
+    return -9;
+
+  // Missing nibs are reported with nil.
+  if (objc_msgSend(bundle, sel_registerName("loadNibNamed:owner:options:"),
+                   objc_msgSend(string_class, with_utf8, "NoSuchNib"), owner,
+                   NULL))
+    return -10;
+
+  objc_msgSend(owner, sel_registerName("release"));
+  return 0;
+}
+
+struct cf_notification_record {
+  int count;
+  CFNotificationCenterRef center;
+  CFStringRef name;
+  const void *object;
+  CFDictionaryRef user_info;
+};
+
+void cf_notification_callback(CFNotificationCenterRef center, void *observer,
+                              CFStringRef name, const void *object,
+                              CFDictionaryRef user_info) {
+  struct cf_notification_record *record = observer;
+  record->count++;
+  record->center = center;
+  record->name = name;
+  record->object = object;
+  record->user_info = user_info;
+}
+
+int test_cf_notification_center() {
+  id string_class = objc_msgSend(NSHomeDirectory(), sel_registerName("class"));
+  SEL with_utf8 = sel_registerName("stringWithUTF8String:");
+  SEL is_equal = sel_registerName("isEqual:");
+  SEL post = sel_registerName("postNotificationName:object:userInfo:");
+  id ns_center = objc_msgSend(
+      NSClassFromString(
+          objc_msgSend(string_class, with_utf8, "NSNotificationCenter")),
+      sel_registerName("defaultCenter"));
+  id name = objc_msgSend(string_class, with_utf8, "TestCFNotification");
+  id other_name = objc_msgSend(string_class, with_utf8, "OtherNotification");
+  id object = objc_msgSend(string_class, with_utf8, "poster");
+  id user_info = objc_msgSend(
+      NSClassFromString(objc_msgSend(string_class, with_utf8, "NSDictionary")),
+      sel_registerName("dictionaryWithObjectsAndKeys:"), object, name, NULL);
+  struct cf_notification_record record = {0};
+  struct cf_notification_record any_record = {0};
+  struct cf_notification_record darwin_record = {0};
+
+  // A CF observer receives notifications posted with the Obj-C API.
+  CFNotificationCenterRef center = CFNotificationCenterGetLocalCenter();
+  CFNotificationCenterAddObserver(
+      center, &record, cf_notification_callback, name, NULL,
+      CFNotificationSuspensionBehaviorDeliverImmediately);
+  objc_msgSend(ns_center, post, name, object, user_info);
+  if (record.count != 1 || record.center != center ||
+      !objc_msgSend((id)record.name, is_equal, name) ||
+      record.object != object ||
+      !objc_msgSend((id)record.user_info, is_equal, user_info))
+    return -1;
+  objc_msgSend(ns_center, post, other_name, object, user_info);
+  if (record.count != 1)
+    return -2;
+
+  // And ones posted with the CF API.
+  CFNotificationCenterPostNotification(center, name, NULL, NULL, 1);
+  if (record.count != 2 || record.object || record.user_info)
+    return -3;
+
+  // A NULL name observes everything, filtered by object.
+  CFNotificationCenterAddObserver(
+      center, &any_record, cf_notification_callback, NULL, object,
+      CFNotificationSuspensionBehaviorDeliverImmediately);
+  objc_msgSend(ns_center, post, other_name, object, NULL);
+  objc_msgSend(ns_center, post, other_name, NULL, NULL);
+  if (any_record.count != 1 ||
+      !objc_msgSend((id)any_record.name, is_equal, other_name))
+    return -4;
+
+  CFNotificationCenterRemoveObserver(center, &record, name, NULL);
+  CFNotificationCenterRemoveEveryObserver(center, &any_record);
+  objc_msgSend(ns_center, post, name, object, NULL);
+  if (record.count != 2 || any_record.count != 1)
+    return -5;
+
+  // The Darwin notify center is separate.
+  CFNotificationCenterRef darwin = CFNotificationCenterGetDarwinNotifyCenter();
+  if (!darwin || darwin == center)
+    return -6;
+  CFNotificationCenterAddObserver(
+      darwin, &darwin_record, cf_notification_callback, name, NULL,
+      CFNotificationSuspensionBehaviorDeliverImmediately);
+  CFNotificationCenterPostNotification(center, name, NULL, NULL, 1);
+  if (darwin_record.count != 0)
+    return -7;
+  CFNotificationCenterPostNotification(darwin, name, NULL, NULL, 1);
+  CFNotificationCenterRemoveEveryObserver(darwin, &darwin_record);
+  CFNotificationCenterPostNotification(darwin, name, NULL, NULL, 1);
+  if (darwin_record.count != 1 || darwin_record.center != darwin)
+    return -8;
+
+  return 0;
+}
+
+int test_NSValue_CGAffineTransform() {
+  id string_class = objc_msgSend(NSHomeDirectory(), sel_registerName("class"));
+  id value_class = NSClassFromString(objc_msgSend(
+      string_class, sel_registerName("stringWithUTF8String:"), "NSValue"));
+  CGAffineTransform t = {1.0, 2.0, 3.0, 4.0, 5.0, 6.0};
+  id value = ((id(*)(id, SEL, CGAffineTransform))objc_msgSend)(
+      value_class, sel_registerName("valueWithCGAffineTransform:"), t);
+  if (!value)
+    return -1;
+  // Struct return, so this needs a function pointer cast.
+  CGAffineTransform (*get_transform)(id, SEL) =
+      (CGAffineTransform(*)(id, SEL))objc_msgSend_stret;
+  CGAffineTransform result =
+      get_transform(value, sel_registerName("CGAffineTransformValue"));
+  if (!CGAffineTransformEqualToTransform(t, result))
+    return -2;
+  return 0;
+}
+
+int test_cg_rect_functions() {
+  // CGRectNull can't be linked in this test app, see CGAffineTransform.c.
+  CGRect null_rect = {{1.0 / 0.0, 1.0 / 0.0}, {0.0, 0.0}};
+  CGRect a = {{0.0, 0.0}, {10.0, 10.0}};
+  CGRect touching = {{10.0, 0.0}, {10.0, 10.0}};
+  CGRect disjoint = {{10.5, 0.0}, {1.0, 1.0}};
+  CGRect flipped = {{12.0, 12.0}, {-4.0, -4.0}};
+
+  if (!CGRectIsNull(null_rect) || CGRectIsNull(a) || CGRectIsEmpty(a))
+    return -1;
+  // Touching rects have an empty, but not null, intersection.
+  CGRect intersection = CGRectIntersection(a, touching);
+  if (!CGRectEqualToRect(intersection, (CGRect){{10.0, 0.0}, {0.0, 10.0}}) ||
+      CGRectIsNull(intersection) || !CGRectIsEmpty(intersection) ||
+      !CGRectIntersectsRect(a, touching))
+    return -2;
+  if (!CGRectIsNull(CGRectIntersection(a, disjoint)) ||
+      CGRectIntersectsRect(a, disjoint))
+    return -3;
+  // Negative sizes are standardized.
+  if (!CGRectEqualToRect(CGRectIntersection(a, flipped),
+                         (CGRect){{8.0, 8.0}, {2.0, 2.0}}) ||
+      CGRectGetMinX(flipped) != 8.0 || CGRectGetMaxX(flipped) != 12.0 ||
+      CGRectGetMidY(flipped) != 10.0 || CGRectGetWidth(flipped) != 4.0)
+    return -4;
+  // Union ignores the null rect.
+  if (!CGRectEqualToRect(CGRectUnion(null_rect, a), a) ||
+      !CGRectEqualToRect(CGRectUnion(a, disjoint),
+                         (CGRect){{0.0, 0.0}, {11.5, 10.0}}) ||
+      !CGRectContainsRect(CGRectUnion(a, flipped), flipped))
+    return -5;
+  if (!CGRectContainsPoint(a, (CGPoint){0.0, 9.5}) ||
+      CGRectContainsPoint(a, (CGPoint){10.0, 5.0}))
+    return -6;
+  if (!CGRectEqualToRect(CGRectInset(a, 1.0, 2.0),
+                         (CGRect){{1.0, 2.0}, {8.0, 6.0}}) ||
+      !CGRectIsNull(CGRectInset(a, 6.0, 0.0)) ||
+      !CGRectEqualToRect(CGRectOffset(flipped, 1.0, -1.0),
+                         (CGRect){{9.0, 7.0}, {4.0, 4.0}}) ||
+      !CGRectEqualToRect(CGRectIntegral((CGRect){{0.5, -0.5}, {1.0, 1.25}}),
+                         (CGRect){{0.0, -1.0}, {2.0, 2.0}}))
+    return -7;
+  return 0;
+}
+
+int test_strtol() {
+  char *end;
+  const char *str;
+
+  str = " -0x7fffffff";
+  if (strtol(str, &end, 0) != -0x7fffffff || end != str + 12)
+    return -1;
+  str = "123abc";
+  if (strtol(str, &end, 10) != 123 || end != str + 3)
+    return -2;
+  str = "zzz";
+  if (strtol(str, &end, 10) != 0 || end != str)
+    return -3;
+  if (strtol(str, &end, 36) != 46655 || end != str + 3)
+    return -4;
+  str = "0x";
+  if (strtol(str, &end, 16) != 0 || end != str + 1)
+    return -5;
+  if (strtol("-017", NULL, 0) != -017 || strtol("+42", NULL, 0) != 42)
+    return -6;
+  errno = 0;
+  if (strtol("-2147483648", NULL, 10) != (-2147483647 - 1) || errno != 0)
+    return -7;
+  str = "2147483648";
+  if (strtol(str, &end, 10) != 2147483647 || errno != ERANGE ||
+      end != str + 10)
+    return -8;
+  errno = 0;
+  if (strtol("-2147483649", NULL, 10) != (-2147483647 - 1) || errno != ERANGE)
+    return -9;
+  errno = 0;
+  if (strtol("99999999999999999999", NULL, 10) != 2147483647 ||
+      errno != ERANGE)
+    return -10;
+  return 0;
+}
+
+int test_string_compare() {
+  id string_class = objc_msgSend(NSHomeDirectory(), sel_registerName("class"));
+  SEL with_utf8 = sel_registerName("stringWithUTF8String:");
+  SEL compare_options = sel_registerName("compare:options:");
+  struct {
+    const char *a;
+    const char *b;
+    NSUInteger options;
+    long expected;
+  } cases[] = {
+      {"a9", "a10", 0, 1},
+      {"a9", "A9", 0, 1},
+      {"A9", "a10", 0, -1},
+      {"a9", "a10", NSNumericSearch, -1},
+      {"a9", "A9", NSNumericSearch, 1},
+      {"A9", "a10", NSNumericSearch, -1},
+      {"a9", "a10", NSCaseInsensitiveSearch, 1},
+      {"a9", "A9", NSCaseInsensitiveSearch, 0},
+      {"A9", "a10", NSCaseInsensitiveSearch, 1},
+      {"a9", "a10", NSNumericSearch | NSCaseInsensitiveSearch, -1},
+      {"a9", "A9", NSNumericSearch | NSCaseInsensitiveSearch, 0},
+      {"A9", "a10", NSNumericSearch | NSCaseInsensitiveSearch, -1},
+      {"a9", "A9", NSCaseInsensitiveSearch | NSForcedOrderingSearch, 1},
+      {"a09", "a9", NSNumericSearch | NSLiteralSearch, -1},
+      {"r\xC3\xA9sum\xC3\xA9", "resume", NSDiacriticInsensitiveSearch, 0},
+      {"\xEF\xBC\xA1", "A", NSWidthInsensitiveSearch, 0},
+  };
+  for (unsigned i = 0; i < sizeof(cases) / sizeof(cases[0]); i++) {
+    id a = objc_msgSend(string_class, with_utf8, cases[i].a);
+    id b = objc_msgSend(string_class, with_utf8, cases[i].b);
+    long result = (long)objc_msgSend(a, compare_options, b, cases[i].options);
+    if (result != cases[i].expected)
+      return -1 - i;
+  }
+
+  id a9 = objc_msgSend(string_class, with_utf8, "a9");
+  id a10 = objc_msgSend(string_class, with_utf8, "a10");
+  id upper_a9 = objc_msgSend(string_class, with_utf8, "A9");
+  // Only the given range of the receiver is compared.
+  id nine = objc_msgSend(string_class, with_utf8, "9");
+  NSRange range = {1, 2};
+  if ((long)objc_msgSend(a10, sel_registerName("compare:options:range:"), nine,
+                         (NSUInteger)NSNumericSearch, range) != 1)
+    return -20;
+  if ((long)objc_msgSend(a9, sel_registerName("caseInsensitiveCompare:"),
+                         upper_a9) != 0)
+    return -21;
+  if ((long)objc_msgSend(
+          a9, sel_registerName("localizedCaseInsensitiveCompare:"), upper_a9) !=
+      0)
+    return -22;
+
+  // CoreFoundation comparisons are literal by default.
+  if (CFStringCompare(a9, a10, kCFCompareNumerically) != -1)
+    return -23;
+  if (CFStringCompare(a9, upper_a9, kCFCompareCaseInsensitive) != 0)
+    return -24;
+  if (CFStringCompare(a9, upper_a9, kCFCompareNonliteral) != 1)
+    return -25;
+  return 0;
+}
+
+int test_large_collections() {
+  id string_class = objc_msgSend(NSHomeDirectory(), sel_registerName("class"));
+  SEL with_utf8 = sel_registerName("stringWithUTF8String:");
+  id number_class =
+      NSClassFromString(objc_msgSend(string_class, with_utf8, "NSNumber"));
+  id array_class = NSClassFromString(
+      objc_msgSend(string_class, with_utf8, "NSMutableArray"));
+  id dict_class = NSClassFromString(
+      objc_msgSend(string_class, with_utf8, "NSMutableDictionary"));
+  SEL alloc = sel_registerName("alloc");
+  SEL init = sel_registerName("init");
+  SEL release = sel_registerName("release");
+  SEL count = sel_registerName("count");
+  SEL hash = sel_registerName("hash");
+  SEL is_equal = sel_registerName("isEqual:");
+  SEL number_with_int = sel_registerName("numberWithInt:");
+  SEL with_double = sel_registerName("numberWithDouble:");
+  SEL init_with_int = sel_registerName("initWithInt:");
+  SEL add_object = sel_registerName("addObject:");
+  SEL object_at_index = sel_registerName("objectAtIndex:");
+  SEL set_object_for_key = sel_registerName("setObject:forKey:");
+  SEL object_for_key = sel_registerName("objectForKey:");
+  id (*number_with_double)(id, SEL, double) =
+      (id(*)(id, SEL, double))objc_msgSend;
+  const int n = 100000;
+
+  // Small integers are shared, so boxing them doesn't allocate.
+  if (objc_msgSend(number_class, number_with_int, 7) !=
+          objc_msgSend(number_class, number_with_int, 7) ||
+      objc_msgSend(number_class, number_with_int, -1) !=
+          objc_msgSend(number_class, number_with_int, -1))
+    return -1;
+  // Numbers are equal if their values are, whatever their types.
+  id one = objc_msgSend(number_class, number_with_int, 1);
+  id one_double = number_with_double(number_class, with_double, 1.0);
+  id one_and_a_half = number_with_double(number_class, with_double, 1.5);
+  if (!objc_msgSend(one, is_equal, one_double) ||
+      objc_msgSend(one, hash) != objc_msgSend(one_double, hash) ||
+      objc_msgSend(one, is_equal, one_and_a_half))
+    return -2;
+
+  SEL init_with_capacity = sel_registerName("initWithCapacity:");
+  id array = objc_msgSend(objc_msgSend(array_class, alloc), init_with_capacity,
+                          (NSUInteger)n);
+  id dict = objc_msgSend(objc_msgSend(dict_class, alloc), init_with_capacity,
+                         (NSUInteger)n);
+  // Containers created without a capacity must behave the same.
+  id slow_array = objc_msgSend(objc_msgSend(array_class, alloc), init);
+  id slow_dict = objc_msgSend(objc_msgSend(dict_class, alloc), init);
+  for (int i = 0; i < n; i++) {
+    id key = objc_msgSend(objc_msgSend(number_class, alloc), init_with_int, i);
+    id value = objc_msgSend(number_class, number_with_int, i % 256);
+    objc_msgSend(array, add_object, value);
+    objc_msgSend(dict, set_object_for_key, value, key);
+    if (i < 1000) {
+      objc_msgSend(slow_array, add_object, value);
+      objc_msgSend(slow_dict, set_object_for_key, value, key);
+    }
+    objc_msgSend(key, release);
+  }
+  if ((NSUInteger)objc_msgSend(array, count) != (NSUInteger)n ||
+      (NSUInteger)objc_msgSend(dict, count) != (NSUInteger)n ||
+      (NSUInteger)objc_msgSend(slow_dict, count) != 1000)
+    return -3;
+
+  int res = 0;
+  for (int i = 0; i < n; i += 997) {
+    id expected = objc_msgSend(number_class, number_with_int, i % 256);
+    // Keys of a different type with an equal value find the same entry.
+    id key = number_with_double(number_class, with_double, i);
+    if (objc_msgSend(array, object_at_index, i) != expected ||
+        objc_msgSend(dict, object_for_key, key) != expected) {
+      res = -4;
+      break;
+    }
+    if (i < 1000 &&
+        (objc_msgSend(slow_array, object_at_index, i) != expected ||
+         objc_msgSend(slow_dict, object_for_key, key) != expected)) {
+      res = -5;
+      break;
+    }
+  }
+  if (objc_msgSend(dict, object_for_key,
+                   objc_msgSend(number_class, number_with_int, -5)))
+    res = -6;
+
+  objc_msgSend(array, release);
+  objc_msgSend(dict, release);
+  objc_msgSend(slow_array, release);
+  objc_msgSend(slow_dict, release);
+  return res;
+}
+
+SEL sort_test_int_value;
+
+// Compares NSNumbers by their value divided by 16, so there are lots of ties.
+long compare_sixteenths(id a, id b, void *context) {
+  (*(int *)context)++;
+  int x = (int)objc_msgSend(a, sort_test_int_value) / 16;
+  int y = (int)objc_msgSend(b, sort_test_int_value) / 16;
+  return x < y ? -1 : x > y;
+}
+
+int test_array_sorting() {
+  id string_class = objc_msgSend(NSHomeDirectory(), sel_registerName("class"));
+  SEL with_utf8 = sel_registerName("stringWithUTF8String:");
+  id number_class =
+      NSClassFromString(objc_msgSend(string_class, with_utf8, "NSNumber"));
+  id array_class = NSClassFromString(
+      objc_msgSend(string_class, with_utf8, "NSMutableArray"));
+  SEL alloc = sel_registerName("alloc");
+  SEL init_with_int = sel_registerName("initWithInt:");
+  SEL release = sel_registerName("release");
+  SEL object_at_index = sel_registerName("objectAtIndex:");
+  SEL sort_using_function = sel_registerName("sortUsingFunction:context:");
+  sort_test_int_value = sel_registerName("intValue");
+  const int n = 10000;
+  int res = 0;
+
+  // The numbers are a permutation of 0 to n - 1, so where each one started out
+  // can be worked out from its value.
+  id array =
+      objc_msgSend(objc_msgSend(array_class, alloc), sel_registerName("init"));
+  int *original_index = malloc(n * sizeof(int));
+  for (int i = 0; i < n; i++) {
+    int value = i * 7919 % n;
+    original_index[value] = i;
+    id number =
+        objc_msgSend(objc_msgSend(number_class, alloc), init_with_int, value);
+    objc_msgSend(array, sel_registerName("addObject:"), number);
+    objc_msgSend(number, release);
+  }
+
+  // Sorting in place doesn't allocate guest memory, and takes at most
+  // n * log2(n) comparisons.
+  unsigned int before, after;
+  int comparisons = 0;
+  if (resident_size(&before) != 0)
+    return -1;
+  objc_msgSend(array, sort_using_function, compare_sixteenths, &comparisons);
+  if (resident_size(&after) != 0)
+    return -1;
+  if (after != before)
+    res = -2;
+  if (comparisons < n - 1 || comparisons > n * 14)
+    res = res ? res : -3;
+  // The sort is stable.
+  int last_key = -1, last_index = -1;
+  for (int i = 0; i < n; i++) {
+    int value = (int)objc_msgSend(objc_msgSend(array, object_at_index, i),
+                                  sort_test_int_value);
+    int key = value / 16, index = original_index[value];
+    if (key < last_key || (key == last_key && index < last_index)) {
+      res = res ? res : -4;
+      break;
+    }
+    last_key = key;
+    last_index = index;
+  }
+  // Sorting an already-sorted array only compares neighbours.
+  comparisons = 0;
+  objc_msgSend(array, sort_using_function, compare_sixteenths, &comparisons);
+  if (comparisons != n - 1)
+    res = res ? res : -5;
+  objc_msgSend(array, release);
+  free(original_index);
+
+  // Sorting with a selector, in place or into a new array.
+  SEL is_equal = sel_registerName("isEqualToString:");
+  SEL compare = sel_registerName("compare:");
+  id fruits = objc_msgSend(
+      array_class, sel_registerName("arrayWithObjects:"),
+      objc_msgSend(string_class, with_utf8, "pear"),
+      objc_msgSend(string_class, with_utf8, "apple"),
+      objc_msgSend(string_class, with_utf8, "fig"), NULL);
+  id sorted = objc_msgSend(
+      fruits, sel_registerName("sortedArrayUsingSelector:"), compare);
+  if (!objc_msgSend(objc_msgSend(sorted, object_at_index, 0), is_equal,
+                    objc_msgSend(string_class, with_utf8, "apple")) ||
+      !objc_msgSend(objc_msgSend(fruits, object_at_index, 0), is_equal,
+                    objc_msgSend(string_class, with_utf8, "pear")))
+    res = res ? res : -6;
+  objc_msgSend(fruits, sel_registerName("sortUsingSelector:"), compare);
+  if (!objc_msgSend(objc_msgSend(fruits, object_at_index, 2), is_equal,
+                    objc_msgSend(string_class, with_utf8, "pear")))
+    res = res ? res : -7;
+
+  return res;
+}
+
+int test_strtoul() {
+  char *end;
+  const char *str;
+
+  // Successive tokens can be scanned using the end pointer.
+  str = "10 017 0x1f 0b";
+  unsigned long values[4];
+  for (int i = 0; i < 4; i++) {
+    values[i] = strtoul(str, &end, 0);
+    if (end == str)
+      return -1;
+    str = end;
+  }
+  if (values[0] != 10 || values[1] != 017 || values[2] != 0x1f ||
+      values[3] != 0 || strcmp(end, "b"))
+    return -2;
+
+  str = "777";
+  if (strtoul(str, &end, 8) != 0777 || end != str + 3)
+    return -3;
+  str = "1012";
+  if (strtoul(str, &end, 2) != 5 || end != str + 3)
+    return -4;
+  str = "Zz!";
+  if (strtoul(str, &end, 36) != 35 * 36 + 35 || end != str + 2)
+    return -5;
+  str = "8";
+  if (strtoul(str, &end, 8) != 0 || end != str)
+    return -6;
+  // The prefix only counts if a hex digit follows it.
+  str = "0xg";
+  if (strtoul(str, &end, 0) != 0 || end != str + 1)
+    return -7;
+  errno = 0;
+  if (strtoul("12", NULL, 1) != 0 || errno != EINVAL)
+    return -8;
+  errno = 0;
+  str = "777777777777";
+  if (strtoul(str, &end, 8) != 0xffffffff || errno != ERANGE ||
+      end != str + 12)
+    return -9;
+  return 0;
+}
+
+int test_strtoll() {
+  char *end;
+  const char *str;
+
+  str = " 9876543210 rest";
+  if (strtoll(str, &end, 10) != 9876543210LL || end != str + 11)
+    return -1;
+  str = "-0x123456789a";
+  if (strtoll(str, &end, 0) != -0x123456789aLL || end != str + 13)
+    return -2;
+  errno = 0;
+  str = "9223372036854775808";
+  if (strtoll(str, &end, 10) != 0x7fffffffffffffffLL || errno != ERANGE ||
+      end != str + 19)
+    return -3;
+  errno = 0;
+  str = "-9223372036854775809";
+  if (strtoll(str, &end, 10) != -0x7fffffffffffffffLL - 1 ||
+      errno != ERANGE || end != str + 20)
+    return -4;
+  return 0;
+}
+
+int test_strtoull() {
+  char *end;
+  const char *str;
+
+  str = "18446744073709551615x";
+  if (strtoull(str, &end, 10) != 0xffffffffffffffffULL || end != str + 20)
+    return -1;
+  str = "0x100000000";
+  if (strtoull(str, &end, 16) != 0x100000000ULL || end != str + 11)
+    return -2;
+  // Negative values wrap around, like for strtoul().
+  if (strtoull("-1", NULL, 10) != 0xffffffffffffffffULL)
+    return -3;
+  errno = 0;
+  str = "18446744073709551616";
+  if (strtoull(str, &end, 10) != 0xffffffffffffffffULL || errno != ERANGE ||
+      end != str + 20)
+    return -4;
+  return 0;
+}
+
+int test_atoi() {
+  if (atoi(" \t-123abc") != -123 || atoi("+42") != 42 || atoi("x1") != 0)
+    return -1;
+  if (atol("2147483647") != 2147483647L || atol("-2147483648") != -2147483648L)
+    return -2;
+  // Millisecond timestamps don't fit in 32 bits.
+  if (atoll("1262304000000") != 1262304000000LL ||
+      atoll(" -1262304000000 ms") != -1262304000000LL)
+    return -3;
+  // 2^53, the largest integer a double can hold exactly.
+  if (atoll("9007199254740992") != 9007199254740992LL)
+    return -4;
+  if (atoll("-") != 0 || atoll("") != 0)
+    return -5;
+  return 0;
+}
+
+int test_abs() {
+  if (abs(-5) != 5 || abs(5) != 5 || labs(-2147483647L) != 2147483647L)
+    return -1;
+  if (llabs(-1262304000000LL) != 1262304000000LL || llabs(0) != 0)
+    return -2;
+  // Undefined in C, but shouldn't crash. Apple's version returns it unchanged.
+  int int_min = -2147483647 - 1;
+  long long llong_min = -9223372036854775807LL - 1;
+  if (abs(int_min) != int_min || llabs(llong_min) != llong_min)
+    return -3;
+  return 0;
+}
+
+int test_div() {
+  // Division truncates toward zero, so the remainder has the numerator's sign.
+  div_t d = div(-7, 2);
+  if (d.quot != -3 || d.rem != -1)
+    return -1;
+  d = div(7, -2);
+  if (d.quot != -3 || d.rem != 1)
+    return -2;
+  ldiv_t ld = ldiv(3600L * 25 + 61, 3600L);
+  if (ld.quot != 25 || ld.rem != 61)
+    return -3;
+  lldiv_t lld = lldiv(-1262304000123LL, 1000LL);
+  if (lld.quot != -1262304000LL || lld.rem != -123LL)
+    return -4;
+  return 0;
+}
+
+int test_rand_r() {
+  // Each sequence on its own
+  int expected_rand[3], expected_rand_r[3];
+  srand(42);
+  unsigned seed = 7;
+  for (int i = 0; i < 3; i++)
+    expected_rand[i] = rand();
+  for (int i = 0; i < 3; i++)
+    expected_rand_r[i] = rand_r(&seed);
+  if (seed == 7)
+    return -1;
+
+  // Interleaving them must give the same sequences
+  srand(42);
+  seed = 7;
+  unsigned other_seed = 7;
+  for (int i = 0; i < 3; i++) {
+    if (rand_r(&seed) != expected_rand_r[i])
+      return -2;
+    if (rand() != expected_rand[i])
+      return -3;
+    if (rand_r(&other_seed) != expected_rand_r[i])
+      return -4;
+  }
+  return seed == other_seed ? 0 : -5;
+}
+
+// Check the results of a getopt() or getopt_long() call.
+int getopt_step(int ret, int expected_ret, int expected_optind,
+                const char *expected_optarg) {
+  if (ret != expected_ret || optind != expected_optind || opterr != 0)
+    return 1;
+  if (expected_optarg == NULL)
+    return optarg != NULL;
+  return optarg == NULL || strcmp(optarg, expected_optarg) != 0;
+}
+
+int test_getopt() {
+  int res = 0;
+  opterr = 0;
+
+  // Bundling, attached and separate arguments, optional arguments
+  char *argv1[] = {"prog", "-ab", "-c", "value", "-dopt",
+                   "-d", "-e", "file", "-a", NULL};
+  const char *opts1 = "abc:d::e";
+  optind = 1;
+  res += getopt_step(getopt(9, argv1, opts1), 'a', 1, NULL);
+  res += getopt_step(getopt(9, argv1, opts1), 'b', 2, NULL);
+  res += getopt_step(getopt(9, argv1, opts1), 'c', 4, "value");
+  res += getopt_step(getopt(9, argv1, opts1), 'd', 5, "opt");
+  res += getopt_step(getopt(9, argv1, opts1), 'd', 6, NULL);
+  res += getopt_step(getopt(9, argv1, opts1), 'e', 7, NULL);
+  // No permutation: parsing stops at the first non-option.
+  res += getopt_step(getopt(9, argv1, opts1), -1, 7, NULL);
+  if (res)
+    return -1;
+
+  // Errors in silent mode, after resetting optind
+  char *argv2[] = {"prog", "-xa", "-c", NULL};
+  optind = 1;
+  res += getopt_step(getopt(3, argv2, ":ac:"), '?', 1, NULL) || optopt != 'x';
+  res += getopt_step(getopt(3, argv2, ":ac:"), 'a', 2, NULL);
+  res += getopt_step(getopt(3, argv2, ":ac:"), ':', 3, NULL) || optopt != 'c';
+  res += getopt_step(getopt(3, argv2, ":ac:"), -1, 3, NULL);
+  // Without the leading ':', a missing argument is reported with '?'.
+  optind = 1;
+  res += getopt_step(getopt(3, argv2, "ac:"), '?', 1, NULL) || optopt != 'x';
+  res += getopt_step(getopt(3, argv2, "ac:"), 'a', 2, NULL);
+  res += getopt_step(getopt(3, argv2, "ac:"), '?', 3, NULL) || optopt != 'c';
+  if (res)
+    return -2;
+
+  // "--" ends the options
+  char *argv3[] = {"prog", "-a", "--", "-b", NULL};
+  optind = 1;
+  res += getopt_step(getopt(4, argv3, "ab"), 'a', 2, NULL);
+  res += getopt_step(getopt(4, argv3, "ab"), -1, 3, NULL);
+  if (res)
+    return -3;
+
+  int flag = 0, index = -1;
+  struct option longopts[] = {
+      {"verbose", no_argument, &flag, 1},
+      {"output", required_argument, NULL, 'o'},
+      {"level", optional_argument, NULL, 'l'},
+      {NULL, 0, NULL, 0},
+  };
+  char *argv4[] = {"prog",    "--verbose", "--output=a.txt", "--output",
+                   "b.txt",   "--level",   "--level=3",      "-o",
+                   "c.txt",   "--verb",    "--bogus",        "--verbose=1",
+                   "--output"};
+  // The app this is for parses its arguments twice.
+  for (int i = 0; i < 2; i++) {
+    optind = 1;
+    flag = 0;
+    int ret = getopt_long(13, argv4, "o:", longopts, &index);
+    res += getopt_step(ret, 0, 2, NULL) || flag != 1 || index != 0;
+    ret = getopt_long(13, argv4, "o:", longopts, &index);
+    res += getopt_step(ret, 'o', 3, "a.txt") || index != 1;
+    ret = getopt_long(13, argv4, "o:", longopts, &index);
+    res += getopt_step(ret, 'o', 5, "b.txt") || index != 1;
+    ret = getopt_long(13, argv4, "o:", longopts, &index);
+    res += getopt_step(ret, 'l', 6, NULL) || index != 2;
+    ret = getopt_long(13, argv4, "o:", longopts, &index);
+    res += getopt_step(ret, 'l', 7, "3") || index != 2;
+    ret = getopt_long(13, argv4, "o:", longopts, &index);
+    res += getopt_step(ret, 'o', 9, "c.txt");
+    // Unambiguous prefixes are accepted.
+    flag = 0;
+    ret = getopt_long(13, argv4, "o:", longopts, &index);
+    res += getopt_step(ret, 0, 10, NULL) || flag != 1 || index != 0;
+    ret = getopt_long(13, argv4, "o:", longopts, &index);
+    res += getopt_step(ret, '?', 11, NULL);
+    ret = getopt_long(13, argv4, "o:", longopts, &index);
+    res += getopt_step(ret, '?', 12, NULL) || optopt != 1;
+    ret = getopt_long(13, argv4, ":o:", longopts, &index);
+    res += getopt_step(ret, ':', 13, NULL) || optopt != 'o';
+    ret = getopt_long(13, argv4, "o:", longopts, &index);
+    res += getopt_step(ret, -1, 13, NULL);
+    if (res)
+      return -4 - i;
+  }
+
+  optind = 1;
+  opterr = 1;
+  return 0;
+}
+
+int test_calloc() {
+  unsigned char *dirty = malloc(256);
+  memset(dirty, 0xab, 256);
+  free(dirty);
+  unsigned char *clean = calloc(64, 4);
+  if (!clean)
+    return -1;
+  for (int i = 0; i < 256; i++) {
+    if (clean[i])
+      return -2;
+  }
+  free(clean);
+
+  errno = 0;
+  if (calloc(0x10000, 0x10000) != NULL || errno != ENOMEM)
+    return -3;
+  return 0;
+}
+
+int test_vm_allocate() {
+  vm_size_t page_size = 0;
+  if (host_page_size(mach_host_self(), &page_size) != KERN_SUCCESS ||
+      page_size != 4096 || vm_page_size != 4096)
+    return -1;
+
+  vm_address_t addr = 0;
+  if (vm_allocate(mach_task_self(), &addr, 3 * 4096 - 100,
+                  VM_FLAGS_ANYWHERE) != KERN_SUCCESS)
+    return -2;
+  if (addr == 0 || addr % 4096 != 0)
+    return -3;
+  unsigned char *pages = (unsigned char *)addr;
+  for (int i = 0; i < 3 * 4096; i++) {
+    if (pages[i])
+      return -4;
+  }
+  memset(pages, 0xab, 3 * 4096);
+
+  // Free just the middle page. The address and size get rounded to pages.
+  if (vm_deallocate(mach_task_self(), addr + 4096 + 16, 100) != KERN_SUCCESS)
+    return -5;
+  if (pages[4095] != 0xab || pages[2 * 4096] != 0xab)
+    return -6;
+
+  // The hole can be reallocated at a fixed address, and is zeroed again, but
+  // the pages around it are still in use.
+  vm_address_t fixed = addr + 4096;
+  if (vm_allocate(mach_task_self(), &fixed, 4096, VM_FLAGS_FIXED) !=
+          KERN_SUCCESS ||
+      fixed != addr + 4096 || pages[4096] != 0 || pages[2 * 4096 - 1] != 0)
+    return -7;
+  fixed = addr;
+  if (vm_allocate(mach_task_self(), &fixed, 4096, VM_FLAGS_FIXED) !=
+      KERN_NO_SPACE)
+    return -8;
+
+  if (vm_protect(mach_task_self(), addr, 4096, 0,
+                 VM_PROT_READ | VM_PROT_WRITE) != KERN_SUCCESS ||
+      vm_protect(mach_task_self(), addr, 4096, 0, 0x100) !=
+          KERN_INVALID_ARGUMENT)
+    return -9;
+
+  // Free the whole range at once even though it's now several allocations.
+  if (vm_deallocate(mach_task_self(), addr, 3 * 4096) != KERN_SUCCESS)
+    return -10;
+  fixed = addr;
+  if (vm_allocate(mach_task_self(), &fixed, 3 * 4096, VM_FLAGS_FIXED) !=
+          KERN_SUCCESS ||
+      pages[0] != 0 || pages[3 * 4096 - 1] != 0)
+    return -11;
+  if (vm_deallocate(mach_task_self(), addr, 3 * 4096) != KERN_SUCCESS)
+    return -12;
+
+  // Memory that wasn't allocated can't be deallocated.
+  int on_stack = 0;
+  if (vm_deallocate(mach_task_self(), 0, 4096) != KERN_INVALID_ADDRESS ||
+      vm_deallocate(mach_task_self(), (vm_address_t)&on_stack, 4) !=
+          KERN_INVALID_ADDRESS ||
+      vm_deallocate(mach_task_self(), addr, 4096) != KERN_INVALID_ADDRESS)
+    return -13;
+  return 0;
+}
+
+void *increment_atomically(void *counter) {
+  for (int i = 0; i < 10000; i++) {
+    OSAtomicIncrement32Barrier(counter);
+  }
+  return NULL;
+}
+
+int test_osatomic() {
+  // The threads can be switched between at any point, so only atomic
+  // increments give the right total.
+  volatile int counter = 0;
+  pthread_t threads[4];
+  for (int i = 0; i < 4; i++) {
+    if (pthread_create(&threads[i], NULL, increment_atomically,
+                       (void *)&counter))
+      return -1;
+  }
+  for (int i = 0; i < 4; i++) {
+    pthread_join(threads[i], NULL);
+  }
+  OSMemoryBarrier();
+  if (counter != 40000)
+    return -2;
+  if (OSAtomicAdd32(-40005, &counter) != -5 ||
+      OSAtomicDecrement32(&counter) != -6)
+    return -3;
+  volatile long long big = 0x7fffffffLL;
+  if (OSAtomicAdd64(2, &big) != 0x80000001LL)
+    return -4;
+
+  volatile int value = 1;
+  if (OSAtomicCompareAndSwap32(2, 3, &value) || value != 1)
+    return -5;
+  if (!OSAtomicCompareAndSwap32Barrier(1, 3, &value) || value != 3)
+    return -6;
+  int a, b;
+  void *volatile ptr = &a;
+  if (OSAtomicCompareAndSwapPtr(&b, NULL, &ptr) || ptr != &a ||
+      !OSAtomicCompareAndSwapPtr(&a, &b, &ptr) || ptr != &b)
+    return -7;
+
+  // Bits are numbered from the most significant bit of the first byte, and
+  // the previous value of the bit is returned.
+  volatile unsigned int flags = 0;
+  if (OSAtomicTestAndSet(0, &flags) || !OSAtomicTestAndSet(0, &flags) ||
+      OSAtomicTestAndSet(9, &flags) || flags != 0x4080)
+    return -8;
+  if (!OSAtomicTestAndClear(9, &flags) || OSAtomicTestAndClear(9, &flags) ||
+      flags != 0x80)
+    return -9;
+  if (OSAtomicOr32Orig(0x1, &flags) != 0x80 || flags != 0x81)
+    return -10;
+
+  // The link pointer is stored at the given offset in each element.
+  struct element {
+    int data;
+    struct element *link;
+  } elements[3] = {{1, NULL}, {2, NULL}, {3, NULL}};
+  OSQueueHead queue = OS_ATOMIC_QUEUE_INIT;
+  size_t offset = (char *)&elements[0].link - (char *)&elements[0];
+  for (int i = 0; i < 3; i++) {
+    OSAtomicEnqueue(&queue, &elements[i], offset);
+  }
+  if (elements[1].link != &elements[0])
+    return -11;
+  for (int i = 2; i >= 0; i--) {
+    struct element *e = OSAtomicDequeue(&queue, offset);
+    if (e != &elements[i])
+      return -12;
+  }
+  if (OSAtomicDequeue(&queue, offset) != NULL)
+    return -13;
+  return 0;
+}
+
+// Queues messages to the main thread to append numbers to an array. Expects
+// {array, NSNumber class, NSArray of modes}.
+void *queue_main_thread_performs(void *arg) {
+  id *objects = arg;
+  SEL add = sel_registerName("addObject:");
+  SEL perform =
+      sel_registerName("performSelectorOnMainThread:withObject:waitUntilDone:");
+  SEL perform_with_modes = sel_registerName(
+      "performSelectorOnMainThread:withObject:waitUntilDone:modes:");
+  for (int i = 0; i <= 1000; i++) {
+    id number = objc_msgSend(objects[1], sel_registerName("alloc"));
+    number = objc_msgSend(number, sel_registerName("initWithInt:"), 1024 + i);
+    // The last one waits, so the main thread must keep running the run loop.
+    BOOL wait = i == 1000;
+    if (i % 2)
+      objc_msgSend(objects[0], perform, add, number, wait);
+    else
+      objc_msgSend(objects[0], perform_with_modes, add, number, wait,
+                   objects[2]);
+    // The queued message should keep it alive.
+    objc_msgSend(number, sel_registerName("release"));
+  }
+  return NULL;
+}
+
+int test_perform_on_main_thread() {
+  id string_class = objc_msgSend(NSHomeDirectory(), sel_registerName("class"));
+  SEL with_utf8 = sel_registerName("stringWithUTF8String:");
+  SEL count = sel_registerName("count");
+  SEL object_at_index = sel_registerName("objectAtIndex:");
+  SEL retain_count = sel_registerName("retainCount");
+  id array_class = NSClassFromString(
+      objc_msgSend(string_class, with_utf8, "NSMutableArray"));
+  id number_class =
+      NSClassFromString(objc_msgSend(string_class, with_utf8, "NSNumber"));
+  id array = objc_msgSend(array_class, sel_registerName("new"));
+  id modes = objc_msgSend(NSClassFromString(objc_msgSend(
+                              string_class, with_utf8, "NSArray")),
+                          sel_registerName("arrayWithObjects:"),
+                          kCFRunLoopDefaultMode, NULL);
+  id objects[3] = {array, number_class, modes};
+
+  pthread_t thread;
+  if (pthread_create(&thread, NULL, queue_main_thread_performs, objects))
+    return -1;
+  while ((NSUInteger)objc_msgSend(array, count) < 1001) {
+    CFRunLoopRunInMode(kCFRunLoopDefaultMode, 0.01, 0);
+  }
+  pthread_join(thread, NULL);
+
+  // The messages are sent in the order they were queued, and nothing queued
+  // is leaked.
+  for (int i = 0; i <= 1000; i++) {
+    id number = objc_msgSend(array, object_at_index, (NSUInteger)i);
+    if ((int)objc_msgSend(number, sel_registerName("intValue")) != 1024 + i)
+      return -2;
+    if ((NSUInteger)objc_msgSend(number, retain_count) != 1)
+      return -3;
+  }
+  if ((NSUInteger)objc_msgSend(array, retain_count) != 1)
+    return -4;
+
+  // Waiting on the main thread sends the message immediately.
+  objc_msgSend(array,
+               sel_registerName(
+                   "performSelectorOnMainThread:withObject:waitUntilDone:"),
+               sel_registerName("removeLastObject"), NULL, (BOOL)1);
+  if ((NSUInteger)objc_msgSend(array, count) != 1000)
+    return -5;
+  objc_msgSend(array, sel_registerName("release"));
+  return 0;
+}
+
+int test_invalid_pointers() {
+  // These are all app bugs, which touchHLE logs and ignores rather than
+  // crashing. integration.rs checks for the log messages.
+  int on_stack = 0;
+  char *heap = malloc(64);
+  free(&on_stack);
+  free(heap + 16);
+  // The allocation survives.
+  heap[63] = 'a';
+  if (heap[63] != 'a')
+    return -1;
+  char *new = realloc(heap + 16, 32);
+  if (!new || new == heap + 16)
+    return -2;
+  new[31] = 'b';
+  free(new);
+  free(heap);
+
+  // Releasing an object that was already deallocated.
+  id string_class = objc_msgSend(NSHomeDirectory(), sel_registerName("class"));
+  id object_class = NSClassFromString(objc_msgSend(
+      string_class, sel_registerName("stringWithUTF8String:"), "NSObject"));
+  id object = objc_msgSend(object_class, sel_registerName("new"));
+  SEL release = sel_registerName("release");
+  objc_msgSend(object, release);
+  objc_msgSend(object, release);
+  return 0;
+}
+
+void *report_stack_size(void *size) {
+  *(size_t *)size = pthread_get_stacksize_np(pthread_self());
+  return NULL;
+}
+
+int test_thread_stack_size() {
+  pthread_attr_t attr;
+  size_t size;
+  if (pthread_attr_init(&attr) || pthread_attr_getstacksize(&attr, &size) ||
+      size != 512 * 1024)
+    return -1;
+  // Too small, or not a whole number of pages.
+  if (pthread_attr_setstacksize(&attr, 4096) != EINVAL ||
+      pthread_attr_setstacksize(&attr, 64 * 1024 + 1) != EINVAL)
+    return -2;
+  if (pthread_attr_setstacksize(&attr, 64 * 1024) ||
+      pthread_attr_getstacksize(&attr, &size) || size != 64 * 1024)
+    return -3;
+
+  pthread_t thread;
+  size_t thread_size = 0;
+  if (pthread_create(&thread, &attr, report_stack_size, &thread_size) ||
+      pthread_join(thread, NULL) || thread_size != 64 * 1024)
+    return -4;
+  pthread_attr_destroy(&attr);
+
+  if (pthread_get_stacksize_np(pthread_self()) != 1024 * 1024)
+    return -5;
+  return 0;
+}
+
+int test_fd_limit() {
+  struct rlimit limit;
+  if (getrlimit(RLIMIT_NOFILE, &limit) != 0 || limit.rlim_cur != 256 ||
+      getdtablesize() != 256)
+    return -1;
+  // The soft limit can't be above the hard limit.
+  struct rlimit invalid = {limit.rlim_max, limit.rlim_max - 1};
+  errno = 0;
+  if (setrlimit(RLIMIT_NOFILE, &invalid) != -1 || errno != EINVAL)
+    return -2;
+  // Lower the limit so that it can be reached quickly.
+  struct rlimit lowered = {32, limit.rlim_max};
+  if (setrlimit(RLIMIT_NOFILE, &lowered) != 0 || getdtablesize() != 32)
+    return -3;
+
+  int res = 0;
+  int fds[32];
+  int count = 0;
+  errno = 0;
+  while (count < 32) {
+    int fd = open(".", O_RDONLY);
+    if (fd == -1)
+      break;
+    fds[count++] = fd;
+  }
+  // Descriptors are allocated lowest first, up to the limit.
+  if (count < 4 || errno != EMFILE || fds[count - 1] != 31)
+    res = -4;
+  for (int i = 1; !res && i < count; i++) {
+    if (fds[i] <= fds[i - 1])
+      res = -5;
+  }
+  errno = 0;
+  if (!res && (dup(fds[0]) != -1 || errno != EMFILE))
+    res = -6;
+  // Closing descriptors makes them available again, lowest first.
+  if (!res) {
+    int second = fds[1], fourth = fds[3];
+    close(fourth);
+    close(second);
+    fds[1] = open(".", O_RDONLY);
+    fds[3] = dup(fds[0]);
+    if (fds[1] != second || fds[3] != fourth || open(".", O_RDONLY) != -1)
+      res = -7;
+  }
+
+  for (int i = 0; i < count; i++)
+    close(fds[i]);
+  if (setrlimit(RLIMIT_NOFILE, &limit) != 0 || getdtablesize() != 256)
+    return -8;
+  return res;
+}
+
+// integration.rs runs the app again with the TESTAPP_MODE environment variable
+// set to one of these to run a single test that is expected to crash or
+// otherwise can't run alongside the others, see main().
+#define STACK_OVERFLOW_TEST_MODE "stack-overflow"
+
+int recurse_forever(int depth) {
+  volatile char frame[256];
+  frame[0] = (char)depth;
+  return recurse_forever(depth + 1) + frame[0];
+}
+
+void *overflow_stack(void *arg) {
+  recurse_forever(0);
+  return NULL;
+}
+
+void run_stack_overflow_test() {
+  pthread_attr_t attr;
+  pthread_attr_init(&attr);
+  pthread_attr_setstacksize(&attr, 16 * 1024);
+  pthread_t thread;
+  pthread_create(&thread, &attr, overflow_stack, NULL);
+  pthread_join(thread, NULL);
+  // touchHLE should have crashed by now.
+  printf("Stack overflow was not caught\n");
+  exit(1);
+}
+
+// Prints some time-dependent values, which integration.rs checks are the same
+// every run. This is run with --deterministic-time=.
+#define DETERMINISTIC_TIME_TEST_MODE "deterministic-time"
+
+void run_deterministic_time_test() {
+  struct timeval tv, tv2;
+  gettimeofday(&tv, NULL);
+  // No frames are presented in headless mode, so the time must not advance.
+  usleep(50000);
+  gettimeofday(&tv2, NULL);
+  if (tv2.tv_sec != tv.tv_sec || tv2.tv_usec != tv.tv_usec) {
+    printf("Time advanced without a frame\n");
+    exit(1);
+  }
+  srand((unsigned)time(NULL));
+  printf("Deterministic time: %ld %ld.%06d %.3f %d\n", (long)time(NULL),
+         (long)tv.tv_sec, tv.tv_usec, CFAbsoluteTimeGetCurrent(), rand());
+  exit(0);
+}
+
+// Checks that a failed assertion is reported and aborts.
+#define ASSERTION_TEST_MODE "assertion"
+
+void assertion_test_exit_handler(void) {
+  printf("Exit handler ran after abort\n");
+}
+
+void run_assertion_test() {
+  // abort() doesn't run these.
+  atexit(assertion_test_exit_handler);
+  int two = 2;
+  assert(two + two == 5);
+  printf("Assertion failure was not caught\n");
+  exit(0);
+}
+
+// Checks that a message sent to a deallocated object is reported. This is run
+// with --zombie-objects.
+#define ZOMBIE_TEST_MODE "zombie"
+
+void run_zombie_test() {
+  id string_class = objc_msgSend(NSHomeDirectory(), sel_registerName("class"));
+  SEL with_utf8 = sel_registerName("stringWithUTF8String:");
+  id name = objc_msgSend(string_class, with_utf8, "NSObject");
+  id object = objc_msgSend(
+      objc_msgSend(NSClassFromString(name), sel_registerName("alloc")),
+      sel_registerName("init"));
+  objc_msgSend(object, sel_registerName("release"));
+  // touchHLE should crash here.
+  objc_msgSend(object, sel_registerName("description"));
+  printf("Zombie was not caught\n");
+  exit(1);
+}
+
+#define FUNC_DEF(func)                                                         \
+  { &func, #func }
+struct {
+  int (*func)();
+  const char *name;
+} test_func_array[] = {
+    FUNC_DEF(test_qsort),   FUNC_DEF(test_vsnprintf),
+    FUNC_DEF(test_sscanf),  FUNC_DEF(test_errno),
+    FUNC_DEF(test_realloc), FUNC_DEF(test_atof),
+    FUNC_DEF(test_strtof),  FUNC_DEF(test_getcwd_chdir),
+    FUNC_DEF(test_sem),     FUNC_DEF(test_CGAffineTransform),
+    FUNC_DEF(test_strncpy), FUNC_DEF(test_strncat),
+    FUNC_DEF(test_setjmp), FUNC_DEF(test_cond_var),
+    FUNC_DEF(test_strlcpy), FUNC_DEF(test_strchr),
+    FUNC_DEF(test_sleep),   FUNC_DEF(test_signal),
+    FUNC_DEF(test_memory_usage), FUNC_DEF(test_ctype),
+    FUNC_DEF(test_vsscanf), FUNC_DEF(test_fscanf),
+    FUNC_DEF(test_cwd_relative_paths),
+    FUNC_DEF(test_user_identity), FUNC_DEF(test_getenv),
+    FUNC_DEF(test_time),
+    FUNC_DEF(test_search_paths),
+    FUNC_DEF(test_cfsocket_loopback),
+    FUNC_DEF(test_cfstream_loopback),
+    FUNC_DEF(test_select_poll),
+    FUNC_DEF(test_system_sounds),
+    FUNC_DEF(test_cfstring_get_cstring),
+    FUNC_DEF(test_ns_unarchiver),
+    FUNC_DEF(test_objc_exceptions),
+    FUNC_DEF(test_ns_null),
+    FUNC_DEF(test_ns_copying),
+    FUNC_DEF(test_ns_description),
+    FUNC_DEF(test_ns_cache),
+    FUNC_DEF(test_ns_exception),
+    FUNC_DEF(test_run_loop_timers),
+    FUNC_DEF(test_glob_ftw),
+    FUNC_DEF(test_libgen),
+    FUNC_DEF(test_regex),
+    FUNC_DEF(test_zlib),
+    FUNC_DEF(test_number_formatter),
+    FUNC_DEF(test_non_utf8_strings),
+    FUNC_DEF(test_cg_geometry_strings),
+    FUNC_DEF(test_process_stubs),
+    FUNC_DEF(test_path_resolution),
+    FUNC_DEF(test_nib_loading),
+    FUNC_DEF(test_file_attributes),
+    FUNC_DEF(test_cf_notification_center),
+    FUNC_DEF(test_NSValue_CGAffineTransform),
+    FUNC_DEF(test_cg_rect_functions),
+    FUNC_DEF(test_strtol),
+    FUNC_DEF(test_strtoul),
+    FUNC_DEF(test_strtoll),
+    FUNC_DEF(test_strtoull),
+    FUNC_DEF(test_strtod),
+    FUNC_DEF(test_atoi),
+    FUNC_DEF(test_abs),
+    FUNC_DEF(test_div),
+    FUNC_DEF(test_rand_r),
+    FUNC_DEF(test_getopt),
+    FUNC_DEF(test_calloc),
+    FUNC_DEF(test_vm_allocate),
+    FUNC_DEF(test_osatomic),
+    FUNC_DEF(test_perform_on_main_thread),
+    FUNC_DEF(test_qsort_r),
+    FUNC_DEF(test_mergesort),
+    FUNC_DEF(test_heapsort),
+    FUNC_DEF(test_string_compare),
+    FUNC_DEF(test_large_collections),
+    FUNC_DEF(test_invalid_pointers),
+    FUNC_DEF(test_thread_stack_size),
+    FUNC_DEF(test_aligned_alloc),
+    FUNC_DEF(test_array_sorting),
+    FUNC_DEF(test_fd_limit),
+    FUNC_DEF(test_strspn_strpbrk),
+    FUNC_DEF(test_mkstemp),
+    FUNC_DEF(test_strtok),
+    FUNC_DEF(test_strcasecmp),
+    FUNC_DEF(test_multibyte),
+    FUNC_DEF(test_strnlen_memrchr),
+    FUNC_DEF(test_wcstombs),
+    FUNC_DEF(test_mkdir_p),
+    FUNC_DEF(test_wide_stdio),
+    FUNC_DEF(test_stpcpy),
+    FUNC_DEF(test_setlocale),
+    FUNC_DEF(test_err_warn),
+    FUNC_DEF(test_strsep),
+    FUNC_DEF(test_confstr_pathconf),
+    FUNC_DEF(test_memccpy),
+    FUNC_DEF(test_url_cache_cookies),
+};
+
+// Because no libc is linked into this executable, there is no libc entry point
+// to call main. Instead, integration.rs tells Clang to set the _main symbol
+// as the entry point. (It has to be _main because a C compiler will throw
+// away stuff not called by main().) Since this is the true entry point, there's
+// no argc or argv and we must call exit() ourselves.
+// integration.rs checks these run once each, in reverse order of registration.
+int exit_status;
+void first_exit_handler(void) {
+  printf("Exit handler 1\n");
+  // exit() must flush this even though it's not a complete line.
+  printf("Unterminated last line");
+}
+void second_exit_handler(void *arg) { printf("Exit handler 2: %s\n", arg); }
+void third_exit_handler(void) {
+  printf("Exit handler 3\n");
+  // Not allowed by the C standard, but exit() should carry on with the
+  // remaining handlers rather than running this one again.
+  exit(exit_status);
+}
+
+int main() {
+  const char *mode = getenv("TESTAPP_MODE");
+  if (mode && !strcmp(mode, STACK_OVERFLOW_TEST_MODE))
+    run_stack_overflow_test();
+  if (mode && !strcmp(mode, DETERMINISTIC_TIME_TEST_MODE))
+    run_deterministic_time_test();
+  if (mode && !strcmp(mode, ASSERTION_TEST_MODE))
+    run_assertion_test();
+  if (mode && !strcmp(mode, ZOMBIE_TEST_MODE))
+    run_zombie_test();
+
+  atexit(first_exit_handler);
+  __cxa_atexit(second_exit_handler, "hello", NULL);
+  atexit(third_exit_handler);
 
-// Because no libc is linked into this executable, there is no libc entry point
-// to call main. Instead, integration.rs tells Clang to set the _main symbol
-// as the entry point. (It has to be _main because a C compiler will throw
-// away stuff not called by main().) Since this is the true entry point, there's
-// no argc or argv and we must call exit() ourselves.
-int main() {
   int tests_run = 0;
   int tests_passed = 0;
 
@@ -678,5 +5822,6 @@ int main() {
   }
 
   printf("Passed %d out of %d tests\n", tests_passed, tests_run);
-  exit(tests_run == tests_passed ? 0 : 1);
+  exit_status = tests_run == tests_passed ? 0 : 1;
+  exit(exit_status);
 }
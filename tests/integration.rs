@@ -98,13 +98,16 @@ fn run_test_app() -> Result<(), Box<dyn Error>> {
     let binary_name = "touchHLE";
     let binary_path = target_dir().join(format!("{}{}", binary_name, env::consts::EXE_SUFFIX));
 
-    let mut cmd = Command::new(binary_path);
+    let mut cmd = Command::new(&binary_path);
 
     let output = cmd
-        .arg(test_app_path)
+        .arg(&test_app_path)
         // headless mode avoids a distracting window briefly appearing during
         // testing, and works in CI.
         .arg("--headless")
+        // exercise call tracing with a function that has predictable arguments
+        // and return values, see test_ctype() in the test app
+        .arg("--trace-calls=toupper")
         .output()
         .expect("failed to execute touchHLE process");
 
@@ -117,6 +120,146 @@ fn run_test_app() -> Result<(), Box<dyn Error>> {
         find_subsequence(output.stderr.as_slice(), b"CPU emulation begins now."),
         None
     );
+    // toupper('a') => 'A'
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.lines().any(|line| line.contains("Traced call: #")
+        && line.contains("_toupper(r0=0x61, r1=")
+        && line.ends_with("=> r0=0x41, r1=0x0")));
+    // invalid pointers are logged rather than crashing, see
+    // test_invalid_pointers() in the test app
+    assert!(stderr
+        .lines()
+        .any(|line| line.contains("Warning: free() called with")
+            && line.contains("points inside the allocation at")));
+    assert!(stderr
+        .lines()
+        .any(|line| line.contains("Warning: realloc() called with")));
+    assert!(stderr
+        .lines()
+        .any(|line| line.contains("was sent release, but it is not part of any allocation")));
+    // warn() and friends, see test_err_warn() in the test app
+    for message in [
+        "TestApp: open /tmp/no_such_dir/warn_test: No such file or directory",
+        "TestApp: warnx 42",
+        "TestApp-syslog: syslog test: File exists",
+    ] {
+        assert!(stderr.lines().any(|line| line == message), "{:?}", message);
+    }
+    // exit handlers run once each, most recently registered first, even though
+    // the last one calls exit() again, see main() in the test app
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let exit_handler_lines: Vec<&str> = stdout
+        .lines()
+        .filter(|line| line.starts_with("Exit handler"))
+        .collect();
+    assert_eq!(
+        exit_handler_lines,
+        ["Exit handler 3", "Exit handler 2: hello", "Exit handler 1"]
+    );
+    assert!(stdout.ends_with("Exit handler 1\nUnterminated last line"));
+
+    // This makes the test app overflow a secondary thread's stack instead of
+    // running the tests, see run_stack_overflow_test() in the test app.
+    let output = Command::new(&binary_path)
+        .arg(&test_app_path)
+        .arg("--headless")
+        .arg("--env=TESTAPP_MODE=stack-overflow")
+        .output()
+        .expect("failed to execute touchHLE process");
+
+    std::io::stdout().write_all(&output.stdout).unwrap();
+    std::io::stderr().write_all(&output.stderr).unwrap();
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr
+        .lines()
+        .any(|line| line.contains("Stack overflow on thread 1 (guest pc ")));
+    assert_eq!(
+        find_subsequence(output.stdout.as_slice(), b"Stack overflow was not caught"),
+        None
+    );
+
+    // This makes the test app fail an assertion instead of running the tests,
+    // see run_assertion_test() in the test app.
+    let output = Command::new(&binary_path)
+        .arg(&test_app_path)
+        .arg("--headless")
+        .arg("--env=TESTAPP_MODE=assertion")
+        .output()
+        .expect("failed to execute touchHLE process");
+
+    std::io::stdout().write_all(&output.stdout).unwrap();
+    std::io::stderr().write_all(&output.stderr).unwrap();
+
+    // abort() exits like a process killed by SIGABRT, without running the exit
+    // handlers.
+    assert_eq!(output.status.code(), Some(134));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.lines().any(|line| line
+        .contains("Assertion failed: (two + two == 5), function run_assertion_test, file ")));
+    assert!(stderr
+        .lines()
+        .any(|line| line.contains("App called abort(), exiting.")));
+    assert_eq!(
+        find_subsequence(
+            output.stdout.as_slice(),
+            b"Assertion failure was not caught"
+        ),
+        None
+    );
+    assert_eq!(
+        find_subsequence(output.stdout.as_slice(), b"Exit handler"),
+        None
+    );
+
+    // This makes the test app send a message to a deallocated object instead
+    // of running the tests, see run_zombie_test() in the test app.
+    let output = Command::new(&binary_path)
+        .arg(&test_app_path)
+        .arg("--headless")
+        .arg("--env=TESTAPP_MODE=zombie")
+        .arg("--zombie-objects")
+        .output()
+        .expect("failed to execute touchHLE process");
+
+    std::io::stdout().write_all(&output.stdout).unwrap();
+    std::io::stderr().write_all(&output.stderr).unwrap();
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.lines().any(|line| line
+        .contains("*** -[NSObject description]: message sent to deallocated instance 0x")));
+    assert_eq!(
+        find_subsequence(output.stdout.as_slice(), b"Zombie was not caught"),
+        None
+    );
+
+    // With a fixed start time and no frames drawn, the time and anything
+    // seeded from it are the same every run, see run_deterministic_time_test()
+    // in the test app.
+    let deterministic_time_line = || -> String {
+        let output = Command::new(&binary_path)
+            .arg(&test_app_path)
+            .arg("--headless")
+            .arg("--deterministic-time=1234567890")
+            .arg("--env=TESTAPP_MODE=deterministic-time")
+            .output()
+            .expect("failed to execute touchHLE process");
+        std::io::stdout().write_all(&output.stdout).unwrap();
+        std::io::stderr().write_all(&output.stderr).unwrap();
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .find(|line| line.starts_with("Deterministic time: "))
+            .expect("deterministic time test didn't run")
+            .to_string()
+    };
+    let first_line = deterministic_time_line();
+    assert!(
+        first_line.starts_with("Deterministic time: 1234567890 1234567890.000000 256260690.000 ")
+    );
+    assert_eq!(first_line, deterministic_time_line());
 
     Ok(())
 }